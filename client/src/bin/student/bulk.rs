@@ -0,0 +1,288 @@
+use crate::sheet::{self, Layout};
+use client::StudentClient;
+use futures::StreamExt;
+use indicatif::{ProgressBar, ProgressStyle};
+use proto::bulk_service_client::BulkServiceClient;
+use proto::BulkCreateStudentsRequest;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+use tonic::transport::Channel;
+
+/// Students sent in each message of an import stream.
+pub const IMPORT_CHUNK: usize = 500;
+
+const PROGRESS_TEMPLATE: &str = "{spinner} {msg:8} [{bar:40}] {pos}/{len} ({per_sec}, ETA {eta})";
+
+/// Outcome of a bulk command, printed as a table once it finishes.
+#[derive(Debug, Default)]
+pub struct Summary {
+    succeeded: usize,
+    // (row or position, record label, error)
+    failures: Vec<(usize, String, String)>,
+}
+
+impl Summary {
+    pub fn has_failures(&self) -> bool {
+        !self.failures.is_empty()
+    }
+
+    pub fn print(&self, verb: &str) {
+        println!("\n📊 Summary");
+        println!("   {:<10} {}", "Succeeded", self.succeeded);
+        println!("   {:<10} {}", "Failed", self.failures.len());
+
+        if self.has_failures() {
+            println!("\n   {:<6}  {:<28}  Error", "Row", "Record");
+            for (row, label, error) in &self.failures {
+                println!("   {:<6}  {:<28}  {}", row, label, error);
+            }
+        } else {
+            println!("✅ All records {} successfully", verb);
+        }
+    }
+}
+
+pub fn progress_bar(len: u64, message: &'static str) -> ProgressBar {
+    let bar = ProgressBar::new(len).with_message(message);
+    bar.set_style(
+        ProgressStyle::with_template(PROGRESS_TEMPLATE)
+            .expect("valid progress template")
+            .progress_chars("=> "),
+    );
+    bar.enable_steady_tick(Duration::from_millis(100));
+    bar
+}
+
+/// Check every student in the file at `path` with `ValidateStudent`, one
+/// row at a time, without creating anything.
+pub async fn check(
+    client: &mut StudentClient,
+    path: &Path,
+    layout: &Layout,
+) -> Result<Summary, Box<dyn std::error::Error>> {
+    let rows = sheet::read(path, layout)?;
+    let bar = progress_bar(rows.len() as u64, "Checking");
+    let mut summary = Summary::default();
+
+    for (row_number, row) in rows {
+        match row {
+            Ok(student) => {
+                let label = student.name.clone();
+                match client.validate_student(student).await {
+                    Ok(response) if response.valid => summary.succeeded += 1,
+                    Ok(response) => summary.failures.push((row_number, label, response.message)),
+                    Err(status) => {
+                        summary
+                            .failures
+                            .push((row_number, label, status.message().to_string()));
+                    }
+                }
+            }
+            Err(e) => summary.failures.push((row_number, String::new(), e)),
+        }
+        bar.inc(1);
+    }
+
+    bar.finish_with_message("Checked");
+    Ok(summary)
+}
+
+/// Create every student in the CSV file or spreadsheet at `path`.
+///
+/// Rows are streamed to `BulkCreateStudents` [`IMPORT_CHUNK`] at a time and
+/// created by the server in parallel; a bad row is recorded in the summary
+/// and does not stop the import.
+pub async fn import(
+    client: &mut BulkServiceClient<Channel>,
+    path: &Path,
+    layout: &Layout,
+) -> Result<Summary, Box<dyn std::error::Error>> {
+    let rows = sheet::read(path, layout)?;
+    let bar = progress_bar(rows.len() as u64, "Importing");
+    let mut summary = Summary::default();
+
+    // Row number and label of each student sent, by position in the stream
+    let mut sent = Vec::with_capacity(rows.len());
+    let mut students = Vec::with_capacity(rows.len());
+    for (row_number, row) in rows {
+        match row {
+            Ok(student) => {
+                sent.push((row_number, student.name.clone()));
+                students.push(student);
+            }
+            Err(e) => {
+                summary.failures.push((row_number, String::new(), e));
+                bar.inc(1);
+            }
+        }
+    }
+
+    let mut chunks = Vec::new();
+    while !students.is_empty() {
+        let rest = students.split_off(students.len().min(IMPORT_CHUNK));
+        chunks.push(BulkCreateStudentsRequest {
+            students: std::mem::replace(&mut students, rest),
+        });
+    }
+    let progress = bar.clone();
+    let requests = futures::stream::iter(chunks).inspect(move |chunk| {
+        progress.inc(chunk.students.len() as u64);
+    });
+    let response = match client.bulk_create_students(requests).await {
+        Ok(response) => response.into_inner(),
+        Err(status) => {
+            bar.abandon();
+            return Err(status.into());
+        }
+    };
+
+    summary.succeeded = response.created_count.max(0) as usize;
+    for failure in response.failures {
+        let (row_number, label) = usize::try_from(failure.index)
+            .ok()
+            .and_then(|index| sent.get(index).cloned())
+            .unwrap_or_default();
+        summary.failures.push((row_number, label, failure.reason));
+    }
+    summary
+        .failures
+        .sort_by_key(|(row_number, _, _)| *row_number);
+
+    bar.finish_with_message("Imported");
+    Ok(summary)
+}
+
+/// Write every student to a CSV file at `path`, one row per student.
+pub async fn export(
+    client: &mut StudentClient,
+    path: &Path,
+) -> Result<Summary, Box<dyn std::error::Error>> {
+    let mut writer = csv::Writer::from_path(path)?;
+
+    // Size the bar from the reported total; the stream itself decides when we're done
+    let total = client.list_students(1, String::new()).await?.total_count;
+    let bar = progress_bar(total.max(0) as u64, "Exporting");
+    let mut summary = Summary::default();
+
+    let mut students = Box::pin(client.list_all(client::DEFAULT_PAGE_SIZE));
+    while let Some(student) = students.next().await {
+        let student = match student {
+            Ok(student) => student,
+            Err(status) => {
+                bar.abandon();
+                return Err(status.into());
+            }
+        };
+        let label = student.name.clone();
+        match writer.serialize(&student) {
+            Ok(()) => summary.succeeded += 1,
+            Err(e) => {
+                let position = summary.succeeded + summary.failures.len() + 1;
+                summary.failures.push((position, label, e.to_string()));
+            }
+        }
+        bar.inc(1);
+    }
+    writer.flush()?;
+
+    bar.finish_with_message("Exported");
+    Ok(summary)
+}
+
+/// Write every student to `path` as canonical proto3 JSON, one per line: the
+/// lines of a server snapshot, which the server reads back with `--force`
+/// for want of a manifest.
+pub async fn backup(
+    client: &mut StudentClient,
+    path: &Path,
+) -> Result<Summary, Box<dyn std::error::Error>> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    let total = client.list_students(1, String::new()).await?.total_count;
+    let bar = progress_bar(total.max(0) as u64, "Backing up");
+    let mut summary = Summary::default();
+
+    let mut students = Box::pin(client.list_all(client::DEFAULT_PAGE_SIZE));
+    while let Some(student) = students.next().await {
+        let student = match student {
+            Ok(student) => student,
+            Err(status) => {
+                bar.abandon();
+                return Err(status.into());
+            }
+        };
+        let label = student.name.clone();
+        match serde_json::to_string(&student) {
+            Ok(line) => {
+                writeln!(writer, "{}", line)?;
+                summary.succeeded += 1;
+            }
+            Err(e) => {
+                let position = summary.succeeded + summary.failures.len() + 1;
+                summary.failures.push((position, label, e.to_string()));
+            }
+        }
+        bar.inc(1);
+    }
+    writer.flush()?;
+
+    bar.finish_with_message("Backed up");
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::student_service_server::StudentServiceServer;
+    use proto::Student;
+    use server::snapshot::{self, Restore};
+    use server::StudentServiceImpl;
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::Server;
+
+    #[tokio::test]
+    async fn backups_restore_as_snapshots() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(StudentServiceServer::new(StudentServiceImpl::new()))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        let mut client = StudentClient::connect_lazy(format!("http://{}", addr)).unwrap();
+        for id in ["s1", "s2", "s3"] {
+            client
+                .create_student(Student {
+                    id: id.to_string(),
+                    name: format!("Student {}", id),
+                    email: format!("{}@university.edu", id),
+                    age: 20,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+
+        let dir = std::env::temp_dir().join(format!("backup-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("students.jsonl");
+        let summary = backup(&mut client, &path).await.unwrap();
+        assert!(!summary.has_failures());
+
+        let listed = client.list_students(10, String::new()).await.unwrap();
+        let force = Restore {
+            force: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            snapshot::restore(&path, None, &force).unwrap(),
+            listed.students
+        );
+        // Without a manifest, only by choice
+        assert!(snapshot::restore(&path, None, &Restore::default()).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,194 @@
+use futures::StreamExt;
+use prost_reflect::prost::Message;
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor, MethodDescriptor};
+use std::io::{self, Read};
+use tonic::codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder};
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::metadata::AsciiMetadataValue;
+use tonic::transport::Endpoint;
+use tonic::{Request, Status};
+
+/// Encodes any message and decodes messages of one type, known at runtime.
+struct DynamicCodec {
+    output: MessageDescriptor,
+}
+
+struct DynamicEncoder;
+
+struct DynamicDecoder {
+    output: MessageDescriptor,
+}
+
+impl Codec for DynamicCodec {
+    type Encode = DynamicMessage;
+    type Decode = DynamicMessage;
+    type Encoder = DynamicEncoder;
+    type Decoder = DynamicDecoder;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        DynamicEncoder
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        DynamicDecoder {
+            output: self.output.clone(),
+        }
+    }
+}
+
+impl Encoder for DynamicEncoder {
+    type Item = DynamicMessage;
+    type Error = Status;
+
+    fn encode(&mut self, item: Self::Item, dst: &mut EncodeBuf<'_>) -> Result<(), Self::Error> {
+        item.encode(dst)
+            .map_err(|e| Status::internal(format!("Failed to encode request: {}", e)))
+    }
+}
+
+impl Decoder for DynamicDecoder {
+    type Item = DynamicMessage;
+    type Error = Status;
+
+    fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<Self::Item>, Self::Error> {
+        DynamicMessage::decode(self.output.clone(), src)
+            .map(Some)
+            .map_err(|e| Status::internal(format!("Failed to decode response: {}", e)))
+    }
+}
+
+// Accepts `GetStudent`, `StudentService/GetStudent`, or the full
+// `student.StudentService/GetStudent` (`.` works in place of `/`)
+fn find_method(pool: &DescriptorPool, name: &str) -> Result<MethodDescriptor, String> {
+    let name = name.replace('/', ".");
+    let methods: Vec<_> = pool
+        .services()
+        .flat_map(|service| service.methods().collect::<Vec<_>>())
+        .collect();
+
+    let matches: Vec<_> = methods
+        .iter()
+        .filter(|method| {
+            let full_name = method.full_name();
+            full_name == name || full_name.ends_with(&format!(".{}", name))
+        })
+        .collect();
+
+    match matches.as_slice() {
+        [method] => Ok((*method).clone()),
+        [] => Err(format!(
+            "unknown method {:?}; available methods:\n  {}",
+            name,
+            methods
+                .iter()
+                .map(|method| method.full_name())
+                .collect::<Vec<_>>()
+                .join("\n  ")
+        )),
+        _ => Err(format!("method name {:?} is ambiguous", name)),
+    }
+}
+
+// A request that asks for messages in `language`, if given
+fn request<T>(message: T, language: &Option<AsciiMetadataValue>) -> Request<T> {
+    let mut request = Request::new(message);
+    if let Some(language) = language {
+        request
+            .metadata_mut()
+            .insert("accept-language", language.clone());
+    }
+    request
+}
+
+/// Call `method` with a request given as JSON (`-` reads it from stdin) and
+/// print each response as JSON, one per line. Methods that take a client
+/// stream are sent every JSON object in `data`, in order.
+pub async fn call(
+    endpoint: Endpoint,
+    method: &str,
+    data: &str,
+    language: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pool = DescriptorPool::decode(proto::FILE_DESCRIPTOR_SET)?;
+    let method = find_method(&pool, method)?;
+
+    let data = match data {
+        "-" => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            input
+        }
+        data => data.to_string(),
+    };
+    let requests = if method.is_client_streaming() {
+        serde_json::Deserializer::from_str(&data)
+            .into_iter::<serde_json::Value>()
+            .map(|value| Ok(DynamicMessage::deserialize(method.input(), value?)?))
+            .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?
+    } else {
+        let mut deserializer = serde_json::Deserializer::from_str(&data);
+        let request = DynamicMessage::deserialize(method.input(), &mut deserializer)?;
+        deserializer.end()?;
+        vec![request]
+    };
+
+    let path = PathAndQuery::try_from(format!(
+        "/{}/{}",
+        method.parent_service().full_name(),
+        method.name()
+    ))?;
+    let codec = DynamicCodec {
+        output: method.output(),
+    };
+
+    let language: Option<AsciiMetadataValue> = language.map(str::parse).transpose()?;
+    let mut grpc = tonic::client::Grpc::new(endpoint.connect_lazy());
+    grpc.ready().await?;
+
+    let mut requests = requests.into_iter();
+    match (method.is_client_streaming(), method.is_server_streaming()) {
+        (true, true) => {
+            let responses = grpc
+                .streaming(
+                    request(futures::stream::iter(requests), &language),
+                    path,
+                    codec,
+                )
+                .await?;
+            print_stream(responses.into_inner()).await?;
+        }
+        (true, false) => {
+            let response = grpc
+                .client_streaming(
+                    request(futures::stream::iter(requests), &language),
+                    path,
+                    codec,
+                )
+                .await?;
+            println!("{}", serde_json::to_string(response.get_ref())?);
+        }
+        (false, true) => {
+            let message = requests.next().ok_or("no request given")?;
+            let responses = grpc
+                .server_streaming(request(message, &language), path, codec)
+                .await?;
+            print_stream(responses.into_inner()).await?;
+        }
+        (false, false) => {
+            let message = requests.next().ok_or("no request given")?;
+            let response = grpc.unary(request(message, &language), path, codec).await?;
+            println!("{}", serde_json::to_string(response.get_ref())?);
+        }
+    }
+
+    Ok(())
+}
+
+async fn print_stream(
+    mut responses: tonic::Streaming<DynamicMessage>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    while let Some(response) = responses.next().await {
+        println!("{}", serde_json::to_string(&response?)?);
+    }
+    Ok(())
+}
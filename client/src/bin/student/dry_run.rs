@@ -0,0 +1,69 @@
+use crate::{gpa, print_student};
+use client::StudentClient;
+use proto::Student;
+
+// The shared rules, checked here first, then the server's; an invalid
+// student is reported as an error
+async fn validate(
+    client: &mut StudentClient,
+    student: &Student,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let response = client.validate_student(student.clone()).await?;
+    if response.valid {
+        return Ok(());
+    }
+
+    println!("🔎 Dry run: this student would be rejected:");
+    for violation in &response.violations {
+        println!("   {}: {}", violation.field, violation.description);
+    }
+    Err(format!("{} validation error(s)", response.violations.len()).into())
+}
+
+pub async fn create(
+    client: &mut StudentClient,
+    student: Student,
+) -> Result<(), Box<dyn std::error::Error>> {
+    validate(client, &student).await?;
+    println!("🔎 Dry run: would create student:");
+    print_student(&student);
+    Ok(())
+}
+
+pub async fn update(
+    client: &mut StudentClient,
+    current: &Student,
+    updated: Student,
+) -> Result<(), Box<dyn std::error::Error>> {
+    validate(client, &updated).await?;
+
+    let changes = [
+        ("Name", current.name.clone(), updated.name.clone()),
+        ("Email", current.email.clone(), updated.email.clone()),
+        ("Age", current.age.to_string(), updated.age.to_string()),
+        ("Major", current.major.clone(), updated.major.clone()),
+        ("GPA", gpa(current), gpa(&updated)),
+    ];
+    let changes: Vec<_> = changes.iter().filter(|(_, old, new)| old != new).collect();
+
+    if changes.is_empty() {
+        println!("🔎 Dry run: no changes for student {}", current.id);
+        return Ok(());
+    }
+
+    println!("🔎 Dry run: would update student {}:", current.id);
+    for (field, old, new) in changes {
+        println!("   {}: {} → {}", field, old, new);
+    }
+    Ok(())
+}
+
+pub async fn delete(
+    client: &mut StudentClient,
+    id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let student = client.get_student(id).await?;
+    println!("🔎 Dry run: would delete student:");
+    print_student(&student);
+    Ok(())
+}
@@ -0,0 +1,592 @@
+//! `student loadgen`: fake students and a mix of calls sent at a steady
+//! rate, with how long each kind of call took.
+//!
+//! Calls start on a fixed schedule whether or not earlier ones have been
+//! answered, up to `--concurrency` at once, so a slow server shows up as
+//! latency rather than as a lower rate. With only creates in the mix, it
+//! fills a server with students for demos.
+//!
+//! Everything random comes from one ChaCha8 generator, seeded with `--seed`
+//! or a seed it prints, so a run can be repeated: for the same seed, any
+//! machine running the same build makes the same `--students` up front,
+//! IDs included, then sends the same calls with the same fake data. Only
+//! which student a call picks can differ, once creates and deletes in the
+//! mix are answered in another order.
+
+use crate::bulk;
+use clap::{Args, ValueEnum};
+use client::StudentClient;
+use fake::faker::address::en::{BuildingNumber, CityName, PostCode, StateAbbr, StreetName};
+use fake::faker::name::en::{FirstName, LastName};
+use fake::Fake;
+use futures::future::BoxFuture;
+use futures::stream::{self, FuturesUnordered, StreamExt};
+use proto::{Address, Student};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::time::{Duration, Instant};
+use tonic::{Code, Status};
+
+const MAJORS: &[&str] = &[
+    "Biology",
+    "Business Administration",
+    "Chemistry",
+    "Computer Science",
+    "Economics",
+    "Electrical Engineering",
+    "English",
+    "History",
+    "Mathematics",
+    "Mechanical Engineering",
+    "Nursing",
+    "Philosophy",
+    "Physics",
+    "Political Science",
+    "Psychology",
+];
+
+const EMAIL_DOMAINS: &[&str] = &[
+    "university.edu",
+    "university.edu",
+    "university.edu",
+    "gmail.com",
+    "outlook.com",
+    "yahoo.com",
+];
+
+/// Students listed by each `list` call.
+const LIST_PAGE_SIZE: i32 = 50;
+
+/// The most calls started each second: any more and the time between them
+/// rounds down to nothing.
+const MAX_RATE: i64 = 1_000_000_000;
+
+#[derive(Debug, Args)]
+pub struct LoadgenArgs {
+    /// Calls started each second, at most one a nanosecond
+    #[arg(
+        long,
+        default_value_t = 100,
+        value_parser = clap::value_parser!(u32).range(1..=MAX_RATE)
+    )]
+    rate: u32,
+    /// How long to send calls for, e.g. `60s`; `rate` times this many calls
+    /// are sent
+    #[arg(long, default_value = "10s", value_parser = client::parse_duration)]
+    duration: Duration,
+    /// How often each call is made relative to the others, as CALL=WEIGHT;
+    /// `--mix create=1` only creates students, to seed a server
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = parse_weight,
+        default_value = "create=20,get=50,update=15,list=10,delete=5"
+    )]
+    mix: Vec<(Call, u32)>,
+    /// Most calls waiting for an answer at once; a call due while this
+    /// many are waiting is skipped
+    #[arg(
+        long,
+        default_value_t = 64,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    concurrency: usize,
+    /// Make the same students and calls as another run with this seed
+    /// [default: a random one, printed]
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Create this many fake students, as fast as the server takes them,
+    /// before sending the mix; `--duration 0s` only creates them
+    #[arg(long, default_value_t = 0)]
+    students: usize,
+}
+
+/// A kind of call in the mix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Call {
+    /// Create a fake student
+    Create,
+    /// Get a student by ID
+    Get,
+    /// Change a student's GPA and credits, read and written back
+    Update,
+    /// List the first page of students
+    List,
+    /// Delete a student
+    Delete,
+}
+
+impl Call {
+    fn name(self) -> &'static str {
+        match self {
+            Call::Create => "create",
+            Call::Get => "get",
+            Call::Update => "update",
+            Call::List => "list",
+            Call::Delete => "delete",
+        }
+    }
+}
+
+fn parse_weight(text: &str) -> Result<(Call, u32), String> {
+    let (call, weight) = text
+        .split_once('=')
+        .ok_or_else(|| format!("{}: expected CALL=WEIGHT, e.g. get=50", text))?;
+    let call = Call::from_str(call.trim(), true)?;
+    let weight = weight
+        .trim()
+        .parse()
+        .map_err(|_| format!("{}: the weight must be a whole number", text))?;
+    Ok((call, weight))
+}
+
+// A call as it was answered: how long it took, and the ID of a student it
+// created
+type Answer = (Call, Duration, Result<Option<String>, Status>);
+
+#[derive(Debug, Default)]
+struct Tally {
+    latencies: Vec<Duration>,
+    // By status code
+    errors: BTreeMap<String, usize>,
+}
+
+impl Tally {
+    fn row(&mut self) -> Row {
+        self.latencies.sort();
+        Row {
+            calls: self.latencies.len(),
+            errors: self.errors.clone(),
+            p50_ms: millis(percentile(&self.latencies, 0.50)),
+            p90_ms: millis(percentile(&self.latencies, 0.90)),
+            p99_ms: millis(percentile(&self.latencies, 0.99)),
+            max_ms: millis(self.latencies.last().copied().unwrap_or_default()),
+        }
+    }
+}
+
+/// What a run did, printed as a table or, with `--json`, as one object.
+#[derive(Debug, Serialize)]
+struct Report {
+    seed: u64,
+    seeding: Option<Seeding>,
+    calls: usize,
+    failed: usize,
+    /// Due while `--concurrency` calls were waiting
+    skipped: u64,
+    seconds: f64,
+    /// Calls answered per second
+    rate: f64,
+    by_call: BTreeMap<Call, Row>,
+    all: Row,
+}
+
+/// The `--students` made before the mix.
+#[derive(Debug, Default, Serialize)]
+struct Seeding {
+    created: usize,
+    /// Made by an earlier run with the same seed
+    existing: usize,
+    failed: BTreeMap<String, usize>,
+    seconds: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct Row {
+    calls: usize,
+    errors: BTreeMap<String, usize>,
+    p50_ms: f64,
+    p90_ms: f64,
+    p99_ms: f64,
+    max_ms: f64,
+}
+
+impl Report {
+    fn print(&self) {
+        if self.calls == 0 {
+            return;
+        }
+        println!(
+            "\n📊 {} calls in {:.1}s ({:.1}/s), {} failed, {} skipped",
+            self.calls, self.seconds, self.rate, self.failed, self.skipped
+        );
+        println!(
+            "   {:<8} {:>7} {:>7} {:>9} {:>9} {:>9} {:>9}",
+            "Call", "Calls", "Errors", "p50", "p90", "p99", "Max"
+        );
+        let rows = self.by_call.iter().map(|(call, row)| (call.name(), row));
+        for (name, row) in rows.chain([("all", &self.all)]) {
+            println!(
+                "   {:<8} {:>7} {:>7} {:>7.1}ms {:>7.1}ms {:>7.1}ms {:>7.1}ms",
+                name,
+                row.calls,
+                row.errors.values().sum::<usize>(),
+                row.p50_ms,
+                row.p90_ms,
+                row.p99_ms,
+                row.max_ms
+            );
+        }
+        for (call, row) in &self.by_call {
+            if !row.errors.is_empty() {
+                let errors: Vec<_> = row
+                    .errors
+                    .iter()
+                    .map(|(code, count)| format!("{} {}", count, code))
+                    .collect();
+                println!("   ❌ {}: {}", call.name(), errors.join(", "));
+            }
+        }
+    }
+}
+
+// Nearest rank: the smallest latency at least `share` of `sorted` are at or
+// under
+fn percentile(sorted: &[Duration], share: f64) -> Duration {
+    let rank = (share * sorted.len() as f64).ceil() as usize;
+    sorted.get(rank.max(1) - 1).copied().unwrap_or_default()
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Send the mix of calls in `args` at its rate, then print how long they
+/// took.
+pub async fn run(
+    client: StudentClient,
+    args: LoadgenArgs,
+    json: bool,
+) -> Result<(), Box<dyn Error>> {
+    let weights = WeightedIndex::new(args.mix.iter().map(|(_, weight)| *weight))
+        .map_err(|_| "--mix needs a call with a weight above 0")?;
+    let seed = args.seed.unwrap_or_else(rand::random);
+    if !json {
+        println!("🎲 Seed {} (repeat this run with --seed {})", seed, seed);
+    }
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    // Gets, updates, and deletes start on the students made up front, or
+    // else on students already there
+    let (seeding, mut ids) = if args.students > 0 {
+        let (seeding, ids) = create_students(&client, &args, &mut rng).await;
+        if !json {
+            seeding.print(args.students);
+        }
+        (Some(seeding), ids)
+    } else {
+        let page = client.clone().list_students(1000, String::new()).await?;
+        let ids = page.students.into_iter().map(|student| student.id);
+        (None, ids.collect())
+    };
+
+    let due = (args.rate as f64 * args.duration.as_secs_f64()).round() as u64;
+    let bar = bulk::progress_bar(due, "Sending");
+    let mut ticks = tokio::time::interval(Duration::from_secs(1) / args.rate);
+    let mut running = FuturesUnordered::new();
+    let mut tallies: BTreeMap<Call, Tally> = BTreeMap::new();
+    let mut skipped = 0;
+    let started = Instant::now();
+    let mut left = due;
+    loop {
+        tokio::select! {
+            _ = ticks.tick(), if left > 0 => {
+                left -= 1;
+                bar.inc(1);
+                if running.len() >= args.concurrency {
+                    skipped += 1;
+                    continue;
+                }
+                let call = args.mix[weights.sample(&mut rng)].0;
+                running.push(start(client.clone(), call, &mut ids, &mut rng));
+            }
+            Some((call, latency, result)) = running.next() => {
+                let tally = tallies.entry(call).or_default();
+                tally.latencies.push(latency);
+                match result {
+                    Ok(Some(id)) => ids.push(id),
+                    Ok(None) => {}
+                    Err(status) => {
+                        *tally.errors.entry(format!("{:?}", status.code())).or_default() += 1;
+                    }
+                }
+            }
+            else => break,
+        }
+    }
+    bar.finish_and_clear();
+
+    let seconds = started.elapsed().as_secs_f64();
+    let mut all = Tally::default();
+    for tally in tallies.values() {
+        all.latencies.extend(&tally.latencies);
+        for (code, count) in &tally.errors {
+            *all.errors.entry(code.clone()).or_default() += count;
+        }
+    }
+    let all = all.row();
+    let report = Report {
+        seed,
+        seeding,
+        calls: all.calls,
+        failed: all.errors.values().sum(),
+        skipped,
+        seconds,
+        rate: if seconds > 0.0 {
+            all.calls as f64 / seconds
+        } else {
+            0.0
+        },
+        by_call: tallies
+            .iter_mut()
+            .map(|(call, tally)| (*call, tally.row()))
+            .collect(),
+        all,
+    };
+    if json {
+        println!("{}", serde_json::to_string(&report)?);
+    } else {
+        report.print();
+    }
+    Ok(())
+}
+
+// `args.students` fake students, created `args.concurrency` at a time, and
+// the IDs of those now on the server, in the order they were made
+async fn create_students(
+    client: &StudentClient,
+    args: &LoadgenArgs,
+    rng: &mut ChaCha8Rng,
+) -> (Seeding, Vec<String>) {
+    let students: Vec<_> = (0..args.students).map(|_| fake_student(rng)).collect();
+    let bar = bulk::progress_bar(students.len() as u64, "Creating");
+    let started = Instant::now();
+    let results: Vec<_> = stream::iter(students)
+        .map(|student| {
+            let mut client = client.clone();
+            let bar = bar.clone();
+            async move {
+                let id = student.id.clone();
+                let result = client.create_student(student).await;
+                bar.inc(1);
+                (id, result)
+            }
+        })
+        // In order, so the IDs are too
+        .buffered(args.concurrency)
+        .collect()
+        .await;
+    bar.finish_and_clear();
+
+    let mut seeding = Seeding {
+        seconds: started.elapsed().as_secs_f64(),
+        ..Default::default()
+    };
+    let mut ids = Vec::new();
+    for (id, result) in results {
+        match result {
+            Ok(_) => seeding.created += 1,
+            Err(status) if status.code() == Code::AlreadyExists => seeding.existing += 1,
+            Err(status) => {
+                *seeding
+                    .failed
+                    .entry(format!("{:?}", status.code()))
+                    .or_default() += 1;
+                continue;
+            }
+        }
+        ids.push(id);
+    }
+    (seeding, ids)
+}
+
+impl Seeding {
+    fn print(&self, students: usize) {
+        println!(
+            "🌱 {} students in {:.1}s: {} created, {} already there, {} failed",
+            students,
+            self.seconds,
+            self.created,
+            self.existing,
+            self.failed.values().sum::<usize>()
+        );
+        for (code, count) in &self.failed {
+            println!("   ❌ {} {}", count, code);
+        }
+    }
+}
+
+// `call` on its way, timed from when it is first polled
+fn start(
+    mut client: StudentClient,
+    call: Call,
+    ids: &mut Vec<String>,
+    rng: &mut ChaCha8Rng,
+) -> BoxFuture<'static, Answer> {
+    // A call on a student needs one; with none left, make one
+    let call = match call {
+        Call::Get | Call::Update | Call::Delete if ids.is_empty() => Call::Create,
+        call => call,
+    };
+    let sent: BoxFuture<'static, Result<Option<String>, Status>> = match call {
+        Call::Create => {
+            let student = fake_student(rng);
+            Box::pin(async move {
+                client
+                    .create_student(student)
+                    .await
+                    .map(|student| Some(student.id))
+            })
+        }
+        Call::Get => {
+            let id = ids.choose(rng).cloned().unwrap_or_default();
+            Box::pin(async move { client.get_student(&id).await.map(|_| None) })
+        }
+        Call::Update => {
+            let id = ids.choose(rng).cloned().unwrap_or_default();
+            let gpa = fake_gpa(rng);
+            let credits = rng.gen_range(0..=130);
+            Box::pin(async move {
+                client
+                    .update_with_retry(&id, |student| {
+                        student.gpa_decimal = gpa.clone();
+                        student.credits = credits;
+                    })
+                    .await
+                    .map(|_| None)
+            })
+        }
+        Call::List => Box::pin(async move {
+            client
+                .list_students(LIST_PAGE_SIZE, String::new())
+                .await
+                .map(|_| None)
+        }),
+        Call::Delete => {
+            // Out of the pool at once, so no later call picks it
+            let id = ids.swap_remove(rng.gen_range(0..ids.len()));
+            Box::pin(async move { client.delete_student(&id).await.map(|_| None) })
+        }
+    };
+    Box::pin(async move {
+        let started = Instant::now();
+        let result = sent.await;
+        (call, started.elapsed(), result)
+    })
+}
+
+/// A student as a registrar might have them: an English name, a matching
+/// email, a plausible age, major, GPA, and credits, and mostly a US address,
+/// under an ID made from `rng` too.
+fn fake_student(rng: &mut ChaCha8Rng) -> Student {
+    let first: String = FirstName().fake_with_rng(rng);
+    let last: String = LastName().fake_with_rng(rng);
+    let handle: String = format!("{}.{}", first, last)
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '.')
+        .collect();
+    let email = format!(
+        "{}{}@{}",
+        handle.to_lowercase(),
+        rng.gen_range(1..1000),
+        EMAIL_DOMAINS.choose(rng).expect("domains")
+    );
+    let preferred_name = if rng.gen_bool(0.1) {
+        FirstName().fake_with_rng(rng)
+    } else {
+        String::new()
+    };
+    let age = if rng.gen_bool(0.9) {
+        rng.gen_range(17..=24)
+    } else {
+        rng.gen_range(25..=60)
+    };
+    let address = rng.gen_bool(0.8).then(|| Address {
+        line1: format!(
+            "{} {}",
+            BuildingNumber().fake_with_rng::<String, _>(rng),
+            StreetName().fake_with_rng::<String, _>(rng)
+        ),
+        city: CityName().fake_with_rng(rng),
+        region: StateAbbr().fake_with_rng(rng),
+        postal_code: PostCode().fake_with_rng(rng),
+        country: "US".to_string(),
+        ..Default::default()
+    });
+    Student {
+        id: uuid::Builder::from_random_bytes(rng.gen())
+            .into_uuid()
+            .to_string(),
+        name: format!("{} {}", first, last),
+        preferred_name,
+        email,
+        age,
+        major: MAJORS.choose(rng).expect("majors").to_string(),
+        gpa_decimal: fake_gpa(rng),
+        credits: rng.gen_range(0..=130),
+        address,
+        ..Default::default()
+    }
+}
+
+fn fake_gpa(rng: &mut ChaCha8Rng) -> String {
+    let hundredths = rng.gen_range(150..=400);
+    format!("{}.{:02}", hundredths / 100, hundredths % 100)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::collections::HashSet;
+
+    #[derive(Debug, Parser)]
+    struct Loadgen {
+        #[command(flatten)]
+        args: LoadgenArgs,
+    }
+
+    fn parse(flags: &[&str]) -> Result<LoadgenArgs, clap::Error> {
+        let args = ["loadgen"].into_iter().chain(flags.iter().copied());
+        Loadgen::try_parse_from(args).map(|loadgen| loadgen.args)
+    }
+
+    #[test]
+    fn rates_leave_time_between_calls() {
+        let args = parse(&["--rate", "1000000000"]).unwrap();
+        assert!(!(Duration::from_secs(1) / args.rate).is_zero());
+        for rate in ["0", "1000000001"] {
+            assert!(parse(&["--rate", rate]).is_err(), "--rate {}", rate);
+        }
+    }
+
+    #[test]
+    fn some_calls_may_wait_at_once() {
+        assert_eq!(parse(&["--concurrency", "1"]).unwrap().concurrency, 1);
+        assert!(parse(&["--concurrency", "0"]).is_err());
+    }
+
+    fn fake_students(seed: u64) -> Vec<Student> {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        (0..10).map(|_| fake_student(&mut rng)).collect()
+    }
+
+    #[test]
+    fn a_seed_makes_the_same_students_every_time() {
+        let students = fake_students(42);
+        assert_eq!(students, fake_students(42));
+        let ids: HashSet<_> = students.iter().map(|student| &student.id).collect();
+        assert_eq!(ids.len(), students.len());
+
+        let others = fake_students(43);
+        for (student, other) in students.iter().zip(&others) {
+            assert_ne!(student.id, other.id);
+            assert_ne!(student, other);
+        }
+    }
+}
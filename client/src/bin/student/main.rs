@@ -0,0 +1,700 @@
+mod bulk;
+mod call;
+mod dry_run;
+mod loadgen;
+mod queue;
+mod sheet;
+mod tui;
+
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use client::{ConnectionSettings, ServiceConfig, StudentClient};
+use futures::StreamExt;
+use proto::bulk_service_client::BulkServiceClient;
+use proto::enrollment_service_client::EnrollmentServiceClient;
+use proto::trash_service_client::TrashServiceClient;
+use proto::{
+    AcademicStanding, Address, GenerateTranscriptRequest, ListStudentsRequest, Student,
+    TranscriptFormat, UndoRequest,
+};
+use queue::Mutation;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use tonic::transport::Endpoint;
+use tonic::Status;
+
+/// Command-line interface for the student management service
+#[derive(Debug, Parser)]
+#[command(name = "student", version, about)]
+struct Cli {
+    /// Address of the gRPC server
+    #[arg(long, global = true, default_value = "http://[::1]:50051")]
+    server: String,
+
+    /// Log every request and response to stderr
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    /// Print students as JSON (one object per line) instead of text
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Ask for error messages and name order in this language, e.g. `zh` or
+    /// `es` (sent as `accept-language`)
+    #[arg(long, global = true)]
+    lang: Option<String>,
+
+    /// A gRPC service config (JSON) with per-method timeouts and retry
+    /// policies
+    #[arg(long, global = true, value_name = "FILE")]
+    service_config: Option<PathBuf>,
+
+    /// Ping the server this often, e.g. `30s`, even between calls, so a
+    /// quiet `call WatchStudents` survives NAT timeouts
+    #[arg(long, global = true, value_parser = client::parse_duration)]
+    keepalive_interval: Option<std::time::Duration>,
+
+    /// Drop the connection when a ping is not answered within this long
+    /// [default: 20s]
+    #[arg(long, global = true, value_parser = client::parse_duration)]
+    keepalive_timeout: Option<std::time::Duration>,
+
+    /// Queue creates, updates, and deletes in this file when the server
+    /// cannot be reached, to send later with `student sync`
+    #[arg(long, global = true, value_name = "FILE")]
+    queue: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Create a new student
+    Create(CreateArgs),
+    /// Show a student by ID
+    Get { id: String },
+    /// Update fields of an existing student
+    Update(UpdateArgs),
+    /// Delete a student by ID
+    Delete {
+        id: String,
+        /// Show what would be deleted without deleting it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Restore a deleted or merged-away student from the trash
+    Undo {
+        /// The operation ID printed when the student was removed
+        operation_id: String,
+    },
+    /// List all students
+    List {
+        /// Number of students fetched per request
+        #[arg(long, default_value_t = client::DEFAULT_PAGE_SIZE)]
+        page_size: i32,
+        /// "id", or "name" to sort by preferred name in the --lang collation
+        #[arg(long, default_value = "id")]
+        order_by: String,
+        /// Only students with an address in this country, e.g. US
+        #[arg(long)]
+        country: Option<String>,
+        /// Only students with an address in this region, e.g. CA
+        #[arg(long)]
+        region: Option<String>,
+    },
+    /// Create students from a CSV file (columns: id,name,email,age,major,gpa)
+    /// or a spreadsheet (.xlsx, .xls, .ods)
+    Import {
+        #[arg(required_unless_present = "file_flag")]
+        file: Option<PathBuf>,
+        /// The file to import, as a flag
+        #[arg(
+            long = "file",
+            id = "file_flag",
+            value_name = "FILE",
+            conflicts_with = "file"
+        )]
+        file_flag: Option<PathBuf>,
+        /// Sheet of a workbook to read [default: the first]
+        #[arg(long)]
+        sheet: Option<String>,
+        /// Where fields are when the header doesn't name them, as
+        /// FIELD=COLUMN by header text or letter, e.g. email=E-mail,name=col:B
+        #[arg(long, value_delimiter = ',', value_parser = sheet::parse_mapping)]
+        map: Vec<(String, sheet::Column)>,
+        /// Validate every row with the server without creating anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Write all students to a CSV file
+    Export { file: PathBuf },
+    /// Write all students to a file as JSON, one per line, in the server's
+    /// snapshot format
+    Backup { file: PathBuf },
+    /// Save a student's transcript as a document
+    Transcript {
+        id: String,
+        /// File to write the document to
+        #[arg(short, long)]
+        output: PathBuf,
+        #[arg(long, value_enum, default_value_t = Format::Pdf)]
+        format: Format,
+    },
+    /// Call any method with a JSON request and print the responses as JSON
+    Call {
+        /// Method name, e.g. GetStudent or student.StudentService/GetStudent
+        method: String,
+        /// Request as JSON (several objects for a client stream); `-` reads it from stdin
+        #[arg(default_value = "{}")]
+        data: String,
+    },
+    /// Send the creates, updates, and deletes queued with --queue while the
+    /// server was unreachable
+    Sync {
+        /// Only list what is queued
+        #[arg(long)]
+        list: bool,
+    },
+    /// Browse the students as they change, and create, edit, or delete them,
+    /// in a full-screen terminal UI
+    Tui,
+    /// Send fake students and a mix of calls at a steady rate, and print the
+    /// latency of each kind of call
+    Loadgen(loadgen::LoadgenArgs),
+    /// Print a shell completion script to stdout
+    Completions { shell: Shell },
+    /// Print the man page (roff) to stdout
+    Man,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Format {
+    Pdf,
+    Html,
+}
+
+impl From<Format> for TranscriptFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Pdf => TranscriptFormat::Pdf,
+            Format::Html => TranscriptFormat::Html,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+struct CreateArgs {
+    /// Explicit ID; generated by the server when omitted
+    #[arg(long)]
+    id: Option<String>,
+    #[arg(long)]
+    name: String,
+    /// What the student goes by, when not their full name
+    #[arg(long, default_value = "")]
+    preferred_name: String,
+    #[arg(long)]
+    email: String,
+    /// A phone number, with a country code unless the server has a default
+    /// region; repeat for more
+    #[arg(long = "phone")]
+    phone_numbers: Vec<String>,
+    #[arg(long)]
+    age: i32,
+    #[arg(long, default_value = "")]
+    major: String,
+    /// Exact to two decimal places, e.g. 3.25
+    #[arg(long, default_value = "")]
+    gpa: String,
+    #[arg(long, default_value_t = 0)]
+    credits: i32,
+    #[command(flatten)]
+    address: AddressArgs,
+    /// Client-owned metadata as KEY=VALUE; repeat for more
+    #[arg(long = "annotation", value_parser = key_value)]
+    annotations: Vec<(String, String)>,
+    /// Validate and show the student without creating it
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// The parts of an address given on the command line.
+#[derive(Debug, Clone, Args, Serialize, Deserialize)]
+struct AddressArgs {
+    /// First line of the address
+    #[arg(long)]
+    line1: Option<String>,
+    #[arg(long)]
+    line2: Option<String>,
+    #[arg(long)]
+    city: Option<String>,
+    /// State or province, e.g. CA
+    #[arg(long)]
+    region: Option<String>,
+    #[arg(long)]
+    postal_code: Option<String>,
+    /// ISO 3166 country code, e.g. US
+    #[arg(long)]
+    country: Option<String>,
+}
+
+impl AddressArgs {
+    // Overlay the parts that were given onto `address`, if any were
+    fn apply(&self, address: Option<Address>) -> Option<Address> {
+        let parts = [
+            &self.line1,
+            &self.line2,
+            &self.city,
+            &self.region,
+            &self.postal_code,
+            &self.country,
+        ];
+        if parts.iter().all(|part| part.is_none()) {
+            return address;
+        }
+        let mut address = address.unwrap_or_default();
+        let fields = [
+            &mut address.line1,
+            &mut address.line2,
+            &mut address.city,
+            &mut address.region,
+            &mut address.postal_code,
+            &mut address.country,
+        ];
+        for (field, part) in fields.into_iter().zip(parts) {
+            if let Some(part) = part {
+                *field = part.clone();
+            }
+        }
+        Some(address)
+    }
+}
+
+impl CreateArgs {
+    fn to_student(&self) -> Student {
+        Student {
+            id: self.id.clone().unwrap_or_default(),
+            name: self.name.clone(),
+            preferred_name: self.preferred_name.clone(),
+            email: self.email.clone(),
+            phone_numbers: self.phone_numbers.clone(),
+            age: self.age,
+            major: self.major.clone(),
+            gpa_decimal: self.gpa.clone(),
+            credits: self.credits,
+            address: self.address.apply(None),
+            annotations: self.annotations.iter().cloned().collect(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Args, Serialize, Deserialize)]
+struct UpdateArgs {
+    id: String,
+    #[arg(long)]
+    name: Option<String>,
+    /// Empty to clear it
+    #[arg(long)]
+    preferred_name: Option<String>,
+    #[arg(long)]
+    email: Option<String>,
+    /// Replaces every phone number; repeat for more
+    #[arg(long = "phone")]
+    phone_numbers: Vec<String>,
+    /// Remove every phone number
+    #[arg(long, conflicts_with = "phone_numbers")]
+    clear_phones: bool,
+    #[arg(long)]
+    age: Option<i32>,
+    #[arg(long)]
+    major: Option<String>,
+    /// Exact to two decimal places, e.g. 3.25
+    #[arg(long)]
+    gpa: Option<String>,
+    #[arg(long)]
+    credits: Option<i32>,
+    #[command(flatten)]
+    address: AddressArgs,
+    /// Remove the address
+    #[arg(long, conflicts_with_all = ["line1", "line2", "city", "region", "postal_code", "country"])]
+    clear_address: bool,
+    /// Add or replace an annotation, as KEY=VALUE; repeat for more
+    #[arg(long = "annotation", value_parser = key_value)]
+    annotations: Vec<(String, String)>,
+    /// Remove the annotation with this key; repeat for more
+    #[arg(long = "remove-annotation")]
+    removed_annotations: Vec<String>,
+    /// Validate and show the changes without applying them
+    #[arg(long)]
+    #[serde(skip)]
+    dry_run: bool,
+}
+
+impl UpdateArgs {
+    // Overlay the flags that were given onto the current record
+    fn apply(&self, mut student: Student) -> Student {
+        if let Some(name) = &self.name {
+            student.name = name.clone();
+        }
+        if let Some(preferred_name) = &self.preferred_name {
+            student.preferred_name = preferred_name.clone();
+        }
+        if let Some(email) = &self.email {
+            student.email = email.clone();
+        }
+        if !self.phone_numbers.is_empty() || self.clear_phones {
+            student.phone_numbers = self.phone_numbers.clone();
+        }
+        if let Some(age) = self.age {
+            student.age = age;
+        }
+        if let Some(major) = &self.major {
+            student.major = major.clone();
+        }
+        if let Some(gpa) = &self.gpa {
+            student.gpa_decimal = gpa.clone();
+        }
+        if let Some(credits) = self.credits {
+            student.credits = credits;
+        }
+        student.address = if self.clear_address {
+            None
+        } else {
+            self.address.apply(student.address)
+        };
+        for key in &self.removed_annotations {
+            student.annotations.remove(key);
+        }
+        student.annotations.extend(self.annotations.iter().cloned());
+        student
+    }
+}
+
+fn key_value(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((key, value)) => Ok((key.to_string(), value.to_string())),
+        None => Err(format!("{:?} is not KEY=VALUE", arg)),
+    }
+}
+
+pub(crate) fn print_student(student: &Student) {
+    println!("   ID: {}", student.id);
+    println!("   Name: {}", student.name);
+    if !student.preferred_name.is_empty() {
+        println!("   Preferred name: {}", student.preferred_name);
+    }
+    println!("   Email: {}", student.email);
+    if !student.phone_numbers.is_empty() {
+        println!("   Phone: {}", student.phone_numbers.join(", "));
+    }
+    if let Some(address) = &student.address {
+        let parts = [
+            &address.line1,
+            &address.line2,
+            &address.city,
+            &address.region,
+            &address.postal_code,
+            &address.country,
+        ];
+        let parts: Vec<&str> = parts
+            .iter()
+            .map(|part| part.as_str())
+            .filter(|part| !part.is_empty())
+            .collect();
+        println!("   Address: {}", parts.join(", "));
+    }
+    println!("   Age: {}", student.age);
+    println!("   Major: {}", student.major);
+    println!("   GPA: {}", gpa(student));
+    println!("   Credits: {}", student.credits);
+    if student.credits_attempted > 0 {
+        println!(
+            "   Graded credits: {} earned of {} attempted",
+            student.credits_earned, student.credits_attempted
+        );
+    }
+    println!("   Standing: {}", standing_name(student.standing()));
+    if !student.annotations.is_empty() {
+        let mut annotations: Vec<_> = student.annotations.iter().collect();
+        annotations.sort();
+        println!("   Annotations:");
+        for (key, value) in annotations {
+            println!("      {} = {}", key, value);
+        }
+    }
+}
+
+// Servers from before exact GPAs only send the float
+pub(crate) fn gpa(student: &Student) -> String {
+    if student.gpa_decimal.is_empty() {
+        format!("{:.2}", student.gpa)
+    } else {
+        student.gpa_decimal.clone()
+    }
+}
+
+pub(crate) fn standing_name(standing: AcademicStanding) -> &'static str {
+    match standing {
+        AcademicStanding::Unspecified => "-",
+        AcademicStanding::Good => "Good",
+        AcademicStanding::DeansList => "Dean's list",
+        AcademicStanding::Probation => "Probation",
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let Cli {
+        server,
+        verbose,
+        json,
+        lang,
+        service_config,
+        keepalive_interval,
+        keepalive_timeout,
+        queue,
+        command,
+    } = cli;
+    let connections = ConnectionSettings {
+        keepalive_interval,
+        keepalive_timeout,
+        keepalive_while_idle: true,
+        ..Default::default()
+    };
+    let endpoint =
+        || Endpoint::from_shared(server.clone()).map(|endpoint| connections.apply(endpoint));
+    let service_config = match service_config {
+        Some(path) => {
+            let json =
+                std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            let config = ServiceConfig::from_json(&json)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            Some(config)
+        }
+        None => None,
+    };
+    // Text output gets a heading; JSON output stays machine-readable
+    let show = |heading: &str, student: &Student| -> Result<(), serde_json::Error> {
+        if json {
+            println!("{}", serde_json::to_string(student)?);
+        } else {
+            if !heading.is_empty() {
+                println!("{}", heading);
+            }
+            print_student(student);
+        }
+        Ok(())
+    };
+    let connect = || {
+        StudentClient::connect_lazy_with(server.clone(), &connections).map(|client| {
+            let mut client = client.with_verbose(verbose);
+            if let Some(config) = &service_config {
+                client = client.with_service_config(config.clone());
+            }
+            match &lang {
+                Some(lang) => client.with_language(lang),
+                None => client,
+            }
+        })
+    };
+
+    let queue_if_offline =
+        |status, key, mutation| queue::push_if_offline(queue.as_deref(), status, key, mutation);
+
+    match command {
+        Command::Create(args) if args.dry_run => {
+            dry_run::create(&mut connect()?, args.to_student()).await?;
+        }
+        Command::Create(args) => {
+            // Queued under the key it was tried with, in case it got through
+            let key = queue::key();
+            let student = args.to_student();
+            match connect()?
+                .create_student_with_key(student.clone(), &key)
+                .await
+            {
+                Ok(student) => show("✅ Created student:", &student)?,
+                Err(status) => queue_if_offline(status, key, Mutation::Create { student })?,
+            }
+        }
+        Command::Get { id } => {
+            let student = connect()?.get_student(&id).await?;
+            show("", &student)?;
+        }
+        Command::Update(args) => {
+            let mut client = connect()?;
+            let current = match client.get_student(&args.id).await {
+                Ok(current) => current,
+                Err(status) if !args.dry_run => {
+                    let mutation = Mutation::Update {
+                        id: args.id.clone(),
+                        changes: args,
+                    };
+                    return queue_if_offline(status, queue::key(), mutation);
+                }
+                Err(status) => return Err(status.into()),
+            };
+            let updated = args.apply(current.clone());
+            if args.dry_run {
+                return dry_run::update(&mut client, &current, updated).await;
+            }
+            match client.update_student(updated).await {
+                Ok(student) => show("✅ Updated student:", &student)?,
+                Err(status) => {
+                    let mutation = Mutation::Update {
+                        id: args.id.clone(),
+                        changes: args,
+                    };
+                    queue_if_offline(status, queue::key(), mutation)?
+                }
+            }
+        }
+        Command::Delete { id, dry_run: true } => {
+            dry_run::delete(&mut connect()?, &id).await?;
+        }
+        Command::Delete { id, .. } => match connect()?.delete_student(&id).await {
+            Ok(response) => {
+                println!("✅ {}", response.message);
+                if !response.operation_id.is_empty() {
+                    println!("↩️  Undo with: student undo {}", response.operation_id);
+                }
+            }
+            Err(status) => queue_if_offline(status, queue::key(), Mutation::Delete { id })?,
+        },
+        Command::Undo { operation_id } => {
+            let channel = endpoint()?.connect_lazy();
+            let student = TrashServiceClient::new(channel)
+                .undo(UndoRequest { operation_id })
+                .await?
+                .into_inner()
+                .student
+                .unwrap_or_default();
+            show("♻️  Restored student:", &student)?;
+        }
+        Command::List {
+            page_size,
+            order_by,
+            country,
+            region,
+        } => {
+            let mut students = Box::pin(connect()?.list_all_with(ListStudentsRequest {
+                page_size,
+                order_by,
+                country: country.unwrap_or_default(),
+                region: region.unwrap_or_default(),
+                ..Default::default()
+            }));
+            let mut count = 0;
+            while let Some(student) = students.next().await {
+                let student = student?;
+                count += 1;
+                if json {
+                    println!("{}", serde_json::to_string(&student)?);
+                } else {
+                    println!(
+                        "{:<36}  {:<24}  {:<28}  {}",
+                        student.id,
+                        student.name,
+                        student.major,
+                        gpa(&student)
+                    );
+                }
+            }
+            if !json {
+                println!("({} students)", count);
+            }
+        }
+        Command::Import {
+            file,
+            file_flag,
+            sheet,
+            map,
+            dry_run,
+        } => {
+            let file = file.or(file_flag).expect("clap requires a file");
+            let layout = sheet::Layout { sheet, map };
+            let summary = if dry_run {
+                bulk::check(&mut connect()?, &file, &layout).await?
+            } else {
+                let channel = endpoint()?.connect_lazy();
+                bulk::import(&mut BulkServiceClient::new(channel), &file, &layout).await?
+            };
+            let verb = if dry_run { "validated" } else { "imported" };
+            summary.print(verb);
+            if summary.has_failures() {
+                return Err(format!("some records could not be {}", verb).into());
+            }
+        }
+        Command::Export { file } => {
+            let summary = bulk::export(&mut connect()?, &file).await?;
+            summary.print("exported");
+            if summary.has_failures() {
+                return Err("some records failed to export".into());
+            }
+        }
+        Command::Backup { file } => {
+            let summary = bulk::backup(&mut connect()?, &file).await?;
+            summary.print("backed up");
+            if summary.has_failures() {
+                return Err("some records failed to back up".into());
+            }
+        }
+        Command::Transcript { id, output, format } => {
+            let channel = endpoint()?.connect_lazy();
+            let mut chunks = EnrollmentServiceClient::new(channel)
+                .generate_transcript(GenerateTranscriptRequest {
+                    student_id: id,
+                    format: TranscriptFormat::from(format).into(),
+                })
+                .await?
+                .into_inner();
+            let mut file = std::fs::File::create(&output)?;
+            let mut size = 0;
+            while let Some(chunk) = chunks.message().await? {
+                file.write_all(&chunk.data)?;
+                size += chunk.data.len();
+            }
+            println!(
+                "✅ Saved transcript to {} ({} bytes)",
+                output.display(),
+                size
+            );
+        }
+        Command::Call { method, data } => {
+            call::call(endpoint()?, &method, &data, lang.as_deref()).await?;
+        }
+        Command::Sync { list } => {
+            let path = queue.as_deref().ok_or("sync needs --queue FILE")?;
+            match list {
+                true => queue::list(path)?,
+                false => queue::sync(&mut connect()?, path).await?,
+            }
+        }
+        Command::Tui => tui::run(connect()?).await?,
+        Command::Loadgen(args) => loadgen::run(connect()?, args, json).await?,
+        Command::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "student", &mut io::stdout());
+        }
+        Command::Man => {
+            clap_mangen::Man::new(Cli::command()).render(&mut io::stdout())?;
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run(Cli::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            match e.downcast_ref::<Status>() {
+                Some(status) => eprintln!("❌ {}", client::Error::from(status.clone())),
+                None => eprintln!("❌ {}", e),
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,468 @@
+//! Creates, updates, and deletes made while the server was unreachable,
+//! kept in a local file until `student sync` sends them.
+//!
+//! The file has one JSON object per line, oldest first, each with an
+//! idempotency key of its own. `sync` sends them in order under their keys,
+//! so a sync that is cut short and run again writes nothing twice. An
+//! update is read against the student as it is when synced, then kept in
+//! its resolved form, guarded by the etag it was read with, until sent.
+
+use crate::UpdateArgs;
+use client::{ErrorKind, StudentClient};
+use proto::batch_write_entry::Write;
+use proto::{BatchWriteEntry, Student};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+use tonic::Status;
+
+/// One mutation waiting to be sent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Queued {
+    /// Sent with every attempt, so the server applies it once
+    pub key: String,
+    #[serde(flatten)]
+    pub mutation: Mutation,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Mutation {
+    Create {
+        student: Student,
+    },
+    /// The changes asked for, to apply to the student once it can be read
+    Update {
+        id: String,
+        changes: UpdateArgs,
+    },
+    /// An update applied to the student as it was read, with its etag
+    Write {
+        student: Student,
+    },
+    Delete {
+        id: String,
+    },
+}
+
+impl Mutation {
+    fn describe(&self) -> String {
+        match self {
+            Mutation::Create { student } => format!("create {}", student.name),
+            Mutation::Update { id, .. } => format!("update {}", id),
+            Mutation::Write { student } => format!("update {}", student.id),
+            Mutation::Delete { id } => format!("delete {}", id),
+        }
+    }
+}
+
+/// A fresh idempotency key for a mutation that may be queued.
+pub fn key() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// Queue `mutation`, which failed with `status`, under `key` if there is a
+/// `queue` and the server could not be reached; otherwise fail with
+/// `status`.
+pub fn push_if_offline(
+    queue: Option<&Path>,
+    status: Status,
+    key: String,
+    mutation: Mutation,
+) -> Result<(), Box<dyn Error>> {
+    match queue {
+        Some(path) if offline(&status) => push(path, key, mutation),
+        _ => Err(status.into()),
+    }
+}
+
+// Whether a call that failed with `status` could not reach the server
+fn offline(status: &Status) -> bool {
+    client::Error::from(status.clone()).kind() == &ErrorKind::Unavailable
+}
+
+fn push(path: &Path, key: String, mutation: Mutation) -> Result<(), Box<dyn Error>> {
+    let queued = Queued { key, mutation };
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    writeln!(file, "{}", serde_json::to_string(&queued)?)?;
+    println!(
+        "📥 Server unreachable; queued {} ({} waiting, send with `student sync`)",
+        queued.mutation.describe(),
+        load(path)?.len()
+    );
+    Ok(())
+}
+
+/// Print what is waiting in the queue at `path`.
+pub fn list(path: &Path) -> Result<(), Box<dyn Error>> {
+    let pending = load(path)?;
+    for (index, queued) in pending.iter().enumerate() {
+        println!("   {}. {}", index + 1, queued.mutation.describe());
+    }
+    println!("({} queued)", pending.len());
+    Ok(())
+}
+
+/// Send everything queued at `path`, oldest first, and remove what was
+/// sent. A mutation the server rejects is moved to `<path>.rejected`;
+/// if the server cannot be reached, the rest stay queued.
+pub async fn sync(client: &mut StudentClient, path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut pending = load(path)?;
+    let (mut sent, mut rejected) = (0, 0);
+    while !pending.is_empty() {
+        let outcome = match resolve(client, &pending[0]).await {
+            Ok(resolved) => {
+                // Resolved once, so a sync cut short sends the same write again
+                pending[0] = resolved;
+                save(path, &pending)?;
+                send(client, &pending[0]).await
+            }
+            Err(status) => Err(status),
+        };
+        let queued = &pending[0];
+        match outcome {
+            Ok(()) => {
+                println!("✅ Sent {}", queued.mutation.describe());
+                sent += 1;
+            }
+            Err(status) if offline(&status) => {
+                println!("📥 {} still queued", pending.len());
+                return Err(status.into());
+            }
+            Err(status) => {
+                println!(
+                    "❌ Rejected {}: {}",
+                    queued.mutation.describe(),
+                    client::Error::from(status)
+                );
+                reject(path, queued)?;
+                rejected += 1;
+            }
+        }
+        pending.remove(0);
+        save(path, &pending)?;
+    }
+    println!("🔄 Synced: {} sent, {} rejected", sent, rejected);
+    if rejected > 0 {
+        return Err(format!(
+            "rejected mutations are in {}",
+            rejected_path(path).display()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+// `queued` with an update applied to the student as it is now
+async fn resolve(client: &mut StudentClient, queued: &Queued) -> Result<Queued, Status> {
+    let Mutation::Update { id, changes } = &queued.mutation else {
+        return Ok(queued.clone());
+    };
+    let current = client.get_student(id).await?;
+    Ok(Queued {
+        key: queued.key.clone(),
+        mutation: Mutation::Write {
+            student: changes.apply(current),
+        },
+    })
+}
+
+async fn send(client: &mut StudentClient, queued: &Queued) -> Result<(), Status> {
+    let write = match &queued.mutation {
+        // As it was first tried, so the key matches if that attempt got through
+        Mutation::Create { student } => {
+            client
+                .create_student_with_key(student.clone(), &queued.key)
+                .await?;
+            return Ok(());
+        }
+        Mutation::Write { student } => Write::Update(student.clone()),
+        Mutation::Delete { id } => Write::DeleteId(id.clone()),
+        Mutation::Update { .. } => unreachable!("updates are resolved before they are sent"),
+    };
+    let entry = BatchWriteEntry { write: Some(write) };
+    match client.batch_write_with_key(vec![entry], &queued.key).await {
+        // Deleted already, e.g. by the attempt that was queued
+        Err(status)
+            if matches!(queued.mutation, Mutation::Delete { .. })
+                && client::Error::from(status.clone()).is_not_found() =>
+        {
+            Ok(())
+        }
+        result => result.map(|_| ()),
+    }
+}
+
+fn load(path: &Path) -> Result<Vec<Queued>, Box<dyn Error>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("{}: {}", path.display(), e).into()),
+    };
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line)
+                .map_err(|e| format!("{}:{}: {}", path.display(), index + 1, e).into())
+        })
+        .collect()
+}
+
+// Replaced whole, so the queue is never left half written
+fn save(path: &Path, pending: &[Queued]) -> Result<(), Box<dyn Error>> {
+    let mut text = String::new();
+    for queued in pending {
+        text.push_str(&serde_json::to_string(queued)?);
+        text.push('\n');
+    }
+    let partial = path.with_extension("partial");
+    fs::write(&partial, text)?;
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+fn reject(path: &Path, queued: &Queued) -> Result<(), Box<dyn Error>> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(rejected_path(path))?;
+    writeln!(file, "{}", serde_json::to_string(queued)?)?;
+    Ok(())
+}
+
+fn rejected_path(path: &Path) -> PathBuf {
+    let mut rejected = path.as_os_str().to_owned();
+    rejected.push(".rejected");
+    PathBuf::from(rejected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use proto::student_service_server::StudentServiceServer;
+    use server::repository::{
+        InMemoryRepository, Remembered, StudentRepository, Transaction, TransactionFailure,
+    };
+    use server::StudentServiceImpl;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::Server;
+    use tonic::Code;
+
+    // A store whose batch writes, while `lose` is set, are applied but
+    // answered with UNAVAILABLE, as if the connection dropped on the way back
+    #[derive(Debug, Default)]
+    struct Lossy {
+        inner: InMemoryRepository,
+        lose: AtomicBool,
+    }
+
+    #[tonic::async_trait]
+    impl StudentRepository for Lossy {
+        async fn create(&self, student: Student) -> Result<Student, Status> {
+            self.inner.create(student).await
+        }
+
+        async fn get(&self, id: &str) -> Result<Student, Status> {
+            self.inner.get(id).await
+        }
+
+        async fn update(&self, student: Student) -> Result<Student, Status> {
+            self.inner.update(student).await
+        }
+
+        async fn delete(&self, id: &str) -> Result<Student, Status> {
+            self.inner.delete(id).await
+        }
+
+        async fn list(
+            &self,
+            page_size: usize,
+            page_token: &str,
+        ) -> Result<proto::ListStudentsResponse, Status> {
+            self.inner.list(page_size, page_token).await
+        }
+
+        async fn commit(
+            &self,
+            transaction: Transaction,
+        ) -> Result<Vec<Student>, TransactionFailure> {
+            let written = self.inner.commit(transaction).await?;
+            if self.lose.load(Ordering::SeqCst) {
+                return Err(TransactionFailure {
+                    index: None,
+                    status: Status::unavailable("connection lost"),
+                });
+            }
+            Ok(written)
+        }
+
+        async fn remembered(&self, key: &str) -> Result<Option<Remembered>, Status> {
+            self.inner.remembered(key).await
+        }
+    }
+
+    async fn start() -> (StudentClient, Arc<Lossy>) {
+        let store = Arc::new(Lossy::default());
+        let service = StudentServiceImpl::new().with_repository(store.clone());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(StudentServiceServer::new(service))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        let client = StudentClient::connect_lazy(format!("http://{}", addr)).unwrap();
+        (client, store)
+    }
+
+    // An empty queue in the temp directory, unique to the test
+    fn queue(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("queue-{}-{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(rejected_path(&path));
+        path
+    }
+
+    fn student(id: &str) -> Student {
+        Student {
+            id: id.to_string(),
+            name: format!("Student {}", id),
+            email: format!("{}@university.edu", id),
+            age: 20,
+            major: "Math".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn update(id: &str, flags: &[&str]) -> Mutation {
+        #[derive(Parser)]
+        struct Update {
+            #[command(flatten)]
+            changes: UpdateArgs,
+        }
+        let args = ["update", id].into_iter().chain(flags.iter().copied());
+        Mutation::Update {
+            id: id.to_string(),
+            changes: Update::parse_from(args).changes,
+        }
+    }
+
+    #[tokio::test]
+    async fn updates_are_saved_resolved_and_sent_once() {
+        let (mut client, store) = start().await;
+        let original = client.create_student(student("s1")).await.unwrap();
+        let path = queue("updates");
+        push(&path, key(), update("s1", &["--major", "Physics"])).unwrap();
+        let key = load(&path).unwrap()[0].key.clone();
+
+        // Applied, but the answer never comes back
+        store.lose.store(true, Ordering::SeqCst);
+        sync(&mut client, &path).await.unwrap_err();
+        let pending = load(&path).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].key, key);
+        let Mutation::Write { student } = &pending[0].mutation else {
+            panic!("not resolved: {:?}", pending[0]);
+        };
+        // Guarded by the etag it was read with
+        assert_eq!(student.major, "Physics");
+        assert_eq!(student.etag, original.etag);
+
+        // Sent again under its key, it is not refused for its old etag
+        store.lose.store(false, Ordering::SeqCst);
+        sync(&mut client, &path).await.unwrap();
+        assert!(load(&path).unwrap().is_empty());
+        assert!(!rejected_path(&path).exists());
+        let stored = client.get_student("s1").await.unwrap();
+        assert_eq!(stored.major, "Physics");
+        assert_ne!(stored.etag, original.etag);
+    }
+
+    #[tokio::test]
+    async fn deletes_of_students_already_gone_succeed() {
+        let (mut client, _) = start().await;
+        client.create_student(student("s1")).await.unwrap();
+        let path = queue("deletes");
+        for id in ["s1", "s1", "s2"] {
+            push(&path, key(), Mutation::Delete { id: id.to_string() }).unwrap();
+        }
+        sync(&mut client, &path).await.unwrap();
+        assert!(load(&path).unwrap().is_empty());
+        assert!(!rejected_path(&path).exists());
+        let status = client.get_student("s1").await.unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn rejected_mutations_are_set_aside() {
+        let (mut client, _) = start().await;
+        client.create_student(student("s1")).await.unwrap();
+        let path = queue("rejected");
+        let taken = Mutation::Create {
+            student: student("s1"),
+        };
+        push(&path, key(), taken).unwrap();
+        push(&path, key(), update("s9", &["--major", "Physics"])).unwrap();
+        let fresh = Mutation::Create {
+            student: student("s2"),
+        };
+        push(&path, key(), fresh).unwrap();
+
+        let error = sync(&mut client, &path).await.unwrap_err();
+        assert!(error.to_string().contains(".rejected"), "{}", error);
+        // The rest were sent, and the queue is empty
+        assert!(load(&path).unwrap().is_empty());
+        client.get_student("s2").await.unwrap();
+        let rejected: Vec<String> = load(&rejected_path(&path))
+            .unwrap()
+            .iter()
+            .map(|queued| queued.mutation.describe())
+            .collect();
+        assert_eq!(rejected, ["create Student s1", "update s9"]);
+    }
+
+    #[tokio::test]
+    async fn the_queue_stays_while_the_server_is_unreachable() {
+        // A port no one is listening on
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut client = StudentClient::connect_lazy(format!("http://{}", addr)).unwrap();
+        let path = queue("unreachable");
+        for id in ["s1", "s2"] {
+            let create = Mutation::Create {
+                student: student(id),
+            };
+            push(&path, key(), create).unwrap();
+        }
+        let before = fs::read_to_string(&path).unwrap();
+
+        sync(&mut client, &path).await.unwrap_err();
+        assert_eq!(fs::read_to_string(&path).unwrap(), before);
+        assert!(!rejected_path(&path).exists());
+
+        // Once it is up, they all go
+        let listener = TcpListener::bind(addr).await.unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(StudentServiceServer::new(StudentServiceImpl::new()))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        sync(&mut client, &path).await.unwrap();
+        assert!(load(&path).unwrap().is_empty());
+        client.get_student("s2").await.unwrap();
+    }
+}
@@ -0,0 +1,394 @@
+//! Reading students from a CSV file or a spreadsheet: finding the header
+//! row and the column that holds each field.
+
+use calamine::{open_workbook_auto, Data, Reader};
+use prost_reflect::DescriptorPool;
+use proto::Student;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+// Rows searched for the header, for the titles registrars put above it
+const HEADER_SEARCH_ROWS: usize = 10;
+
+/// Where a field is read from, as given to `--map`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Column {
+    /// A spreadsheet column letter, given as `col:B`, as its position from
+    /// zero.
+    Letter(usize),
+    /// The title of the column in the header row; text such as `B` that no
+    /// header cell matches is read as a column letter instead.
+    Header(String),
+}
+
+/// Which sheet of a workbook to read, and where each field is; fields not
+/// mapped come from the columns whose header names them.
+#[derive(Debug, Default)]
+pub struct Layout {
+    pub sheet: Option<String>,
+    pub map: Vec<(String, Column)>,
+}
+
+/// A student read from row `row` (counted from 1, as a spreadsheet does),
+/// or why it could not be read.
+pub type Row = (usize, Result<Student, String>);
+
+// The snake_case and JSON names of each student field
+fn field_names() -> Vec<(String, String)> {
+    let pool = DescriptorPool::decode(proto::FILE_DESCRIPTOR_SET)
+        .expect("the compiled descriptor set decodes");
+    let student = pool
+        .get_message_by_name("student.Student")
+        .expect("the descriptor set has Student");
+    student
+        .fields()
+        .map(|field| (field.name().to_string(), field.json_name().to_string()))
+        .collect()
+}
+
+// The field a header cell names, forgiving case, spaces, and hyphens, e.g.
+// "Preferred Name" for `preferred_name`
+fn field_named(title: &str, fields: &[(String, String)]) -> Option<String> {
+    let title = title.trim().to_lowercase().replace([' ', '-'], "_");
+    fields
+        .iter()
+        .find(|(name, json_name)| title == *name || title == json_name.to_lowercase())
+        .map(|(name, _)| name.clone())
+}
+
+// `B` → 1, `AA` → 26; None unless the text is one to three capital letters
+fn column_index(letters: &str) -> Option<usize> {
+    if letters.is_empty() || letters.len() > 3 || !letters.bytes().all(|b| b.is_ascii_uppercase()) {
+        return None;
+    }
+    let number = letters
+        .bytes()
+        .fold(0, |number, b| number * 26 + usize::from(b - b'A' + 1));
+    Some(number - 1)
+}
+
+/// Parse a `--map` entry, `FIELD=COLUMN`, where the column is the text of
+/// its header or a letter, written `col:B` to rule out a header named `B`.
+pub fn parse_mapping(text: &str) -> Result<(String, Column), String> {
+    let (field, column) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected FIELD=COLUMN, e.g. name=B, not `{}`", text))?;
+    let field = field_named(field, &field_names())
+        .ok_or_else(|| format!("students have no field `{}`", field.trim()))?;
+    let column = column.trim();
+    let column =
+        match column.strip_prefix("col:") {
+            Some(letters) => Column::Letter(column_index(letters.trim()).ok_or_else(|| {
+                format!("`{}` is not a column letter, e.g. col:B", letters.trim())
+            })?),
+            None if column.is_empty() => return Err(format!("no column given for {}", field)),
+            None => Column::Header(column.to_string()),
+        };
+    Ok((field, column))
+}
+
+// The position of the column headed `title` in the header row, or else of
+// the column `title` names by letter
+fn column_position(title: &str, header: Option<&[String]>) -> Option<usize> {
+    header
+        .and_then(|header| {
+            header
+                .iter()
+                .position(|cell| cell.eq_ignore_ascii_case(title))
+        })
+        .or_else(|| column_index(title))
+}
+
+fn cell_text(cell: &Data) -> String {
+    match cell {
+        Data::Empty => String::new(),
+        Data::String(text) => text.trim().to_string(),
+        // Whole numbers, e.g. ages, are stored as floats; Display drops the `.0`
+        Data::Float(number) => number.to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn is_spreadsheet(path: &Path) -> bool {
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    matches!(
+        extension.map(str::to_lowercase).as_deref(),
+        Some("xlsx" | "xlsm" | "xlsb" | "xls" | "ods")
+    )
+}
+
+// Every row of the sheet, including blank ones, so positions match the
+// sheet's own row numbers and column letters
+fn read_sheet(path: &Path, sheet: Option<&str>) -> Result<Vec<Vec<String>>, String> {
+    let mut workbook = open_workbook_auto(path).map_err(|e| e.to_string())?;
+    let names = workbook.sheet_names();
+    let name = match sheet {
+        Some(sheet) if names.iter().any(|name| name == sheet) => sheet.to_string(),
+        Some(sheet) => {
+            return Err(format!(
+                "no sheet named `{}`; the workbook has {}",
+                sheet,
+                names.join(", ")
+            ))
+        }
+        None => names.first().cloned().ok_or("the workbook has no sheets")?,
+    };
+    let range = workbook.worksheet_range(&name).map_err(|e| e.to_string())?;
+    let (first_row, first_column) = range.start().unwrap_or_default();
+    let mut grid = vec![Vec::new(); first_row as usize];
+    for row in range.rows() {
+        let blanks = std::iter::repeat_n(String::new(), first_column as usize);
+        grid.push(blanks.chain(row.iter().map(cell_text)).collect());
+    }
+    Ok(grid)
+}
+
+fn read_csv(path: &Path) -> Result<Vec<Vec<String>>, csv::Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(path)?;
+    reader
+        .records()
+        .map(|record| Ok(record?.iter().map(str::to_string).collect()))
+        .collect()
+}
+
+// The generated JSON mapping can't read cells by column directly, so each row
+// goes through a JSON object: empty cells count as missing fields, and the
+// mapping accepts numbers written as strings.
+fn parse_row(record: HashMap<String, String>) -> Result<Student, String> {
+    let fields: serde_json::Map<String, Value> = record
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(field, value)| (field, Value::String(value)))
+        .collect();
+    serde_json::from_value(Value::Object(fields.clone())).map_err(|e| {
+        // serde's message doesn't always say which cell was wrong
+        fields
+            .into_iter()
+            .find_map(|(field, value)| {
+                let alone = serde_json::Map::from_iter([(field.clone(), value.clone())]);
+                let e = serde_json::from_value::<Student>(Value::Object(alone)).err()?;
+                Some(format!("{} {}: {}", field, value, e))
+            })
+            .unwrap_or_else(|| e.to_string())
+    })
+}
+
+// The position of the header row: the first that names a student field, or
+// every header in `--map` that couldn't instead be a column letter
+fn find_header(
+    grid: &[Vec<String>],
+    map: &[(String, Column)],
+    fields: &[(String, String)],
+) -> Option<usize> {
+    let titles: Vec<&str> = map
+        .iter()
+        .filter_map(|(_, column)| match column {
+            Column::Header(title) if column_index(title).is_none() => Some(title.as_str()),
+            Column::Header(_) | Column::Letter(_) => None,
+        })
+        .collect();
+    grid.iter().take(HEADER_SEARCH_ROWS).position(|row| {
+        if titles.is_empty() {
+            row.iter().any(|cell| field_named(cell, fields).is_some())
+        } else {
+            titles
+                .iter()
+                .all(|title| row.iter().any(|cell| cell.eq_ignore_ascii_case(title)))
+        }
+    })
+}
+
+/// Every student in the CSV file or spreadsheet at `path`, by row.
+///
+/// The header is the first of the top rows to name a student field (or all
+/// the headers in `layout.map`); rows above it and blank rows are skipped.
+/// Without a header, `layout.map` must place every field by letter.
+pub fn read(path: &Path, layout: &Layout) -> Result<Vec<Row>, Box<dyn std::error::Error>> {
+    let grid = if is_spreadsheet(path) {
+        read_sheet(path, layout.sheet.as_deref())?
+    } else if layout.sheet.is_some() {
+        return Err("--sheet only applies to spreadsheets".into());
+    } else {
+        read_csv(path)?
+    };
+    let fields = field_names();
+    let header = find_header(&grid, &layout.map, &fields);
+
+    // (position, key in the JSON object) of each column read
+    let mut columns = Vec::new();
+    for (field, column) in &layout.map {
+        let index = match column {
+            Column::Letter(index) => *index,
+            Column::Header(title) => {
+                column_position(title, header.map(|header| grid[header].as_slice()))
+                    .ok_or_else(|| format!("no column headed `{}`", title))?
+            }
+        };
+        columns.push((index, field.clone()));
+    }
+    if let Some(header) = header {
+        for (index, cell) in grid[header].iter().enumerate() {
+            if cell.is_empty() || columns.iter().any(|(mapped, _)| *mapped == index) {
+                continue;
+            }
+            // Without --map, unknown headers are kept for each row to fail on
+            let key = match field_named(cell, &fields) {
+                Some(field) => field,
+                None if layout.map.is_empty() => cell.clone(),
+                None => continue,
+            };
+            if !columns.iter().any(|(_, field)| *field == key) {
+                columns.push((index, key));
+            }
+        }
+    }
+    if columns.is_empty() {
+        return Err(format!(
+            "{}: found no header naming student fields; say where they are with --map, e.g. name=col:B,email=col:C",
+            path.display()
+        )
+        .into());
+    }
+
+    let first = header.map_or(0, |header| header + 1);
+    Ok(grid
+        .iter()
+        .enumerate()
+        .skip(first)
+        .filter(|(_, row)| row.iter().any(|cell| !cell.is_empty()))
+        .map(|(index, row)| {
+            let record = columns
+                .iter()
+                .map(|(column, key)| (key.clone(), row.get(*column).cloned().unwrap_or_default()))
+                .collect();
+            (index + 1, parse_row(record))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // A CSV file in the temp directory, unique to the test
+    fn csv_file(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("sheet-{}-{}.csv", std::process::id(), name));
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn layout(map: &[&str]) -> Layout {
+        Layout {
+            sheet: None,
+            map: map
+                .iter()
+                .map(|text| parse_mapping(text).unwrap())
+                .collect(),
+        }
+    }
+
+    fn students(rows: Vec<Row>) -> Vec<(usize, Student)> {
+        rows.into_iter()
+            .map(|(row, student)| (row, student.unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn mappings_name_headers_unless_given_as_letters() {
+        assert_eq!(
+            parse_mapping("name=B"),
+            Ok(("name".to_string(), Column::Header("B".to_string())))
+        );
+        assert_eq!(
+            parse_mapping("Preferred Name=E-mail Address"),
+            Ok((
+                "preferred_name".to_string(),
+                Column::Header("E-mail Address".to_string())
+            ))
+        );
+        assert_eq!(
+            parse_mapping("name=col:B"),
+            Ok(("name".to_string(), Column::Letter(1)))
+        );
+        assert_eq!(
+            parse_mapping("email= col:AA "),
+            Ok(("email".to_string(), Column::Letter(26)))
+        );
+        for bad in ["name", "name=", "name=col:b", "name=col:B2", "nickname=B"] {
+            assert!(parse_mapping(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn headers_are_found_below_titles() {
+        let path = csv_file(
+            "titles",
+            "Fall roster,,\n,,\nName,Preferred Name,E-mail\nAda,Addie,ada@example.com\n,,\nGrace,,grace@example.com\n",
+        );
+        let rows = students(read(&path, &layout(&["email=E-mail"])).unwrap());
+        assert_eq!(
+            rows.iter()
+                .map(|(row, student)| (*row, student.name.as_str(), student.email.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (4, "Ada", "ada@example.com"),
+                (6, "Grace", "grace@example.com")
+            ]
+        );
+        assert_eq!(rows[0].1.preferred_name, "Addie");
+    }
+
+    #[test]
+    fn short_capital_headers_are_read_as_headers() {
+        // "ID" is also a column letter, far to the right of these
+        let path = csv_file("capitals", "Name,ID,GPA\nAda,s1,3.9\n");
+        let rows = students(read(&path, &layout(&["id=ID", "name=A"])).unwrap());
+        assert_eq!(rows[0].1.id, "s1");
+        assert_eq!(rows[0].1.name, "Ada");
+
+        // Text no header matches falls back to the letter
+        let rows = students(read(&path, &layout(&["major=B"])).unwrap());
+        assert_eq!(rows[0].1.major, "s1");
+    }
+
+    #[test]
+    fn sheets_without_headers_are_read_by_letter() {
+        let path = csv_file("letters", "Ada,ada@example.com\nGrace,grace@example.com\n");
+        let rows = students(read(&path, &layout(&["name=col:A", "email=col:B"])).unwrap());
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            rows[1],
+            (
+                2,
+                Student {
+                    name: "Grace".to_string(),
+                    email: "grace@example.com".to_string(),
+                    ..Default::default()
+                }
+            )
+        );
+
+        assert!(read(&path, &layout(&[])).is_err());
+    }
+
+    #[test]
+    fn bad_cells_fail_only_their_row() {
+        let path = csv_file(
+            "errors",
+            "name,age,nickname\nAda,36,\nGrace,twenty,\nAlan,41,Al\n",
+        );
+        let rows = read(&path, &layout(&[])).unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].1.as_ref().unwrap().age, 36);
+        let (row, error) = (&rows[1].0, rows[1].1.as_ref().unwrap_err());
+        assert_eq!(*row, 3);
+        assert!(error.starts_with("age \"twenty\""), "{}", error);
+        // Without --map, a header naming no field fails the rows that fill it
+        assert!(rows[2].1.as_ref().unwrap_err().contains("nickname"));
+    }
+}
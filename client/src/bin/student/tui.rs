@@ -0,0 +1,468 @@
+//! `student tui`: a table of students that follows `WatchStudents`, with
+//! forms to create, edit, and delete them from the keyboard.
+//!
+//! The watch is opened before the students are listed, so a change made
+//! while the list is read still reaches the table. If the watch ends, e.g.
+//! when the server restarts, the table says so; `r` reads everything again
+//! and watches anew.
+
+use crate::{gpa, standing_name};
+use client::StudentClient;
+use futures::channel::mpsc;
+use futures::StreamExt;
+use proto::{ChangeType, Student, StudentEvent};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Clear, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::BTreeMap;
+use std::error::Error;
+use tonic::{Status, Streaming};
+
+const HELP: &str = "↑/↓ move  c create  e edit  d delete  r reload  q quit";
+
+/// Run the TUI until `q` is pressed.
+pub async fn run(client: StudentClient) -> Result<(), Box<dyn Error>> {
+    let mut app = App::new(client);
+    app.reload().await?;
+
+    // crossterm reads keys by blocking, so a thread forwards them
+    let (keys, mut events) = mpsc::unbounded();
+    std::thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if keys.unbounded_send(event).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal, &mut events).await;
+    ratatui::restore();
+    result
+}
+
+struct App {
+    client: StudentClient,
+    /// By ID, the order of the table
+    students: BTreeMap<String, Student>,
+    table: TableState,
+    watch: Option<Streaming<StudentEvent>>,
+    mode: Mode,
+    /// What happened last, shown under the table
+    message: String,
+    quit: bool,
+}
+
+enum Next {
+    Terminal(Option<Event>),
+    Watched(Box<Result<Option<StudentEvent>, Status>>),
+}
+
+enum Mode {
+    Browse,
+    Form(Box<Form>),
+    /// Waiting for `y` to delete the student with this ID
+    Delete(String),
+}
+
+impl App {
+    fn new(client: StudentClient) -> Self {
+        Self {
+            client,
+            students: BTreeMap::new(),
+            table: TableState::default().with_selected(0),
+            watch: None,
+            mode: Mode::Browse,
+            message: String::new(),
+            quit: false,
+        }
+    }
+
+    async fn run(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        events: &mut mpsc::UnboundedReceiver<Event>,
+    ) -> Result<(), Box<dyn Error>> {
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+            let watched = async {
+                match &mut self.watch {
+                    Some(watch) => watch.message().await,
+                    None => std::future::pending().await,
+                }
+            };
+            let next = tokio::select! {
+                event = events.next() => Next::Terminal(event),
+                watched = watched => Next::Watched(Box::new(watched)),
+            };
+            match next {
+                Next::Terminal(Some(Event::Key(key))) if key.kind == KeyEventKind::Press => {
+                    self.key(key).await
+                }
+                Next::Terminal(Some(_)) => {}
+                Next::Terminal(None) => return Err("the terminal closed".into()),
+                Next::Watched(watched) => self.watched(*watched),
+            }
+        }
+        Ok(())
+    }
+
+    // Read every student again, watching from before the first is read
+    async fn reload(&mut self) -> Result<(), Status> {
+        let watch = self.client.watch_students("").await?;
+        let mut students = BTreeMap::new();
+        let mut pages = Box::pin(self.client.list_all(client::DEFAULT_PAGE_SIZE));
+        while let Some(student) = pages.next().await {
+            let student = student?;
+            students.insert(student.id.clone(), student);
+        }
+        self.students = students;
+        self.watch = Some(watch);
+        self.select(self.table.selected().unwrap_or(0));
+        self.message = format!("🔄 Loaded {} students", self.students.len());
+        Ok(())
+    }
+
+    fn watched(&mut self, watched: Result<Option<StudentEvent>, Status>) {
+        let event = match watched {
+            Ok(Some(event)) => event,
+            Ok(None) => return self.unwatched("the server ended the watch"),
+            Err(status) => return self.unwatched(&client::Error::from(status).to_string()),
+        };
+        let student = event.student.clone().unwrap_or_default();
+        match event.change_type() {
+            ChangeType::Created | ChangeType::Updated => self.upsert(student),
+            ChangeType::Deleted => self.remove(&student.id),
+            ChangeType::ShuttingDown => self.unwatched("the server is shutting down"),
+            ChangeType::Unspecified => {}
+        }
+    }
+
+    fn unwatched(&mut self, why: &str) {
+        self.watch = None;
+        self.message = format!("⚠️  No longer live: {}; press r to reload", why);
+    }
+
+    fn upsert(&mut self, student: Student) {
+        self.students.insert(student.id.clone(), student);
+    }
+
+    fn remove(&mut self, id: &str) {
+        self.students.remove(id);
+        self.select(self.table.selected().unwrap_or(0));
+    }
+
+    fn select(&mut self, index: usize) {
+        let last = self.students.len().saturating_sub(1);
+        self.table.select(Some(index.min(last)));
+    }
+
+    fn selected(&self) -> Option<&Student> {
+        self.students.values().nth(self.table.selected()?)
+    }
+
+    async fn key(&mut self, key: KeyEvent) {
+        match std::mem::replace(&mut self.mode, Mode::Browse) {
+            Mode::Browse => self.browse(key).await,
+            Mode::Form(form) => self.fill(form, key).await,
+            Mode::Delete(id) if key.code == KeyCode::Char('y') => self.delete(&id).await,
+            Mode::Delete(_) => self.message = "Not deleted".to_string(),
+        }
+    }
+
+    async fn browse(&mut self, key: KeyEvent) {
+        let index = self.table.selected().unwrap_or(0);
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Down | KeyCode::Char('j') => self.select(index + 1),
+            KeyCode::Up | KeyCode::Char('k') => self.select(index.saturating_sub(1)),
+            KeyCode::Home => self.select(0),
+            KeyCode::End => self.select(usize::MAX),
+            KeyCode::Char('c') => self.mode = Mode::Form(Box::new(Form::create())),
+            KeyCode::Char('e') | KeyCode::Enter => {
+                if let Some(student) = self.selected() {
+                    self.mode = Mode::Form(Box::new(Form::edit(student.clone())));
+                }
+            }
+            KeyCode::Char('d') | KeyCode::Delete => {
+                if let Some(student) = self.selected().cloned() {
+                    self.message = format!("Delete {} ({})? y/n", student.name, student.id);
+                    self.mode = Mode::Delete(student.id);
+                }
+            }
+            KeyCode::Char('r') => {
+                if let Err(status) = self.reload().await {
+                    self.unwatched(&client::Error::from(status).to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    async fn fill(&mut self, mut form: Box<Form>, key: KeyEvent) {
+        let count = form.fields.len();
+        match key.code {
+            KeyCode::Esc => {
+                self.message = "Cancelled".to_string();
+                return;
+            }
+            KeyCode::Enter => return self.submit(form).await,
+            KeyCode::Tab | KeyCode::Down => form.focus = (form.focus + 1) % count,
+            KeyCode::BackTab | KeyCode::Up => form.focus = (form.focus + count - 1) % count,
+            KeyCode::Backspace => {
+                form.fields[form.focus].value.pop();
+            }
+            KeyCode::Char(c) => form.fields[form.focus].value.push(c),
+            _ => {}
+        }
+        self.mode = Mode::Form(form);
+    }
+
+    // Send the form; if the server refuses it, keep it open with the reasons
+    async fn submit(&mut self, mut form: Box<Form>) {
+        let student = match form.student() {
+            Ok(student) => student,
+            Err(problem) => {
+                self.message = format!("❌ {}", problem);
+                self.mode = Mode::Form(form);
+                return;
+            }
+        };
+        let (result, verb) = match &form.original {
+            Some(_) => (self.client.update_student(student).await, "Updated"),
+            None => (self.client.create_student(student).await, "Created"),
+        };
+        match result {
+            Ok(student) => {
+                self.message = format!("✅ {} {} ({})", verb, student.name, student.id);
+                self.upsert(student);
+            }
+            Err(status) => {
+                let error = client::Error::from(status);
+                self.message = match error.is_changed() {
+                    true => "❌ Changed by someone else meanwhile; edit it again".to_string(),
+                    false => format!("❌ {:?}: {}", error.code(), error.message()),
+                };
+                form.refused(&error);
+                self.mode = Mode::Form(form);
+            }
+        }
+    }
+
+    async fn delete(&mut self, id: &str) {
+        match self.client.delete_student(id).await {
+            Ok(response) => {
+                self.message = format!("🗑️  {}", response.message);
+                if !response.operation_id.is_empty() {
+                    self.message += &format!(" (undo: student undo {})", response.operation_id);
+                }
+                self.remove(id);
+            }
+            Err(status) => self.message = format!("❌ {}", client::Error::from(status)),
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [table, message, help] = Layout::vertical([
+            Constraint::Min(3),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let live = match self.watch {
+            Some(_) => " live ".green(),
+            None => " not live ".red(),
+        };
+        let title = Line::from(vec![
+            format!(" Students ({}) ", self.students.len()).bold(),
+            live,
+        ]);
+        let rows = self.students.values().map(|student| {
+            Row::new([
+                student.id.clone(),
+                student.name.clone(),
+                student.email.clone(),
+                student.major.clone(),
+                gpa(student),
+                student.credits.to_string(),
+                standing_name(student.standing()).to_string(),
+            ])
+        });
+        let widths = [
+            Constraint::Length(12),
+            Constraint::Fill(2),
+            Constraint::Fill(2),
+            Constraint::Fill(1),
+            Constraint::Length(5),
+            Constraint::Length(7),
+            Constraint::Length(11),
+        ];
+        let header = ["ID", "Name", "Email", "Major", "GPA", "Credits", "Standing"];
+        let students = Table::new(rows, widths)
+            .header(Row::new(header).bold())
+            .block(Block::bordered().title(title))
+            .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(students, table, &mut self.table);
+        frame.render_widget(Paragraph::new(self.message.as_str()), message);
+        frame.render_widget(Paragraph::new(HELP.dark_gray()), help);
+
+        if let Mode::Form(form) = &self.mode {
+            form.draw(frame);
+        }
+    }
+}
+
+/// A student as fields of text, to create one or edit `original`.
+struct Form {
+    original: Option<Student>,
+    fields: Vec<Field>,
+    focus: usize,
+}
+
+struct Field {
+    label: &'static str,
+    /// The names the server gives this field in `BadRequest`
+    names: &'static [&'static str],
+    value: String,
+    /// Why the server refused the value, if it did
+    problem: Option<String>,
+}
+
+impl Field {
+    fn new(label: &'static str, names: &'static [&'static str], value: String) -> Self {
+        Self {
+            label,
+            names,
+            value,
+            problem: None,
+        }
+    }
+}
+
+impl Form {
+    fn create() -> Self {
+        let mut form = Self::edit(Student::default());
+        form.original = None;
+        for field in &mut form.fields {
+            field.value.clear();
+        }
+        // The server generates one when it is left empty
+        form.fields
+            .insert(0, Field::new("ID", &["id"], String::new()));
+        form
+    }
+
+    fn edit(student: Student) -> Self {
+        let fields = vec![
+            Field::new("Name", &["name"], student.name.clone()),
+            Field::new("Email", &["email"], student.email.clone()),
+            Field::new("Age", &["age"], student.age.to_string()),
+            Field::new("Major", &["major", "major_id"], student.major.clone()),
+            Field::new("GPA", &["gpa_decimal", "gpa"], gpa(&student)),
+            Field::new("Credits", &["credits"], student.credits.to_string()),
+        ];
+        Self {
+            original: Some(student),
+            fields,
+            focus: 0,
+        }
+    }
+
+    fn value(&self, label: &str) -> &str {
+        self.fields
+            .iter()
+            .find(|field| field.label == label)
+            .map_or("", |field| field.value.trim())
+    }
+
+    // The student to send: the original, with its etag, and the fields over it
+    fn student(&self) -> Result<Student, String> {
+        let number = |label: &str| match self.value(label) {
+            "" => Ok(0),
+            value => value
+                .parse::<i32>()
+                .map_err(|_| format!("{} {:?} is not a whole number", label, value)),
+        };
+        let mut student = self.original.clone().unwrap_or_default();
+        if self.original.is_none() {
+            student.id = self.value("ID").to_string();
+        }
+        student.name = self.value("Name").to_string();
+        student.email = self.value("Email").to_string();
+        student.age = number("Age")?;
+        student.major = self.value("Major").to_string();
+        student.gpa_decimal = self.value("GPA").to_string();
+        student.credits = number("Credits")?;
+        Ok(student)
+    }
+
+    // Mark the fields the server found fault with, and focus the first
+    fn refused(&mut self, error: &client::Error) {
+        for field in &mut self.fields {
+            field.problem = error
+                .field_violations()
+                .iter()
+                .find(|violation| field.names.contains(&violation.field.as_str()))
+                .map(|violation| violation.description.clone());
+        }
+        if let Some(first) = self.fields.iter().position(|field| field.problem.is_some()) {
+            self.focus = first;
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let title = match &self.original {
+            Some(student) => format!(" Edit {} ", student.id),
+            None => " New student ".to_string(),
+        };
+        let width = 60;
+        let mut lines = Vec::new();
+        let mut cursor = (0, 0);
+        for (index, field) in self.fields.iter().enumerate() {
+            let label = format!("{:>8}: ", field.label);
+            let style = match index == self.focus {
+                true => Style::new().fg(Color::Yellow).bold(),
+                false => Style::new(),
+            };
+            if index == self.focus {
+                cursor = (
+                    (label.len() + field.value.chars().count()) as u16,
+                    lines.len() as u16,
+                );
+            }
+            lines.push(Line::from(vec![
+                Span::styled(label, style),
+                Span::raw(field.value.as_str()),
+            ]));
+            if let Some(problem) = &field.problem {
+                lines.push(Line::from(format!("{:10}{}", "", problem).red()));
+            }
+        }
+        lines.push(Line::default());
+        lines.push(Line::from("Tab next  Enter save  Esc cancel".dark_gray()));
+
+        let area = centered(frame.area(), width, lines.len() as u16 + 2);
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(title)),
+            area,
+        );
+        frame.set_cursor_position((area.x + 1 + cursor.0, area.y + 1 + cursor.1));
+    }
+}
+
+// A `width` by `height` rectangle in the middle of `area`, clipped to fit
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
@@ -0,0 +1,53 @@
+use proto::Student;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// TTL cache of `GetStudent` responses keyed by student id.
+///
+/// Entries are dropped lazily: an expired entry is removed the next time it
+/// is looked up.
+#[derive(Debug)]
+pub struct StudentCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Instant, Student)>>,
+}
+
+impl StudentCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Return a fresh cached copy of the student, if any.
+    pub fn get(&self, id: &str) -> Option<Student> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(id) {
+            Some((cached_at, student)) if cached_at.elapsed() < self.ttl => Some(student.clone()),
+            Some(_) => {
+                entries.remove(id);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, student: Student) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(student.id.clone(), (Instant::now(), student));
+    }
+
+    pub fn invalidate(&self, id: &str) {
+        self.entries.lock().unwrap().remove(id);
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
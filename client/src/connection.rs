@@ -0,0 +1,89 @@
+//! HTTP/2 and TCP settings for the client's connections.
+//!
+//! A watch stream can go quiet for longer than a NAT gateway keeps an idle
+//! connection, which is then dropped without either side knowing. HTTP/2
+//! keepalive pings, sent even while no call is open, keep the connection
+//! busy and find out when it is gone.
+
+use std::time::Duration;
+use tonic::transport::Endpoint;
+
+/// How the client's connections are made and kept; by default, as tonic
+/// makes them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionSettings {
+    /// Ping the server this often [default: never]
+    pub keepalive_interval: Option<Duration>,
+    /// Drop the connection if a ping is not answered within this long
+    /// [default: 20s]
+    pub keepalive_timeout: Option<Duration>,
+    /// Ping while no call is open, too
+    pub keepalive_while_idle: bool,
+    /// Most calls open at once; more wait for one to finish
+    /// [default: no limit]
+    pub max_concurrent_streams: Option<usize>,
+    /// Give up connecting after this long [default: never]
+    pub connect_timeout: Option<Duration>,
+    /// TCP keepalive probes after this long idle [default: off]
+    pub tcp_keepalive: Option<Duration>,
+    /// Send small frames at once instead of batching them (Nagle's
+    /// algorithm off)
+    pub tcp_nodelay: bool,
+}
+
+impl Default for ConnectionSettings {
+    fn default() -> Self {
+        Self {
+            keepalive_interval: None,
+            keepalive_timeout: None,
+            keepalive_while_idle: false,
+            max_concurrent_streams: None,
+            connect_timeout: None,
+            tcp_keepalive: None,
+            tcp_nodelay: true,
+        }
+    }
+}
+
+impl ConnectionSettings {
+    /// `endpoint` with these settings.
+    pub fn apply(&self, mut endpoint: Endpoint) -> Endpoint {
+        if let Some(interval) = self.keepalive_interval {
+            endpoint = endpoint
+                .http2_keep_alive_interval(interval)
+                .keep_alive_while_idle(self.keepalive_while_idle);
+        }
+        if let Some(timeout) = self.keepalive_timeout {
+            endpoint = endpoint.keep_alive_timeout(timeout);
+        }
+        if let Some(limit) = self.max_concurrent_streams {
+            endpoint = endpoint.concurrency_limit(limit);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            endpoint = endpoint.connect_timeout(timeout);
+        }
+        endpoint
+            .tcp_keepalive(self.tcp_keepalive)
+            .tcp_nodelay(self.tcp_nodelay)
+    }
+}
+
+/// A duration from the command line: a number of `ms`, `s`, `m`, or `h`,
+/// e.g. `30s`.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("{}: expected e.g. 30s", text))?;
+    match unit {
+        "ms" => Ok(Duration::from_millis(number)),
+        "s" => Ok(Duration::from_secs(number)),
+        "m" => Ok(Duration::from_secs(number * 60)),
+        "h" => Ok(Duration::from_secs(number * 60 * 60)),
+        _ => Err(format!("{}: expected e.g. 30s", text)),
+    }
+}
@@ -0,0 +1,218 @@
+//! Failed calls as typed errors, read from the details the server sends.
+//!
+//! The SDK returns `tonic::Status`. [`Error::from`] reads the status'
+//! `google.rpc.ErrorInfo`, `BadRequest`, and `PreconditionFailure` details
+//! into an [`ErrorKind`] with the values they carry as fields, so programs
+//! can branch on what went wrong without parsing the message, which may be
+//! reworded or in another language.
+//!
+//! ```no_run
+//! # async fn demo(client: &mut client::StudentClient) {
+//! match client.get_student("s1").await.map_err(client::Error::from) {
+//!     Err(e) if e.is_not_found() => println!("no such student"),
+//!     Err(e) => eprintln!("❌ {}", e),
+//!     Ok(student) => println!("{}", student.name),
+//! }
+//! # }
+//! ```
+
+use crate::{bad_request, error_info, precondition_failure};
+use proto::google::rpc::bad_request::FieldViolation;
+use proto::google::rpc::precondition_failure::Violation;
+use std::collections::HashMap;
+use std::fmt;
+use tonic::{Code, Status};
+
+/// What went wrong, with the values the server sent about it.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The request broke the rules for its fields, each one listed with
+    /// what is wrong with it.
+    Invalid {
+        violations: Vec<FieldViolation>,
+    },
+    /// What the call names does not exist, e.g. `STUDENT_NOT_FOUND`.
+    NotFound,
+    /// What the call creates exists already, e.g. `STUDENT_ALREADY_EXISTS`.
+    AlreadyExists,
+    /// The student was changed by someone else since it was read; read it
+    /// again and retry.
+    Changed,
+    /// Refused as things stand, e.g. an enrollment whose prerequisites are
+    /// not met, with each reason if the server listed them.
+    FailedPrecondition {
+        violations: Vec<Violation>,
+    },
+    /// The tenant has used up its quota of students.
+    QuotaExceeded {
+        tenant: String,
+        limit: u64,
+    },
+    /// Sent to a replica that is not the leader; `leader` is where to send
+    /// it instead, if any replica holds the lease.
+    NotLeader {
+        leader: Option<String>,
+    },
+    Unauthenticated,
+    PermissionDenied,
+    /// The server could not be reached, or cannot serve calls for now.
+    Unavailable,
+    DeadlineExceeded,
+    /// Anything else; the status says what.
+    Other,
+}
+
+/// A failed call: its status, and what the status' details say about it.
+#[derive(Debug, Clone)]
+pub struct Error {
+    status: Status,
+    kind: ErrorKind,
+    reason: Option<String>,
+    metadata: HashMap<String, String>,
+}
+
+impl Error {
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    pub fn code(&self) -> Code {
+        self.status.code()
+    }
+
+    /// The message, in the language asked for with
+    /// [`with_language`](crate::StudentClient::with_language).
+    pub fn message(&self) -> &str {
+        self.status.message()
+    }
+
+    /// `ErrorInfo.reason`, e.g. `STUDENT_NOT_FOUND`: the same in every
+    /// language and release.
+    pub fn reason(&self) -> Option<&str> {
+        self.reason.as_deref()
+    }
+
+    /// `ErrorInfo.metadata`: the values in the message, such as an ID.
+    pub fn metadata(&self) -> &HashMap<String, String> {
+        &self.metadata
+    }
+
+    /// The fields at fault, if the request broke the rules for any.
+    pub fn field_violations(&self) -> &[FieldViolation] {
+        match &self.kind {
+            ErrorKind::Invalid { violations } => violations,
+            _ => &[],
+        }
+    }
+
+    /// Whether the server gave `reason` as the reason.
+    pub fn is(&self, reason: &str) -> bool {
+        self.reason() == Some(reason)
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.kind == ErrorKind::NotFound
+    }
+
+    pub fn is_already_exists(&self) -> bool {
+        self.kind == ErrorKind::AlreadyExists
+    }
+
+    pub fn is_invalid(&self) -> bool {
+        matches!(self.kind, ErrorKind::Invalid { .. })
+    }
+
+    pub fn is_changed(&self) -> bool {
+        self.kind == ErrorKind::Changed
+    }
+
+    /// Whether the request's email was what the server refused.
+    pub fn is_invalid_email(&self) -> bool {
+        self.field_violations()
+            .iter()
+            .any(|violation| violation.field == "email")
+    }
+
+    pub fn status(&self) -> &Status {
+        &self.status
+    }
+
+    pub fn into_status(self) -> Status {
+        self.status
+    }
+}
+
+impl From<Status> for Error {
+    fn from(status: Status) -> Self {
+        let info = error_info(&status);
+        let reason = info.as_ref().map(|info| info.reason.clone());
+        let metadata = info.map(|info| info.metadata).unwrap_or_default();
+        let kind = match (reason.as_deref(), status.code()) {
+            (Some("STUDENT_CHANGED"), _) => ErrorKind::Changed,
+            (Some("QUOTA_EXCEEDED"), _) => ErrorKind::QuotaExceeded {
+                tenant: metadata.get("tenant").cloned().unwrap_or_default(),
+                limit: metadata
+                    .get("limit")
+                    .and_then(|limit| limit.parse().ok())
+                    .unwrap_or_default(),
+            },
+            (Some("NOT_LEADER" | "NO_LEADER"), _) => ErrorKind::NotLeader {
+                leader: metadata.get("leader").cloned(),
+            },
+            (_, Code::InvalidArgument) => ErrorKind::Invalid {
+                violations: bad_request(&status)
+                    .map(|bad_request| bad_request.field_violations)
+                    .unwrap_or_default(),
+            },
+            (_, Code::NotFound) => ErrorKind::NotFound,
+            (_, Code::AlreadyExists) => ErrorKind::AlreadyExists,
+            (_, Code::FailedPrecondition) => ErrorKind::FailedPrecondition {
+                violations: precondition_failure(&status)
+                    .map(|failure| failure.violations)
+                    .unwrap_or_default(),
+            },
+            (_, Code::Unauthenticated) => ErrorKind::Unauthenticated,
+            (_, Code::PermissionDenied) => ErrorKind::PermissionDenied,
+            (_, Code::Unavailable) => ErrorKind::Unavailable,
+            (_, Code::DeadlineExceeded) => ErrorKind::DeadlineExceeded,
+            _ => ErrorKind::Other,
+        };
+        Self {
+            status,
+            kind,
+            reason,
+            metadata,
+        }
+    }
+}
+
+impl From<Error> for Status {
+    fn from(error: Error) -> Self {
+        error.status
+    }
+}
+
+/// The code and message, then each field or precondition at fault on a
+/// line of its own, e.g. for a command line.
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.code(), self.message())?;
+        match &self.kind {
+            ErrorKind::Invalid { violations } if violations.len() > 1 => {
+                for violation in violations {
+                    write!(f, "\n   {}: {}", violation.field, violation.description)?;
+                }
+            }
+            ErrorKind::FailedPrecondition { violations } => {
+                for violation in violations {
+                    write!(f, "\n   {}", violation.description)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {}
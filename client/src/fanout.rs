@@ -0,0 +1,271 @@
+//! One operation run against many servers, or many tenants, at once.
+//!
+//! [`FanOut`] calls each [`Target`] through a [`StudentClient`] of its own,
+//! a bounded number at a time, and gathers what each returned into a
+//! [`Report`]. A target that fails or does not answer does not hold up or
+//! stop the others; its error is reported alongside the results.
+
+use crate::{ConnectionSettings, StudentClient};
+use futures::stream::{self, StreamExt};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::str::FromStr;
+use std::time::Duration;
+use tonic::transport::{Channel, Endpoint};
+use tonic::Status;
+
+/// Targets called at once unless [`FanOut::with_concurrency`] says otherwise.
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+/// A server to call, and the tenant to call it on behalf of, if any.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Target {
+    /// E.g. `http://[::1]:50051`; `http://` is assumed without a scheme.
+    pub endpoint: String,
+    pub tenant: Option<String>,
+}
+
+impl Target {
+    pub fn new(endpoint: &str) -> Self {
+        Self {
+            endpoint: endpoint.to_string(),
+            tenant: None,
+        }
+    }
+
+    pub fn with_tenant(mut self, tenant: &str) -> Self {
+        self.tenant = Some(tenant.to_string());
+        self
+    }
+
+    fn uri(&self) -> String {
+        match self.endpoint.contains("://") {
+            true => self.endpoint.clone(),
+            false => format!("http://{}", self.endpoint),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.tenant {
+            Some(tenant) => write!(f, "{}@{}", tenant, self.endpoint),
+            None => write!(f, "{}", self.endpoint),
+        }
+    }
+}
+
+/// A target from its command-line form, `[TENANT@]ENDPOINT`, e.g.
+/// `acme@students.example.edu:50051`.
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let (tenant, endpoint) = match text.split_once('@') {
+            Some((tenant, endpoint)) => (Some(tenant.trim()), endpoint.trim()),
+            None => (None, text),
+        };
+        if endpoint.is_empty() || tenant.is_some_and(str::is_empty) {
+            return Err(format!("{}: expected [TENANT@]ENDPOINT", text));
+        }
+        let target = Target::new(endpoint);
+        Ok(match tenant {
+            Some(tenant) => target.with_tenant(tenant),
+            None => target,
+        })
+    }
+}
+
+/// Runs one operation against every target, a bounded number at a time.
+///
+/// Targets on the same endpoint share one connection, so fanning out over
+/// the tenants of a server opens a single channel to it.
+#[derive(Debug, Clone)]
+pub struct FanOut {
+    targets: Vec<Target>,
+    concurrency: usize,
+    timeout: Option<Duration>,
+    connections: ConnectionSettings,
+}
+
+impl FanOut {
+    pub fn new(targets: impl IntoIterator<Item = Target>) -> Self {
+        Self {
+            targets: targets.into_iter().collect(),
+            concurrency: DEFAULT_CONCURRENCY,
+            timeout: None,
+            connections: ConnectionSettings::default(),
+        }
+    }
+
+    /// Call at most `concurrency` targets at once (at least one).
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Give up on a target that has not finished within `timeout`, reporting
+    /// `DEADLINE_EXCEEDED` for it.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Connect to the targets with `settings`.
+    pub fn with_connection_settings(mut self, settings: ConnectionSettings) -> Self {
+        self.connections = settings;
+        self
+    }
+
+    pub fn targets(&self) -> &[Target] {
+        &self.targets
+    }
+
+    /// Run `operation` against every target and report each outcome, in the
+    /// order the targets were given.
+    ///
+    /// `operation` gets a client for the target, connecting on first use and
+    /// sending the target's tenant with every call. Configure it further
+    /// there, e.g. with [`with_token`](StudentClient::with_token).
+    pub async fn run<T, F, Fut>(&self, operation: F) -> Report<T>
+    where
+        F: Fn(StudentClient, Target) -> Fut,
+        Fut: Future<Output = Result<T, Status>>,
+    {
+        let mut channels: HashMap<&str, Result<Channel, Status>> = HashMap::new();
+        for target in &self.targets {
+            channels.entry(&target.endpoint).or_insert_with(|| {
+                Endpoint::from_shared(target.uri())
+                    .map(|endpoint| self.connections.apply(endpoint).connect_lazy())
+                    .map_err(|e| {
+                        Status::invalid_argument(format!("Endpoint {}: {}", target.endpoint, e))
+                    })
+            });
+        }
+
+        let operation = &operation;
+        let channels = &channels;
+        let mut results: Vec<(usize, Target, Result<T, Status>)> =
+            stream::iter(self.targets.iter().cloned().enumerate())
+                .map(|(index, target)| async move {
+                    let result = match &channels[target.endpoint.as_str()] {
+                        Ok(channel) => self.call(channel.clone(), &target, operation).await,
+                        Err(status) => Err(status.clone()),
+                    };
+                    (index, target, result)
+                })
+                .buffer_unordered(self.concurrency)
+                .collect()
+                .await;
+        results.sort_by_key(|(index, _, _)| *index);
+        Report {
+            results: results
+                .into_iter()
+                .map(|(_, target, result)| (target, result))
+                .collect(),
+        }
+    }
+
+    async fn call<T, F, Fut>(
+        &self,
+        channel: Channel,
+        target: &Target,
+        operation: &F,
+    ) -> Result<T, Status>
+    where
+        F: Fn(StudentClient, Target) -> Fut,
+        Fut: Future<Output = Result<T, Status>>,
+    {
+        let mut client = StudentClient::new(channel);
+        if let Some(tenant) = &target.tenant {
+            client = client.with_tenant(tenant);
+        }
+        let call = operation(client, target.clone());
+        let Some(timeout) = self.timeout else {
+            return call.await;
+        };
+        tokio::time::timeout(timeout, call)
+            .await
+            .unwrap_or_else(|_| {
+                Err(Status::deadline_exceeded(format!(
+                    "No answer within {:?}",
+                    timeout
+                )))
+            })
+    }
+}
+
+/// What each target of a [`FanOut`] returned, in the order they were given.
+#[derive(Debug)]
+pub struct Report<T> {
+    pub results: Vec<(Target, Result<T, Status>)>,
+}
+
+impl<T> Report<T> {
+    pub fn successes(&self) -> impl Iterator<Item = (&Target, &T)> {
+        self.results
+            .iter()
+            .filter_map(|(target, result)| result.as_ref().ok().map(|value| (target, value)))
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = (&Target, &Status)> {
+        self.results
+            .iter()
+            .filter_map(|(target, result)| result.as_ref().err().map(|status| (target, status)))
+    }
+
+    /// Whether every target succeeded.
+    pub fn is_success(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// Every target's result, or an error listing the targets that failed.
+    pub fn into_result(self) -> Result<Vec<(Target, T)>, FanOutError> {
+        let targets = self.results.len();
+        let mut successes = Vec::with_capacity(targets);
+        let mut failures = Vec::new();
+        for (target, result) in self.results {
+            match result {
+                Ok(value) => successes.push((target, value)),
+                Err(status) => failures.push((target, status)),
+            }
+        }
+        match failures.is_empty() {
+            true => Ok(successes),
+            false => Err(FanOutError { targets, failures }),
+        }
+    }
+}
+
+/// The targets of a [`FanOut`] that failed, with why.
+#[derive(Debug)]
+pub struct FanOutError {
+    /// How many targets there were in all
+    pub targets: usize,
+    pub failures: Vec<(Target, Status)>,
+}
+
+impl fmt::Display for FanOutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} targets failed",
+            self.failures.len(),
+            self.targets
+        )?;
+        for (target, status) in &self.failures {
+            write!(
+                f,
+                "\n  {}: {:?}: {}",
+                target,
+                status.code(),
+                status.message()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for FanOutError {}
@@ -0,0 +1,997 @@
+// `tonic::Status` is large by design and is the error type throughout the SDK.
+#![allow(clippy::result_large_err)]
+
+mod cache;
+mod connection;
+mod error;
+mod fanout;
+mod logging;
+mod service_config;
+mod validation;
+
+pub use cache::StudentCache;
+pub use connection::{parse_duration, ConnectionSettings};
+pub use error::{Error, ErrorKind};
+pub use fanout::{FanOut, FanOutError, Report, Target, DEFAULT_CONCURRENCY};
+pub use service_config::{MethodConfig, RetryPolicy, ServiceConfig};
+pub use student_core::{Problem, Profile};
+pub use validation::{check_entries, check_student};
+
+use futures::stream::{self, FuturesUnordered, Stream, StreamExt, TryStreamExt};
+use logging::Redact;
+use prost::Message;
+use proto::google::rpc::{BadRequest, ErrorInfo, PreconditionFailure};
+use proto::statistics_service_client::StatisticsServiceClient;
+use proto::student_service_client::StudentServiceClient;
+use proto::batch_write_entry::Write;
+use proto::{
+    AcademicStanding, BatchWriteEntry, BatchWriteRequest, CreateStudentRequest, DeleteStudentRequest, DeleteStudentResponse,
+    GetStatisticsRequest, GetStatisticsResponse, GetStudentRequest, ListStudentsByStandingRequest, ListStudentsRequest, ListStudentsResponse,
+    Student, StudentEvent, UpdateStudentRequest, ValidateStudentRequest, ValidateStudentResponse,
+    WatchStudentsRequest,
+};
+use std::fmt::Debug;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+use tonic::metadata::AsciiMetadataValue;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Request, Response, Status, Streaming};
+
+/// Default number of students fetched per `ListStudents` call by `list_all`.
+pub const DEFAULT_PAGE_SIZE: i32 = 50;
+
+// Backoff between attempts while waiting for the channel to become ready
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(2);
+
+/// The metadata key writes carry their idempotency key in.
+pub const IDEMPOTENCY_KEY: &str = "idempotency-key";
+
+/// Attempts `create_idempotent` and `update_with_retry` make before giving up.
+pub const MUTATION_ATTEMPTS: u32 = 5;
+
+// Services as a service config names them
+const STUDENT_SERVICE: &str = "student.StudentService";
+const STATISTICS_SERVICE: &str = "student.StatisticsService";
+
+/// Thin SDK wrapper around the generated `StudentServiceClient`.
+///
+/// Cloning is cheap: clones share the same underlying channel and cache.
+#[derive(Debug, Clone)]
+pub struct StudentClient {
+    channel: Channel,
+    inner: StudentServiceClient<Channel>,
+    cache: Option<Arc<StudentCache>>,
+    wait_for_ready: Option<Duration>,
+    hedging: Option<Hedging>,
+    service_config: Option<Arc<ServiceConfig>>,
+    // Sent as `accept-language` with every call
+    language: Option<AsciiMetadataValue>,
+    // Sent as `authorization` with every call
+    token: Option<AsciiMetadataValue>,
+    // Sent as `x-request-id` with every call
+    request_id: Option<AsciiMetadataValue>,
+    // Sent as `tenant` with every call
+    tenant: Option<AsciiMetadataValue>,
+    verbose: bool,
+    // Check students against the shared rules before sending them
+    local_validation: bool,
+}
+
+// Read hedging policy: extra endpoints tried in order, one per `delay`
+#[derive(Debug, Clone)]
+struct Hedging {
+    delay: Duration,
+    alternates: Vec<StudentServiceClient<Channel>>,
+}
+
+impl StudentClient {
+    /// Connect to the student service at `dst` (e.g. `http://[::1]:50051`).
+    pub async fn connect<D>(dst: D) -> Result<Self, tonic::transport::Error>
+    where
+        D: TryInto<Endpoint>,
+        D::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        Self::connect_with(dst, &ConnectionSettings::default()).await
+    }
+
+    /// Like [`connect`](Self::connect), with `settings` for the connection,
+    /// e.g. keepalive pings to keep quiet watch streams open.
+    pub async fn connect_with<D>(
+        dst: D,
+        settings: &ConnectionSettings,
+    ) -> Result<Self, tonic::transport::Error>
+    where
+        D: TryInto<Endpoint>,
+        D::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let channel = settings.apply(Endpoint::new(dst)?).connect().await?;
+        Ok(Self::new(channel))
+    }
+
+    /// Create a client without connecting; the connection is established on
+    /// the first RPC and re-established after failures.
+    ///
+    /// Only fails if `dst` is not a valid endpoint URI. Pair with
+    /// [`with_wait_for_ready`](Self::with_wait_for_ready) to tolerate a server
+    /// that is not up yet.
+    pub fn connect_lazy<D>(dst: D) -> Result<Self, tonic::transport::Error>
+    where
+        D: TryInto<Endpoint>,
+        D::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        Self::connect_lazy_with(dst, &ConnectionSettings::default())
+    }
+
+    /// Like [`connect_lazy`](Self::connect_lazy), with `settings` for the
+    /// connection.
+    pub fn connect_lazy_with<D>(
+        dst: D,
+        settings: &ConnectionSettings,
+    ) -> Result<Self, tonic::transport::Error>
+    where
+        D: TryInto<Endpoint>,
+        D::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let channel = settings.apply(Endpoint::new(dst)?).connect_lazy();
+        Ok(Self::new(channel))
+    }
+
+    /// Build a client on top of an existing channel.
+    pub fn new(channel: Channel) -> Self {
+        Self {
+            inner: StudentServiceClient::new(channel.clone()),
+            channel,
+            cache: None,
+            wait_for_ready: None,
+            hedging: None,
+            service_config: None,
+            language: None,
+            token: None,
+            request_id: None,
+            tenant: None,
+            verbose: false,
+            local_validation: true,
+        }
+    }
+
+    /// Ask for error messages in `accept_language`, e.g. `zh` or
+    /// `es-MX, en;q=0.5`. A value that is not printable ASCII is ignored.
+    pub fn with_language(mut self, accept_language: &str) -> Self {
+        self.language = accept_language.parse().ok();
+        self
+    }
+
+    /// Call as the caller `token` names, a bearer token the server issued. A
+    /// token that is not printable ASCII is ignored. Clones share the
+    /// [cache](Self::with_cache), so give a cached client's clones one token.
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = format!("Bearer {}", token.trim()).parse().ok();
+        self
+    }
+
+    /// Send `id` as `x-request-id` with every call, so the server's traces
+    /// can be matched to the request that made them. An ID that is not
+    /// printable ASCII is ignored.
+    pub fn with_request_id(mut self, id: &str) -> Self {
+        self.request_id = id.parse().ok();
+        self
+    }
+
+    /// Call on behalf of `tenant`, sent as `tenant` with every call: its
+    /// quota counts the students created, and its feature flags apply. A
+    /// tenant that is not printable ASCII is ignored.
+    pub fn with_tenant(mut self, tenant: &str) -> Self {
+        self.tenant = tenant.parse().ok();
+        self
+    }
+
+    /// Log every request and response to stderr, including metadata and
+    /// timing. Emails and credential-bearing metadata are redacted.
+    pub fn with_verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Check students against the rules the server enforces before sending
+    /// them, so one that breaks a rule fails at once, with the status the
+    /// server would send (see [`check_student`]). On by default; the
+    /// messages are English, so with a [language](Self::with_language)
+    /// other than English the server checks them instead.
+    pub fn with_local_validation(mut self, enabled: bool) -> Self {
+        self.local_validation = enabled;
+        self
+    }
+
+    /// Make RPCs wait up to `deadline` for the server to become reachable.
+    ///
+    /// While waiting, calls failing with `UNAVAILABLE` are retried with
+    /// exponential backoff; any other status is returned immediately.
+    /// `UpdateStudent` and `DeleteStudent` carry no idempotency key, so they
+    /// are retried only while the connection can't be made, when the server
+    /// can't have seen them.
+    pub fn with_wait_for_ready(mut self, deadline: Duration) -> Self {
+        self.wait_for_ready = Some(deadline);
+        self
+    }
+
+    /// Cache `GetStudent` responses for `ttl`.
+    ///
+    /// Mutations made through this client (or its clones) refresh or
+    /// invalidate the affected entry; changes made by other clients are only
+    /// picked up once the entry expires.
+    pub fn with_cache(mut self, ttl: Duration) -> Self {
+        self.cache = Some(Arc::new(StudentCache::new(ttl)));
+        self
+    }
+
+    /// Hedge `GetStudent` and `ListStudents` across `alternates`.
+    ///
+    /// Reads go to the primary channel first; each time `delay` passes
+    /// without a successful response (or an attempt fails with
+    /// `UNAVAILABLE`, `DEADLINE_EXCEEDED` or `RESOURCE_EXHAUSTED`), the
+    /// request is re-issued to the next alternate. The first successful
+    /// response wins and the remaining attempts are dropped. Any other
+    /// error is returned at once, and if every attempt fails, the first
+    /// attempt's error is returned. Alternates must serve the same
+    /// data, since page tokens are passed through unchanged.
+    pub fn with_hedging(
+        mut self,
+        delay: Duration,
+        alternates: impl IntoIterator<Item = Channel>,
+    ) -> Self {
+        self.hedging = Some(Hedging {
+            delay,
+            alternates: alternates
+                .into_iter()
+                .map(StudentServiceClient::new)
+                .collect(),
+        });
+        self
+    }
+
+    /// Time out and retry calls as `config` says for each method, like
+    /// gRPC clients in other languages given the same service config.
+    ///
+    /// A retry policy retries a call failing with one of its
+    /// `retryableStatusCodes`, waiting `initialBackoff` at first and
+    /// `backoffMultiplier` times longer each time after, up to `maxBackoff`,
+    /// or as long as the server asks in `grpc-retry-pushback-ms`. A
+    /// `timeout` bounds the whole call, retries included, and is sent to the
+    /// server as each attempt's deadline.
+    pub fn with_service_config(mut self, config: ServiceConfig) -> Self {
+        self.service_config = Some(Arc::new(config));
+        self
+    }
+
+    pub fn cache(&self) -> Option<&StudentCache> {
+        self.cache.as_deref()
+    }
+
+    /// Creates `student`. Every attempt carries the same fresh idempotency
+    /// key, so a retry after a lost response does not create it twice.
+    pub async fn create_student(&mut self, student: Student) -> Result<Student, Status> {
+        self.create_student_with_key(student, &idempotency_key())
+            .await
+    }
+
+    /// Creates `student` under idempotency `key`: repeating the call with
+    /// the same key and student, even from another client, returns the
+    /// student created the first time instead of creating another.
+    pub async fn create_student_with_key(
+        &mut self,
+        student: Student,
+        key: &str,
+    ) -> Result<Student, Status> {
+        if self.validates_locally() {
+            check_student(&student)?;
+        }
+        let key = key_value(key)?;
+        let request = CreateStudentRequest {
+            student: Some(student),
+        };
+        let response = self
+            .call("CreateStudent", request, |mut inner, mut request| {
+                request
+                    .metadata_mut()
+                    .insert(IDEMPOTENCY_KEY, key.clone());
+                async move { inner.create_student(request).await }
+            })
+            .await?;
+        let student = required(response.student)?;
+        self.cache_insert(&student);
+        Ok(student)
+    }
+
+    pub async fn get_student(&mut self, id: &str) -> Result<Student, Status> {
+        if let Some(student) = self.cache.as_ref().and_then(|cache| cache.get(id)) {
+            return Ok(student);
+        }
+
+        let request = GetStudentRequest { id: id.to_string() };
+        let response = self
+            .hedged_call("GetStudent", request, |mut inner, request| async move {
+                inner.get_student(request).await
+            })
+            .await?;
+        let student = required(response.student)?;
+        self.cache_insert(&student);
+        Ok(student)
+    }
+
+    pub async fn update_student(&mut self, student: Student) -> Result<Student, Status> {
+        if self.validates_locally() {
+            check_student(&student)?;
+        }
+        // Whatever the outcome, the cached copy can no longer be trusted
+        self.cache_invalidate(&student.id);
+
+        let request = UpdateStudentRequest {
+            student: Some(student),
+        };
+        let response = self
+            .call("UpdateStudent", request, |mut inner, request| async move {
+                inner.update_student(request).await
+            })
+            .await?;
+        let student = required(response.student)?;
+        self.cache_insert(&student);
+        Ok(student)
+    }
+
+    /// Creates `student` under idempotency `key`, trying again after
+    /// `UNAVAILABLE` or `DEADLINE_EXCEEDED`, with backoff, up to
+    /// [`MUTATION_ATTEMPTS`] times. Every attempt carries `key`, so the
+    /// student is created once however many of them reach the server, and
+    /// the one that answers gets it back.
+    ///
+    /// Keep `key` with the request it belongs to, e.g. in a job queue, to
+    /// retry from another process or after a restart.
+    pub async fn create_idempotent(
+        &mut self,
+        student: Student,
+        key: &str,
+    ) -> Result<Student, Status> {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 1;
+        loop {
+            match self.create_student_with_key(student.clone(), key).await {
+                Err(status)
+                    if matches!(status.code(), Code::Unavailable | Code::DeadlineExceeded)
+                        && attempt < MUTATION_ATTEMPTS =>
+                {
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Reads student `id`, has `change` change it, and writes it back
+    /// guarded by the etag it was read with. If someone else changed the
+    /// student in between, reads it again and applies `change` to that, up to
+    /// [`MUTATION_ATTEMPTS`] times, so no one's change is overwritten.
+    ///
+    /// `change` may run more than once, so it should only change the
+    /// student it is given. A write that fails any other way is not tried
+    /// again: it may have been applied.
+    pub async fn update_with_retry<F>(
+        &mut self,
+        id: &str,
+        mut change: F,
+    ) -> Result<Student, Status>
+    where
+        F: FnMut(&mut Student),
+    {
+        let mut attempt = 1;
+        loop {
+            // A cached copy may be the stale one
+            self.cache_invalidate(id);
+            let mut student = self.get_student(id).await?;
+            let etag = std::mem::take(&mut student.etag);
+            change(&mut student);
+            student.id = id.to_string();
+            student.etag = etag;
+            match self.update_student(student).await {
+                Err(status) if attempt < MUTATION_ATTEMPTS => {
+                    let error = Error::from(status);
+                    if !error.is_changed() {
+                        return Err(error.into_status());
+                    }
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    pub async fn delete_student(&mut self, id: &str) -> Result<DeleteStudentResponse, Status> {
+        self.cache_invalidate(id);
+
+        let request = DeleteStudentRequest { id: id.to_string() };
+        self.call("DeleteStudent", request, |mut inner, request| async move {
+            inner.delete_student(request).await
+        })
+        .await
+    }
+
+    /// Apply `entries` all together, or none of them if one fails; returns
+    /// one student per entry, in order. Like `create_student`, every attempt
+    /// carries the same idempotency key.
+    pub async fn batch_write(
+        &mut self,
+        entries: Vec<BatchWriteEntry>,
+    ) -> Result<Vec<Student>, Status> {
+        self.batch_write_with_key(entries, &idempotency_key())
+            .await
+    }
+
+    /// Like [`batch_write`](Self::batch_write), under idempotency `key`:
+    /// repeating it with the same key and entries writes nothing again and
+    /// returns what was written the first time.
+    pub async fn batch_write_with_key(
+        &mut self,
+        entries: Vec<BatchWriteEntry>,
+        key: &str,
+    ) -> Result<Vec<Student>, Status> {
+        if self.validates_locally() {
+            check_entries(&entries)?;
+        }
+        // Whatever the outcome, cached copies of what is written can no longer be trusted
+        let mut deleted = Vec::with_capacity(entries.len());
+        for entry in &entries {
+            match &entry.write {
+                Some(Write::Update(student)) => self.cache_invalidate(&student.id),
+                Some(Write::DeleteId(id)) => self.cache_invalidate(id),
+                _ => {}
+            }
+            deleted.push(matches!(entry.write, Some(Write::DeleteId(_))));
+        }
+
+        let key = key_value(key)?;
+        let request = BatchWriteRequest { entries };
+        let response = self
+            .call("BatchWrite", request, |mut inner, mut request| {
+                request
+                    .metadata_mut()
+                    .insert(IDEMPOTENCY_KEY, key.clone());
+                async move { inner.batch_write(request).await }
+            })
+            .await?;
+        for (student, deleted) in response.students.iter().zip(deleted) {
+            if !deleted {
+                self.cache_insert(student);
+            }
+        }
+        Ok(response.students)
+    }
+
+    /// Ask the server whether `student` would be accepted, without storing it.
+    ///
+    /// With [local validation](Self::with_local_validation), a student that
+    /// breaks one of the shared rules is answered for here, with every rule
+    /// it breaks, and not sent.
+    pub async fn validate_student(
+        &mut self,
+        student: Student,
+    ) -> Result<ValidateStudentResponse, Status> {
+        if self.validates_locally() {
+            let response = validation::validate_student(&student);
+            if !response.valid {
+                return Ok(response);
+            }
+        }
+        let request = ValidateStudentRequest {
+            student: Some(student),
+        };
+        self.call(
+            "ValidateStudent",
+            request,
+            |mut inner, request| async move { inner.validate_student(request).await },
+        )
+        .await
+    }
+
+    /// Fetch a single page of students.
+    pub async fn list_students(
+        &mut self,
+        page_size: i32,
+        page_token: String,
+    ) -> Result<ListStudentsResponse, Status> {
+        self.list_students_ordered("", page_size, page_token).await
+    }
+
+    /// Fetch a single page of students in `order_by` order: `"id"` (the
+    /// default when empty) or `"name"`.
+    pub async fn list_students_ordered(
+        &mut self,
+        order_by: &str,
+        page_size: i32,
+        page_token: String,
+    ) -> Result<ListStudentsResponse, Status> {
+        self.list_students_with(ListStudentsRequest {
+            page_size,
+            page_token,
+            order_by: order_by.to_string(),
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Fetch the single page of students `request` asks for, e.g. to filter
+    /// them by `country` and `region`.
+    pub async fn list_students_with(
+        &mut self,
+        request: ListStudentsRequest,
+    ) -> Result<ListStudentsResponse, Status> {
+        self.hedged_call("ListStudents", request, |mut inner, request| async move {
+            inner.list_students(request).await
+        })
+        .await
+    }
+
+    /// Fetch a single page of the students with `standing`.
+    pub async fn list_students_by_standing(
+        &mut self,
+        standing: AcademicStanding,
+        page_size: i32,
+        page_token: String,
+    ) -> Result<ListStudentsResponse, Status> {
+        let request = ListStudentsByStandingRequest {
+            standing: standing.into(),
+            page_size,
+            page_token,
+        };
+        self.hedged_call(
+            "ListStudentsByStanding",
+            request,
+            |mut inner, request| async move { inner.list_students_by_standing(request).await },
+        )
+        .await
+    }
+
+    /// The server's aggregates over every student, from `StatisticsService`.
+    pub async fn get_statistics(&self) -> Result<GetStatisticsResponse, Status> {
+        let statistics = StatisticsServiceClient::new(self.channel.clone());
+        self.call_on(
+            statistics,
+            STATISTICS_SERVICE,
+            "GetStatistics",
+            GetStatisticsRequest {},
+            &|mut inner: StatisticsServiceClient<Channel>, request| async move {
+                inner.get_statistics(request).await
+            },
+        )
+        .await
+    }
+
+    /// Stream every student, transparently following `next_page_token`.
+    ///
+    /// Pages are fetched lazily as the stream is polled; an error ends the
+    /// stream after it has been yielded.
+    pub fn list_all(&self, page_size: i32) -> impl Stream<Item = Result<Student, Status>> {
+        self.list_all_ordered("", page_size)
+    }
+
+    /// Like [`list_all`](Self::list_all), in `order_by` order (see
+    /// [`list_students_ordered`](Self::list_students_ordered)).
+    pub fn list_all_ordered(
+        &self,
+        order_by: &str,
+        page_size: i32,
+    ) -> impl Stream<Item = Result<Student, Status>> {
+        self.list_all_with(ListStudentsRequest {
+            page_size,
+            order_by: order_by.to_string(),
+            ..Default::default()
+        })
+    }
+
+    /// Like [`list_all`](Self::list_all), for every student `request` asks
+    /// for (see [`list_students_with`](Self::list_students_with)), starting
+    /// from its `page_token`.
+    pub fn list_all_with(
+        &self,
+        request: ListStudentsRequest,
+    ) -> impl Stream<Item = Result<Student, Status>> {
+        let client = self.clone();
+        let first_page = request.page_token.clone();
+        stream::try_unfold(Some(first_page), move |page_token| {
+            let mut client = client.clone();
+            let request = request.clone();
+            async move {
+                let Some(page_token) = page_token else {
+                    return Ok::<_, Status>(None);
+                };
+                let page = client
+                    .list_students_with(ListStudentsRequest {
+                        page_token,
+                        ..request
+                    })
+                    .await?;
+                let next_page_token =
+                    (!page.next_page_token.is_empty()).then_some(page.next_page_token);
+                let students = stream::iter(page.students.into_iter().map(Ok));
+                Ok(Some((students, next_page_token)))
+            }
+        })
+        .try_flatten()
+    }
+
+    /// Subscribe to create/update/delete events, optionally for one `major`
+    /// only (empty for all students).
+    ///
+    /// Events start from the moment of subscription. The stream yields an
+    /// error and ends if the server drops a watcher that fell behind. When the
+    /// server shuts down, the last event is `CHANGE_TYPE_SHUTTING_DOWN`; pass
+    /// its resume token to [`resume_watch`](Self::resume_watch).
+    pub async fn watch_students(&mut self, major: &str) -> Result<Streaming<StudentEvent>, Status> {
+        self.resume_watch(major, "").await
+    }
+
+    /// Like [`watch_students`](Self::watch_students), but first replays the
+    /// events after the one with `resume_token`.
+    ///
+    /// Fails with `OUT_OF_RANGE` if the server no longer has those events (or
+    /// never had them, e.g. after a restart); re-read the students instead.
+    pub async fn resume_watch(
+        &mut self,
+        major: &str,
+        resume_token: &str,
+    ) -> Result<Streaming<StudentEvent>, Status> {
+        self.watch(WatchStudentsRequest {
+            major: major.to_string(),
+            resume_token: resume_token.to_string(),
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Like [`resume_watch`](Self::resume_watch), with every filter
+    /// `request` has, such as a standing.
+    pub async fn watch(
+        &mut self,
+        request: WatchStudentsRequest,
+    ) -> Result<Streaming<StudentEvent>, Status> {
+        let response = self.inner.clone().watch_students(self.request(request)).await?;
+        Ok(response.into_inner())
+    }
+
+    async fn call<Req, Res, F, Fut>(
+        &self,
+        method: &'static str,
+        request: Req,
+        rpc: F,
+    ) -> Result<Res, Status>
+    where
+        Req: Clone + Redact + Debug,
+        Res: Redact + Debug,
+        F: Fn(StudentServiceClient<Channel>, Request<Req>) -> Fut,
+        Fut: Future<Output = Result<Response<Res>, Status>>,
+    {
+        self.call_on(self.inner.clone(), STUDENT_SERVICE, method, request, &rpc)
+            .await
+    }
+
+    // Like `call`, but hedged across the alternate endpoints when configured
+    async fn hedged_call<Req, Res, F, Fut>(
+        &self,
+        method: &'static str,
+        request: Req,
+        rpc: F,
+    ) -> Result<Res, Status>
+    where
+        Req: Clone + Redact + Debug,
+        Res: Redact + Debug,
+        F: Fn(StudentServiceClient<Channel>, Request<Req>) -> Fut,
+        Fut: Future<Output = Result<Response<Res>, Status>>,
+    {
+        let Some(hedging) = &self.hedging else {
+            return self.call(method, request, rpc).await;
+        };
+
+        // Attempts are numbered in the order they start, the primary's first
+        let mut targets = std::iter::once(self.inner.clone())
+            .chain(hedging.alternates.iter().cloned())
+            .enumerate()
+            .peekable();
+        let attempt = |(number, target)| {
+            let call = self.call_on(target, STUDENT_SERVICE, method, request.clone(), &rpc);
+            async move { (number, call.await) }
+        };
+        let mut in_flight = FuturesUnordered::new();
+        in_flight.extend(targets.next().map(attempt));
+        // Reported when every attempt fails
+        let mut first_error: Option<(usize, Status)> = None;
+
+        loop {
+            tokio::select! {
+                Some((number, result)) = in_flight.next() => match result {
+                    Ok(response) => return Ok(response),
+                    // Another endpoint would give the same answer
+                    Err(status) if !is_hedgeable(status.code()) => return Err(status),
+                    Err(status) => {
+                        if first_error.as_ref().is_none_or(|(first, _)| number < *first) {
+                            first_error = Some((number, status));
+                        }
+                        match targets.next() {
+                            // Don't wait out the delay once an attempt has failed
+                            Some(target) => in_flight.push(attempt(target)),
+                            None if in_flight.is_empty() => {
+                                return Err(first_error.map(|(_, status)| status).unwrap())
+                            }
+                            None => {}
+                        }
+                    }
+                },
+                _ = tokio::time::sleep(hedging.delay), if targets.peek().is_some() => {
+                    in_flight.extend(targets.next().map(attempt));
+                }
+            }
+        }
+    }
+
+    // Run an RPC on `inner` under the service config's timeout and retry
+    // policy for the method, if it has any
+    async fn call_on<C, Req, Res, F, Fut>(
+        &self,
+        inner: C,
+        service: &'static str,
+        method: &'static str,
+        request: Req,
+        rpc: &F,
+    ) -> Result<Res, Status>
+    where
+        Req: Clone + Redact + Debug,
+        Res: Redact + Debug,
+        C: Clone,
+        F: Fn(C, Request<Req>) -> Fut,
+        Fut: Future<Output = Result<Response<Res>, Status>>,
+    {
+        let config = self
+            .service_config
+            .as_ref()
+            .and_then(|config| config.method(service, method));
+        let Some(config) = config else {
+            return self.ready_call(inner, method, request, rpc, None).await;
+        };
+
+        let deadline = config.timeout.map(|timeout| Instant::now() + timeout);
+        let attempts = async {
+            let mut attempt = 1;
+            loop {
+                let result = self
+                    .ready_call(inner.clone(), method, request.clone(), rpc, deadline)
+                    .await;
+                let retry = match (&result, &config.retry) {
+                    (Err(status), Some(policy)) => policy.retry_after(status, attempt),
+                    _ => None,
+                };
+                let Some(wait) = retry else {
+                    return result;
+                };
+                // Not worth waiting for if the deadline comes first
+                if deadline.is_some_and(|deadline| Instant::now() + wait >= deadline) {
+                    return result;
+                }
+                tokio::time::sleep(wait).await;
+                attempt += 1;
+            }
+        };
+        let (Some(deadline), Some(timeout)) = (deadline, config.timeout) else {
+            return attempts.await;
+        };
+        let exceeded = || {
+            Status::deadline_exceeded(format!(
+                "{} did not finish within {:?}",
+                method, timeout
+            ))
+        };
+        match tokio::time::timeout_at(deadline, attempts).await {
+            // The channel keeps the grpc-timeout too, and says CANCELLED
+            // when its timer goes off first
+            Ok(Err(status))
+                if status.code() == Code::Cancelled && Instant::now() >= deadline =>
+            {
+                Err(exceeded())
+            }
+            Ok(result) => result,
+            Err(_) => Err(exceeded()),
+        }
+    }
+
+    // Run an RPC on `inner`, retrying `UNAVAILABLE` until the wait-for-ready
+    // deadline (for writes without an idempotency key, only while the
+    // connection can't be made); each attempt has until `deadline`, if given
+    async fn ready_call<C, Req, Res, F, Fut>(
+        &self,
+        inner: C,
+        method: &'static str,
+        request: Req,
+        rpc: &F,
+        deadline: Option<Instant>,
+    ) -> Result<Res, Status>
+    where
+        Req: Clone + Redact + Debug,
+        Res: Redact + Debug,
+        C: Clone,
+        F: Fn(C, Request<Req>) -> Fut,
+        Fut: Future<Output = Result<Response<Res>, Status>>,
+    {
+        let Some(wait) = self.wait_for_ready else {
+            return self.attempt(inner, method, request, rpc, deadline).await;
+        };
+
+        let ready_by = Instant::now() + wait;
+        let mut backoff = INITIAL_BACKOFF;
+        loop {
+            match self
+                .attempt(inner.clone(), method, request.clone(), rpc, deadline)
+                .await
+            {
+                Err(status)
+                    if status.code() == Code::Unavailable
+                        && (is_repeatable(method) || never_sent(&status))
+                        && Instant::now() + backoff < ready_by =>
+                {
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    // A single RPC attempt, logged when verbose mode is on
+    async fn attempt<C, Req, Res, F, Fut>(
+        &self,
+        inner: C,
+        method: &'static str,
+        request: Req,
+        rpc: &F,
+        deadline: Option<Instant>,
+    ) -> Result<Res, Status>
+    where
+        Req: Redact + Debug,
+        Res: Redact + Debug,
+        F: Fn(C, Request<Req>) -> Fut,
+        Fut: Future<Output = Result<Response<Res>, Status>>,
+    {
+        let mut request = self.request(request);
+        if let Some(deadline) = deadline {
+            request.set_timeout(deadline.saturating_duration_since(Instant::now()));
+        }
+        if !self.verbose {
+            return rpc(inner, request).await.map(Response::into_inner);
+        }
+
+        logging::log_request(method, &request);
+        let started = Instant::now();
+        let result = rpc(inner, request).await;
+        logging::log_response(method, &result, started.elapsed());
+        result.map(Response::into_inner)
+    }
+
+    // `message` with the metadata sent with every call
+    fn request<T>(&self, message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        if let Some(language) = &self.language {
+            request
+                .metadata_mut()
+                .insert("accept-language", language.clone());
+        }
+        if let Some(token) = &self.token {
+            request.metadata_mut().insert("authorization", token.clone());
+        }
+        if let Some(id) = &self.request_id {
+            request.metadata_mut().insert("x-request-id", id.clone());
+        }
+        if let Some(tenant) = &self.tenant {
+            request.metadata_mut().insert("tenant", tenant.clone());
+        }
+        request
+    }
+
+    // Whether to check students here: messages made here are English
+    fn validates_locally(&self) -> bool {
+        let english = |language: &AsciiMetadataValue| {
+            let language = language.to_str().unwrap_or_default().trim_start();
+            language.get(..2).is_some_and(|tag| tag.eq_ignore_ascii_case("en"))
+        };
+        self.local_validation && self.language.as_ref().is_none_or(english)
+    }
+
+    fn cache_insert(&self, student: &Student) {
+        if let Some(cache) = &self.cache {
+            cache.insert(student.clone());
+        }
+    }
+
+    fn cache_invalidate(&self, id: &str) {
+        if let Some(cache) = &self.cache {
+            cache.invalidate(id);
+        }
+    }
+}
+
+// Whether a call can be repeated without applying it twice: reads, and
+// writes that carry an idempotency key
+fn is_repeatable(method: &str) -> bool {
+    !matches!(method, "UpdateStudent" | "DeleteStudent")
+}
+
+// Whether the call failed connecting, before anything was sent
+fn never_sent(status: &Status) -> bool {
+    let mut source = std::error::Error::source(status);
+    while let Some(error) = source {
+        if error
+            .downcast_ref::<hyper::Error>()
+            .is_some_and(hyper::Error::is_connect)
+        {
+            return true;
+        }
+        source = error.source();
+    }
+    false
+}
+
+// Failures another endpoint might not share; anything else, such as
+// NOT_FOUND, is the answer
+fn is_hedgeable(code: Code) -> bool {
+    matches!(
+        code,
+        Code::Unavailable | Code::DeadlineExceeded | Code::ResourceExhausted
+    )
+}
+
+// The server always sets `student` on success; treat a missing one as a protocol error.
+fn required(student: Option<Student>) -> Result<Student, Status> {
+    student.ok_or_else(|| Status::internal("Response is missing the student"))
+}
+
+// A key for one call, however many attempts it takes
+fn idempotency_key() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+fn key_value(key: &str) -> Result<AsciiMetadataValue, Status> {
+    AsciiMetadataValue::try_from(key)
+        .map_err(|_| Status::invalid_argument("Idempotency key must be ASCII"))
+}
+
+/// The `google.rpc.PreconditionFailure` in a `FAILED_PRECONDITION` status'
+/// details, e.g. why `EnrollmentService.Enroll` refused an enrollment.
+pub fn precondition_failure(status: &Status) -> Option<PreconditionFailure> {
+    let details = proto::google::rpc::Status::decode(status.details()).ok()?;
+    details
+        .details
+        .iter()
+        .find(|any| any.type_url.ends_with("/google.rpc.PreconditionFailure"))
+        .and_then(|any| PreconditionFailure::decode(any.value.as_ref()).ok())
+}
+
+/// The `google.rpc.BadRequest` in an `INVALID_ARGUMENT` status' details:
+/// every field of the request that broke a rule, with what is wrong with it.
+pub fn bad_request(status: &Status) -> Option<BadRequest> {
+    let details = proto::google::rpc::Status::decode(status.details()).ok()?;
+    details
+        .details
+        .iter()
+        .find(|any| any.type_url.ends_with("/google.rpc.BadRequest"))
+        .and_then(|any| BadRequest::decode(any.value.as_ref()).ok())
+}
+
+/// The `google.rpc.ErrorInfo` in a status' details. Its `reason` says what
+/// went wrong in the same words whatever language the message is in.
+pub fn error_info(status: &Status) -> Option<ErrorInfo> {
+    let details = proto::google::rpc::Status::decode(status.details()).ok()?;
+    details
+        .details
+        .iter()
+        .find(|any| any.type_url.ends_with("/google.rpc.ErrorInfo"))
+        .and_then(|any| ErrorInfo::decode(any.value.as_ref()).ok())
+}
@@ -0,0 +1,211 @@
+use proto::batch_write_entry::Write;
+use proto::{
+    BatchWriteEntry, BatchWriteRequest, BatchWriteResponse, CreateStudentRequest,
+    CreateStudentResponse, DeleteStudentRequest, DeleteStudentResponse, GetStatisticsRequest,
+    GetStatisticsResponse, GetStudentRequest, GetStudentResponse, ListStudentsByStandingRequest,
+    ListStudentsRequest, ListStudentsResponse, Student, UpdateStudentRequest,
+    UpdateStudentResponse, ValidateStudentRequest, ValidateStudentResponse,
+};
+use std::fmt::Debug;
+use std::time::Duration;
+use tonic::metadata::{KeyAndValueRef, MetadataMap};
+use tonic::{Request, Response, Status};
+
+// Metadata keys whose values never show up in verbose output
+const SENSITIVE_METADATA: &[&str] = &["authorization", "cookie", "x-api-key"];
+
+/// Messages that can be logged with their sensitive fields masked.
+pub(crate) trait Redact {
+    fn redact(&self) -> Self;
+}
+
+impl Redact for Student {
+    fn redact(&self) -> Self {
+        Student {
+            email: mask_email(&self.email),
+            ..self.clone()
+        }
+    }
+}
+
+macro_rules! redact_student_field {
+    ($($message:ty),* $(,)?) => {$(
+        impl Redact for $message {
+            fn redact(&self) -> Self {
+                Self {
+                    student: self.student.as_ref().map(Redact::redact),
+                }
+            }
+        }
+    )*};
+}
+
+redact_student_field!(
+    CreateStudentRequest,
+    CreateStudentResponse,
+    GetStudentResponse,
+    UpdateStudentRequest,
+    UpdateStudentResponse,
+    ValidateStudentRequest,
+);
+
+macro_rules! redact_nothing {
+    ($($message:ty),* $(,)?) => {$(
+        impl Redact for $message {
+            fn redact(&self) -> Self {
+                self.clone()
+            }
+        }
+    )*};
+}
+
+redact_nothing!(
+    GetStatisticsRequest,
+    GetStatisticsResponse,
+    GetStudentRequest,
+    DeleteStudentRequest,
+    DeleteStudentResponse,
+    ListStudentsRequest,
+    ListStudentsByStandingRequest,
+    ValidateStudentResponse,
+);
+
+impl Redact for BatchWriteRequest {
+    fn redact(&self) -> Self {
+        Self {
+            entries: self
+                .entries
+                .iter()
+                .map(|entry| BatchWriteEntry {
+                    write: match &entry.write {
+                        Some(Write::Create(student)) => Some(Write::Create(student.redact())),
+                        Some(Write::Update(student)) => Some(Write::Update(student.redact())),
+                        write => write.clone(),
+                    },
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Redact for BatchWriteResponse {
+    fn redact(&self) -> Self {
+        Self {
+            students: self.students.iter().map(Redact::redact).collect(),
+        }
+    }
+}
+
+impl Redact for ListStudentsResponse {
+    fn redact(&self) -> Self {
+        Self {
+            students: self.students.iter().map(Redact::redact).collect(),
+            ..self.clone()
+        }
+    }
+}
+
+pub(crate) fn log_request<Req: Redact + Debug>(method: &str, request: &Request<Req>) {
+    eprintln!(
+        "➡️  {} {:?} metadata={}",
+        method,
+        request.get_ref().redact(),
+        format_metadata(request.metadata())
+    );
+}
+
+pub(crate) fn log_response<Res: Redact + Debug>(
+    method: &str,
+    result: &Result<Response<Res>, Status>,
+    elapsed: Duration,
+) {
+    let metadata = match result {
+        Ok(response) => {
+            eprintln!(
+                "⬅️  {} OK in {:.1?} metadata={} {:?}",
+                method,
+                elapsed,
+                format_metadata(response.metadata()),
+                response.get_ref().redact()
+            );
+            response.metadata()
+        }
+        Err(status) => {
+            eprintln!(
+                "⬅️  {} {:?} in {:.1?} metadata={}: {}",
+                method,
+                status.code(),
+                elapsed,
+                format_metadata(status.metadata()),
+                status.message()
+            );
+            status.metadata()
+        }
+    };
+    log_server_timing(metadata, elapsed);
+}
+
+// Break down the server's `server-timing` metadata; whatever the server did
+// not account for was spent on the network and in the client
+fn log_server_timing(metadata: &MetadataMap, elapsed: Duration) {
+    let Some(timing) = metadata.get("server-timing").and_then(|v| v.to_str().ok()) else {
+        return;
+    };
+    let instance = metadata
+        .get("server-instance")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("unknown");
+
+    let mut parts = Vec::new();
+    let mut server_total = None;
+    for entry in timing.split(',') {
+        let mut fields = entry.trim().split(';');
+        let name = fields.next().unwrap_or_default();
+        let duration = fields
+            .find_map(|field| field.trim().strip_prefix("dur="))
+            .and_then(|ms| ms.parse::<f64>().ok());
+        if let Some(ms) = duration {
+            parts.push(format!("{} {:.2}ms", name, ms));
+            if name == "total" {
+                server_total = Some(ms);
+            }
+        }
+    }
+    if let Some(total) = server_total {
+        let round_trip = elapsed.as_secs_f64() * 1000.0;
+        parts.push(format!(
+            "network+client {:.2}ms",
+            (round_trip - total).max(0.0)
+        ));
+    }
+
+    eprintln!("⏱️  server {}: {}", instance, parts.join(", "));
+}
+
+fn format_metadata(metadata: &MetadataMap) -> String {
+    let entries: Vec<String> = metadata
+        .iter()
+        .map(|entry| match entry {
+            KeyAndValueRef::Ascii(key, _) if SENSITIVE_METADATA.contains(&key.as_str()) => {
+                format!("{}=<redacted>", key)
+            }
+            KeyAndValueRef::Ascii(key, value) => {
+                format!("{}={}", key, value.to_str().unwrap_or("<non-ascii>"))
+            }
+            KeyAndValueRef::Binary(key, _) => format!("{}=<binary>", key),
+        })
+        .collect();
+    format!("{{{}}}", entries.join(", "))
+}
+
+// Keep the first character and the domain: "alice@uni.edu" -> "a***@uni.edu"
+fn mask_email(email: &str) -> String {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            let first = local.chars().next().map(String::from).unwrap_or_default();
+            format!("{}***@{}", first, domain)
+        }
+        None if email.is_empty() => String::new(),
+        None => "***".to_string(),
+    }
+}
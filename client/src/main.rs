@@ -1,11 +1,7 @@
-use proto::student_service_client::StudentServiceClient;
-use proto::{
-    CreateStudentRequest, DeleteStudentRequest, GetStudentRequest, ListStudentsRequest, Student,
-    UpdateStudentRequest,
-};
-use tonic::transport::Channel;
-
-type StudentClient = StudentServiceClient<Channel>;
+use client::StudentClient;
+use futures::StreamExt;
+use proto::Student;
+use std::time::Duration;
 
 async fn create_sample_students(client: &mut StudentClient) -> Result<Vec<String>, Box<dyn std::error::Error>> {
     println!("\n🎓 Creating sample students...");
@@ -18,6 +14,7 @@ async fn create_sample_students(client: &mut StudentClient) -> Result<Vec<String
             age: 20,
             major: "Computer Science".to_string(),
             gpa: 3.8,
+            ..Default::default() // Timestamps are set by the server
         },
         Student {
             id: String::new(),
@@ -26,6 +23,7 @@ async fn create_sample_students(client: &mut StudentClient) -> Result<Vec<String
             age: 22,
             major: "Mathematics".to_string(),
             gpa: 3.6,
+            ..Default::default()
         },
         Student {
             id: String::new(),
@@ -34,19 +32,15 @@ async fn create_sample_students(client: &mut StudentClient) -> Result<Vec<String
             age: 19,
             major: "Physics".to_string(),
             gpa: 3.9,
+            ..Default::default()
         },
     ];
 
     let mut created_ids = Vec::new();
 
     for student in students {
-        let request = tonic::Request::new(CreateStudentRequest {
-            student: Some(student.clone()),
-        });
-
-        match client.create_student(request).await {
-            Ok(response) => {
-                let created_student = response.into_inner().student.unwrap();
+        match client.create_student(student.clone()).await {
+            Ok(created_student) => {
                 println!("✅ Created: {} (ID: {})", created_student.name, created_student.id);
                 created_ids.push(created_student.id);
             }
@@ -62,13 +56,8 @@ async fn create_sample_students(client: &mut StudentClient) -> Result<Vec<String
 async fn demonstrate_get_student(client: &mut StudentClient, student_id: &str) -> Result<(), Box<dyn std::error::Error>> {
     println!("\n🔍 Getting student by ID: {}", student_id);
     
-    let request = tonic::Request::new(GetStudentRequest {
-        id: student_id.to_string(),
-    });
-
-    match client.get_student(request).await {
-        Ok(response) => {
-            let student = response.into_inner().student.unwrap();
+    match client.get_student(student_id).await {
+        Ok(student) => {
             println!("✅ Found student:");
             println!("   Name: {}", student.name);
             println!("   Email: {}", student.email);
@@ -88,12 +77,8 @@ async fn demonstrate_update_student(client: &mut StudentClient, student_id: &str
     println!("\n📝 Updating student: {}", student_id);
     
     // First get the current student
-    let get_request = tonic::Request::new(GetStudentRequest {
-        id: student_id.to_string(),
-    });
-
-    let current_student = match client.get_student(get_request).await {
-        Ok(response) => response.into_inner().student.unwrap(),
+    let current_student = match client.get_student(student_id).await {
+        Ok(student) => student,
         Err(e) => {
             println!("❌ Failed to get student for update: {}", e);
             return Ok(());
@@ -102,21 +87,13 @@ async fn demonstrate_update_student(client: &mut StudentClient, student_id: &str
 
     // Update the student's GPA and major
     let updated_student = Student {
-        id: current_student.id,
-        name: current_student.name,
-        email: current_student.email,
-        age: current_student.age,
         major: "Computer Engineering".to_string(), // Changed major
         gpa: 3.95, // Improved GPA
+        ..current_student
     };
 
-    let update_request = tonic::Request::new(UpdateStudentRequest {
-        student: Some(updated_student.clone()),
-    });
-
-    match client.update_student(update_request).await {
-        Ok(response) => {
-            let student = response.into_inner().student.unwrap();
+    match client.update_student(updated_student).await {
+        Ok(student) => {
             println!("✅ Updated student:");
             println!("   Name: {}", student.name);
             println!("   Major: {} (changed)", student.major);
@@ -133,43 +110,34 @@ async fn demonstrate_update_student(client: &mut StudentClient, student_id: &str
 async fn demonstrate_list_students(client: &mut StudentClient) -> Result<(), Box<dyn std::error::Error>> {
     println!("\n📋 Listing all students...");
     
-    let request = tonic::Request::new(ListStudentsRequest {
-        page_size: 10,
-        page_token: String::new(),
-    });
-
-    match client.list_students(request).await {
-        Ok(response) => {
-            let response = response.into_inner();
-            println!("✅ Found {} students (total: {}):", response.students.len(), response.total_count);
-            
-            for (i, student) in response.students.iter().enumerate() {
+    // `list_all` follows `next_page_token` for us, one page at a time
+    let mut students = Box::pin(client.list_all(2));
+    let mut count = 0;
+
+    while let Some(student) = students.next().await {
+        match student {
+            Ok(student) => {
+                count += 1;
                 println!("   {}. {} - {} (GPA: {:.2})", 
-                    i + 1, student.name, student.major, student.gpa);
+                    count, student.name, student.major, student.gpa);
             }
-            
-            if !response.next_page_token.is_empty() {
-                println!("   (More students available - next page token: {})", response.next_page_token);
+            Err(e) => {
+                println!("❌ Failed to list students: {}", e);
+                return Ok(());
             }
         }
-        Err(e) => {
-            println!("❌ Failed to list students: {}", e);
-        }
     }
 
+    println!("✅ Found {} students", count);
+
     Ok(())
 }
 
 async fn demonstrate_delete_student(client: &mut StudentClient, student_id: &str) -> Result<(), Box<dyn std::error::Error>> {
     println!("\n🗑️  Deleting student: {}", student_id);
     
-    let request = tonic::Request::new(DeleteStudentRequest {
-        id: student_id.to_string(),
-    });
-
-    match client.delete_student(request).await {
+    match client.delete_student(student_id).await {
         Ok(response) => {
-            let response = response.into_inner();
             if response.success {
                 println!("✅ {}", response.message);
             } else {
@@ -187,10 +155,15 @@ async fn demonstrate_delete_student(client: &mut StudentClient, student_id: &str
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("🚀 Starting Student Management gRPC Client Demo");
+
+    // `--verbose` / `-v` logs every request and response to stderr
+    let verbose = std::env::args().skip(1).any(|arg| arg == "--verbose" || arg == "-v");
     
-    // Connect to the server
-    let mut client = StudentServiceClient::connect("http://[::1]:50051").await?;
-    println!("✅ Connected to gRPC server");
+    // Connect lazily and give the server some time to come up
+    let mut client = StudentClient::connect_lazy("http://[::1]:50051")?
+        .with_wait_for_ready(Duration::from_secs(30))
+        .with_verbose(verbose);
+    println!("✅ Client ready (waiting up to 30s for the gRPC server)");
 
     // Demonstrate all CRUD operations
     
@@ -0,0 +1,245 @@
+//! Retry policies and timeouts from a standard gRPC service config.
+//!
+//! The JSON is the one every gRPC implementation reads (`methodConfig`, each
+//! with `name`, `timeout`, and `retryPolicy`), so one file can tune the
+//! clients in every language. Other fields, such as `hedgingPolicy` and
+//! `retryThrottling`, are accepted and ignored.
+
+use serde::Deserialize;
+use std::time::Duration;
+use tonic::{Code, Status};
+
+// The most attempts a retry policy may ask for; more are capped, as in
+// other gRPC clients
+const MAX_ATTEMPTS: u32 = 5;
+
+// Server metadata asking the client to wait so many milliseconds before
+// retrying, or, when negative, not to retry
+const RETRY_PUSHBACK: &str = "grpc-retry-pushback-ms";
+
+/// Per-method timeouts and retry policies.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ServiceConfig {
+    methods: Vec<(Name, MethodConfig)>,
+}
+
+/// How calls to one method, service, or to everything are made.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MethodConfig {
+    /// Deadline of the whole call, across every attempt
+    pub timeout: Option<Duration>,
+    pub retry: Option<RetryPolicy>,
+}
+
+/// When a failed call is tried again, and how long to wait first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Including the first; at most 5
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub backoff_multiplier: f64,
+    pub retryable_codes: Vec<Code>,
+}
+
+impl RetryPolicy {
+    /// How long to wait after failed attempt `attempt` (from 1), unless the
+    /// server pushed back.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = self.backoff_multiplier.powi(attempt as i32 - 1);
+        self.initial_backoff
+            .mul_f64(factor.min(1e6))
+            .min(self.max_backoff)
+    }
+
+    /// Whether `status` may be retried after `attempt` attempts, and after
+    /// how long.
+    pub fn retry_after(&self, status: &Status, attempt: u32) -> Option<Duration> {
+        if attempt >= self.max_attempts || !self.retryable_codes.contains(&status.code()) {
+            return None;
+        }
+        match status.metadata().get(RETRY_PUSHBACK) {
+            Some(pushback) => {
+                let millis = pushback.to_str().ok()?.trim().parse::<u64>().ok()?;
+                Some(Duration::from_millis(millis))
+            }
+            None => Some(self.backoff(attempt)),
+        }
+    }
+}
+
+// What a method config applies to: a method, a whole service, or anything
+#[derive(Debug, Clone, PartialEq)]
+struct Name {
+    service: String,
+    method: String,
+}
+
+impl ServiceConfig {
+    /// Parse a service config, e.g. read from a file.
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let raw: RawServiceConfig = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let mut methods = Vec::new();
+        for (n, raw) in raw.method_config.into_iter().enumerate() {
+            let at = |field: &str| format!("methodConfig[{}].{}", n, field);
+            let config = MethodConfig {
+                timeout: raw
+                    .timeout
+                    .as_deref()
+                    .map(|timeout| {
+                        duration(timeout).map_err(|e| format!("{}: {}", at("timeout"), e))
+                    })
+                    .transpose()?,
+                retry: raw
+                    .retry_policy
+                    .map(|policy| {
+                        retry_policy(policy).map_err(|e| format!("{}{}", at("retryPolicy"), e))
+                    })
+                    .transpose()?,
+            };
+            for (m, name) in raw.name.into_iter().enumerate() {
+                let name = Name {
+                    service: name.service.unwrap_or_default(),
+                    method: name.method.unwrap_or_default(),
+                };
+                if name.service.is_empty() && !name.method.is_empty() {
+                    return Err(format!(
+                        "{}: a method needs its service",
+                        at(&format!("name[{}]", m))
+                    ));
+                }
+                if methods.iter().any(|(known, _)| *known == name) {
+                    return Err(format!("{}: named twice", at(&format!("name[{}]", m))));
+                }
+                methods.push((name, config.clone()));
+            }
+        }
+        Ok(Self { methods })
+    }
+
+    /// The config for `method` of `service` (e.g. `student.StudentService`):
+    /// the one naming the method, else its service, else the default.
+    pub fn method(&self, service: &str, method: &str) -> Option<&MethodConfig> {
+        let find = |service: &str, method: &str| {
+            self.methods
+                .iter()
+                .find(|(name, _)| name.service == service && name.method == method)
+                .map(|(_, config)| config)
+        };
+        find(service, method)
+            .or_else(|| find(service, ""))
+            .or_else(|| find("", ""))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawServiceConfig {
+    #[serde(default)]
+    method_config: Vec<RawMethodConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawMethodConfig {
+    #[serde(default)]
+    name: Vec<RawName>,
+    timeout: Option<String>,
+    retry_policy: Option<RawRetryPolicy>,
+}
+
+#[derive(Deserialize)]
+struct RawName {
+    service: Option<String>,
+    method: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawRetryPolicy {
+    max_attempts: Option<u32>,
+    initial_backoff: Option<String>,
+    max_backoff: Option<String>,
+    backoff_multiplier: Option<f64>,
+    #[serde(default)]
+    retryable_status_codes: Vec<serde_json::Value>,
+}
+
+// Every field is required, as in other gRPC clients
+fn retry_policy(raw: RawRetryPolicy) -> Result<RetryPolicy, String> {
+    let max_attempts = match raw.max_attempts {
+        Some(attempts) if attempts >= 2 => attempts.min(MAX_ATTEMPTS),
+        _ => return Err(".maxAttempts: expected 2 or more".to_string()),
+    };
+    let backoff = |field: &str, text: Option<String>| match text.as_deref().map(duration) {
+        Some(Ok(backoff)) if !backoff.is_zero() => Ok(backoff),
+        Some(Err(e)) => Err(format!(".{}: {}", field, e)),
+        _ => Err(format!(".{}: expected a duration above 0s", field)),
+    };
+    let initial_backoff = backoff("initialBackoff", raw.initial_backoff)?;
+    let max_backoff = backoff("maxBackoff", raw.max_backoff)?;
+    let backoff_multiplier = match raw.backoff_multiplier {
+        Some(multiplier) if multiplier > 0.0 => multiplier,
+        _ => return Err(".backoffMultiplier: expected a number above 0".to_string()),
+    };
+    if raw.retryable_status_codes.is_empty() {
+        return Err(".retryableStatusCodes: expected at least one code".to_string());
+    }
+    let retryable_codes = raw
+        .retryable_status_codes
+        .iter()
+        .map(|code| {
+            status_code(code)
+                .ok_or_else(|| format!(".retryableStatusCodes: {} is not a status code", code))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(RetryPolicy {
+        max_attempts,
+        initial_backoff,
+        max_backoff,
+        backoff_multiplier,
+        retryable_codes,
+    })
+}
+
+// A duration in its JSON form, seconds with an `s`, e.g. `1.5s`
+fn duration(text: &str) -> Result<Duration, String> {
+    text.strip_suffix('s')
+        .and_then(|seconds| seconds.parse::<f64>().ok())
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| format!("{}: expected seconds, e.g. 1.5s", text))
+}
+
+// A status code by name, e.g. `UNAVAILABLE`, or by number
+fn status_code(code: &serde_json::Value) -> Option<Code> {
+    const NAMES: [&str; 17] = [
+        "OK",
+        "CANCELLED",
+        "UNKNOWN",
+        "INVALID_ARGUMENT",
+        "DEADLINE_EXCEEDED",
+        "NOT_FOUND",
+        "ALREADY_EXISTS",
+        "PERMISSION_DENIED",
+        "RESOURCE_EXHAUSTED",
+        "FAILED_PRECONDITION",
+        "ABORTED",
+        "OUT_OF_RANGE",
+        "UNIMPLEMENTED",
+        "INTERNAL",
+        "UNAVAILABLE",
+        "DATA_LOSS",
+        "UNAUTHENTICATED",
+    ];
+    let number = match code {
+        serde_json::Value::String(name) => NAMES.iter().position(|known| known == name)? as i32,
+        serde_json::Value::Number(number) => i32::try_from(number.as_u64()?).ok()?,
+        _ => return None,
+    };
+    match Code::from_i32(number) {
+        // Unknown numbers come back as UNKNOWN, and OK is never retried
+        Code::Unknown if number != Code::Unknown as i32 => None,
+        Code::Ok => None,
+        code => Some(code),
+    }
+}
@@ -0,0 +1,126 @@
+//! Students checked against the server's rules before they are sent.
+//!
+//! The rules come from `student-core`, the crate the server enforces them
+//! with, so a student the client lets through is one the server's rules
+//! accept, and one it refuses fails at once, without a round trip, with
+//! the status the server would have sent: `INVALID_ARGUMENT` with the same
+//! `ErrorInfo` reason and every field at fault in a `BadRequest`. Rules
+//! that need the server's data or settings, such as a `major_id` in its
+//! catalog or phone numbers, which it reads in its default region, are
+//! still checked there.
+
+use prost::Message;
+use proto::batch_write_entry::Write;
+use proto::google::rpc::bad_request::FieldViolation;
+use proto::google::rpc::{BadRequest, ErrorInfo, LocalizedMessage};
+use proto::{BatchWriteEntry, Student, ValidateStudentResponse};
+use student_core::{Problem, Profile, ERROR_DOMAIN};
+use tonic::{Code, Status};
+
+/// `INVALID_ARGUMENT` for every rule `student` breaks, as the server would
+/// refuse it, or `Ok` if it breaks none.
+pub fn check_student(student: &Student) -> Result<(), Status> {
+    let problems = problems(student);
+    match problems.first() {
+        Some((_, first)) => Err(invalid(first, &problems, None)),
+        None => Ok(()),
+    }
+}
+
+/// Like [`check_student`], for the students `entries` create or update:
+/// the first entry at fault, with its index in the message,
+/// `ErrorInfo.metadata`, and each field, as `BatchWrite` reports it.
+pub fn check_entries(entries: &[BatchWriteEntry]) -> Result<(), Status> {
+    for (index, entry) in entries.iter().enumerate() {
+        let (Some(Write::Create(student)) | Some(Write::Update(student))) = &entry.write else {
+            continue;
+        };
+        let problems = problems(student);
+        if let Some((_, first)) = problems.first() {
+            return Err(invalid(first, &problems, Some(index)));
+        }
+    }
+    Ok(())
+}
+
+/// What `ValidateStudent` would answer for `student`, as far as the rules
+/// here go: a student they let through may still break one the server
+/// checks itself.
+pub fn validate_student(student: &Student) -> ValidateStudentResponse {
+    let violations: Vec<_> = problems(student)
+        .into_iter()
+        .map(|(field, problem)| proto::FieldViolation {
+            field: field.to_string(),
+            description: problem.to_string(),
+        })
+        .collect();
+    let message = violations
+        .iter()
+        .map(|violation| violation.description.as_str())
+        .collect::<Vec<_>>()
+        .join("; ");
+    ValidateStudentResponse {
+        valid: violations.is_empty(),
+        message,
+        violations,
+    }
+}
+
+// Every rule `student` breaks, as the field and what is wrong with it,
+// with the student tidied up as the server's default (lenient) validation
+// would first
+fn problems(student: &Student) -> Vec<(&'static str, Problem)> {
+    Profile::Lenient.check(&mut student.clone())
+}
+
+// The status the server describes `problems` with, in English
+fn invalid(first: &Problem, problems: &[(&str, Problem)], entry: Option<usize>) -> Status {
+    let prefix = entry.map_or(String::new(), |index| format!("entries[{}]", index));
+    let message = match entry {
+        Some(_) => format!("{}: {}", prefix, first),
+        None => first.to_string(),
+    };
+    let mut metadata = first.metadata();
+    if let Some(index) = entry {
+        metadata.insert("entry".to_string(), index.to_string());
+    }
+    let info = ErrorInfo {
+        reason: first.reason().to_string(),
+        domain: ERROR_DOMAIN.to_string(),
+        metadata,
+    };
+    let localized = LocalizedMessage {
+        locale: "en".to_string(),
+        message: first.to_string(),
+    };
+    let bad_request = BadRequest {
+        field_violations: problems
+            .iter()
+            .map(|(field, problem)| FieldViolation {
+                field: match entry {
+                    Some(_) => format!("{}.{}", prefix, field),
+                    None => field.to_string(),
+                },
+                description: problem.to_string(),
+            })
+            .collect(),
+    };
+    let any = |name: &str, value: Vec<u8>| proto::Any {
+        type_url: format!("type.googleapis.com/google.rpc.{}", name),
+        value: value.into(),
+    };
+    let details = proto::google::rpc::Status {
+        code: Code::InvalidArgument as i32,
+        message: message.clone(),
+        details: vec![
+            any("ErrorInfo", info.encode_to_vec()),
+            any("LocalizedMessage", localized.encode_to_vec()),
+            any("BadRequest", bad_request.encode_to_vec()),
+        ],
+    };
+    Status::with_details(
+        Code::InvalidArgument,
+        message,
+        details.encode_to_vec().into(),
+    )
+}
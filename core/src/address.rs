@@ -0,0 +1,180 @@
+//! What a valid postal address looks like in each country.
+//!
+//! Countries are ISO 3166-1 alpha-2 codes (`US`, `GB`). For the countries
+//! in [`COUNTRIES`] the postal code must fit one of the country's formats,
+//! and where the country lists its regions (US states, Canadian provinces,
+//! Australian states) the region must be one of them. Addresses in other
+//! countries only need a street line and a city.
+//!
+//! [`crate::problems`] checks addresses against these rules; the server's
+//! lenient validation also rewrites a postal code into its format, so
+//! `k1a0b1` becomes `K1A 0B1`.
+
+use crate::phone;
+
+/// The address rules of one country.
+#[derive(Debug)]
+pub struct Country {
+    pub code: &'static str,
+    /// Formats of a postal code: `9` is a digit, `A` a letter, and anything
+    /// else itself; an empty list means no postal code is checked
+    pub postal_codes: &'static [&'static str],
+    /// Codes of the regions, if the country's addresses need one
+    pub regions: &'static [&'static str],
+}
+
+/// The countries whose postal codes and regions are checked.
+pub const COUNTRIES: &[Country] = &[
+    Country {
+        code: "US",
+        postal_codes: &["99999", "99999-9999"],
+        regions: &[
+            "AL", "AK", "AZ", "AR", "CA", "CO", "CT", "DE", "DC", "FL", "GA", "HI", "ID", "IL",
+            "IN", "IA", "KS", "KY", "LA", "ME", "MD", "MA", "MI", "MN", "MS", "MO", "MT", "NE",
+            "NV", "NH", "NJ", "NM", "NY", "NC", "ND", "OH", "OK", "OR", "PA", "RI", "SC", "SD",
+            "TN", "TX", "UT", "VT", "VA", "WA", "WV", "WI", "WY", "AS", "GU", "MP", "PR", "VI",
+            "AA", "AE", "AP",
+        ],
+    },
+    Country {
+        code: "CA",
+        postal_codes: &["A9A 9A9"],
+        regions: &[
+            "AB", "BC", "MB", "NB", "NL", "NS", "NT", "NU", "ON", "PE", "QC", "SK", "YT",
+        ],
+    },
+    Country {
+        code: "AU",
+        postal_codes: &["9999"],
+        regions: &["ACT", "NSW", "NT", "QLD", "SA", "TAS", "VIC", "WA"],
+    },
+    Country {
+        code: "GB",
+        postal_codes: &[
+            "A9 9AA", "A99 9AA", "AA9 9AA", "AA99 9AA", "A9A 9AA", "AA9A 9AA",
+        ],
+        regions: &[],
+    },
+    Country {
+        code: "BR",
+        postal_codes: &["99999-999"],
+        regions: &[],
+    },
+    Country {
+        code: "CN",
+        postal_codes: &["999999"],
+        regions: &[],
+    },
+    Country {
+        code: "DE",
+        postal_codes: &["99999"],
+        regions: &[],
+    },
+    Country {
+        code: "ES",
+        postal_codes: &["99999"],
+        regions: &[],
+    },
+    Country {
+        code: "FR",
+        postal_codes: &["99999"],
+        regions: &[],
+    },
+    Country {
+        code: "IN",
+        postal_codes: &["999999"],
+        regions: &[],
+    },
+    Country {
+        code: "JP",
+        postal_codes: &["999-9999"],
+        regions: &[],
+    },
+    Country {
+        code: "MX",
+        postal_codes: &["99999"],
+        regions: &[],
+    },
+    Country {
+        code: "NL",
+        postal_codes: &["9999 AA"],
+        regions: &[],
+    },
+];
+
+/// Whether `code` names a country, in any case.
+pub fn is_country(code: &str) -> bool {
+    // The phone metadata knows every region with a country code of its own
+    phone::parse_region(code).is_ok()
+}
+
+/// The rules of the country with `code`, if it has any.
+pub fn country(code: &str) -> Option<&'static Country> {
+    COUNTRIES
+        .iter()
+        .find(|country| country.code.eq_ignore_ascii_case(code.trim()))
+}
+
+/// The postal code formats of `country`, such as `99999 | 99999-9999`.
+pub fn postal_code_formats(country: &str) -> String {
+    self::country(country)
+        .map(|country| country.postal_codes.join(" | "))
+        .unwrap_or_default()
+}
+
+// Neither a letter nor a digit: a separator such as a space or hyphen
+fn is_separator(c: char) -> bool {
+    !c.is_ascii_alphanumeric()
+}
+
+fn fits(format: char, c: char) -> bool {
+    match format {
+        '9' => c.is_ascii_digit(),
+        'A' => c.is_ascii_alphabetic(),
+        _ => false,
+    }
+}
+
+impl Country {
+    /// `postal_code` written in the country's format, ignoring case and
+    /// separators, or `None` if it fits no format. A country without
+    /// formats takes any code as given.
+    pub fn postal_code(&self, postal_code: &str) -> Option<String> {
+        if self.postal_codes.is_empty() {
+            return Some(postal_code.to_string());
+        }
+        let characters: Vec<char> = postal_code
+            .chars()
+            .filter(|&c| !is_separator(c))
+            .map(|c| c.to_ascii_uppercase())
+            .collect();
+        self.postal_codes.iter().find_map(|format| {
+            let mut characters = characters.iter();
+            let mut written = String::new();
+            for place in format.chars() {
+                if is_separator(place) {
+                    written.push(place);
+                    continue;
+                }
+                match characters.next() {
+                    Some(&c) if fits(place, c) => written.push(c),
+                    _ => return None,
+                }
+            }
+            characters.next().is_none().then_some(written)
+        })
+    }
+
+    /// `region` as the country writes it (upper case), or `None` if the
+    /// country lists its regions and this is not one. A country that does
+    /// not list them takes any region as given.
+    pub fn region(&self, region: &str) -> Option<String> {
+        if self.regions.is_empty() {
+            return Some(region.to_string());
+        }
+        self.regions
+            .iter()
+            .find(|known| known.eq_ignore_ascii_case(region))
+            .map(|known| known.to_string())
+    }
+}
@@ -0,0 +1,73 @@
+//! Client-owned metadata on a student.
+//!
+//! `annotations` is a map of strings the server stores and returns as it
+//! was given: it is not tidied by lenient validation, not matched by
+//! `ListStudents` filters or duplicate detection, and not indexed by any
+//! backend. Only its shape is checked: keys in the format below, and at
+//! most [`MAX_BYTES`] of keys and values together.
+//!
+//! A key is a name of up to 63 letters, digits, `-`, `_`, and `.`, which
+//! starts and ends with a letter or digit, optionally after a prefix that
+//! says who owns it: a lower-case DNS name of up to 253 characters and a
+//! `/`, as in `registrar.example.edu/batch-id`.
+
+use crate::Problem;
+use std::collections::HashMap;
+
+/// The most bytes of keys and values a student's annotations may hold.
+pub const MAX_BYTES: usize = 16 * 1024;
+
+const MAX_NAME: usize = 63;
+const MAX_PREFIX: usize = 253;
+
+/// Whether `key` can name an annotation.
+pub fn is_key(key: &str) -> bool {
+    let (prefix, name) = match key.rsplit_once('/') {
+        Some((prefix, name)) => (Some(prefix), name),
+        None => (None, key),
+    };
+    prefix.is_none_or(is_prefix) && is_name(name)
+}
+
+fn is_name(name: &str) -> bool {
+    let alphanumeric = |c: char| c.is_ascii_alphanumeric();
+    name.len() <= MAX_NAME
+        && name.starts_with(alphanumeric)
+        && name.ends_with(alphanumeric)
+        && name
+            .chars()
+            .all(|c| alphanumeric(c) || matches!(c, '-' | '_' | '.'))
+}
+
+// Dot-separated labels of lower-case letters, digits, and inner hyphens
+fn is_prefix(prefix: &str) -> bool {
+    let label = |label: &str| {
+        let lower_or_digit = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit();
+        label.starts_with(lower_or_digit)
+            && label.ends_with(lower_or_digit)
+            && label.chars().all(|c| lower_or_digit(c) || c == '-')
+    };
+    prefix.len() <= MAX_PREFIX && prefix.split('.').all(label)
+}
+
+/// Keys and values together, in bytes.
+pub fn size(annotations: &HashMap<String, String>) -> usize {
+    annotations
+        .iter()
+        .map(|(key, value)| key.len() + value.len())
+        .sum()
+}
+
+/// Every rule `annotations` break: each bad key, in order, then the size.
+pub fn problems(annotations: &HashMap<String, String>) -> Vec<Problem> {
+    let mut bad_keys: Vec<&String> = annotations.keys().filter(|key| !is_key(key)).collect();
+    bad_keys.sort();
+    let mut problems: Vec<Problem> = bad_keys
+        .into_iter()
+        .map(|key| Problem::AnnotationKeyInvalid(key.clone()))
+        .collect();
+    if size(annotations) > MAX_BYTES {
+        problems.push(Problem::AnnotationsTooLarge);
+    }
+    problems
+}
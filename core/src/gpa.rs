@@ -0,0 +1,142 @@
+//! GPAs as exact decimals.
+//!
+//! A [`Gpa`] is a whole number of hundredths of a grade point, so 3.5 is
+//! 350 and comparing, summing, or rounding GPAs never meets a binary
+//! fraction. On the wire a student's GPA is the decimal string
+//! `gpa_decimal` ("3.50"); the `double gpa` of the v1 schema is still
+//! accepted and always filled in, so older clients keep working. A double
+//! is read as the decimal it prints as, so 1.005 is 1.005 and rounds to
+//! 1.01, not to the 1.00 its binary value would.
+
+use proto::Student;
+use std::fmt;
+use std::str::FromStr;
+
+/// A GPA in hundredths of a grade point.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Gpa(i64);
+
+impl Gpa {
+    /// The lowest valid GPA, 0.00.
+    pub const MIN: Gpa = Gpa(0);
+    /// The highest valid GPA, 4.00.
+    pub const MAX: Gpa = Gpa(400);
+
+    pub fn from_hundredths(hundredths: i64) -> Self {
+        Gpa(hundredths)
+    }
+
+    pub fn hundredths(self) -> i64 {
+        self.0
+    }
+
+    /// Whether this is a GPA a student can have, from 0.00 to 4.00.
+    pub fn is_valid(self) -> bool {
+        (Gpa::MIN..=Gpa::MAX).contains(&self)
+    }
+
+    /// `text` rounded half up to hundredths, or `None` if it is not a
+    /// decimal number such as `3.25`, `-1`, or `.5`.
+    pub fn round(text: &str) -> Option<Gpa> {
+        read(text).map(|(gpa, _)| gpa)
+    }
+
+    /// `value` read as the decimal it prints as, rounded to hundredths;
+    /// `None` for NaN and infinities.
+    pub fn from_f64(value: f64) -> Option<Gpa> {
+        Gpa::round(&value.to_string())
+    }
+
+    /// The closest double, for the v1 `gpa` field.
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / 100.0
+    }
+}
+
+// `text` to hundredths, rounded half up, and whether that was exact
+fn read(text: &str) -> Option<(Gpa, bool)> {
+    let text = text.trim();
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    let all_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    if (whole.is_empty() && fraction.is_empty()) || !all_digits(whole) || !all_digits(fraction) {
+        return None;
+    }
+    // Far out of range either way; this keeps the sum from overflowing
+    let whole = whole.trim_start_matches('0');
+    let whole: i64 = if whole.len() > 15 {
+        10_i64.pow(15)
+    } else {
+        whole.parse().unwrap_or(0)
+    };
+    let mut places = fraction.bytes().map(|b| i64::from(b - b'0'));
+    let mut place = || places.next().unwrap_or(0);
+    let mut hundredths = whole * 100 + place() * 10 + place();
+    let exact = fraction.bytes().skip(2).all(|b| b == b'0');
+    if place() >= 5 {
+        hundredths += 1;
+    }
+    Some((Gpa(if negative { -hundredths } else { hundredths }), exact))
+}
+
+/// Exact only: fails on more than two decimal places rather than rounding.
+impl FromStr for Gpa {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match read(text) {
+            Some((gpa, true)) => Ok(gpa),
+            Some((_, false)) => Err(format!("{}: more than two decimal places", text)),
+            None => Err(format!("{}: not a decimal number such as 3.25", text)),
+        }
+    }
+}
+
+/// Two decimal places, as stored in `gpa_decimal`: `3.50`.
+impl fmt::Display for Gpa {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let hundredths = self.0.unsigned_abs();
+        write!(f, "{}{}.{:02}", sign, hundredths / 100, hundredths % 100)
+    }
+}
+
+/// The GPA `student` was given as written: `gpa_decimal` if set, else the
+/// v1 `gpa` as the decimal it prints as.
+pub fn given(student: &Student) -> String {
+    match student.gpa_decimal.trim() {
+        "" => student.gpa.to_string(),
+        decimal => decimal.to_string(),
+    }
+}
+
+/// The field `student`'s GPA was given in: `gpa_decimal` if set, else
+/// `gpa`.
+pub fn field(student: &Student) -> &'static str {
+    if student.gpa_decimal.trim().is_empty() {
+        "gpa"
+    } else {
+        "gpa_decimal"
+    }
+}
+
+/// Whether `student` has both forms and they are different GPAs, as when a
+/// client changes one and sends back the other as it read it.
+pub fn forms_disagree(student: &Student) -> bool {
+    let decimal = student.gpa_decimal.trim();
+    !decimal.is_empty() && Gpa::round(decimal) != Gpa::from_f64(student.gpa)
+}
+
+/// The GPA of a stored, and so valid, student.
+pub fn of(student: &Student) -> Gpa {
+    Gpa::round(&given(student)).unwrap_or_default()
+}
+
+/// Give `student` the GPA `gpa` in both its forms.
+pub fn set(student: &mut Student, gpa: Gpa) {
+    student.gpa_decimal = gpa.to_string();
+    student.gpa = gpa.to_f64();
+}
@@ -0,0 +1,279 @@
+//! The rules of a valid student, shared by the server, which enforces
+//! them, and the client, which checks input against them before sending
+//! it.
+//!
+//! [`problems`] lists every rule a student breaks, as the field at fault
+//! and a [`Problem`]; a validation [`Profile`] first tidies input up or
+//! also refuses what is not canonical. A problem's [`reason`](Problem::reason) and
+//! [`metadata`](Problem::metadata) are what the server sends in
+//! `google.rpc.ErrorInfo` when it refuses the student, and its `Display`
+//! is the server's English message; the server also has each message in
+//! the other languages it speaks.
+
+pub mod address;
+pub mod annotations;
+pub mod gpa;
+pub mod phone;
+mod profile;
+
+pub use profile::Profile;
+
+use gpa::Gpa;
+use proto::{Address, Student};
+use std::collections::HashMap;
+use std::fmt;
+
+/// `ErrorInfo.domain` for every error the server describes.
+pub const ERROR_DOMAIN: &str = "students.example.edu";
+
+/// A rule a student breaks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    NameEmpty,
+    EmailEmpty,
+    AgeOutOfRange,
+    GpaOutOfRange,
+    /// The GPA given
+    GpaInvalid(String),
+    CreditsNegative,
+    AddressLineEmpty,
+    CityEmpty,
+    /// The country code given
+    CountryUnknown(String),
+    /// The country, and the region given
+    RegionUnknown(String, String),
+    /// The country, and the postal code given
+    PostalCodeInvalid(String, String),
+    /// The key given
+    AnnotationKeyInvalid(String),
+    AnnotationsTooLarge,
+    IdNotCanonical,
+    NameNotCanonical,
+    PreferredNameNotCanonical,
+    EmailNotCanonical,
+    EmailInvalid,
+    MajorNotCanonical,
+    MajorIdNotCanonical,
+    GpaNotCanonical,
+    AddressNotCanonical,
+    /// The number given, in each of these
+    PhoneInvalid(String),
+    PhoneRegionRequired(String),
+    PhoneNotCanonical(String),
+}
+
+impl Problem {
+    /// `ErrorInfo.reason`: stable, and the same in every language.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Problem::NameEmpty => "NAME_EMPTY",
+            Problem::EmailEmpty => "EMAIL_EMPTY",
+            Problem::AgeOutOfRange => "AGE_OUT_OF_RANGE",
+            Problem::GpaOutOfRange => "GPA_OUT_OF_RANGE",
+            Problem::GpaInvalid(_) => "GPA_INVALID",
+            Problem::CreditsNegative => "CREDITS_NEGATIVE",
+            Problem::AddressLineEmpty => "ADDRESS_LINE_EMPTY",
+            Problem::CityEmpty => "CITY_EMPTY",
+            Problem::CountryUnknown(_) => "COUNTRY_UNKNOWN",
+            Problem::RegionUnknown(..) => "REGION_UNKNOWN",
+            Problem::PostalCodeInvalid(..) => "POSTAL_CODE_INVALID",
+            Problem::AnnotationKeyInvalid(_) => "ANNOTATION_KEY_INVALID",
+            Problem::AnnotationsTooLarge => "ANNOTATIONS_TOO_LARGE",
+            Problem::IdNotCanonical => "ID_NOT_CANONICAL",
+            Problem::NameNotCanonical => "NAME_NOT_CANONICAL",
+            Problem::PreferredNameNotCanonical => "PREFERRED_NAME_NOT_CANONICAL",
+            Problem::EmailNotCanonical => "EMAIL_NOT_CANONICAL",
+            Problem::EmailInvalid => "EMAIL_INVALID",
+            Problem::MajorNotCanonical => "MAJOR_NOT_CANONICAL",
+            Problem::MajorIdNotCanonical => "MAJOR_ID_NOT_CANONICAL",
+            Problem::GpaNotCanonical => "GPA_NOT_CANONICAL",
+            Problem::AddressNotCanonical => "ADDRESS_NOT_CANONICAL",
+            Problem::PhoneInvalid(_) => "PHONE_NUMBER_INVALID",
+            Problem::PhoneRegionRequired(_) => "PHONE_REGION_REQUIRED",
+            Problem::PhoneNotCanonical(_) => "PHONE_NUMBER_NOT_CANONICAL",
+        }
+    }
+
+    /// The values filled into the message, as `ErrorInfo.metadata`.
+    pub fn metadata(&self) -> HashMap<String, String> {
+        match self {
+            Problem::GpaInvalid(gpa) => HashMap::from([("gpa".to_string(), gpa.clone())]),
+            Problem::PhoneInvalid(number)
+            | Problem::PhoneRegionRequired(number)
+            | Problem::PhoneNotCanonical(number) => {
+                HashMap::from([("phone_number".to_string(), number.clone())])
+            }
+            Problem::CountryUnknown(country) => {
+                HashMap::from([("country".to_string(), country.clone())])
+            }
+            Problem::RegionUnknown(country, region) => HashMap::from([
+                ("country".to_string(), country.clone()),
+                ("region".to_string(), region.clone()),
+            ]),
+            Problem::PostalCodeInvalid(country, postal_code) => HashMap::from([
+                ("country".to_string(), country.clone()),
+                ("postal_code".to_string(), postal_code.clone()),
+                ("formats".to_string(), address::postal_code_formats(country)),
+            ]),
+            Problem::AnnotationKeyInvalid(key) => HashMap::from([("key".to_string(), key.clone())]),
+            Problem::AnnotationsTooLarge => {
+                HashMap::from([("max_bytes".to_string(), annotations::MAX_BYTES.to_string())])
+            }
+            _ => HashMap::new(),
+        }
+    }
+}
+
+/// The message in English.
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::NameEmpty => write!(f, "Student name cannot be empty"),
+            Problem::EmailEmpty => write!(f, "Student email cannot be empty"),
+            Problem::AgeOutOfRange => write!(f, "Student age must be between 0 and 150"),
+            Problem::GpaOutOfRange => write!(f, "Student GPA must be between 0.0 and 4.0"),
+            Problem::GpaInvalid(gpa) => write!(
+                f,
+                "Student GPA must be a decimal number such as 3.25, not {:?}",
+                gpa
+            ),
+            Problem::CreditsNegative => write!(f, "Student credits cannot be negative"),
+            Problem::AddressLineEmpty => write!(f, "Address line 1 cannot be empty"),
+            Problem::CityEmpty => write!(f, "Address city cannot be empty"),
+            Problem::CountryUnknown(country) => write!(
+                f,
+                "Unknown country {:?}; use an ISO 3166 code such as US",
+                country
+            ),
+            Problem::RegionUnknown(country, region) => {
+                write!(f, "{:?} is not a region of {}", region, country)
+            }
+            Problem::PostalCodeInvalid(country, postal_code) => write!(
+                f,
+                "Postal code {:?} is not valid in {}; expected {}",
+                postal_code,
+                country,
+                address::postal_code_formats(country)
+            ),
+            Problem::AnnotationKeyInvalid(key) => write!(
+                f,
+                "Annotation key {:?} must be a name of letters, digits, '-', '_', and '.', optionally after a DNS name and '/'",
+                key
+            ),
+            Problem::AnnotationsTooLarge => write!(
+                f,
+                "Annotations cannot hold more than {} bytes of keys and values",
+                annotations::MAX_BYTES
+            ),
+            Problem::IdNotCanonical => write!(f, "Student ID must not start or end with spaces"),
+            Problem::NameNotCanonical => write!(
+                f,
+                "Student name must be in Unicode NFC, without spaces around it or doubled spaces"
+            ),
+            Problem::PreferredNameNotCanonical => write!(
+                f,
+                "Preferred name must be in Unicode NFC, without spaces around it or doubled spaces"
+            ),
+            Problem::EmailNotCanonical => write!(
+                f,
+                "Student email must be lower case without spaces around it"
+            ),
+            Problem::EmailInvalid => write!(f, "Student email must look like name@domain.tld"),
+            Problem::MajorNotCanonical => write!(
+                f,
+                "Student major must be in Unicode NFC, without spaces around it or doubled spaces"
+            ),
+            Problem::MajorIdNotCanonical => write!(f, "Major ID must not start or end with spaces"),
+            Problem::GpaNotCanonical => {
+                write!(f, "Student GPA must have at most two decimal places")
+            }
+            Problem::AddressNotCanonical => write!(
+                f,
+                "Address parts must be written without extra spaces, with the country, region, and postal code as the country writes them"
+            ),
+            Problem::PhoneInvalid(number) => {
+                write!(f, "Not a valid phone number: {:?}", number)
+            }
+            Problem::PhoneRegionRequired(number) => write!(
+                f,
+                "Phone number {:?} needs a country code such as +1, as no default region is set",
+                number
+            ),
+            Problem::PhoneNotCanonical(number) => write!(
+                f,
+                "Phone number {:?} must be in E.164 form, like +12025550123, and listed once",
+                number
+            ),
+        }
+    }
+}
+
+/// Every rule `student` breaks, as the field and what is wrong with it.
+pub fn problems(student: &Student) -> Vec<(&'static str, Problem)> {
+    let mut problems = Vec::new();
+
+    if student.name.trim().is_empty() {
+        problems.push(("name", Problem::NameEmpty));
+    }
+    if student.email.trim().is_empty() {
+        problems.push(("email", Problem::EmailEmpty));
+    }
+    if student.age < 0 || student.age > 150 {
+        problems.push(("age", Problem::AgeOutOfRange));
+    }
+    let given = gpa::given(student);
+    match Gpa::round(&given) {
+        Some(gpa) if gpa.is_valid() => {}
+        Some(_) => problems.push((gpa::field(student), Problem::GpaOutOfRange)),
+        None => problems.push((gpa::field(student), Problem::GpaInvalid(given))),
+    }
+    if student.credits < 0 {
+        problems.push(("credits", Problem::CreditsNegative));
+    }
+    if let Some(address) = &student.address {
+        problems.extend(address_problems(address));
+    }
+    problems.extend(
+        annotations::problems(&student.annotations)
+            .into_iter()
+            .map(|problem| ("annotations", problem)),
+    );
+
+    problems
+}
+
+// A street line and a city everywhere; a known region and a postal code in
+// the right format where the country's rules say so
+fn address_problems(address: &Address) -> Vec<(&'static str, Problem)> {
+    let mut problems = Vec::new();
+    if address.line1.trim().is_empty() {
+        problems.push(("address.line1", Problem::AddressLineEmpty));
+    }
+    if address.city.trim().is_empty() {
+        problems.push(("address.city", Problem::CityEmpty));
+    }
+    let country = address.country.trim();
+    if !address::is_country(country) {
+        problems.push((
+            "address.country",
+            Problem::CountryUnknown(country.to_string()),
+        ));
+        return problems;
+    }
+    if let Some(rules) = address::country(country) {
+        let region = address.region.trim();
+        if rules.region(region).is_none() {
+            problems.push((
+                "address.region",
+                Problem::RegionUnknown(rules.code.to_string(), region.to_string()),
+            ));
+        }
+        if rules.postal_code(&address.postal_code).is_none() {
+            problems.push((
+                "address.postal_code",
+                Problem::PostalCodeInvalid(rules.code.to_string(), address.postal_code.clone()),
+            ));
+        }
+    }
+    problems
+}
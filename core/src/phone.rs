@@ -0,0 +1,39 @@
+//! Phone numbers, stored in E.164 form (`+12025550123`).
+//!
+//! Numbers are parsed and checked with the `phonenumber` crate, a port of
+//! libphonenumber's metadata. A number written with its country code
+//! (`+44 20 7031 3000`) needs nothing else; one written the national way
+//! (`(650) 253-0000`) is read as a number of the region given, such as the
+//! server's `--phone-region`, and is refused without one.
+
+use crate::Problem;
+use phonenumber::country::Id;
+use phonenumber::Mode;
+
+/// The region named by its ISO 3166 code, e.g. `US` or `gb`.
+pub fn parse_region(text: &str) -> Result<Id, String> {
+    text.trim()
+        .to_ascii_uppercase()
+        .parse()
+        .map_err(|_| format!("{}: not a region code such as US or GB", text))
+}
+
+/// `number` in E.164 form, reading it as a number of `region` unless it
+/// starts with a country code, or why it is not a phone number.
+pub fn normalize(number: &str, region: Option<Id>) -> Result<String, Problem> {
+    let number = number.trim();
+    if number.is_empty() {
+        return Err(Problem::PhoneInvalid(String::new()));
+    }
+    let international = number.starts_with('+');
+    if !international && region.is_none() {
+        return Err(Problem::PhoneRegionRequired(number.to_string()));
+    }
+    let parsed = phonenumber::parse(region.filter(|_| !international), number)
+        .map_err(|_| Problem::PhoneInvalid(number.to_string()))?;
+    // E.164 has no room for an extension, and dropping it would lose it
+    if !parsed.is_valid() || parsed.extension().is_some() {
+        return Err(Problem::PhoneInvalid(number.to_string()));
+    }
+    Ok(parsed.format().mode(Mode::E164).to_string())
+}
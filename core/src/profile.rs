@@ -0,0 +1,240 @@
+//! The lenient and strict validation [`Profile`]s: what becomes of input
+//! that follows the rules but is not written the canonical way.
+
+use crate::address;
+use crate::gpa::{self, Gpa};
+use crate::phone;
+use crate::Problem;
+use phonenumber::country::Id;
+use proto::{Address, Student};
+use std::str::FromStr;
+use unicode_normalization::UnicodeNormalization;
+
+// `address` as lenient validation stores it
+fn tidy_address(address: &Address) -> Address {
+    let country = address.country.trim().to_ascii_uppercase();
+    let mut region = collapse(&address.region);
+    let mut postal_code = collapse(&address.postal_code);
+    if let Some(rules) = address::country(&country) {
+        region = rules.region(&region).unwrap_or(region);
+        postal_code = rules.postal_code(&postal_code).unwrap_or(postal_code);
+    }
+    Address {
+        line1: collapse(&address.line1),
+        line2: collapse(&address.line2),
+        city: collapse(&address.city),
+        region,
+        postal_code,
+        country,
+    }
+}
+
+// Composed (NFC), with single spaces between words and none around them,
+// so "e" and a combining accent are stored as the one "é" a keyboard gives
+fn collapse(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .nfc()
+        .collect()
+}
+
+fn canonical_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+fn looks_like_email(email: &str) -> bool {
+    match email.split_once('@') {
+        Some((name, domain)) => !name.is_empty() && domain.contains('.') && !domain.contains('@'),
+        None => false,
+    }
+}
+
+/// How forgiving validation is about the form of what it is given.
+///
+/// Either way the rules in [`crate::problems`] apply; the profile decides
+/// what happens to input that is valid but not canonical: surrounding or
+/// doubled spaces, text not in Unicode NFC, upper case in an email, a GPA
+/// with more than two decimal places, a phone number not in E.164 form, or
+/// an address part not written the way its country writes it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Profile {
+    /// Tidy input up, e.g. when importing messy legacy data: trim and
+    /// collapse spaces, compose names to NFC, lower-case emails and drop a
+    /// `mailto:` or angle brackets around them, round GPAs to two decimal
+    /// places, put phone numbers in E.164 form, and write countries,
+    /// regions, and postal codes the way the country does.
+    #[default]
+    Lenient,
+    /// Reject anything not already canonical, and emails that do not look
+    /// like `name@domain.tld`, e.g. for live input from forms.
+    Strict,
+}
+
+impl Profile {
+    /// Apply the profile to `student`, returning every rule it breaks:
+    /// lenient tidies `student` up first; strict leaves it as it is and
+    /// also reports what is not canonical.
+    pub fn check(self, student: &mut Student) -> Vec<(&'static str, Problem)> {
+        let form = self.tidy(student);
+        let mut problems = crate::problems(student);
+        problems.extend(form);
+        problems
+    }
+
+    /// Only the form of `student`: lenient tidies it up and finds nothing
+    /// wrong; strict reports each field that is not canonical. Either way a
+    /// GPA that reads as a number ends up in both `gpa_decimal` and `gpa`.
+    pub fn tidy(self, student: &mut Student) -> Vec<(&'static str, Problem)> {
+        let mut problems = Vec::new();
+        match self {
+            Profile::Lenient => {
+                student.id = student.id.trim().to_string();
+                student.name = collapse(&student.name);
+                student.preferred_name = collapse(&student.preferred_name);
+                student.email = lenient_email(&student.email);
+                student.major = collapse(&student.major);
+                student.major_id = student.major_id.trim().to_string();
+                if let Some(gpa) = Gpa::round(&gpa::given(student)) {
+                    gpa::set(student, gpa);
+                }
+                if let Some(address) = &student.address {
+                    let address = tidy_address(address);
+                    // An address with nothing in it is no address
+                    student.address = (address != Address::default()).then_some(address);
+                }
+            }
+            Profile::Strict => {
+                if student.id != student.id.trim() {
+                    problems.push(("id", Problem::IdNotCanonical));
+                }
+                // An empty name or email is reported as such
+                if !student.name.trim().is_empty() && student.name != collapse(&student.name) {
+                    problems.push(("name", Problem::NameNotCanonical));
+                }
+                if student.preferred_name != collapse(&student.preferred_name) {
+                    problems.push(("preferred_name", Problem::PreferredNameNotCanonical));
+                }
+                if !student.email.trim().is_empty() {
+                    if let Err(text) = strict_email(&student.email) {
+                        problems.push(("email", text));
+                    }
+                }
+                if student.major != collapse(&student.major) {
+                    problems.push(("major", Problem::MajorNotCanonical));
+                }
+                if student.major_id != student.major_id.trim() {
+                    problems.push(("major_id", Problem::MajorIdNotCanonical));
+                }
+                let given = gpa::given(student);
+                match given.parse::<Gpa>() {
+                    Ok(gpa) => gpa::set(student, gpa),
+                    Err(_) if Gpa::round(&given).is_some() => {
+                        problems.push((gpa::field(student), Problem::GpaNotCanonical));
+                    }
+                    // Not a number at all, which the rules report
+                    Err(_) => {}
+                }
+                if let Some(address) = &student.address {
+                    let tidy = tidy_address(address);
+                    let fields = [
+                        ("address.line1", &address.line1, &tidy.line1),
+                        ("address.line2", &address.line2, &tidy.line2),
+                        ("address.city", &address.city, &tidy.city),
+                        ("address.region", &address.region, &tidy.region),
+                        (
+                            "address.postal_code",
+                            &address.postal_code,
+                            &tidy.postal_code,
+                        ),
+                        ("address.country", &address.country, &tidy.country),
+                    ];
+                    for (field, given, canonical) in fields {
+                        if given != canonical {
+                            problems.push((field, Problem::AddressNotCanonical));
+                        }
+                    }
+                }
+            }
+        }
+        problems
+    }
+
+    /// `email` as it should be stored, or why it cannot be.
+    pub fn email(self, email: &str) -> Result<String, Problem> {
+        if email.trim().is_empty() {
+            return Err(Problem::EmailEmpty);
+        }
+        match self {
+            Profile::Lenient => Ok(lenient_email(email)),
+            Profile::Strict => strict_email(email).map(|()| email.to_string()),
+        }
+    }
+
+    /// Apply the profile to `student`'s phone numbers, reading any without
+    /// a country code as numbers of `region`, and return every problem:
+    /// lenient stores each number once in E.164 form and drops empty ones;
+    /// strict reports any number not already so.
+    pub fn phone_numbers(
+        self,
+        student: &mut Student,
+        region: Option<Id>,
+    ) -> Vec<(&'static str, Problem)> {
+        let mut problems = Vec::new();
+        let mut numbers: Vec<String> = Vec::new();
+        for number in &student.phone_numbers {
+            if self == Profile::Lenient && number.trim().is_empty() {
+                continue;
+            }
+            match phone::normalize(number, region) {
+                Ok(normalized) => {
+                    let repeated = numbers.contains(&normalized);
+                    if self == Profile::Strict && (repeated || normalized != *number) {
+                        problems
+                            .push(("phone_numbers", Problem::PhoneNotCanonical(number.clone())));
+                    }
+                    if !repeated {
+                        numbers.push(normalized);
+                    }
+                }
+                Err(text) => problems.push(("phone_numbers", text)),
+            }
+        }
+        if self == Profile::Lenient && problems.is_empty() {
+            student.phone_numbers = numbers;
+        }
+        problems
+    }
+}
+
+fn lenient_email(email: &str) -> String {
+    let email = email.trim();
+    let email = email.strip_prefix("mailto:").unwrap_or(email);
+    let email = email
+        .strip_prefix('<')
+        .and_then(|email| email.strip_suffix('>'))
+        .unwrap_or(email);
+    canonical_email(email)
+}
+
+fn strict_email(email: &str) -> Result<(), Problem> {
+    if email != canonical_email(email) {
+        return Err(Problem::EmailNotCanonical);
+    }
+    if !looks_like_email(email) {
+        return Err(Problem::EmailInvalid);
+    }
+    Ok(())
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.trim() {
+            "lenient" => Ok(Profile::Lenient),
+            "strict" => Ok(Profile::Strict),
+            _ => Err(format!("{}: expected lenient or strict", text)),
+        }
+    }
+}
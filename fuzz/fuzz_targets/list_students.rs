@@ -0,0 +1,58 @@
+//! Arbitrary page sizes and page tokens through `ListStudents`.
+//!
+//! A bad token must be rejected with `INVALID_ARGUMENT`, never a panic, and a
+//! page must not be larger than asked for or than the store.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use proto::student_service_server::StudentService;
+use proto::{CreateStudentRequest, ListStudentsRequest, Student};
+use server::StudentServiceImpl;
+use tonic::{Code, Request};
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    students: u8,
+    page_size: i32,
+    page_token: String,
+}
+
+fuzz_target!(|input: Input| {
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    runtime.block_on(async {
+        let service = StudentServiceImpl::new();
+        for n in 0..input.students {
+            service
+                .create_student(Request::new(CreateStudentRequest {
+                    student: Some(Student {
+                        name: format!("Student {}", n),
+                        email: format!("student{}@university.edu", n),
+                        age: 20,
+                        ..Default::default()
+                    }),
+                }))
+                .await
+                .expect("valid student");
+        }
+
+        let listed = service
+            .list_students(Request::new(ListStudentsRequest {
+                page_size: input.page_size,
+                page_token: input.page_token,
+            }))
+            .await;
+        match listed {
+            Ok(page) => {
+                let page = page.into_inner();
+                assert_eq!(page.total_count, i32::from(input.students));
+                assert!(page.students.len() <= usize::from(input.students));
+                if input.page_size > 0 {
+                    assert!(page.students.len() <= input.page_size as usize);
+                }
+            }
+            Err(status) => assert_eq!(status.code(), Code::InvalidArgument),
+        }
+    });
+});
@@ -0,0 +1,62 @@
+//! Arbitrary students through `ValidateStudent` and `CreateStudent`.
+//!
+//! Neither may panic, and they must agree: a student is created exactly when
+//! validation finds no violations.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use proto::student_service_server::StudentService;
+use proto::{CreateStudentRequest, Student, ValidateStudentRequest};
+use server::StudentServiceImpl;
+use tonic::{Code, Request};
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    id: String,
+    name: String,
+    email: String,
+    age: i32,
+    major: String,
+    gpa: f64,
+}
+
+fuzz_target!(|input: Input| {
+    let student = Student {
+        id: input.id,
+        name: input.name,
+        email: input.email,
+        age: input.age,
+        major: input.major,
+        gpa: input.gpa,
+        ..Default::default()
+    };
+
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    runtime.block_on(async {
+        let service = StudentServiceImpl::new();
+
+        let validation = service
+            .validate_student(Request::new(ValidateStudentRequest {
+                student: Some(student.clone()),
+            }))
+            .await
+            .expect("ValidateStudent never fails")
+            .into_inner();
+        assert_eq!(validation.valid, validation.violations.is_empty());
+
+        let created = service
+            .create_student(Request::new(CreateStudentRequest {
+                student: Some(student),
+            }))
+            .await;
+        match created {
+            Ok(_) => assert!(validation.valid, "created an invalid student"),
+            Err(status) => {
+                assert_eq!(status.code(), Code::InvalidArgument);
+                assert!(!validation.valid, "rejected a valid student");
+            }
+        }
+    });
+});
@@ -0,0 +1,323 @@
+use crate::status::{error_response, RequestId};
+use axum::extract::State;
+use axum::http::header::{AUTHORIZATION, COOKIE, HOST, ORIGIN, SET_COOKIE, UPGRADE};
+use axum::http::{HeaderMap, HeaderValue, Method, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use client::StudentClient;
+use serde_json::json;
+use std::sync::Arc;
+use tonic::Status;
+
+/// The cookie a browser's bearer token is kept in, out of reach of scripts.
+pub const TOKEN_COOKIE: &str = "student_token";
+/// The cookie holding the value browsers send back as [`CSRF_HEADER`].
+pub const CSRF_COOKIE: &str = "csrf_token";
+/// The header that must repeat [`CSRF_COOKIE`] on writes authenticated by cookie.
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// The web origins, e.g. `https://registrar.example.edu`, whose pages may
+/// call the gateway with their visitors' cookies.
+#[derive(Debug, Clone, Default)]
+pub struct AllowedOrigins(pub Arc<Vec<HeaderValue>>);
+
+impl AllowedOrigins {
+    // Whether a page at `origin` may write, as one of the allowed origins or
+    // one served by the gateway itself
+    fn allows(&self, origin: &HeaderValue, host: Option<&HeaderValue>) -> bool {
+        if self.0.contains(origin) {
+            return true;
+        }
+        let origin = origin.to_str().unwrap_or_default();
+        let origin_host = origin.split_once("://").map(|(_, host)| host);
+        host.and_then(|host| host.to_str().ok())
+            .is_some_and(|host| origin_host == Some(host))
+    }
+}
+
+/// Who the request is from: the bearer token it carries, in the
+/// `Authorization` header or the [`TOKEN_COOKIE`], if any. The gRPC server
+/// checks it, so gateway and gRPC callers are the same principals under the
+/// same policies.
+#[derive(Debug, Clone, Default)]
+pub struct Caller {
+    token: Option<String>,
+    request_id: Option<RequestId>,
+}
+
+impl Caller {
+    /// `client`, calling as this caller, with the request's ID.
+    pub fn client(&self, mut client: StudentClient) -> StudentClient {
+        if let Some(token) = &self.token {
+            client = client.with_token(token);
+        }
+        if let Some(RequestId(id)) = &self.request_id {
+            client = client.with_request_id(id.to_str().unwrap_or_default());
+        }
+        client
+    }
+}
+
+// The value of the cookie `name` in `headers`
+fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+        .filter(|value| !value.is_empty())
+}
+
+fn bearer(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(AUTHORIZATION)?.to_str().ok()?.trim();
+    let token = value.strip_prefix("Bearer ").unwrap_or(value).trim();
+    (!token.is_empty()).then_some(token)
+}
+
+/// Finds the [`Caller`] of every request. A token in the `Authorization`
+/// header is taken as it is, since browsers never send one on their own. A
+/// token from the cookie is refused for anything but reads unless the
+/// request repeats the [`CSRF_COOKIE`] in the [`CSRF_HEADER`] and, if it says
+/// where it is from, comes from an allowed origin. Websocket upgrades
+/// authenticated by cookie must come from an allowed origin too.
+pub async fn authenticate<B>(
+    State(origins): State<AllowedOrigins>,
+    mut request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let headers = request.headers();
+    let token = if let Some(token) = bearer(headers) {
+        Some(token.to_string())
+    } else if let Some(token) = cookie(headers, TOKEN_COOKIE) {
+        let safe = matches!(
+            *request.method(),
+            Method::GET | Method::HEAD | Method::OPTIONS
+        );
+        if !safe {
+            let csrf = cookie(headers, CSRF_COOKIE);
+            let echoed = headers
+                .get(CSRF_HEADER)
+                .and_then(|value| value.to_str().ok());
+            if csrf.is_none() || csrf != echoed {
+                return error_response(Status::permission_denied(format!(
+                    "Writes authenticated by cookie must repeat the {} cookie in {}",
+                    CSRF_COOKIE, CSRF_HEADER
+                )));
+            }
+        }
+        // A websocket carries mutations too, and cannot send the CSRF header
+        if !safe || headers.contains_key(UPGRADE) {
+            if let Some(origin) = headers.get(ORIGIN) {
+                if !origins.allows(origin, headers.get(HOST)) {
+                    return error_response(Status::permission_denied(format!(
+                        "Origin {} may not write with cookies",
+                        origin.to_str().unwrap_or_default()
+                    )));
+                }
+            }
+        }
+        Some(token.to_string())
+    } else {
+        None
+    };
+    let caller = Caller {
+        token,
+        request_id: request.extensions().get::<RequestId>().cloned(),
+    };
+    request.extensions_mut().insert(caller);
+    next.run(request).await
+}
+
+// Whether `c` may appear in a cookie's value as it is
+fn cookie_octet(c: char) -> bool {
+    c.is_ascii_graphic() && !matches!(c, '"' | ',' | ';' | '\\')
+}
+
+// A cookie for the whole gateway, sent only over HTTPS (or to localhost)
+// and never with requests from other sites
+fn set_cookie(name: &str, value: &str, http_only: bool, max_age: Option<u32>) -> HeaderValue {
+    let mut cookie = format!("{}={}; Path=/; Secure; SameSite=Strict", name, value);
+    if http_only {
+        cookie.push_str("; HttpOnly");
+    }
+    if let Some(max_age) = max_age {
+        cookie.push_str(&format!("; Max-Age={}", max_age));
+    }
+    HeaderValue::from_str(&cookie).expect("tokens and cookie names are printable ASCII")
+}
+
+/// `POST /v1/login` — keep the bearer token in the `Authorization` header
+/// in an `HttpOnly` cookie, for browsers, and hand out a CSRF token, both as
+/// the [`CSRF_COOKIE`] and as `csrfToken` in the body. Nothing is kept in
+/// the gateway.
+pub async fn login(headers: HeaderMap) -> Response {
+    let Some(token) = bearer(&headers).filter(|token| token.chars().all(cookie_octet)) else {
+        let status = Status::unauthenticated("Log in with Authorization: Bearer <token>");
+        return error_response(status);
+    };
+    let csrf = uuid::Uuid::new_v4().simple().to_string();
+    let mut response = Json(json!({ "csrfToken": csrf })).into_response();
+    let cookies = response.headers_mut();
+    cookies.append(SET_COOKIE, set_cookie(TOKEN_COOKIE, token, true, None));
+    cookies.append(SET_COOKIE, set_cookie(CSRF_COOKIE, &csrf, false, None));
+    response
+}
+
+/// `POST /v1/logout` — forget the cookies [`login`] set.
+pub async fn logout() -> Response {
+    let mut response = StatusCode::NO_CONTENT.into_response();
+    let cookies = response.headers_mut();
+    cookies.append(SET_COOKIE, set_cookie(TOKEN_COOKIE, "", true, Some(0)));
+    cookies.append(SET_COOKIE, set_cookie(CSRF_COOKIE, "", false, Some(0)));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::routing::{get, post};
+    use axum::{middleware, Extension, Router};
+    use tower::ServiceExt;
+
+    const ALLOWED: &str = "https://registrar.example.edu";
+
+    // Answers with the token the request was authenticated by
+    async fn whoami(Extension(caller): Extension<Caller>) -> String {
+        caller.token.unwrap_or_default()
+    }
+
+    fn app() -> Router {
+        let origins = AllowedOrigins(Arc::new(vec![HeaderValue::from_static(ALLOWED)]));
+        Router::new()
+            .route("/", get(whoami).post(whoami))
+            .route("/v1/login", post(login))
+            .layer(middleware::from_fn_with_state(origins, authenticate))
+    }
+
+    async fn send(request: axum::http::request::Builder) -> (StatusCode, String) {
+        let response = app()
+            .oneshot(
+                request
+                    .header(HOST, "gateway.example.edu")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    // A write from the browser of someone logged in
+    fn cookie_post() -> axum::http::request::Builder {
+        Request::post("/").header(COOKIE, "student_token=secret; csrf_token=c5rf")
+    }
+
+    #[tokio::test]
+    async fn cookie_writes_must_repeat_the_csrf_cookie() {
+        let (status, _) = send(cookie_post()).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send(cookie_post().header(CSRF_HEADER, "guess")).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        // Without the cookie there is nothing to repeat
+        let request = Request::post("/")
+            .header(COOKIE, "student_token=secret")
+            .header(CSRF_HEADER, "");
+        assert_eq!(send(request).await.0, StatusCode::FORBIDDEN);
+
+        let (status, token) = send(cookie_post().header(CSRF_HEADER, "c5rf")).await;
+        assert_eq!((status, token.as_str()), (StatusCode::OK, "secret"));
+        // Reads need no CSRF token
+        let request = Request::get("/").header(COOKIE, "student_token=secret");
+        assert_eq!(send(request).await, (StatusCode::OK, "secret".to_string()));
+    }
+
+    #[tokio::test]
+    async fn cookie_writes_must_come_from_an_allowed_origin() {
+        let from = |origin| {
+            cookie_post()
+                .header(CSRF_HEADER, "c5rf")
+                .header(ORIGIN, origin)
+        };
+        assert_eq!(
+            send(from("https://evil.example.com")).await.0,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(send(from(ALLOWED)).await.0, StatusCode::OK);
+        // Pages served by the gateway itself may write too
+        assert_eq!(
+            send(from("https://gateway.example.edu")).await.0,
+            StatusCode::OK
+        );
+
+        // So must websockets, which can't send the CSRF header
+        let upgrade = |origin| {
+            Request::get("/")
+                .header(COOKIE, "student_token=secret")
+                .header(UPGRADE, "websocket")
+                .header(ORIGIN, origin)
+        };
+        assert_eq!(
+            send(upgrade("https://evil.example.com")).await.0,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(send(upgrade(ALLOWED)).await.0, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn bearer_writes_need_no_csrf_token() {
+        let request = Request::post("/")
+            .header(AUTHORIZATION, "Bearer secret")
+            .header(ORIGIN, "https://evil.example.com");
+        assert_eq!(send(request).await, (StatusCode::OK, "secret".to_string()));
+        // The header wins over the cookie
+        let request = cookie_post().header(AUTHORIZATION, "Bearer other");
+        assert_eq!(send(request).await, (StatusCode::OK, "other".to_string()));
+    }
+
+    #[tokio::test]
+    async fn login_sets_locked_down_cookies() {
+        let request = Request::post("/v1/login").header(AUTHORIZATION, "Bearer secret");
+        let response = app()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let cookies: Vec<String> = response
+            .headers()
+            .get_all(SET_COOKIE)
+            .iter()
+            .map(|value| value.to_str().unwrap().to_string())
+            .collect();
+        let [token, csrf] = &cookies[..] else {
+            panic!("expected two cookies, got {:?}", cookies);
+        };
+        assert_eq!(
+            token,
+            "student_token=secret; Path=/; Secure; SameSite=Strict; HttpOnly"
+        );
+        // Scripts read this one, to send it back in the header
+        let value = csrf
+            .strip_prefix("csrf_token=")
+            .and_then(|csrf| csrf.strip_suffix("; Path=/; Secure; SameSite=Strict"))
+            .unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["csrfToken"], *value);
+
+        // Logging in takes a token
+        assert_eq!(
+            send(Request::post("/v1/login")).await.0,
+            StatusCode::UNAUTHORIZED
+        );
+        // and one that can be a cookie's value
+        let request = Request::post("/v1/login").header(AUTHORIZATION, "Bearer a;b");
+        assert_eq!(send(request).await.0, StatusCode::UNAUTHORIZED);
+    }
+}
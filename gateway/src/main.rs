@@ -0,0 +1,99 @@
+mod auth;
+mod rest;
+mod schema;
+mod status;
+mod subscriptions;
+mod watch;
+
+use async_graphql::http::GraphiQLSource;
+use auth::{AllowedOrigins, Caller, CSRF_HEADER};
+use axum::extract::State;
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE, ETAG, IF_MATCH, IF_NONE_MATCH};
+use axum::http::{HeaderName, HeaderValue, Method};
+use axum::response::Html;
+use axum::routing::{get, post};
+use axum::{middleware, Extension, Json, Router};
+use clap::Parser;
+use client::StudentClient;
+use schema::StudentSchema;
+use std::sync::Arc;
+use std::time::Duration;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+#[derive(Parser, Debug)]
+#[command(about = "GraphQL and REST gateway to the student service")]
+struct Args {
+    /// Let pages from this origin, e.g. `https://registrar.example.edu`,
+    /// call the gateway from the browser, with their visitors' cookies; may
+    /// be repeated
+    #[arg(long = "allow-origin", value_name = "ORIGIN")]
+    allowed_origins: Vec<HeaderValue>,
+}
+
+async fn graphql(
+    State(schema): State<StudentSchema>,
+    Extension(caller): Extension<Caller>,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    Json(schema.execute(request.data(caller)).await)
+}
+
+async fn graphiql() -> Html<String> {
+    Html(
+        GraphiQLSource::build()
+            .endpoint("/graphql")
+            .subscription_endpoint("/graphql/ws")
+            .finish(),
+    )
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    let addr = "[::1]:8080".parse()?;
+
+    // The gRPC server may start after the gateway
+    let client = StudentClient::connect_lazy("http://[::1]:50051")?
+        .with_wait_for_ready(Duration::from_secs(5));
+
+    let app = Router::new()
+        .route("/graphql", get(graphiql).post(graphql))
+        .route("/graphql/ws", get(subscriptions::graphql_ws))
+        .with_state(schema::build_schema(client.clone()))
+        .route("/v1/students:watch", get(watch::watch_students))
+        .route(
+            "/v1/students/:id",
+            get(rest::get_student).put(rest::put_student),
+        )
+        .route("/v1/login", post(auth::login))
+        .route("/v1/logout", post(auth::logout))
+        .with_state(client);
+
+    let origins = AllowedOrigins(Arc::new(args.allowed_origins));
+    let cors = CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins.0.iter().cloned()))
+        .allow_credentials(true)
+        .allow_methods([Method::GET, Method::POST, Method::PUT])
+        .allow_headers([
+            AUTHORIZATION,
+            CONTENT_TYPE,
+            IF_MATCH,
+            IF_NONE_MATCH,
+            HeaderName::from_static(CSRF_HEADER),
+            HeaderName::from_static("last-event-id"),
+            status::REQUEST_ID,
+        ])
+        .expose_headers([ETAG, status::REQUEST_ID]);
+    let app = app
+        .layer(middleware::from_fn_with_state(origins, auth::authenticate))
+        .layer(middleware::from_fn(status::assign_request_id))
+        .layer(cors);
+
+    println!("🕸️  GraphQL gateway listening on http://{}/graphql", addr);
+
+    axum::Server::bind(&addr)
+        .serve(app.into_make_service())
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,281 @@
+use crate::auth::Caller;
+use crate::status::{error_response, error_response_as};
+use axum::extract::{Path, State};
+use axum::http::header::{ETAG, IF_MATCH, IF_NONE_MATCH};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use client::StudentClient;
+use proto::Student;
+use tonic::{Code, Status};
+
+// Send the student's etag as a strong entity tag
+fn with_etag(mut response: Response, etag: &str) -> Response {
+    if let Ok(value) = HeaderValue::from_str(&format!("\"{}\"", etag)) {
+        response.headers_mut().insert(ETAG, value);
+    }
+    response
+}
+
+// The tags listed in an If-Match / If-None-Match header; `*` is kept as is.
+// A weak tag keeps its `W/` unless `weak` compares it as the strong one,
+// as If-None-Match does
+fn entity_tags(
+    headers: &HeaderMap,
+    name: impl axum::http::header::AsHeaderName,
+    weak: bool,
+) -> Option<Vec<String>> {
+    let value = headers.get(name)?.to_str().unwrap_or_default();
+    Some(
+        value
+            .split(',')
+            .map(|tag| {
+                let tag = tag.trim();
+                match tag.strip_prefix("W/") {
+                    Some(opaque) if !weak => format!("W/{}", opaque.trim_matches('"')),
+                    Some(opaque) => opaque.trim_matches('"').to_string(),
+                    None => tag.trim_matches('"').to_string(),
+                }
+            })
+            .filter(|tag| !tag.is_empty())
+            .collect(),
+    )
+}
+
+fn student_response(student: Student) -> Response {
+    let etag = student.etag.clone();
+    with_etag(Json(student).into_response(), &etag)
+}
+
+/// `GET /v1/students/{id}` — the student as JSON, with its etag as `ETag`.
+///
+/// With `If-None-Match` naming the current etag (or `*`), answers
+/// `304 Not Modified` without a body. The tags are compared weakly, so
+/// `W/"..."` matches too.
+pub async fn get_student(
+    State(client): State<StudentClient>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let mut client = caller.client(client);
+    let student = match client.get_student(&id).await {
+        Ok(student) => student,
+        Err(status) => return error_response(status),
+    };
+
+    if let Some(tags) = entity_tags(&headers, IF_NONE_MATCH, true) {
+        if tags.iter().any(|tag| tag == "*" || *tag == student.etag) {
+            return with_etag(StatusCode::NOT_MODIFIED.into_response(), &student.etag);
+        }
+    }
+
+    student_response(student)
+}
+
+/// `PUT /v1/students/{id}` — replace the student with the JSON body.
+///
+/// `If-Match` makes the update conditional on the student's current etag,
+/// like sending `etag` over gRPC: if it has changed since, or the student no
+/// longer exists, the answer is `412 Precondition Failed`. The tag is
+/// compared strongly, so a weak one (`W/"..."`) always fails. `If-Match: *`
+/// only requires the student to exist. Without `If-Match`, an `etag` in the
+/// body is honored the same way.
+///
+/// `If-None-Match: *` creates the student instead, answering `201 Created`,
+/// or `412 Precondition Failed` if one with the ID already exists.
+pub async fn put_student(
+    State(client): State<StudentClient>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    Json(mut student): Json<Student>,
+) -> Response {
+    let mut client = caller.client(client);
+    student.id = id;
+
+    match entity_tags(&headers, IF_NONE_MATCH, true).as_deref() {
+        None => {}
+        Some([tag]) if tag == "*" => {
+            student.etag.clear();
+            return match client.create_student(student).await {
+                Ok(student) => {
+                    let etag = student.etag.clone();
+                    let response = (StatusCode::CREATED, Json(student)).into_response();
+                    with_etag(response, &etag)
+                }
+                Err(status) if status.code() == Code::AlreadyExists => {
+                    error_response_as(StatusCode::PRECONDITION_FAILED, status)
+                }
+                Err(status) => error_response(status),
+            };
+        }
+        Some(_) => {
+            return error_response(Status::invalid_argument(
+                "If-None-Match on PUT must be *, to create the student",
+            ))
+        }
+    }
+
+    let if_match = entity_tags(&headers, IF_MATCH, false);
+    match if_match.as_deref() {
+        None => {}
+        Some([tag]) if tag == "*" => student.etag.clear(),
+        // Compared strongly, a weak tag matches nothing
+        Some([tag]) if tag.starts_with("W/") => {
+            return error_response_as(
+                StatusCode::PRECONDITION_FAILED,
+                Status::failed_precondition("If-Match needs a strong entity tag"),
+            )
+        }
+        Some([tag]) => student.etag = tag.clone(),
+        Some(_) => {
+            return error_response(Status::invalid_argument(
+                "If-Match must name exactly one entity tag",
+            ))
+        }
+    }
+
+    match client.update_student(student).await {
+        Ok(student) => student_response(student),
+        // A conditional request on a changed or missing resource fails its precondition
+        Err(status)
+            if status.code() == Code::Aborted
+                || (status.code() == Code::NotFound && if_match.is_some()) =>
+        {
+            error_response_as(StatusCode::PRECONDITION_FAILED, status)
+        }
+        Err(status) => error_response(status),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{HeaderName, Request};
+    use axum::routing::get;
+    use axum::Router;
+    use proto::student_service_server::StudentServiceServer;
+    use server::StudentServiceImpl;
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::Server;
+    use tower::ServiceExt;
+
+    // The REST routes, calling an in-memory student service, and the etag of
+    // student `s1` in it
+    async fn app() -> (Router, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(StudentServiceServer::new(StudentServiceImpl::new()))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        let mut client = StudentClient::connect_lazy(format!("http://{}", addr)).unwrap();
+        let created = client.create_student(student("s1")).await.unwrap();
+        let app = Router::new()
+            .route("/v1/students/:id", get(get_student).put(put_student))
+            .layer(Extension(Caller::default()))
+            .with_state(client);
+        (app, created.etag)
+    }
+
+    fn student(id: &str) -> Student {
+        Student {
+            id: id.to_string(),
+            name: format!("Student {}", id),
+            email: format!("{}@university.edu", id),
+            age: 20,
+            ..Default::default()
+        }
+    }
+
+    fn get_request(id: &str, headers: &[(HeaderName, &str)]) -> Request<Body> {
+        let mut request = Request::get(format!("/v1/students/{}", id));
+        for (name, value) in headers {
+            request = request.header(name, *value);
+        }
+        request.body(Body::empty()).unwrap()
+    }
+
+    fn put_request(id: &str, headers: &[(HeaderName, &str)]) -> Request<Body> {
+        let mut request = Request::put(format!("/v1/students/{}", id))
+            .header(axum::http::header::CONTENT_TYPE, "application/json");
+        for (name, value) in headers {
+            request = request.header(name, *value);
+        }
+        let body = serde_json::to_vec(&Student {
+            major: "Mathematics".to_string(),
+            ..student(id)
+        })
+        .unwrap();
+        request.body(Body::from(body)).unwrap()
+    }
+
+    async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Option<String>) {
+        let response = app.clone().oneshot(request).await.unwrap();
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .map(|value| value.to_str().unwrap().to_string());
+        (response.status(), etag)
+    }
+
+    #[tokio::test]
+    async fn gets_carry_the_etag_and_honor_if_none_match() {
+        let (app, etag) = app().await;
+        let quoted = format!("\"{}\"", etag);
+
+        let (status, sent) = send(&app, get_request("s1", &[])).await;
+        assert_eq!((status, sent.as_deref()), (StatusCode::OK, Some(&*quoted)));
+
+        // Compared weakly: the tag matches with or without W/
+        let weak = format!("W/{}", quoted);
+        for tag in [quoted.as_str(), weak.as_str(), "\"other\", *"] {
+            let (status, sent) = send(&app, get_request("s1", &[(IF_NONE_MATCH, tag)])).await;
+            assert_eq!(
+                (status, sent.as_deref()),
+                (StatusCode::NOT_MODIFIED, Some(&*quoted)),
+                "{}",
+                tag
+            );
+        }
+        let (status, _) = send(&app, get_request("s1", &[(IF_NONE_MATCH, "\"other\"")])).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn puts_with_if_none_match_only_create() {
+        let (app, _) = app().await;
+
+        let (status, etag) = send(&app, put_request("s2", &[(IF_NONE_MATCH, "*")])).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert!(etag.is_some());
+        let (status, _) = send(&app, put_request("s1", &[(IF_NONE_MATCH, "*")])).await;
+        assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+        let (status, _) = send(&app, put_request("s3", &[(IF_NONE_MATCH, "\"tag\"")])).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn puts_with_if_match_need_the_current_strong_etag() {
+        let (app, etag) = app().await;
+        let quoted = format!("\"{}\"", etag);
+
+        // Weak, stale, or for a missing student: the precondition fails
+        let weak = format!("W/{}", quoted);
+        for (id, tag) in [("s1", weak.as_str()), ("s1", "\"stale\""), ("s9", "*")] {
+            let (status, _) = send(&app, put_request(id, &[(IF_MATCH, tag)])).await;
+            assert_eq!(status, StatusCode::PRECONDITION_FAILED, "{} {}", id, tag);
+        }
+
+        let (status, updated) = send(&app, put_request("s1", &[(IF_MATCH, &quoted)])).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_ne!(updated.as_deref(), Some(&*quoted));
+        // Now stale
+        let (status, _) = send(&app, put_request("s1", &[(IF_MATCH, &quoted)])).await;
+        assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+    }
+}
@@ -0,0 +1,624 @@
+use crate::auth::Caller;
+use async_graphql::{
+    Context, Enum, Error, ErrorExtensions, InputObject, Object, Result, Schema, SimpleObject,
+    Subscription, ID,
+};
+use client::StudentClient;
+use futures::{Stream, StreamExt};
+use proto::{AcademicStanding, Address, ChangeType, ListStudentsRequest, Student, StudentEvent};
+use std::collections::HashMap;
+use tonic::{Code, Status};
+
+pub type StudentSchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
+
+/// Build the schema; resolvers call the gRPC service through `client`.
+pub fn build_schema(client: StudentClient) -> StudentSchema {
+    Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
+        .data(client)
+        .finish()
+}
+
+/// A student record
+#[derive(SimpleObject)]
+#[graphql(name = "Student")]
+struct StudentObject {
+    id: ID,
+    name: String,
+    /// What the student goes by; empty when it is their name
+    preferred_name: String,
+    email: String,
+    /// In E.164 form, e.g. `+12025550123`
+    phone_numbers: Vec<String>,
+    address: Option<AddressObject>,
+    age: i32,
+    major: String,
+    /// Catalog major, if declared; `major` then holds its name
+    major_id: String,
+    /// The closest float to `gpaDecimal`
+    gpa: f64,
+    /// Exact, with two decimal places, e.g. `3.50`
+    gpa_decimal: String,
+    credits: i32,
+    /// Credits of the courses graded, kept by the server from recorded grades
+    credits_attempted: i32,
+    /// Credits of the courses passed, kept by the server from recorded grades
+    credits_earned: i32,
+    /// Derived by the server from the GPA and `credits`
+    standing: Standing,
+    /// Client-owned metadata, by key
+    annotations: Vec<Annotation>,
+    /// Changes on every update; pass it to `updateStudent` to guard against lost updates
+    etag: String,
+}
+
+impl From<Student> for StudentObject {
+    fn from(student: Student) -> Self {
+        let standing = student.standing().into();
+        StudentObject {
+            id: ID(student.id),
+            name: student.name,
+            preferred_name: student.preferred_name,
+            email: student.email,
+            phone_numbers: student.phone_numbers,
+            address: student.address.map(Into::into),
+            age: student.age,
+            major: student.major,
+            major_id: student.major_id,
+            gpa: student.gpa,
+            gpa_decimal: student.gpa_decimal,
+            credits: student.credits,
+            credits_attempted: student.credits_attempted,
+            credits_earned: student.credits_earned,
+            standing,
+            annotations: annotations(student.annotations),
+            etag: student.etag,
+        }
+    }
+}
+
+/// One entry of a student's client-owned metadata
+#[derive(SimpleObject, InputObject)]
+#[graphql(input_name = "AnnotationInput")]
+struct Annotation {
+    /// A name of letters, digits, `-`, `_`, and `.`, optionally after a DNS
+    /// name and `/`, e.g. `example.edu/batch-id`
+    key: String,
+    value: String,
+}
+
+// Sorted by key, as maps have no order of their own
+fn annotations(annotations: HashMap<String, String>) -> Vec<Annotation> {
+    let mut annotations: Vec<Annotation> = annotations
+        .into_iter()
+        .map(|(key, value)| Annotation { key, value })
+        .collect();
+    annotations.sort_by(|a, b| a.key.cmp(&b.key));
+    annotations
+}
+
+fn annotation_map(annotations: Vec<Annotation>) -> HashMap<String, String> {
+    annotations
+        .into_iter()
+        .map(|annotation| (annotation.key, annotation.value))
+        .collect()
+}
+
+/// A postal address
+#[derive(SimpleObject)]
+#[graphql(name = "Address")]
+struct AddressObject {
+    line1: String,
+    line2: String,
+    city: String,
+    /// State, province, or similar, e.g. `CA`
+    region: String,
+    postal_code: String,
+    /// ISO 3166-1 alpha-2 code, e.g. `US`
+    country: String,
+}
+
+impl From<Address> for AddressObject {
+    fn from(address: Address) -> Self {
+        AddressObject {
+            line1: address.line1,
+            line2: address.line2,
+            city: address.city,
+            region: address.region,
+            postal_code: address.postal_code,
+            country: address.country,
+        }
+    }
+}
+
+#[derive(InputObject)]
+struct AddressInput {
+    line1: String,
+    #[graphql(default)]
+    line2: String,
+    city: String,
+    #[graphql(default)]
+    region: String,
+    #[graphql(default)]
+    postal_code: String,
+    country: String,
+}
+
+impl From<AddressInput> for Address {
+    fn from(input: AddressInput) -> Self {
+        Address {
+            line1: input.line1,
+            line2: input.line2,
+            city: input.city,
+            region: input.region,
+            postal_code: input.postal_code,
+            country: input.country,
+        }
+    }
+}
+
+#[derive(Enum, Copy, Clone, PartialEq, Eq)]
+enum Standing {
+    Unspecified,
+    Good,
+    DeansList,
+    Probation,
+}
+
+impl From<AcademicStanding> for Standing {
+    fn from(standing: AcademicStanding) -> Self {
+        match standing {
+            AcademicStanding::Unspecified => Standing::Unspecified,
+            AcademicStanding::Good => Standing::Good,
+            AcademicStanding::DeansList => Standing::DeansList,
+            AcademicStanding::Probation => Standing::Probation,
+        }
+    }
+}
+
+#[derive(Enum, Copy, Clone, PartialEq, Eq)]
+enum Change {
+    Unspecified,
+    Created,
+    Updated,
+    Deleted,
+    /// The server is going away; resubscribe with the last `resumeToken`
+    ShuttingDown,
+}
+
+impl From<ChangeType> for Change {
+    fn from(change: ChangeType) -> Self {
+        match change {
+            ChangeType::Unspecified => Change::Unspecified,
+            ChangeType::Created => Change::Created,
+            ChangeType::Updated => Change::Updated,
+            ChangeType::Deleted => Change::Deleted,
+            ChangeType::ShuttingDown => Change::ShuttingDown,
+        }
+    }
+}
+
+/// A change to a student, as `WatchStudents` reports it
+#[derive(SimpleObject)]
+#[graphql(name = "StudentEvent")]
+struct StudentEventObject {
+    change: Change,
+    /// State after the change; the last known state for deletions
+    student: Option<StudentObject>,
+    /// Pass as `resumeToken` to continue after this event
+    resume_token: String,
+}
+
+impl From<StudentEvent> for StudentEventObject {
+    fn from(event: StudentEvent) -> Self {
+        StudentEventObject {
+            change: event.change_type().into(),
+            student: event.student.map(Into::into),
+            resume_token: event.resume_token,
+        }
+    }
+}
+
+/// One page of `ListStudents` results
+#[derive(SimpleObject)]
+struct StudentPage {
+    students: Vec<StudentObject>,
+    /// Pass as `pageToken` to fetch the next page; empty on the last page
+    next_page_token: String,
+    total_count: i32,
+}
+
+#[derive(SimpleObject)]
+struct FieldViolation {
+    field: String,
+    description: String,
+}
+
+#[derive(SimpleObject)]
+struct ValidationResult {
+    valid: bool,
+    violations: Vec<FieldViolation>,
+}
+
+#[derive(InputObject)]
+struct CreateStudentInput {
+    /// Generated by the server when omitted
+    id: Option<ID>,
+    name: String,
+    #[graphql(default)]
+    preferred_name: String,
+    email: String,
+    /// With a country code unless the server has a default region
+    #[graphql(default)]
+    phone_numbers: Vec<String>,
+    address: Option<AddressInput>,
+    age: i32,
+    #[graphql(default)]
+    major: String,
+    /// A catalog major; takes precedence over `major`
+    #[graphql(default)]
+    major_id: String,
+    #[graphql(default)]
+    gpa: f64,
+    /// Exact, e.g. `3.25`; takes precedence over `gpa`
+    #[graphql(default)]
+    gpa_decimal: String,
+    #[graphql(default)]
+    credits: i32,
+    #[graphql(default)]
+    annotations: Vec<Annotation>,
+}
+
+impl From<CreateStudentInput> for Student {
+    fn from(input: CreateStudentInput) -> Self {
+        Student {
+            id: input.id.map(|id| id.0).unwrap_or_default(),
+            name: input.name,
+            preferred_name: input.preferred_name,
+            email: input.email,
+            phone_numbers: input.phone_numbers,
+            address: input.address.map(Into::into),
+            age: input.age,
+            major: input.major,
+            major_id: input.major_id,
+            gpa: input.gpa,
+            gpa_decimal: input.gpa_decimal,
+            credits: input.credits,
+            annotations: annotation_map(input.annotations),
+            ..Default::default()
+        }
+    }
+}
+
+/// Fields left out keep their current values
+#[derive(InputObject)]
+struct UpdateStudentInput {
+    id: ID,
+    name: Option<String>,
+    preferred_name: Option<String>,
+    email: Option<String>,
+    /// Replaces every phone number
+    phone_numbers: Option<Vec<String>>,
+    /// Replaces the whole address
+    address: Option<AddressInput>,
+    age: Option<i32>,
+    major: Option<String>,
+    major_id: Option<String>,
+    gpa: Option<f64>,
+    /// Exact, e.g. `3.25`; takes precedence over `gpa`
+    gpa_decimal: Option<String>,
+    credits: Option<i32>,
+    /// Replaces every annotation
+    annotations: Option<Vec<Annotation>>,
+    /// Fail with code `Aborted` unless this is still the student's etag
+    etag: Option<String>,
+}
+
+impl UpdateStudentInput {
+    fn apply(self, mut student: Student) -> Student {
+        if let Some(name) = self.name {
+            student.name = name;
+        }
+        if let Some(preferred_name) = self.preferred_name {
+            student.preferred_name = preferred_name;
+        }
+        if let Some(email) = self.email {
+            student.email = email;
+        }
+        if let Some(phone_numbers) = self.phone_numbers {
+            student.phone_numbers = phone_numbers;
+        }
+        if let Some(address) = self.address {
+            student.address = Some(address.into());
+        }
+        if let Some(age) = self.age {
+            student.age = age;
+        }
+        if let Some(major) = self.major {
+            student.major = major;
+        }
+        if let Some(major_id) = self.major_id {
+            student.major_id = major_id;
+        }
+        if let Some(gpa) = self.gpa {
+            // The new float is then the only GPA sent
+            student.gpa = gpa;
+            student.gpa_decimal.clear();
+        }
+        if let Some(gpa_decimal) = self.gpa_decimal {
+            student.gpa_decimal = gpa_decimal;
+        }
+        if let Some(credits) = self.credits {
+            student.credits = credits;
+        }
+        if let Some(annotations) = self.annotations {
+            student.annotations = annotation_map(annotations);
+        }
+        if let Some(etag) = self.etag {
+            student.etag = etag;
+        }
+        student
+    }
+}
+
+// The client to call as the request's caller with
+fn client(ctx: &Context<'_>) -> StudentClient {
+    let client = ctx.data_unchecked::<StudentClient>().clone();
+    match ctx.data_opt::<Caller>() {
+        Some(caller) => caller.client(client),
+        None => client,
+    }
+}
+
+// Keep the gRPC status code available to GraphQL clients
+fn to_error(status: Status) -> Error {
+    Error::new(status.message()).extend_with(|_, extensions| {
+        extensions.set("code", format!("{:?}", status.code()));
+    })
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Look up a student by ID; null if there is no such student
+    async fn student(&self, ctx: &Context<'_>, id: ID) -> Result<Option<StudentObject>> {
+        match client(ctx).get_student(&id).await {
+            Ok(student) => Ok(Some(student.into())),
+            Err(status) if status.code() == Code::NotFound => Ok(None),
+            Err(status) => Err(to_error(status)),
+        }
+    }
+
+    /// List students one page at a time, by ID, or by preferred name (falling
+    /// back to name) with `orderBy: "name"`; `country` and `region` keep
+    /// only students whose address is there
+    async fn students(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 10)] page_size: i32,
+        #[graphql(default)] page_token: String,
+        #[graphql(default)] order_by: String,
+        #[graphql(default)] country: String,
+        #[graphql(default)] region: String,
+    ) -> Result<StudentPage> {
+        let page = client(ctx)
+            .list_students_with(ListStudentsRequest {
+                page_size,
+                page_token,
+                order_by,
+                country,
+                region,
+            })
+            .await
+            .map_err(to_error)?;
+        Ok(StudentPage {
+            students: page.students.into_iter().map(Into::into).collect(),
+            next_page_token: page.next_page_token,
+            total_count: page.total_count,
+        })
+    }
+
+    /// Check a student against the server's validation rules without storing it
+    async fn validate_student(
+        &self,
+        ctx: &Context<'_>,
+        input: CreateStudentInput,
+    ) -> Result<ValidationResult> {
+        let response = client(ctx)
+            .validate_student(input.into())
+            .await
+            .map_err(to_error)?;
+        Ok(ValidationResult {
+            valid: response.valid,
+            violations: response
+                .violations
+                .into_iter()
+                .map(|violation| FieldViolation {
+                    field: violation.field,
+                    description: violation.description,
+                })
+                .collect(),
+        })
+    }
+}
+
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    async fn create_student(
+        &self,
+        ctx: &Context<'_>,
+        input: CreateStudentInput,
+    ) -> Result<StudentObject> {
+        let student = client(ctx)
+            .create_student(input.into())
+            .await
+            .map_err(to_error)?;
+        Ok(student.into())
+    }
+
+    async fn update_student(
+        &self,
+        ctx: &Context<'_>,
+        input: UpdateStudentInput,
+    ) -> Result<StudentObject> {
+        let mut client = client(ctx);
+        let current = client.get_student(&input.id).await.map_err(to_error)?;
+        let student = client
+            .update_student(input.apply(current))
+            .await
+            .map_err(to_error)?;
+        Ok(student.into())
+    }
+
+    /// Returns true once the student has been deleted
+    async fn delete_student(&self, ctx: &Context<'_>, id: ID) -> Result<bool> {
+        let response = client(ctx).delete_student(&id).await.map_err(to_error)?;
+        Ok(response.success)
+    }
+}
+
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// Changes to students as they happen, optionally only those in `major`;
+    /// the stream ends after its first error
+    async fn students(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] major: String,
+        #[graphql(default)] resume_token: String,
+    ) -> Result<impl Stream<Item = Result<StudentEventObject>>> {
+        let events = client(ctx)
+            .resume_watch(&major, &resume_token)
+            .await
+            .map_err(to_error)?;
+        Ok(events.map(|event| event.map(Into::into).map_err(to_error)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_graphql::{Request, Value};
+    use proto::student_service_server::StudentServiceServer;
+    use serde_json::json;
+    use server::StudentServiceImpl;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::Server;
+
+    // The schema, calling an in-memory student service
+    async fn schema() -> StudentSchema {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(StudentServiceServer::new(StudentServiceImpl::new()))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        build_schema(StudentClient::connect_lazy(format!("http://{}", addr)).unwrap())
+    }
+
+    // The data of a request that succeeded, as JSON
+    async fn data(schema: &StudentSchema, request: impl Into<Request>) -> serde_json::Value {
+        let response = schema.execute(request).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        response.data.into_json().unwrap()
+    }
+
+    const CREATE: &str = r#"mutation {
+        createStudent(input: {id: "s1", name: "Ada Lovelace", email: "ada@university.edu",
+                              age: 36, gpaDecimal: "3.90"}) { id name standing etag }
+    }"#;
+
+    #[tokio::test]
+    async fn queries_read_what_mutations_wrote() {
+        let schema = schema().await;
+        let created = data(&schema, CREATE).await;
+        assert_eq!(created["createStudent"]["name"], "Ada Lovelace");
+        assert_eq!(created["createStudent"]["standing"], "GOOD");
+        let etag = created["createStudent"]["etag"].as_str().unwrap();
+
+        let found = data(&schema, r#"{ student(id: "s1") { name gpaDecimal } }"#).await;
+        assert_eq!(
+            found,
+            json!({ "student": { "name": "Ada Lovelace", "gpaDecimal": "3.90" } })
+        );
+        let missing = data(&schema, r#"{ student(id: "s9") { name } }"#).await;
+        assert_eq!(missing, json!({ "student": null }));
+        let page = data(&schema, "{ students { totalCount students { id } } }").await;
+        assert_eq!(
+            page,
+            json!({ "students": { "totalCount": 1, "students": [{ "id": "s1" }] } })
+        );
+
+        let update = r#"mutation($etag: String) {
+            updateStudent(input: {id: "s1", major: "Mathematics", etag: $etag}) { major }
+        }"#;
+        let request = Request::new(update)
+            .variables(async_graphql::Variables::from_json(json!({ "etag": etag })));
+        let updated = data(&schema, request).await;
+        assert_eq!(
+            updated,
+            json!({ "updateStudent": { "major": "Mathematics" } })
+        );
+        // The etag has moved on: the gRPC code comes back with the error
+        let request = Request::new(update)
+            .variables(async_graphql::Variables::from_json(json!({ "etag": etag })));
+        let response = schema.execute(request).await;
+        let extensions = response.errors[0].extensions.as_ref().unwrap();
+        assert_eq!(extensions.get("code"), Some(&Value::from("Aborted")));
+
+        let deleted = data(&schema, r#"mutation { deleteStudent(id: "s1") }"#).await;
+        assert_eq!(deleted, json!({ "deleteStudent": true }));
+    }
+
+    #[tokio::test]
+    async fn validation_lists_each_violation() {
+        let schema = schema().await;
+        let checked = data(
+            &schema,
+            r#"{ validateStudent(input: {name: "", email: "ada@university.edu", age: 200}) {
+                valid violations { field }
+            } }"#,
+        )
+        .await;
+        assert_eq!(
+            checked,
+            json!({ "validateStudent": { "valid": false, "violations": [{ "field": "name" }, { "field": "age" }] } })
+        );
+    }
+
+    #[tokio::test]
+    async fn subscriptions_follow_watch_students() {
+        let schema = schema().await;
+        let mut events = schema.execute_stream(
+            r#"subscription { students(major: "History") { change student { name } } }"#,
+        );
+        let mut first = tokio::spawn(async move { events.next().await });
+
+        // Until the watch has started, and seen one of them
+        let mut n = 0;
+        let event = loop {
+            n += 1;
+            let create = format!(
+                r#"mutation {{ createStudent(input: {{name: "Student {}",
+                    email: "s{}@university.edu", age: 20, major: "History"}}) {{ id }} }}"#,
+                n, n
+            );
+            data(&schema, create).await;
+            if let Ok(event) = tokio::time::timeout(Duration::from_millis(50), &mut first).await {
+                break event.unwrap().unwrap();
+            }
+        };
+        assert!(event.errors.is_empty(), "{:?}", event.errors);
+        let event = event.data.into_json().unwrap();
+        assert_eq!(event["students"]["change"], "CREATED");
+        let name = event["students"]["student"]["name"].as_str().unwrap();
+        assert!(name.starts_with("Student "), "{}", name);
+    }
+}
@@ -0,0 +1,257 @@
+use axum::http::header::HeaderName;
+use axum::http::{HeaderValue, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use prost::Message;
+use proto::google::rpc::{self, BadRequest, ErrorInfo, LocalizedMessage, PreconditionFailure};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tonic::{Code, Status};
+
+/// The header a request's ID travels in, from the browser to the gRPC
+/// server and back.
+pub const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// The ID of the request being handled, the caller's own if they sent one.
+#[derive(Debug, Clone)]
+pub struct RequestId(pub HeaderValue);
+
+// A caller's ID is kept if it is short and printable; any other is replaced
+fn usable(id: &HeaderValue) -> bool {
+    let id = id.as_bytes();
+    !id.is_empty() && id.len() <= 128 && id.iter().all(u8::is_ascii_graphic)
+}
+
+/// Gives every request a [`RequestId`] and answers with it in
+/// [`REQUEST_ID`], errors included.
+pub async fn assign_request_id<B>(mut request: Request<B>, next: Next<B>) -> Response {
+    let id = match request.headers().get(&REQUEST_ID) {
+        Some(id) if usable(id) => id.clone(),
+        _ => HeaderValue::from_str(&uuid::Uuid::new_v4().simple().to_string())
+            .expect("a UUID is a header value"),
+    };
+    request.extensions_mut().insert(RequestId(id.clone()));
+    let mut response = next.run(request).await;
+    response.headers_mut().insert(REQUEST_ID, id);
+    response
+}
+
+/// The HTTP status for a gRPC status code, as grpc-gateway maps them.
+pub fn http_status(code: Code) -> StatusCode {
+    match code {
+        Code::Ok => StatusCode::OK,
+        // Client Closed Request, as nginx has it
+        Code::Cancelled => StatusCode::from_u16(499).expect("499 is a status code"),
+        Code::InvalidArgument | Code::FailedPrecondition | Code::OutOfRange => {
+            StatusCode::BAD_REQUEST
+        }
+        Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+        Code::PermissionDenied => StatusCode::FORBIDDEN,
+        Code::NotFound => StatusCode::NOT_FOUND,
+        Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+        Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+        Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+        Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+        Code::Unknown | Code::Internal | Code::DataLoss => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+// One detail in proto3 JSON form, as an `Any` with its fields inline
+fn detail<T: Message + Default + Serialize>(type_url: &str, bytes: &[u8]) -> Option<Value> {
+    let mut value = serde_json::to_value(T::decode(bytes).ok()?).ok()?;
+    value
+        .as_object_mut()?
+        .insert("@type".to_string(), Value::String(type_url.to_string()));
+    Some(value)
+}
+
+// The details the server sent with `status`; those of types the gateway
+// does not know are shown by type alone
+fn details(status: &Status) -> Vec<Value> {
+    let Ok(rpc::Status { details, .. }) = rpc::Status::decode(status.details()) else {
+        return Vec::new();
+    };
+    details
+        .iter()
+        .map(|any| {
+            let (url, bytes) = (any.type_url.as_str(), any.value.as_ref());
+            let value = match url.rsplit('/').next().unwrap_or_default() {
+                "google.rpc.ErrorInfo" => detail::<ErrorInfo>(url, bytes),
+                "google.rpc.PreconditionFailure" => detail::<PreconditionFailure>(url, bytes),
+                "google.rpc.BadRequest" => detail::<BadRequest>(url, bytes),
+                "google.rpc.LocalizedMessage" => detail::<LocalizedMessage>(url, bytes),
+                _ => None,
+            };
+            value.unwrap_or_else(|| json!({ "@type": any.type_url }))
+        })
+        .collect()
+}
+
+/// `status` as a `google.rpc.Status` in JSON, with its details, answered
+/// with the HTTP status its code maps to.
+pub fn error_response(status: Status) -> Response {
+    error_response_as(http_status(status.code()), status)
+}
+
+/// Like [`error_response`], but answered with `http_status`.
+pub fn error_response_as(http_status: StatusCode, status: Status) -> Response {
+    let body = json!({
+        "code": status.code() as i32,
+        "message": status.message(),
+        "details": details(&status),
+    });
+    (http_status, Json(body)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::routing::get;
+    use axum::{middleware, Extension, Router};
+    use proto::google::rpc::bad_request::FieldViolation;
+    use std::collections::HashMap;
+    use tower::ServiceExt;
+
+    #[test]
+    fn codes_map_to_http_statuses() {
+        for (code, status) in [
+            (Code::Ok, 200),
+            (Code::Cancelled, 499),
+            (Code::Unknown, 500),
+            (Code::InvalidArgument, 400),
+            (Code::DeadlineExceeded, 504),
+            (Code::NotFound, 404),
+            (Code::AlreadyExists, 409),
+            (Code::PermissionDenied, 403),
+            (Code::ResourceExhausted, 429),
+            (Code::FailedPrecondition, 400),
+            (Code::Aborted, 409),
+            (Code::OutOfRange, 400),
+            (Code::Unimplemented, 501),
+            (Code::Internal, 500),
+            (Code::Unavailable, 503),
+            (Code::DataLoss, 500),
+            (Code::Unauthenticated, 401),
+        ] {
+            assert_eq!(http_status(code).as_u16(), status, "{:?}", code);
+        }
+    }
+
+    fn any<T: Message>(name: &str, message: &T) -> proto::Any {
+        proto::Any {
+            type_url: format!("type.googleapis.com/google.rpc.{}", name),
+            value: message.encode_to_vec().into(),
+        }
+    }
+
+    // A detail's fields, without its type
+    fn decoded<T: serde::de::DeserializeOwned>(detail: &Value) -> T {
+        let mut fields = detail.as_object().unwrap().clone();
+        fields.remove("@type");
+        serde_json::from_value(Value::Object(fields)).unwrap()
+    }
+
+    async fn body(response: Response) -> Value {
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn details_come_back_as_json() {
+        let info = ErrorInfo {
+            reason: "STUDENT_NOT_FOUND".to_string(),
+            domain: "student.example.edu".to_string(),
+            metadata: HashMap::from([("id".to_string(), "s1".to_string())]),
+        };
+        let bad = BadRequest {
+            field_violations: vec![FieldViolation {
+                field: "email".to_string(),
+                description: "must contain @".to_string(),
+            }],
+        };
+        let details = rpc::Status {
+            code: Code::InvalidArgument as i32,
+            message: "Invalid student".to_string(),
+            details: vec![
+                any("ErrorInfo", &info),
+                any("BadRequest", &bad),
+                any("Unheard", &info),
+            ],
+        };
+        let status = Status::with_details(
+            Code::InvalidArgument,
+            "Invalid student",
+            details.encode_to_vec().into(),
+        );
+
+        let response = error_response(status);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = body(response).await;
+        assert_eq!(body["code"], 3);
+        assert_eq!(body["message"], "Invalid student");
+        let details = body["details"].as_array().unwrap();
+        assert_eq!(details.len(), 3);
+        // Each decodes back to the message it was made from
+        assert_eq!(
+            details[0]["@type"],
+            "type.googleapis.com/google.rpc.ErrorInfo"
+        );
+        assert_eq!(decoded::<ErrorInfo>(&details[0]), info);
+        assert_eq!(
+            details[1]["@type"],
+            "type.googleapis.com/google.rpc.BadRequest"
+        );
+        assert_eq!(decoded::<BadRequest>(&details[1]), bad);
+        assert_eq!(
+            details[2],
+            json!({ "@type": "type.googleapis.com/google.rpc.Unheard" })
+        );
+    }
+
+    #[tokio::test]
+    async fn error_responses_can_take_another_http_status() {
+        let status = Status::failed_precondition("Stale etag");
+        let response = error_response_as(StatusCode::PRECONDITION_FAILED, status);
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+        assert_eq!(body(response).await["details"], json!([]));
+    }
+
+    // Answers with the ID the request was given
+    async fn echo(Extension(RequestId(id)): Extension<RequestId>) -> String {
+        id.to_str().unwrap().to_string()
+    }
+
+    async fn request_id(sent: Option<&[u8]>) -> (String, String) {
+        let app = Router::new()
+            .route("/", get(echo))
+            .layer(middleware::from_fn(assign_request_id));
+        let mut request = Request::get("/");
+        if let Some(sent) = sent {
+            request = request.header(REQUEST_ID, HeaderValue::from_bytes(sent).unwrap());
+        }
+        let response = app
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let header = response.headers()[REQUEST_ID].to_str().unwrap().to_string();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (header, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn request_ids_are_kept_or_replaced() {
+        let (header, seen) = request_id(Some(b"req-42")).await;
+        assert_eq!((header.as_str(), seen.as_str()), ("req-42", "req-42"));
+
+        let long = [b'a'; 129];
+        for sent in [None, Some(&b""[..]), Some(b"has space"), Some(&long[..])] {
+            let (header, seen) = request_id(sent).await;
+            assert_eq!(header, seen);
+            assert_eq!(header.len(), 32, "{:?}", header);
+            assert!(header.bytes().all(|b| b.is_ascii_hexdigit()));
+        }
+    }
+}
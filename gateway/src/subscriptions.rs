@@ -0,0 +1,194 @@
+use crate::auth::Caller;
+use crate::schema::StudentSchema;
+use async_graphql::http::{WebSocket, WebSocketProtocols, WsMessage, ALL_WEBSOCKET_PROTOCOLS};
+use async_graphql::Data;
+use axum::extract::ws::{CloseFrame, Message, WebSocketUpgrade};
+use axum::extract::State;
+use axum::http::header::SEC_WEBSOCKET_PROTOCOL;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use futures::{future, SinkExt, StreamExt};
+
+/// `GET /graphql/ws` — run GraphQL subscriptions over a websocket, speaking
+/// `graphql-transport-ws` or the older `graphql-ws`, whichever the client
+/// asks for in `Sec-WebSocket-Protocol`.
+///
+/// Resolvers call the server as whoever opened the socket, with the token
+/// in its `Authorization` header or cookie, like every other request.
+pub async fn graphql_ws(
+    State(schema): State<StudentSchema>,
+    Extension(caller): Extension<Caller>,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let protocol = headers
+        .get(SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|value| value.to_str().ok())
+        .into_iter()
+        .flat_map(|value| value.split(','))
+        .find_map(|protocol| protocol.trim().parse::<WebSocketProtocols>().ok());
+    let Some(protocol) = protocol else {
+        return (StatusCode::BAD_REQUEST, "Unsupported websocket subprotocol").into_response();
+    };
+
+    upgrade
+        .protocols(ALL_WEBSOCKET_PROTOCOLS)
+        .on_upgrade(move |socket| async move {
+            let (mut sink, stream) = socket.split();
+            let input = stream
+                .take_while(|message| future::ready(message.is_ok()))
+                .filter_map(|message| {
+                    future::ready(match message {
+                        Ok(Message::Text(text)) => Some(text.into_bytes()),
+                        Ok(Message::Binary(bytes)) => Some(bytes),
+                        _ => None,
+                    })
+                });
+            let mut data = Data::default();
+            data.insert(caller);
+            let mut output = WebSocket::new(schema, input, protocol)
+                .connection_data(data)
+                .map(|message| match message {
+                    WsMessage::Text(text) => Message::Text(text),
+                    WsMessage::Close(code, reason) => Message::Close(Some(CloseFrame {
+                        code,
+                        reason: reason.into(),
+                    })),
+                });
+            while let Some(message) = output.next().await {
+                if sink.send(message).await.is_err() {
+                    break;
+                }
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema;
+    use axum::routing::get;
+    use axum::Router;
+    use client::StudentClient;
+    use proto::student_service_server::StudentServiceServer;
+    use proto::Student;
+    use serde_json::{json, Value};
+    use server::StudentServiceImpl;
+    use std::time::Duration;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::tungstenite::{self, Message as WsFrame};
+    use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+    use tonic::transport::Server;
+
+    // The websocket's URL, on a gateway in front of an in-memory student
+    // service, and a client of that service
+    async fn start() -> (String, StudentClient) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(StudentServiceServer::new(StudentServiceImpl::new()))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        let client = StudentClient::connect_lazy(format!("http://{}", addr)).unwrap();
+
+        let app = Router::new()
+            .route("/graphql/ws", get(graphql_ws))
+            .layer(Extension(Caller::default()))
+            .with_state(schema::build_schema(client.clone()));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+        (format!("ws://{}/graphql/ws", addr), client)
+    }
+
+    type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    async fn connect(url: &str, protocol: &'static str) -> Result<Socket, tungstenite::Error> {
+        let mut request = url.into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert(SEC_WEBSOCKET_PROTOCOL, protocol.parse().unwrap());
+        tokio_tungstenite::connect_async(request)
+            .await
+            .map(|(socket, _)| socket)
+    }
+
+    fn text(message: Value) -> WsFrame {
+        WsFrame::Text(message.to_string())
+    }
+
+    #[tokio::test]
+    async fn both_protocols_carry_subscriptions() {
+        let (url, mut client) = start().await;
+        let query = "subscription { students { change student { name } } }";
+
+        for (protocol, subscribe, next) in [
+            ("graphql-transport-ws", "subscribe", "next"),
+            ("graphql-ws", "start", "data"),
+        ] {
+            let mut socket = connect(&url, protocol).await.unwrap();
+            socket
+                .send(text(json!({ "type": "connection_init" })))
+                .await
+                .unwrap();
+            let ack = socket.next().await.unwrap().unwrap();
+            let ack: Value = serde_json::from_str(ack.to_text().unwrap()).unwrap();
+            assert_eq!(ack["type"], "connection_ack", "{}", protocol);
+            socket
+                .send(text(json!({
+                    "id": "1",
+                    "type": subscribe,
+                    "payload": { "query": query },
+                })))
+                .await
+                .unwrap();
+
+            // Until the watch has started, and seen one of them
+            let mut n = 0;
+            let message = loop {
+                n += 1;
+                client
+                    .create_student(Student {
+                        name: format!("{} {}", protocol, n),
+                        email: format!("{}-{}@university.edu", protocol, n),
+                        age: 20,
+                        ..Default::default()
+                    })
+                    .await
+                    .unwrap();
+                let read = tokio::time::timeout(Duration::from_millis(50), socket.next());
+                if let Ok(message) = read.await {
+                    break message.unwrap().unwrap();
+                }
+            };
+            let message: Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+            assert_eq!(message["type"], next, "{}", protocol);
+            assert_eq!(message["id"], "1");
+            let event = &message["payload"]["data"]["students"];
+            assert_eq!(event["change"], "CREATED");
+            assert!(event["student"]["name"]
+                .as_str()
+                .unwrap()
+                .starts_with(protocol));
+        }
+    }
+
+    #[tokio::test]
+    async fn other_protocols_are_refused() {
+        let (url, _) = start().await;
+        match connect(&url, "mqtt").await {
+            Err(tungstenite::Error::Http(response)) => {
+                assert_eq!(response.status(), StatusCode::BAD_REQUEST)
+            }
+            other => panic!("expected 400, got {:?}", other.map(|_| ())),
+        }
+    }
+}
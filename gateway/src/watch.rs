@@ -0,0 +1,93 @@
+use crate::auth::Caller;
+use crate::status::error_response;
+use axum::extract::{Query, State};
+use axum::http::HeaderMap;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::Response;
+use axum::Extension;
+use client::StudentClient;
+use futures::{Stream, StreamExt};
+use proto::{AcademicStanding, ChangeType, WatchStudentsRequest};
+use serde::Deserialize;
+use std::convert::Infallible;
+use tonic::Status;
+
+#[derive(Debug, Deserialize)]
+pub struct WatchParams {
+    /// Only forward events for this major
+    #[serde(default)]
+    major: String,
+    /// Only forward events for students in this standing: `good`,
+    /// `deans_list`, or `probation`
+    #[serde(default)]
+    standing: String,
+}
+
+// The standing named in `?standing=`, or any for none
+#[allow(clippy::result_large_err)]
+fn standing(name: &str) -> Result<AcademicStanding, Status> {
+    match name {
+        "" => Ok(AcademicStanding::Unspecified),
+        "good" => Ok(AcademicStanding::Good),
+        "deans_list" => Ok(AcademicStanding::DeansList),
+        "probation" => Ok(AcademicStanding::Probation),
+        _ => Err(Status::invalid_argument(format!(
+            "Unknown standing {:?}; expected good, deans_list, or probation",
+            name
+        ))),
+    }
+}
+
+/// `GET /v1/students:watch?major=...&standing=...` — bridge `WatchStudents`
+/// to the browser as Server-Sent Events.
+///
+/// Each event is named after its change type (`created`, `updated`,
+/// `deleted`, `shutting_down`), carries the `StudentEvent` as canonical JSON,
+/// and uses its resume token as the event ID. If the gRPC stream fails (it
+/// ends after its first error), a final `error` event is sent; the browser's
+/// `EventSource` then reconnects on its own, sending `Last-Event-ID` so the
+/// stream resumes after the last event it received.
+pub async fn watch_students(
+    State(client): State<StudentClient>,
+    Extension(caller): Extension<Caller>,
+    Query(params): Query<WatchParams>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, Response> {
+    let resume_token = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let request = WatchStudentsRequest {
+        major: params.major,
+        resume_token: resume_token.to_string(),
+        standing: standing(&params.standing).map_err(error_response)? as i32,
+    };
+    let events = caller
+        .client(client)
+        .watch(request)
+        .await
+        .map_err(error_response)?;
+
+    let events = events.map(|event| {
+        let event = match event {
+            Ok(event) => {
+                let name = match event.change_type() {
+                    ChangeType::Created => "created",
+                    ChangeType::Updated => "updated",
+                    ChangeType::Deleted => "deleted",
+                    ChangeType::ShuttingDown => "shutting_down",
+                    ChangeType::Unspecified => "unspecified",
+                };
+                Event::default()
+                    .event(name)
+                    .id(event.resume_token.clone())
+                    .json_data(&event)
+                    .unwrap_or_else(|e| Event::default().event("error").data(e.to_string()))
+            }
+            Err(status) => Event::default().event("error").data(status.message()),
+        };
+        Ok(event)
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
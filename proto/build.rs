@@ -1,4 +1,208 @@
+use prost::Message;
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::{DescriptorProto, FileDescriptorSet};
+use std::env;
+use std::fmt::Write;
+use std::path::PathBuf;
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::compile_protos("proto/student.proto")?;
+    let descriptor_path = PathBuf::from(env::var("OUT_DIR")?).join("student_descriptor.bin");
+
+    tonic_build::configure()
+        // `connect` needs tonic's transport, which wasm32 builds go without
+        .build_transport(env::var_os("CARGO_FEATURE_TRANSPORT").is_some())
+        .file_descriptor_set_path(&descriptor_path)
+        // Well-known types that come with their JSON mapping
+        .compile_well_known_types(true)
+        .extern_path(".google.protobuf", "::pbjson_types")
+        .compile(
+            &[
+                "proto/options.proto",
+                "proto/student.proto",
+                "proto/enrollment.proto",
+                "proto/catalog.proto",
+                "proto/professor.proto",
+                "proto/attendance.proto",
+                "proto/operations.proto",
+                "proto/scholarship.proto",
+                "proto/scheduler.proto",
+                "proto/duplicates.proto",
+                "proto/trash.proto",
+                "proto/bulk.proto",
+                "proto/email.proto",
+                "proto/statistics.proto",
+                "proto/logging.proto",
+                "proto/flags.proto",
+                "proto/quota.proto",
+                "proto/storage.proto",
+                "proto/google/rpc/status.proto",
+                "proto/google/rpc/error_details.proto",
+                "proto/grpc/health/v1/health.proto",
+            ],
+            &["proto"],
+        )?;
+
+    // Canonical proto3 JSON mapping (camelCase names, both spellings accepted on input).
+    // Default values are emitted so every record has the same shape, which CSV needs.
+    let descriptor_set = std::fs::read(&descriptor_path)?;
+    pbjson_build::Builder::new()
+        .register_descriptors(&descriptor_set)?
+        .emit_fields()
+        .build(&[".student", ".google.rpc"])?;
+
+    // Field mask merging for every message, generated from the same descriptors
+    let files = FileDescriptorSet::decode(descriptor_set.as_slice())?;
+    let messages: Vec<&DescriptorProto> = files
+        .file
+        .iter()
+        .filter(|file| file.package() == "student")
+        .flat_map(|file| &file.message_type)
+        .collect();
+    let mut code = String::new();
+    for message in &messages {
+        write_mask_merge(&mut code, message, &messages)?;
+    }
+    std::fs::write(
+        PathBuf::from(env::var("OUT_DIR")?).join("student.mask.rs"),
+        code,
+    )?;
+
     Ok(())
 }
+
+// `impl MaskMerge` for one message: a match arm per field name, copying the
+// field as prost represents it, and one per `student` message field for
+// dotted paths into it
+fn write_mask_merge(
+    code: &mut String,
+    message: &DescriptorProto,
+    messages: &[&DescriptorProto],
+) -> Result<(), std::fmt::Error> {
+    let mut whole = Vec::new();
+    let mut nested = Vec::new();
+    for (index, oneof) in message.oneof_decl.iter().enumerate() {
+        let members = message
+            .field
+            .iter()
+            .filter(|field| field.oneof_index == Some(index as i32));
+        let mut names = vec![oneof.name().to_string()];
+        names.extend(members.map(|field| field.name().to_string()));
+        let rust = rust_name(oneof.name());
+        whole.push(format!(
+            "{} => self.{} = from.{}.clone(),",
+            pattern(&names),
+            rust,
+            rust
+        ));
+    }
+    for field in message
+        .field
+        .iter()
+        .filter(|field| field.oneof_index.is_none())
+    {
+        let rust = rust_name(field.name());
+        let names = [field.name().to_string()];
+        let copy = field.label() != Label::Repeated
+            && !matches!(field.r#type(), Type::String | Type::Bytes | Type::Message);
+        let clone = if copy { "" } else { ".clone()" };
+        whole.push(format!(
+            "{} => self.{} = from.{}{},",
+            pattern(&names),
+            rust,
+            rust,
+            clone
+        ));
+        let message_field = field.label() != Label::Repeated
+            && field.r#type() == Type::Message
+            && field
+                .type_name()
+                .strip_prefix(".student.")
+                .is_some_and(|type_name| messages.iter().any(|m| m.name() == type_name));
+        if message_field {
+            nested.push(format!(
+                "{pattern} => {{\n\
+                 let mut {rust} = self.{rust}.clone().unwrap_or_default();\n\
+                 crate::MaskMerge::merge_path(&mut {rust}, \
+                 from.{rust}.as_ref().unwrap_or(&Default::default()), rest)\n\
+                 .map_err(|_| crate::UnknownPath(path.to_string()))?;\n\
+                 self.{rust} = Some({rust});\n\
+                 }}",
+                pattern = pattern(&names),
+            ));
+        }
+    }
+
+    let (from, unknown) = if whole.is_empty() {
+        ("_", "Err(crate::UnknownPath(path.to_string()))")
+    } else {
+        ("from", "return Err(crate::UnknownPath(path.to_string()))")
+    };
+    writeln!(code, "impl crate::MaskMerge for {} {{", message.name())?;
+    writeln!(
+        code,
+        "fn merge_path(&mut self, {}: &Self, path: &str) -> Result<(), crate::UnknownPath> {{",
+        from
+    )?;
+    if whole.is_empty() {
+        writeln!(code, "{}\n}}\n}}", unknown)?;
+        return Ok(());
+    }
+    writeln!(code, "match path.split_once('.') {{")?;
+    writeln!(code, "None => match path {{")?;
+    for arm in &whole {
+        writeln!(code, "{}", arm)?;
+    }
+    writeln!(code, "_ => {},\n}},", unknown)?;
+    if !nested.is_empty() {
+        writeln!(code, "Some((head, rest)) => match head {{")?;
+        for arm in &nested {
+            writeln!(code, "{}", arm)?;
+        }
+        writeln!(code, "_ => {},\n}},", unknown)?;
+    } else {
+        writeln!(code, "Some(_) => {},", unknown)?;
+    }
+    writeln!(code, "}}\nOk(())\n}}\n}}")?;
+    Ok(())
+}
+
+// A pattern matching each name as written in the proto and in the JSON
+// mapping's lowerCamelCase
+fn pattern(names: &[String]) -> String {
+    let mut spellings: Vec<String> = Vec::new();
+    for name in names {
+        for spelling in [name.clone(), lower_camel(name)] {
+            if !spellings.contains(&spelling) {
+                spellings.push(spelling);
+            }
+        }
+    }
+    let quoted: Vec<String> = spellings.iter().map(|s| format!("{:?}", s)).collect();
+    quoted.join(" | ")
+}
+
+fn lower_camel(name: &str) -> String {
+    let mut camel = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.chars() {
+        match c {
+            '_' => upper = true,
+            c if upper => {
+                camel.extend(c.to_uppercase());
+                upper = false;
+            }
+            c => camel.push(c),
+        }
+    }
+    camel
+}
+
+// Field names as prost writes them; none of ours collide with other keywords
+fn rust_name(name: &str) -> String {
+    match name {
+        "type" | "ref" | "match" | "move" | "use" | "mod" | "in" | "fn" | "loop" => {
+            format!("r#{}", name)
+        }
+        name => name.to_string(),
+    }
+}
@@ -1,5 +1,59 @@
 pub mod student {
     tonic::include_proto!("student");
+    include!(concat!(env!("OUT_DIR"), "/student.serde.rs"));
+    include!(concat!(env!("OUT_DIR"), "/student.mask.rs"));
+}
+
+/// The standard rich error model, for error details in `tonic::Status`.
+pub mod google {
+    pub mod rpc {
+        tonic::include_proto!("google.rpc");
+        include!(concat!(env!("OUT_DIR"), "/google.rpc.serde.rs"));
+    }
+}
+
+/// The standard health checking protocol, which load balancers and
+/// orchestrators speak.
+pub mod grpc {
+    pub mod health {
+        pub mod v1 {
+            tonic::include_proto!("grpc.health.v1");
+        }
+    }
 }
 
 pub use student::*;
+pub use pbjson_types::{Any, FieldMask, Timestamp};
+
+/// Setting the fields a `google.protobuf.FieldMask` names. The build script
+/// implements it for every message in the `student` package, so merging
+/// needs no hand-written match per field.
+pub trait MaskMerge {
+    /// Copies the field at `path` from `from`. The path is a field name as
+    /// in the proto or in lowerCamelCase; a dotted one such as
+    /// `address.city` reaches into a message field, which is created if
+    /// unset. A oneof is copied whole, by its own name or any member's.
+    fn merge_path(&mut self, from: &Self, path: &str) -> Result<(), UnknownPath>;
+
+    /// Copies every field in `paths` from `from`, stopping at the first
+    /// unknown one.
+    fn merge_mask(&mut self, from: &Self, paths: &[String]) -> Result<(), UnknownPath> {
+        paths.iter().try_for_each(|path| self.merge_path(from, path))
+    }
+}
+
+/// A field mask path that names no field of the message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownPath(pub String);
+
+impl std::fmt::Display for UnknownPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "No field {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownPath {}
+
+/// Encoded `FileDescriptorSet` for the service protos and everything they import.
+pub const FILE_DESCRIPTOR_SET: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/student_descriptor.bin"));
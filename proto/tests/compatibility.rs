@@ -0,0 +1,209 @@
+//! Wire-compatibility check of the schema against the last released one.
+//!
+//! `compat/student.binpb` is the descriptor set of the released schema. The
+//! current schema may add messages, fields, enum values and methods, but must
+//! not remove or renumber anything, change a field's type, or reuse a
+//! reserved number. Deleting a field is fine once its number is reserved.
+//!
+//! After a release, refresh the baseline from the `proto/` directory:
+//!
+//! ```text
+//! protoc --include_imports -Iproto --descriptor_set_out=compat/student.binpb proto/student.proto
+//! ```
+
+use prost_reflect::prost::Message;
+use prost_reflect::prost_types::FileDescriptorSet;
+use prost_reflect::{Cardinality, DescriptorPool, FieldDescriptor, Kind};
+
+const BASELINE: &[u8] = include_bytes!("../compat/student.binpb");
+
+fn type_name(field: &FieldDescriptor) -> String {
+    let kind = match field.kind() {
+        Kind::Message(message) => message.full_name().to_string(),
+        Kind::Enum(enum_type) => enum_type.full_name().to_string(),
+        scalar => format!("{:?}", scalar).to_lowercase(),
+    };
+    match field.cardinality() {
+        Cardinality::Repeated => format!("repeated {}", kind),
+        _ => kind,
+    }
+}
+
+/// Every change from `old` to `new` that breaks existing clients or data.
+fn breaking_changes(old: &DescriptorPool, new: &DescriptorPool) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    for old_message in old.all_messages() {
+        let name = old_message.full_name();
+        // Imported well-known types are not ours to check
+        if name.starts_with("google.protobuf.") {
+            continue;
+        }
+        let Some(new_message) = new.get_message_by_name(name) else {
+            problems.push(format!("message {} was removed", name));
+            continue;
+        };
+
+        for old_field in old_message.fields() {
+            let number = old_field.number();
+            match new_message.get_field(number) {
+                Some(new_field) => {
+                    if new_field.name() != old_field.name() {
+                        problems.push(format!(
+                            "{} field {} was renamed from {} to {}",
+                            name,
+                            number,
+                            old_field.name(),
+                            new_field.name()
+                        ));
+                    }
+                    if type_name(&new_field) != type_name(&old_field) {
+                        problems.push(format!(
+                            "{}.{} changed type from {} to {}",
+                            name,
+                            old_field.name(),
+                            type_name(&old_field),
+                            type_name(&new_field)
+                        ));
+                    }
+                }
+                None if new_message
+                    .reserved_ranges()
+                    .any(|range| range.contains(&number)) => {}
+                None => problems.push(format!(
+                    "{}.{} (field {}) was removed without reserving its number",
+                    name,
+                    old_field.name(),
+                    number
+                )),
+            }
+        }
+
+        for new_field in new_message.fields() {
+            if old_message
+                .reserved_ranges()
+                .any(|range| range.contains(&new_field.number()))
+            {
+                problems.push(format!(
+                    "{}.{} reuses reserved field number {}",
+                    name,
+                    new_field.name(),
+                    new_field.number()
+                ));
+            }
+        }
+    }
+
+    for old_enum in old.all_enums() {
+        let name = old_enum.full_name();
+        if name.starts_with("google.protobuf.") {
+            continue;
+        }
+        let Some(new_enum) = new.get_enum_by_name(name) else {
+            problems.push(format!("enum {} was removed", name));
+            continue;
+        };
+
+        for old_value in old_enum.values() {
+            let number = old_value.number();
+            if new_enum.get_value(number).is_none()
+                && !new_enum
+                    .reserved_ranges()
+                    .any(|range| range.contains(&number))
+            {
+                problems.push(format!(
+                    "{}.{} (value {}) was removed without reserving its number",
+                    name,
+                    old_value.name(),
+                    number
+                ));
+            }
+        }
+    }
+
+    for old_service in old.services() {
+        let name = old_service.full_name();
+        let Some(new_service) = new.get_service_by_name(name) else {
+            problems.push(format!("service {} was removed", name));
+            continue;
+        };
+
+        for old_method in old_service.methods() {
+            let Some(new_method) = new_service
+                .methods()
+                .find(|method| method.name() == old_method.name())
+            else {
+                problems.push(format!("{}.{} was removed", name, old_method.name()));
+                continue;
+            };
+            let signature = |method: &prost_reflect::MethodDescriptor| {
+                (
+                    method.input().full_name().to_string(),
+                    method.output().full_name().to_string(),
+                    method.is_client_streaming(),
+                    method.is_server_streaming(),
+                )
+            };
+            if signature(&new_method) != signature(&old_method) {
+                problems.push(format!(
+                    "{}.{} changed its request, response or streaming",
+                    name,
+                    old_method.name()
+                ));
+            }
+        }
+    }
+
+    problems
+}
+
+fn pool(bytes: &[u8]) -> DescriptorPool {
+    DescriptorPool::decode(bytes).expect("valid descriptor set")
+}
+
+#[test]
+fn schema_is_wire_compatible_with_baseline() {
+    let problems = breaking_changes(&pool(BASELINE), &pool(proto::FILE_DESCRIPTOR_SET));
+    assert!(
+        problems.is_empty(),
+        "breaking schema changes:\n  {}",
+        problems.join("\n  ")
+    );
+}
+
+// Make sure the check notices a field removed without a reservation, and
+// accepts the same removal once the number is reserved
+#[test]
+fn removing_a_field_requires_reserving_it() {
+    let mut files = FileDescriptorSet::decode(BASELINE).unwrap();
+    let student = files
+        .file
+        .iter_mut()
+        .flat_map(|file| file.message_type.iter_mut())
+        .find(|message| message.name() == "Student")
+        .unwrap();
+    student.field.retain(|field| field.name() != "gpa");
+
+    let removed = pool(&files.encode_to_vec());
+    let problems = breaking_changes(&pool(BASELINE), &removed);
+    assert_eq!(
+        problems,
+        ["student.Student.gpa (field 6) was removed without reserving its number"]
+    );
+
+    let student = files
+        .file
+        .iter_mut()
+        .flat_map(|file| file.message_type.iter_mut())
+        .find(|message| message.name() == "Student")
+        .unwrap();
+    student.reserved_range.push(
+        prost_reflect::prost_types::descriptor_proto::ReservedRange {
+            start: Some(6),
+            end: Some(7),
+        },
+    );
+
+    let reserved = pool(&files.encode_to_vec());
+    assert!(breaking_changes(&pool(BASELINE), &reserved).is_empty());
+}
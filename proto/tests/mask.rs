@@ -0,0 +1,100 @@
+//! The generated `MaskMerge` impls.
+
+use proto::batch_write_entry::Write;
+use proto::{Address, BatchWriteEntry, ListDepartmentsRequest, MaskMerge, Student, UnknownPath};
+
+fn ada() -> Student {
+    Student {
+        id: "s1".to_string(),
+        name: "Ada".to_string(),
+        age: 20,
+        major_id: "math".to_string(),
+        address: Some(Address {
+            city: "London".to_string(),
+            country: "GB".to_string(),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn masked_fields_are_copied_and_nothing_else() {
+    let mut student = ada();
+    let from = Student {
+        id: "other".to_string(),
+        name: "Grace".to_string(),
+        age: 30,
+        major_id: "cs".to_string(),
+        phone_numbers: vec!["+16502530000".to_string()],
+        ..Default::default()
+    };
+
+    let paths = ["age", "majorId", "phone_numbers"].map(String::from);
+    student.merge_mask(&from, &paths).unwrap();
+    assert_eq!(
+        student,
+        Student {
+            age: 30,
+            major_id: "cs".to_string(),
+            phone_numbers: vec!["+16502530000".to_string()],
+            ..ada()
+        }
+    );
+}
+
+#[test]
+fn dotted_paths_reach_into_messages() {
+    let mut student = ada();
+    let from = Student {
+        address: Some(Address {
+            city: "Paris".to_string(),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    student.merge_path(&from, "address.city").unwrap();
+    let address = student.address.clone().unwrap();
+    assert_eq!(
+        (address.city.as_str(), address.country.as_str()),
+        ("Paris", "GB")
+    );
+
+    // An unset message reads as its defaults, and an unset target is created
+    student
+        .merge_path(&Student::default(), "address.country")
+        .unwrap();
+    assert_eq!(student.address.unwrap().country, "");
+    let mut blank = Student::default();
+    blank.merge_path(&ada(), "address.postalCode").unwrap();
+    assert_eq!(blank.address, Some(Address::default()));
+}
+
+#[test]
+fn oneofs_are_copied_whole() {
+    let mut entry = BatchWriteEntry::default();
+    let from = BatchWriteEntry {
+        write: Some(Write::DeleteId("s1".to_string())),
+    };
+    entry.merge_path(&from, "delete_id").unwrap();
+    assert_eq!(entry, from);
+    entry
+        .merge_path(&BatchWriteEntry::default(), "write")
+        .unwrap();
+    assert_eq!(entry.write, None);
+}
+
+#[test]
+fn unknown_paths_are_refused() {
+    let mut student = ada();
+    for path in ["nickname", "age.years", "address.planet", ""] {
+        assert_eq!(
+            student.merge_path(&Student::default(), path),
+            Err(UnknownPath(path.to_string()))
+        );
+    }
+    assert_eq!(student, ada());
+
+    let mut request = ListDepartmentsRequest::default();
+    assert!(request.merge_path(&request.clone(), "pageSize").is_ok());
+}
@@ -0,0 +1,131 @@
+//! Importing a large CSV: one `CreateStudent` call per row, as `student
+//! import` used to, against streaming the rows to `BulkCreateStudents`.
+//!
+//! Run with `cargo bench -p server --bench import > /dev/null`. The server's
+//! per-student log lines go to stdout and the results to stderr.
+
+use proto::bulk_service_client::BulkServiceClient;
+use proto::bulk_service_server::BulkServiceServer;
+use proto::student_service_client::StudentServiceClient;
+use proto::student_service_server::StudentServiceServer;
+use proto::{BulkCreateStudentsRequest, CreateStudentRequest, Student};
+use server::bulk::BulkServiceImpl;
+use server::catalog::Catalog;
+use server::operations::Operations;
+use server::repository::{InMemoryRepository, StudentRepository};
+use server::service::StudentServiceImpl;
+use server::standing::StandingRules;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::{Channel, Server};
+
+const ROWS: usize = 100_000;
+
+// As the CLI sends them
+const CHUNK: usize = 500;
+
+fn row(index: usize) -> Student {
+    Student {
+        name: format!("Student {}", index),
+        email: format!("s{}@university.edu", index),
+        age: 20,
+        major: "Computer Science".to_string(),
+        gpa: (index % 40) as f64 / 10.0,
+        credits: 30,
+        ..Default::default()
+    }
+}
+
+// A fresh server with an empty store, and a channel to it
+async fn start() -> (Arc<InMemoryRepository>, Channel) {
+    let store = Arc::new(InMemoryRepository::new());
+    let students = StudentServiceImpl::new().with_repository(store.clone());
+    let bulk = BulkServiceImpl::new(
+        store.clone(),
+        Arc::new(Catalog::new()),
+        StandingRules::default(),
+        Operations::new(),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .add_service(StudentServiceServer::new(students))
+            .add_service(BulkServiceServer::new(bulk))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    let channel = Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    (store, channel)
+}
+
+fn report(name: &str, elapsed: Duration) {
+    eprintln!(
+        "{:<28} {:>8.2} s {:>10.0} rows/s",
+        name,
+        elapsed.as_secs_f64(),
+        ROWS as f64 / elapsed.as_secs_f64()
+    );
+}
+
+async fn sequential() -> Duration {
+    let (store, channel) = start().await;
+    let mut client = StudentServiceClient::new(channel);
+    let started = Instant::now();
+    for index in 0..ROWS {
+        client
+            .create_student(CreateStudentRequest {
+                student: Some(row(index)),
+            })
+            .await
+            .unwrap();
+    }
+    let elapsed = started.elapsed();
+    assert_eq!(store.list(1, "").await.unwrap().total_count, ROWS as i32);
+    elapsed
+}
+
+async fn streamed() -> Duration {
+    let (store, channel) = start().await;
+    let mut client = BulkServiceClient::new(channel);
+    let rows: Vec<Student> = (0..ROWS).map(row).collect();
+    let requests: Vec<BulkCreateStudentsRequest> = rows
+        .chunks(CHUNK)
+        .map(|chunk| BulkCreateStudentsRequest {
+            students: chunk.to_vec(),
+        })
+        .collect();
+    let started = Instant::now();
+    let response = client
+        .bulk_create_students(tokio_stream::iter(requests))
+        .await
+        .unwrap()
+        .into_inner();
+    let elapsed = started.elapsed();
+    assert_eq!(response.created_count, ROWS as i32);
+    assert_eq!(store.list(1, "").await.unwrap().total_count, ROWS as i32);
+    elapsed
+}
+
+fn main() {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        eprintln!("{} rows\n", ROWS);
+        let one_by_one = sequential().await;
+        report("CreateStudent per row", one_by_one);
+        let bulk = streamed().await;
+        report("BulkCreateStudents", bulk);
+        eprintln!(
+            "\n{:.1}x faster",
+            one_by_one.as_secs_f64() / bulk.as_secs_f64()
+        );
+    });
+}
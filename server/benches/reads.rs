@@ -0,0 +1,138 @@
+//! Reads from the in-memory store: owned copies against shared ones.
+//!
+//! Run with `cargo bench -p server --bench reads`. Prints the time and heap
+//! allocations per call for each way of reading.
+
+use proto::Student;
+use server::repository::{InMemoryRepository, StudentRepository};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+// Counts allocations so the table can show them
+struct Counting;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const STUDENTS: usize = 100_000;
+
+fn student(index: usize) -> Student {
+    Student {
+        id: format!("s{:06}", index),
+        name: format!("Student {}", index),
+        email: format!("s{}@university.edu", index),
+        age: 20,
+        major: "Computer Science".to_string(),
+        gpa: (index % 40) as f64 / 10.0,
+        credits: 30,
+        ..Default::default()
+    }
+}
+
+// Time `iterations` calls of `call` and print the cost of one
+async fn measure<F, Fut>(name: &str, iterations: usize, mut call: F)
+where
+    F: FnMut(usize) -> Fut,
+    Fut: std::future::Future<Output = ()>,
+{
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let started = Instant::now();
+    for iteration in 0..iterations {
+        call(iteration).await;
+    }
+    let elapsed = started.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    println!(
+        "{:<36} {:>12.0} ns {:>12.1} allocations",
+        name,
+        elapsed.as_nanos() as f64 / iterations as f64,
+        allocations as f64 / iterations as f64
+    );
+}
+
+// What the standing filter did before `all`: read every page as owned copies
+async fn owned_scan(store: &InMemoryRepository) -> Vec<Student> {
+    let mut students = Vec::new();
+    let mut page_token = String::new();
+    loop {
+        let page = store.list(100, &page_token).await.unwrap();
+        students.extend(page.students);
+        if page.next_page_token.is_empty() {
+            return students;
+        }
+        page_token = page.next_page_token;
+    }
+}
+
+fn main() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let store = InMemoryRepository::new();
+        for index in 0..STUDENTS {
+            store.create(student(index)).await.unwrap();
+        }
+        let id = |iteration: usize| format!("s{:06}", iteration * 7919 % STUDENTS);
+
+        println!("{} students\n", STUDENTS);
+        measure("get (owned copy)", 200_000, |i| {
+            let store = &store;
+            async move {
+                black_box(store.get(&id(i)).await.unwrap());
+            }
+        })
+        .await;
+        measure("get_shared", 200_000, |i| {
+            let store = &store;
+            async move {
+                black_box(store.get_shared(&id(i)).await.unwrap());
+            }
+        })
+        .await;
+
+        // A tenth of the students match, as with a standing filter
+        measure("scan + filter, owned pages", 20, |_| {
+            let store = &store;
+            async move {
+                let matching: Vec<Student> = owned_scan(store)
+                    .await
+                    .into_iter()
+                    .filter(|student| student.gpa < 0.4)
+                    .collect();
+                black_box(matching);
+            }
+        })
+        .await;
+        measure("scan + filter, shared (all)", 20, |_| {
+            let store = &store;
+            async move {
+                let matching: Vec<Student> = store
+                    .all()
+                    .await
+                    .unwrap()
+                    .iter()
+                    .filter(|student| student.gpa < 0.4)
+                    .map(|student| Student::clone(student))
+                    .collect();
+                black_box(matching);
+            }
+        })
+        .await;
+    });
+}
@@ -0,0 +1,26 @@
+-- Written with IF NOT EXISTS, as the schema was before there were
+-- migrations, so databases created then take these as already applied
+CREATE TABLE IF NOT EXISTS students (
+    id           TEXT PRIMARY KEY,
+    name         TEXT NOT NULL,
+    email        TEXT NOT NULL,
+    age          INTEGER NOT NULL,
+    major        TEXT NOT NULL,
+    gpa          DOUBLE PRECISION NOT NULL,
+    create_secs  BIGINT,
+    create_nanos INTEGER,
+    update_secs  BIGINT,
+    update_nanos INTEGER
+);
+ALTER TABLE students ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT 1;
+ALTER TABLE students ADD COLUMN IF NOT EXISTS major_id TEXT NOT NULL DEFAULT '';
+ALTER TABLE students ADD COLUMN IF NOT EXISTS credits INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE students ADD COLUMN IF NOT EXISTS preferred_name TEXT NOT NULL DEFAULT '';
+ALTER TABLE students ADD COLUMN IF NOT EXISTS phone_numbers TEXT[] NOT NULL DEFAULT '{}';
+ALTER TABLE students ADD COLUMN IF NOT EXISTS address JSONB;
+ALTER TABLE students ADD COLUMN IF NOT EXISTS credits_attempted INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE students ADD COLUMN IF NOT EXISTS credits_earned INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE students ADD COLUMN IF NOT EXISTS annotations JSONB NOT NULL DEFAULT '{}';
+-- The transaction that created the row, which updates leave alone
+ALTER TABLE students ADD COLUMN IF NOT EXISTS created_xid xid8 NOT NULL
+    DEFAULT pg_current_xact_id();
@@ -0,0 +1,6 @@
+-- Each change to a student, until the relay has published it
+CREATE TABLE IF NOT EXISTS student_outbox (
+    id          BIGSERIAL PRIMARY KEY,
+    change_type INTEGER NOT NULL,
+    student     JSONB NOT NULL
+);
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    key          TEXT PRIMARY KEY,
+    fingerprint  TEXT NOT NULL,
+    -- Each written student, protobuf-encoded
+    students     BYTEA[] NOT NULL DEFAULT '{}',
+    committed_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE INDEX IF NOT EXISTS idempotency_keys_committed_at
+    ON idempotency_keys (committed_at);
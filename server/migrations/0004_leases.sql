@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS leases (
+    name       TEXT PRIMARY KEY,
+    holder     TEXT NOT NULL,
+    -- Where clients reach the holder
+    address    TEXT NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,5 @@
+-- Every write takes the next version from one sequence, so no two writes
+-- share an etag, even to a student deleted and created again
+CREATE SEQUENCE IF NOT EXISTS student_versions;
+SELECT setval('student_versions', GREATEST((SELECT MAX(version) FROM students), 1));
+ALTER TABLE students ALTER COLUMN version SET DEFAULT nextval('student_versions');
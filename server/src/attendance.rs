@@ -0,0 +1,230 @@
+//! Attendance at the courses of the enrollment service.
+//!
+//! Scanners stream check-ins through `RecordAttendance`; each record is
+//! checked on its own, so one bad scan does not lose the rest of the batch.
+//! A course meets at most once per UTC day, so a record replaces any earlier
+//! one for the same student, course, and day, which also makes re-sending a
+//! batch after a failed call safe. Records are kept in memory.
+
+use crate::enrollment::EnrollmentServiceImpl;
+use crate::errors::Error;
+use proto::attendance_service_server::AttendanceService;
+use proto::{
+    AttendanceRecord, AttendanceStatus, GetAttendanceSummaryRequest, GetAttendanceSummaryResponse,
+    ListAttendanceRequest, ListAttendanceResponse, RecordAttendanceResponse, RejectedRecord,
+    Timestamp,
+};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tonic::{Request, Response, Status, Streaming};
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+// Student ID, course ID, and days since the Unix epoch (UTC)
+type SessionKey = (String, String, i64);
+
+#[derive(Debug)]
+pub struct AttendanceServiceImpl {
+    enrollment: Arc<EnrollmentServiceImpl>,
+    records: Mutex<BTreeMap<SessionKey, AttendanceRecord>>,
+}
+
+fn sort_key(time: &Timestamp) -> (i64, i32) {
+    (time.seconds, time.nanos)
+}
+
+// Start is inclusive, end exclusive; either may be missing
+fn in_range(time: &Timestamp, start: Option<&Timestamp>, end: Option<&Timestamp>) -> bool {
+    start.is_none_or(|start| sort_key(time) >= sort_key(start))
+        && end.is_none_or(|end| sort_key(time) < sort_key(end))
+}
+
+fn check_range(start: Option<&Timestamp>, end: Option<&Timestamp>) -> Result<(), Status> {
+    if let (Some(start), Some(end)) = (start, end) {
+        if sort_key(end) < sort_key(start) {
+            return Err(Error::TimeRangeReversed.into());
+        }
+    }
+    Ok(())
+}
+
+impl AttendanceServiceImpl {
+    /// Record attendance for the courses and rosters of `enrollment`.
+    pub fn new(enrollment: Arc<EnrollmentServiceImpl>) -> Self {
+        Self {
+            enrollment,
+            records: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn records(&self) -> MutexGuard<'_, BTreeMap<SessionKey, AttendanceRecord>> {
+        self.records.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Move the attendance records of student `from` to student `to`,
+    /// returning the course and day of each record moved. A record for a
+    /// day `to` already has one for is dropped. With `only`, just those
+    /// records move and `from` keeps the rest.
+    pub fn transfer(
+        &self,
+        from: &str,
+        to: &str,
+        only: Option<&[(String, i64)]>,
+    ) -> Vec<(String, i64)> {
+        let mut records = self.records();
+        let keys: Vec<SessionKey> = records
+            .keys()
+            .filter(|(student_id, course_id, day)| {
+                student_id == from
+                    && only.is_none_or(|only| only.contains(&(course_id.clone(), *day)))
+            })
+            .cloned()
+            .collect();
+
+        let mut moved = Vec::new();
+        for key in keys {
+            let mut record = records.remove(&key).expect("listed above");
+            let (_, course_id, day) = key;
+            let new_key = (to.to_string(), course_id, day);
+            if records.contains_key(&new_key) {
+                continue;
+            }
+            record.student_id = to.to_string();
+            records.insert(new_key.clone(), record);
+            moved.push((new_key.1, new_key.2));
+        }
+        moved
+    }
+
+    // The reason a record is rejected, if it is
+    fn check(&self, record: &AttendanceRecord) -> Result<SessionKey, String> {
+        let Some(check_in_time) = &record.check_in_time else {
+            return Err("check_in_time is required".to_string());
+        };
+        if !matches!(
+            AttendanceStatus::try_from(record.status),
+            Ok(AttendanceStatus::Present
+                | AttendanceStatus::Late
+                | AttendanceStatus::Absent
+                | AttendanceStatus::Excused)
+        ) {
+            return Err(format!("Invalid attendance status: {}", record.status));
+        }
+        let Some(enrolled) = self.enrollment.enrolled_students(&record.course_id) else {
+            return Err(format!("Unknown course: {}", record.course_id));
+        };
+        if !enrolled.contains(&record.student_id) {
+            return Err(format!(
+                "Student {} does not hold a seat in {}",
+                record.student_id, record.course_id
+            ));
+        }
+        Ok((
+            record.student_id.clone(),
+            record.course_id.clone(),
+            check_in_time.seconds.div_euclid(SECONDS_PER_DAY),
+        ))
+    }
+}
+
+#[tonic::async_trait]
+impl AttendanceService for AttendanceServiceImpl {
+    async fn record_attendance(
+        &self,
+        request: Request<Streaming<AttendanceRecord>>,
+    ) -> Result<Response<RecordAttendanceResponse>, Status> {
+        let mut stream = request.into_inner();
+
+        let mut recorded_count = 0;
+        let mut rejected = Vec::new();
+        let mut index = 0;
+        // Records are kept as they arrive, so a broken stream keeps what came before
+        while let Some(record) = stream.message().await? {
+            match self.check(&record) {
+                Ok(key) => {
+                    self.records().insert(key, record);
+                    recorded_count += 1;
+                }
+                Err(reason) => rejected.push(RejectedRecord { index, reason }),
+            }
+            index += 1;
+        }
+
+        info!(
+            "Recorded attendance: {} records, {} rejected",
+            recorded_count,
+            rejected.len()
+        );
+
+        Ok(Response::new(RecordAttendanceResponse {
+            recorded_count,
+            rejected,
+        }))
+    }
+
+    async fn get_attendance_summary(
+        &self,
+        request: Request<GetAttendanceSummaryRequest>,
+    ) -> Result<Response<GetAttendanceSummaryResponse>, Status> {
+        let request = request.into_inner();
+        let (start, end) = (request.start_time.as_ref(), request.end_time.as_ref());
+
+        if request.student_id.is_empty() || request.course_id.is_empty() {
+            return Err(Error::IdsRequired("student_id", "course_id").into());
+        }
+        check_range(start, end)?;
+
+        let mut summary = GetAttendanceSummaryResponse::default();
+        let records = self.records();
+        let sessions = records
+            .iter()
+            .filter(|((student_id, course_id, _), _)| {
+                *student_id == request.student_id && *course_id == request.course_id
+            })
+            .map(|(_, record)| record)
+            .filter(|record| {
+                record
+                    .check_in_time
+                    .as_ref()
+                    .is_some_and(|time| in_range(time, start, end))
+            });
+        for record in sessions {
+            summary.sessions += 1;
+            match record.status() {
+                AttendanceStatus::Present => summary.present += 1,
+                AttendanceStatus::Late => summary.late += 1,
+                AttendanceStatus::Absent => summary.absent += 1,
+                AttendanceStatus::Excused => summary.excused += 1,
+                AttendanceStatus::Unspecified => {}
+            }
+        }
+
+        Ok(Response::new(summary))
+    }
+
+    async fn list_attendance(
+        &self,
+        request: Request<ListAttendanceRequest>,
+    ) -> Result<Response<ListAttendanceResponse>, Status> {
+        let request = request.into_inner();
+        let (start, end) = (request.start_time.as_ref(), request.end_time.as_ref());
+        check_range(start, end)?;
+
+        let mut records: Vec<AttendanceRecord> = self
+            .records()
+            .values()
+            .filter(|record| {
+                (request.student_id.is_empty() || record.student_id == request.student_id)
+                    && (request.course_id.is_empty() || record.course_id == request.course_id)
+                    && record
+                        .check_in_time
+                        .as_ref()
+                        .is_some_and(|time| in_range(time, start, end))
+            })
+            .cloned()
+            .collect();
+        records.sort_by_key(|record| record.check_in_time.as_ref().map(sort_key));
+
+        Ok(Response::new(ListAttendanceResponse { records }))
+    }
+}
@@ -0,0 +1,337 @@
+//! Authorization against Cedar policies.
+//!
+//! [`AuthorizationLayer`] asks a Cedar policy set whether the caller may
+//! make each request, so institutions can write rules such as "advisors
+//! may read only their advisees" without changing the server. Each request
+//! is put to the policies as:
+//!
+//! - principal: the caller from [`identity`](crate::identity), e.g.
+//!   `Professor::"p1"`, in the group `Role::"professor"`; a professor in a
+//!   department has it as `department`. Anonymous callers are
+//!   `Anonymous::""`.
+//! - action: the method, e.g. `Action::"GetStudent"`.
+//! - resource: for a request about one student (its `student_id`, its
+//!   `student`'s ID, or a `StudentService` request's `id`), that student,
+//!   e.g. `Student::"s1"`, with its `advisor` and its major's `department`
+//!   where it has them; otherwise the service, e.g.
+//!   `Service::"student.CatalogService"`.
+//! - context: `tenant`, the request's tenant or "".
+//!
+//! As always with Cedar, a request is allowed only if some policy permits
+//! it and none forbids it; the rest are refused with `PERMISSION_DENIED`.
+//! Health checks are always allowed.
+
+use crate::catalog::Catalog;
+use crate::flags;
+use crate::health;
+use crate::identity::{self, Principal, Role};
+use crate::locale::{self, Text};
+use crate::professor::ProfessorServiceImpl;
+use crate::repository::StudentRepository;
+use crate::required;
+use cedar_policy::{
+    Authorizer, Context as CedarContext, Decision, Entities, Entity, EntityId, EntityTypeName,
+    EntityUid, PolicySet, Request as CedarRequest, RestrictedExpression,
+};
+use hyper::Body;
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor, Value};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tonic::body::BoxBody;
+use tonic::codegen::http::{Request, Response};
+use tonic::codegen::Service;
+use tonic::{Code, Status};
+use tower_layer::Layer;
+
+const STUDENT_SERVICE: &str = "student.StudentService";
+
+/// The Cedar policies in the file at `path`.
+pub fn load_policies(path: &Path) -> Result<PolicySet, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    PolicySet::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// What the policies are told about the callers and the students.
+#[derive(Debug, Clone)]
+pub struct Directory {
+    pub professors: Arc<ProfessorServiceImpl>,
+    pub catalog: Arc<Catalog>,
+    pub students: Arc<dyn StudentRepository>,
+}
+
+impl Directory {
+    // The caller, unless they are the student the request is about
+    fn principal(&self, principal: Option<&Principal>) -> Entity {
+        let Some(principal) = principal else {
+            return Entity::with_uid(uid("Anonymous", ""));
+        };
+        let mut attrs = HashMap::new();
+        if principal.role == Role::Professor {
+            let department = self
+                .professors
+                .professor(&principal.id)
+                .map(|professor| professor.department_id)
+                .filter(|department| !department.is_empty());
+            if let Some(department) = department {
+                attrs.insert(
+                    "department".to_string(),
+                    reference("Department", &department),
+                );
+            }
+        }
+        Entity::new(principal_uid(principal), attrs, role_of(principal))
+    }
+
+    // The student with this ID; `parents` for a student asking about themselves
+    async fn student(&self, id: &str, parents: HashSet<EntityUid>) -> Entity {
+        let mut attrs = HashMap::new();
+        if let Some(advisor) = self.professors.advisor(id) {
+            attrs.insert("advisor".to_string(), reference("Professor", &advisor));
+        }
+        let department = match self.students.get_shared(id).await {
+            Ok(student) => self.catalog.major(&student.major_id),
+            Err(_) => None,
+        }
+        .map(|major| major.department_id)
+        .filter(|department| !department.is_empty());
+        if let Some(department) = department {
+            attrs.insert(
+                "department".to_string(),
+                reference("Department", &department),
+            );
+        }
+        Entity::new(uid("Student", id), attrs, parents)
+    }
+}
+
+fn principal_uid(principal: &Principal) -> EntityUid {
+    let kind = match principal.role {
+        Role::Admin => "Admin",
+        Role::Staff => "Staff",
+        Role::Professor => "Professor",
+        Role::Student => "Student",
+    };
+    uid(kind, &principal.id)
+}
+
+fn role_of(principal: &Principal) -> HashSet<EntityUid> {
+    HashSet::from([uid("Role", principal.role.as_str())])
+}
+
+fn uid(kind: &str, id: &str) -> EntityUid {
+    let kind = EntityTypeName::from_str(kind).expect("entity types are valid names");
+    let id = EntityId::from_str(id).expect("any string is an entity ID");
+    EntityUid::from_type_name_and_id(kind, id)
+}
+
+fn reference(kind: &str, id: &str) -> RestrictedExpression {
+    RestrictedExpression::from_str(&uid(kind, id).to_string())
+        .expect("an entity UID is a restricted expression")
+}
+
+#[derive(Debug)]
+struct Rules {
+    policies: PolicySet,
+    directory: Directory,
+    pool: DescriptorPool,
+}
+
+/// Refuses requests the policies do not allow.
+#[derive(Debug, Clone, Default)]
+pub struct AuthorizationLayer {
+    // None lets every request through, e.g. when replaying
+    rules: Option<Arc<Rules>>,
+}
+
+impl AuthorizationLayer {
+    pub fn new(policies: PolicySet, directory: Directory) -> Self {
+        Self {
+            rules: Some(Arc::new(Rules {
+                policies,
+                directory,
+                pool: DescriptorPool::decode(proto::FILE_DESCRIPTOR_SET)
+                    .expect("the proto crate's descriptor set is valid"),
+            })),
+        }
+    }
+
+    // The request message of `method`, if it is unary or server-streaming
+    fn input(&self, service: &str, method: &str) -> Option<MessageDescriptor> {
+        let method = self
+            .rules
+            .as_ref()?
+            .pool
+            .get_service_by_name(service)?
+            .methods()
+            .find(|candidate| candidate.name() == method)?;
+        (!method.is_client_streaming()).then(|| method.input())
+    }
+
+    /// Whether the current caller may call `method` of `service` with
+    /// `message`, if it could be read.
+    pub async fn allowed(
+        &self,
+        service: &str,
+        method: &str,
+        message: Option<&DynamicMessage>,
+    ) -> bool {
+        let Some(rules) = &self.rules else {
+            return true;
+        };
+        let caller = identity::current();
+        let principal = caller
+            .as_ref()
+            .map_or_else(|| uid("Anonymous", ""), principal_uid);
+        let mut entities = Vec::new();
+        let resource = match message.and_then(|message| student_id(service, message)) {
+            // One entity, both principal and resource
+            Some(id)
+                if caller
+                    .as_ref()
+                    .is_some_and(|caller| caller.role == Role::Student && caller.id == id) =>
+            {
+                let parents = caller.as_ref().map(role_of).unwrap_or_default();
+                entities.push(rules.directory.student(&id, parents).await);
+                principal.clone()
+            }
+            Some(id) => {
+                entities.push(rules.directory.principal(caller.as_ref()));
+                entities.push(rules.directory.student(&id, HashSet::new()).await);
+                uid("Student", &id)
+            }
+            None => {
+                entities.push(rules.directory.principal(caller.as_ref()));
+                uid("Service", service)
+            }
+        };
+        let roles = [Role::Admin, Role::Staff, Role::Professor, Role::Student];
+        entities.extend(roles.map(|role| Entity::with_uid(uid("Role", role.as_str()))));
+
+        let tenant = flags::current_tenant().unwrap_or_default();
+        let context = CedarContext::from_pairs([(
+            "tenant".to_string(),
+            RestrictedExpression::new_string(tenant),
+        )]);
+        let request = CedarRequest::new(
+            Some(principal),
+            Some(uid("Action", method)),
+            Some(resource),
+            context,
+        );
+        let entities = match Entities::from_entities(entities) {
+            Ok(entities) => entities,
+            Err(e) => {
+                error!(
+                    "❌ Cannot build the entities to authorize {}: {}",
+                    method, e
+                );
+                return false;
+            }
+        };
+        let response = Authorizer::new().is_authorized(&request, &rules.policies, &entities);
+        for error in response.diagnostics().errors() {
+            warn!("⚠️ Policy error authorizing {}: {}", method, error);
+        }
+        response.decision() == Decision::Allow
+    }
+}
+
+// The ID of the one student `message` is about, if it is about one
+fn student_id(service: &str, message: &DynamicMessage) -> Option<String> {
+    let string = |message: &DynamicMessage, name: &str| match message.get_field_by_name(name) {
+        Some(value) => value.as_str().map(str::to_string),
+        None => None,
+    };
+    let id = string(message, "student_id")
+        .or_else(|| match message.get_field_by_name("student").as_deref() {
+            Some(Value::Message(student)) => string(student, "id"),
+            _ => None,
+        })
+        .or_else(|| {
+            (service == STUDENT_SERVICE)
+                .then(|| string(message, "id"))
+                .flatten()
+        })?;
+    (!id.trim().is_empty()).then_some(id)
+}
+
+impl<S> Layer<S> for AuthorizationLayer {
+    type Service = Authorized<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Authorized {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// The service produced by [`AuthorizationLayer`].
+#[derive(Debug, Clone)]
+pub struct Authorized<S> {
+    inner: S,
+    layer: AuthorizationLayer,
+}
+
+impl<S> Service<Request<Body>> for Authorized<S>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        // Probes must get through without a token
+        let path = request.uri().path().to_string();
+        if self.layer.rules.is_none() || path.starts_with(health::SERVICE_PATH) {
+            return Box::pin(self.inner.call(request));
+        }
+        let (service, method) = path
+            .strip_prefix('/')
+            .and_then(|path| path.split_once('/'))
+            .map_or((String::new(), path.clone()), |(service, method)| {
+                (service.to_string(), method.to_string())
+            });
+        let input = self
+            .layer
+            .input(&service, &method)
+            .filter(|_| required::binary(&request));
+
+        // The clone that was made ready is the one that must be called
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+        Box::pin(async move {
+            let (parts, body) = request.into_parts();
+            let (body, message) = match input {
+                Some(input) => {
+                    let bytes = match hyper::body::to_bytes(body).await {
+                        Ok(bytes) => bytes,
+                        Err(error) => return Ok(Status::from_error(Box::new(error)).to_http()),
+                    };
+                    let message = required::message(&bytes)
+                        .and_then(|message| DynamicMessage::decode(input, message).ok());
+                    (Body::from(bytes), message)
+                }
+                None => (body, None),
+            };
+            if !layer.allowed(&service, &method, message.as_ref()).await {
+                let status = locale::status(Code::PermissionDenied, Text::NotAuthorized(method));
+                return Ok(status.to_http());
+            }
+            inner.call(Request::from_parts(parts, body)).await
+        })
+    }
+}
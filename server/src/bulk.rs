@@ -0,0 +1,355 @@
+//! Changing many students at once.
+//!
+//! `UpdateStudentsMatching` starts an [`Operations`] job that reads the
+//! students a page at a time and sets the masked fields on each one that
+//! matches the filter. Each new version is validated like `UpdateStudent`
+//! and written conditionally on its etag, so an edit made meanwhile is not
+//! overwritten; such students are reported as failures and left alone.
+//!
+//! `BulkCreateStudents` takes a stream of new students and creates them
+//! through the pipeline in [`crate::import`].
+
+use crate::catalog::Catalog;
+use crate::clock::{self, Clock, SystemClock};
+use crate::errors::Error;
+use crate::events::EventLog;
+use crate::ids::{IdGenerator, UuidGenerator};
+use crate::import::Pipeline;
+use crate::locale::{self, Text};
+use crate::operations::{Operations, Progress};
+use crate::repository::{StudentRepository, SCAN_PAGE_SIZE};
+use crate::standing::StandingRules;
+use crate::validation::{self, Profile};
+use phonenumber::country::Id;
+use proto::bulk_service_server::BulkService;
+use proto::MaskMerge;
+use proto::{
+    AcademicStanding, BulkCreateStudentsRequest, BulkCreateStudentsResponse, BulkUpdateFailure,
+    ChangeType, GetBulkUpdateResultRequest, Operation, Student, StudentFilter,
+    UpdateStudentsMatchingRequest, UpdateStudentsMatchingResponse,
+};
+use std::sync::Arc;
+use tonic::{Code, Request, Response, Status, Streaming};
+
+/// The kind of operation `UpdateStudentsMatching` starts.
+pub const KIND: &str = "bulk-update";
+
+// The fields a bulk update may set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Major,
+    MajorId,
+    Age,
+    Gpa,
+    Credits,
+}
+
+impl Field {
+    // The student field, as validation names it
+    fn name(self) -> &'static str {
+        match self {
+            Field::Major => "major",
+            Field::MajorId => "major_id",
+            Field::Age => "age",
+            Field::Gpa => "gpa",
+            Field::Credits => "credits",
+        }
+    }
+
+    // The student fields it sets; a GPA is kept in both forms
+    fn paths(self) -> &'static [&'static str] {
+        match self {
+            Field::Major => &["major"],
+            Field::MajorId => &["major_id"],
+            Field::Age => &["age"],
+            Field::Gpa => &["gpa", "gpa_decimal"],
+            Field::Credits => &["credits"],
+        }
+    }
+}
+
+fn parse_mask(paths: &[String]) -> Result<Vec<Field>, Status> {
+    if paths.is_empty() {
+        return Err(Error::UpdateMaskEmpty.into());
+    }
+    paths
+        .iter()
+        .map(|path| match path.as_str() {
+            "major" => Ok(Field::Major),
+            "major_id" | "majorId" => Ok(Field::MajorId),
+            "age" => Ok(Field::Age),
+            "gpa" => Ok(Field::Gpa),
+            "credits" => Ok(Field::Credits),
+            _ => Err(Error::FieldNotBulkUpdatable(path.clone()).into()),
+        })
+        .collect()
+}
+
+fn check_filter(filter: &StudentFilter) -> Result<(), Status> {
+    if filter.major.is_empty()
+        && filter.major_id.is_empty()
+        && filter.standing == AcademicStanding::Unspecified as i32
+        && filter.ids.is_empty()
+    {
+        return Err(Error::FilterEmpty.into());
+    }
+    Ok(())
+}
+
+/// Whether `student` matches every field set in `filter`.
+pub fn matches(filter: &StudentFilter, student: &Student, rules: &StandingRules) -> bool {
+    (filter.major.is_empty() || student.major.eq_ignore_ascii_case(&filter.major))
+        && (filter.major_id.is_empty() || student.major_id == filter.major_id)
+        && (filter.standing == AcademicStanding::Unspecified as i32
+            || rules.standing(student) as i32 == filter.standing)
+        && (filter.ids.is_empty() || filter.ids.contains(&student.id))
+}
+
+// Everything a job needs, moved into it
+struct Job {
+    filter: StudentFilter,
+    fields: Vec<Field>,
+    new_values: Student,
+    // The catalog name of the new major_id, which replaces `major`
+    major_name: Option<String>,
+    dry_run: bool,
+    store: Arc<dyn StudentRepository>,
+    rules: Arc<StandingRules>,
+    clock: Arc<dyn Clock>,
+    events: Option<Arc<EventLog>>,
+}
+
+impl Job {
+    fn apply(&self, mut student: Student) -> Student {
+        for path in self.fields.iter().flat_map(|field| field.paths()) {
+            student
+                .merge_path(&self.new_values, path)
+                .expect("bulk fields are student fields");
+        }
+        if let Some(name) = &self.major_name {
+            student.major = name.clone();
+        }
+        student
+    }
+
+    async fn run(self, progress: Progress) -> Result<UpdateStudentsMatchingResponse, Status> {
+        let mut response = UpdateStudentsMatchingResponse::default();
+        let mut page_token = String::new();
+        loop {
+            let page = self.store.list(SCAN_PAGE_SIZE, &page_token).await?;
+            progress.set_total(page.total_count.max(0) as usize);
+            for student in &page.students {
+                if !matches(&self.filter, student, &self.rules) {
+                    continue;
+                }
+                response.matched_count += 1;
+                let mut changed = self.apply(student.clone());
+                if changed == *student {
+                    continue;
+                }
+                if let Some(violation) = validation::violations(&changed).into_iter().next() {
+                    response.failures.push(BulkUpdateFailure {
+                        student_id: student.id.clone(),
+                        reason: violation.description,
+                    });
+                    continue;
+                }
+                if self.dry_run {
+                    response.updated_count += 1;
+                    continue;
+                }
+
+                changed.update_time = Some(clock::timestamp(self.clock.now()));
+                match self.store.update(changed).await {
+                    Ok(mut updated) => {
+                        response.updated_count += 1;
+                        if let Some(events) = &self.events {
+                            updated.standing = self.rules.standing(&updated) as i32;
+                            events.publish(ChangeType::Updated, &updated);
+                        }
+                    }
+                    Err(status) if matches!(status.code(), Code::Aborted | Code::NotFound) => {
+                        response.failures.push(BulkUpdateFailure {
+                            student_id: student.id.clone(),
+                            reason: status.message().to_string(),
+                        });
+                    }
+                    Err(status) => return Err(status),
+                }
+            }
+            progress.advance(page.students.len());
+
+            if page.next_page_token.is_empty() {
+                info!(
+                    "📝 Bulk update: {} of {} matching students updated{}",
+                    response.updated_count,
+                    response.matched_count,
+                    if self.dry_run { " (dry run)" } else { "" }
+                );
+                return Ok(response);
+            }
+            page_token = page.next_page_token;
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BulkServiceImpl {
+    store: Arc<dyn StudentRepository>,
+    catalog: Arc<Catalog>,
+    rules: Arc<StandingRules>,
+    operations: Operations,
+    ids: Arc<dyn IdGenerator>,
+    clock: Arc<dyn Clock>,
+    // Publishes a Created or Updated event per student, when the store does
+    // not record its own events
+    events: Option<Arc<EventLog>>,
+    validation: Profile,
+    phone_region: Option<Id>,
+}
+
+impl BulkServiceImpl {
+    /// Update students in `store`, checking major IDs against `catalog` and
+    /// standings with `rules`, as operations in `operations`.
+    pub fn new(
+        store: Arc<dyn StudentRepository>,
+        catalog: Arc<Catalog>,
+        rules: StandingRules,
+        operations: Operations,
+    ) -> Self {
+        Self {
+            store,
+            catalog,
+            rules: Arc::new(rules),
+            operations,
+            ids: Arc::new(UuidGenerator),
+            clock: Arc::new(SystemClock),
+            events: None,
+            validation: Profile::default(),
+            phone_region: None,
+        }
+    }
+
+    /// Use `ids` for created students that come without one.
+    pub fn with_id_generator(mut self, ids: Arc<dyn IdGenerator>) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    /// Use `clock` for create and update times.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Tell watchers in `events` about each student created or updated.
+    pub fn with_events(mut self, events: Arc<EventLog>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Tidy up or reject non-canonical input as `profile` says (lenient by
+    /// default).
+    pub fn with_validation(mut self, profile: Profile) -> Self {
+        self.validation = profile;
+        self
+    }
+
+    /// Read phone numbers of created students that come without a country
+    /// code as numbers of `region`; by default they are refused.
+    pub fn with_phone_region(mut self, region: Id) -> Self {
+        self.phone_region = Some(region);
+        self
+    }
+}
+
+#[tonic::async_trait]
+impl BulkService for BulkServiceImpl {
+    async fn update_students_matching(
+        &self,
+        request: Request<UpdateStudentsMatchingRequest>,
+    ) -> Result<Response<Operation>, Status> {
+        let request = request.into_inner();
+        let filter = request.filter.unwrap_or_default();
+        check_filter(&filter)?;
+        let paths = request
+            .update_mask
+            .map(|mask| mask.paths)
+            .unwrap_or_default();
+        let fields = parse_mask(&paths)?;
+        let mut new_values = request.new_values.unwrap_or_default();
+        // Only the form of the new values; each student is checked against
+        // the rules as it changes
+        let form = self.validation.tidy(&mut new_values);
+        if let Some((_, problem)) = form
+            .into_iter()
+            .find(|(name, _)| fields.iter().any(|field| field.name() == *name))
+        {
+            return Err(locale::status(Code::InvalidArgument, problem.into()));
+        }
+
+        let mut major_name = None;
+        if fields.contains(&Field::MajorId) && !new_values.major_id.is_empty() {
+            let major = self.catalog.major(&new_values.major_id).ok_or_else(|| {
+                locale::status(
+                    Code::InvalidArgument,
+                    Text::UnknownMajor(new_values.major_id.clone()),
+                )
+            })?;
+            major_name = Some(major.name);
+        }
+
+        let job = Job {
+            filter,
+            fields,
+            new_values,
+            major_name,
+            dry_run: request.dry_run,
+            store: self.store.clone(),
+            rules: self.rules.clone(),
+            clock: self.clock.clone(),
+            events: self.events.clone(),
+        };
+        let operation = self
+            .operations
+            .start(KIND, move |progress| job.run(progress));
+        Ok(Response::new(operation))
+    }
+
+    async fn get_bulk_update_result(
+        &self,
+        request: Request<GetBulkUpdateResultRequest>,
+    ) -> Result<Response<UpdateStudentsMatchingResponse>, Status> {
+        let id = request.into_inner().operation_id;
+        if self.operations.get(&id)?.kind != KIND {
+            return Err(Error::WrongOperationKind(id, "bulk update").into());
+        }
+
+        let operation = self.operations.wait(&id).await?;
+        if let Some(error) = operation.error {
+            return Err(Status::new(error.code.into(), error.message));
+        }
+        let response = self
+            .operations
+            .result::<UpdateStudentsMatchingResponse>(&id)
+            .ok_or_else(|| Status::internal("The operation has no result"))?;
+        Ok(Response::new(response.as_ref().clone()))
+    }
+
+    async fn bulk_create_students(
+        &self,
+        request: Request<Streaming<BulkCreateStudentsRequest>>,
+    ) -> Result<Response<BulkCreateStudentsResponse>, Status> {
+        let pipeline = Pipeline {
+            store: self.store.clone(),
+            catalog: self.catalog.clone(),
+            rules: self.rules.clone(),
+            ids: self.ids.clone(),
+            clock: self.clock.clone(),
+            events: self.events.clone(),
+            validation: self.validation,
+            phone_region: self.phone_region,
+        };
+        Ok(Response::new(pipeline.run(request.into_inner()).await?))
+    }
+}
@@ -0,0 +1,396 @@
+//! The department and major catalog.
+//!
+//! [`Catalog`] holds departments and majors in memory and is shared with the
+//! student service, which checks `Student.major_id` against it.
+//! [`CatalogServiceImpl`] serves the catalog's CRUD, on [`crate::crud`],
+//! and `MigrateMajors`, which maps legacy free-text majors to catalog
+//! entries.
+
+use crate::crud::{Collection, Entity};
+use crate::errors::Error;
+use crate::events::EventLog;
+use crate::repository::StudentRepository;
+use proto::catalog_service_server::CatalogService;
+use proto::{
+    ChangeType, CreateDepartmentRequest, CreateDepartmentResponse, CreateMajorRequest,
+    CreateMajorResponse, DeleteDepartmentRequest, DeleteDepartmentResponse, DeleteMajorRequest,
+    DeleteMajorResponse, Department, GetDepartmentRequest, GetDepartmentResponse, GetMajorRequest,
+    GetMajorResponse, ListDepartmentsRequest, ListDepartmentsResponse, ListMajorsRequest,
+    ListMajorsResponse, Major, MajorMapping, MigrateMajorsRequest, MigrateMajorsResponse, Student,
+    UpdateDepartmentRequest, UpdateDepartmentResponse, UpdateMajorRequest, UpdateMajorResponse,
+};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use tonic::{Code, Request, Response, Status};
+
+impl Entity for Department {
+    const KIND: &'static str = "Department";
+    const NOT_FOUND: Error = Error::DepartmentNotFound;
+    const EXISTS: Error = Error::DepartmentExists;
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn id_mut(&mut self) -> &mut String {
+        &mut self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Entity for Major {
+    const KIND: &'static str = "Major";
+    const NOT_FOUND: Error = Error::MajorNotFound;
+    const EXISTS: Error = Error::MajorExists;
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn id_mut(&mut self) -> &mut String {
+        &mut self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    departments: Collection<Department>,
+    majors: Collection<Major>,
+}
+
+/// Departments and majors, kept in memory.
+#[derive(Debug, Default)]
+pub struct Catalog {
+    state: RwLock<State>,
+}
+
+// Names and aliases match ignoring case and surrounding whitespace
+fn normalize(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+fn spellings(major: &Major) -> impl Iterator<Item = String> + '_ {
+    std::iter::once(&major.name)
+        .chain(&major.aliases)
+        .map(|name| normalize(name))
+}
+
+impl Catalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, State> {
+        self.state.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, State> {
+        self.state.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The department with this ID.
+    pub fn department(&self, id: &str) -> Option<Department> {
+        self.read().departments.get(id).cloned()
+    }
+
+    /// The major with this ID.
+    pub fn major(&self, id: &str) -> Option<Major> {
+        self.read().majors.get(id).cloned()
+    }
+
+    /// The major whose name or one of its aliases is `legacy`.
+    pub fn match_major(&self, legacy: &str) -> Option<Major> {
+        let legacy = normalize(legacy);
+        self.read()
+            .majors
+            .values()
+            .find(|major| spellings(major).any(|spelling| spelling == legacy))
+            .cloned()
+    }
+}
+
+/// Serves the catalog; students are scanned in `students` to keep majors in
+/// use from being deleted and for `MigrateMajors`.
+#[derive(Debug)]
+pub struct CatalogServiceImpl {
+    catalog: Arc<Catalog>,
+    students: Arc<dyn StudentRepository>,
+    // Publishes an Updated event for each migrated student, when the store
+    // does not record its own events
+    events: Option<Arc<EventLog>>,
+}
+
+impl CatalogServiceImpl {
+    pub fn new(catalog: Arc<Catalog>, students: Arc<dyn StudentRepository>) -> Self {
+        Self {
+            catalog,
+            students,
+            events: None,
+        }
+    }
+
+    /// Tell watchers in `events` about students `MigrateMajors` updates.
+    pub fn with_events(mut self, events: Arc<EventLog>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    async fn all_students(&self) -> Result<Vec<Arc<Student>>, Status> {
+        self.students.all().await
+    }
+}
+
+fn check_department(department: &Department) -> Result<(), Status> {
+    if department.name.trim().is_empty() {
+        return Err(Error::DepartmentNameEmpty.into());
+    }
+    Ok(())
+}
+
+fn check_major(state: &State, major: &Major) -> Result<(), Status> {
+    if major.name.trim().is_empty() {
+        return Err(Error::MajorNameEmpty.into());
+    }
+    if !state.departments.contains(&major.department_id) {
+        return Err(Error::UnknownDepartment(major.department_id.clone()).into());
+    }
+    // Every spelling must point at one major, or legacy strings become ambiguous
+    for other in state.majors.values().filter(|other| other.id != major.id) {
+        if let Some(taken) =
+            spellings(major).find(|spelling| spellings(other).any(|s| s == *spelling))
+        {
+            return Err(Error::MajorNameTaken(taken.to_string(), other.id.clone()).into());
+        }
+    }
+    Ok(())
+}
+
+#[tonic::async_trait]
+impl CatalogService for CatalogServiceImpl {
+    async fn create_department(
+        &self,
+        request: Request<CreateDepartmentRequest>,
+    ) -> Result<Response<CreateDepartmentResponse>, Status> {
+        let department = request.into_inner().department.unwrap_or_default();
+
+        check_department(&department)?;
+        let department = self.catalog.write().departments.create(department)?;
+
+        Ok(Response::new(CreateDepartmentResponse {
+            department: Some(department),
+        }))
+    }
+
+    async fn get_department(
+        &self,
+        request: Request<GetDepartmentRequest>,
+    ) -> Result<Response<GetDepartmentResponse>, Status> {
+        let id = request.into_inner().id;
+
+        let department = self.catalog.read().departments.find(&id)?.clone();
+
+        Ok(Response::new(GetDepartmentResponse {
+            department: Some(department),
+        }))
+    }
+
+    async fn update_department(
+        &self,
+        request: Request<UpdateDepartmentRequest>,
+    ) -> Result<Response<UpdateDepartmentResponse>, Status> {
+        let department = request.into_inner().department.unwrap_or_default();
+
+        check_department(&department)?;
+        let department = self.catalog.write().departments.update(department)?;
+
+        Ok(Response::new(UpdateDepartmentResponse {
+            department: Some(department),
+        }))
+    }
+
+    async fn delete_department(
+        &self,
+        request: Request<DeleteDepartmentRequest>,
+    ) -> Result<Response<DeleteDepartmentResponse>, Status> {
+        let id = request.into_inner().id;
+
+        let mut state = self.catalog.write();
+        state.departments.find(&id)?;
+        if let Some(major) = state
+            .majors
+            .values()
+            .find(|major| major.department_id == id)
+        {
+            return Err(Error::DepartmentHasMajors(major.id.clone()).into());
+        }
+        state.departments.delete(&id)?;
+
+        Ok(Response::new(DeleteDepartmentResponse { success: true }))
+    }
+
+    async fn list_departments(
+        &self,
+        request: Request<ListDepartmentsRequest>,
+    ) -> Result<Response<ListDepartmentsResponse>, Status> {
+        let request = request.into_inner();
+
+        let (departments, next_page_token) = self.catalog.read().departments.page(
+            |_| true,
+            request.page_size,
+            &request.page_token,
+        )?;
+
+        Ok(Response::new(ListDepartmentsResponse {
+            departments,
+            next_page_token,
+        }))
+    }
+
+    async fn create_major(
+        &self,
+        request: Request<CreateMajorRequest>,
+    ) -> Result<Response<CreateMajorResponse>, Status> {
+        let mut major = request.into_inner().major.unwrap_or_default();
+
+        let mut state = self.catalog.write();
+        state.majors.assign_id(&mut major);
+        check_major(&state, &major)?;
+        let major = state.majors.create(major)?;
+
+        Ok(Response::new(CreateMajorResponse { major: Some(major) }))
+    }
+
+    async fn get_major(
+        &self,
+        request: Request<GetMajorRequest>,
+    ) -> Result<Response<GetMajorResponse>, Status> {
+        let id = request.into_inner().id;
+
+        let major = self.catalog.read().majors.find(&id)?.clone();
+
+        Ok(Response::new(GetMajorResponse { major: Some(major) }))
+    }
+
+    async fn update_major(
+        &self,
+        request: Request<UpdateMajorRequest>,
+    ) -> Result<Response<UpdateMajorResponse>, Status> {
+        let major = request.into_inner().major.unwrap_or_default();
+
+        let mut state = self.catalog.write();
+        state.majors.find(&major.id)?;
+        check_major(&state, &major)?;
+        let major = state.majors.update(major)?;
+
+        Ok(Response::new(UpdateMajorResponse { major: Some(major) }))
+    }
+
+    async fn delete_major(
+        &self,
+        request: Request<DeleteMajorRequest>,
+    ) -> Result<Response<DeleteMajorResponse>, Status> {
+        let id = request.into_inner().id;
+
+        self.catalog.read().majors.find(&id)?;
+        let declared = self
+            .all_students()
+            .await?
+            .iter()
+            .filter(|student| student.major_id == id)
+            .count();
+        if declared > 0 {
+            return Err(Error::MajorDeclared(declared).into());
+        }
+        self.catalog.write().majors.delete(&id)?;
+
+        Ok(Response::new(DeleteMajorResponse { success: true }))
+    }
+
+    async fn list_majors(
+        &self,
+        request: Request<ListMajorsRequest>,
+    ) -> Result<Response<ListMajorsResponse>, Status> {
+        let request = request.into_inner();
+        let department_id = request.department_id;
+
+        let (majors, next_page_token) = self.catalog.read().majors.page(
+            |major| department_id.is_empty() || major.department_id == department_id,
+            request.page_size,
+            &request.page_token,
+        )?;
+
+        Ok(Response::new(ListMajorsResponse {
+            majors,
+            next_page_token,
+        }))
+    }
+
+    async fn migrate_majors(
+        &self,
+        request: Request<MigrateMajorsRequest>,
+    ) -> Result<Response<MigrateMajorsResponse>, Status> {
+        let dry_run = request.into_inner().dry_run;
+
+        let mut mappings: BTreeMap<String, MajorMapping> = BTreeMap::new();
+        let mut updated_count = 0;
+        let legacy = self
+            .all_students()
+            .await?
+            .into_iter()
+            .filter(|student| student.major_id.is_empty() && !student.major.trim().is_empty());
+        for student in legacy {
+            let major = self.catalog.match_major(&student.major);
+            let mapping = mappings
+                .entry(student.major.clone())
+                .or_insert_with(|| MajorMapping {
+                    legacy_major: student.major.clone(),
+                    major_id: major
+                        .as_ref()
+                        .map(|major| major.id.clone())
+                        .unwrap_or_default(),
+                    student_count: 0,
+                });
+            mapping.student_count += 1;
+
+            let Some(major) = major else { continue };
+            if dry_run {
+                continue;
+            }
+            // Conditional on the etag, so a concurrent edit is not overwritten;
+            // the student is picked up again on the next run
+            let migrated = Student {
+                major_id: major.id,
+                major: major.name,
+                ..Student::clone(&student)
+            };
+            match self.students.update(migrated).await {
+                Ok(student) => {
+                    updated_count += 1;
+                    if let Some(events) = &self.events {
+                        events.publish(ChangeType::Updated, &student);
+                    }
+                }
+                Err(status) if matches!(status.code(), Code::Aborted | Code::NotFound) => {}
+                Err(status) => return Err(status),
+            }
+        }
+
+        info!(
+            "Migrated majors: {} students updated{}",
+            updated_count,
+            if dry_run { " (dry run)" } else { "" }
+        );
+
+        Ok(Response::new(MigrateMajorsResponse {
+            mappings: mappings.into_values().collect(),
+            updated_count,
+        }))
+    }
+}
@@ -0,0 +1,371 @@
+//! What each of the server's connections is doing, after gRPC's channelz.
+//!
+//! A client that seems stuck is often waiting on a connection the server
+//! no longer knows about, or on calls it has left open. [`Channelz`] keeps
+//! every open connection: who is on the other end, since when, the bytes
+//! each way, and the calls open on it by method. [`ChannelzLayer`] counts
+//! the calls, and [`Channelz::track`] the connections and bytes; the list
+//! is served over HTTP as `/debug/connections` by
+//! [`serve_http`](crate::health::serve_http).
+
+use hyper::body::SizeHint;
+use hyper::Body;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tonic::body::BoxBody;
+use tonic::codegen::http::{HeaderMap, Request, Response};
+use tonic::codegen::{Bytes, Service};
+use tonic::transport::server::{Connected, TcpConnectInfo};
+use tonic::Status;
+use tower_layer::Layer;
+
+/// The server's open connections.
+#[derive(Debug, Clone, Default)]
+pub struct Channelz {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    next_id: AtomicU64,
+    // By the client's address, which is the one the calls on them carry
+    sockets: Mutex<HashMap<SocketAddr, Arc<Socket>>>,
+}
+
+// One open connection
+#[derive(Debug)]
+struct Socket {
+    id: u64,
+    remote: Option<SocketAddr>,
+    local: Option<SocketAddr>,
+    opened: Instant,
+    bytes_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    calls_started: AtomicU64,
+    // Calls open now, by method
+    streams: Mutex<BTreeMap<String, u64>>,
+}
+
+/// One open connection, as [`Channelz::sockets`] sees it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SocketInfo {
+    pub id: u64,
+    pub remote: Option<SocketAddr>,
+    pub local: Option<SocketAddr>,
+    pub age: Duration,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    /// Every call made on it so far, open or not
+    pub calls_started: u64,
+    /// Calls open now, by path, e.g. `/student.StudentService/WatchStudents`
+    pub streams: BTreeMap<String, u64>,
+}
+
+impl Channelz {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `io` counted as an open connection until it is dropped.
+    pub fn track<IO: Connected<ConnectInfo = TcpConnectInfo>>(&self, io: IO) -> Tracked<IO> {
+        let info = io.connect_info();
+        let socket = Arc::new(Socket {
+            id: self.inner.next_id.fetch_add(1, Ordering::Relaxed) + 1,
+            remote: info.remote_addr(),
+            local: info.local_addr(),
+            opened: Instant::now(),
+            bytes_received: AtomicU64::new(0),
+            bytes_sent: AtomicU64::new(0),
+            calls_started: AtomicU64::new(0),
+            streams: Mutex::new(BTreeMap::new()),
+        });
+        if let Some(remote) = socket.remote {
+            self.lock().insert(remote, socket.clone());
+        }
+        Tracked {
+            io,
+            socket,
+            channelz: self.clone(),
+        }
+    }
+
+    /// Every open connection, oldest first.
+    pub fn sockets(&self) -> Vec<SocketInfo> {
+        let mut sockets: Vec<SocketInfo> = self
+            .lock()
+            .values()
+            .map(|socket| SocketInfo {
+                id: socket.id,
+                remote: socket.remote,
+                local: socket.local,
+                age: socket.opened.elapsed(),
+                bytes_received: socket.bytes_received.load(Ordering::Relaxed),
+                bytes_sent: socket.bytes_sent.load(Ordering::Relaxed),
+                calls_started: socket.calls_started.load(Ordering::Relaxed),
+                streams: socket.streams.lock().unwrap().clone(),
+            })
+            .collect();
+        sockets.sort_by_key(|socket| socket.id);
+        sockets
+    }
+
+    /// The open connections as text, one paragraph each, for
+    /// `/debug/connections`.
+    pub fn render(&self) -> String {
+        let sockets = self.sockets();
+        let mut text = format!("{} connections\n", sockets.len());
+        let addr = |addr: Option<SocketAddr>| match addr {
+            Some(addr) => addr.to_string(),
+            None => "?".to_string(),
+        };
+        for socket in &sockets {
+            let _ = writeln!(
+                text,
+                "\n#{} {} -> {}, open {:.1}s, {} bytes in, {} bytes out, {} calls",
+                socket.id,
+                addr(socket.remote),
+                addr(socket.local),
+                socket.age.as_secs_f64(),
+                socket.bytes_received,
+                socket.bytes_sent,
+                socket.calls_started,
+            );
+            for (method, open) in &socket.streams {
+                let _ = writeln!(text, "  {} open {}", open, method);
+            }
+        }
+        text
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<SocketAddr, Arc<Socket>>> {
+        self.inner.sockets.lock().unwrap()
+    }
+}
+
+/// A connection counted by [`Channelz`], with the bytes read and written.
+#[derive(Debug)]
+pub struct Tracked<IO> {
+    io: IO,
+    socket: Arc<Socket>,
+    channelz: Channelz,
+}
+
+impl<IO> Drop for Tracked<IO> {
+    fn drop(&mut self) {
+        if let Some(remote) = self.socket.remote {
+            let mut sockets = self.channelz.lock();
+            // Unless the address was taken again by a newer connection
+            if sockets
+                .get(&remote)
+                .is_some_and(|socket| Arc::ptr_eq(socket, &self.socket))
+            {
+                sockets.remove(&remote);
+            }
+        }
+    }
+}
+
+impl<IO: AsyncRead + Unpin> AsyncRead for Tracked<IO> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let read = Pin::new(&mut self.io).poll_read(cx, buf);
+        let received = (buf.filled().len() - before) as u64;
+        self.socket
+            .bytes_received
+            .fetch_add(received, Ordering::Relaxed);
+        read
+    }
+}
+
+impl<IO: AsyncWrite + Unpin> AsyncWrite for Tracked<IO> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let written = Pin::new(&mut self.io).poll_write(cx, buf);
+        self.sent(written)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let written = Pin::new(&mut self.io).poll_write_vectored(cx, bufs);
+        self.sent(written)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_shutdown(cx)
+    }
+}
+
+impl<IO> Tracked<IO> {
+    fn sent(&self, written: Poll<io::Result<usize>>) -> Poll<io::Result<usize>> {
+        if let Poll::Ready(Ok(sent)) = &written {
+            self.socket
+                .bytes_sent
+                .fetch_add(*sent as u64, Ordering::Relaxed);
+        }
+        written
+    }
+}
+
+impl<IO: Connected> Connected for Tracked<IO> {
+    type ConnectInfo = IO::ConnectInfo;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        self.io.connect_info()
+    }
+}
+
+/// Counts each call against the connection it came on, while it is open.
+#[derive(Debug, Clone)]
+pub struct ChannelzLayer {
+    channelz: Channelz,
+}
+
+impl ChannelzLayer {
+    pub fn new(channelz: Channelz) -> Self {
+        Self { channelz }
+    }
+}
+
+impl<S> Layer<S> for ChannelzLayer {
+    type Service = Counted<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Counted {
+            inner,
+            channelz: self.channelz.clone(),
+        }
+    }
+}
+
+/// The service produced by [`ChannelzLayer`].
+#[derive(Debug, Clone)]
+pub struct Counted<S> {
+    inner: S,
+    channelz: Channelz,
+}
+
+impl<S> Service<Request<Body>> for Counted<S>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let socket = request
+            .extensions()
+            .get::<TcpConnectInfo>()
+            .and_then(TcpConnectInfo::remote_addr)
+            .and_then(|remote| self.channelz.lock().get(&remote).cloned());
+        let open = socket.map(|socket| OpenCall::new(socket, request.uri().path()));
+        let call = self.inner.call(request);
+        Box::pin(async move {
+            let response = call.await?;
+            // Open until the last of the response is sent
+            Ok(response.map(|body| match open {
+                Some(open) => BoxBody::new(Open { body, _call: open }),
+                None => body,
+            }))
+        })
+    }
+}
+
+// A call counted as open on its connection until dropped
+struct OpenCall {
+    socket: Arc<Socket>,
+    method: String,
+}
+
+impl OpenCall {
+    fn new(socket: Arc<Socket>, method: &str) -> Self {
+        socket.calls_started.fetch_add(1, Ordering::Relaxed);
+        *socket
+            .streams
+            .lock()
+            .unwrap()
+            .entry(method.to_string())
+            .or_default() += 1;
+        Self {
+            socket,
+            method: method.to_string(),
+        }
+    }
+}
+
+impl Drop for OpenCall {
+    fn drop(&mut self) {
+        let mut streams = self.socket.streams.lock().unwrap();
+        if let Some(open) = streams.get_mut(&self.method) {
+            *open -= 1;
+            if *open == 0 {
+                streams.remove(&self.method);
+            }
+        }
+    }
+}
+
+// A response body that keeps its call open
+struct Open {
+    body: BoxBody,
+    _call: OpenCall,
+}
+
+impl tonic::codegen::Body for Open {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Pin::new(&mut self.body).poll_data(cx)
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.body).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
@@ -0,0 +1,88 @@
+//! Injectable source of time.
+//!
+//! Production uses [`SystemClock`]; tests and golden files swap in
+//! [`FixedClock`] so every timestamp is known in advance.
+
+use proto::Timestamp;
+use std::fmt::Debug;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The current time, as seen by the service.
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// The system's wall clock.
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that only moves when told to.
+#[derive(Debug)]
+pub struct FixedClock {
+    now: Mutex<SystemTime>,
+}
+
+impl FixedClock {
+    pub fn new(now: SystemTime) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    /// Move the clock forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner()) += by;
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Convert a point in time to its protobuf representation.
+pub fn timestamp(time: SystemTime) -> Timestamp {
+    // Times before 1970 count backwards from the epoch
+    let (seconds, nanos) = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => (since.as_secs() as i64, since.subsec_nanos() as i32),
+        Err(e) => {
+            let before = e.duration();
+            match before.subsec_nanos() {
+                0 => (-(before.as_secs() as i64), 0),
+                n => (-(before.as_secs() as i64) - 1, 1_000_000_000 - n as i32),
+            }
+        }
+    };
+    Timestamp { seconds, nanos }
+}
+
+/// Parse an RFC 3339 time, e.g. `2025-01-03T12:00:00Z`.
+pub fn parse_timestamp(text: &str) -> Result<Timestamp, String> {
+    serde_json::from_value(serde_json::Value::String(text.to_string())).map_err(|_| {
+        format!(
+            "expected an RFC 3339 time, e.g. 2025-01-03T12:00:00Z, not `{}`",
+            text
+        )
+    })
+}
+
+/// `time` in RFC 3339 form.
+pub fn rfc3339(time: &Timestamp) -> String {
+    match serde_json::to_value(time) {
+        Ok(serde_json::Value::String(text)) => text,
+        _ => format!("{}s", time.seconds),
+    }
+}
+
+/// Whether `time` is later than `than`.
+pub fn is_after(time: &Timestamp, than: &Timestamp) -> bool {
+    (time.seconds, time.nanos) > (than.seconds, than.nanos)
+}
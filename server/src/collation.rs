@@ -0,0 +1,68 @@
+//! Ordering and matching names the way readers of a language expect.
+//!
+//! Sorting by code point puts "Zoe" before "Åsa" and "álvarez" after
+//! "zapata". [`compare`] instead uses ICU4X's collator with the CLDR data
+//! for the [`Locale`]: letters first, ignoring case and accents, so "Å"
+//! sorts with "A"; then accents, unaccented first; then case, lower first.
+//! In Spanish "ñ" is a letter of its own between "n" and "o", and Chinese
+//! is ordered by pinyin.
+//!
+//! [`fold`] is for matching rather than ordering, and is the server's own.
+
+use crate::locale::Locale;
+use icu_collator::{Collator, CollatorOptions};
+use std::cmp::Ordering;
+use std::sync::OnceLock;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+// Letters folded to more than one, or to another
+fn expand(letter: char) -> Option<&'static str> {
+    Some(match letter {
+        'ß' => "ss",
+        'æ' => "ae",
+        'œ' => "oe",
+        'ø' => "o",
+        'đ' => "d",
+        'ł' => "l",
+        'ı' => "i",
+        'þ' => "th",
+        _ => return None,
+    })
+}
+
+// The collator for `locale`, loaded on first use
+fn collator(locale: Locale) -> &'static Collator {
+    static EN: OnceLock<Collator> = OnceLock::new();
+    static ZH: OnceLock<Collator> = OnceLock::new();
+    static ES: OnceLock<Collator> = OnceLock::new();
+    let collator = match locale {
+        Locale::En => &EN,
+        Locale::Zh => &ZH,
+        Locale::Es => &ES,
+    };
+    collator.get_or_init(|| {
+        let tag: icu_locid::Locale = locale.tag().parse().expect("locale tags are BCP 47");
+        Collator::try_new(&(&tag).into(), CollatorOptions::new())
+            .expect("the compiled data has a collation for every locale")
+    })
+}
+
+/// How `a` and `b` compare in `locale`; equal only when they are the same
+/// to a reader, down to accents and case.
+pub fn compare(locale: Locale, a: &str, b: &str) -> Ordering {
+    collator(locale).compare(a, b)
+}
+
+/// `text` lower-cased and without accents, e.g. to match names as people
+/// type them: "Åsa Ødegård" folds to "asa odegard".
+pub fn fold(text: &str) -> String {
+    text.nfd()
+        .filter(|&c| !is_combining_mark(c))
+        .flat_map(char::to_lowercase)
+        .flat_map(|c| match expand(c) {
+            Some(letters) => letters.chars().collect::<Vec<_>>(),
+            None => vec![c],
+        })
+        .collect()
+}
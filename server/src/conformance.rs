@@ -0,0 +1,486 @@
+//! Conformance suite for [`StudentRepository`] implementations.
+//!
+//! Every backend must behave the same way: CRUD semantics and status codes,
+//! ID uniqueness, etag checks on update, stable pagination, transactions
+//! that keep all their writes or none, idempotency keys committed once, and safety under concurrent writers.
+//! Run the whole suite against a backend from an integration test with
+//! [`repository_conformance!`](crate::repository_conformance):
+//!
+//! ```ignore
+//! server::repository_conformance!(InMemoryRepository::new());
+//! ```
+//!
+//! The expression is evaluated once per test, so each test starts from an
+//! empty repository. It is pasted into an async test function, so it may
+//! `.await` (for example to connect to a database).
+
+use crate::gpa::{self, Gpa};
+use crate::repository::{IdempotencyKey, StudentRepository, Transaction};
+use proto::{Address, Student, Timestamp};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tonic::Code;
+
+fn student(id: &str) -> Student {
+    Student {
+        id: id.to_string(),
+        name: format!("Student {}", id),
+        preferred_name: "Stu".to_string(),
+        phone_numbers: vec!["+16502530000".to_string(), "+442070313000".to_string()],
+        address: Some(Address {
+            line1: "1 Main St".to_string(),
+            city: "Springfield".to_string(),
+            region: "IL".to_string(),
+            postal_code: "62701".to_string(),
+            country: "US".to_string(),
+            ..Default::default()
+        }),
+        email: format!("{}@university.edu", id),
+        age: 20,
+        major: "Physics".to_string(),
+        gpa: 3.5,
+        gpa_decimal: "3.50".to_string(),
+        create_time: Some(Timestamp {
+            seconds: 1_700_000_000,
+            nanos: 0,
+        }),
+        update_time: Some(Timestamp {
+            seconds: 1_700_000_000,
+            nanos: 0,
+        }),
+        etag: String::new(),
+        major_id: "physics".to_string(),
+        credits: 30,
+        credits_attempted: 24,
+        credits_earned: 21,
+        annotations: HashMap::from([
+            (
+                "registrar.example.edu/batch".to_string(),
+                "2024-fall".to_string(),
+            ),
+            ("note".to_string(), String::new()),
+        ]),
+        // Derived by the service, so repositories need not keep it
+        standing: 0,
+    }
+}
+
+pub async fn create_then_get(repository: impl StudentRepository) {
+    let created = repository.create(student("s1")).await.unwrap();
+    assert!(!created.etag.is_empty(), "no etag on a new student");
+    assert_eq!(
+        created,
+        Student {
+            etag: created.etag.clone(),
+            ..student("s1")
+        }
+    );
+    assert_eq!(repository.get("s1").await.unwrap(), created);
+}
+
+pub async fn get_missing_is_not_found(repository: impl StudentRepository) {
+    let status = repository.get("missing").await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+pub async fn duplicate_id_is_already_exists(repository: impl StudentRepository) {
+    repository.create(student("s1")).await.unwrap();
+
+    let mut duplicate = student("s1");
+    duplicate.name = "Someone Else".to_string();
+    let status = repository.create(duplicate).await.unwrap_err();
+    assert_eq!(status.code(), Code::AlreadyExists);
+
+    // The original is untouched
+    assert_eq!(repository.get("s1").await.unwrap().name, student("s1").name);
+}
+
+pub async fn update_replaces_fields_but_not_create_time(repository: impl StudentRepository) {
+    let created = repository.create(student("s1")).await.unwrap();
+
+    let mut changed = student("s1");
+    changed.major = "Mathematics".to_string();
+    gpa::set(&mut changed, Gpa::from_hundredths(390));
+    changed.credits_earned = 24;
+    changed.annotations.remove("note");
+    changed.create_time = Some(Timestamp {
+        seconds: 1,
+        nanos: 0,
+    });
+    changed.update_time = Some(Timestamp {
+        seconds: 1_800_000_000,
+        nanos: 0,
+    });
+    let updated = repository.update(changed.clone()).await.unwrap();
+    assert_ne!(updated.etag, created.etag, "etag not changed by an update");
+
+    let expected = Student {
+        create_time: student("s1").create_time,
+        etag: updated.etag.clone(),
+        ..changed
+    };
+    assert_eq!(updated, expected);
+    assert_eq!(repository.get("s1").await.unwrap(), expected);
+}
+
+pub async fn update_with_stale_etag_is_aborted(repository: impl StudentRepository) {
+    let created = repository.create(student("s1")).await.unwrap();
+
+    // Two writers read the same version; the second to write loses
+    let mut first = created.clone();
+    first.major = "Mathematics".to_string();
+    let first = repository.update(first).await.unwrap();
+
+    let mut second = created.clone();
+    second.major = "Chemistry".to_string();
+    let status = repository.update(second).await.unwrap_err();
+    assert_eq!(status.code(), Code::Aborted);
+    assert_eq!(repository.get("s1").await.unwrap(), first);
+
+    // With the current etag, or none at all, the update goes through
+    let mut retried = first.clone();
+    retried.major = "Chemistry".to_string();
+    let retried = repository.update(retried).await.unwrap();
+
+    let mut unconditional = retried.clone();
+    unconditional.etag.clear();
+    gpa::set(&mut unconditional, Gpa::from_hundredths(200));
+    repository.update(unconditional).await.unwrap();
+}
+
+pub async fn update_missing_is_not_found(repository: impl StudentRepository) {
+    let status = repository.update(student("missing")).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+pub async fn delete_returns_and_removes(repository: impl StudentRepository) {
+    let created = repository.create(student("s1")).await.unwrap();
+
+    assert_eq!(repository.delete("s1").await.unwrap(), created);
+    assert_eq!(
+        repository.get("s1").await.unwrap_err().code(),
+        Code::NotFound
+    );
+    assert_eq!(
+        repository.delete("s1").await.unwrap_err().code(),
+        Code::NotFound
+    );
+
+    // A deleted ID can be reused, under an etag the old student never had
+    let recreated = repository.create(student("s1")).await.unwrap();
+    assert_ne!(recreated.etag, created.etag, "etag reused after a delete");
+    let status = repository.update(created).await.unwrap_err();
+    assert_eq!(status.code(), Code::Aborted);
+}
+
+pub async fn pages_cover_every_student_once(repository: impl StudentRepository) {
+    let ids: HashSet<String> = (0..23).map(|n| format!("s{:02}", n)).collect();
+    for id in &ids {
+        repository.create(student(id)).await.unwrap();
+    }
+
+    let mut seen = Vec::new();
+    let mut token = String::new();
+    loop {
+        let page = repository.list(5, &token).await.unwrap();
+        assert_eq!(page.total_count, 23);
+        assert!(page.students.len() <= 5);
+        seen.extend(page.students.into_iter().map(|student| student.id));
+        if page.next_page_token.is_empty() {
+            break;
+        }
+        token = page.next_page_token;
+    }
+
+    assert_eq!(seen.len(), ids.len(), "a student was returned twice");
+    assert_eq!(seen.into_iter().collect::<HashSet<_>>(), ids);
+}
+
+pub async fn page_order_is_stable(repository: impl StudentRepository) {
+    for n in 0..10 {
+        repository
+            .create(student(&format!("s{}", n)))
+            .await
+            .unwrap();
+    }
+
+    let first = repository.list(4, "").await.unwrap();
+    let again = repository.list(4, "").await.unwrap();
+    assert_eq!(first, again);
+
+    let next = repository.list(4, &first.next_page_token).await.unwrap();
+    let next_again = repository.list(4, &first.next_page_token).await.unwrap();
+    assert_eq!(next, next_again);
+}
+
+pub async fn empty_repository_has_one_empty_page(repository: impl StudentRepository) {
+    let page = repository.list(10, "").await.unwrap();
+    assert!(page.students.is_empty());
+    assert!(page.next_page_token.is_empty());
+    assert_eq!(page.total_count, 0);
+}
+
+pub async fn bad_page_token_is_invalid_argument(repository: impl StudentRepository) {
+    repository.create(student("s1")).await.unwrap();
+
+    for token in ["not-a-token", "-1", "99999999999999999999999"] {
+        let status = repository.list(10, token).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument, "token {:?}", token);
+    }
+}
+
+pub async fn create_many_reports_each_student(repository: impl StudentRepository) {
+    repository.create(student("s1")).await.unwrap();
+
+    let results = repository
+        .create_many(vec![
+            student("s0"),
+            student("s1"),
+            student("s2"),
+            student("s2"),
+        ])
+        .await;
+    assert_eq!(results.len(), 4);
+    assert_eq!(results[0].as_ref().unwrap().id, "s0");
+    assert!(!results[0].as_ref().unwrap().etag.is_empty());
+    assert_eq!(results[1].as_ref().unwrap_err().code(), Code::AlreadyExists);
+    assert!(results[2].is_ok());
+    assert_eq!(results[3].as_ref().unwrap_err().code(), Code::AlreadyExists);
+
+    assert_eq!(repository.list(10, "").await.unwrap().total_count, 3);
+    assert!(repository.create_many(Vec::new()).await.is_empty());
+}
+
+pub async fn shared_reads_match_owned_reads(repository: impl StudentRepository) {
+    for n in 0..150 {
+        repository
+            .create(student(&format!("s{:03}", n)))
+            .await
+            .unwrap();
+    }
+    let mut updated = repository.get("s001").await.unwrap();
+    gpa::set(&mut updated, Gpa::from_hundredths(200));
+    let updated = repository.update(updated).await.unwrap();
+
+    assert_eq!(*repository.get_shared("s001").await.unwrap(), updated);
+    let status = repository.get_shared("missing").await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    let mut paged = Vec::new();
+    let mut token = String::new();
+    loop {
+        let page = repository.list(40, &token).await.unwrap();
+        paged.extend(page.students);
+        if page.next_page_token.is_empty() {
+            break;
+        }
+        token = page.next_page_token;
+    }
+    let all: Vec<Student> = repository
+        .all()
+        .await
+        .unwrap()
+        .iter()
+        .map(|student| Student::clone(student))
+        .collect();
+    assert_eq!(all, paged);
+}
+
+pub async fn transaction_keeps_every_write(repository: impl StudentRepository) {
+    let s1 = repository.create(student("s1")).await.unwrap();
+    let s2 = repository.create(student("s2")).await.unwrap();
+
+    let mut changed = s1.clone();
+    changed.major = "Mathematics".to_string();
+    let mut renamed = student("s3");
+    renamed.name = "Renamed".to_string();
+    let written = repository
+        .commit(
+            Transaction::new()
+                .with_create(student("s3"))
+                .with_update(changed)
+                .with_delete("s2")
+                // A later write sees the earlier ones
+                .with_update(renamed),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(written.len(), 4);
+    assert_eq!(written[2], s2);
+    assert_eq!(repository.get("s1").await.unwrap(), written[1]);
+    assert_eq!(written[1].major, "Mathematics");
+    assert_ne!(written[1].etag, s1.etag);
+    assert_eq!(repository.get("s3").await.unwrap(), written[3]);
+    assert_eq!(
+        repository.get("s2").await.unwrap_err().code(),
+        Code::NotFound
+    );
+    assert_eq!(repository.list(10, "").await.unwrap().total_count, 2);
+
+    assert!(repository
+        .commit(Transaction::new())
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+pub async fn failed_transaction_keeps_nothing(repository: impl StudentRepository) {
+    let s1 = repository.create(student("s1")).await.unwrap();
+    let s2 = repository.create(student("s2")).await.unwrap();
+
+    let mut changed = s1.clone();
+    changed.major = "Mathematics".to_string();
+    let failure = repository
+        .commit(
+            Transaction::new()
+                .with_create(student("s3"))
+                .with_update(changed.clone())
+                .with_delete("s2")
+                .with_delete("missing"),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(failure.index, Some(3));
+    assert_eq!(failure.status.code(), Code::NotFound);
+
+    let mut stale = changed;
+    stale.etag = "stale".to_string();
+    let failure = repository
+        .commit(
+            Transaction::new()
+                .with_delete("s2")
+                .with_update(stale)
+                .with_create(student("s3")),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(failure.index, Some(1));
+    assert_eq!(failure.status.code(), Code::Aborted);
+
+    assert_eq!(repository.get("s1").await.unwrap(), s1);
+    assert_eq!(repository.get("s2").await.unwrap(), s2);
+    assert_eq!(
+        repository.get("s3").await.unwrap_err().code(),
+        Code::NotFound
+    );
+    assert_eq!(repository.list(10, "").await.unwrap().total_count, 2);
+
+    // Nothing is left half done for the next call
+    repository.create(student("s3")).await.unwrap();
+}
+
+pub async fn idempotency_key_commits_once(repository: impl StudentRepository) {
+    let key = |key: &str| IdempotencyKey {
+        key: key.to_string(),
+        fingerprint: "create s1".to_string(),
+    };
+    assert!(repository.remembered("k1").await.unwrap().is_none());
+
+    // A failed transaction does not use its key up
+    repository.create(student("s1")).await.unwrap();
+    let failure = repository
+        .commit(
+            Transaction::new()
+                .with_create(student("s1"))
+                .with_idempotency_key(key("k1")),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(failure.status.code(), Code::AlreadyExists);
+    assert!(repository.remembered("k1").await.unwrap().is_none());
+
+    let written = repository
+        .commit(
+            Transaction::new()
+                .with_create(student("s2"))
+                .with_idempotency_key(key("k1")),
+        )
+        .await
+        .unwrap();
+    let remembered = repository.remembered("k1").await.unwrap().unwrap();
+    assert_eq!(remembered.fingerprint, "create s1");
+    assert_eq!(remembered.students, written);
+
+    let failure = repository
+        .commit(
+            Transaction::new()
+                .with_create(student("s3"))
+                .with_idempotency_key(key("k1")),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(failure.index, None);
+    assert_eq!(failure.status.code(), Code::AlreadyExists);
+    assert_eq!(
+        repository.get("s3").await.unwrap_err().code(),
+        Code::NotFound
+    );
+
+    // Other keys are unaffected
+    repository
+        .commit(
+            Transaction::new()
+                .with_create(student("s3"))
+                .with_idempotency_key(key("k2")),
+        )
+        .await
+        .unwrap();
+    assert_eq!(repository.list(10, "").await.unwrap().total_count, 3);
+}
+
+pub async fn concurrent_creates_keep_ids_unique(repository: impl StudentRepository + 'static) {
+    let repository = Arc::new(repository);
+
+    // Every ID is created by four tasks at once; exactly one of each may win
+    let tasks: Vec<_> = (0..64)
+        .map(|n| {
+            let repository = Arc::clone(&repository);
+            tokio::spawn(async move { repository.create(student(&format!("s{}", n % 16))).await })
+        })
+        .collect();
+
+    let mut created = 0;
+    for task in tasks {
+        match task.await.unwrap() {
+            Ok(_) => created += 1,
+            Err(status) => assert_eq!(status.code(), Code::AlreadyExists),
+        }
+    }
+
+    assert_eq!(created, 16);
+    assert_eq!(repository.list(100, "").await.unwrap().total_count, 16);
+}
+
+/// Generate one `#[tokio::test]` per conformance check for the repository
+/// built by `$repository`.
+#[macro_export]
+macro_rules! repository_conformance {
+    ($repository:expr) => {
+        $crate::repository_conformance!(@tests $repository;
+            create_then_get,
+            get_missing_is_not_found,
+            duplicate_id_is_already_exists,
+            update_replaces_fields_but_not_create_time,
+            update_with_stale_etag_is_aborted,
+            update_missing_is_not_found,
+            delete_returns_and_removes,
+            pages_cover_every_student_once,
+            page_order_is_stable,
+            empty_repository_has_one_empty_page,
+            bad_page_token_is_invalid_argument,
+            create_many_reports_each_student,
+            shared_reads_match_owned_reads,
+            transaction_keeps_every_write,
+            failed_transaction_keeps_nothing,
+            idempotency_key_commits_once,
+            concurrent_creates_keep_ids_unique,
+        );
+    };
+    (@tests $repository:expr; $($check:ident),* $(,)?) => {$(
+        #[tokio::test(flavor = "multi_thread")]
+        async fn $check() {
+            $crate::conformance::$check($repository).await;
+        }
+    )*};
+}
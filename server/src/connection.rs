@@ -0,0 +1,294 @@
+//! HTTP/2 and TCP settings for the connections the server accepts.
+//!
+//! Long-lived calls, such as `WatchStudents`, can go quiet for minutes.
+//! NAT gateways and load balancers drop connections idle for longer than
+//! their timeout, often without telling either side, so the watcher waits
+//! forever. HTTP/2 keepalive pings keep such connections busy, and close
+//! the ones that stop answering.
+//!
+//! Clients keep a channel's connection for as long as it lives, so behind
+//! an L4 load balancer the servers started last in a rolling deploy get no
+//! traffic. With a maximum connection age, each connection is sent GOAWAY
+//! once it is that old, give or take a tenth, and its client reconnects,
+//! landing wherever the balancer sends it. Calls already open finish first,
+//! for up to the grace period.
+
+use crate::channelz::Channelz;
+use futures::stream::{self, StreamExt};
+use hyper::Body;
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::BuildHasher;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinSet;
+use tonic::codegen::http::{Request, Response};
+use tonic::codegen::{Bytes, Service};
+use tonic::transport::server::{Connected, Router, Routes, TcpIncoming};
+use tonic::transport::Server;
+use tower_layer::Layer;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// How the server's connections are kept and used; by default, as tonic
+/// keeps them but with `TCP_NODELAY`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionSettings {
+    /// Ping each connection this often [default: never]
+    pub keepalive_interval: Option<Duration>,
+    /// Close a connection whose ping is not answered within this long
+    /// [default: 20s]
+    pub keepalive_timeout: Option<Duration>,
+    /// Most calls open at once on one connection [default: no limit]
+    pub max_concurrent_streams: Option<u32>,
+    /// TCP keepalive probes after this long idle [default: off]
+    pub tcp_keepalive: Option<Duration>,
+    /// Send small frames, such as single watch events, at once instead of
+    /// batching them (Nagle's algorithm off)
+    pub tcp_nodelay: bool,
+    /// Send GOAWAY on connections this old, so clients reconnect
+    /// [default: never]
+    pub max_connection_age: Option<Duration>,
+    /// Close a connection this long after its GOAWAY, even with calls still
+    /// open [default: wait for them]
+    pub max_connection_age_grace: Option<Duration>,
+}
+
+impl Default for ConnectionSettings {
+    fn default() -> Self {
+        Self {
+            keepalive_interval: None,
+            keepalive_timeout: None,
+            max_concurrent_streams: None,
+            tcp_keepalive: None,
+            tcp_nodelay: true,
+            max_connection_age: None,
+            max_connection_age_grace: None,
+        }
+    }
+}
+
+impl ConnectionSettings {
+    /// `server` with these settings.
+    pub fn apply<L>(&self, server: Server<L>) -> Server<L> {
+        server
+            .http2_keepalive_interval(self.keepalive_interval)
+            .http2_keepalive_timeout(self.keepalive_timeout)
+            .max_concurrent_streams(self.max_concurrent_streams)
+            .tcp_keepalive(self.tcp_keepalive)
+            .tcp_nodelay(self.tcp_nodelay)
+    }
+
+    /// Serve `routes` on `addr` with `server`, set up with these settings,
+    /// until `signal`, then wait for open calls.
+    ///
+    /// With a maximum connection age each connection is served on its own,
+    /// so it can be sent GOAWAY without the others.
+    pub async fn serve<L, ResBody>(
+        &self,
+        server: Server<L>,
+        routes: Routes,
+        addr: SocketAddr,
+        channelz: &Channelz,
+        signal: impl Future<Output = ()>,
+    ) -> Result<(), BoxError>
+    where
+        L: Layer<Routes> + Clone + Send + 'static,
+        L::Service: Service<Request<Body>, Response = Response<ResBody>> + Clone + Send + 'static,
+        <L::Service as Service<Request<Body>>>::Future: Send + 'static,
+        <L::Service as Service<Request<Body>>>::Error: Into<BoxError> + Send,
+        ResBody: tonic::codegen::Body<Data = Bytes> + Send + 'static,
+        ResBody::Error: Into<BoxError>,
+    {
+        let mut server = self.apply(server);
+        let mut incoming = TcpIncoming::new(addr, self.tcp_nodelay, self.tcp_keepalive)?;
+        let Some(max_age) = self.max_connection_age else {
+            let incoming = incoming.map(|io| io.map(|io| channelz.track(io)));
+            server
+                .add_routes(routes)
+                .serve_with_incoming_shutdown(incoming, signal)
+                .await?;
+            return Ok(());
+        };
+
+        let (stop, stopping) = watch::channel(());
+        let mut connections = JoinSet::new();
+        tokio::pin!(signal);
+        loop {
+            let io = tokio::select! {
+                _ = &mut signal => break,
+                // Forget connections as they close
+                Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+                io = incoming.next() => io,
+            };
+            match io {
+                Some(Ok(io)) => {
+                    let age = jittered(max_age, io.remote_addr());
+                    let router = server.add_routes(routes.clone());
+                    let goaway = Goaway {
+                        age,
+                        grace: self.max_connection_age_grace,
+                        stopping: stopping.clone(),
+                    };
+                    connections.spawn(goaway.serve(router, channelz.track(io)));
+                }
+                Some(Err(e)) => warn!("⚠️  Cannot accept a connection: {}", e),
+                None => break,
+            }
+        }
+        // Stop listening, then send every connection GOAWAY
+        drop(incoming);
+        let _ = stop.send(());
+        while connections.join_next().await.is_some() {}
+        Ok(())
+    }
+}
+
+// `age` give or take a tenth, so connections opened together, e.g. when a
+// server starts, are not all sent GOAWAY together
+fn jittered(age: Duration, remote: SocketAddr) -> Duration {
+    let spread = RandomState::new().hash_one(remote) as f64 / u64::MAX as f64;
+    age.mul_f64(0.9 + 0.2 * spread)
+}
+
+// When one connection is sent GOAWAY, and how long it then has
+struct Goaway {
+    age: Duration,
+    grace: Option<Duration>,
+    // Changes when the server shuts down
+    stopping: watch::Receiver<()>,
+}
+
+impl Goaway {
+    async fn serve<L, IO, ResBody>(mut self, router: Router<L>, io: IO)
+    where
+        IO: AsyncRead + AsyncWrite + Connected + Unpin + Send + 'static,
+        IO::ConnectInfo: Clone + Send + Sync + 'static,
+        L: Layer<Routes>,
+        L::Service: Service<Request<Body>, Response = Response<ResBody>> + Clone + Send + 'static,
+        <L::Service as Service<Request<Body>>>::Future: Send + 'static,
+        <L::Service as Service<Request<Body>>>::Error: Into<BoxError> + Send,
+        ResBody: tonic::codegen::Body<Data = Bytes> + Send + 'static,
+        ResBody::Error: Into<BoxError>,
+    {
+        let (cutting, cut) = oneshot::channel();
+        let (closing, closed) = oneshot::channel();
+        let io = Aging {
+            io,
+            cutting: Some(cut),
+            cut: false,
+            _closing: closing,
+        };
+        let (sent, goaway) = oneshot::channel();
+        let signal = async move {
+            tokio::select! {
+                _ = tokio::time::sleep(self.age) => {}
+                _ = self.stopping.changed() => {}
+            }
+            let _ = sent.send(());
+        };
+        // The one connection, then nothing: the server only drains its
+        // connections on `signal` while it may still accept more
+        let incoming = stream::iter([Ok::<_, io::Error>(io)]).chain(stream::pending());
+        let serving = router.serve_with_incoming_shutdown(incoming, signal);
+        tokio::pin!(serving);
+        tokio::select! {
+            _ = &mut serving => return,
+            // The client hung up first
+            _ = closed => return,
+            _ = goaway => {}
+        }
+
+        let Some(grace) = self.grace else {
+            let _ = serving.await;
+            return;
+        };
+        if tokio::time::timeout(grace, &mut serving).await.is_err() {
+            let _ = cutting.send(());
+            let _ = serving.await;
+        }
+    }
+}
+
+// A connection that can be cut from outside, and says when it is dropped
+struct Aging<IO> {
+    io: IO,
+    // Until it is cut, or can no longer be
+    cutting: Option<oneshot::Receiver<()>>,
+    cut: bool,
+    _closing: oneshot::Sender<()>,
+}
+
+impl<IO> Aging<IO> {
+    // Once the connection is cut every read and write fails, so the server
+    // closes it
+    fn poll_cut(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Some(cutting) = &mut self.cutting {
+            if let Poll::Ready(cut) = Pin::new(cutting).poll(cx) {
+                self.cut = cut.is_ok();
+                self.cutting = None;
+            }
+        }
+        match self.cut {
+            true => Poll::Ready(Err(io::ErrorKind::ConnectionAborted.into())),
+            false => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+impl<IO: AsyncRead + Unpin> AsyncRead for Aging<IO> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        ready!(self.poll_cut(cx))?;
+        Pin::new(&mut self.io).poll_read(cx, buf)
+    }
+}
+
+impl<IO: AsyncWrite + Unpin> AsyncWrite for Aging<IO> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        ready!(self.poll_cut(cx))?;
+        Pin::new(&mut self.io).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        ready!(self.poll_cut(cx))?;
+        Pin::new(&mut self.io).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_cut(cx))?;
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_shutdown(cx)
+    }
+}
+
+impl<IO: Connected> Connected for Aging<IO> {
+    type ConnectInfo = IO::ConnectInfo;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        self.io.connect_info()
+    }
+}
@@ -0,0 +1,161 @@
+//! CRUD for entities kept in memory by ID.
+//!
+//! Departments, majors, professors, and courses are created with an ID the
+//! client may choose, read, replaced, deleted, and listed a page at a time
+//! in the same way. Each implements [`Entity`] and lives in a
+//! [`Collection`], which fills in missing IDs, answers `NOT_FOUND` and
+//! `ALREADY_EXISTS`, logs the change, and pages in ID order. A service adds
+//! only its own checks, made under the same lock as the write, and the
+//! mapping to its request and response messages.
+
+use crate::errors::Error;
+use crate::ids::{IdGenerator, UuidGenerator};
+use crate::repository::{next_page_token, page_offset};
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+use tonic::Status;
+
+/// Something a [`Collection`] keeps.
+pub trait Entity: Clone + Debug + Send + Sync + 'static {
+    /// What one is called in messages, such as "Department".
+    const KIND: &'static str;
+
+    /// The errors for one that does not exist, and for one whose ID is
+    /// taken, such as [`Error::DepartmentNotFound`].
+    const NOT_FOUND: Error;
+    const EXISTS: Error;
+
+    fn id(&self) -> &str;
+
+    fn id_mut(&mut self) -> &mut String;
+
+    /// What it is called in log lines.
+    fn name(&self) -> &str;
+}
+
+/// `NOT_FOUND` for an `E` that does not exist.
+pub fn not_found<E: Entity>() -> Status {
+    E::NOT_FOUND.into()
+}
+
+/// The entities of one kind, by ID.
+#[derive(Debug)]
+pub struct Collection<E> {
+    entities: BTreeMap<String, E>,
+    ids: Arc<dyn IdGenerator>,
+}
+
+impl<E> Default for Collection<E> {
+    fn default() -> Self {
+        Self {
+            entities: BTreeMap::new(),
+            ids: Arc::new(UuidGenerator),
+        }
+    }
+}
+
+impl<E: Entity> Collection<E> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// IDs for entities created without one come from `ids`.
+    pub fn with_ids(mut self, ids: Arc<dyn IdGenerator>) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    pub fn get(&self, id: &str) -> Option<&E> {
+        self.entities.get(id)
+    }
+
+    /// The entity with `id`, or `NOT_FOUND`.
+    pub fn find(&self, id: &str) -> Result<&E, Status> {
+        self.get(id).ok_or_else(not_found::<E>)
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.entities.contains_key(id)
+    }
+
+    /// Every entity, in ID order.
+    pub fn values(&self) -> impl Iterator<Item = &E> {
+        self.entities.values()
+    }
+
+    /// Gives `entity` a new ID if it has none, so checks can see the one it
+    /// will be created with.
+    pub fn assign_id(&self, entity: &mut E) {
+        if entity.id().is_empty() {
+            *entity.id_mut() = self.ids.next_id();
+        }
+    }
+
+    /// Adds `entity`, with a new ID if it has none.
+    pub fn create(&mut self, mut entity: E) -> Result<E, Status> {
+        self.assign_id(&mut entity);
+        if self.contains(entity.id()) {
+            return Err(E::EXISTS.into());
+        }
+        self.entities
+            .insert(entity.id().to_string(), entity.clone());
+
+        info!(
+            "Created {}: {} ({})",
+            kind::<E>(),
+            entity.name(),
+            entity.id()
+        );
+        Ok(entity)
+    }
+
+    /// Replaces the stored entity with `entity`'s ID.
+    pub fn update(&mut self, entity: E) -> Result<E, Status> {
+        let stored = self
+            .entities
+            .get_mut(entity.id())
+            .ok_or_else(not_found::<E>)?;
+        *stored = entity.clone();
+
+        info!(
+            "Updated {}: {} ({})",
+            kind::<E>(),
+            entity.name(),
+            entity.id()
+        );
+        Ok(entity)
+    }
+
+    /// Removes the entity with `id`, returning it.
+    pub fn delete(&mut self, id: &str) -> Result<E, Status> {
+        let entity = self.entities.remove(id).ok_or_else(not_found::<E>)?;
+
+        info!("Deleted {}: {}", kind::<E>(), id);
+        Ok(entity)
+    }
+
+    /// The page of the entities `matching` that `page_token` points at, in
+    /// ID order, and the token for the next one. A `page_size` of 0 or less
+    /// is everything from there on.
+    pub fn page(
+        &self,
+        matching: impl Fn(&E) -> bool,
+        page_size: i32,
+        page_token: &str,
+    ) -> Result<(Vec<E>, String), Status> {
+        let entities: Vec<&E> = self.values().filter(|entity| matching(entity)).collect();
+        let start = page_offset(page_token, entities.len())?;
+        let end = match usize::try_from(page_size) {
+            Ok(page_size) if page_size > 0 => (start + page_size).min(entities.len()),
+            _ => entities.len(),
+        };
+        let page = entities[start..end].iter().copied().cloned().collect();
+        Ok((page, next_page_token(end, entities.len())))
+    }
+}
+
+// The kind as it reads mid-sentence
+fn kind<E: Entity>() -> String {
+    E::KIND.to_lowercase()
+}
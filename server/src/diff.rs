@@ -0,0 +1,179 @@
+//! How two stores' students differ, such as two snapshots, or a snapshot
+//! and the database it was migrated to: the students only one of them has,
+//! and each field that differs for a student in both.
+//!
+//! Students are compared as [`transfer::checksum`] sees them, so stores
+//! with the same checksum have no differences: the etag, the standing, and
+//! `gpa_decimal`, which each backend derives, are left out.
+
+use crate::transfer;
+use proto::Student;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// The students `after` compared with those `before`, each list by ID.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Diff {
+    /// Only after
+    pub added: Vec<Student>,
+    /// Only before
+    pub removed: Vec<Student>,
+    pub changed: Vec<Changed>,
+    /// Students the same before and after
+    pub unchanged: usize,
+}
+
+/// A student in both, and the fields that differ.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Changed {
+    pub id: String,
+    pub name: String,
+    pub fields: Vec<FieldChange>,
+}
+
+/// A field that differs, with its values as JSON; `null` where unset.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldChange {
+    /// The field's name, e.g. `gpa`; a part of the address or an annotation
+    /// after `address.` or `annotations.`
+    pub field: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compare the students `after` with those `before`, by ID.
+pub fn diff<'a>(
+    before: impl IntoIterator<Item = &'a Student>,
+    after: impl IntoIterator<Item = &'a Student>,
+) -> Diff {
+    let before: BTreeMap<&str, &Student> = before
+        .into_iter()
+        .map(|student| (student.id.as_str(), student))
+        .collect();
+    let after: BTreeMap<&str, &Student> = after
+        .into_iter()
+        .map(|student| (student.id.as_str(), student))
+        .collect();
+
+    let mut diff = Diff::default();
+    for (id, old) in &before {
+        let Some(new) = after.get(id) else {
+            diff.removed.push((*old).clone());
+            continue;
+        };
+        let fields = changes(old, new);
+        if fields.is_empty() {
+            diff.unchanged += 1;
+        } else {
+            diff.changed.push(Changed {
+                id: id.to_string(),
+                name: new.name.clone(),
+                fields,
+            });
+        }
+    }
+    diff.added = after
+        .iter()
+        .filter(|(id, _)| !before.contains_key(*id))
+        .map(|(_, student)| (*student).clone())
+        .collect();
+    diff
+}
+
+fn changes(before: &Student, after: &Student) -> Vec<FieldChange> {
+    let before = fields(before);
+    let after = fields(after);
+    let names: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let old = before.get(name).cloned().unwrap_or(Value::Null);
+            let new = after.get(name).cloned().unwrap_or(Value::Null);
+            (old != new).then(|| FieldChange {
+                field: name.clone(),
+                before: old,
+                after: new,
+            })
+        })
+        .collect()
+}
+
+// The fields `student` has set, as in its canonical JSON but by proto
+// name, with each part of the address and each annotation on its own
+fn fields(student: &Student) -> BTreeMap<String, Value> {
+    let mut fields = BTreeMap::new();
+    let Ok(Value::Object(json)) = serde_json::to_value(transfer::stored(student)) else {
+        return fields;
+    };
+    for (name, value) in json {
+        let name = snake_case(&name);
+        match value {
+            Value::Object(parts) => {
+                for (part, value) in parts {
+                    // Annotation keys are the client's own
+                    let part = match name.as_str() {
+                        "annotations" => part,
+                        _ => snake_case(&part),
+                    };
+                    fields.insert(format!("{}.{}", name, part), value);
+                }
+            }
+            // Unset, as proto3 JSON leaves it out
+            Value::Null => {}
+            Value::Array(items) if items.is_empty() => {}
+            value => {
+                fields.insert(name, value);
+            }
+        }
+    }
+    fields
+}
+
+// `postalCode` as `postal_code`
+fn snake_case(name: &str) -> String {
+    let mut snake = String::with_capacity(name.len() + 4);
+    for c in name.chars() {
+        if c.is_ascii_uppercase() {
+            snake.push('_');
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+/// One line per student added (`+`), removed (`-`), or changed (`~`), the
+/// last with a line per field, then a count of each.
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for student in &self.added {
+            writeln!(f, "+ {}  {}", student.id, student.name)?;
+        }
+        for student in &self.removed {
+            writeln!(f, "- {}  {}", student.id, student.name)?;
+        }
+        for changed in &self.changed {
+            writeln!(f, "~ {}  {}", changed.id, changed.name)?;
+            for field in &changed.fields {
+                writeln!(f, "    {}: {} → {}", field.field, field.before, field.after)?;
+            }
+        }
+        write!(
+            f,
+            "{} added, {} removed, {} changed, {} unchanged",
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len(),
+            self.unchanged
+        )
+    }
+}
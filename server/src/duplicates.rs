@@ -0,0 +1,233 @@
+//! Finding and merging students recorded twice.
+//!
+//! `FindDuplicates` compares every pair of students: the same email address
+//! (ignoring case and any `+tag`) is a certain match, and names are scored
+//! with Jaro-Winkler similarity after folding case and accents and sorting
+//! their words, so "Lovelace, Ada" matches "Ada Lovelace" and "José" matches
+//! "Jose". `MergeStudents` moves the
+//! duplicate's seats, grades, attendance, and advisor to the primary and
+//! puts the duplicate in the [`Trash`]; `Undo` there restores it and moves
+//! back what the merge moved.
+
+use crate::attendance::AttendanceServiceImpl;
+use crate::collation;
+use crate::enrollment::EnrollmentServiceImpl;
+use crate::errors::Error;
+use crate::events::EventLog;
+use crate::professor::ProfessorServiceImpl;
+use crate::repository::StudentRepository;
+use crate::trash::{AfterRestore, Trash};
+use proto::duplicate_service_server::DuplicateService;
+use proto::{
+    ChangeType, DuplicateMatch, FindDuplicatesRequest, FindDuplicatesResponse,
+    MergeStudentsRequest, MergeStudentsResponse, Student, TrashReason,
+};
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+
+const DEFAULT_MIN_SCORE: f64 = 0.9;
+
+// Name scores worth giving as a reason
+const SIMILAR_NAMES: f64 = 0.8;
+
+// Folded words in sorted order, without punctuation
+fn normalize_name(name: &str) -> String {
+    let cleaned: String = collation::fold(name)
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+    let mut words: Vec<&str> = cleaned.split_whitespace().collect();
+    words.sort_unstable();
+    words.join(" ")
+}
+
+// Lowercase, without a "+tag" in the local part
+fn normalize_email(email: &str) -> Option<String> {
+    let email = email.trim().to_lowercase();
+    let (local, domain) = email.split_once('@')?;
+    let local = local.split('+').next().unwrap_or(local);
+    Some(format!("{}@{}", local, domain))
+}
+
+/// How alike `student` and `other` are, from 0 to 1, with the reasons.
+pub fn similarity(student: &Student, other: &Student) -> (f64, Vec<String>) {
+    let mut reasons = Vec::new();
+    let mut score = 0.0;
+    let email = normalize_email(&student.email);
+    if email.is_some() && email == normalize_email(&other.email) {
+        reasons.push("Same email address".to_string());
+        score = 1.0;
+    }
+    let (name, other_name) = (normalize_name(&student.name), normalize_name(&other.name));
+    if !name.is_empty() && !other_name.is_empty() {
+        let name_score = strsim::jaro_winkler(&name, &other_name);
+        if name == other_name {
+            reasons.push("Same name".to_string());
+        } else if name_score >= SIMILAR_NAMES {
+            reasons.push(format!("Similar names ({:.2})", name_score));
+        }
+        score = f64::max(score, name_score);
+    }
+    (score, reasons)
+}
+
+#[derive(Debug)]
+pub struct DuplicateServiceImpl {
+    store: Arc<dyn StudentRepository>,
+    enrollment: Arc<EnrollmentServiceImpl>,
+    attendance: Arc<AttendanceServiceImpl>,
+    professors: Arc<ProfessorServiceImpl>,
+    trash: Trash,
+    // Publishes a Deleted event for each merged-away duplicate, when the
+    // store does not record its own events
+    events: Option<Arc<EventLog>>,
+}
+
+impl DuplicateServiceImpl {
+    /// Merge students in `store` along with what the other services hold
+    /// for them, keeping merged-away duplicates in `trash`.
+    pub fn new(
+        store: Arc<dyn StudentRepository>,
+        enrollment: Arc<EnrollmentServiceImpl>,
+        attendance: Arc<AttendanceServiceImpl>,
+        professors: Arc<ProfessorServiceImpl>,
+        trash: Trash,
+    ) -> Self {
+        Self {
+            store,
+            enrollment,
+            attendance,
+            professors,
+            trash,
+            events: None,
+        }
+    }
+
+    /// Tell watchers in `events` about merged-away duplicates; give the
+    /// trash the same log, so they hear of an undone merge too.
+    pub fn with_events(mut self, events: Arc<EventLog>) -> Self {
+        self.events = Some(events);
+        self
+    }
+}
+
+#[tonic::async_trait]
+impl DuplicateService for DuplicateServiceImpl {
+    async fn find_duplicates(
+        &self,
+        request: Request<FindDuplicatesRequest>,
+    ) -> Result<Response<FindDuplicatesResponse>, Status> {
+        let min_score = match request.into_inner().min_score {
+            0.0 => DEFAULT_MIN_SCORE,
+            score if (0.0..=1.0).contains(&score) => score,
+            _ => return Err(Error::MinScoreOutOfRange.into()),
+        };
+
+        let mut students = self.store.all().await?;
+        students.sort_by(|a, b| a.id.cmp(&b.id));
+        let mut matches = Vec::new();
+        for (index, student) in students.iter().enumerate() {
+            for other in &students[index + 1..] {
+                let (score, reasons) = similarity(student, other);
+                if score >= min_score {
+                    matches.push(DuplicateMatch {
+                        student: Some(Student::clone(student)),
+                        other: Some(Student::clone(other)),
+                        score,
+                        reasons,
+                    });
+                }
+            }
+        }
+        // Stable, so equal scores stay in ID order
+        matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+        info!("🔍 Found {} possible duplicates", matches.len());
+        Ok(Response::new(FindDuplicatesResponse { matches }))
+    }
+
+    async fn merge_students(
+        &self,
+        request: Request<MergeStudentsRequest>,
+    ) -> Result<Response<MergeStudentsResponse>, Status> {
+        let MergeStudentsRequest {
+            primary_id,
+            duplicate_id,
+        } = request.into_inner();
+        if primary_id.is_empty() || duplicate_id.is_empty() {
+            return Err(Error::IdsRequired("primary_id", "duplicate_id").into());
+        }
+        if primary_id == duplicate_id {
+            return Err(Error::MergeIntoItself.into());
+        }
+
+        let primary = self.store.get(&primary_id).await?;
+        let duplicate = self.store.delete(&duplicate_id).await?;
+        if let Some(events) = &self.events {
+            events.publish(ChangeType::Deleted, &duplicate);
+        }
+        let enrollments = self.enrollment.transfer(&duplicate_id, &primary_id, None);
+        let attendance = self.attendance.transfer(&duplicate_id, &primary_id, None);
+        let advisor = self
+            .professors
+            .transfer_advisee(&duplicate_id, &primary_id, None);
+
+        let response = MergeStudentsResponse {
+            primary: Some(primary),
+            moved_enrollments: enrollments.places.len() as i32,
+            moved_grades: enrollments.grades.len() as i32,
+            moved_attendance: attendance.len() as i32,
+            moved_advisor: advisor.is_some(),
+            ..Default::default()
+        };
+
+        // Undoing the merge moves back just what moved, if the primary
+        // still has it
+        let (enrollment, attendance_service, professors) = (
+            self.enrollment.clone(),
+            self.attendance.clone(),
+            self.professors.clone(),
+        );
+        let merged_into = primary_id.clone();
+        let move_back: AfterRestore = Box::new(move |duplicate: &Student| {
+            enrollment.transfer(&merged_into, &duplicate.id, Some(&enrollments));
+            // Both students' credits follow the grades; neither waits on it
+            let ids = [merged_into.clone(), duplicate.id.clone()];
+            tokio::spawn(async move {
+                for id in ids {
+                    if let Err(status) = enrollment.update_credits(&id).await {
+                        warn!("⚠️  Credits of {} not updated: {}", id, status.message());
+                    }
+                }
+            });
+            attendance_service.transfer(&merged_into, &duplicate.id, Some(&attendance));
+            if let Some(advisor) = &advisor {
+                professors.transfer_advisee(&merged_into, &duplicate.id, Some(advisor));
+            }
+        });
+        let entry = self
+            .trash
+            .put(duplicate, TrashReason::Merged, &primary_id, Some(move_back));
+
+        // The primary's credits now count the grades that moved
+        let primary = match self.enrollment.update_credits(&primary_id).await {
+            Ok(primary) => Some(primary),
+            Err(status) => {
+                warn!(
+                    "⚠️  Credits of {} not updated: {}",
+                    primary_id,
+                    status.message()
+                );
+                response.primary
+            }
+        };
+
+        info!("🔀 Merged student {} into {}", duplicate_id, primary_id);
+        Ok(Response::new(MergeStudentsResponse {
+            primary,
+            operation_id: entry.operation_id,
+            undo_deadline: entry.expire_time,
+            ..response
+        }))
+    }
+}
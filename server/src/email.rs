@@ -0,0 +1,204 @@
+//! Changing a student's email address.
+//!
+//! `RequestEmailChange` sends a code to the new address through a
+//! [`Notifier`] and keeps the change waiting; the student keeps the old
+//! address until `ConfirmEmailChange` gets the code back. Codes expire after
+//! a while and after a few wrong guesses. Waiting changes are kept in memory.
+
+use crate::clock::{self, Clock, SystemClock};
+use crate::errors::Error;
+use crate::events::EventLog;
+use crate::locale;
+use crate::notify::{Message, Notifier};
+use crate::repository::StudentRepository;
+use crate::validation::Profile;
+use proto::email_service_server::EmailService;
+use proto::{
+    ChangeType, ConfirmEmailChangeRequest, ConfirmEmailChangeResponse, RequestEmailChangeRequest,
+    RequestEmailChangeResponse,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+use tonic::{Code, Request, Response, Status};
+
+/// How long a code can be confirmed for, unless set with
+/// [`EmailServiceImpl::with_code_lifetime`].
+pub const DEFAULT_CODE_LIFETIME: Duration = Duration::from_secs(15 * 60);
+
+/// Wrong codes tried before a waiting change is dropped.
+pub const MAX_ATTEMPTS: u32 = 5;
+
+#[derive(Debug, Clone)]
+struct Pending {
+    email: String,
+    code: String,
+    expires: SystemTime,
+    attempts: u32,
+}
+
+// Six digits, easy to type in
+fn new_code() -> String {
+    format!("{:06}", uuid::Uuid::new_v4().as_u128() % 1_000_000)
+}
+
+#[derive(Debug)]
+pub struct EmailServiceImpl {
+    store: Arc<dyn StudentRepository>,
+    notifier: Arc<dyn Notifier>,
+    lifetime: Duration,
+    clock: Arc<dyn Clock>,
+    // Publishes an Updated event per confirmed change, when the store does
+    // not record its own events
+    events: Option<Arc<EventLog>>,
+    // By student ID
+    pending: Mutex<HashMap<String, Pending>>,
+    validation: Profile,
+}
+
+impl EmailServiceImpl {
+    /// Change addresses of students in `store`, sending codes with `notifier`.
+    pub fn new(store: Arc<dyn StudentRepository>, notifier: Arc<dyn Notifier>) -> Self {
+        Self {
+            store,
+            notifier,
+            lifetime: DEFAULT_CODE_LIFETIME,
+            clock: Arc::new(SystemClock),
+            events: None,
+            pending: Mutex::new(HashMap::new()),
+            validation: Profile::default(),
+        }
+    }
+
+    /// Tidy up or reject non-canonical addresses as `profile` says (lenient
+    /// by default).
+    pub fn with_validation(mut self, profile: Profile) -> Self {
+        self.validation = profile;
+        self
+    }
+
+    /// Let codes be confirmed for `lifetime` instead of 15 minutes.
+    pub fn with_code_lifetime(mut self, lifetime: Duration) -> Self {
+        self.lifetime = lifetime;
+        self
+    }
+
+    /// Use `clock` for expiry and update times.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Tell watchers in `events` about confirmed changes.
+    pub fn with_events(mut self, events: Arc<EventLog>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    fn pending(&self) -> MutexGuard<'_, HashMap<String, Pending>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // The change waiting for `student_id`, taken if `code` is right
+    fn take(&self, student_id: &str, code: &str) -> Result<Pending, Status> {
+        let mut pending = self.pending();
+        let change = pending
+            .get_mut(student_id)
+            .ok_or_else(|| Error::EmailChangeNotPending(student_id.to_string()))?;
+        if change.expires <= self.clock.now() {
+            pending.remove(student_id);
+            return Err(Error::EmailChangeExpired(student_id.to_string()).into());
+        }
+        if change.code != code.trim() {
+            change.attempts += 1;
+            if change.attempts >= MAX_ATTEMPTS {
+                pending.remove(student_id);
+            }
+            return Err(Error::VerificationCodeWrong.into());
+        }
+        Ok(pending.remove(student_id).unwrap())
+    }
+}
+
+#[tonic::async_trait]
+impl EmailService for EmailServiceImpl {
+    async fn request_email_change(
+        &self,
+        request: Request<RequestEmailChangeRequest>,
+    ) -> Result<Response<RequestEmailChangeResponse>, Status> {
+        let request = request.into_inner();
+        let email = self
+            .validation
+            .email(&request.new_email)
+            .map_err(|problem| locale::status(Code::InvalidArgument, problem.into()))?;
+        if !email.contains('@') {
+            return Err(Error::NewEmailInvalid(request.new_email).into());
+        }
+        let student = self.store.get_shared(&request.student_id).await?;
+        if student.email.eq_ignore_ascii_case(&email) {
+            return Err(Error::EmailUnchanged(student.id.clone(), email).into());
+        }
+
+        let code = new_code();
+        self.notifier
+            .send(Message {
+                to: email.clone(),
+                subject: "Confirm your new email address".to_string(),
+                body: format!(
+                    "Your verification code for {} is {}. It expires in {} minutes.",
+                    student.name,
+                    code,
+                    self.lifetime.as_secs() / 60
+                ),
+            })
+            .await?;
+
+        let expires = self.clock.now() + self.lifetime;
+        self.pending().insert(
+            student.id.clone(),
+            Pending {
+                email: email.clone(),
+                code,
+                expires,
+                attempts: 0,
+            },
+        );
+        info!("📧 Verification code sent to {} for {}", email, student.id);
+        Ok(Response::new(RequestEmailChangeResponse {
+            pending_email: email,
+            expire_time: Some(clock::timestamp(expires)),
+        }))
+    }
+
+    async fn confirm_email_change(
+        &self,
+        request: Request<ConfirmEmailChangeRequest>,
+    ) -> Result<Response<ConfirmEmailChangeResponse>, Status> {
+        let request = request.into_inner();
+        let change = self.take(&request.student_id, &request.code)?;
+
+        let result = async {
+            let mut student = self.store.get(&request.student_id).await?;
+            student.email = change.email.clone();
+            student.update_time = Some(clock::timestamp(self.clock.now()));
+            self.store.update(student).await
+        }
+        .await;
+        let student = match result {
+            Ok(student) => student,
+            Err(status) => {
+                // The same code can be tried again
+                self.pending().entry(request.student_id).or_insert(change);
+                return Err(status);
+            }
+        };
+
+        if let Some(events) = &self.events {
+            events.publish(ChangeType::Updated, &student);
+        }
+        info!("📧 Email for {} changed to {}", student.id, student.email);
+        Ok(Response::new(ConfirmEmailChangeResponse {
+            student: Some(student),
+        }))
+    }
+}
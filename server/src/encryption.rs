@@ -0,0 +1,233 @@
+//! Encrypting what the server writes to disk.
+//!
+//! Data is sealed with AES-256-GCM under the first key of a [`Keyring`];
+//! every other key in the ring can still open what it sealed earlier. To
+//! rotate, put a new key first, keep the old ones, re-encrypt what is on
+//! disk (`server --reencrypt-snapshots`), then drop the old keys.
+//!
+//! Keys come from a [`KeyProvider`], chosen when the server starts (see
+//! [`from_url`]):
+//!
+//! - [`EnvKeys`] reads them from an environment variable
+//! - [`FileKeys`] reads them from a file, again each time they are needed,
+//!   so a key added there is used without a restart
+//!
+//! Either holds `id:hex` pairs separated by commas or new lines, each key
+//! 32 random bytes in hex, e.g. from `openssl rand -hex 32`.
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use std::fmt::{self, Debug};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+// Starts everything sealed, so it can be told from plain text
+const MAGIC: &[u8] = b"STUDENTS-AES256GCM1";
+
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+
+/// Named AES-256 keys; the first seals, all of them open.
+#[derive(Clone)]
+pub struct Keyring {
+    keys: Vec<(String, Aes256Gcm)>,
+}
+
+// Never print the keys themselves
+impl Debug for Keyring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keyring")
+            .field(
+                "ids",
+                &self.keys.iter().map(|(id, _)| id).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+/// The bytes spelled by `hex`, two digits each.
+pub(crate) fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|at| u8::from_str_radix(hex.get(at..at + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_key(hex: &str) -> Option<[u8; KEY_LENGTH]> {
+    from_hex(hex)?.try_into().ok()
+}
+
+impl Keyring {
+    /// Parse `id:hex` pairs separated by commas or new lines; the first
+    /// seals from now on.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut keys: Vec<(String, Aes256Gcm)> = Vec::new();
+        for entry in text.split([',', '\n']).map(str::trim) {
+            if entry.is_empty() || entry.starts_with('#') {
+                continue;
+            }
+            let (id, hex) = entry
+                .split_once(':')
+                .ok_or_else(|| "expected keys as id:hex".to_string())?;
+            let id = id.trim();
+            if id.is_empty() || id.len() > u8::MAX as usize {
+                return Err(format!("{:?}: key IDs must be 1 to 255 bytes", id));
+            }
+            if keys.iter().any(|(known, _)| known == id) {
+                return Err(format!("Key {} is given twice", id));
+            }
+            let key = parse_key(hex.trim())
+                .ok_or_else(|| format!("Key {}: expected {} bytes in hex", id, KEY_LENGTH))?;
+            let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+            keys.push((id.to_string(), cipher));
+        }
+        if keys.is_empty() {
+            return Err("No encryption keys given".to_string());
+        }
+        Ok(Self { keys })
+    }
+
+    /// The ID of the key that seals.
+    pub fn primary(&self) -> &str {
+        &self.keys[0].0
+    }
+
+    /// Encrypt `plaintext` under the first key.
+    pub fn seal(&self, plaintext: &[u8]) -> Vec<u8> {
+        let (id, cipher) = &self.keys[0];
+        let mut sealed = header(id);
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: &sealed,
+                },
+            )
+            .expect("AES-GCM encrypts any length the server writes");
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        sealed
+    }
+
+    /// Decrypt what [`Keyring::seal`] made, with whichever key sealed it.
+    pub fn open(&self, sealed: &[u8]) -> Result<Vec<u8>, String> {
+        let id = sealed_with(sealed).ok_or_else(|| "Not encrypted".to_string())?;
+        let (_, cipher) = self
+            .keys
+            .iter()
+            .find(|(known, _)| known == id)
+            .ok_or_else(|| format!("Encrypted with key {}, which is not in the keyring", id))?;
+        let aad = header(id);
+        let rest = &sealed[aad.len()..];
+        if rest.len() < NONCE_LENGTH {
+            return Err("Encrypted data is truncated".to_string());
+        }
+        let (nonce, ciphertext) = rest.split_at(NONCE_LENGTH);
+        cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| format!("Cannot decrypt with key {}: wrong key or damaged data", id))
+    }
+}
+
+// The magic, then the key ID and its length; authenticated with the data
+fn header(id: &str) -> Vec<u8> {
+    let mut header = MAGIC.to_vec();
+    header.push(id.len() as u8);
+    header.extend_from_slice(id.as_bytes());
+    header
+}
+
+/// The ID of the key `data` was sealed with, or `None` if it is not sealed.
+pub fn sealed_with(data: &[u8]) -> Option<&str> {
+    let rest = data.strip_prefix(MAGIC)?;
+    let (&length, rest) = rest.split_first()?;
+    std::str::from_utf8(rest.get(..length as usize)?).ok()
+}
+
+/// Where the keys come from, e.g. a key management service.
+pub trait KeyProvider: Debug + Send + Sync {
+    /// The keys as they are now.
+    fn keys(&self) -> Result<Keyring, String>;
+}
+
+/// Keys in an environment variable.
+#[derive(Debug, Clone)]
+pub struct EnvKeys {
+    var: String,
+}
+
+impl EnvKeys {
+    pub fn new(var: &str) -> Self {
+        Self {
+            var: var.to_string(),
+        }
+    }
+}
+
+impl KeyProvider for EnvKeys {
+    fn keys(&self) -> Result<Keyring, String> {
+        let text = std::env::var(&self.var).map_err(|_| format!("{} is not set", self.var))?;
+        Keyring::parse(&text).map_err(|e| format!("{}: {}", self.var, e))
+    }
+}
+
+/// Keys in a file, read each time they are asked for.
+#[derive(Debug, Clone)]
+pub struct FileKeys {
+    path: PathBuf,
+}
+
+impl FileKeys {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl KeyProvider for FileKeys {
+    fn keys(&self) -> Result<Keyring, String> {
+        let text = std::fs::read_to_string(&self.path)
+            .map_err(|e| format!("Cannot read {}: {}", self.path.display(), e))?;
+        Keyring::parse(&text).map_err(|e| format!("{}: {}", self.path.display(), e))
+    }
+}
+
+/// The text in `env:<VARIABLE>` or in `file:<path>`, trimmed, for secrets
+/// that do not change while the server runs.
+pub fn read_secret(source: &str) -> Result<String, String> {
+    let text = if let Some(var) = source.strip_prefix("env:") {
+        std::env::var(var).map_err(|_| format!("{} is not set", var))?
+    } else if let Some(path) = source.strip_prefix("file:") {
+        std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path, e))?
+    } else {
+        return Err(format!(
+            "{}: expected env:<VARIABLE> or file:<path>",
+            source
+        ));
+    };
+    Ok(text.trim().to_string())
+}
+
+/// A key provider from its command-line form: `env:<VARIABLE>` or
+/// `file:<path>`. The keys are read once here to check them.
+pub fn from_url(url: &str) -> Result<Arc<dyn KeyProvider>, String> {
+    let provider: Arc<dyn KeyProvider> = if let Some(var) = url.strip_prefix("env:") {
+        Arc::new(EnvKeys::new(var))
+    } else if let Some(path) = url.strip_prefix("file:") {
+        Arc::new(FileKeys::new(path))
+    } else {
+        return Err(format!("{}: expected env:<VARIABLE> or file:<path>", url));
+    };
+    provider.keys()?;
+    Ok(provider)
+}
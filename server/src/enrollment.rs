@@ -0,0 +1,740 @@
+//! Course enrollment: capacity limits, prerequisites, and waitlists.
+//!
+//! Courses (a [`crate::crud`] collection), rosters, and transcripts are
+//! kept in memory; students are looked
+//! up in the same [`StudentRepository`] as the student service uses.
+//! Rule violations fail with `FAILED_PRECONDITION` and carry a
+//! `google.rpc.PreconditionFailure` in the status details, listing every
+//! reason with a machine-readable type (see `enrollment.proto`).
+//!
+//! Grades are weighted by their course's credits. Recording one rewrites
+//! the student's `credits_attempted` and `credits_earned` from the whole
+//! transcript; `RecomputeGpa` does the same for their GPA.
+
+use crate::clock::{self, Clock, SystemClock};
+use crate::crud::{Collection, Entity};
+use crate::errors::Error;
+use crate::events::EventLog;
+use crate::gpa::{self, Gpa};
+use crate::locale::{self, Text};
+use crate::repository::StudentRepository;
+use crate::transcript::{self, Transcript, TranscriptLine};
+use prost::Message;
+use proto::enrollment_service_server::EnrollmentService;
+use proto::google::rpc::{precondition_failure::Violation, PreconditionFailure};
+use proto::{
+    ChangeType, Course, CreateCourseRequest, CreateCourseResponse, DropCourseRequest,
+    DropCourseResponse, EnrollRequest, EnrollResponse, Enrollment, EnrollmentStatus,
+    GenerateTranscriptRequest, GetCourseRequest, GetCourseResponse, GetTranscriptRequest,
+    GetTranscriptResponse, GpaRecomputation, ListEnrollmentsRequest, ListEnrollmentsResponse,
+    RecomputeGpaRequest, RecomputeGpaResponse, RecordGradeRequest, RecordGradeResponse, Student,
+    TranscriptChunk, TranscriptEntry,
+};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Code, Request, Response, Status};
+
+/// Violation types reported in `PreconditionFailure` details.
+pub const ALREADY_ENROLLED: &str = "ALREADY_ENROLLED";
+pub const ALREADY_PASSED: &str = "ALREADY_PASSED";
+pub const PREREQUISITE_NOT_MET: &str = "PREREQUISITE_NOT_MET";
+pub const WAITLIST_FULL: &str = "WAITLIST_FULL";
+
+pub(crate) const GRADES: &[&str] = &[
+    "A+", "A", "A-", "B+", "B", "B-", "C+", "C", "C-", "D+", "D", "D-", "F", "P",
+];
+
+fn passed(grade: &str) -> bool {
+    grade != "F"
+}
+
+// Grade points in tenths, for the grades that count toward GPA; A+ is
+// worth no more than A, as GPAs stop at 4.0
+fn grade_points(grade: &str) -> Option<i64> {
+    let points = match grade {
+        "A+" | "A" => 40,
+        "A-" => 37,
+        "B+" => 33,
+        "B" => 30,
+        "B-" => 27,
+        "C+" => 23,
+        "C" => 20,
+        "C-" => 17,
+        "D+" => 13,
+        "D" => 10,
+        "D-" => 7,
+        "F" => 0,
+        // Pass/fail
+        _ => return None,
+    };
+    Some(points)
+}
+
+// Times a write of a student's credits or GPA is retried when someone else
+// changes the student in between
+const WRITE_ATTEMPTS: usize = 3;
+
+// Rendered transcripts are streamed in pieces of this many bytes
+const TRANSCRIPT_CHUNK_SIZE: usize = 16 * 1024;
+
+#[derive(Debug, Default)]
+struct Roster {
+    enrolled: Vec<String>,
+    waitlist: VecDeque<String>,
+}
+
+impl Entity for Course {
+    const KIND: &'static str = "Course";
+    const NOT_FOUND: Error = Error::CourseNotFound;
+    const EXISTS: Error = Error::CourseExists;
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn id_mut(&mut self) -> &mut String {
+        &mut self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.title
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    courses: Collection<Course>,
+    rosters: HashMap<String, Roster>,
+    // Student ID to course ID to grade
+    transcripts: HashMap<String, BTreeMap<String, String>>,
+}
+
+// What a student's grades add up to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Totals {
+    credits_attempted: i32,
+    credits_earned: i32,
+    // Credit-weighted; `None` without graded credits
+    gpa: Option<Gpa>,
+}
+
+impl State {
+    fn totals(&self, student_id: &str) -> Totals {
+        let mut totals = Totals::default();
+        // Grade points in tenths times credits, and the credits they cover
+        let (mut points, mut graded) = (0_i64, 0_i64);
+        for (course_id, grade) in self.transcripts.get(student_id).into_iter().flatten() {
+            let credits = self
+                .courses
+                .get(course_id)
+                .map_or(0, |course| course.credits);
+            totals.credits_attempted += credits;
+            if passed(grade) {
+                totals.credits_earned += credits;
+            }
+            if let Some(grade_points) = grade_points(grade) {
+                points += grade_points * i64::from(credits);
+                graded += i64::from(credits);
+            }
+        }
+        // In hundredths, rounded half up
+        totals.gpa =
+            (graded > 0).then(|| Gpa::from_hundredths((points * 10 + graded / 2) / graded));
+        totals
+    }
+}
+
+/// What [`EnrollmentServiceImpl::transfer`] moved from one student to another.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Transferred {
+    /// Courses whose seat or waitlist place moved
+    pub places: Vec<String>,
+    /// Courses whose grade moved
+    pub grades: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct EnrollmentServiceImpl {
+    students: Arc<dyn StudentRepository>,
+    state: Mutex<State>,
+    clock: Arc<dyn Clock>,
+    events: Option<Arc<EventLog>>,
+}
+
+impl EnrollmentServiceImpl {
+    /// Enroll the students kept in `students`.
+    pub fn new(students: Arc<dyn StudentRepository>) -> Self {
+        Self {
+            students,
+            state: Mutex::new(State::default()),
+            clock: Arc::new(SystemClock),
+            events: None,
+        }
+    }
+
+    /// Use `clock` for the update times of students whose grades change.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Tell watchers in `events` about students whose credits or GPA change.
+    pub fn with_events(mut self, events: Arc<EventLog>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The students holding a seat in `course_id`, or `None` for an unknown course.
+    pub fn enrolled_students(&self, course_id: &str) -> Option<Vec<String>> {
+        let state = self.state();
+        state.courses.get(course_id)?;
+        Some(
+            state
+                .rosters
+                .get(course_id)
+                .map(|roster| roster.enrolled.clone())
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Give the seats, waitlist places, and grades of student `from` to
+    /// student `to`, keeping their place in line. Where `to` already has a
+    /// place or grade in the course, `from`'s is dropped instead, and a
+    /// dropped seat goes to the waitlist. With `only`, just those entries
+    /// move and `from` keeps the rest.
+    pub fn transfer(&self, from: &str, to: &str, only: Option<&Transferred>) -> Transferred {
+        let mut state = self.state();
+        let mut moved = Transferred::default();
+
+        let mut course_ids: Vec<String> = state.rosters.keys().cloned().collect();
+        course_ids.sort();
+        for course_id in course_ids {
+            if only.is_some_and(|only| !only.places.contains(&course_id)) {
+                continue;
+            }
+            let roster = state.rosters.get_mut(&course_id).expect("listed above");
+            let seat = roster.enrolled.iter().position(|id| id == from);
+            let place = roster.waitlist.iter().position(|id| id == from);
+            let has_own = roster
+                .enrolled
+                .iter()
+                .chain(&roster.waitlist)
+                .any(|id| id == to);
+            match (seat, place) {
+                (Some(index), _) if has_own => {
+                    roster.enrolled.remove(index);
+                    if let Some(next) = roster.waitlist.pop_front() {
+                        info!("Promoted student {} from the {} waitlist", next, course_id);
+                        roster.enrolled.push(next);
+                    }
+                }
+                (None, Some(index)) if has_own => {
+                    roster.waitlist.remove(index);
+                }
+                (Some(index), _) => {
+                    roster.enrolled[index] = to.to_string();
+                    moved.places.push(course_id);
+                }
+                (None, Some(index)) => {
+                    roster.waitlist[index] = to.to_string();
+                    moved.places.push(course_id);
+                }
+                (None, None) => {}
+            }
+        }
+
+        let mut grades = state.transcripts.remove(from).unwrap_or_default();
+        let kept: BTreeMap<String, String> = match only {
+            Some(only) => {
+                let (moving, kept) = grades
+                    .into_iter()
+                    .partition(|(course_id, _)| only.grades.contains(course_id));
+                grades = moving;
+                kept
+            }
+            None => BTreeMap::new(),
+        };
+        if !kept.is_empty() {
+            state.transcripts.insert(from.to_string(), kept);
+        }
+        let transcript = state.transcripts.entry(to.to_string()).or_default();
+        for (course_id, grade) in grades {
+            if !transcript.contains_key(&course_id) {
+                transcript.insert(course_id.clone(), grade);
+                moved.grades.push(course_id);
+            }
+        }
+        if transcript.is_empty() {
+            state.transcripts.remove(to);
+        }
+        moved
+    }
+
+    /// Write `student_id`'s credits as their grades add them up, e.g. after
+    /// grades were moved to or from them with [`Self::transfer`].
+    pub async fn update_credits(&self, student_id: &str) -> Result<Student, Status> {
+        let (_, student) = self.apply_grades(student_id, false, false).await?;
+        Ok(student)
+    }
+
+    // Give the student the credits, and with `with_gpa` the GPA, their
+    // grades add up to, returning them before and after. A dry run only
+    // works out the after.
+    async fn apply_grades(
+        &self,
+        student_id: &str,
+        with_gpa: bool,
+        dry_run: bool,
+    ) -> Result<(Student, Student), Status> {
+        let mut attempts = 0;
+        loop {
+            let stored = self.students.get(student_id).await?;
+            let totals = self.state().totals(student_id);
+            let mut student = stored.clone();
+            student.credits_attempted = totals.credits_attempted;
+            student.credits_earned = totals.credits_earned;
+            if let Some(gpa) = totals.gpa.filter(|_| with_gpa) {
+                gpa::set(&mut student, gpa);
+            }
+            if dry_run || student == stored {
+                return Ok((stored, student));
+            }
+
+            // The stored etag makes the write fail if the student changed
+            student.update_time = Some(clock::timestamp(self.clock.now()));
+            attempts += 1;
+            match self.students.update(student).await {
+                Ok(student) => {
+                    if let Some(events) = &self.events {
+                        events.publish(ChangeType::Updated, &student);
+                    }
+                    return Ok((stored, student));
+                }
+                Err(status) if status.code() == Code::Aborted && attempts < WRITE_ATTEMPTS => {}
+                Err(status) => return Err(status),
+            }
+        }
+    }
+
+    // Fails with NOT_FOUND for unknown students
+    async fn check_student(&self, student_id: &str) -> Result<(), Status> {
+        if student_id.trim().is_empty() {
+            return Err(locale::status(Code::InvalidArgument, Text::IdEmpty));
+        }
+        self.students.get(student_id).await.map(|_| ())
+    }
+}
+
+fn violation(kind: &str, subject: String, description: String) -> Violation {
+    Violation {
+        r#type: kind.to_string(),
+        subject,
+        description,
+    }
+}
+
+/// `FAILED_PRECONDITION` with `violations` as a `google.rpc.PreconditionFailure`
+/// in the status details.
+fn precondition_failed(violations: Vec<Violation>) -> Status {
+    let message = violations
+        .iter()
+        .map(|violation| violation.description.as_str())
+        .collect::<Vec<_>>()
+        .join("; ");
+    let failure = PreconditionFailure { violations };
+    let details = proto::google::rpc::Status {
+        code: Code::FailedPrecondition as i32,
+        message: message.clone(),
+        details: vec![proto::Any {
+            type_url: "type.googleapis.com/google.rpc.PreconditionFailure".to_string(),
+            value: failure.encode_to_vec().into(),
+        }],
+    };
+    Status::with_details(
+        Code::FailedPrecondition,
+        message,
+        details.encode_to_vec().into(),
+    )
+}
+
+fn enrolled(student_id: &str, course_id: &str) -> Enrollment {
+    Enrollment {
+        student_id: student_id.to_string(),
+        course_id: course_id.to_string(),
+        status: EnrollmentStatus::Enrolled as i32,
+        waitlist_position: 0,
+    }
+}
+
+fn waitlisted(student_id: &str, course_id: &str, position: usize) -> Enrollment {
+    Enrollment {
+        student_id: student_id.to_string(),
+        course_id: course_id.to_string(),
+        status: EnrollmentStatus::Waitlisted as i32,
+        waitlist_position: position as i32,
+    }
+}
+
+#[tonic::async_trait]
+impl EnrollmentService for EnrollmentServiceImpl {
+    type GenerateTranscriptStream = ReceiverStream<Result<TranscriptChunk, Status>>;
+
+    async fn create_course(
+        &self,
+        request: Request<CreateCourseRequest>,
+    ) -> Result<Response<CreateCourseResponse>, Status> {
+        let course = request.into_inner().course.unwrap_or_default();
+
+        if course.title.trim().is_empty() {
+            return Err(Error::CourseTitleEmpty.into());
+        }
+        if course.capacity < 1 {
+            return Err(Error::CapacityTooSmall.into());
+        }
+        if course.waitlist_capacity < 0 {
+            return Err(Error::WaitlistCapacityNegative.into());
+        }
+        if course.credits < 0 {
+            return Err(Error::CourseCreditsNegative.into());
+        }
+
+        let mut state = self.state();
+        if let Some(unknown) = course
+            .prerequisite_ids
+            .iter()
+            .find(|id| !state.courses.contains(id))
+        {
+            return Err(Error::UnknownPrerequisite(unknown.clone()).into());
+        }
+        let course = state.courses.create(course)?;
+
+        Ok(Response::new(CreateCourseResponse {
+            course: Some(course),
+        }))
+    }
+
+    async fn get_course(
+        &self,
+        request: Request<GetCourseRequest>,
+    ) -> Result<Response<GetCourseResponse>, Status> {
+        let id = request.into_inner().id;
+
+        let course = self.state().courses.find(&id)?.clone();
+
+        Ok(Response::new(GetCourseResponse {
+            course: Some(course),
+        }))
+    }
+
+    async fn enroll(
+        &self,
+        request: Request<EnrollRequest>,
+    ) -> Result<Response<EnrollResponse>, Status> {
+        let EnrollRequest {
+            student_id,
+            course_id,
+        } = request.into_inner();
+        self.check_student(&student_id).await?;
+
+        let mut state = self.state();
+        let course = state.courses.find(&course_id)?.clone();
+        let empty = BTreeMap::new();
+        let transcript = state.transcripts.get(&student_id).unwrap_or(&empty);
+        let has_passed = |id: &String| transcript.get(id).is_some_and(|grade| passed(grade));
+
+        let mut violations = Vec::new();
+        if has_passed(&course.id) {
+            violations.push(violation(
+                ALREADY_PASSED,
+                format!("students/{}", student_id),
+                format!("Student has already passed {}", course.id),
+            ));
+        }
+        for prerequisite in &course.prerequisite_ids {
+            if !has_passed(prerequisite) {
+                violations.push(violation(
+                    PREREQUISITE_NOT_MET,
+                    format!("courses/{}", prerequisite),
+                    format!("Prerequisite {} has not been passed", prerequisite),
+                ));
+            }
+        }
+
+        let roster = state.rosters.entry(course_id.clone()).or_default();
+        if roster.enrolled.contains(&student_id) || roster.waitlist.contains(&student_id) {
+            violations.push(violation(
+                ALREADY_ENROLLED,
+                format!("students/{}", student_id),
+                format!(
+                    "Student is already enrolled in or waitlisted for {}",
+                    course_id
+                ),
+            ));
+        }
+        let has_seat = roster.enrolled.len() < course.capacity as usize;
+        if !has_seat && roster.waitlist.len() >= course.waitlist_capacity as usize {
+            violations.push(violation(
+                WAITLIST_FULL,
+                format!("courses/{}", course_id),
+                format!("{} is full and so is its waitlist", course_id),
+            ));
+        }
+        if !violations.is_empty() {
+            return Err(precondition_failed(violations));
+        }
+
+        let enrollment = if has_seat {
+            roster.enrolled.push(student_id.clone());
+            info!("Enrolled student {} in {}", student_id, course_id);
+            enrolled(&student_id, &course_id)
+        } else {
+            roster.waitlist.push_back(student_id.clone());
+            info!(
+                "Waitlisted student {} for {} (position {})",
+                student_id,
+                course_id,
+                roster.waitlist.len()
+            );
+            waitlisted(&student_id, &course_id, roster.waitlist.len())
+        };
+
+        Ok(Response::new(EnrollResponse {
+            enrollment: Some(enrollment),
+        }))
+    }
+
+    async fn drop_course(
+        &self,
+        request: Request<DropCourseRequest>,
+    ) -> Result<Response<DropCourseResponse>, Status> {
+        let DropCourseRequest {
+            student_id,
+            course_id,
+        } = request.into_inner();
+
+        let mut state = self.state();
+        state.courses.find(&course_id)?;
+        let roster = state.rosters.entry(course_id.clone()).or_default();
+
+        let mut promoted = None;
+        if let Some(index) = roster.enrolled.iter().position(|id| *id == student_id) {
+            roster.enrolled.remove(index);
+            // The freed seat goes to whoever has waited longest
+            if let Some(next) = roster.waitlist.pop_front() {
+                roster.enrolled.push(next.clone());
+                info!("Promoted student {} from the {} waitlist", next, course_id);
+                promoted = Some(enrolled(&next, &course_id));
+            }
+        } else if let Some(index) = roster.waitlist.iter().position(|id| *id == student_id) {
+            roster.waitlist.remove(index);
+        } else {
+            return Err(Error::NotEnrolled.into());
+        }
+
+        info!("Dropped student {} from {}", student_id, course_id);
+
+        Ok(Response::new(DropCourseResponse { promoted }))
+    }
+
+    async fn record_grade(
+        &self,
+        request: Request<RecordGradeRequest>,
+    ) -> Result<Response<RecordGradeResponse>, Status> {
+        let RecordGradeRequest { student_id, entry } = request.into_inner();
+        let entry = entry.unwrap_or_default();
+
+        if !GRADES.contains(&entry.grade.as_str()) {
+            return Err(Error::GradeInvalid.into());
+        }
+        self.check_student(&student_id).await?;
+
+        {
+            let mut state = self.state();
+            state.courses.find(&entry.course_id)?;
+            state
+                .transcripts
+                .entry(student_id.clone())
+                .or_default()
+                .insert(entry.course_id.clone(), entry.grade.clone());
+        }
+
+        info!(
+            "Recorded grade {} in {} for student {}",
+            entry.grade, entry.course_id, student_id
+        );
+        self.update_credits(&student_id).await?;
+
+        Ok(Response::new(RecordGradeResponse { entry: Some(entry) }))
+    }
+
+    async fn recompute_gpa(
+        &self,
+        request: Request<RecomputeGpaRequest>,
+    ) -> Result<Response<RecomputeGpaResponse>, Status> {
+        let RecomputeGpaRequest {
+            mut student_ids,
+            dry_run,
+        } = request.into_inner();
+        if student_ids.is_empty() {
+            student_ids = self.state().transcripts.keys().cloned().collect();
+            student_ids.sort();
+        }
+        if student_ids.iter().any(|id| id.trim().is_empty()) {
+            return Err(locale::status(Code::InvalidArgument, Text::IdEmpty));
+        }
+
+        let mut students = Vec::new();
+        for student_id in student_ids {
+            let (before, after) = self.apply_grades(&student_id, true, dry_run).await?;
+            let (previous_gpa, gpa) = (gpa::of(&before), gpa::of(&after));
+            students.push(GpaRecomputation {
+                student_id,
+                previous_gpa: previous_gpa.to_string(),
+                gpa: gpa.to_string(),
+                credits_attempted: after.credits_attempted,
+                credits_earned: after.credits_earned,
+                changed: previous_gpa != gpa
+                    || before.credits_attempted != after.credits_attempted
+                    || before.credits_earned != after.credits_earned,
+            });
+        }
+
+        let changed = students.iter().filter(|student| student.changed).count();
+        info!(
+            "🧮 Recomputed GPA for {} students, {} changed{}",
+            students.len(),
+            changed,
+            if dry_run { " (dry run)" } else { "" }
+        );
+        Ok(Response::new(RecomputeGpaResponse { students }))
+    }
+
+    async fn get_transcript(
+        &self,
+        request: Request<GetTranscriptRequest>,
+    ) -> Result<Response<GetTranscriptResponse>, Status> {
+        let student_id = request.into_inner().student_id;
+        self.check_student(&student_id).await?;
+
+        let entries = self
+            .state()
+            .transcripts
+            .get(&student_id)
+            .map(|grades| {
+                grades
+                    .iter()
+                    .map(|(course_id, grade)| TranscriptEntry {
+                        course_id: course_id.clone(),
+                        grade: grade.clone(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(Response::new(GetTranscriptResponse { entries }))
+    }
+
+    async fn generate_transcript(
+        &self,
+        request: Request<GenerateTranscriptRequest>,
+    ) -> Result<Response<Self::GenerateTranscriptStream>, Status> {
+        let request = request.into_inner();
+        let format = request.format();
+        if request.student_id.trim().is_empty() {
+            return Err(locale::status(Code::InvalidArgument, Text::IdEmpty));
+        }
+        if !transcript::supported(format) {
+            return Err(Status::unimplemented(
+                "This server was built without PDF transcripts",
+            ));
+        }
+        let student = self.students.get(&request.student_id).await?;
+
+        let lines = {
+            let state = self.state();
+            state
+                .transcripts
+                .get(&student.id)
+                .map(|grades| {
+                    grades
+                        .iter()
+                        .map(|(course_id, grade)| TranscriptLine {
+                            course_id: course_id.clone(),
+                            title: state
+                                .courses
+                                .get(course_id)
+                                .map(|course| course.title.clone())
+                                .unwrap_or_default(),
+                            grade: grade.clone(),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let transcript = Transcript { student, lines };
+
+        // Rendering would hold up the async workers, so it gets a blocking
+        // thread. Dropping the response stream, e.g. when the client hangs
+        // up, closes the channel, which stops the rendering.
+        let (tx, rx) = mpsc::channel(4);
+        tokio::task::spawn_blocking(move || {
+            let student_id = &transcript.student.id;
+            let Some(document) = transcript::render(&transcript, format, || tx.is_closed()) else {
+                info!("Transcript for student {} cancelled", student_id);
+                return;
+            };
+            info!(
+                "Generated transcript for student {} ({} bytes)",
+                student_id,
+                document.len()
+            );
+            for (index, data) in document.chunks(TRANSCRIPT_CHUNK_SIZE).enumerate() {
+                let chunk = TranscriptChunk {
+                    data: data.to_vec(),
+                    content_type: match index {
+                        0 => transcript::content_type(format).to_string(),
+                        _ => String::new(),
+                    },
+                };
+                if tx.blocking_send(Ok(chunk)).is_err() {
+                    info!("Transcript for student {} cancelled", student_id);
+                    return;
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn list_enrollments(
+        &self,
+        request: Request<ListEnrollmentsRequest>,
+    ) -> Result<Response<ListEnrollmentsResponse>, Status> {
+        let course_id = request.into_inner().course_id;
+
+        let state = self.state();
+        state.courses.find(&course_id)?;
+        let (enrolled_ids, waitlisted_ids) = match state.rosters.get(&course_id) {
+            Some(roster) => (roster.enrolled.as_slice(), roster.waitlist.iter().collect()),
+            None => (&[][..], Vec::new()),
+        };
+
+        Ok(Response::new(ListEnrollmentsResponse {
+            enrolled: enrolled_ids
+                .iter()
+                .map(|id| enrolled(id, &course_id))
+                .collect(),
+            waitlisted: waitlisted_ids
+                .iter()
+                .enumerate()
+                .map(|(index, id)| waitlisted(id, &course_id, index + 1))
+                .collect(),
+        }))
+    }
+}
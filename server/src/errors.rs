@@ -0,0 +1,398 @@
+//! Stable, machine-readable codes for the errors the server reports.
+//!
+//! Messages are for people, and get reworded. Every [`Error`] becomes a
+//! status whose details hold a `google.rpc.ErrorInfo` with the error's
+//! [`code`](Error::code), e.g. `COURSE_NOT_FOUND`, as its `reason`, the
+//! [`ERROR_DOMAIN`] as its `domain`, and the values in the message, such as
+//! the ID that was not found, as its `metadata`. Clients branch on the code.
+//! A code keeps its meaning once released and is never reused.
+//!
+//! The errors in [`Text`](crate::locale::Text) are described the same way,
+//! in the caller's language; these are in English only.
+
+pub use crate::locale::ERROR_DOMAIN;
+use prost::Message;
+use proto::google::rpc::ErrorInfo;
+use std::collections::HashMap;
+use std::fmt;
+use tonic::{Code, Status};
+
+/// An error the server reports, with a code of its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The two fields, neither of which may be empty
+    IdsRequired(&'static str, &'static str),
+    TimeRangeReversed,
+    UpdateMaskEmpty,
+    /// The field path given
+    FieldNotBulkUpdatable(String),
+    FilterEmpty,
+    /// The operation ID, and what it should have been, e.g. "bulk update"
+    WrongOperationKind(String, &'static str),
+    /// The operation ID
+    OperationNotFound(String),
+    DepartmentNotFound,
+    DepartmentExists,
+    DepartmentNameEmpty,
+    /// The department ID given
+    UnknownDepartment(String),
+    /// A major of the department
+    DepartmentHasMajors(String),
+    MajorNotFound,
+    MajorExists,
+    MajorNameEmpty,
+    /// The name, and the major that already has it
+    MajorNameTaken(String, String),
+    /// How many students have declared the major
+    MajorDeclared(usize),
+    ProfessorNotFound,
+    ProfessorExists,
+    ProfessorNameEmpty,
+    /// How many students the professor advises
+    ProfessorHasAdvisees(usize),
+    ReassignToInvalid,
+    NoAdvisor,
+    CourseNotFound,
+    CourseExists,
+    CourseTitleEmpty,
+    CapacityTooSmall,
+    WaitlistCapacityNegative,
+    CourseCreditsNegative,
+    /// The course ID given
+    UnknownPrerequisite(String),
+    NotEnrolled,
+    GradeInvalid,
+    MinScoreOutOfRange,
+    MergeIntoItself,
+    /// The student ID
+    EmailChangeNotPending(String),
+    /// The student ID
+    EmailChangeExpired(String),
+    VerificationCodeWrong,
+    /// The address given
+    NewEmailInvalid(String),
+    /// The student ID, and the address it already has
+    EmailUnchanged(String, String),
+    MinGpaOutOfRange,
+    MinCreditsNegative,
+    /// The resume token of the oldest change asked for
+    ChangesExpired(String),
+    ResumeTokenInvalid,
+    ResumeTokenExpired,
+    /// The flag's name
+    FlagNotFound(String),
+    FlagClearNeedsTenant,
+    TenantRequired,
+    /// The tenant, and its limit
+    QuotaExceeded(String, u64),
+    /// The bytes used, and the limit
+    StoreFull(usize, usize),
+    TransactionCommitted,
+    CrossShardTransaction,
+    /// The task's name
+    TaskNotFound(String),
+    /// The task's name
+    TaskRunning(String),
+    /// The operation ID
+    NothingInTrash(String),
+    /// Why the destination cannot be used
+    DestinationInvalid(String),
+    DestinationNotEmpty,
+    PointInTimeNeedsSnapshot,
+    /// Why the configuration was refused
+    LoggingConfigInvalid(String),
+    /// The service asked about
+    UnknownService(String),
+}
+
+impl Error {
+    /// `ErrorInfo.reason`: stable, and what clients should match on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::IdsRequired(..) => "IDS_REQUIRED",
+            Error::TimeRangeReversed => "TIME_RANGE_REVERSED",
+            Error::UpdateMaskEmpty => "UPDATE_MASK_EMPTY",
+            Error::FieldNotBulkUpdatable(_) => "FIELD_NOT_BULK_UPDATABLE",
+            Error::FilterEmpty => "FILTER_EMPTY",
+            Error::WrongOperationKind(..) => "WRONG_OPERATION_KIND",
+            Error::OperationNotFound(_) => "OPERATION_NOT_FOUND",
+            Error::DepartmentNotFound => "DEPARTMENT_NOT_FOUND",
+            Error::DepartmentExists => "DEPARTMENT_ALREADY_EXISTS",
+            Error::DepartmentNameEmpty => "DEPARTMENT_NAME_EMPTY",
+            Error::UnknownDepartment(_) => "UNKNOWN_DEPARTMENT",
+            Error::DepartmentHasMajors(_) => "DEPARTMENT_HAS_MAJORS",
+            Error::MajorNotFound => "MAJOR_NOT_FOUND",
+            Error::MajorExists => "MAJOR_ALREADY_EXISTS",
+            Error::MajorNameEmpty => "MAJOR_NAME_EMPTY",
+            Error::MajorNameTaken(..) => "MAJOR_NAME_TAKEN",
+            Error::MajorDeclared(_) => "MAJOR_DECLARED",
+            Error::ProfessorNotFound => "PROFESSOR_NOT_FOUND",
+            Error::ProfessorExists => "PROFESSOR_ALREADY_EXISTS",
+            Error::ProfessorNameEmpty => "PROFESSOR_NAME_EMPTY",
+            Error::ProfessorHasAdvisees(_) => "PROFESSOR_HAS_ADVISEES",
+            Error::ReassignToInvalid => "REASSIGN_TO_INVALID",
+            Error::NoAdvisor => "NO_ADVISOR",
+            Error::CourseNotFound => "COURSE_NOT_FOUND",
+            Error::CourseExists => "COURSE_ALREADY_EXISTS",
+            Error::CourseTitleEmpty => "COURSE_TITLE_EMPTY",
+            Error::CapacityTooSmall => "CAPACITY_TOO_SMALL",
+            Error::WaitlistCapacityNegative => "WAITLIST_CAPACITY_NEGATIVE",
+            Error::CourseCreditsNegative => "COURSE_CREDITS_NEGATIVE",
+            Error::UnknownPrerequisite(_) => "UNKNOWN_PREREQUISITE",
+            Error::NotEnrolled => "NOT_ENROLLED",
+            Error::GradeInvalid => "GRADE_INVALID",
+            Error::MinScoreOutOfRange => "MIN_SCORE_OUT_OF_RANGE",
+            Error::MergeIntoItself => "MERGE_INTO_ITSELF",
+            Error::EmailChangeNotPending(_) => "EMAIL_CHANGE_NOT_PENDING",
+            Error::EmailChangeExpired(_) => "EMAIL_CHANGE_EXPIRED",
+            Error::VerificationCodeWrong => "VERIFICATION_CODE_WRONG",
+            Error::NewEmailInvalid(_) => "NEW_EMAIL_INVALID",
+            Error::EmailUnchanged(..) => "EMAIL_UNCHANGED",
+            Error::MinGpaOutOfRange => "MIN_GPA_OUT_OF_RANGE",
+            Error::MinCreditsNegative => "MIN_CREDITS_NEGATIVE",
+            Error::ChangesExpired(_) => "CHANGES_EXPIRED",
+            Error::ResumeTokenInvalid => "RESUME_TOKEN_INVALID",
+            Error::ResumeTokenExpired => "RESUME_TOKEN_EXPIRED",
+            Error::FlagNotFound(_) => "FLAG_NOT_FOUND",
+            Error::FlagClearNeedsTenant => "FLAG_CLEAR_NEEDS_TENANT",
+            Error::TenantRequired => "TENANT_REQUIRED",
+            Error::QuotaExceeded(..) => "QUOTA_EXCEEDED",
+            Error::StoreFull(..) => "STORE_FULL",
+            Error::TransactionCommitted => "TRANSACTION_ALREADY_COMMITTED",
+            Error::CrossShardTransaction => "CROSS_SHARD_TRANSACTION",
+            Error::TaskNotFound(_) => "TASK_NOT_FOUND",
+            Error::TaskRunning(_) => "TASK_ALREADY_RUNNING",
+            Error::NothingInTrash(_) => "NOTHING_IN_TRASH",
+            Error::DestinationInvalid(_) => "DESTINATION_INVALID",
+            Error::DestinationNotEmpty => "DESTINATION_NOT_EMPTY",
+            Error::PointInTimeNeedsSnapshot => "POINT_IN_TIME_NEEDS_SNAPSHOT",
+            Error::LoggingConfigInvalid(_) => "LOGGING_CONFIG_INVALID",
+            Error::UnknownService(_) => "UNKNOWN_SERVICE",
+        }
+    }
+
+    /// The gRPC status code it is reported with.
+    pub fn status_code(&self) -> Code {
+        match self {
+            Error::OperationNotFound(_)
+            | Error::DepartmentNotFound
+            | Error::MajorNotFound
+            | Error::ProfessorNotFound
+            | Error::NoAdvisor
+            | Error::CourseNotFound
+            | Error::NotEnrolled
+            | Error::EmailChangeNotPending(_)
+            | Error::EmailChangeExpired(_)
+            | Error::FlagNotFound(_)
+            | Error::TaskNotFound(_)
+            | Error::NothingInTrash(_)
+            | Error::UnknownService(_) => Code::NotFound,
+            Error::DepartmentExists
+            | Error::MajorExists
+            | Error::ProfessorExists
+            | Error::CourseExists
+            | Error::TransactionCommitted => Code::AlreadyExists,
+            Error::DepartmentHasMajors(_)
+            | Error::MajorDeclared(_)
+            | Error::ProfessorHasAdvisees(_)
+            | Error::CrossShardTransaction
+            | Error::TaskRunning(_)
+            | Error::DestinationNotEmpty => Code::FailedPrecondition,
+            Error::ChangesExpired(_) | Error::ResumeTokenExpired => Code::OutOfRange,
+            Error::QuotaExceeded(..) | Error::StoreFull(..) => Code::ResourceExhausted,
+            _ => Code::InvalidArgument,
+        }
+    }
+
+    /// The values in the message, as `ErrorInfo.metadata`.
+    pub fn metadata(&self) -> HashMap<String, String> {
+        let one = |key: &str, value: &str| HashMap::from([(key.to_string(), value.to_string())]);
+        match self {
+            Error::IdsRequired(first, second) => HashMap::from([
+                ("field".to_string(), first.to_string()),
+                ("other_field".to_string(), second.to_string()),
+            ]),
+            Error::FieldNotBulkUpdatable(path) => one("field", path),
+            Error::WrongOperationKind(id, expected) => HashMap::from([
+                ("operation_id".to_string(), id.clone()),
+                ("expected".to_string(), expected.to_string()),
+            ]),
+            Error::OperationNotFound(id) | Error::NothingInTrash(id) => one("operation_id", id),
+            Error::UnknownDepartment(id) => one("department_id", id),
+            Error::DepartmentHasMajors(id) => one("major_id", id),
+            Error::MajorNameTaken(name, id) => HashMap::from([
+                ("name".to_string(), name.clone()),
+                ("major_id".to_string(), id.clone()),
+            ]),
+            Error::MajorDeclared(students) | Error::ProfessorHasAdvisees(students) => {
+                one("students", &students.to_string())
+            }
+            Error::UnknownPrerequisite(id) => one("course_id", id),
+            Error::EmailChangeNotPending(id) | Error::EmailChangeExpired(id) => {
+                one("student_id", id)
+            }
+            Error::NewEmailInvalid(email) => one("email", email),
+            Error::EmailUnchanged(id, email) => HashMap::from([
+                ("student_id".to_string(), id.clone()),
+                ("email".to_string(), email.clone()),
+            ]),
+            Error::ChangesExpired(token) => one("resume_token", token),
+            Error::FlagNotFound(name) => one("flag", name),
+            Error::QuotaExceeded(tenant, limit) => HashMap::from([
+                ("tenant".to_string(), tenant.clone()),
+                ("limit".to_string(), limit.to_string()),
+            ]),
+            Error::StoreFull(used, limit) => HashMap::from([
+                ("used_bytes".to_string(), used.to_string()),
+                ("limit_bytes".to_string(), limit.to_string()),
+            ]),
+            Error::TaskNotFound(name) | Error::TaskRunning(name) => one("task", name),
+            Error::UnknownService(service) => one("service", service),
+            _ => HashMap::new(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::IdsRequired(first, second) => write!(f, "{} and {} are required", first, second),
+            Error::TimeRangeReversed => write!(f, "end_time cannot be before start_time"),
+            Error::UpdateMaskEmpty => write!(f, "update_mask cannot be empty"),
+            Error::FieldNotBulkUpdatable(path) => {
+                write!(f, "Field {} cannot be updated in bulk", path)
+            }
+            Error::FilterEmpty => write!(f, "filter must set at least one field"),
+            Error::WrongOperationKind(id, expected) => {
+                write!(f, "Operation {} is not a {}", id, expected)
+            }
+            Error::OperationNotFound(id) => write!(f, "Operation {} not found", id),
+            Error::DepartmentNotFound => write!(f, "Department not found"),
+            Error::DepartmentExists => write!(f, "Department with this ID already exists"),
+            Error::DepartmentNameEmpty => write!(f, "Department name cannot be empty"),
+            Error::UnknownDepartment(id) => write!(f, "Unknown department: {}", id),
+            Error::DepartmentHasMajors(id) => write!(f, "Department still has major {}", id),
+            Error::MajorNotFound => write!(f, "Major not found"),
+            Error::MajorExists => write!(f, "Major with this ID already exists"),
+            Error::MajorNameEmpty => write!(f, "Major name cannot be empty"),
+            Error::MajorNameTaken(name, id) => write!(f, "{:?} already names major {}", name, id),
+            Error::MajorDeclared(students) => {
+                write!(f, "{} students have declared this major", students)
+            }
+            Error::ProfessorNotFound => write!(f, "Professor not found"),
+            Error::ProfessorExists => write!(f, "Professor with this ID already exists"),
+            Error::ProfessorNameEmpty => write!(f, "Professor name cannot be empty"),
+            Error::ProfessorHasAdvisees(students) => write!(
+                f,
+                "Professor still advises {} students; reassign or unassign them",
+                students
+            ),
+            Error::ReassignToInvalid => {
+                write!(f, "reassign_to must name another existing professor")
+            }
+            Error::NoAdvisor => write!(f, "Student has no advisor"),
+            Error::CourseNotFound => write!(f, "Course not found"),
+            Error::CourseExists => write!(f, "Course with this ID already exists"),
+            Error::CourseTitleEmpty => write!(f, "Course title cannot be empty"),
+            Error::CapacityTooSmall => write!(f, "Capacity must be at least 1"),
+            Error::WaitlistCapacityNegative => write!(f, "Waitlist capacity cannot be negative"),
+            Error::CourseCreditsNegative => write!(f, "Credits cannot be negative"),
+            Error::UnknownPrerequisite(id) => write!(f, "Unknown prerequisite course: {}", id),
+            Error::NotEnrolled => write!(
+                f,
+                "Student is not enrolled in or waitlisted for this course"
+            ),
+            Error::GradeInvalid => write!(
+                f,
+                "Grade must be one of {}",
+                crate::enrollment::GRADES.join(", ")
+            ),
+            Error::MinScoreOutOfRange => write!(f, "min_score must be between 0.0 and 1.0"),
+            Error::MergeIntoItself => write!(f, "A student cannot be merged into itself"),
+            Error::EmailChangeNotPending(id) => {
+                write!(f, "No email change is waiting for {}", id)
+            }
+            Error::EmailChangeExpired(id) => write!(f, "The email change for {} has expired", id),
+            Error::VerificationCodeWrong => write!(f, "Wrong verification code"),
+            Error::NewEmailInvalid(email) => write!(f, "Not an email address: {}", email),
+            Error::EmailUnchanged(id, email) => {
+                write!(f, "{} already has the address {}", id, email)
+            }
+            Error::MinGpaOutOfRange => write!(f, "min_gpa must be between 0.0 and 4.0"),
+            Error::MinCreditsNegative => write!(f, "min_credits cannot be negative"),
+            Error::ChangesExpired(token) => write!(
+                f,
+                "Changes after {} are no longer kept; take a full snapshot",
+                token
+            ),
+            Error::ResumeTokenInvalid => write!(f, "Invalid resume token"),
+            Error::ResumeTokenExpired => write!(
+                f,
+                "Resume token is no longer available; re-read with ListStudents and watch again"
+            ),
+            Error::FlagNotFound(name) => write!(f, "No feature flag named {}", name),
+            Error::FlagClearNeedsTenant => {
+                write!(f, "Only a tenant's own setting can be cleared")
+            }
+            Error::TenantRequired => {
+                write!(f, "Name a tenant, in the request or its tenant metadata")
+            }
+            Error::QuotaExceeded(tenant, limit) => write!(
+                f,
+                "Tenant {} has reached its quota of {} students",
+                tenant, limit
+            ),
+            Error::StoreFull(used, limit) => write!(
+                f,
+                "Student store is full ({} of {} bytes used)",
+                used, limit
+            ),
+            Error::TransactionCommitted => write!(
+                f,
+                "A transaction with this idempotency key was already committed"
+            ),
+            Error::CrossShardTransaction => write!(
+                f,
+                "A transaction cannot write students on more than one shard"
+            ),
+            Error::TaskNotFound(name) => write!(f, "No scheduled task named {}", name),
+            Error::TaskRunning(name) => write!(f, "Task {} is already running", name),
+            Error::NothingInTrash(id) => write!(f, "Nothing in the trash for {}", id),
+            Error::DestinationInvalid(reason) | Error::LoggingConfigInvalid(reason) => {
+                write!(f, "{}", reason)
+            }
+            Error::DestinationNotEmpty => write!(
+                f,
+                "The destination already has students; copy into an empty store"
+            ),
+            Error::PointInTimeNeedsSnapshot => {
+                write!(f, "Only snapshots can be restored to a point in time")
+            }
+            Error::UnknownService(service) => write!(f, "Unknown service: {}", service),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The error as a status with an `ErrorInfo` in its details.
+impl From<Error> for Status {
+    fn from(error: Error) -> Self {
+        let code = error.status_code();
+        let message = error.to_string();
+        let info = ErrorInfo {
+            reason: error.code().to_string(),
+            domain: ERROR_DOMAIN.to_string(),
+            metadata: error.metadata(),
+        };
+        let details = proto::google::rpc::Status {
+            code: code as i32,
+            message: message.clone(),
+            details: vec![proto::Any {
+                type_url: "type.googleapis.com/google.rpc.ErrorInfo".to_string(),
+                value: info.encode_to_vec().into(),
+            }],
+        };
+        Status::with_details(code, message, details.encode_to_vec().into())
+    }
+}
@@ -0,0 +1,202 @@
+//! The stream of student changes behind `WatchStudents`.
+//!
+//! Every event gets a resume token, `<log>:<sequence>`, naming this log and
+//! the event's position in it. A watcher that reconnects with the token of the
+//! last event it saw gets the events it missed before the live ones, as long
+//! as they are still among the most recent [`HISTORY`] events.
+//!
+//! On shutdown, [`EventLog::shut_down`] sends every watcher a final
+//! `CHANGE_TYPE_SHUTTING_DOWN` event carrying the token to resume from and
+//! then ends their streams, so the server can finish its graceful shutdown.
+//!
+//! The same history backs incremental snapshots: [`EventLog::since`] gives
+//! the changes after a sequence number, with the time of each.
+
+use crate::errors::Error;
+use crate::{clock, memory};
+use proto::{ChangeType, Student, StudentEvent, Timestamp};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tokio::sync::broadcast;
+use tonic::Status;
+
+/// Events kept for resuming, and buffered per watcher before a slow watcher
+/// is dropped.
+pub const HISTORY: usize = 1024;
+
+/// An event as kept in the log: its place, and when it was published.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Change {
+    pub sequence: u64,
+    pub time: Timestamp,
+    pub event: StudentEvent,
+}
+
+#[derive(Debug)]
+struct Inner {
+    // Sequence number of the last event published; the first event is 1
+    last: u64,
+    history: VecDeque<Change>,
+    // Taken on shutdown, which ends every subscription
+    sender: Option<broadcast::Sender<StudentEvent>>,
+}
+
+/// Publishes student changes to watchers and keeps recent ones for resuming.
+#[derive(Debug)]
+pub struct EventLog {
+    // Distinguishes tokens from other servers and earlier runs
+    id: String,
+    inner: Mutex<Inner>,
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(HISTORY);
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        Self {
+            id: id[..8].to_string(),
+            inner: Mutex::new(Inner {
+                last: 0,
+                history: VecDeque::with_capacity(HISTORY),
+                sender: Some(sender),
+            }),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn token(&self, sequence: u64) -> String {
+        format!("{}:{}", self.id, sequence)
+    }
+
+    /// Names this log among those of other servers and earlier runs.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The sequence number of the last event published; 0 before the first.
+    pub fn last(&self) -> u64 {
+        self.lock().last
+    }
+
+    // The events after `sequence`, unless some have left the history
+    fn after(&self, inner: &Inner, sequence: u64) -> Option<Vec<Change>> {
+        // The first event still retained must directly follow `sequence`
+        let oldest = inner
+            .history
+            .front()
+            .map_or(inner.last + 1, |change| change.sequence);
+        if sequence > inner.last || sequence + 1 < oldest {
+            return None;
+        }
+        Some(
+            inner
+                .history
+                .iter()
+                .filter(|change| change.sequence > sequence)
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// Every change after `sequence`, oldest first; `OUT_OF_RANGE` once the
+    /// history no longer reaches back that far.
+    pub fn since(&self, sequence: u64) -> Result<Vec<Change>, Status> {
+        let inner = self.lock();
+        self.after(&inner, sequence)
+            .ok_or_else(|| Error::ChangesExpired(self.token(sequence)).into())
+    }
+
+    /// Record a change and send it to current watchers; having none is not an error.
+    pub fn publish(&self, change_type: ChangeType, student: &Student) {
+        let mut inner = self.lock();
+        inner.last += 1;
+        let event = StudentEvent {
+            change_type: change_type as i32,
+            student: Some(student.clone()),
+            resume_token: self.token(inner.last),
+        };
+
+        if inner.history.len() == HISTORY {
+            inner.history.pop_front();
+        }
+        let sequence = inner.last;
+        inner.history.push_back(Change {
+            sequence,
+            time: clock::timestamp(SystemTime::now()),
+            event: event.clone(),
+        });
+        if let Some(sender) = &inner.sender {
+            let _ = sender.send(event);
+        }
+    }
+
+    /// Estimated bytes held by the events kept for resuming.
+    pub fn history_size(&self) -> usize {
+        let inner = self.lock();
+        // The history's room is allocated up front
+        let spare = inner.history.capacity() - inner.history.len();
+        spare * std::mem::size_of::<Change>()
+            + inner
+                .history
+                .iter()
+                .map(|change| memory::event_size(&change.event))
+                .sum::<usize>()
+    }
+
+    /// Events after `resume_token` (none if it is empty), then a receiver for
+    /// everything published from now on.
+    pub fn subscribe(
+        &self,
+        resume_token: &str,
+    ) -> Result<(Vec<StudentEvent>, broadcast::Receiver<StudentEvent>), Status> {
+        let inner = self.lock();
+        let receiver = match &inner.sender {
+            Some(sender) => sender.subscribe(),
+            None => return Err(Status::unavailable("Server is shutting down")),
+        };
+        if resume_token.is_empty() {
+            return Ok((Vec::new(), receiver));
+        }
+
+        let (log, sequence) = resume_token
+            .split_once(':')
+            .and_then(|(log, sequence)| Some((log, sequence.parse::<u64>().ok()?)))
+            .ok_or(Error::ResumeTokenInvalid)?;
+        let missed = self
+            .after(&inner, sequence)
+            .filter(|_| log == self.id)
+            .ok_or(Error::ResumeTokenExpired)?
+            .into_iter()
+            .map(|change| change.event)
+            .collect();
+        Ok((missed, receiver))
+    }
+
+    /// Tell every watcher the server is going away and end their streams.
+    ///
+    /// Each watcher gets a `CHANGE_TYPE_SHUTTING_DOWN` event whose resume
+    /// token is that of the last event published. Later subscriptions fail
+    /// with `UNAVAILABLE`.
+    pub fn shut_down(&self) {
+        let mut inner = self.lock();
+        let event = StudentEvent {
+            change_type: ChangeType::ShuttingDown as i32,
+            student: None,
+            resume_token: self.token(inner.last),
+        };
+        if let Some(sender) = inner.sender.take() {
+            let _ = sender.send(event);
+        }
+    }
+}
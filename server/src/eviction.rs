@@ -0,0 +1,300 @@
+//! Forgetting students, for public demo servers that anyone can write to.
+//!
+//! [`EvictingRepository`] wraps the store and deletes students that have
+//! not been written for longer than a time to live, and, once the store
+//! holds the most students allowed, the least recently used one for each
+//! new one. Expired students are swept out at the start of every call, so
+//! nobody reads one after its time is up.
+//!
+//! Evictions are deletes through the wrapped store, so the statistics follow
+//! them, and watchers see each as a `CHANGE_TYPE_DELETED` event.
+
+use crate::clock::{Clock, SystemClock};
+use crate::events::EventLog;
+use crate::repository::{Remembered, StudentRepository, Transaction, TransactionFailure, Write};
+use proto::{ChangeType, ListStudentsResponse, Student};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+use tonic::{Code, Status};
+
+/// When students are forgotten.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Eviction {
+    /// How long after its last create or update a student is deleted
+    pub ttl: Option<Duration>,
+    /// Most students kept; beyond this the least recently read or written
+    /// one goes
+    pub max_students: Option<usize>,
+}
+
+impl Eviction {
+    /// Whether this evicts anything at all.
+    pub fn is_enabled(&self) -> bool {
+        self.ttl.is_some() || self.max_students.is_some()
+    }
+}
+
+// When each student was last used and last written, indexed both ways
+#[derive(Debug, Default)]
+struct Recency {
+    next_use: u64,
+    // ID -> (last use, last write)
+    students: HashMap<String, (u64, SystemTime)>,
+    by_use: BTreeMap<u64, String>,
+    by_write: BTreeSet<(SystemTime, String)>,
+}
+
+impl Recency {
+    // A read, or a write if `written`, of `id` at `now`
+    fn touch(&mut self, id: &str, now: SystemTime, written: bool) {
+        self.next_use += 1;
+        let used = self.next_use;
+        let last_write = match self.students.get(id) {
+            Some(&(last_use, last_write)) => {
+                self.by_use.remove(&last_use);
+                if written {
+                    self.by_write.remove(&(last_write, id.to_string()));
+                }
+                (!written).then_some(last_write)
+            }
+            // Not seen before, e.g. stored before the server started
+            None => None,
+        };
+        let last_write = last_write.unwrap_or_else(|| {
+            self.by_write.insert((now, id.to_string()));
+            now
+        });
+        self.by_use.insert(used, id.to_string());
+        self.students.insert(id.to_string(), (used, last_write));
+    }
+
+    fn forget(&mut self, id: &str) {
+        if let Some((last_use, last_write)) = self.students.remove(id) {
+            self.by_use.remove(&last_use);
+            self.by_write.remove(&(last_write, id.to_string()));
+        }
+    }
+
+    // Students last written before `before`
+    fn written_before(&self, before: SystemTime) -> Vec<String> {
+        self.by_write
+            .iter()
+            .take_while(|(written, _)| *written < before)
+            .map(|(_, id)| id.clone())
+            .collect()
+    }
+
+    fn least_recently_used(&self) -> Option<String> {
+        self.by_use.values().next().cloned()
+    }
+}
+
+/// Wraps a repository and deletes students under an [`Eviction`] policy.
+#[derive(Debug)]
+pub struct EvictingRepository {
+    inner: Arc<dyn StudentRepository>,
+    eviction: Eviction,
+    recency: Mutex<Recency>,
+    clock: Arc<dyn Clock>,
+    events: Option<Arc<EventLog>>,
+}
+
+impl EvictingRepository {
+    /// Evict students from `inner` as `eviction` says.
+    pub fn new(inner: Arc<dyn StudentRepository>, eviction: Eviction) -> Self {
+        Self {
+            inner,
+            eviction,
+            recency: Mutex::default(),
+            clock: Arc::new(SystemClock),
+            events: None,
+        }
+    }
+
+    /// Use `clock` to tell when students expire.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Tell watchers in `events` about each student evicted.
+    pub fn with_events(mut self, events: Arc<EventLog>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Start the clock on the students already stored, once, before serving.
+    pub async fn track_existing(&self) -> Result<(), Status> {
+        let now = self.clock.now();
+        let students = self.inner.all().await?;
+        let mut recency = self.recency();
+        for student in students {
+            recency.touch(&student.id, now, true);
+        }
+        Ok(())
+    }
+
+    fn recency(&self) -> MutexGuard<'_, Recency> {
+        self.recency.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    async fn evict(&self, id: &str, why: &str) -> Result<(), Status> {
+        self.recency().forget(id);
+        match self.inner.delete(id).await {
+            Ok(student) => {
+                info!("🍂 Evicted student {} ({})", student.id, why);
+                if let Some(events) = &self.events {
+                    events.publish(ChangeType::Deleted, &student);
+                }
+                Ok(())
+            }
+            // Deleted meanwhile
+            Err(status) if status.code() == Code::NotFound => Ok(()),
+            Err(status) => Err(status),
+        }
+    }
+
+    // Delete every student whose time to live is up
+    async fn sweep(&self) -> Result<(), Status> {
+        let Some(ttl) = self.eviction.ttl else {
+            return Ok(());
+        };
+        let Some(before) = self.clock.now().checked_sub(ttl) else {
+            return Ok(());
+        };
+        let expired = self.recency().written_before(before);
+        for id in expired {
+            self.evict(&id, "expired").await?;
+        }
+        Ok(())
+    }
+
+    // Delete the least recently used students until there are at most the
+    // most allowed
+    async fn trim(&self) -> Result<(), Status> {
+        let Some(max) = self.eviction.max_students else {
+            return Ok(());
+        };
+        loop {
+            let oldest = {
+                let recency = self.recency();
+                if recency.students.len() <= max {
+                    return Ok(());
+                }
+                recency.least_recently_used()
+            };
+            match oldest {
+                Some(id) => self.evict(&id, "least recently used").await?,
+                None => return Ok(()),
+            }
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl StudentRepository for EvictingRepository {
+    async fn create(&self, student: Student) -> Result<Student, Status> {
+        self.sweep().await?;
+        let created = self.inner.create(student).await?;
+        self.recency().touch(&created.id, self.clock.now(), true);
+        self.trim().await?;
+        Ok(created)
+    }
+
+    async fn get(&self, id: &str) -> Result<Student, Status> {
+        self.sweep().await?;
+        let student = self.inner.get(id).await?;
+        self.recency().touch(id, self.clock.now(), false);
+        Ok(student)
+    }
+
+    async fn update(&self, student: Student) -> Result<Student, Status> {
+        self.sweep().await?;
+        let updated = self.inner.update(student).await?;
+        self.recency().touch(&updated.id, self.clock.now(), true);
+        Ok(updated)
+    }
+
+    async fn delete(&self, id: &str) -> Result<Student, Status> {
+        self.sweep().await?;
+        let deleted = self.inner.delete(id).await?;
+        self.recency().forget(id);
+        Ok(deleted)
+    }
+
+    // Listing does not count as using the students listed
+    async fn list(
+        &self,
+        page_size: usize,
+        page_token: &str,
+    ) -> Result<ListStudentsResponse, Status> {
+        self.sweep().await?;
+        self.inner.list(page_size, page_token).await
+    }
+
+    async fn create_many(&self, students: Vec<Student>) -> Vec<Result<Student, Status>> {
+        if let Err(status) = self.sweep().await {
+            return students.iter().map(|_| Err(status.clone())).collect();
+        }
+        let results = self.inner.create_many(students).await;
+        {
+            let now = self.clock.now();
+            let mut recency = self.recency();
+            for created in results.iter().flatten() {
+                recency.touch(&created.id, now, true);
+            }
+        }
+        // The students were created either way; a failed eviction is only
+        // logged and retried on the next create
+        if let Err(status) = self.trim().await {
+            warn!("⚠️  Failed to evict students: {}", status.message());
+        }
+        results
+    }
+
+    async fn get_shared(&self, id: &str) -> Result<Arc<Student>, Status> {
+        self.sweep().await?;
+        let student = self.inner.get_shared(id).await?;
+        self.recency().touch(id, self.clock.now(), false);
+        Ok(student)
+    }
+
+    async fn all(&self) -> Result<Vec<Arc<Student>>, Status> {
+        self.sweep().await?;
+        self.inner.all().await
+    }
+
+    async fn commit(&self, transaction: Transaction) -> Result<Vec<Student>, TransactionFailure> {
+        self.sweep().await.map_err(|status| TransactionFailure {
+            index: None,
+            status,
+        })?;
+        let deleted: Vec<bool> = transaction
+            .writes()
+            .iter()
+            .map(|write| matches!(write, Write::Delete(_)))
+            .collect();
+        let written = self.inner.commit(transaction).await?;
+        {
+            let now = self.clock.now();
+            let mut recency = self.recency();
+            for (student, deleted) in written.iter().zip(deleted) {
+                if deleted {
+                    recency.forget(&student.id);
+                } else {
+                    recency.touch(&student.id, now, true);
+                }
+            }
+        }
+        // Committed either way, as in `create_many`
+        if let Err(status) = self.trim().await {
+            warn!("⚠️  Failed to evict students: {}", status.message());
+        }
+        Ok(written)
+    }
+
+    async fn remembered(&self, key: &str) -> Result<Option<Remembered>, Status> {
+        self.inner.remembered(key).await
+    }
+}
@@ -0,0 +1,352 @@
+//! Exporting every student as one Parquet or Arrow IPC file, for loading
+//! into analytics tools such as DuckDB and Spark.
+//!
+//! A student is a row, with the address as a struct, phone numbers as a
+//! list, and annotations as a map. The GPA is the exact `decimal(3, 2)`
+//! and the standing the name of its enum value; the etag is left out. The
+//! store is read a page at a time and the file encoded as it goes, so
+//! neither is held in memory whole.
+//!
+//! Built with the `analytics` feature.
+
+use crate::gpa;
+use crate::repository::{StudentRepository, SCAN_PAGE_SIZE};
+use arrow_array::builder::{ListBuilder, MapBuilder, StringBuilder, StructBuilder};
+use arrow_array::{
+    ArrayRef, Decimal128Array, Int32Array, RecordBatch, StringArray, TimestampMicrosecondArray,
+};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{DataType, Field, Fields, SchemaRef};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use proto::{ExportChunk, ExportFormat, Student, Timestamp};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::Status;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+// Rows per Parquet row group; the writer holds one group at a time
+const ROW_GROUP_SIZE: usize = 64 * 1024;
+
+/// The MIME type of files in `format`.
+pub fn content_type(format: ExportFormat) -> &'static str {
+    match format {
+        ExportFormat::Parquet | ExportFormat::Unspecified => "application/vnd.apache.parquet",
+        ExportFormat::Arrow => "application/vnd.apache.arrow.file",
+    }
+}
+
+/// The format of a file named `path`: Parquet for `.parquet`, Arrow for
+/// `.arrow`, `.feather`, or `.ipc`.
+pub fn format_for(path: &Path) -> Result<ExportFormat, String> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("parquet") => Ok(ExportFormat::Parquet),
+        Some("arrow" | "feather" | "ipc") => Ok(ExportFormat::Arrow),
+        _ => Err(format!(
+            "{}: expected a .parquet or .arrow file",
+            path.display()
+        )),
+    }
+}
+
+fn micros(time: &Timestamp) -> i64 {
+    time.seconds * 1_000_000 + i64::from(time.nanos / 1_000)
+}
+
+/// `students` as a batch of rows.
+pub fn record_batch(students: &[Student]) -> RecordBatch {
+    let strings = |field: fn(&Student) -> &str| -> ArrayRef {
+        Arc::new(StringArray::from_iter_values(students.iter().map(field)))
+    };
+    let numbers = |field: fn(&Student) -> i32| -> ArrayRef {
+        Arc::new(Int32Array::from_iter_values(students.iter().map(field)))
+    };
+    let times = |field: fn(&Student) -> Option<&Timestamp>| -> ArrayRef {
+        let times = students.iter().map(|student| field(student).map(micros));
+        Arc::new(TimestampMicrosecondArray::from_iter(times).with_timezone("UTC"))
+    };
+
+    let gpas = students
+        .iter()
+        .map(|student| i128::from(gpa::of(student).hundredths()));
+    let gpas = Decimal128Array::from_iter_values(gpas)
+        .with_precision_and_scale(3, 2)
+        .expect("3 digits, 2 after the point, is a valid decimal type");
+
+    let mut phone_numbers = ListBuilder::new(StringBuilder::new());
+    let address_fields: Fields = ["line1", "line2", "city", "region", "postal_code", "country"]
+        .into_iter()
+        .map(|name| Field::new(name, DataType::Utf8, false))
+        .collect();
+    let mut addresses = StructBuilder::from_fields(address_fields, students.len());
+    let mut annotations = MapBuilder::new(None, StringBuilder::new(), StringBuilder::new());
+    for student in students {
+        for number in &student.phone_numbers {
+            phone_numbers.values().append_value(number);
+        }
+        phone_numbers.append(true);
+
+        let address = student.address.clone().unwrap_or_default();
+        let lines = [
+            address.line1,
+            address.line2,
+            address.city,
+            address.region,
+            address.postal_code,
+            address.country,
+        ];
+        for (index, line) in lines.iter().enumerate() {
+            addresses
+                .field_builder::<StringBuilder>(index)
+                .expect("every address field is a string")
+                .append_value(line);
+        }
+        addresses.append(student.address.is_some());
+
+        let mut sorted: Vec<_> = student.annotations.iter().collect();
+        sorted.sort();
+        for (key, value) in sorted {
+            annotations.keys().append_value(key);
+            annotations.values().append_value(value);
+        }
+        annotations
+            .append(true)
+            .expect("as many keys as values were appended");
+    }
+
+    RecordBatch::try_from_iter_with_nullable([
+        ("id", strings(|student| &student.id), false),
+        ("name", strings(|student| &student.name), false),
+        (
+            "preferred_name",
+            strings(|student| &student.preferred_name),
+            false,
+        ),
+        ("email", strings(|student| &student.email), false),
+        ("age", numbers(|student| student.age), false),
+        ("major", strings(|student| &student.major), false),
+        ("major_id", strings(|student| &student.major_id), false),
+        ("gpa", Arc::new(gpas) as ArrayRef, false),
+        ("credits", numbers(|student| student.credits), false),
+        (
+            "credits_attempted",
+            numbers(|student| student.credits_attempted),
+            false,
+        ),
+        (
+            "credits_earned",
+            numbers(|student| student.credits_earned),
+            false,
+        ),
+        (
+            "standing",
+            strings(|student| student.standing().as_str_name()),
+            false,
+        ),
+        ("phone_numbers", Arc::new(phone_numbers.finish()), false),
+        ("address", Arc::new(addresses.finish()), true),
+        ("annotations", Arc::new(annotations.finish()), false),
+        (
+            "create_time",
+            times(|student| student.create_time.as_ref()),
+            true,
+        ),
+        (
+            "update_time",
+            times(|student| student.update_time.as_ref()),
+            true,
+        ),
+    ])
+    .expect("the columns are as long as each other")
+}
+
+/// The columns of an export.
+pub fn schema() -> SchemaRef {
+    record_batch(&[]).schema()
+}
+
+// What the writer has written, shared so it can be taken as it grows
+#[derive(Debug, Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Buffer {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+enum Writer {
+    Parquet(ArrowWriter<Buffer>),
+    Arrow(FileWriter<Buffer>),
+}
+
+fn encoding_failed(error: impl std::fmt::Display) -> Status {
+    Status::internal(format!("Cannot encode the export: {}", error))
+}
+
+/// Encodes students into a file a batch at a time, handing back the bytes
+/// of the file as they are ready.
+pub struct Encoder {
+    writer: Writer,
+    buffer: Buffer,
+}
+
+impl Encoder {
+    pub fn new(format: ExportFormat) -> Result<Self, Status> {
+        let buffer = Buffer::default();
+        let writer = match format {
+            ExportFormat::Parquet | ExportFormat::Unspecified => {
+                let properties = WriterProperties::builder()
+                    .set_compression(Compression::SNAPPY)
+                    .set_max_row_group_size(ROW_GROUP_SIZE)
+                    .build();
+                let writer = ArrowWriter::try_new(buffer.clone(), schema(), Some(properties))
+                    .map_err(encoding_failed)?;
+                Writer::Parquet(writer)
+            }
+            ExportFormat::Arrow => Writer::Arrow(
+                FileWriter::try_new(buffer.clone(), &schema()).map_err(encoding_failed)?,
+            ),
+        };
+        Ok(Self { writer, buffer })
+    }
+
+    /// Encode `students`, returning the bytes of the file written since
+    /// the last call; possibly none, as Parquet writes whole row groups.
+    pub fn encode(&mut self, students: &[Student]) -> Result<Vec<u8>, Status> {
+        let batch = record_batch(students);
+        match &mut self.writer {
+            Writer::Parquet(writer) => writer.write(&batch).map_err(encoding_failed)?,
+            Writer::Arrow(writer) => writer.write(&batch).map_err(encoding_failed)?,
+        }
+        Ok(self.buffer.take())
+    }
+
+    /// The rest of the file.
+    pub fn finish(self) -> Result<Vec<u8>, Status> {
+        match self.writer {
+            Writer::Parquet(writer) => drop(writer.close().map_err(encoding_failed)?),
+            Writer::Arrow(mut writer) => writer.finish().map_err(encoding_failed)?,
+        }
+        Ok(self.buffer.take())
+    }
+}
+
+// A piece of the file, and how many students it adds
+struct Part {
+    students: usize,
+    data: Vec<u8>,
+}
+
+// Encode every student in `store` on a task of its own; the parts
+// received, in order, make up the file. Dropping the receiver stops it.
+fn parts(
+    store: Arc<dyn StudentRepository>,
+    format: ExportFormat,
+) -> mpsc::Receiver<Result<Part, Status>> {
+    let (tx, rx) = mpsc::channel(4);
+    tokio::spawn(async move {
+        if let Err(status) = encode(store.as_ref(), format, &tx).await {
+            let _ = tx.send(Err(status)).await;
+        }
+    });
+    rx
+}
+
+async fn encode(
+    store: &dyn StudentRepository,
+    format: ExportFormat,
+    tx: &mpsc::Sender<Result<Part, Status>>,
+) -> Result<(), Status> {
+    let mut encoder = Encoder::new(format)?;
+    let mut page_token = String::new();
+    loop {
+        let page = store.list(SCAN_PAGE_SIZE, &page_token).await?;
+        let part = Part {
+            students: page.students.len(),
+            data: encoder.encode(&page.students)?,
+        };
+        if tx.send(Ok(part)).await.is_err() {
+            info!("Export cancelled");
+            return Ok(());
+        }
+        if page.next_page_token.is_empty() {
+            break;
+        }
+        page_token = page.next_page_token;
+    }
+    let data = encoder.finish()?;
+    let _ = tx.send(Ok(Part { students: 0, data })).await;
+    Ok(())
+}
+
+/// Every student in `store` as a file in `format`, in chunks.
+pub fn stream(
+    store: Arc<dyn StudentRepository>,
+    format: ExportFormat,
+) -> ReceiverStream<Result<ExportChunk, Status>> {
+    let mut parts = parts(store, format);
+    let (tx, rx) = mpsc::channel(4);
+    tokio::spawn(async move {
+        let mut content_type = content_type(format);
+        while let Some(part) = parts.recv().await {
+            let part = match part {
+                Ok(part) => part,
+                Err(status) => {
+                    let _ = tx.send(Err(status)).await;
+                    return;
+                }
+            };
+            for data in part.data.chunks(CHUNK_SIZE) {
+                let chunk = ExportChunk {
+                    data: data.to_vec(),
+                    content_type: std::mem::take(&mut content_type).to_string(),
+                };
+                if tx.send(Ok(chunk)).await.is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    ReceiverStream::new(rx)
+}
+
+/// Write every student in `store` to a file at `path` in `format`,
+/// returning how many there were. The file is written under a temporary
+/// name and renamed once complete.
+pub async fn write(
+    store: Arc<dyn StudentRepository>,
+    format: ExportFormat,
+    path: &Path,
+) -> Result<usize, Status> {
+    let cannot_write =
+        |e: std::io::Error| Status::failed_precondition(format!("{}: {}", path.display(), e));
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let mut file = std::fs::File::create(&partial).map_err(cannot_write)?;
+    let mut parts = parts(store, format);
+    let mut count = 0;
+    while let Some(part) = parts.recv().await {
+        let part = part?;
+        file.write_all(&part.data).map_err(cannot_write)?;
+        count += part.students;
+    }
+    file.sync_all().map_err(cannot_write)?;
+    std::fs::rename(&partial, path).map_err(cannot_write)?;
+    Ok(count)
+}
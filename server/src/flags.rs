@@ -0,0 +1,263 @@
+//! Feature flags, for rolling a behavior out tenant by tenant.
+//!
+//! Each flag the server knows is on or off for everyone, and can be set
+//! differently for single tenants. [`TenantLayer`] reads the `tenant`
+//! metadata of each request, and [`FeatureFlags::enabled`] answers for the
+//! tenant of the request being handled. Flags start as `--feature-flag`
+//! says, change through `FeatureFlagService`, and are not kept across
+//! restarts.
+
+use crate::errors::Error;
+use proto::feature_flag_service_server::FeatureFlagService;
+use proto::{
+    FeatureFlag, ListFeatureFlagsRequest, ListFeatureFlagsResponse, SetFeatureFlagRequest,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+use tonic::codegen::http::Request as HttpRequest;
+use tonic::codegen::Service;
+use tonic::{Request, Response, Status};
+use tower_layer::Layer;
+
+/// The metadata naming the tenant a request is from.
+pub const TENANT: &str = "tenant";
+
+/// Validate StudentService writes with the strict profile, whatever
+/// `--validation` says.
+pub const STRICT_VALIDATION: &str = "strict-validation";
+
+// Every flag the server has, with what turning it on does
+const FLAGS: &[(&str, &str)] = &[(
+    STRICT_VALIDATION,
+    "Validate StudentService writes with the strict profile",
+)];
+
+tokio::task_local! {
+    static CURRENT_TENANT: Option<String>;
+}
+
+/// The tenant of the request being handled, if it named one.
+pub fn current_tenant() -> Option<String> {
+    CURRENT_TENANT.try_with(Clone::clone).ok().flatten()
+}
+
+/// Run `call` as if it were a request from `tenant`, e.g. in tests.
+pub async fn scope<F: std::future::Future>(tenant: Option<String>, call: F) -> F::Output {
+    CURRENT_TENANT.scope(tenant, call).await
+}
+
+#[derive(Debug, Clone, Default)]
+struct Setting {
+    enabled: bool,
+    tenants: HashMap<String, bool>,
+}
+
+/// The flags and how they are set, shared by every service that checks them.
+#[derive(Debug, Clone)]
+pub struct FeatureFlags {
+    settings: Arc<RwLock<BTreeMap<&'static str, Setting>>>,
+}
+
+impl FeatureFlags {
+    /// Every flag off.
+    pub fn new() -> Self {
+        let settings = FLAGS
+            .iter()
+            .map(|(name, _)| (*name, Setting::default()))
+            .collect();
+        Self {
+            settings: Arc::new(RwLock::new(settings)),
+        }
+    }
+
+    /// Whether `name` is on for the tenant of the request being handled.
+    pub fn enabled(&self, name: &str) -> bool {
+        self.enabled_for(name, current_tenant().as_deref())
+    }
+
+    /// Whether `name` is on for `tenant`, or for requests without one.
+    pub fn enabled_for(&self, name: &str, tenant: Option<&str>) -> bool {
+        let settings = self.settings.read().unwrap_or_else(|e| e.into_inner());
+        settings.get(name).is_some_and(|setting| {
+            tenant
+                .and_then(|tenant| setting.tenants.get(tenant))
+                .copied()
+                .unwrap_or(setting.enabled)
+        })
+    }
+
+    /// Turn `name` on or off for `tenant`, or for everyone without a
+    /// setting of their own.
+    pub fn set(&self, name: &str, tenant: Option<&str>, enabled: bool) -> Result<(), Status> {
+        let mut settings = self.settings.write().unwrap_or_else(|e| e.into_inner());
+        let setting = settings.get_mut(name).ok_or_else(|| not_found(name))?;
+        match tenant {
+            Some(tenant) => {
+                setting.tenants.insert(tenant.to_string(), enabled);
+            }
+            None => setting.enabled = enabled,
+        }
+        Ok(())
+    }
+
+    /// Remove `tenant`'s own setting of `name`.
+    pub fn clear(&self, name: &str, tenant: &str) -> Result<(), Status> {
+        let mut settings = self.settings.write().unwrap_or_else(|e| e.into_inner());
+        let setting = settings.get_mut(name).ok_or_else(|| not_found(name))?;
+        setting.tenants.remove(tenant);
+        Ok(())
+    }
+
+    /// Every flag with its settings, ordered by name.
+    pub fn list(&self) -> Vec<FeatureFlag> {
+        let settings = self.settings.read().unwrap_or_else(|e| e.into_inner());
+        FLAGS
+            .iter()
+            .map(|(name, description)| {
+                let setting = &settings[name];
+                FeatureFlag {
+                    name: name.to_string(),
+                    description: description.to_string(),
+                    enabled: setting.enabled,
+                    tenants: setting.tenants.clone(),
+                }
+            })
+            .collect()
+    }
+
+    fn get(&self, name: &str) -> Result<FeatureFlag, Status> {
+        self.list()
+            .into_iter()
+            .find(|flag| flag.name == name)
+            .ok_or_else(|| not_found(name))
+    }
+}
+
+impl Default for FeatureFlags {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn not_found(name: &str) -> Status {
+    Error::FlagNotFound(name.to_string()).into()
+}
+
+/// A flag setting from its command-line form, `NAME=on` or
+/// `NAME@TENANT=off`: the name, the tenant if any, and whether it is on.
+pub fn parse_setting(text: &str) -> Result<(String, Option<String>, bool), String> {
+    let (flag, value) = text
+        .split_once('=')
+        .ok_or_else(|| format!("{}: expected NAME[@TENANT]=on|off", text))?;
+    let enabled = match value.trim() {
+        "on" | "true" => true,
+        "off" | "false" => false,
+        _ => return Err(format!("{}: expected on or off after '='", text)),
+    };
+    let (name, tenant) = match flag.split_once('@') {
+        Some((name, tenant)) => (name, Some(tenant.trim().to_string())),
+        None => (flag, None),
+    };
+    let name = name.trim();
+    if !FLAGS.iter().any(|(known, _)| *known == name) {
+        let known: Vec<_> = FLAGS.iter().map(|(known, _)| *known).collect();
+        return Err(format!(
+            "No feature flag named {}; there are {}",
+            name,
+            known.join(", ")
+        ));
+    }
+    Ok((name.to_string(), tenant, enabled))
+}
+
+/// Handles every request as one from the tenant its `tenant` metadata names.
+#[derive(Debug, Clone, Default)]
+pub struct TenantLayer;
+
+impl<S> Layer<S> for TenantLayer {
+    type Service = Tenanted<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Tenanted { inner }
+    }
+}
+
+/// The service produced by [`TenantLayer`].
+#[derive(Debug, Clone)]
+pub struct Tenanted<S> {
+    inner: S,
+}
+
+impl<S, ReqBody> Service<HttpRequest<ReqBody>> for Tenanted<S>
+where
+    S: Service<HttpRequest<ReqBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = tokio::task::futures::TaskLocalFuture<Option<String>, S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: HttpRequest<ReqBody>) -> Self::Future {
+        let tenant = request_tenant(&request);
+        CURRENT_TENANT.scope(tenant, self.inner.call(request))
+    }
+}
+
+/// The tenant `request`'s `tenant` metadata names, if any.
+pub fn request_tenant<B>(request: &HttpRequest<B>) -> Option<String> {
+    request
+        .headers()
+        .get(TENANT)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|tenant| !tenant.is_empty())
+        .map(str::to_string)
+}
+
+#[derive(Debug)]
+pub struct FeatureFlagServiceImpl {
+    flags: FeatureFlags,
+}
+
+impl FeatureFlagServiceImpl {
+    pub fn new(flags: FeatureFlags) -> Self {
+        Self { flags }
+    }
+}
+
+#[tonic::async_trait]
+impl FeatureFlagService for FeatureFlagServiceImpl {
+    async fn list_feature_flags(
+        &self,
+        _request: Request<ListFeatureFlagsRequest>,
+    ) -> Result<Response<ListFeatureFlagsResponse>, Status> {
+        Ok(Response::new(ListFeatureFlagsResponse {
+            flags: self.flags.list(),
+        }))
+    }
+
+    async fn set_feature_flag(
+        &self,
+        request: Request<SetFeatureFlagRequest>,
+    ) -> Result<Response<FeatureFlag>, Status> {
+        let request = request.into_inner();
+        let tenant = Some(request.tenant.trim()).filter(|tenant| !tenant.is_empty());
+        let whom = tenant.map_or("everyone".to_string(), |tenant| {
+            format!("tenant {}", tenant)
+        });
+        if request.clear {
+            let tenant = tenant.ok_or(Error::FlagClearNeedsTenant)?;
+            self.flags.clear(&request.name, tenant)?;
+            info!("🚩 Cleared {} for {}", request.name, whom);
+        } else {
+            self.flags.set(&request.name, tenant, request.enabled)?;
+            let state = if request.enabled { "on" } else { "off" };
+            info!("🚩 Turned {} {} for {}", request.name, state, whom);
+        }
+        Ok(Response::new(self.flags.get(&request.name)?))
+    }
+}
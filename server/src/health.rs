@@ -0,0 +1,310 @@
+//! Liveness and readiness.
+//!
+//! A server is *live* while the process answers at all; a live server that
+//! is not *ready* should be left running but sent no traffic. [`Health`]
+//! decides readiness from its checks, all of which must pass:
+//!
+//! - `storage`: the store answers a one-student read ([`StorageCheck`]);
+//! - `maintenance`: the `--maintenance-file` does not exist
+//!   ([`MaintenanceFile`]), so an operator can take an instance out of
+//!   rotation by creating it;
+//! - `shutdown`: the server has not started shutting down.
+//!
+//! Other parts of the server add their own with [`Health::with_check`]. Both
+//! answers are served by [`HealthServiceImpl`] as `grpc.health.v1.Health`,
+//! where the service `liveness` is liveness and `""` or any served service
+//! is readiness, and over HTTP by [`serve_http`] as `/livez` and `/readyz`,
+//! along with the server's [`Metrics`] as `/metrics` (in OpenMetrics when
+//! the scraper asks for it) and its open connections ([`Channelz`]) as
+//! `/debug/connections`.
+
+use crate::channelz::Channelz;
+use crate::errors::Error;
+use crate::metrics::{self, Metrics};
+use crate::repository::StudentRepository;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use proto::grpc::health::v1::health_check_response::ServingStatus;
+use proto::grpc::health::v1::health_server::Health as HealthService;
+use proto::grpc::health::v1::{HealthCheckRequest, HealthCheckResponse};
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::fmt::Debug;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+use tonic::Status;
+
+/// The start of the path of every `grpc.health.v1.Health` call.
+pub const SERVICE_PATH: &str = "/grpc.health.v1.Health/";
+
+/// The `grpc.health.v1` service name that asks after liveness.
+pub const LIVENESS: &str = "liveness";
+
+// How often `Watch` looks again
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// One condition for readiness.
+#[tonic::async_trait]
+pub trait ReadinessCheck: Debug + Send + Sync {
+    /// Why the server is not ready, if this condition is not met.
+    async fn check(&self) -> Result<(), String>;
+}
+
+/// Ready while the store answers.
+#[derive(Debug)]
+pub struct StorageCheck(pub Arc<dyn StudentRepository>);
+
+#[tonic::async_trait]
+impl ReadinessCheck for StorageCheck {
+    async fn check(&self) -> Result<(), String> {
+        match self.0.list(1, "").await {
+            Ok(_) => Ok(()),
+            Err(status) => Err(status.message().to_string()),
+        }
+    }
+}
+
+/// Not ready while the file exists.
+#[derive(Debug)]
+pub struct MaintenanceFile(pub PathBuf);
+
+#[tonic::async_trait]
+impl ReadinessCheck for MaintenanceFile {
+    async fn check(&self) -> Result<(), String> {
+        if self.0.exists() {
+            return Err(format!("{} exists", self.0.display()));
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of every readiness check, in the order they were added.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Readiness {
+    pub checks: Vec<(String, Result<(), String>)>,
+}
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        self.checks.iter().all(|(_, result)| result.is_ok())
+    }
+}
+
+/// Whether the server is ready.
+#[derive(Debug, Clone, Default)]
+pub struct Health {
+    checks: Vec<(String, Arc<dyn ReadinessCheck>)>,
+    shutting_down: Arc<AtomicBool>,
+}
+
+impl Health {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Be ready only while `check` passes, reported as `name`.
+    pub fn with_check(mut self, name: &str, check: Arc<dyn ReadinessCheck>) -> Self {
+        self.checks.push((name.to_string(), check));
+        self
+    }
+
+    /// Stop being ready for good, as the server starts shutting down.
+    pub fn shut_down(&self) {
+        self.shutting_down.store(true, Ordering::Relaxed);
+    }
+
+    /// Run every check.
+    pub async fn readiness(&self) -> Readiness {
+        let mut checks = Vec::with_capacity(self.checks.len() + 1);
+        for (name, check) in &self.checks {
+            checks.push((name.clone(), check.check().await));
+        }
+        let shutdown = match self.shutting_down.load(Ordering::Relaxed) {
+            true => Err("shutting down".to_string()),
+            false => Ok(()),
+        };
+        checks.push(("shutdown".to_string(), shutdown));
+        Readiness { checks }
+    }
+}
+
+/// Serves [`Health`] as `grpc.health.v1.Health`.
+#[derive(Debug, Clone)]
+pub struct HealthServiceImpl {
+    health: Health,
+    services: Arc<HashSet<String>>,
+}
+
+impl HealthServiceImpl {
+    /// Answers for `services`, the full names of the services served, e.g.
+    /// `student.StudentService`, besides `""` and [`LIVENESS`].
+    pub fn new(health: Health, services: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            health,
+            services: Arc::new(services.into_iter().collect()),
+        }
+    }
+
+    async fn status(&self, service: &str) -> ServingStatus {
+        if service == LIVENESS {
+            return ServingStatus::Serving;
+        }
+        if !service.is_empty() && !self.services.contains(service) {
+            return ServingStatus::ServiceUnknown;
+        }
+        match self.health.readiness().await.is_ready() {
+            true => ServingStatus::Serving,
+            false => ServingStatus::NotServing,
+        }
+    }
+}
+
+fn response(status: ServingStatus) -> HealthCheckResponse {
+    HealthCheckResponse {
+        status: status as i32,
+    }
+}
+
+pub type HealthCheckStream =
+    Pin<Box<dyn Stream<Item = Result<HealthCheckResponse, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl HealthService for HealthServiceImpl {
+    async fn check(
+        &self,
+        request: tonic::Request<HealthCheckRequest>,
+    ) -> Result<tonic::Response<HealthCheckResponse>, Status> {
+        let service = request.into_inner().service;
+        match self.status(&service).await {
+            ServingStatus::ServiceUnknown => Err(Error::UnknownService(service).into()),
+            status => Ok(tonic::Response::new(response(status))),
+        }
+    }
+
+    type WatchStream = HealthCheckStream;
+
+    // The status now, then each change, until the watcher goes away
+    async fn watch(
+        &self,
+        request: tonic::Request<HealthCheckRequest>,
+    ) -> Result<tonic::Response<Self::WatchStream>, Status> {
+        let service = request.into_inner().service;
+        let (sender, receiver) = tokio::sync::mpsc::channel(1);
+        let this = self.clone();
+        tokio::spawn(async move {
+            let mut last = None;
+            loop {
+                let status = this.status(&service).await;
+                if last != Some(status) {
+                    if sender.send(Ok(response(status))).await.is_err() {
+                        return;
+                    }
+                    last = Some(status);
+                }
+                tokio::select! {
+                    _ = sender.closed() => return,
+                    _ = tokio::time::sleep(WATCH_INTERVAL) => {}
+                }
+            }
+        });
+        Ok(tonic::Response::new(Box::pin(ReceiverStream::new(
+            receiver,
+        ))))
+    }
+}
+
+// `/livez` and `/readyz`, each check on a line as Kubernetes writes them,
+// and `/metrics`, in OpenMetrics if the scraper accepts it
+// What the probes serve from
+#[derive(Clone)]
+struct Probed {
+    health: Health,
+    metrics: Metrics,
+    channelz: Channelz,
+}
+
+async fn probe(probed: Probed, request: Request<Body>) -> Response<Body> {
+    let Probed {
+        health,
+        metrics,
+        channelz,
+    } = probed;
+    let text = |status: StatusCode, body: String| {
+        Response::builder()
+            .status(status)
+            .header("content-type", "text/plain; charset=utf-8")
+            .body(Body::from(body))
+            .expect("a status and a content type make a response")
+    };
+    if !matches!(*request.method(), Method::GET | Method::HEAD) {
+        return text(StatusCode::METHOD_NOT_ALLOWED, String::new());
+    }
+    match request.uri().path() {
+        "/livez" => text(StatusCode::OK, "ok\n".to_string()),
+        "/metrics" if accepts_openmetrics(&request) => Response::builder()
+            .header("content-type", metrics::OPENMETRICS)
+            .body(Body::from(metrics.render_openmetrics()))
+            .expect("a content type makes a response"),
+        "/metrics" => text(StatusCode::OK, metrics.render()),
+        "/debug/connections" => text(StatusCode::OK, channelz.render()),
+        "/readyz" => {
+            let readiness = health.readiness().await;
+            let mut body = String::new();
+            for (name, result) in &readiness.checks {
+                match result {
+                    Ok(()) => body.push_str(&format!("[+]{} ok\n", name)),
+                    Err(reason) => body.push_str(&format!("[-]{} failed: {}\n", name, reason)),
+                }
+            }
+            match readiness.is_ready() {
+                true => text(StatusCode::OK, body + "ok\n"),
+                false => text(StatusCode::SERVICE_UNAVAILABLE, body + "not ready\n"),
+            }
+        }
+        _ => text(StatusCode::NOT_FOUND, String::new()),
+    }
+}
+
+fn accepts_openmetrics(request: &Request<Body>) -> bool {
+    request
+        .headers()
+        .get_all(hyper::header::ACCEPT)
+        .iter()
+        .filter_map(|accept| accept.to_str().ok())
+        .any(|accept| accept.contains("application/openmetrics-text"))
+}
+
+/// Serve `/livez`, `/readyz`, `/metrics`, and `/debug/connections` over
+/// HTTP on `addr` until `shutdown`.
+pub async fn serve_http(
+    addr: SocketAddr,
+    health: Health,
+    metrics: Metrics,
+    channelz: Channelz,
+    shutdown: impl std::future::Future<Output = ()>,
+) -> Result<(), hyper::Error> {
+    let probed = Probed {
+        health,
+        metrics,
+        channelz,
+    };
+    let make_service = make_service_fn(move |_| {
+        let probed = probed.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let probed = probed.clone();
+                async move { Ok::<_, Infallible>(probe(probed, request).await) }
+            }))
+        }
+    });
+    hyper::Server::try_bind(&addr)?
+        .serve(make_service)
+        .with_graceful_shutdown(shutdown)
+        .await
+}
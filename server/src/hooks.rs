@@ -0,0 +1,33 @@
+//! Checks a deployment adds to student writes, such as an institution's
+//! own email rules, without changing the server.
+//!
+//! [`StudentServiceImpl`](crate::StudentServiceImpl) runs each hook in turn
+//! on every student it is about to create or update, once the built-in
+//! validation has passed. A hook can let the student through, reject it, or
+//! hand back a changed student, which the next hook sees and which must
+//! itself pass the built-in validation.
+
+use proto::Student;
+use std::fmt;
+
+/// What a hook decided about a student.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Accept,
+    /// Write this student instead; its ID and ETag are kept as they were
+    Replace(Box<Student>),
+    /// Refuse the write, saying what is wrong with which field
+    Reject {
+        field: String,
+        message: String,
+    },
+}
+
+/// A check run on each student before it is written.
+pub trait WriteHook: fmt::Debug + Send + Sync {
+    /// The name callers see when the hook rejects a student.
+    fn name(&self) -> &str;
+
+    /// The verdict on `student`, or why the hook could not reach one.
+    fn check(&self, student: &Student) -> Result<Verdict, String>;
+}
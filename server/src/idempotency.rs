@@ -0,0 +1,78 @@
+//! Retrying writes without repeating them.
+//!
+//! A client sends the same `idempotency-key` metadata with every attempt of
+//! one `CreateStudent` or `BatchWrite` call. The first attempt to commit
+//! stores its writes and the key together (see
+//! [`crate::repository::Transaction::with_idempotency_key`]); any later one
+//! gets back what was written then, without writing or publishing anything.
+//! Keys live in the storage backend for [`KEY_RETENTION`], so with
+//! PostgreSQL they outlast restarts and are shared by every server on the
+//! database.
+//!
+//! A key belongs to one request: reusing it for a different method or body
+//! fails with `INVALID_ARGUMENT`.
+
+use crate::locale::{self, Text};
+pub use crate::repository::KEY_RETENTION;
+use crate::repository::{IdempotencyKey, StudentRepository};
+use proto::Student;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tonic::{Code, Request, Status};
+
+/// The metadata key clients send their idempotency key in.
+pub const METADATA_KEY: &str = "idempotency-key";
+
+/// The longest idempotency key accepted, in characters.
+pub const MAX_KEY_LENGTH: usize = 255;
+
+/// The idempotency key of `request` to `method`, if it has one, with the
+/// fingerprint of the request.
+pub fn key<T: Serialize>(
+    request: &Request<T>,
+    method: &str,
+) -> Result<Option<IdempotencyKey>, Status> {
+    let Some(value) = request.metadata().get(METADATA_KEY) else {
+        return Ok(None);
+    };
+    let key = value
+        .to_str()
+        .ok()
+        .map(str::trim)
+        .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LENGTH)
+        .ok_or_else(|| locale::status(Code::InvalidArgument, Text::IdempotencyKeyInvalid))?;
+    Ok(Some(IdempotencyKey {
+        key: key.to_string(),
+        fingerprint: fingerprint(method, request.get_ref()),
+    }))
+}
+
+// The method and the request's canonical JSON, whose objects come out with
+// sorted keys, so map fields hash the same however they were ordered
+fn fingerprint<T: Serialize>(method: &str, request: &T) -> String {
+    let json = serde_json::to_value(request).unwrap_or_default();
+    let digest = Sha256::new()
+        .chain_update(method)
+        .chain_update([0])
+        .chain_update(json.to_string())
+        .finalize();
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// What the first request with `key` wrote, if `store` remembers it; fails
+/// if the key was used for a different request.
+pub async fn replay(
+    store: &dyn StudentRepository,
+    key: Option<&IdempotencyKey>,
+) -> Result<Option<Vec<Student>>, Status> {
+    let Some(key) = key else {
+        return Ok(None);
+    };
+    match store.remembered(&key.key).await? {
+        Some(remembered) if remembered.fingerprint != key.fingerprint => Err(locale::status(
+            Code::InvalidArgument,
+            Text::IdempotencyKeyReused,
+        )),
+        remembered => Ok(remembered.map(|remembered| remembered.students)),
+    }
+}
@@ -0,0 +1,291 @@
+//! Who is calling.
+//!
+//! Callers say who they are with a bearer token in the `authorization`
+//! metadata, which the server signed with its `--auth-secret` and which
+//! names a [`Principal`], e.g. `professor:p1`, until it expires.
+//! [`IdentityLayer`] checks the token and [`current`] answers for the
+//! request being handled. A request without a token is anonymous; one with
+//! a token that was changed, made up, or is past its expiry is refused with
+//! `UNAUTHENTICATED`. A server without a secret treats every request as
+//! anonymous.
+
+use crate::clock::{Clock, SystemClock};
+use crate::encryption::from_hex;
+use crate::locale::{self, Text};
+use hmac::{Hmac, Mac};
+use hyper::Body;
+use sha2::Sha256;
+use std::fmt::{self, Debug};
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, UNIX_EPOCH};
+use tonic::body::BoxBody;
+use tonic::codegen::http::{header, Request, Response};
+use tonic::codegen::Service;
+use tonic::{Code, Status};
+use tower_layer::Layer;
+
+/// How long a token is good for unless told otherwise.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Bytes of the HMAC kept in a token, as for page tokens
+const TAG_LENGTH: usize = 16;
+
+/// What a caller is to the school.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Role {
+    Admin,
+    Staff,
+    Professor,
+    Student,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Staff => "staff",
+            Role::Professor => "professor",
+            Role::Student => "student",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "admin" => Ok(Role::Admin),
+            "staff" => Ok(Role::Staff),
+            "professor" => Ok(Role::Professor),
+            "student" => Ok(Role::Student),
+            _ => Err(format!(
+                "Unknown role {:?}: expected admin, staff, professor, or student",
+                text
+            )),
+        }
+    }
+}
+
+/// A caller: their role, and their ID among the professors, students, or
+/// staff, written like `professor:p1`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Principal {
+    pub role: Role,
+    pub id: String,
+}
+
+impl FromStr for Principal {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (role, id) = text
+            .split_once(':')
+            .ok_or_else(|| format!("{}: expected ROLE:ID, e.g. professor:p1", text))?;
+        let id = id.trim();
+        if id.is_empty() {
+            return Err(format!("{}: expected an ID after ':'", text));
+        }
+        Ok(Self {
+            role: role.parse()?,
+            id: id.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Principal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.role.as_str(), self.id)
+    }
+}
+
+tokio::task_local! {
+    static CURRENT_PRINCIPAL: Option<Principal>;
+}
+
+/// The caller of the request being handled, unless it is anonymous.
+pub fn current() -> Option<Principal> {
+    CURRENT_PRINCIPAL.try_with(Clone::clone).ok().flatten()
+}
+
+/// The caller of the request being handled, or None outside of any
+/// request, e.g. in a scheduled task; `Some(None)` is an anonymous caller.
+pub fn caller() -> Option<Option<Principal>> {
+    CURRENT_PRINCIPAL.try_with(Clone::clone).ok()
+}
+
+/// Run `call` as if it were a request from `principal`, e.g. in tests.
+pub async fn scope<F: Future>(principal: Option<Principal>, call: F) -> F::Output {
+    CURRENT_PRINCIPAL.scope(principal, call).await
+}
+
+/// Issues tokens naming a principal and checks them on the way in.
+#[derive(Clone)]
+pub struct Tokens {
+    mac: Hmac<Sha256>,
+    ttl: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+// Never print the secret
+impl Debug for Tokens {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tokens")
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Tokens {
+    /// Sign with `secret`.
+    pub fn new(secret: &[u8]) -> Self {
+        Self {
+            mac: Hmac::new_from_slice(secret).expect("HMAC takes secrets of any length"),
+            ttl: DEFAULT_TTL,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Let tokens be used for `ttl` after they were issued.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Use `clock` to tell when tokens expire.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn tag(&self, expires: u64, principal: &str) -> Hmac<Sha256> {
+        let mut mac = self.mac.clone();
+        for part in [&expires.to_be_bytes(), principal.as_bytes()] {
+            mac.update(&(part.len() as u64).to_be_bytes());
+            mac.update(part);
+        }
+        mac
+    }
+
+    fn now(&self) -> u64 {
+        let now = self.clock.now().duration_since(UNIX_EPOCH);
+        now.map_or(0, |since| since.as_secs())
+    }
+
+    /// A token naming `principal`.
+    pub fn issue(&self, principal: &Principal) -> String {
+        let expires = self.now().saturating_add(self.ttl.as_secs());
+        let principal = principal.to_string();
+        let tag = self.tag(expires, &principal).finalize().into_bytes();
+        let tag: String = tag[..TAG_LENGTH]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        format!("{}.{}.{}", expires, principal, tag)
+    }
+
+    /// The principal `token` names, if it is one of ours and still good.
+    pub fn verify(&self, token: &str) -> Result<Principal, Status> {
+        let invalid = || locale::status(Code::Unauthenticated, Text::AuthTokenInvalid);
+        let (expires, rest) = token.split_once('.').ok_or_else(invalid)?;
+        let (principal, tag) = rest.rsplit_once('.').ok_or_else(invalid)?;
+        let expires: u64 = expires.parse().map_err(|_| invalid())?;
+        let tag = from_hex(tag)
+            .filter(|tag| tag.len() == TAG_LENGTH)
+            .ok_or_else(invalid)?;
+        self.tag(expires, principal)
+            .verify_truncated_left(&tag)
+            .map_err(|_| invalid())?;
+        if expires <= self.now() {
+            return Err(locale::status(
+                Code::Unauthenticated,
+                Text::AuthTokenExpired,
+            ));
+        }
+        principal.parse().map_err(|_| invalid())
+    }
+}
+
+/// Handles every request as one from the principal its bearer token names.
+#[derive(Debug, Clone, Default)]
+pub struct IdentityLayer {
+    // None takes every request as anonymous
+    tokens: Option<Tokens>,
+}
+
+impl IdentityLayer {
+    /// Check bearer tokens with `tokens`.
+    pub fn new(tokens: Tokens) -> Self {
+        Self {
+            tokens: Some(tokens),
+        }
+    }
+}
+
+impl<S> Layer<S> for IdentityLayer {
+    type Service = Identified<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Identified {
+            inner,
+            tokens: self.tokens.clone(),
+        }
+    }
+}
+
+/// The service produced by [`IdentityLayer`].
+#[derive(Debug, Clone)]
+pub struct Identified<S> {
+    inner: S,
+    tokens: Option<Tokens>,
+}
+
+impl<S> Service<Request<Body>> for Identified<S>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let token = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .map(|value| value.to_str().unwrap_or_default().trim())
+            .map(|value| {
+                value
+                    .strip_prefix("Bearer ")
+                    .unwrap_or(value)
+                    .trim()
+                    .to_string()
+            });
+        let (Some(token), Some(tokens)) = (token, self.tokens.clone()) else {
+            return Box::pin(CURRENT_PRINCIPAL.scope(None, self.inner.call(request)));
+        };
+
+        // The clone that was made ready is the one that must be called
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            // Checked here, where the caller's language is known
+            match tokens.verify(&token) {
+                Ok(principal) => {
+                    CURRENT_PRINCIPAL
+                        .scope(Some(principal), inner.call(request))
+                        .await
+                }
+                Err(status) => Ok(status.to_http()),
+            }
+        })
+    }
+}
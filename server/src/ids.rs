@@ -0,0 +1,45 @@
+//! Injectable source of student IDs.
+//!
+//! Production uses [`UuidGenerator`]; tests and golden files swap in
+//! [`SequentialIds`] so every generated ID is known in advance.
+
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use uuid::Uuid;
+
+/// Produces IDs for students created without one.
+pub trait IdGenerator: Debug + Send + Sync {
+    fn next_id(&self) -> String;
+}
+
+/// Random version 4 UUIDs.
+#[derive(Debug, Default)]
+pub struct UuidGenerator;
+
+impl IdGenerator for UuidGenerator {
+    fn next_id(&self) -> String {
+        Uuid::new_v4().to_string()
+    }
+}
+
+/// `{prefix}1`, `{prefix}2`, ... in call order.
+#[derive(Debug)]
+pub struct SequentialIds {
+    prefix: String,
+    next: AtomicU64,
+}
+
+impl SequentialIds {
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+            next: AtomicU64::new(1),
+        }
+    }
+}
+
+impl IdGenerator for SequentialIds {
+    fn next_id(&self) -> String {
+        format!("{}{}", self.prefix, self.next.fetch_add(1, Ordering::Relaxed))
+    }
+}
@@ -0,0 +1,261 @@
+//! The pipeline behind `BulkCreateStudents`.
+//!
+//! Streamed students pass through three stages joined by bounded channels,
+//! so a fast sender is slowed down rather than queued up in memory:
+//!
+//! 1. the request stream is read and each student numbered
+//! 2. validation workers, one per CPU, check and complete students the way
+//!    `CreateStudent` does
+//! 3. a single writer stores whatever has been validated, up to
+//!    [`BATCH_SIZE`] students at a time, with one `create_many` call
+//!
+//! A student that fails validation or cannot be stored is reported and the
+//! rest carry on; only a storage error other than `ALREADY_EXISTS` stops the
+//! import. So does an error reading the request stream, or the call being
+//! cancelled: the students read but not yet stored are then dropped, and an
+//! error says how many were stored before.
+
+use crate::catalog::Catalog;
+use crate::clock::{self, Clock};
+use crate::events::EventLog;
+use crate::flags;
+use crate::ids::IdGenerator;
+use crate::locale::{Locale, Text};
+use crate::repository::StudentRepository;
+use crate::standing::StandingRules;
+use crate::validation::Profile;
+use phonenumber::country::Id;
+use proto::{
+    BulkCreateFailure, BulkCreateStudentsRequest, BulkCreateStudentsResponse, ChangeType, Student,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Code, Status};
+
+/// Most students stored in one `create_many` call.
+pub const BATCH_SIZE: usize = 500;
+
+// Students waiting between stages
+const CHANNEL_CAPACITY: usize = 4 * BATCH_SIZE;
+
+// A student and its position in the stream
+type Row = (i32, Student);
+
+/// What the stages share.
+#[derive(Debug, Clone)]
+pub(crate) struct Pipeline {
+    pub(crate) store: Arc<dyn StudentRepository>,
+    pub(crate) catalog: Arc<Catalog>,
+    pub(crate) rules: Arc<StandingRules>,
+    pub(crate) ids: Arc<dyn IdGenerator>,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) events: Option<Arc<EventLog>>,
+    pub(crate) validation: Profile,
+    pub(crate) phone_region: Option<Id>,
+}
+
+type Failures = Arc<Mutex<Vec<BulkCreateFailure>>>;
+
+fn fail(failures: &Failures, index: i32, student_id: &str, reason: &str) {
+    failures
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(BulkCreateFailure {
+            index,
+            student_id: student_id.to_string(),
+            reason: reason.to_string(),
+        });
+}
+
+// Aborts the writer if dropped before the request stream was read to its
+// end, on a read error or because the call was cancelled
+struct Reading {
+    aborted: Arc<AtomicBool>,
+    done: bool,
+}
+
+impl Drop for Reading {
+    fn drop(&mut self) {
+        if !self.done {
+            self.aborted.store(true, Ordering::Release);
+        }
+    }
+}
+
+impl Pipeline {
+    // The student as CreateStudent would store it, or why it cannot be
+    fn prepare(&self, mut student: Student) -> Result<Student, String> {
+        let mut problems = self.validation.check(&mut student);
+        problems.extend(
+            self.validation
+                .phone_numbers(&mut student, self.phone_region),
+        );
+        if let Some((_, problem)) = problems.into_iter().next() {
+            return Err(Text::from(problem).localized());
+        }
+        if !student.major_id.is_empty() {
+            match self.catalog.major(&student.major_id) {
+                Some(major) => student.major = major.name,
+                None => return Err(Text::UnknownMajor(student.major_id).localized()),
+            }
+        }
+        if student.id.is_empty() {
+            student.id = self.ids.next_id();
+        }
+        let now = clock::timestamp(self.clock.now());
+        student.create_time = Some(now.clone());
+        student.update_time = Some(now);
+        // Kept by the grades, and a new student has none
+        student.credits_attempted = 0;
+        student.credits_earned = 0;
+        Ok(student)
+    }
+
+    async fn validate(
+        self,
+        rows: Arc<tokio::sync::Mutex<mpsc::Receiver<Row>>>,
+        valid: mpsc::Sender<Row>,
+        failures: Failures,
+    ) {
+        loop {
+            // Released before the work, so the other workers can take rows
+            let Some((index, student)) = rows.lock().await.recv().await else {
+                return;
+            };
+            let id = student.id.clone();
+            match self.prepare(student) {
+                Ok(student) => {
+                    // The writer has stopped
+                    if valid.send((index, student)).await.is_err() {
+                        return;
+                    }
+                }
+                Err(reason) => fail(&failures, index, &id, &reason),
+            }
+        }
+    }
+
+    // Stores batches until `valid` runs out, or only drains it once `aborted`
+    async fn write(
+        self,
+        mut valid: mpsc::Receiver<Row>,
+        failures: Failures,
+        aborted: Arc<AtomicBool>,
+    ) -> Result<i32, Status> {
+        let mut created_count = 0;
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        while valid.recv_many(&mut batch, BATCH_SIZE).await > 0 {
+            if aborted.load(Ordering::Acquire) {
+                batch.clear();
+                continue;
+            }
+            let (indexes, students): (Vec<i32>, Vec<Student>) = batch.drain(..).unzip();
+            let ids: Vec<String> = students.iter().map(|student| student.id.clone()).collect();
+            let results = self.store.create_many(students).await;
+            for ((index, id), result) in indexes.into_iter().zip(ids).zip(results) {
+                match result {
+                    Ok(mut student) => {
+                        created_count += 1;
+                        if let Some(events) = &self.events {
+                            student.standing = self.rules.standing(&student) as i32;
+                            events.publish(ChangeType::Created, &student);
+                        }
+                    }
+                    Err(status) if status.code() == Code::AlreadyExists => {
+                        fail(&failures, index, &id, status.message())
+                    }
+                    Err(status) => return Err(status),
+                }
+            }
+        }
+        Ok(created_count)
+    }
+
+    /// Create every student in `requests`.
+    pub(crate) async fn run(
+        self,
+        mut requests: impl Stream<Item = Result<BulkCreateStudentsRequest, Status>> + Unpin,
+    ) -> Result<BulkCreateStudentsResponse, Status> {
+        let failures = Failures::default();
+        let (rows, to_validate) = mpsc::channel::<Row>(CHANNEL_CAPACITY);
+        let (validated, to_write) = mpsc::channel::<Row>(CHANNEL_CAPACITY);
+        let to_validate = Arc::new(tokio::sync::Mutex::new(to_validate));
+
+        // The stages speak the caller's language, like the handler
+        let locale = Locale::current();
+        let workers = std::thread::available_parallelism().map_or(4, |n| n.get());
+        for _ in 0..workers {
+            tokio::spawn(locale.scope(self.clone().validate(
+                to_validate.clone(),
+                validated.clone(),
+                failures.clone(),
+            )));
+        }
+        drop((to_validate, validated));
+        // The writes count against the quota of the caller's tenant
+        let aborted = Arc::new(AtomicBool::new(false));
+        let write = flags::scope(
+            flags::current_tenant(),
+            self.write(to_write, failures.clone(), aborted.clone()),
+        );
+        let writer = tokio::spawn(locale.scope(write));
+
+        let mut reading = Reading {
+            aborted,
+            done: false,
+        };
+        let mut index = 0;
+        let mut read = Ok(());
+        'reading: while let Some(request) = requests.next().await {
+            let request = match request {
+                Ok(request) => request,
+                Err(status) => {
+                    read = Err(status);
+                    break;
+                }
+            };
+            for student in request.students {
+                // The writer has stopped, and with it the workers
+                if rows.send((index, student)).await.is_err() {
+                    break 'reading;
+                }
+                index += 1;
+            }
+        }
+        // The workers finish the rows already read, then the writer the
+        // rest, unless the stream broke
+        reading.done = read.is_ok();
+        drop(reading);
+        drop(rows);
+
+        let created_count = writer
+            .await
+            .map_err(|e| Status::internal(format!("Import writer failed: {}", e)))??;
+        if let Err(status) = read {
+            warn!(
+                "⚠️  Bulk create stopped by a broken request stream after {} students",
+                created_count
+            );
+            return Err(Status::new(
+                status.code(),
+                format!(
+                    "{} ({} students were created before the stream broke)",
+                    status.message(),
+                    created_count
+                ),
+            ));
+        }
+        let mut failures = std::mem::take(&mut *failures.lock().unwrap_or_else(|e| e.into_inner()));
+        failures.sort_by_key(|failure| failure.index);
+        info!(
+            "📥 Bulk create: {} of {} students created",
+            created_count, index
+        );
+        Ok(BulkCreateStudentsResponse {
+            created_count,
+            failures,
+        })
+    }
+}
@@ -0,0 +1,486 @@
+//! Leader election over a lease, for running two or more replicas on shared
+//! storage without a consensus protocol.
+//!
+//! Each replica runs an [`Election`], which takes the lease in a
+//! [`LeaseStore`] every replica can reach when it is free or has expired,
+//! and renews it well before it expires. The holder is the leader. A leader
+//! that cannot renew stops taking writes when its lease runs out, by its
+//! own clock, before any other replica can take the lease over.
+//!
+//! [`LeaderLayer`] lets followers serve reads and refuses everything else
+//! with `UNAVAILABLE`, reason `NOT_LEADER` and the leader's address as
+//! `leader` in the `ErrorInfo` metadata, so clients know where to send it.
+//! The services that only touch the replica they run on, such as logging
+//! and feature flags, are served by followers too.
+//!
+//! With [`LeaderLayer::with_forwarding`], followers instead send those RPCs
+//! on to the leader as they came, metadata and `grpc-timeout` included, and
+//! pass its response back, so clients need not know which replica leads.
+//! A forwarded RPC is marked with `forwarded-by`, and is refused rather than
+//! forwarded again if it reaches a follower, e.g. while the lead changes.
+
+use crate::clock::{self, Clock, SystemClock};
+use crate::locale::{self, Text};
+use hyper::body::HttpBody;
+use hyper::Body;
+use proto::Timestamp;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::fs::{File, OpenOptions};
+use std::future::Future;
+use std::io::{Read, Seek, Write};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+use tonic::body::BoxBody;
+use tonic::codegen::http::header::{HeaderName, HeaderValue};
+use tonic::codegen::http::{Request, Response};
+use tonic::codegen::Service;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Status};
+use tower_layer::Layer;
+
+// Methods that only read, by the start of their names
+const READS: &[&str] = &[
+    "Get", "List", "Watch", "Validate", "Find", "Export", "Stream", "Generate",
+];
+
+// Services whose every method only touches the replica it is called on
+const LOCAL_SERVICES: &[&str] = &[
+    "grpc.health.v1.Health",
+    "student.LoggingService",
+    "student.FeatureFlagService",
+    "student.OperationsService",
+    "student.QuotaService",
+];
+
+/// The metadata naming the follower that forwarded an RPC to the leader.
+pub const FORWARDED_BY: &str = "forwarded-by";
+
+// Headers about the connection to this replica, not the RPC
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+    "host",
+];
+
+/// Who holds the lease, where to reach them, and until when.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lease {
+    /// The `--instance-id` of the replica
+    pub holder: String,
+    /// The address clients reach the replica at
+    pub address: String,
+    pub expires: Timestamp,
+}
+
+/// Storage for the lease that every replica can reach.
+#[tonic::async_trait]
+pub trait LeaseStore: Debug + Send + Sync {
+    /// Take or renew the lease for `holder` at `address` until `ttl` from
+    /// now, if nobody holds it, it has expired, or `holder` already does,
+    /// and return the lease as it then is, whoever holds it. `now` is the
+    /// caller's time; stores with a clock of their own may go by theirs.
+    async fn acquire(
+        &self,
+        holder: &str,
+        address: &str,
+        now: SystemTime,
+        ttl: Duration,
+    ) -> Result<Lease, Status>;
+
+    /// Give the lease up if `holder` has it, so another replica need not
+    /// wait for it to expire.
+    async fn release(&self, holder: &str) -> Result<(), Status>;
+}
+
+/// A lease kept in a JSON file, e.g. on a volume every replica mounts. The
+/// file is locked while it is read and written.
+#[derive(Debug, Clone)]
+pub struct FileLease {
+    path: PathBuf,
+}
+
+impl FileLease {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    // Run `change` on the lease in the file, if any, holding the file's
+    // lock, and write back what it returns, if anything
+    async fn locked<T: Send + 'static>(
+        &self,
+        change: impl FnOnce(Option<Lease>) -> (Option<Lease>, T) + Send + 'static,
+    ) -> Result<T, Status> {
+        let path = self.path.clone();
+        let io = |e: std::io::Error| {
+            Status::unavailable(format!(
+                "Cannot read the lease in {}: {}",
+                path.display(),
+                e
+            ))
+        };
+        let path = self.path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)?;
+            file.lock()?;
+            let mut text = String::new();
+            file.read_to_string(&mut text)?;
+            // An empty or unreadable file holds no lease
+            let (write, result) = change(serde_json::from_str(&text).ok());
+            if let Some(lease) = write {
+                rewrite(&mut file, &serde_json::to_vec(&lease)?)?;
+            }
+            Ok(result)
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(io)
+    }
+}
+
+fn rewrite(file: &mut File, contents: &[u8]) -> std::io::Result<()> {
+    file.set_len(0)?;
+    file.rewind()?;
+    file.write_all(contents)?;
+    file.sync_data()
+}
+
+#[tonic::async_trait]
+impl LeaseStore for FileLease {
+    async fn acquire(
+        &self,
+        holder: &str,
+        address: &str,
+        now: SystemTime,
+        ttl: Duration,
+    ) -> Result<Lease, Status> {
+        let ours = Lease {
+            holder: holder.to_string(),
+            address: address.to_string(),
+            expires: clock::timestamp(now + ttl),
+        };
+        let now = clock::timestamp(now);
+        self.locked(move |current| match current {
+            Some(lease) if lease.holder != ours.holder && clock::is_after(&lease.expires, &now) => {
+                (None, lease)
+            }
+            _ => (Some(ours.clone()), ours),
+        })
+        .await
+    }
+
+    async fn release(&self, holder: &str) -> Result<(), Status> {
+        let holder = holder.to_string();
+        self.locked(move |current| match current {
+            Some(mut lease) if lease.holder == holder => {
+                lease.expires = Timestamp::default();
+                (Some(lease), ())
+            }
+            _ => (None, ()),
+        })
+        .await
+    }
+}
+
+// The lease as last seen, and when this replica's own lease runs out by
+// its clock
+#[derive(Debug, Default)]
+struct State {
+    lease: Option<Lease>,
+    leading_until: Option<SystemTime>,
+}
+
+/// One replica's part in electing a leader.
+#[derive(Debug)]
+pub struct Election {
+    store: Arc<dyn LeaseStore>,
+    holder: String,
+    address: String,
+    ttl: Duration,
+    clock: Arc<dyn Clock>,
+    state: Mutex<State>,
+}
+
+impl Election {
+    /// Take part as `holder`, reached at `address`, with leases of `ttl`.
+    pub fn new(
+        store: Arc<dyn LeaseStore>,
+        holder: impl Into<String>,
+        address: impl Into<String>,
+        ttl: Duration,
+    ) -> Self {
+        Self {
+            store,
+            holder: holder.into(),
+            address: address.into(),
+            ttl,
+            clock: Arc::new(SystemClock),
+            state: Mutex::default(),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Take or renew the lease if it can be had, and return whether this
+    /// replica now leads.
+    pub async fn campaign(&self) -> Result<bool, Status> {
+        // Counted from before asking, so this replica stops leading no
+        // later than the store lets another take over
+        let asked = self.clock.now();
+        let lease = self
+            .store
+            .acquire(&self.holder, &self.address, asked, self.ttl)
+            .await?;
+        let leading = lease.holder == self.holder;
+        let mut state = self.state();
+        let was_leading = state.leading_until.is_some_and(|until| until > asked);
+        match (was_leading, leading) {
+            (false, true) => info!("👑 Leading, as {} at {}", self.holder, self.address),
+            (true, false) => warn!("⚠️  No longer leading; {} is", lease.holder),
+            _ => {}
+        }
+        if !leading && state.lease.as_ref().map(|lease| &lease.holder) != Some(&lease.holder) {
+            info!("🪑 Following {} at {}", lease.holder, lease.address);
+        }
+        state.leading_until = leading.then(|| asked + self.ttl);
+        state.lease = Some(lease);
+        Ok(leading)
+    }
+
+    /// Whether this replica holds the lease and it has not run out.
+    pub fn is_leader(&self) -> bool {
+        let now = self.clock.now();
+        self.state().leading_until.is_some_and(|until| until > now)
+    }
+
+    /// The lease as last seen, if it has not expired.
+    pub fn leader(&self) -> Option<Lease> {
+        let now = clock::timestamp(self.clock.now());
+        self.state()
+            .lease
+            .clone()
+            .filter(|lease| clock::is_after(&lease.expires, &now))
+    }
+
+    /// Campaign every third of the lease's time to live, for as long as the
+    /// server runs.
+    pub async fn run(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(self.ttl / 3);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if let Err(status) = self.campaign().await {
+                warn!("⚠️  Cannot reach the leader lease: {}", status.message());
+            }
+        }
+    }
+
+    /// Give the lease up, if this replica holds it, e.g. on shutdown.
+    pub async fn resign(&self) {
+        if !self.is_leader() {
+            return;
+        }
+        self.state().leading_until = None;
+        match self.store.release(&self.holder).await {
+            Ok(()) => info!("👑 Gave up the lead"),
+            Err(status) => warn!("⚠️  Cannot give up the lead: {}", status.message()),
+        }
+    }
+}
+
+/// Whether followers serve the RPC at `path`, e.g.
+/// `/student.StudentService/GetStudent`: it only reads, or only touches the
+/// replica it is called on.
+pub fn served_by_followers(path: &str) -> bool {
+    let Some((service, method)) = path.strip_prefix('/').and_then(|path| path.split_once('/'))
+    else {
+        return false;
+    };
+    LOCAL_SERVICES.contains(&service) || READS.iter().any(|read| method.starts_with(read))
+}
+
+// Sends RPCs on to the leader, over a channel kept while it leads
+#[derive(Debug, Default)]
+struct Forwarder {
+    channel: Mutex<Option<(String, Channel)>>,
+}
+
+impl Forwarder {
+    // A channel to the replica at `address`, made when the lead changes
+    fn channel(&self, address: &str) -> Result<Channel, Status> {
+        let mut channel = self.channel.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((_, channel)) = channel.as_ref().filter(|(to, _)| to == address) {
+            return Ok(channel.clone());
+        }
+        let uri = match address.contains("://") {
+            true => address.to_string(),
+            false => format!("http://{}", address),
+        };
+        let endpoint = Endpoint::from_shared(uri).map_err(|e| {
+            Status::internal(format!("Leader address {} is not valid: {}", address, e))
+        })?;
+        let connected = endpoint.connect_lazy();
+        *channel = Some((address.to_string(), connected.clone()));
+        Ok(connected)
+    }
+
+    async fn forward(
+        &self,
+        address: &str,
+        holder: &str,
+        request: Request<Body>,
+    ) -> Result<Response<BoxBody>, Status> {
+        let (mut parts, body) = request.into_parts();
+        for header in HOP_BY_HOP {
+            parts.headers.remove(*header);
+        }
+        if let Ok(holder) = HeaderValue::from_str(holder) {
+            parts
+                .headers
+                .insert(HeaderName::from_static(FORWARDED_BY), holder);
+        }
+        let body = body
+            .map_err(|e| Status::from_error(Box::new(e)))
+            .boxed_unsync();
+        let mut channel = self.channel(address)?;
+        std::future::poll_fn(|cx| channel.poll_ready(cx))
+            .await
+            .map_err(|e| Status::unavailable(e.to_string()))?;
+        let response = channel
+            .call(Request::from_parts(parts, body))
+            .await
+            .map_err(|e| Status::unavailable(e.to_string()))?;
+        Ok(response.map(|body| {
+            body.map_err(|e| Status::from_error(Box::new(e)))
+                .boxed_unsync()
+        }))
+    }
+}
+
+/// Refuses the RPCs only the leader serves while this replica follows, or
+/// forwards them to the leader.
+#[derive(Debug, Clone)]
+pub struct LeaderLayer {
+    // None when replicas do not elect a leader
+    election: Option<Arc<Election>>,
+    forwarder: Option<Arc<Forwarder>>,
+}
+
+impl LeaderLayer {
+    pub fn new(election: Arc<Election>) -> Self {
+        Self {
+            election: Some(election),
+            forwarder: None,
+        }
+    }
+
+    /// Every replica serves everything.
+    pub fn disabled() -> Self {
+        Self {
+            election: None,
+            forwarder: None,
+        }
+    }
+
+    /// Forward the RPCs only the leader serves to it, instead of refusing
+    /// them.
+    pub fn with_forwarding(mut self) -> Self {
+        self.forwarder = Some(Arc::default());
+        self
+    }
+}
+
+impl<S> Layer<S> for LeaderLayer {
+    type Service = Led<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Led {
+            inner,
+            election: self.election.clone(),
+            forwarder: self.forwarder.clone(),
+        }
+    }
+}
+
+/// The service produced by [`LeaderLayer`].
+#[derive(Debug, Clone)]
+pub struct Led<S> {
+    inner: S,
+    election: Option<Arc<Election>>,
+    forwarder: Option<Arc<Forwarder>>,
+}
+
+impl<S> Service<Request<Body>> for Led<S>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let election = match &self.election {
+            Some(election)
+                if !election.is_leader() && !served_by_followers(request.uri().path()) =>
+            {
+                election.clone()
+            }
+            _ => return Box::pin(self.inner.call(request)),
+        };
+        // Forwarded once at most, so replicas that disagree on the leader
+        // do not send an RPC back and forth
+        let forwarder = self
+            .forwarder
+            .clone()
+            .filter(|_| !request.headers().contains_key(FORWARDED_BY));
+        // Said here, where the caller's language is known
+        Box::pin(async move {
+            let Some(lease) = election.leader() else {
+                return Ok(locale::status(Code::Unavailable, Text::NoLeader).to_http());
+            };
+            let Some(forwarder) = forwarder else {
+                return Ok(
+                    locale::status(Code::Unavailable, Text::NotLeader(lease.address)).to_http(),
+                );
+            };
+            match forwarder
+                .forward(&lease.address, &election.holder, request)
+                .await
+            {
+                Ok(response) => Ok(response),
+                Err(status) => {
+                    warn!(
+                        "⚠️  Cannot forward to the leader at {}: {}",
+                        lease.address,
+                        status.message()
+                    );
+                    Ok(locale::status(Code::Unavailable, Text::NotLeader(lease.address)).to_http())
+                }
+            }
+        })
+    }
+}
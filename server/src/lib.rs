@@ -0,0 +1,75 @@
+// `tonic::Status` is large by design and is the error type throughout the service.
+#![allow(clippy::result_large_err)]
+
+// First, so its log macros can be used in every module below
+#[macro_use]
+pub mod logging;
+pub mod attendance;
+#[cfg(feature = "cedar")]
+pub mod authz;
+pub mod bulk;
+pub mod catalog;
+pub mod channelz;
+pub mod clock;
+pub mod collation;
+pub mod config;
+pub mod connection;
+pub mod conformance;
+pub mod crud;
+pub mod diff;
+pub mod duplicates;
+pub mod email;
+pub mod encryption;
+pub mod enrollment;
+pub mod errors;
+pub mod events;
+pub mod eviction;
+#[cfg(feature = "analytics")]
+pub mod export;
+pub mod flags;
+pub mod health;
+pub mod hooks;
+pub mod idempotency;
+pub mod identity;
+pub mod ids;
+pub mod import;
+pub mod leader;
+pub mod locale;
+pub mod memory;
+pub mod metrics;
+pub mod migrations;
+pub mod notify;
+pub mod operations;
+pub mod outbox;
+pub mod pagination;
+#[cfg(feature = "plugins")]
+pub mod plugins;
+#[cfg(feature = "policies")]
+pub mod policies;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod professor;
+pub mod quota;
+pub mod recording;
+pub mod replication;
+pub mod repository;
+#[cfg(feature = "required")]
+pub mod required;
+pub mod scheduler;
+pub mod scholarship;
+pub mod scope;
+pub mod service;
+pub mod sharding;
+pub mod snapshot;
+pub mod standing;
+pub mod statistics;
+pub mod storage;
+pub mod timing;
+pub mod transcript;
+pub mod transfer;
+pub mod trash;
+pub mod validation;
+
+pub use service::StudentServiceImpl;
+// The rules shared with the client, where the server has always had them
+pub use student_core::{address, annotations, gpa, phone};
@@ -0,0 +1,779 @@
+//! Error messages in the caller's language.
+//!
+//! [`LocaleLayer`] reads the `accept-language` metadata of each request and
+//! picks the best [`Locale`] the server speaks (English, Chinese, or
+//! Spanish; English if none fits). Validation messages and the common
+//! student errors come from the catalog in [`Text`] and are rendered in
+//! that locale.
+//!
+//! Programs should not match on the message. Statuses made with [`status`]
+//! carry a `google.rpc.ErrorInfo` whose `reason` is the same in every
+//! language, next to a `google.rpc.LocalizedMessage`, and field violations
+//! keep their locale-independent `field`.
+
+use crate::address;
+use crate::annotations;
+use crate::idempotency;
+use prost::Message;
+use proto::google::rpc::bad_request::FieldViolation;
+use proto::google::rpc::{BadRequest, ErrorInfo, LocalizedMessage};
+use std::collections::HashMap;
+use std::task::{Context, Poll};
+use student_core::Problem;
+use tonic::codegen::http::Request;
+use tonic::codegen::Service;
+use tonic::{Code, Status};
+use tower_layer::Layer;
+
+pub const ACCEPT_LANGUAGE: &str = "accept-language";
+
+pub use student_core::ERROR_DOMAIN;
+
+/// A language the server has messages in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Locale {
+    #[default]
+    En,
+    Zh,
+    Es,
+}
+
+tokio::task_local! {
+    static LOCALE: Locale;
+}
+
+impl Locale {
+    /// The BCP 47 tag, as sent in `LocalizedMessage.locale`.
+    pub fn tag(self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Zh => "zh",
+            Locale::Es => "es",
+        }
+    }
+
+    fn from_tag(tag: &str) -> Option<Self> {
+        // Only the primary language matters: en-GB is English, zh-Hant Chinese
+        let language = tag.split(['-', '_']).next()?.trim();
+        match language.to_ascii_lowercase().as_str() {
+            "en" => Some(Locale::En),
+            "zh" => Some(Locale::Zh),
+            "es" => Some(Locale::Es),
+            _ => None,
+        }
+    }
+
+    /// The best match for an `accept-language` value such as
+    /// `es-MX, es;q=0.9, en;q=0.5`: the supported language with the
+    /// highest weight, the earliest of equal ones, or English.
+    pub fn negotiate(accept_language: &str) -> Self {
+        let mut best: Option<(Locale, f32)> = None;
+        for range in accept_language.split(',') {
+            let mut parts = range.split(';');
+            let tag = parts.next().unwrap_or_default().trim();
+            let weight = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())
+                .unwrap_or(0.0);
+            let Some(locale) = Locale::from_tag(tag) else {
+                continue;
+            };
+            if weight > 0.0 && best.is_none_or(|(_, best)| weight > best) {
+                best = Some((locale, weight));
+            }
+        }
+        best.map_or_else(Locale::default, |(locale, _)| locale)
+    }
+
+    /// The locale of the request being handled; English outside one.
+    pub fn current() -> Self {
+        LOCALE.try_with(|locale| *locale).unwrap_or_default()
+    }
+
+    /// Run `call` as if it were a request in this locale, e.g. in tests.
+    pub async fn scope<F: std::future::Future>(self, call: F) -> F::Output {
+        LOCALE.scope(self, call).await
+    }
+}
+
+/// Things the server tells callers, in every supported language.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Text {
+    NameEmpty,
+    EmailEmpty,
+    AgeOutOfRange,
+    GpaOutOfRange,
+    CreditsNegative,
+    IdEmpty,
+    StudentNotFound,
+    StudentExists,
+    StudentChanged,
+    /// The major ID given
+    UnknownMajor(String),
+    EmailChangeUnverified,
+    StandingRequired,
+    PageTokenInvalid,
+    PageTokenExpired,
+    IdNotCanonical,
+    NameNotCanonical,
+    EmailNotCanonical,
+    EmailInvalid,
+    MajorNotCanonical,
+    MajorIdNotCanonical,
+    GpaNotCanonical,
+    PreferredNameNotCanonical,
+    /// The order asked for
+    UnknownOrderBy(String),
+    /// The number given, in each of these
+    PhoneInvalid(String),
+    PhoneRegionRequired(String),
+    PhoneNotCanonical(String),
+    AddressLineEmpty,
+    CityEmpty,
+    /// The country code given
+    CountryUnknown(String),
+    /// The country, and the region given
+    RegionUnknown(String, String),
+    /// The country, and the postal code given
+    PostalCodeInvalid(String, String),
+    AddressNotCanonical,
+    /// The GPA given
+    GpaInvalid(String),
+    /// The key given
+    AnnotationKeyInvalid(String),
+    AnnotationsTooLarge,
+    BatchEntryEmpty,
+    /// The student ID written twice
+    BatchIdRepeated(String),
+    IdempotencyKeyInvalid,
+    IdempotencyKeyReused,
+    /// The path of the required field left unset
+    FieldRequired(String),
+    /// The write hook, and what it said
+    HookRejected(String, String),
+    AuthTokenInvalid,
+    AuthTokenExpired,
+    /// The RPC called
+    NotAuthorized(String),
+    /// The address of the leader
+    NotLeader(String),
+    NoLeader,
+}
+
+impl Text {
+    /// `ErrorInfo.reason`: stable, and the same in every language.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Text::NameEmpty => "NAME_EMPTY",
+            Text::EmailEmpty => "EMAIL_EMPTY",
+            Text::AgeOutOfRange => "AGE_OUT_OF_RANGE",
+            Text::GpaOutOfRange => "GPA_OUT_OF_RANGE",
+            Text::CreditsNegative => "CREDITS_NEGATIVE",
+            Text::IdEmpty => "STUDENT_ID_EMPTY",
+            Text::StudentNotFound => "STUDENT_NOT_FOUND",
+            Text::StudentExists => "STUDENT_ALREADY_EXISTS",
+            Text::StudentChanged => "STUDENT_CHANGED",
+            Text::UnknownMajor(_) => "UNKNOWN_MAJOR",
+            Text::EmailChangeUnverified => "EMAIL_CHANGE_UNVERIFIED",
+            Text::StandingRequired => "STANDING_REQUIRED",
+            Text::PageTokenInvalid => "PAGE_TOKEN_INVALID",
+            Text::PageTokenExpired => "PAGE_TOKEN_EXPIRED",
+            Text::IdNotCanonical => "ID_NOT_CANONICAL",
+            Text::NameNotCanonical => "NAME_NOT_CANONICAL",
+            Text::EmailNotCanonical => "EMAIL_NOT_CANONICAL",
+            Text::EmailInvalid => "EMAIL_INVALID",
+            Text::MajorNotCanonical => "MAJOR_NOT_CANONICAL",
+            Text::MajorIdNotCanonical => "MAJOR_ID_NOT_CANONICAL",
+            Text::GpaNotCanonical => "GPA_NOT_CANONICAL",
+            Text::PreferredNameNotCanonical => "PREFERRED_NAME_NOT_CANONICAL",
+            Text::UnknownOrderBy(_) => "UNKNOWN_ORDER_BY",
+            Text::PhoneInvalid(_) => "PHONE_NUMBER_INVALID",
+            Text::PhoneRegionRequired(_) => "PHONE_REGION_REQUIRED",
+            Text::PhoneNotCanonical(_) => "PHONE_NUMBER_NOT_CANONICAL",
+            Text::AddressLineEmpty => "ADDRESS_LINE_EMPTY",
+            Text::CityEmpty => "CITY_EMPTY",
+            Text::CountryUnknown(_) => "COUNTRY_UNKNOWN",
+            Text::RegionUnknown(..) => "REGION_UNKNOWN",
+            Text::PostalCodeInvalid(..) => "POSTAL_CODE_INVALID",
+            Text::AddressNotCanonical => "ADDRESS_NOT_CANONICAL",
+            Text::GpaInvalid(_) => "GPA_INVALID",
+            Text::AnnotationKeyInvalid(_) => "ANNOTATION_KEY_INVALID",
+            Text::AnnotationsTooLarge => "ANNOTATIONS_TOO_LARGE",
+            Text::BatchEntryEmpty => "BATCH_ENTRY_EMPTY",
+            Text::BatchIdRepeated(_) => "BATCH_ID_REPEATED",
+            Text::IdempotencyKeyInvalid => "IDEMPOTENCY_KEY_INVALID",
+            Text::IdempotencyKeyReused => "IDEMPOTENCY_KEY_REUSED",
+            Text::FieldRequired(_) => "FIELD_REQUIRED",
+            Text::HookRejected(..) => "HOOK_REJECTED",
+            Text::AuthTokenInvalid => "AUTH_TOKEN_INVALID",
+            Text::AuthTokenExpired => "AUTH_TOKEN_EXPIRED",
+            Text::NotAuthorized(_) => "NOT_AUTHORIZED",
+            Text::NotLeader(_) => "NOT_LEADER",
+            Text::NoLeader => "NO_LEADER",
+        }
+    }
+
+    /// The values filled into the message, as `ErrorInfo.metadata`.
+    pub fn metadata(&self) -> HashMap<String, String> {
+        match self {
+            Text::UnknownMajor(id) => HashMap::from([("major_id".to_string(), id.clone())]),
+            Text::UnknownOrderBy(order_by) => {
+                HashMap::from([("order_by".to_string(), order_by.clone())])
+            }
+            Text::PhoneInvalid(number)
+            | Text::PhoneRegionRequired(number)
+            | Text::PhoneNotCanonical(number) => {
+                HashMap::from([("phone_number".to_string(), number.clone())])
+            }
+            Text::GpaInvalid(gpa) => HashMap::from([("gpa".to_string(), gpa.clone())]),
+            Text::AnnotationKeyInvalid(key) => HashMap::from([("key".to_string(), key.clone())]),
+            Text::BatchIdRepeated(id) => HashMap::from([("student_id".to_string(), id.clone())]),
+            Text::FieldRequired(field) => HashMap::from([("field".to_string(), field.clone())]),
+            Text::NotAuthorized(rpc) => HashMap::from([("rpc".to_string(), rpc.clone())]),
+            Text::NotLeader(address) => HashMap::from([("leader".to_string(), address.clone())]),
+            Text::HookRejected(hook, message) => HashMap::from([
+                ("hook".to_string(), hook.clone()),
+                ("message".to_string(), message.clone()),
+            ]),
+            Text::IdempotencyKeyInvalid => HashMap::from([(
+                "max_length".to_string(),
+                idempotency::MAX_KEY_LENGTH.to_string(),
+            )]),
+            Text::AnnotationsTooLarge => {
+                HashMap::from([("max_bytes".to_string(), annotations::MAX_BYTES.to_string())])
+            }
+            Text::CountryUnknown(country) => {
+                HashMap::from([("country".to_string(), country.clone())])
+            }
+            Text::RegionUnknown(country, region) => HashMap::from([
+                ("country".to_string(), country.clone()),
+                ("region".to_string(), region.clone()),
+            ]),
+            Text::PostalCodeInvalid(country, postal_code) => HashMap::from([
+                ("country".to_string(), country.clone()),
+                ("postal_code".to_string(), postal_code.clone()),
+                ("formats".to_string(), address::postal_code_formats(country)),
+            ]),
+            _ => HashMap::new(),
+        }
+    }
+
+    /// The message in `locale`.
+    pub fn render(&self, locale: Locale) -> String {
+        use Locale::*;
+        let text = match (self, locale) {
+            (Text::NameEmpty, En) => "Student name cannot be empty",
+            (Text::NameEmpty, Zh) => "学生姓名不能为空",
+            (Text::NameEmpty, Es) => "El nombre del estudiante no puede estar vacío",
+            (Text::EmailEmpty, En) => "Student email cannot be empty",
+            (Text::EmailEmpty, Zh) => "学生邮箱不能为空",
+            (Text::EmailEmpty, Es) => "El correo del estudiante no puede estar vacío",
+            (Text::AgeOutOfRange, En) => "Student age must be between 0 and 150",
+            (Text::AgeOutOfRange, Zh) => "学生年龄必须在 0 到 150 之间",
+            (Text::AgeOutOfRange, Es) => "La edad del estudiante debe estar entre 0 y 150",
+            (Text::GpaOutOfRange, En) => "Student GPA must be between 0.0 and 4.0",
+            (Text::GpaOutOfRange, Zh) => "学生 GPA 必须在 0.0 到 4.0 之间",
+            (Text::GpaOutOfRange, Es) => "El GPA del estudiante debe estar entre 0.0 y 4.0",
+            (Text::CreditsNegative, En) => "Student credits cannot be negative",
+            (Text::CreditsNegative, Zh) => "学生学分不能为负数",
+            (Text::CreditsNegative, Es) => "Los créditos del estudiante no pueden ser negativos",
+            (Text::IdEmpty, En) => "Student ID cannot be empty",
+            (Text::IdEmpty, Zh) => "学生 ID 不能为空",
+            (Text::IdEmpty, Es) => "El ID del estudiante no puede estar vacío",
+            (Text::StudentNotFound, En) => "Student not found",
+            (Text::StudentNotFound, Zh) => "未找到该学生",
+            (Text::StudentNotFound, Es) => "No se encontró el estudiante",
+            (Text::StudentExists, En) => "Student with this ID already exists",
+            (Text::StudentExists, Zh) => "已存在使用此 ID 的学生",
+            (Text::StudentExists, Es) => "Ya existe un estudiante con este ID",
+            (Text::StudentChanged, En) => {
+                "Student was changed by someone else; fetch it again and retry"
+            }
+            (Text::StudentChanged, Zh) => "该学生已被他人修改；请重新获取后重试",
+            (Text::StudentChanged, Es) => {
+                "Otra persona modificó al estudiante; vuelva a obtenerlo e inténtelo de nuevo"
+            }
+            (Text::UnknownMajor(id), En) => return format!("Unknown major ID: {}", id),
+            (Text::UnknownMajor(id), Zh) => return format!("未知的专业 ID：{}", id),
+            (Text::UnknownMajor(id), Es) => return format!("ID de carrera desconocido: {}", id),
+            (Text::UnknownOrderBy(order_by), En) => {
+                return format!("Cannot order by {:?}; use \"id\" or \"name\"", order_by)
+            }
+            (Text::UnknownOrderBy(order_by), Zh) => {
+                return format!("无法按 {:?} 排序；请使用 \"id\" 或 \"name\"", order_by)
+            }
+            (Text::PhoneInvalid(number), En) => {
+                return format!("Not a valid phone number: {:?}", number)
+            }
+            (Text::PhoneInvalid(number), Zh) => return format!("无效的电话号码：{:?}", number),
+            (Text::PhoneInvalid(number), Es) => {
+                return format!("No es un número de teléfono válido: {:?}", number)
+            }
+            (Text::PhoneRegionRequired(number), En) => {
+                return format!(
+                    "Phone number {:?} needs a country code such as +1, as no default region is set",
+                    number
+                )
+            }
+            (Text::PhoneRegionRequired(number), Zh) => {
+                return format!("电话号码 {:?} 需要国家代码（如 +1），因为未设置默认地区", number)
+            }
+            (Text::PhoneRegionRequired(number), Es) => {
+                return format!(
+                    "El número de teléfono {:?} necesita un código de país como +1, porque no hay una región predeterminada",
+                    number
+                )
+            }
+            (Text::PhoneNotCanonical(number), En) => {
+                return format!(
+                    "Phone number {:?} must be in E.164 form, like +12025550123, and listed once",
+                    number
+                )
+            }
+            (Text::PhoneNotCanonical(number), Zh) => {
+                return format!(
+                    "电话号码 {:?} 必须为 E.164 格式（如 +12025550123），且只能列出一次",
+                    number
+                )
+            }
+            (Text::PhoneNotCanonical(number), Es) => {
+                return format!(
+                    "El número de teléfono {:?} debe estar en formato E.164, como +12025550123, y figurar una sola vez",
+                    number
+                )
+            }
+            (Text::AddressLineEmpty, En) => "Address line 1 cannot be empty",
+            (Text::AddressLineEmpty, Zh) => "地址第一行不能为空",
+            (Text::AddressLineEmpty, Es) => "La línea 1 de la dirección no puede estar vacía",
+            (Text::CityEmpty, En) => "Address city cannot be empty",
+            (Text::CityEmpty, Zh) => "地址中的城市不能为空",
+            (Text::CityEmpty, Es) => "La ciudad de la dirección no puede estar vacía",
+            (Text::CountryUnknown(country), En) => {
+                return format!("Unknown country {:?}; use an ISO 3166 code such as US", country)
+            }
+            (Text::CountryUnknown(country), Zh) => {
+                return format!("未知的国家 {:?}；请使用 ISO 3166 代码，如 US", country)
+            }
+            (Text::CountryUnknown(country), Es) => {
+                return format!("País desconocido {:?}; use un código ISO 3166 como US", country)
+            }
+            (Text::RegionUnknown(country, region), En) => {
+                return format!("{:?} is not a region of {}", region, country)
+            }
+            (Text::RegionUnknown(country, region), Zh) => {
+                return format!("{:?} 不是 {} 的地区", region, country)
+            }
+            (Text::RegionUnknown(country, region), Es) => {
+                return format!("{:?} no es una región de {}", region, country)
+            }
+            (Text::PostalCodeInvalid(country, postal_code), En) => {
+                return format!(
+                    "Postal code {:?} is not valid in {}; expected {}",
+                    postal_code,
+                    country,
+                    address::postal_code_formats(country)
+                )
+            }
+            (Text::PostalCodeInvalid(country, postal_code), Zh) => {
+                return format!(
+                    "邮政编码 {:?} 在 {} 无效；应为 {}",
+                    postal_code,
+                    country,
+                    address::postal_code_formats(country)
+                )
+            }
+            (Text::PostalCodeInvalid(country, postal_code), Es) => {
+                return format!(
+                    "El código postal {:?} no es válido en {}; se esperaba {}",
+                    postal_code,
+                    country,
+                    address::postal_code_formats(country)
+                )
+            }
+            (Text::AddressNotCanonical, En) => {
+                "Address parts must be written without extra spaces, with the country, region, and postal code as the country writes them"
+            }
+            (Text::AddressNotCanonical, Zh) => {
+                "地址各部分不能有多余空格，国家、地区和邮政编码须按该国的写法书写"
+            }
+            (Text::AddressNotCanonical, Es) => {
+                "Las partes de la dirección no pueden tener espacios de más, y el país, la región y el código postal deben escribirse como en ese país"
+            }
+            (Text::GpaInvalid(gpa), En) => {
+                return format!("Student GPA must be a decimal number such as 3.25, not {:?}", gpa)
+            }
+            (Text::GpaInvalid(gpa), Zh) => {
+                return format!("学生 GPA 必须是 3.25 这样的小数，而不是 {:?}", gpa)
+            }
+            (Text::GpaInvalid(gpa), Es) => {
+                return format!(
+                    "El GPA del estudiante debe ser un número decimal como 3.25, no {:?}",
+                    gpa
+                )
+            }
+            (Text::AnnotationKeyInvalid(key), En) => {
+                return format!(
+                    "Annotation key {:?} must be a name of letters, digits, '-', '_', and '.', optionally after a DNS name and '/'",
+                    key
+                )
+            }
+            (Text::AnnotationKeyInvalid(key), Zh) => {
+                return format!(
+                    "注解键 {:?} 必须由字母、数字、'-'、'_' 和 '.' 组成，前面可以加上 DNS 名称和 '/'",
+                    key
+                )
+            }
+            (Text::AnnotationKeyInvalid(key), Es) => {
+                return format!(
+                    "La clave de anotación {:?} debe ser un nombre de letras, dígitos, '-', '_' y '.', opcionalmente tras un nombre DNS y '/'",
+                    key
+                )
+            }
+            (Text::AnnotationsTooLarge, En) => {
+                return format!(
+                    "Annotations cannot hold more than {} bytes of keys and values",
+                    annotations::MAX_BYTES
+                )
+            }
+            (Text::AnnotationsTooLarge, Zh) => {
+                return format!("注解的键和值合计不能超过 {} 字节", annotations::MAX_BYTES)
+            }
+            (Text::AnnotationsTooLarge, Es) => {
+                return format!(
+                    "Las anotaciones no pueden ocupar más de {} bytes entre claves y valores",
+                    annotations::MAX_BYTES
+                )
+            }
+            (Text::BatchIdRepeated(id), En) => {
+                return format!("Student {:?} is written by more than one batch entry", id)
+            }
+            (Text::BatchIdRepeated(id), Zh) => return format!("学生 {:?} 被多个批量条目写入", id),
+            (Text::BatchIdRepeated(id), Es) => {
+                return format!(
+                    "El estudiante {:?} aparece en más de una entrada del lote",
+                    id
+                )
+            }
+            (Text::UnknownOrderBy(order_by), Es) => {
+                return format!("No se puede ordenar por {:?}; use \"id\" o \"name\"", order_by)
+            }
+            (Text::EmailChangeUnverified, En) => {
+                "Email changes must be verified; use RequestEmailChange"
+            }
+            (Text::EmailChangeUnverified, Zh) => "邮箱变更必须经过验证；请使用 RequestEmailChange",
+            (Text::EmailChangeUnverified, Es) => {
+                "Los cambios de correo deben verificarse; use RequestEmailChange"
+            }
+            (Text::BatchEntryEmpty, En) => {
+                "Each batch entry must create, update, or delete a student"
+            }
+            (Text::BatchEntryEmpty, Zh) => "每个批量条目都必须创建、更新或删除一名学生",
+            (Text::BatchEntryEmpty, Es) => {
+                "Cada entrada del lote debe crear, actualizar o eliminar un estudiante"
+            }
+            (Text::FieldRequired(field), En) => return format!("{} is required", field),
+            (Text::FieldRequired(field), Zh) => return format!("{} 为必填项", field),
+            (Text::FieldRequired(field), Es) => return format!("{} es obligatorio", field),
+            (Text::HookRejected(hook, message), En) => {
+                return format!("Rejected by {}: {}", hook, message)
+            }
+            (Text::HookRejected(hook, message), Zh) => {
+                return format!("被 {} 拒绝：{}", hook, message)
+            }
+            (Text::HookRejected(hook, message), Es) => {
+                return format!("Rechazado por {}: {}", hook, message)
+            }
+            (Text::AuthTokenInvalid, En) => "Invalid authorization token",
+            (Text::AuthTokenInvalid, Zh) => "无效的授权令牌",
+            (Text::AuthTokenInvalid, Es) => "Token de autorización no válido",
+            (Text::AuthTokenExpired, En) => "Authorization token has expired; get a new one",
+            (Text::AuthTokenExpired, Zh) => "授权令牌已过期；请重新获取",
+            (Text::AuthTokenExpired, Es) => "El token de autorización caducó; obtenga uno nuevo",
+            (Text::NotAuthorized(rpc), En) => {
+                return format!("You are not allowed to call {}", rpc)
+            }
+            (Text::NotAuthorized(rpc), Zh) => return format!("您无权调用 {}", rpc),
+            (Text::NotAuthorized(rpc), Es) => {
+                return format!("No tiene permiso para llamar a {}", rpc)
+            }
+            (Text::NotLeader(address), En) => {
+                return format!("This replica is not the leader; send writes to {}", address)
+            }
+            (Text::NotLeader(address), Zh) => {
+                return format!("此副本不是主节点；请将写请求发送到 {}", address)
+            }
+            (Text::NotLeader(address), Es) => {
+                return format!("Esta réplica no es la líder; envíe las escrituras a {}", address)
+            }
+            (Text::NoLeader, En) => "No replica is the leader yet; try again shortly",
+            (Text::NoLeader, Zh) => "目前还没有主节点；请稍后重试",
+            (Text::NoLeader, Es) => "Todavía no hay una réplica líder; inténtelo de nuevo en breve",
+            (Text::IdempotencyKeyInvalid, En) => {
+                return format!(
+                    "Idempotency key must be 1 to {} ASCII characters",
+                    idempotency::MAX_KEY_LENGTH
+                )
+            }
+            (Text::IdempotencyKeyInvalid, Zh) => {
+                return format!(
+                    "幂等键必须是 1 到 {} 个 ASCII 字符",
+                    idempotency::MAX_KEY_LENGTH
+                )
+            }
+            (Text::IdempotencyKeyInvalid, Es) => {
+                return format!(
+                    "La clave de idempotencia debe tener de 1 a {} caracteres ASCII",
+                    idempotency::MAX_KEY_LENGTH
+                )
+            }
+            (Text::IdempotencyKeyReused, En) => {
+                "Idempotency key was already used for a different request"
+            }
+            (Text::IdempotencyKeyReused, Zh) => "该幂等键已用于另一个请求",
+            (Text::IdempotencyKeyReused, Es) => {
+                "La clave de idempotencia ya se usó para otra solicitud"
+            }
+            (Text::StandingRequired, En) => "A standing is required",
+            (Text::StandingRequired, Zh) => "必须指定学业状态",
+            (Text::StandingRequired, Es) => "Se requiere una situación académica",
+            (Text::PageTokenInvalid, En) => "Invalid page token",
+            (Text::PageTokenInvalid, Zh) => "无效的分页令牌",
+            (Text::PageTokenInvalid, Es) => "Token de página no válido",
+            (Text::PageTokenExpired, En) => {
+                "Page token has expired; list again from the first page"
+            }
+            (Text::PageTokenExpired, Zh) => "分页令牌已过期；请从第一页重新列出",
+            (Text::PageTokenExpired, Es) => {
+                "El token de página caducó; vuelva a listar desde la primera página"
+            }
+            (Text::IdNotCanonical, En) => "Student ID must not start or end with spaces",
+            (Text::IdNotCanonical, Zh) => "学生 ID 的开头和结尾不能有空格",
+            (Text::IdNotCanonical, Es) => {
+                "El ID del estudiante no puede empezar ni terminar con espacios"
+            }
+            (Text::NameNotCanonical, En) => {
+                "Student name must be in Unicode NFC, without spaces around it or doubled spaces"
+            }
+            (Text::NameNotCanonical, Zh) => "学生姓名必须为 Unicode NFC 形式，前后不能有空格，中间也不能有连续空格",
+            (Text::NameNotCanonical, Es) => {
+                "El nombre del estudiante debe estar en Unicode NFC, sin espacios alrededor ni espacios dobles"
+            }
+            (Text::EmailNotCanonical, En) => {
+                "Student email must be lower case without spaces around it"
+            }
+            (Text::EmailNotCanonical, Zh) => "学生邮箱必须为小写，且前后不能有空格",
+            (Text::EmailNotCanonical, Es) => {
+                "El correo del estudiante debe estar en minúsculas y sin espacios alrededor"
+            }
+            (Text::EmailInvalid, En) => "Student email must look like name@domain.tld",
+            (Text::EmailInvalid, Zh) => "学生邮箱必须形如 name@domain.tld",
+            (Text::EmailInvalid, Es) => {
+                "El correo del estudiante debe tener la forma nombre@dominio.tld"
+            }
+            (Text::MajorNotCanonical, En) => {
+                "Student major must be in Unicode NFC, without spaces around it or doubled spaces"
+            }
+            (Text::MajorNotCanonical, Zh) => "专业名称必须为 Unicode NFC 形式，前后不能有空格，中间也不能有连续空格",
+            (Text::MajorNotCanonical, Es) => {
+                "La carrera debe estar en Unicode NFC, sin espacios alrededor ni espacios dobles"
+            }
+            (Text::MajorIdNotCanonical, En) => "Major ID must not start or end with spaces",
+            (Text::MajorIdNotCanonical, Zh) => "专业 ID 的开头和结尾不能有空格",
+            (Text::MajorIdNotCanonical, Es) => {
+                "El ID de carrera no puede empezar ni terminar con espacios"
+            }
+            (Text::GpaNotCanonical, En) => "Student GPA must have at most two decimal places",
+            (Text::GpaNotCanonical, Zh) => "学生 GPA 最多保留两位小数",
+            (Text::GpaNotCanonical, Es) => {
+                "El GPA del estudiante debe tener como máximo dos decimales"
+            }
+            (Text::PreferredNameNotCanonical, En) => {
+                "Preferred name must be in Unicode NFC, without spaces around it or doubled spaces"
+            }
+            (Text::PreferredNameNotCanonical, Zh) => "常用名必须为 Unicode NFC 形式，前后不能有空格，中间也不能有连续空格",
+            (Text::PreferredNameNotCanonical, Es) => {
+                "El nombre preferido debe estar en Unicode NFC, sin espacios alrededor ni espacios dobles"
+            }
+        };
+        text.to_string()
+    }
+
+    /// The message in the current request's locale.
+    pub fn localized(&self) -> String {
+        self.render(Locale::current())
+    }
+}
+
+/// A broken rule of the ones shared with the client, to say in any locale.
+impl From<Problem> for Text {
+    fn from(problem: Problem) -> Self {
+        match problem {
+            Problem::NameEmpty => Text::NameEmpty,
+            Problem::EmailEmpty => Text::EmailEmpty,
+            Problem::AgeOutOfRange => Text::AgeOutOfRange,
+            Problem::GpaOutOfRange => Text::GpaOutOfRange,
+            Problem::GpaInvalid(gpa) => Text::GpaInvalid(gpa),
+            Problem::CreditsNegative => Text::CreditsNegative,
+            Problem::AddressLineEmpty => Text::AddressLineEmpty,
+            Problem::CityEmpty => Text::CityEmpty,
+            Problem::CountryUnknown(country) => Text::CountryUnknown(country),
+            Problem::RegionUnknown(country, region) => Text::RegionUnknown(country, region),
+            Problem::PostalCodeInvalid(country, postal_code) => {
+                Text::PostalCodeInvalid(country, postal_code)
+            }
+            Problem::AnnotationKeyInvalid(key) => Text::AnnotationKeyInvalid(key),
+            Problem::AnnotationsTooLarge => Text::AnnotationsTooLarge,
+            Problem::IdNotCanonical => Text::IdNotCanonical,
+            Problem::NameNotCanonical => Text::NameNotCanonical,
+            Problem::PreferredNameNotCanonical => Text::PreferredNameNotCanonical,
+            Problem::EmailNotCanonical => Text::EmailNotCanonical,
+            Problem::EmailInvalid => Text::EmailInvalid,
+            Problem::MajorNotCanonical => Text::MajorNotCanonical,
+            Problem::MajorIdNotCanonical => Text::MajorIdNotCanonical,
+            Problem::GpaNotCanonical => Text::GpaNotCanonical,
+            Problem::AddressNotCanonical => Text::AddressNotCanonical,
+            Problem::PhoneInvalid(number) => Text::PhoneInvalid(number),
+            Problem::PhoneRegionRequired(number) => Text::PhoneRegionRequired(number),
+            Problem::PhoneNotCanonical(number) => Text::PhoneNotCanonical(number),
+        }
+    }
+}
+
+/// A status saying `text` in the current request's locale, with an
+/// `ErrorInfo` and a `LocalizedMessage` in its details.
+pub fn status(code: Code, text: Text) -> Status {
+    described(code, &text, Vec::new())
+}
+
+/// `INVALID_ARGUMENT` saying the first of `problems`, which must not be
+/// empty, with every one of them by field in a `google.rpc.BadRequest`.
+pub fn invalid<F: AsRef<str>>(problems: &[(F, Text)]) -> Status {
+    let locale = Locale::current();
+    let bad_request = BadRequest {
+        field_violations: problems
+            .iter()
+            .map(|(field, text)| FieldViolation {
+                field: field.as_ref().to_string(),
+                description: text.render(locale),
+            })
+            .collect(),
+    };
+    let details = vec![proto::Any {
+        type_url: "type.googleapis.com/google.rpc.BadRequest".to_string(),
+        value: bad_request.encode_to_vec().into(),
+    }];
+    described(Code::InvalidArgument, &problems[0].1, details)
+}
+
+// `text` as a status, with `details` after its `ErrorInfo` and
+// `LocalizedMessage`
+fn described(code: Code, text: &Text, mut details: Vec<proto::Any>) -> Status {
+    let locale = Locale::current();
+    let message = text.render(locale);
+    let info = ErrorInfo {
+        reason: text.reason().to_string(),
+        domain: ERROR_DOMAIN.to_string(),
+        metadata: text.metadata(),
+    };
+    let localized = LocalizedMessage {
+        locale: locale.tag().to_string(),
+        message: message.clone(),
+    };
+    details.splice(
+        0..0,
+        [
+            proto::Any {
+                type_url: "type.googleapis.com/google.rpc.ErrorInfo".to_string(),
+                value: info.encode_to_vec().into(),
+            },
+            proto::Any {
+                type_url: "type.googleapis.com/google.rpc.LocalizedMessage".to_string(),
+                value: localized.encode_to_vec().into(),
+            },
+        ],
+    );
+    let details = proto::google::rpc::Status {
+        code: code as i32,
+        message: message.clone(),
+        details,
+    };
+    Status::with_details(code, message, details.encode_to_vec().into())
+}
+
+/// `status` as the failure of entry `index` of a batch: the message starts
+/// with `entries[index]: `, its `ErrorInfo`, if any, gets the index as
+/// `entry` in its metadata, and the fields of its `BadRequest` start with
+/// `entries[index].`.
+pub fn in_entry(status: Status, index: usize) -> Status {
+    let message = format!("entries[{}]: {}", index, status.message());
+    let details = match proto::google::rpc::Status::decode(status.details()) {
+        Ok(details) if !status.details().is_empty() => details,
+        _ => return Status::new(status.code(), message),
+    };
+    let details = proto::google::rpc::Status {
+        message: message.clone(),
+        details: details
+            .details
+            .into_iter()
+            .map(|mut any| {
+                if any.type_url.ends_with("/google.rpc.ErrorInfo") {
+                    if let Ok(mut info) = ErrorInfo::decode(&any.value[..]) {
+                        info.metadata.insert("entry".to_string(), index.to_string());
+                        any.value = info.encode_to_vec().into();
+                    }
+                }
+                if any.type_url.ends_with("/google.rpc.BadRequest") {
+                    if let Ok(mut bad_request) = BadRequest::decode(&any.value[..]) {
+                        for violation in &mut bad_request.field_violations {
+                            violation.field = format!("entries[{}].{}", index, violation.field);
+                        }
+                        any.value = bad_request.encode_to_vec().into();
+                    }
+                }
+                any
+            })
+            .collect(),
+        ..details
+    };
+    Status::with_details(status.code(), message, details.encode_to_vec().into())
+}
+
+/// Handles every request in the locale its `accept-language` asks for.
+#[derive(Debug, Clone, Default)]
+pub struct LocaleLayer;
+
+impl<S> Layer<S> for LocaleLayer {
+    type Service = Localized<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Localized { inner }
+    }
+}
+
+/// The service produced by [`LocaleLayer`].
+#[derive(Debug, Clone)]
+pub struct Localized<S> {
+    inner: S,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for Localized<S>
+where
+    S: Service<Request<ReqBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = tokio::task::futures::TaskLocalFuture<Locale, S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let locale = request
+            .headers()
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+            .map_or_else(Locale::default, Locale::negotiate);
+        LOCALE.scope(locale, self.inner.call(request))
+    }
+}
@@ -0,0 +1,325 @@
+//! What the server logs, changeable while it runs.
+//!
+//! Log lines go through [`error!`], [`warn!`], [`info!`], and [`debug!`],
+//! which print only what the current [`Filter`] lets through for the module
+//! they are in. At debug level [`TimingLayer`](crate::timing::TimingLayer)
+//! also logs a sample of requests with their method and timings. Both can
+//! be changed through `LoggingService` or, in the server binary, by
+//! sending it SIGHUP to reread its configuration.
+
+use crate::errors::Error;
+use proto::logging_service_server::LoggingService;
+use proto::{GetLogConfigRequest, LogConfig, MaskMerge, UpdateLogConfigRequest};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use tonic::{Request, Response, Status};
+
+/// Print a line if the filter lets errors through for this module.
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::log!($crate::logging::Level::Error, $($arg)*)
+    };
+}
+
+/// Print a line if the filter lets warnings through for this module.
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        $crate::log!($crate::logging::Level::Warn, $($arg)*)
+    };
+}
+
+/// Print a line if the filter lets info through for this module.
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::log!($crate::logging::Level::Info, $($arg)*)
+    };
+}
+
+/// Print a line if the filter lets debug output through for this module.
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::log!($crate::logging::Level::Debug, $($arg)*)
+    };
+}
+
+/// Print a line at `level` if the filter lets it through for this module.
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)*) => {
+        if $crate::logging::enabled($level, module_path!()) {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// How much to log, from nothing to everything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "off" => Ok(Level::Off),
+            "error" => Ok(Level::Error),
+            "warn" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            _ => Err(format!(
+                "Unknown log level {:?}: expected off, error, warn, info, or debug",
+                text
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Level::Off => "off",
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+        })
+    }
+}
+
+// Modules are named as in the crate, e.g. `bulk` for `server::bulk`
+const CRATE_PREFIX: &str = "server::";
+
+/// A level for every module, then levels for single modules (and the
+/// modules inside them), written like `info,bulk=debug,eviction=off`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    default: Level,
+    modules: Vec<(String, Level)>,
+}
+
+impl Filter {
+    /// `level` for every module.
+    pub const fn new(level: Level) -> Self {
+        Self {
+            default: level,
+            modules: Vec::new(),
+        }
+    }
+
+    /// The level for the module at `module_path`, e.g. `server::bulk`: the
+    /// one for the closest module given, or the one for every module.
+    pub fn level(&self, module_path: &str) -> Level {
+        let module = module_path
+            .strip_prefix(CRATE_PREFIX)
+            .unwrap_or(module_path);
+        self.modules
+            .iter()
+            .filter(|(name, _)| {
+                module
+                    .strip_prefix(name.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .max_by_key(|(name, _)| name.len())
+            .map_or(self.default, |(_, level)| *level)
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::new(Level::Info)
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut filter = Filter::default();
+        let mut directives = text.split(',').map(str::trim).filter(|d| !d.is_empty());
+        let Some(first) = directives.next() else {
+            return Err("The log filter is empty".to_string());
+        };
+        for directive in std::iter::once(first).chain(directives) {
+            match directive.split_once('=') {
+                Some((module, level)) => {
+                    let module = module.trim();
+                    let module = module.strip_prefix(CRATE_PREFIX).unwrap_or(module);
+                    if module.is_empty() {
+                        return Err(format!("{}: expected a module before '='", directive));
+                    }
+                    filter.modules.retain(|(name, _)| name != module);
+                    filter.modules.push((module.to_string(), level.parse()?));
+                }
+                None => filter.default = directive.parse()?,
+            }
+        }
+        Ok(filter)
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.default)?;
+        for (module, level) in &self.modules {
+            write!(f, ",{}={}", module, level)?;
+        }
+        Ok(())
+    }
+}
+
+/// Everything that decides what is logged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub filter: Filter,
+    /// The share of requests, from 0 to 1, traced at debug level
+    pub trace_sample_rate: f64,
+}
+
+impl Config {
+    const DEFAULT: Config = Config {
+        filter: Filter::new(Level::Info),
+        trace_sample_rate: 1.0,
+    };
+
+    /// The configuration in a `LogConfig` message.
+    pub fn from_proto(config: &LogConfig) -> Result<Self, String> {
+        Ok(Self {
+            filter: config.filter.parse()?,
+            trace_sample_rate: check_sample_rate(config.trace_sample_rate)?,
+        })
+    }
+
+    pub fn to_proto(&self) -> LogConfig {
+        LogConfig {
+            filter: self.filter.to_string(),
+            trace_sample_rate: self.trace_sample_rate,
+        }
+    }
+
+    /// The configuration in a JSON `LogConfig` file, e.g.
+    /// `{"filter": "info,bulk=debug", "traceSampleRate": 0.1}`.
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        let config: LogConfig =
+            serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::from_proto(&config).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// A trace sample rate, from 0 to 1.
+pub fn parse_sample_rate(text: &str) -> Result<f64, String> {
+    text.trim()
+        .parse()
+        .map_err(|_| format!("{}: expected a number from 0 to 1", text))
+        .and_then(check_sample_rate)
+}
+
+fn check_sample_rate(rate: f64) -> Result<f64, String> {
+    if !(0.0..=1.0).contains(&rate) {
+        return Err(format!(
+            "Trace sample rate {}: expected a number from 0 to 1",
+            rate
+        ));
+    }
+    Ok(rate)
+}
+
+static CONFIG: RwLock<Config> = RwLock::new(Config::DEFAULT);
+
+// Requests considered for tracing so far
+static TRACE_CANDIDATES: AtomicU64 = AtomicU64::new(0);
+
+/// The configuration in effect.
+pub fn config() -> Config {
+    CONFIG.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Replace the configuration in effect, saying so unless logging is off.
+pub fn set_config(config: Config) {
+    if config.filter != Filter::new(Level::Off) {
+        println!(
+            "🔧 Logging {}, tracing {}% of requests at debug",
+            config.filter,
+            config.trace_sample_rate * 100.0
+        );
+    }
+    *CONFIG.write().unwrap_or_else(|e| e.into_inner()) = config;
+}
+
+/// Whether a line at `level` from the module at `module_path` is logged.
+pub fn enabled(level: Level, module_path: &str) -> bool {
+    let config = CONFIG.read().unwrap_or_else(|e| e.into_inner());
+    level != Level::Off && level <= config.filter.level(module_path)
+}
+
+/// Whether to trace the request now starting, from the module at
+/// `module_path`. Requests are picked evenly: at a rate of 0.25, every
+/// fourth one.
+pub fn trace(module_path: &str) -> bool {
+    if !enabled(Level::Debug, module_path) {
+        return false;
+    }
+    let rate = CONFIG
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .trace_sample_rate;
+    let n = TRACE_CANDIDATES.fetch_add(1, Ordering::Relaxed) as f64;
+    ((n + 1.0) * rate).floor() > (n * rate).floor()
+}
+
+#[derive(Debug, Default)]
+pub struct LoggingServiceImpl;
+
+#[tonic::async_trait]
+impl LoggingService for LoggingServiceImpl {
+    async fn get_log_config(
+        &self,
+        _request: Request<GetLogConfigRequest>,
+    ) -> Result<Response<LogConfig>, Status> {
+        Ok(Response::new(config().to_proto()))
+    }
+
+    async fn update_log_config(
+        &self,
+        request: Request<UpdateLogConfigRequest>,
+    ) -> Result<Response<LogConfig>, Status> {
+        let request = request.into_inner();
+        let new_values = request.config.unwrap_or_default();
+        let paths = request
+            .update_mask
+            .map(|mask| mask.paths)
+            .unwrap_or_default();
+
+        let mut updated = config().to_proto();
+        if paths.is_empty() {
+            updated = new_values;
+        } else {
+            updated
+                .merge_mask(&new_values, &paths)
+                .map_err(|e| Error::LoggingConfigInvalid(e.to_string()))?;
+        }
+        let updated = Config::from_proto(&updated).map_err(Error::LoggingConfigInvalid)?;
+        set_config(updated.clone());
+        Ok(Response::new(updated.to_proto()))
+    }
+}
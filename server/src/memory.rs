@@ -0,0 +1,117 @@
+//! Rough accounting of what the in-memory store holds.
+//!
+//! Sizes are estimates: the structs themselves plus the text they own,
+//! without allocator overhead. They are good for watching growth and for a
+//! cap that keeps a demo server from filling its machine, not for exact
+//! figures.
+
+use crate::events::Change;
+use proto::{Student, StudentEvent};
+use std::mem::size_of;
+use std::sync::Arc;
+
+// An Arc allocation also holds its strong and weak counts
+const ARC_COUNTS: usize = 2 * size_of::<usize>();
+
+// A B-tree node holds up to 11 entries, and nodes are between half full and
+// full; count each entry's share of its node's header and spare room as this
+const BTREE_ENTRY_OVERHEAD: usize = 16;
+
+// The text a student owns outside the struct, phone numbers, address, and
+// annotations included
+fn text_size(student: &Student) -> usize {
+    let fields: usize = [
+        &student.id,
+        &student.name,
+        &student.preferred_name,
+        &student.email,
+        &student.major,
+        &student.etag,
+        &student.major_id,
+    ]
+    .iter()
+    .map(|text| text.len())
+    .sum();
+    let address: usize = student.address.as_ref().map_or(0, |address| {
+        [
+            &address.line1,
+            &address.line2,
+            &address.city,
+            &address.region,
+            &address.postal_code,
+            &address.country,
+        ]
+        .iter()
+        .map(|text| text.len())
+        .sum()
+    });
+    let phone_numbers: usize = student
+        .phone_numbers
+        .iter()
+        .map(|number| size_of::<String>() + number.len())
+        .sum();
+    // A hash map's entries, with no share of its spare room
+    let annotations: usize = student
+        .annotations
+        .iter()
+        .map(|(key, value)| 2 * size_of::<String>() + key.len() + value.len())
+        .sum();
+    fields + phone_numbers + address + annotations
+}
+
+/// A stored student: the shared allocation and its text.
+pub fn student_size(student: &Student) -> usize {
+    ARC_COUNTS + size_of::<Student>() + text_size(student)
+}
+
+/// A student's entry in the ID index: its key and a pointer to the student.
+pub fn index_entry_size(id: &str) -> usize {
+    size_of::<String>() + id.len() + size_of::<Arc<Student>>() + BTREE_ENTRY_OVERHEAD
+}
+
+/// An event kept in the watch history.
+pub fn event_size(event: &StudentEvent) -> usize {
+    size_of::<Change>()
+        + event.student.as_ref().map_or(0, text_size)
+        + event.resume_token.len()
+}
+
+/// What the in-memory store holds, in bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StoreMemory {
+    pub records: usize,
+    pub index: usize,
+    /// Creates that would go over this fail
+    pub limit: Option<usize>,
+}
+
+impl StoreMemory {
+    /// Records and index together; what the limit applies to.
+    pub fn used(&self) -> usize {
+        self.records + self.index
+    }
+}
+
+/// Parse a size like `512MiB`, `64M`, `1GB`, or a plain number of bytes.
+/// Suffixes count in powers of 1024 either way.
+pub fn parse_size(text: &str) -> Result<usize, String> {
+    let text = text.trim();
+    let digits = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(digits);
+    let number: usize = number
+        .parse()
+        .map_err(|_| format!("{}: expected a size like 512MiB", text))?;
+    let scale: usize = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        _ => return Err(format!("{}: unknown unit {}", text, unit.trim())),
+    };
+    number
+        .checked_mul(scale)
+        .filter(|&bytes| bytes > 0)
+        .ok_or_else(|| format!("{}: size out of range", text))
+}
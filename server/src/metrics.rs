@@ -0,0 +1,262 @@
+//! Metrics for Prometheus to scrape.
+//!
+//! [`Metrics`] keeps counters, gauges, and histograms by name and labels,
+//! and writes them in the Prometheus text format or in OpenMetrics;
+//! [`crate::health::serve_http`] serves them as `/metrics` beside the probes
+//! on `--metrics-addr`. Each metric is declared once as a [`Metric`]
+//! constant by the module that updates it.
+//!
+//! A histogram bucket can keep an exemplar: the labels of one observation
+//! that fell in it, such as the trace ID of a slow request. Only
+//! OpenMetrics can carry them, so Prometheus sees them when it asks for
+//! that format, as it does with exemplar storage enabled.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Whether a metric only goes up, or is set to what it is now.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Counter,
+    Gauge,
+    /// Observations counted into [`BUCKETS`]
+    Histogram,
+}
+
+/// The upper bounds of every histogram's buckets, in seconds, as the
+/// Prometheus client libraries have them by default. A last bucket holds
+/// everything.
+pub const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// The content type of [`Metrics::render_openmetrics`].
+pub const OPENMETRICS: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// A metric's name, what it measures, and its kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metric {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub kind: Kind,
+}
+
+// The values of one metric, by their labels in order; a histogram's are
+// kept apart
+#[derive(Debug)]
+struct Family {
+    metric: Metric,
+    samples: BTreeMap<Vec<(String, String)>, f64>,
+    histograms: BTreeMap<Vec<(String, String)>, Histogram>,
+}
+
+// The observations in each bucket, not counting those below it, and the
+// latest exemplar in each
+#[derive(Debug, Default)]
+struct Histogram {
+    counts: [u64; BUCKETS.len() + 1],
+    sum: f64,
+    exemplars: [Option<Exemplar>; BUCKETS.len() + 1],
+}
+
+#[derive(Debug, Clone)]
+struct Exemplar {
+    labels: Vec<(String, String)>,
+    value: f64,
+}
+
+/// Every metric the server reports, shared by whatever updates them.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    families: Arc<Mutex<BTreeMap<&'static str, Family>>>,
+}
+
+fn label_set(labels: &[(&str, &str)]) -> Vec<(String, String)> {
+    labels
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+// A label value as the text format quotes it
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// `{name="value",...}`, or nothing without labels
+fn braces(labels: &[(String, String)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(label, value)| format!("{}=\"{}\"", label, escape(value)))
+        .collect();
+    format!("{{{}}}", labels.join(","))
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn families(&self) -> MutexGuard<'_, BTreeMap<&'static str, Family>> {
+        self.families.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn family<T>(&self, metric: &Metric, change: impl FnOnce(&mut Family) -> T) -> T {
+        let mut families = self.families();
+        let family = families.entry(metric.name).or_insert_with(|| Family {
+            metric: *metric,
+            samples: BTreeMap::new(),
+            histograms: BTreeMap::new(),
+        });
+        change(family)
+    }
+
+    fn sample<T>(
+        &self,
+        metric: &Metric,
+        labels: &[(&str, &str)],
+        change: impl FnOnce(&mut f64) -> T,
+    ) -> T {
+        self.family(metric, |family| {
+            change(family.samples.entry(label_set(labels)).or_default())
+        })
+    }
+
+    /// Add one to the counter `metric` with `labels`.
+    pub fn increment(&self, metric: &Metric, labels: &[(&str, &str)]) {
+        self.sample(metric, labels, |value| *value += 1.0);
+    }
+
+    /// Set the gauge `metric` with `labels` to `value`.
+    pub fn set(&self, metric: &Metric, labels: &[(&str, &str)], value: f64) {
+        self.sample(metric, labels, |sample| *sample = value);
+    }
+
+    /// Count `value` into the histogram `metric` with `labels`.
+    pub fn observe(&self, metric: &Metric, labels: &[(&str, &str)], value: f64) {
+        self.observe_with(metric, labels, value, None);
+    }
+
+    /// Count `value` into the histogram `metric` with `labels`, and keep
+    /// `exemplar` as the latest example of its bucket unless it is empty.
+    pub fn observe_with_exemplar(
+        &self,
+        metric: &Metric,
+        labels: &[(&str, &str)],
+        value: f64,
+        exemplar: &[(&str, &str)],
+    ) {
+        let exemplar = (!exemplar.is_empty()).then(|| Exemplar {
+            labels: label_set(exemplar),
+            value,
+        });
+        self.observe_with(metric, labels, value, exemplar);
+    }
+
+    fn observe_with(
+        &self,
+        metric: &Metric,
+        labels: &[(&str, &str)],
+        value: f64,
+        exemplar: Option<Exemplar>,
+    ) {
+        let bucket = BUCKETS
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(BUCKETS.len());
+        self.family(metric, |family| {
+            let histogram = family.histograms.entry(label_set(labels)).or_default();
+            histogram.counts[bucket] += 1;
+            histogram.sum += value;
+            if exemplar.is_some() {
+                histogram.exemplars[bucket] = exemplar;
+            }
+        });
+    }
+
+    /// The value of `metric` with `labels`, or the number of observations
+    /// of a histogram; 0 if it was never set.
+    pub fn get(&self, metric: &Metric, labels: &[(&str, &str)]) -> f64 {
+        let families = self.families();
+        let Some(family) = families.get(metric.name) else {
+            return 0.0;
+        };
+        let labels = label_set(labels);
+        match family.metric.kind {
+            Kind::Histogram => family
+                .histograms
+                .get(&labels)
+                .map_or(0.0, |histogram| histogram.counts.iter().sum::<u64>() as f64),
+            _ => family.samples.get(&labels).copied().unwrap_or_default(),
+        }
+    }
+
+    /// Every metric in the Prometheus text format, ordered by name.
+    pub fn render(&self) -> String {
+        self.write(false)
+    }
+
+    /// Every metric in OpenMetrics, ordered by name, with the exemplars of
+    /// histogram buckets.
+    pub fn render_openmetrics(&self) -> String {
+        let mut text = self.write(true);
+        text.push_str("# EOF\n");
+        text
+    }
+
+    fn write(&self, openmetrics: bool) -> String {
+        let mut text = String::new();
+        for family in self.families().values() {
+            let kind = match family.metric.kind {
+                Kind::Counter => "counter",
+                Kind::Gauge => "gauge",
+                Kind::Histogram => "histogram",
+            };
+            let name = family.metric.name;
+            // OpenMetrics names a counter without the `_total` of its samples
+            let family_name = match (openmetrics, family.metric.kind) {
+                (true, Kind::Counter) => name.strip_suffix("_total").unwrap_or(name),
+                _ => name,
+            };
+            let _ = writeln!(text, "# HELP {} {}", family_name, family.metric.help);
+            let _ = writeln!(text, "# TYPE {} {}", family_name, kind);
+            for (labels, value) in &family.samples {
+                let _ = writeln!(text, "{}{} {}", name, braces(labels), value);
+            }
+            for (labels, histogram) in &family.histograms {
+                histogram.write(&mut text, name, labels, openmetrics);
+            }
+        }
+        text
+    }
+}
+
+impl Histogram {
+    // Each bucket counts what is in it and below it, as `le` says
+    fn write(&self, text: &mut String, name: &str, labels: &[(String, String)], openmetrics: bool) {
+        let mut count = 0;
+        for (bucket, observed) in self.counts.iter().enumerate() {
+            count += observed;
+            let bound = match BUCKETS.get(bucket) {
+                Some(bound) => bound.to_string(),
+                None => "+Inf".to_string(),
+            };
+            let mut bucket_labels = labels.to_vec();
+            bucket_labels.push(("le".to_string(), bound));
+            let _ = write!(text, "{}_bucket{} {}", name, braces(&bucket_labels), count);
+            if let (true, Some(exemplar)) = (openmetrics, &self.exemplars[bucket]) {
+                let _ = write!(text, " # {} {}", braces(&exemplar.labels), exemplar.value);
+            }
+            text.push('\n');
+        }
+        let _ = writeln!(text, "{}_sum{} {}", name, braces(labels), self.sum);
+        let _ = writeln!(text, "{}_count{} {}", name, braces(labels), count);
+    }
+}
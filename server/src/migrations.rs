@@ -0,0 +1,93 @@
+//! Schema migrations for the SQL stores.
+//!
+//! Each change to the schema is a numbered SQL file under
+//! `server/migrations`, built into the server as one of [`MIGRATIONS`]. A
+//! store applies the ones its database has not had, in order, on startup or
+//! with `--migrate`, and records each in its `schema_migrations` table. A
+//! released migration is never edited; the next change is a new file.
+
+use crate::health::ReadinessCheck;
+use std::fmt::Debug;
+use std::sync::Arc;
+use tonic::Status;
+
+/// One change to the schema.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Migration {
+    /// Its place in the order, from 1 with no gaps.
+    pub version: i32,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+/// Every migration, oldest first.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "students",
+        sql: include_str!("../migrations/0001_students.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "student_outbox",
+        sql: include_str!("../migrations/0002_student_outbox.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "idempotency_keys",
+        sql: include_str!("../migrations/0003_idempotency_keys.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "leases",
+        sql: include_str!("../migrations/0004_leases.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "student_versions",
+        sql: include_str!("../migrations/0005_student_versions.sql"),
+    },
+];
+
+/// The version of the newest migration.
+pub fn latest() -> i32 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+/// The migrations a database at `version` has not had.
+pub fn pending(version: i32) -> &'static [Migration] {
+    let applied = MIGRATIONS
+        .iter()
+        .take_while(|migration| migration.version <= version)
+        .count();
+    &MIGRATIONS[applied..]
+}
+
+/// A store whose database has a schema.
+#[tonic::async_trait]
+pub trait SchemaStore: Debug + Send + Sync {
+    /// The version of the latest migration applied; 0 if there is none.
+    async fn schema_version(&self) -> Result<i32, Status>;
+}
+
+/// Ready once every migration has been applied.
+#[derive(Debug)]
+pub struct MigrationsCheck(pub Arc<dyn SchemaStore>);
+
+#[tonic::async_trait]
+impl ReadinessCheck for MigrationsCheck {
+    async fn check(&self) -> Result<(), String> {
+        let version = self
+            .0
+            .schema_version()
+            .await
+            .map_err(|status| status.message().to_string())?;
+        match pending(version).len() {
+            0 => Ok(()),
+            count => Err(format!(
+                "{} migrations to apply, from version {}",
+                count, version
+            )),
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Sending messages to people: verification codes and admin alerts.
+//!
+//! Services hold an `Arc<dyn Notifier>` so the way messages go out is
+//! chosen when the server starts (see [`from_url`]):
+//!
+//! - [`LogNotifier`] prints them, for development
+//! - [`SmtpNotifier`] hands them to a mail relay, without TLS or login
+//! - [`WebhookNotifier`] posts them as JSON to an HTTP endpoint
+//!
+//! [`MockNotifier`] keeps them for tests to read back.
+
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Method, Uri};
+use serde::Serialize;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::TcpStream;
+use tonic::Status;
+
+/// How long sending one message may take before it is given up on.
+pub const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A message for one recipient.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Message {
+    /// An email address
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Somewhere messages are sent.
+#[tonic::async_trait]
+pub trait Notifier: Debug + Send + Sync {
+    async fn send(&self, message: Message) -> Result<(), Status>;
+}
+
+/// Prints messages instead of sending them.
+#[derive(Debug, Default)]
+pub struct LogNotifier;
+
+#[tonic::async_trait]
+impl Notifier for LogNotifier {
+    async fn send(&self, message: Message) -> Result<(), Status> {
+        println!(
+            "📨 To {}: {}\n{}",
+            message.to, message.subject, message.body
+        );
+        Ok(())
+    }
+}
+
+/// Sends messages as plain-text email through an SMTP relay, e.g. a local
+/// Postfix or a test server like MailHog.
+#[derive(Debug, Clone)]
+pub struct SmtpNotifier {
+    // host:port
+    addr: String,
+    from: String,
+}
+
+type Replies = Lines<BufReader<OwnedReadHalf>>;
+
+impl SmtpNotifier {
+    /// Relay through `addr` (host:port), sending from address `from`.
+    pub fn new(addr: &str, from: &str) -> Self {
+        Self {
+            addr: addr.to_string(),
+            from: from.to_string(),
+        }
+    }
+
+    // The whole reply to the last command, which must have code `expected`
+    async fn expect(replies: &mut Replies, expected: u16) -> Result<(), Status> {
+        loop {
+            let line = replies
+                .next_line()
+                .await
+                .map_err(|e| Status::unavailable(format!("SMTP relay: {}", e)))?
+                .ok_or_else(|| Status::unavailable("SMTP relay closed the connection"))?;
+            // Lines of a multi-line reply have a '-' after the code
+            if line.as_bytes().get(3) == Some(&b'-') {
+                continue;
+            }
+            return match line.get(..3).and_then(|code| code.parse::<u16>().ok()) {
+                Some(code) if code == expected => Ok(()),
+                _ => Err(Status::unavailable(format!("SMTP relay said: {}", line))),
+            };
+        }
+    }
+
+    async fn converse(&self, message: &Message) -> Result<(), Status> {
+        let stream = TcpStream::connect(&self.addr)
+            .await
+            .map_err(|e| Status::unavailable(format!("SMTP relay {}: {}", self.addr, e)))?;
+        let (read, mut write) = stream.into_split();
+        let mut replies = BufReader::new(read).lines();
+        Self::expect(&mut replies, 220).await?;
+
+        // Lines starting with a dot are escaped with another
+        let mut data = format!(
+            "From: <{}>\r\nTo: <{}>\r\nSubject: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n",
+            self.from, message.to, message.subject
+        );
+        for line in message.body.lines() {
+            if line.starts_with('.') {
+                data.push('.');
+            }
+            data.push_str(line);
+            data.push_str("\r\n");
+        }
+        data.push('.');
+
+        for (command, expected) in [
+            ("EHLO localhost".to_string(), 250),
+            (format!("MAIL FROM:<{}>", self.from), 250),
+            (format!("RCPT TO:<{}>", message.to), 250),
+            ("DATA".to_string(), 354),
+            (data, 250),
+            ("QUIT".to_string(), 221),
+        ] {
+            write
+                .write_all(format!("{}\r\n", command).as_bytes())
+                .await
+                .map_err(|e| Status::unavailable(format!("SMTP relay: {}", e)))?;
+            Self::expect(&mut replies, expected).await?;
+        }
+        Ok(())
+    }
+}
+
+#[tonic::async_trait]
+impl Notifier for SmtpNotifier {
+    async fn send(&self, message: Message) -> Result<(), Status> {
+        // Line breaks would let the text add headers or commands of its own
+        if [&message.to, &message.subject]
+            .iter()
+            .any(|text| text.contains(['\r', '\n']))
+        {
+            return Err(Status::invalid_argument(
+                "Recipients and subjects cannot contain line breaks",
+            ));
+        }
+        tokio::time::timeout(SEND_TIMEOUT, self.converse(&message))
+            .await
+            .map_err(|_| Status::deadline_exceeded("SMTP relay did not answer in time"))?
+    }
+}
+
+/// Posts each message as a JSON object with `to`, `subject`, and `body`
+/// fields, e.g. to a chat integration. Only `http://` URLs are supported.
+#[derive(Debug, Clone)]
+pub struct WebhookNotifier {
+    url: Uri,
+    client: Client<HttpConnector>,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: &str) -> Result<Self, String> {
+        let url: Uri = url.parse().map_err(|e| format!("{}: {}", url, e))?;
+        if url.scheme_str() != Some("http") {
+            return Err(format!("{}: only http:// webhooks are supported", url));
+        }
+        Ok(Self {
+            url,
+            client: Client::new(),
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl Notifier for WebhookNotifier {
+    async fn send(&self, message: Message) -> Result<(), Status> {
+        let body = serde_json::to_vec(&message).map_err(|e| Status::internal(e.to_string()))?;
+        let request = hyper::Request::builder()
+            .method(Method::POST)
+            .uri(self.url.clone())
+            .header("content-type", "application/json")
+            .body(Body::from(body))
+            .map_err(|e| Status::internal(e.to_string()))?;
+        let response = tokio::time::timeout(SEND_TIMEOUT, self.client.request(request))
+            .await
+            .map_err(|_| Status::deadline_exceeded("Webhook did not answer in time"))?
+            .map_err(|e| Status::unavailable(format!("Webhook {}: {}", self.url, e)))?;
+        if !response.status().is_success() {
+            return Err(Status::unavailable(format!(
+                "Webhook {} answered {}",
+                self.url,
+                response.status()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Keeps messages instead of sending them, for tests.
+#[derive(Debug, Default)]
+pub struct MockNotifier {
+    sent: Mutex<Vec<Message>>,
+    failure: Mutex<Option<Status>>,
+}
+
+impl MockNotifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every message sent so far, oldest first.
+    pub fn sent(&self) -> Vec<Message> {
+        self.sent.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Fail every send with `status` from now on, or succeed again with `None`.
+    pub fn fail_with(&self, status: Option<Status>) {
+        *self.failure.lock().unwrap_or_else(|e| e.into_inner()) = status;
+    }
+}
+
+#[tonic::async_trait]
+impl Notifier for MockNotifier {
+    async fn send(&self, message: Message) -> Result<(), Status> {
+        if let Some(status) = &*self.failure.lock().unwrap_or_else(|e| e.into_inner()) {
+            return Err(status.clone());
+        }
+        self.sent
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(message);
+        Ok(())
+    }
+}
+
+/// The notifier `url` names: `log`, `smtp://host[:port]?from=address`, or
+/// an `http://` webhook URL.
+pub fn from_url(url: &str) -> Result<Arc<dyn Notifier>, String> {
+    if url == "log" {
+        return Ok(Arc::new(LogNotifier));
+    }
+    if let Some(rest) = url.strip_prefix("smtp://") {
+        let (addr, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let from = query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("from="))
+            .filter(|from| from.contains('@'))
+            .ok_or_else(|| format!("{}: expected ?from=<address>", url))?;
+        let addr = match addr.trim_end_matches('/') {
+            "" => return Err(format!("{}: expected a host", url)),
+            host if host.contains(':') => host.to_string(),
+            host => format!("{}:25", host),
+        };
+        return Ok(Arc::new(SmtpNotifier::new(&addr, from)));
+    }
+    if url.starts_with("http://") {
+        return Ok(Arc::new(WebhookNotifier::new(url)?));
+    }
+    Err(format!(
+        "{}: expected log, smtp://host[:port]?from=<address>, or an http:// URL",
+        url
+    ))
+}
+
+/// Alerts for whoever runs the server, e.g. when a scheduled task fails.
+#[derive(Debug, Clone)]
+pub struct Alerts {
+    notifier: Arc<dyn Notifier>,
+    to: String,
+}
+
+impl Alerts {
+    /// Send alerts to address `to` through `notifier`.
+    pub fn new(notifier: Arc<dyn Notifier>, to: &str) -> Self {
+        Self {
+            notifier,
+            to: to.to_string(),
+        }
+    }
+
+    /// Send an alert; failing to is only logged, as there is nobody to tell.
+    pub async fn send(&self, subject: &str, body: &str) {
+        let message = Message {
+            to: self.to.clone(),
+            subject: subject.to_string(),
+            body: body.to_string(),
+        };
+        if let Err(status) = self.notifier.send(message).await {
+            warn!(
+                "⚠️  Failed to send alert \"{}\": {}",
+                subject,
+                status.message()
+            );
+        }
+    }
+}
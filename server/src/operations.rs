@@ -0,0 +1,331 @@
+//! Long-running operations, modeled on google.longrunning.
+//!
+//! [`Operations`] runs background jobs and keeps track of them. A service
+//! starts a job with [`Operations::start`] and returns the [`Operation`] to
+//! its caller straight away; the job reports progress through its
+//! [`Progress`] handle, and its result is kept for the service to fetch with
+//! [`Operations::result`]. [`OperationsServiceImpl`] lets clients poll, list,
+//! and cancel operations of every kind. Operations are kept in memory until
+//! [`Operations::purge`] removes them or the server stops.
+
+use crate::clock::{self, Clock, SystemClock};
+use crate::errors::Error;
+use crate::pagination::PageTokens;
+use crate::repository::{next_page_token, page_offset};
+use proto::operations_service_server::OperationsService;
+use proto::{
+    CancelOperationRequest, GetOperationRequest, ListOperationsRequest, ListOperationsResponse,
+    Operation,
+};
+use std::any::Any;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
+use tokio::sync::watch;
+use tokio::task::AbortHandle;
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+
+type JobResult = Arc<dyn Any + Send + Sync>;
+
+#[derive(Debug)]
+struct Job {
+    // Position in start order
+    order: u64,
+    operation: Operation,
+    result: Option<JobResult>,
+    // Set to true once the operation is done
+    done: watch::Sender<bool>,
+    abort: Option<AbortHandle>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    jobs: Mutex<HashMap<String, Job>>,
+    started: AtomicU64,
+    clock: Arc<dyn Clock>,
+}
+
+impl Inner {
+    fn jobs(&self) -> MutexGuard<'_, HashMap<String, Job>> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn update(&self, id: &str, change: impl FnOnce(&mut Operation)) {
+        if let Some(job) = self.jobs().get_mut(id) {
+            change(&mut job.operation);
+        }
+    }
+
+    // The first outcome wins, so a job that finishes as it is cancelled
+    // keeps whichever came first
+    fn finish(&self, id: &str, outcome: Result<JobResult, Status>) {
+        let mut jobs = self.jobs();
+        let Some(job) = jobs.get_mut(id).filter(|job| !job.operation.done) else {
+            return;
+        };
+        job.operation.done = true;
+        job.operation.end_time = Some(clock::timestamp(self.clock.now()));
+        job.abort = None;
+        match outcome {
+            Ok(result) => {
+                info!("✅ Operation {} ({}) finished", id, job.operation.kind);
+                job.result = Some(result);
+            }
+            Err(status) => {
+                error!(
+                    "❌ Operation {} ({}) failed: {}",
+                    id,
+                    job.operation.kind,
+                    status.message()
+                );
+                job.operation.error = Some(proto::google::rpc::Status {
+                    code: status.code() as i32,
+                    message: status.message().to_string(),
+                    details: vec![],
+                });
+            }
+        }
+        job.done.send_replace(true);
+    }
+}
+
+/// Reports a job's progress on its operation.
+#[derive(Debug, Clone)]
+pub struct Progress {
+    id: String,
+    inner: Arc<Inner>,
+}
+
+impl Progress {
+    /// Set how many items the job expects to process.
+    pub fn set_total(&self, total: usize) {
+        self.inner.update(&self.id, |operation| {
+            operation.total_count = (total as i32).max(operation.processed_count);
+        });
+    }
+
+    /// Count `count` more items as processed.
+    pub fn advance(&self, count: usize) {
+        self.inner.update(&self.id, |operation| {
+            operation.processed_count += count as i32;
+            // The total may have been a guess, e.g. if items were added since
+            operation.total_count = operation.total_count.max(operation.processed_count);
+        });
+    }
+}
+
+/// Every operation the server has started.
+#[derive(Debug, Clone)]
+pub struct Operations {
+    inner: Arc<Inner>,
+}
+
+impl Default for Operations {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Operations {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                jobs: Mutex::new(HashMap::new()),
+                started: AtomicU64::new(0),
+                clock: Arc::new(SystemClock),
+            }),
+        }
+    }
+
+    /// Run `job` in the background as a new operation of `kind`.
+    ///
+    /// A job that fails, panics, or is cancelled leaves the operation with
+    /// an error and no result. Cancelling aborts the job's task at its next
+    /// `.await`, so a job should not leave half-done work across one.
+    pub fn start<T, F>(&self, kind: &str, job: impl FnOnce(Progress) -> F) -> Operation
+    where
+        T: Send + Sync + 'static,
+        F: Future<Output = Result<T, Status>> + Send + 'static,
+    {
+        let operation = Operation {
+            id: Uuid::new_v4().to_string(),
+            kind: kind.to_string(),
+            create_time: Some(clock::timestamp(self.inner.clock.now())),
+            ..Default::default()
+        };
+        let id = operation.id.clone();
+        let progress = Progress {
+            id: id.clone(),
+            inner: self.inner.clone(),
+        };
+
+        let job = job(progress);
+        // Held until the job is registered, so it cannot finish first
+        let mut jobs = self.inner.jobs();
+        let task = tokio::spawn(job);
+        let order = self.inner.started.fetch_add(1, Ordering::Relaxed);
+        jobs.insert(
+            id.clone(),
+            Job {
+                order,
+                operation: operation.clone(),
+                result: None,
+                done: watch::channel(false).0,
+                abort: Some(task.abort_handle()),
+            },
+        );
+        drop(jobs);
+
+        let inner = self.inner.clone();
+        tokio::spawn(async move {
+            let outcome = match task.await {
+                Ok(Ok(result)) => Ok(Arc::new(result) as JobResult),
+                Ok(Err(status)) => Err(status),
+                Err(e) if e.is_cancelled() => Err(Status::cancelled("The operation was cancelled")),
+                Err(_) => Err(Status::internal("The operation failed unexpectedly")),
+            };
+            inner.finish(&id, outcome);
+        });
+
+        info!("⏳ Started operation {} ({})", operation.id, kind);
+        operation
+    }
+
+    /// The current state of operation `id`.
+    pub fn get(&self, id: &str) -> Result<Operation, Status> {
+        self.inner
+            .jobs()
+            .get(id)
+            .map(|job| job.operation.clone())
+            .ok_or_else(|| not_found(id))
+    }
+
+    /// Wait for operation `id` to be done.
+    pub async fn wait(&self, id: &str) -> Result<Operation, Status> {
+        let mut done = self
+            .inner
+            .jobs()
+            .get(id)
+            .map(|job| job.done.subscribe())
+            .ok_or_else(|| not_found(id))?;
+        // Fails only if the operation was purged, which get() reports
+        let _ = done.wait_for(|done| *done).await;
+        self.get(id)
+    }
+
+    /// The result of finished operation `id`, if it succeeded with a `T`.
+    pub fn result<T: Send + Sync + 'static>(&self, id: &str) -> Option<Arc<T>> {
+        let result = self.inner.jobs().get(id)?.result.clone()?;
+        result.downcast().ok()
+    }
+
+    /// Ask operation `id` to stop, returning its state.
+    pub fn cancel(&self, id: &str) -> Result<Operation, Status> {
+        let jobs = self.inner.jobs();
+        let job = jobs.get(id).ok_or_else(|| not_found(id))?;
+        if let Some(abort) = &job.abort {
+            info!("🛑 Cancelling operation {} ({})", id, job.operation.kind);
+            abort.abort();
+        }
+        Ok(job.operation.clone())
+    }
+
+    /// Forget operations that finished before `cutoff`, returning how many.
+    pub fn purge(&self, cutoff: SystemTime) -> usize {
+        let cutoff = clock::timestamp(cutoff);
+        let cutoff = (cutoff.seconds, cutoff.nanos);
+        let mut jobs = self.inner.jobs();
+        let before = jobs.len();
+        jobs.retain(|_, job| match &job.operation.end_time {
+            Some(end) => (end.seconds, end.nanos) >= cutoff,
+            None => true,
+        });
+        before - jobs.len()
+    }
+
+    /// Operations of `kind` (or of every kind if empty), oldest first.
+    pub fn list(&self, kind: &str) -> Vec<Operation> {
+        let jobs = self.inner.jobs();
+        let mut matching: Vec<&Job> = jobs
+            .values()
+            .filter(|job| kind.is_empty() || job.operation.kind == kind)
+            .collect();
+        matching.sort_by_key(|job| job.order);
+        matching.iter().map(|job| job.operation.clone()).collect()
+    }
+}
+
+fn not_found(id: &str) -> Status {
+    Error::OperationNotFound(id.to_string()).into()
+}
+
+#[derive(Debug)]
+pub struct OperationsServiceImpl {
+    operations: Operations,
+    page_tokens: PageTokens,
+}
+
+impl OperationsServiceImpl {
+    pub fn new(operations: Operations) -> Self {
+        Self {
+            operations,
+            page_tokens: PageTokens::random(),
+        }
+    }
+
+    /// Sign page tokens with `page_tokens` instead of a random secret.
+    pub fn with_page_tokens(mut self, page_tokens: PageTokens) -> Self {
+        self.page_tokens = page_tokens;
+        self
+    }
+}
+
+#[tonic::async_trait]
+impl OperationsService for OperationsServiceImpl {
+    async fn get_operation(
+        &self,
+        request: Request<GetOperationRequest>,
+    ) -> Result<Response<Operation>, Status> {
+        let operation = self.operations.get(&request.into_inner().id)?;
+        Ok(Response::new(operation))
+    }
+
+    async fn list_operations(
+        &self,
+        request: Request<ListOperationsRequest>,
+    ) -> Result<Response<ListOperationsResponse>, Status> {
+        let request = request.into_inner();
+        let page_size = if request.page_size <= 0 {
+            10
+        } else {
+            request.page_size as usize
+        };
+
+        let operations = self.operations.list(&request.kind);
+        // A token only goes on with the kind it was made for
+        let listing = format!("operations/{}", request.kind);
+        let page_token = self.page_tokens.verify(&listing, &request.page_token)?;
+        let start = page_offset(&page_token, operations.len())?;
+        let end = (start + page_size).min(operations.len());
+
+        info!("Listed {} of {} operations", end - start, operations.len());
+
+        Ok(Response::new(ListOperationsResponse {
+            next_page_token: self
+                .page_tokens
+                .sign(&listing, next_page_token(end, operations.len())),
+            operations: operations[start..end].to_vec(),
+        }))
+    }
+
+    async fn cancel_operation(
+        &self,
+        request: Request<CancelOperationRequest>,
+    ) -> Result<Response<Operation>, Status> {
+        let operation = self.operations.cancel(&request.into_inner().id)?;
+        Ok(Response::new(operation))
+    }
+}
@@ -0,0 +1,84 @@
+//! Reliable event publishing through a transactional outbox.
+//!
+//! A persistent repository can record each change event in the same
+//! transaction as the change itself, so an event exists exactly when its
+//! change was committed. [`relay`] then moves recorded events to the
+//! [`EventLog`] and only removes them from the outbox once published,
+//! retrying with backoff while storage is unavailable. Events left over when
+//! the server stops are published on its next start.
+//!
+//! Delivery is at least once: if removing published events fails, they are
+//! published again. A repeated event carries the same student `etag`.
+
+use crate::events::EventLog;
+use proto::StudentEvent;
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tonic::Status;
+
+// Events moved per round trip to storage
+const BATCH: usize = 100;
+// How often to look for events when not woken up, e.g. for leftovers
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const RETRY_MIN: Duration = Duration::from_millis(100);
+const RETRY_MAX: Duration = Duration::from_secs(10);
+
+/// Change events recorded by a repository, waiting to be published.
+#[tonic::async_trait]
+pub trait Outbox: Debug + Send + Sync {
+    /// Up to `limit` unpublished events with their outbox IDs, oldest first.
+    async fn pending(&self, limit: usize) -> Result<Vec<(i64, StudentEvent)>, Status>;
+
+    /// Remove published events.
+    async fn acknowledge(&self, ids: &[i64]) -> Result<(), Status>;
+}
+
+// Publish one batch, returning how many events it had
+async fn publish_pending(outbox: &dyn Outbox, events: &EventLog) -> Result<usize, Status> {
+    let pending = outbox.pending(BATCH).await?;
+    if pending.is_empty() {
+        return Ok(0);
+    }
+
+    for (_, event) in &pending {
+        if let Some(student) = &event.student {
+            events.publish(event.change_type(), student);
+        }
+    }
+    let ids: Vec<i64> = pending.iter().map(|(id, _)| *id).collect();
+    outbox.acknowledge(&ids).await?;
+    Ok(pending.len())
+}
+
+/// Publish events from `outbox` to `events` forever.
+///
+/// Runs whenever `wake` is notified (after each change) and at least every
+/// second.
+pub async fn relay(outbox: Arc<dyn Outbox>, events: Arc<EventLog>, wake: Arc<Notify>) {
+    let mut retry = RETRY_MIN;
+    loop {
+        match publish_pending(&*outbox, &events).await {
+            Ok(published) => {
+                retry = RETRY_MIN;
+                // A full batch means there may be more waiting
+                if published < BATCH {
+                    tokio::select! {
+                        _ = wake.notified() => {}
+                        _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                    }
+                }
+            }
+            Err(status) => {
+                warn!(
+                    "⚠️  Failed to publish events, retrying in {:?}: {}",
+                    retry,
+                    status.message()
+                );
+                tokio::time::sleep(retry).await;
+                retry = (retry * 2).min(RETRY_MAX);
+            }
+        }
+    }
+}
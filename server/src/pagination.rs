@@ -0,0 +1,131 @@
+//! Page tokens that callers cannot forge or keep forever.
+//!
+//! Stores hand out plain offsets as page tokens. Before a service passes
+//! one on, [`PageTokens`] adds an expiry and an HMAC-SHA256 over both and
+//! over the listing the token belongs to, e.g. one standing's students.
+//! A token that was changed, made up, taken from another listing, or is
+//! past its expiry fails with `INVALID_ARGUMENT`, so callers cannot jump to
+//! offsets they were never given.
+//!
+//! Servers behind one address should share a secret (`--page-token-secret`)
+//! so a token from one is good at the others; without one each picks a
+//! random secret and its tokens die with it.
+
+use crate::clock::{Clock, SystemClock};
+use crate::encryption::from_hex;
+use crate::locale::{self, Text};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use tonic::{Code, Status};
+
+/// How long a page token is good for unless told otherwise.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+
+// Bytes of the HMAC kept in a token; plenty against guessing
+const TAG_LENGTH: usize = 16;
+
+/// Signs page tokens on the way out and checks them on the way in.
+#[derive(Clone)]
+pub struct PageTokens {
+    mac: Hmac<Sha256>,
+    ttl: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+// Never print the secret
+impl Debug for PageTokens {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PageTokens")
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PageTokens {
+    /// Sign with `secret`.
+    pub fn new(secret: &[u8]) -> Self {
+        Self {
+            mac: Hmac::new_from_slice(secret).expect("HMAC takes secrets of any length"),
+            ttl: DEFAULT_TTL,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Sign with a random secret, so only this process accepts the tokens.
+    pub fn random() -> Self {
+        let mut secret = [0; 32];
+        OsRng.fill_bytes(&mut secret);
+        Self::new(&secret)
+    }
+
+    /// Let tokens be used for `ttl` after they were handed out.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Use `clock` to tell when tokens expire.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn tag(&self, listing: &str, expires: u64, token: &str) -> Hmac<Sha256> {
+        let mut mac = self.mac.clone();
+        for part in [listing.as_bytes(), &expires.to_be_bytes(), token.as_bytes()] {
+            mac.update(&(part.len() as u64).to_be_bytes());
+            mac.update(part);
+        }
+        mac
+    }
+
+    fn now(&self) -> u64 {
+        let now = self.clock.now().duration_since(UNIX_EPOCH);
+        now.map_or(0, |since| since.as_secs())
+    }
+
+    /// The store's `token` for the next page of `listing`, signed. The empty
+    /// token, which ends a listing, stays empty.
+    pub fn sign(&self, listing: &str, token: String) -> String {
+        if token.is_empty() {
+            return token;
+        }
+        let expires = self.now().saturating_add(self.ttl.as_secs());
+        let tag = self.tag(listing, expires, &token).finalize().into_bytes();
+        let tag: String = tag[..TAG_LENGTH]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        format!("{}.{}.{}", expires, token, tag)
+    }
+
+    /// The store's token inside a signed page token for `listing`. The empty
+    /// token, which starts a listing, stays empty.
+    pub fn verify(&self, listing: &str, signed: &str) -> Result<String, Status> {
+        if signed.is_empty() {
+            return Ok(String::new());
+        }
+        let invalid = || locale::status(Code::InvalidArgument, Text::PageTokenInvalid);
+        let (expires, rest) = signed.split_once('.').ok_or_else(invalid)?;
+        let (token, tag) = rest.rsplit_once('.').ok_or_else(invalid)?;
+        let expires: u64 = expires.parse().map_err(|_| invalid())?;
+        let tag = from_hex(tag)
+            .filter(|tag| tag.len() == TAG_LENGTH)
+            .ok_or_else(invalid)?;
+        self.tag(listing, expires, token)
+            .verify_truncated_left(&tag)
+            .map_err(|_| invalid())?;
+        if expires <= self.now() {
+            return Err(locale::status(
+                Code::InvalidArgument,
+                Text::PageTokenExpired,
+            ));
+        }
+        Ok(token.to_string())
+    }
+}
@@ -0,0 +1,183 @@
+//! Write hooks compiled to WebAssembly, loaded with `--plugin`.
+//!
+//! A plugin is a `.wasm` (or `.wat`) module exporting:
+//!
+//! - `memory`, its linear memory;
+//! - `alloc(len: i32) -> i32`, returning where the server may write `len`
+//!   bytes of input;
+//! - `check(ptr: i32, len: i32) -> i64`, called with the student as
+//!   proto3 JSON, as `GetStudent` would return it over gRPC-Web.
+//!
+//! `check` returns 0 to let the student through. Anything else is the
+//! address of a JSON reply in the high 32 bits and its length in the low
+//! 32: `{"student": {...}}` to write that student instead, or
+//! `{"field": "email", "message": "..."}` to reject the write. A plugin may
+//! import `host.log(ptr: i32, len: i32)` to log a line of UTF-8.
+//!
+//! Each call gets a fresh instance, so nothing carries over between
+//! students, and runs with a fuel budget and a cap on memory; a plugin
+//! that runs out of either fails the write instead of holding it up.
+
+use crate::hooks::{Verdict, WriteHook};
+use proto::Student;
+use serde::Deserialize;
+use std::fmt;
+use std::path::Path;
+use wasmtime::{
+    Caller, Config, Engine, Error, Extern, Instance, Linker, Module, Store, StoreLimits,
+    StoreLimitsBuilder,
+};
+
+// Enough for JSON-parsing a student many times over
+const FUEL: u64 = 50_000_000;
+const MEMORY_BYTES: usize = 16 << 20;
+
+struct State {
+    plugin: String,
+    limits: StoreLimits,
+}
+
+#[derive(Debug, Deserialize)]
+struct Reply {
+    student: Option<Student>,
+    #[serde(default)]
+    field: String,
+    #[serde(default)]
+    message: String,
+}
+
+/// A plugin, compiled and ready to check students.
+pub struct Plugin {
+    name: String,
+    engine: Engine,
+    module: Module,
+    linker: Linker<State>,
+}
+
+impl Plugin {
+    /// The plugin at `path`, named after its file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let name = path.file_stem().map_or_else(
+            || path.display().to_string(),
+            |stem| stem.to_string_lossy().into_owned(),
+        );
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(|e| format!("{}: {:#}", path.display(), e))?;
+        let module =
+            Module::from_file(&engine, path).map_err(|e| format!("{}: {:#}", path.display(), e))?;
+        let mut linker = Linker::new(&engine);
+        linker
+            .func_wrap("host", "log", log)
+            .map_err(|e| format!("{}: {:#}", path.display(), e))?;
+        let plugin = Self {
+            name,
+            engine,
+            module,
+            linker,
+        };
+
+        // Missing exports and unknown imports are found now, not on the first write
+        let mut store = plugin.store();
+        plugin
+            .instantiate(&mut store)
+            .map_err(|e| format!("{}: {:#}", path.display(), e))?;
+        Ok(plugin)
+    }
+
+    fn store(&self) -> Store<State> {
+        let limits = StoreLimitsBuilder::new().memory_size(MEMORY_BYTES).build();
+        let mut store = Store::new(
+            &self.engine,
+            State {
+                plugin: self.name.clone(),
+                limits,
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        store
+    }
+
+    fn instantiate(&self, store: &mut Store<State>) -> Result<Instance, Error> {
+        store.set_fuel(FUEL)?;
+        let instance = self.linker.instantiate(&mut *store, &self.module)?;
+        instance
+            .get_memory(&mut *store, "memory")
+            .ok_or_else(|| Error::msg("the module does not export its memory"))?;
+        instance.get_typed_func::<i32, i32>(&mut *store, "alloc")?;
+        instance.get_typed_func::<(i32, i32), i64>(&mut *store, "check")?;
+        Ok(instance)
+    }
+
+    // The plugin's reply about `input`, if it has one
+    fn call(&self, input: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let mut store = self.store();
+        let instance = self.instantiate(&mut store)?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| Error::msg("the module does not export its memory"))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+        let check = instance.get_typed_func::<(i32, i32), i64>(&mut store, "check")?;
+
+        let len = i32::try_from(input.len())?;
+        let ptr = alloc.call(&mut store, len)?;
+        memory.write(&mut store, ptr as u32 as usize, input)?;
+        let reply = check.call(&mut store, (ptr, len))? as u64;
+        if reply == 0 {
+            return Ok(None);
+        }
+        let len = (reply & 0xffff_ffff) as usize;
+        if len > memory.data_size(&store) {
+            return Err(Error::msg("the reply is longer than the module's memory"));
+        }
+        let mut output = vec![0; len];
+        memory.read(&store, (reply >> 32) as usize, &mut output)?;
+        Ok(Some(output))
+    }
+}
+
+impl fmt::Debug for Plugin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Plugin").field("name", &self.name).finish()
+    }
+}
+
+impl WriteHook for Plugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn check(&self, student: &Student) -> Result<Verdict, String> {
+        let input = serde_json::to_vec(student).map_err(|e| e.to_string())?;
+        let Some(output) = self.call(&input).map_err(|e| format!("{:#}", e))? else {
+            return Ok(Verdict::Accept);
+        };
+        let reply: Reply =
+            serde_json::from_slice(&output).map_err(|e| format!("Cannot read the reply: {}", e))?;
+        match reply {
+            Reply {
+                student: Some(student),
+                ..
+            } => Ok(Verdict::Replace(Box::new(student))),
+            Reply { field, message, .. } if !message.is_empty() => {
+                Ok(Verdict::Reject { field, message })
+            }
+            _ => Err("The reply has neither a student nor a message".to_string()),
+        }
+    }
+}
+
+// `host.log`: a line from the plugin, logged under its name
+fn log(mut caller: Caller<'_, State>, ptr: i32, len: i32) {
+    let Some(Extern::Memory(memory)) = caller.get_export("memory") else {
+        return;
+    };
+    let mut line = vec![0; (len.max(0) as usize).min(memory.data_size(&caller))];
+    if memory.read(&caller, ptr as u32 as usize, &mut line).is_ok() {
+        info!(
+            "🧩 {}: {}",
+            caller.data().plugin,
+            String::from_utf8_lossy(&line)
+        );
+    }
+}
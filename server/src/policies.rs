@@ -0,0 +1,360 @@
+//! Request policies written in Rhai, one script per RPC.
+//!
+//! [`PolicyLayer`] runs the script configured for a method on each of its
+//! requests before the handler sees it. The script gets the request as a
+//! map in proto3 JSON form (`request`), the method name (`rpc`), and the
+//! tenant the request is from, or "" (`tenant`). What it evaluates to
+//! decides:
+//!
+//! - nothing (`()`): the request goes through as it is;
+//! - a map: the request goes through as that map says instead;
+//! - a string: the request is refused with `PERMISSION_DENIED`, and the
+//!   string as the reason.
+//!
+//! Scripts are reread when their file changes, so a policy can be changed
+//! without restarting; one that no longer compiles is logged and the last
+//! good version stays in effect. Like [`RequiredLayer`](crate::required::RequiredLayer),
+//! only requests the server reads as a single binary message are checked.
+
+use crate::flags;
+use crate::locale::{self, Text};
+use crate::required;
+use hyper::Body;
+use prost::Message;
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor};
+use rhai::{Dynamic, Engine, Scope, AST};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+use tonic::body::BoxBody;
+use tonic::codegen::http::{Request, Response};
+use tonic::codegen::Service;
+use tonic::{Code, Status};
+use tower_layer::Layer;
+
+// Enough for any sensible policy; a script stuck in a loop fails instead
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// What a policy decided about a request.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    Allow,
+    /// Pass this request on instead, in proto3 JSON form
+    Replace(serde_json::Value),
+    /// Refuse the request, saying why
+    Deny(String),
+}
+
+struct Script {
+    modified: Option<SystemTime>,
+    ast: Arc<AST>,
+}
+
+/// A policy script, reread whenever its file changes.
+pub struct Policy {
+    name: String,
+    path: PathBuf,
+    engine: Engine,
+    script: RwLock<Script>,
+}
+
+impl Policy {
+    /// The script at `path`, named after its file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let name = path.file_stem().map_or_else(
+            || path.display().to_string(),
+            |stem| stem.to_string_lossy().into_owned(),
+        );
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let modified = modified(path);
+        let ast = compile(&engine, path)?;
+        Ok(Self {
+            name,
+            path: path.to_path_buf(),
+            engine,
+            script: RwLock::new(Script {
+                modified,
+                ast: Arc::new(ast),
+            }),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // The script as its file is now, or the last version that compiled
+    fn current(&self) -> Arc<AST> {
+        let modified = modified(&self.path);
+        let script = self.script.read().unwrap_or_else(|e| e.into_inner());
+        if modified.is_none() || modified == script.modified {
+            return script.ast.clone();
+        }
+        drop(script);
+
+        let mut script = self.script.write().unwrap_or_else(|e| e.into_inner());
+        if modified != script.modified {
+            // Tried once per change, so a broken script is logged only once
+            script.modified = modified;
+            match compile(&self.engine, &self.path) {
+                Ok(ast) => {
+                    info!("📜 Reloaded policy {}", self.name);
+                    script.ast = Arc::new(ast);
+                }
+                Err(e) => error!("❌ Policy {} not reloaded: {}", self.name, e),
+            }
+        }
+        script.ast.clone()
+    }
+
+    /// The decision on `request`, a request to the method named `rpc` from
+    /// `tenant`, or why the script could not reach one.
+    pub fn decide(
+        &self,
+        rpc: &str,
+        tenant: &str,
+        request: &serde_json::Value,
+    ) -> Result<Decision, String> {
+        let mut scope = Scope::new();
+        scope.push_dynamic(
+            "request",
+            rhai::serde::to_dynamic(request).map_err(|e| e.to_string())?,
+        );
+        scope.push_constant("rpc", rpc.to_string());
+        scope.push_constant("tenant", tenant.to_string());
+        let result: Dynamic = self
+            .engine
+            .eval_ast_with_scope(&mut scope, &self.current())
+            .map_err(|e| e.to_string())?;
+        if result.is_unit() {
+            return Ok(Decision::Allow);
+        }
+        if result.is_string() {
+            return Ok(Decision::Deny(result.to_string()));
+        }
+        if result.is_map() {
+            let request = rhai::serde::from_dynamic(&result).map_err(|e| e.to_string())?;
+            return Ok(Decision::Replace(request));
+        }
+        Err(format!(
+            "The script returned {}; expected nothing, a map, or a string",
+            result.type_name()
+        ))
+    }
+}
+
+impl fmt::Debug for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Policy")
+            .field("name", &self.name)
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn compile(engine: &Engine, path: &Path) -> Result<AST, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    engine
+        .compile(text)
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// A policy setting from its command-line form, `RPC=PATH`: the method,
+/// as `CreateStudent` or `student.StudentService/CreateStudent`, and the
+/// script, loaded.
+pub fn parse_setting(text: &str) -> Result<(String, Arc<Policy>), String> {
+    let (rpc, path) = text
+        .split_once('=')
+        .ok_or_else(|| format!("{}: expected RPC=PATH", text))?;
+    let policy = Policy::load(Path::new(path.trim()))?;
+    Ok((rpc.trim().to_string(), Arc::new(policy)))
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    method: String,
+    input: MessageDescriptor,
+    policy: Arc<Policy>,
+}
+
+/// Runs each RPC's policy on its requests.
+#[derive(Debug, Clone)]
+pub struct PolicyLayer {
+    pool: DescriptorPool,
+    // By request path, e.g. `/student.StudentService/CreateStudent`
+    policies: Arc<HashMap<String, Entry>>,
+}
+
+impl PolicyLayer {
+    /// No policies: every request goes through.
+    pub fn new() -> Self {
+        Self {
+            pool: DescriptorPool::decode(proto::FILE_DESCRIPTOR_SET)
+                .expect("the proto crate's descriptor set is valid"),
+            policies: Arc::new(HashMap::new()),
+        }
+    }
+
+    /// Run `policy` on requests to `rpc`, either `CreateStudent` or, where
+    /// more than one service has a method of that name,
+    /// `student.StudentService/CreateStudent`.
+    pub fn with_policy(mut self, rpc: &str, policy: Arc<Policy>) -> Result<Self, String> {
+        let (service, name) = match rpc.split_once('/') {
+            Some((service, name)) => (Some(service), name),
+            None => (None, rpc),
+        };
+        let methods: Vec<_> = self
+            .pool
+            .services()
+            .filter(|candidate| service.is_none_or(|service| candidate.full_name() == service))
+            .filter_map(|service| service.methods().find(|method| method.name() == name))
+            .collect();
+        let method = match methods.as_slice() {
+            [method] => method,
+            [] => return Err(format!("No RPC named {}", rpc)),
+            _ => {
+                return Err(format!(
+                    "More than one service has an RPC named {}; name the service too",
+                    rpc
+                ))
+            }
+        };
+        if method.is_client_streaming() {
+            return Err(format!(
+                "{}: client-streaming RPCs cannot have policies",
+                rpc
+            ));
+        }
+        let path = format!("/{}/{}", method.parent_service().full_name(), method.name());
+        let mut policies = HashMap::clone(&self.policies);
+        if policies.contains_key(&path) {
+            return Err(format!("{} has more than one policy", rpc));
+        }
+        policies.insert(
+            path,
+            Entry {
+                method: method.name().to_string(),
+                input: method.input(),
+                policy,
+            },
+        );
+        self.policies = Arc::new(policies);
+        Ok(self)
+    }
+}
+
+impl Default for PolicyLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for PolicyLayer {
+    type Service = Policed<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Policed {
+            inner,
+            policies: self.policies.clone(),
+        }
+    }
+}
+
+/// The service produced by [`PolicyLayer`].
+#[derive(Debug, Clone)]
+pub struct Policed<S> {
+    inner: S,
+    policies: Arc<HashMap<String, Entry>>,
+}
+
+impl<S> Service<Request<Body>> for Policed<S>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        if !required::binary(&request) || !self.policies.contains_key(request.uri().path()) {
+            return Box::pin(self.inner.call(request));
+        }
+
+        // The clone that was made ready is the one that must be called
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let policies = self.policies.clone();
+        Box::pin(async move {
+            let entry = &policies[request.uri().path()];
+            let (parts, body) = request.into_parts();
+            let bytes = match hyper::body::to_bytes(body).await {
+                Ok(bytes) => bytes,
+                Err(error) => return Ok(Status::from_error(Box::new(error)).to_http()),
+            };
+            let Some(message) = required::message(&bytes)
+                .and_then(|message| DynamicMessage::decode(entry.input.clone(), message).ok())
+            else {
+                return inner
+                    .call(Request::from_parts(parts, Body::from(bytes)))
+                    .await;
+            };
+            match police(entry, &message) {
+                Ok(None) => {
+                    inner
+                        .call(Request::from_parts(parts, Body::from(bytes)))
+                        .await
+                }
+                Ok(Some(replaced)) => {
+                    inner
+                        .call(Request::from_parts(parts, Body::from(replaced)))
+                        .await
+                }
+                Err(status) => Ok(status.to_http()),
+            }
+        })
+    }
+}
+
+// The request `entry`'s policy passes on in place of `message`, framed,
+// if it changed it
+fn police(entry: &Entry, message: &DynamicMessage) -> Result<Option<Vec<u8>>, Status> {
+    let policy = &entry.policy;
+    let failed = |e: String| {
+        error!("❌ Policy {} failed: {}", policy.name(), e);
+        Status::internal(format!("Policy {} failed", policy.name()))
+    };
+    let request = serde_json::to_value(message).map_err(|e| failed(e.to_string()))?;
+    let tenant = flags::current_tenant().unwrap_or_default();
+    match policy
+        .decide(&entry.method, &tenant, &request)
+        .map_err(failed)?
+    {
+        Decision::Allow => Ok(None),
+        Decision::Deny(reason) => Err(locale::status(
+            Code::PermissionDenied,
+            Text::HookRejected(policy.name().to_string(), reason),
+        )),
+        Decision::Replace(request) => {
+            let replaced = DynamicMessage::deserialize(entry.input.clone(), request)
+                .map_err(|e| failed(e.to_string()))?
+                .encode_to_vec();
+            Ok(Some(required::frame(&replaced)))
+        }
+    }
+}
@@ -0,0 +1,318 @@
+//! Professors and advisor assignment.
+//!
+//! Professors, in a [`crate::crud`] collection, and the student-to-advisor
+//! links are kept in memory. A link whose student has since been deleted is
+//! dropped the next time it is read.
+
+use crate::catalog::Catalog;
+use crate::crud::{Collection, Entity};
+use crate::errors::Error;
+use crate::locale::{self, Text};
+use crate::repository::StudentRepository;
+use proto::professor_service_server::ProfessorService;
+use proto::{
+    AdviseePolicy, AssignAdvisorRequest, AssignAdvisorResponse, CreateProfessorRequest,
+    CreateProfessorResponse, DeleteProfessorRequest, DeleteProfessorResponse, GetAdvisorRequest,
+    GetAdvisorResponse, GetProfessorRequest, GetProfessorResponse, ListAdviseesRequest,
+    ListAdviseesResponse, ListProfessorsRequest, ListProfessorsResponse, Professor,
+    UpdateProfessorRequest, UpdateProfessorResponse,
+};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tonic::{Code, Request, Response, Status};
+
+impl Entity for Professor {
+    const KIND: &'static str = "Professor";
+    const NOT_FOUND: Error = Error::ProfessorNotFound;
+    const EXISTS: Error = Error::ProfessorExists;
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn id_mut(&mut self) -> &mut String {
+        &mut self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    professors: Collection<Professor>,
+    // Student ID to the ID of their advisor
+    advisors: BTreeMap<String, String>,
+}
+
+impl State {
+    fn advisees(&self, professor_id: &str) -> Vec<String> {
+        self.advisors
+            .iter()
+            .filter(|(_, advisor)| *advisor == professor_id)
+            .map(|(student, _)| student.clone())
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct ProfessorServiceImpl {
+    catalog: Arc<Catalog>,
+    students: Arc<dyn StudentRepository>,
+    state: Mutex<State>,
+}
+
+impl ProfessorServiceImpl {
+    /// Departments are checked against `catalog`; advisees are looked up in `students`.
+    pub fn new(catalog: Arc<Catalog>, students: Arc<dyn StudentRepository>) -> Self {
+        Self {
+            catalog,
+            students,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The professor with this ID.
+    pub fn professor(&self, id: &str) -> Option<Professor> {
+        self.state().professors.get(id).cloned()
+    }
+
+    /// The ID of the advisor of the student with this ID, if they have one.
+    pub fn advisor(&self, student_id: &str) -> Option<String> {
+        self.state().advisors.get(student_id).cloned()
+    }
+
+    /// Hand student `from`'s advisor to student `to`, returning the
+    /// advisor's ID, unless `to` already has one; either way `from` is left
+    /// without. With `only`, the link moves just if it is to that advisor.
+    pub fn transfer_advisee(&self, from: &str, to: &str, only: Option<&str>) -> Option<String> {
+        let mut state = self.state();
+        let advisor = state.advisors.get(from)?;
+        if only.is_some_and(|only| only != advisor) {
+            return None;
+        }
+        let advisor = state.advisors.remove(from)?;
+        if state.advisors.contains_key(to) {
+            return None;
+        }
+        state.advisors.insert(to.to_string(), advisor.clone());
+        Some(advisor)
+    }
+
+    fn check_professor(&self, professor: &Professor) -> Result<(), Status> {
+        if professor.name.trim().is_empty() {
+            return Err(Error::ProfessorNameEmpty.into());
+        }
+        if !professor.department_id.is_empty()
+            && self.catalog.department(&professor.department_id).is_none()
+        {
+            return Err(Error::UnknownDepartment(professor.department_id.clone()).into());
+        }
+        Ok(())
+    }
+}
+
+#[tonic::async_trait]
+impl ProfessorService for ProfessorServiceImpl {
+    async fn create_professor(
+        &self,
+        request: Request<CreateProfessorRequest>,
+    ) -> Result<Response<CreateProfessorResponse>, Status> {
+        let professor = request.into_inner().professor.unwrap_or_default();
+
+        self.check_professor(&professor)?;
+        let professor = self.state().professors.create(professor)?;
+
+        Ok(Response::new(CreateProfessorResponse {
+            professor: Some(professor),
+        }))
+    }
+
+    async fn get_professor(
+        &self,
+        request: Request<GetProfessorRequest>,
+    ) -> Result<Response<GetProfessorResponse>, Status> {
+        let id = request.into_inner().id;
+
+        let professor = self.state().professors.find(&id)?.clone();
+
+        Ok(Response::new(GetProfessorResponse {
+            professor: Some(professor),
+        }))
+    }
+
+    async fn update_professor(
+        &self,
+        request: Request<UpdateProfessorRequest>,
+    ) -> Result<Response<UpdateProfessorResponse>, Status> {
+        let professor = request.into_inner().professor.unwrap_or_default();
+
+        self.check_professor(&professor)?;
+        let professor = self.state().professors.update(professor)?;
+
+        Ok(Response::new(UpdateProfessorResponse {
+            professor: Some(professor),
+        }))
+    }
+
+    async fn delete_professor(
+        &self,
+        request: Request<DeleteProfessorRequest>,
+    ) -> Result<Response<DeleteProfessorResponse>, Status> {
+        let request = request.into_inner();
+        let policy = request.advisee_policy();
+
+        let mut state = self.state();
+        state.professors.find(&request.id)?;
+        let advisees = state.advisees(&request.id);
+
+        match policy {
+            AdviseePolicy::Unspecified if !advisees.is_empty() => {
+                return Err(Error::ProfessorHasAdvisees(advisees.len()).into());
+            }
+            AdviseePolicy::Unspecified => {}
+            AdviseePolicy::Reassign => {
+                if request.reassign_to == request.id
+                    || !state.professors.contains(&request.reassign_to)
+                {
+                    return Err(Error::ReassignToInvalid.into());
+                }
+                for student in &advisees {
+                    state
+                        .advisors
+                        .insert(student.clone(), request.reassign_to.clone());
+                }
+            }
+            AdviseePolicy::Unassign => {
+                for student in &advisees {
+                    state.advisors.remove(student);
+                }
+            }
+        }
+        state.professors.delete(&request.id)?;
+
+        info!(
+            "Advisees of professor {}: {} {}",
+            request.id,
+            advisees.len(),
+            match policy {
+                AdviseePolicy::Reassign => "reassigned",
+                _ => "unassigned",
+            }
+        );
+
+        Ok(Response::new(DeleteProfessorResponse {
+            success: true,
+            affected_advisees: advisees.len() as i32,
+        }))
+    }
+
+    async fn list_professors(
+        &self,
+        request: Request<ListProfessorsRequest>,
+    ) -> Result<Response<ListProfessorsResponse>, Status> {
+        let request = request.into_inner();
+
+        let (professors, next_page_token) =
+            self.state()
+                .professors
+                .page(|_| true, request.page_size, &request.page_token)?;
+
+        Ok(Response::new(ListProfessorsResponse {
+            professors,
+            next_page_token,
+        }))
+    }
+
+    async fn assign_advisor(
+        &self,
+        request: Request<AssignAdvisorRequest>,
+    ) -> Result<Response<AssignAdvisorResponse>, Status> {
+        let AssignAdvisorRequest {
+            student_id,
+            professor_id,
+        } = request.into_inner();
+
+        if student_id.trim().is_empty() {
+            return Err(locale::status(Code::InvalidArgument, Text::IdEmpty));
+        }
+        self.students.get_shared(&student_id).await?;
+
+        let mut state = self.state();
+        let previous = if professor_id.is_empty() {
+            state.advisors.remove(&student_id)
+        } else {
+            state.professors.find(&professor_id)?;
+            state
+                .advisors
+                .insert(student_id.clone(), professor_id.clone())
+        };
+
+        info!(
+            "Assigned advisor {} to student {}",
+            if professor_id.is_empty() {
+                "(none)"
+            } else {
+                &professor_id
+            },
+            student_id
+        );
+
+        Ok(Response::new(AssignAdvisorResponse {
+            previous_professor_id: previous.unwrap_or_default(),
+        }))
+    }
+
+    async fn get_advisor(
+        &self,
+        request: Request<GetAdvisorRequest>,
+    ) -> Result<Response<GetAdvisorResponse>, Status> {
+        let student_id = request.into_inner().student_id;
+
+        let professor = {
+            let state = self.state();
+            state
+                .advisors
+                .get(&student_id)
+                .and_then(|id| state.professors.get(id))
+                .cloned()
+        };
+        let professor = professor.ok_or(Error::NoAdvisor)?;
+
+        Ok(Response::new(GetAdvisorResponse {
+            professor: Some(professor),
+        }))
+    }
+
+    async fn list_advisees(
+        &self,
+        request: Request<ListAdviseesRequest>,
+    ) -> Result<Response<ListAdviseesResponse>, Status> {
+        let professor_id = request.into_inner().professor_id;
+
+        let advisees = {
+            let state = self.state();
+            state.professors.find(&professor_id)?;
+            state.advisees(&professor_id)
+        };
+
+        let mut students = Vec::with_capacity(advisees.len());
+        for student_id in advisees {
+            match self.students.get(&student_id).await {
+                Ok(student) => students.push(student),
+                // The student was deleted; forget the link
+                Err(status) if status.code() == Code::NotFound => {
+                    self.state().advisors.remove(&student_id);
+                }
+                Err(status) => return Err(status),
+            }
+        }
+
+        Ok(Response::new(ListAdviseesResponse { students }))
+    }
+}
@@ -0,0 +1,375 @@
+//! Student quotas per tenant.
+//!
+//! [`QuotaRepository`] wraps the store and counts the students each tenant
+//! creates, by the tenant of the request that created them (see
+//! [`crate::flags::TenantLayer`]). A create that would take a tenant past
+//! its quota fails with `RESOURCE_EXHAUSTED`. Before it comes to that, a
+//! tenant reaching [`WARNING_PERCENT`] of its quota is warned about once:
+//! the warning is logged, counted in `student_quota_warnings_total`, and
+//! sent to the admin. It is warned about again only after dropping back
+//! below. `QuotaService.GetUsage` reports where a tenant stands.
+//!
+//! Students created without a tenant, or stored before the server started,
+//! count against no quota, and counts are not kept across restarts.
+
+use crate::errors::Error;
+use crate::flags;
+use crate::metrics::{Kind, Metric, Metrics};
+use crate::notify::Alerts;
+use crate::repository::{Remembered, StudentRepository, Transaction, TransactionFailure, Write};
+use proto::quota_service_server::QuotaService;
+use proto::{GetUsageRequest, GetUsageResponse, ListStudentsResponse, Student};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tonic::{Request, Response, Status};
+
+/// The share of its quota, in percent, at which a tenant is warned about.
+pub const WARNING_PERCENT: u64 = 80;
+
+/// Times a tenant reached the warning share of its quota, by tenant.
+pub const QUOTA_WARNINGS: Metric = Metric {
+    name: "student_quota_warnings_total",
+    help: "Times a tenant reached the warning share of its student quota",
+    kind: Kind::Counter,
+};
+
+/// Students each tenant has, by tenant.
+pub const TENANT_STUDENTS: Metric = Metric {
+    name: "tenant_students",
+    help: "Students created by each tenant and not deleted",
+    kind: Kind::Gauge,
+};
+
+/// How many students tenants may have.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// For tenants without a quota of their own
+    pub default: Option<u64>,
+    pub tenants: HashMap<String, u64>,
+}
+
+impl Limits {
+    /// The quota of `tenant`, if it has one.
+    pub fn of(&self, tenant: &str) -> Option<u64> {
+        self.tenants.get(tenant).copied().or(self.default)
+    }
+}
+
+/// A tenant's quota from its command-line form, `TENANT=N`.
+pub fn parse_quota(text: &str) -> Result<(String, u64), String> {
+    let (tenant, quota) = text
+        .split_once('=')
+        .ok_or_else(|| format!("{}: expected TENANT=N", text))?;
+    let tenant = tenant.trim();
+    if tenant.is_empty() {
+        return Err(format!("{}: no tenant given", text));
+    }
+    match quota.trim().parse() {
+        Ok(0) => Err(format!("{}: a quota must allow at least one student", text)),
+        Ok(quota) => Ok((tenant.to_string(), quota)),
+        Err(_) => Err(format!("{}: expected a number of students after '='", text)),
+    }
+}
+
+// Who created each student, and how many each tenant has, counting creates
+// still in progress
+#[derive(Debug, Default)]
+struct Usage {
+    owners: HashMap<String, String>,
+    counts: HashMap<String, u64>,
+    warned: HashSet<String>,
+}
+
+/// The quotas and what each tenant uses of them, shared by the store that
+/// counts and the service that reports.
+#[derive(Debug)]
+pub struct Quotas {
+    limits: Limits,
+    usage: Mutex<Usage>,
+    metrics: Metrics,
+    alerts: Option<Alerts>,
+}
+
+impl Quotas {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            usage: Mutex::default(),
+            metrics: Metrics::new(),
+            alerts: None,
+        }
+    }
+
+    /// Report usage and warnings in `metrics`.
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Send a warning to `alerts` when a tenant nears its quota.
+    pub fn with_alerts(mut self, alerts: Alerts) -> Self {
+        self.alerts = Some(alerts);
+        self
+    }
+
+    fn usage_lock(&self) -> MutexGuard<'_, Usage> {
+        self.usage.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Where `tenant` stands against its quota.
+    pub fn usage(&self, tenant: &str) -> GetUsageResponse {
+        let usage = self.usage_lock();
+        GetUsageResponse {
+            tenant: tenant.to_string(),
+            student_count: usage.counts.get(tenant).copied().unwrap_or_default() as i64,
+            quota: self.limits.of(tenant).unwrap_or_default() as i64,
+            warning: usage.warned.contains(tenant),
+        }
+    }
+
+    fn exhausted(&self, tenant: &str) -> Status {
+        Error::QuotaExceeded(
+            tenant.to_string(),
+            self.limits.of(tenant).unwrap_or_default(),
+        )
+        .into()
+    }
+
+    // Count up to `wanted` more students for `tenant`, as many as its quota
+    // has room for, and return how many
+    fn reserve(&self, tenant: &str, wanted: usize) -> usize {
+        let mut usage = self.usage_lock();
+        let count = usage.counts.entry(tenant.to_string()).or_default();
+        let room = match self.limits.of(tenant) {
+            Some(quota) => quota.saturating_sub(*count) as usize,
+            None => wanted,
+        };
+        let reserved = wanted.min(room);
+        *count += reserved as u64;
+        self.check(&mut usage, tenant);
+        reserved
+    }
+
+    // Stop counting `count` students for `tenant`, created or not
+    fn release(&self, tenant: &str, count: usize) {
+        let mut usage = self.usage_lock();
+        if let Some(counted) = usage.counts.get_mut(tenant) {
+            *counted = counted.saturating_sub(count as u64);
+        }
+        self.check(&mut usage, tenant);
+    }
+
+    fn created(&self, tenant: &str, id: &str) {
+        let mut usage = self.usage_lock();
+        usage.owners.insert(id.to_string(), tenant.to_string());
+    }
+
+    fn deleted(&self, id: &str) {
+        let owner = self.usage_lock().owners.remove(id);
+        if let Some(tenant) = owner {
+            self.release(&tenant, 1);
+        }
+    }
+
+    // Report `tenant`'s count, and warn if it has just reached the warning
+    // share of its quota
+    fn check(&self, usage: &mut Usage, tenant: &str) {
+        let count = usage.counts.get(tenant).copied().unwrap_or_default();
+        self.metrics
+            .set(&TENANT_STUDENTS, &[("tenant", tenant)], count as f64);
+        let Some(quota) = self.limits.of(tenant) else {
+            return;
+        };
+        if count * 100 < quota * WARNING_PERCENT {
+            usage.warned.remove(tenant);
+            return;
+        }
+        if !usage.warned.insert(tenant.to_string()) {
+            return;
+        }
+        warn!(
+            "⚠️  Tenant {} has {} of its quota of {} students",
+            tenant, count, quota
+        );
+        self.metrics
+            .increment(&QUOTA_WARNINGS, &[("tenant", tenant)]);
+        if let Some(alerts) = self.alerts.clone() {
+            let subject = format!("Tenant {} is nearing its student quota", tenant);
+            let body = format!(
+                "Tenant {} has {} students, {}% of its quota of {}. Creates beyond the quota will fail.",
+                tenant,
+                count,
+                count * 100 / quota.max(1),
+                quota
+            );
+            tokio::spawn(async move { alerts.send(&subject, &body).await });
+        }
+    }
+}
+
+/// Wraps a repository and holds each tenant to its quota.
+#[derive(Debug)]
+pub struct QuotaRepository {
+    inner: Arc<dyn StudentRepository>,
+    quotas: Arc<Quotas>,
+}
+
+impl QuotaRepository {
+    pub fn new(inner: Arc<dyn StudentRepository>, quotas: Arc<Quotas>) -> Self {
+        Self { inner, quotas }
+    }
+}
+
+#[tonic::async_trait]
+impl StudentRepository for QuotaRepository {
+    async fn create(&self, student: Student) -> Result<Student, Status> {
+        let Some(tenant) = flags::current_tenant() else {
+            return self.inner.create(student).await;
+        };
+        if self.quotas.reserve(&tenant, 1) == 0 {
+            return Err(self.quotas.exhausted(&tenant));
+        }
+        match self.inner.create(student).await {
+            Ok(created) => {
+                self.quotas.created(&tenant, &created.id);
+                Ok(created)
+            }
+            Err(status) => {
+                self.quotas.release(&tenant, 1);
+                Err(status)
+            }
+        }
+    }
+
+    async fn get(&self, id: &str) -> Result<Student, Status> {
+        self.inner.get(id).await
+    }
+
+    async fn update(&self, student: Student) -> Result<Student, Status> {
+        self.inner.update(student).await
+    }
+
+    async fn delete(&self, id: &str) -> Result<Student, Status> {
+        let deleted = self.inner.delete(id).await?;
+        self.quotas.deleted(id);
+        Ok(deleted)
+    }
+
+    async fn list(
+        &self,
+        page_size: usize,
+        page_token: &str,
+    ) -> Result<ListStudentsResponse, Status> {
+        self.inner.list(page_size, page_token).await
+    }
+
+    // The students that fit are created; the rest fail
+    async fn create_many(&self, mut students: Vec<Student>) -> Vec<Result<Student, Status>> {
+        let Some(tenant) = flags::current_tenant() else {
+            return self.inner.create_many(students).await;
+        };
+        let reserved = self.quotas.reserve(&tenant, students.len());
+        let over = students.split_off(reserved);
+        let mut results = self.inner.create_many(students).await;
+        let mut failed = 0;
+        for result in &results {
+            match result {
+                Ok(created) => self.quotas.created(&tenant, &created.id),
+                Err(_) => failed += 1,
+            }
+        }
+        self.quotas.release(&tenant, failed);
+        results.extend(over.iter().map(|_| Err(self.quotas.exhausted(&tenant))));
+        results
+    }
+
+    async fn get_shared(&self, id: &str) -> Result<Arc<Student>, Status> {
+        self.inner.get_shared(id).await
+    }
+
+    async fn all(&self) -> Result<Vec<Arc<Student>>, Status> {
+        self.inner.all().await
+    }
+
+    async fn commit(&self, transaction: Transaction) -> Result<Vec<Student>, TransactionFailure> {
+        let Some(tenant) = flags::current_tenant() else {
+            let deletes: Vec<String> = transaction
+                .writes()
+                .iter()
+                .filter(|write| matches!(write, Write::Delete(_)))
+                .map(|write| write.id().to_string())
+                .collect();
+            let written = self.inner.commit(transaction).await?;
+            for id in deletes {
+                self.quotas.deleted(&id);
+            }
+            return Ok(written);
+        };
+        let writes = transaction.writes().to_vec();
+        let creates = writes
+            .iter()
+            .filter(|write| matches!(write, Write::Create(_)))
+            .count();
+        let reserved = self.quotas.reserve(&tenant, creates);
+        if reserved < creates {
+            self.quotas.release(&tenant, reserved);
+            // The first create there is no room for
+            let index = writes
+                .iter()
+                .enumerate()
+                .filter(|(_, write)| matches!(write, Write::Create(_)))
+                .nth(reserved)
+                .map(|(index, _)| index);
+            return Err(TransactionFailure {
+                index,
+                status: self.quotas.exhausted(&tenant),
+            });
+        }
+
+        match self.inner.commit(transaction).await {
+            Ok(written) => {
+                for (write, student) in writes.iter().zip(&written) {
+                    match write {
+                        Write::Create(_) => self.quotas.created(&tenant, &student.id),
+                        Write::Delete(id) => self.quotas.deleted(id),
+                        Write::Update(_) => {}
+                    }
+                }
+                Ok(written)
+            }
+            Err(failure) => {
+                self.quotas.release(&tenant, creates);
+                Err(failure)
+            }
+        }
+    }
+
+    async fn remembered(&self, key: &str) -> Result<Option<Remembered>, Status> {
+        self.inner.remembered(key).await
+    }
+}
+
+#[derive(Debug)]
+pub struct QuotaServiceImpl {
+    quotas: Arc<Quotas>,
+}
+
+impl QuotaServiceImpl {
+    pub fn new(quotas: Arc<Quotas>) -> Self {
+        Self { quotas }
+    }
+}
+
+#[tonic::async_trait]
+impl QuotaService for QuotaServiceImpl {
+    async fn get_usage(
+        &self,
+        request: Request<GetUsageRequest>,
+    ) -> Result<Response<GetUsageResponse>, Status> {
+        let tenant = Some(request.into_inner().tenant.trim().to_string())
+            .filter(|tenant| !tenant.is_empty())
+            .or_else(flags::current_tenant)
+            .ok_or(Error::TenantRequired)?;
+        Ok(Response::new(self.quotas.usage(&tenant)))
+    }
+}
@@ -0,0 +1,320 @@
+//! Record-and-replay of `StudentService` traffic.
+//!
+//! [`Recorder`] wraps a service and appends every unary call to a JSON lines
+//! file: the method name, the request, and either the response or the error.
+//! [`Replayer`] serves a recording back without a store, so client tests and
+//! bug reports can run against exactly the answers that were captured.
+//!
+//! Requests and responses use the canonical proto3 JSON mapping, so recordings
+//! can be read and edited by hand. Metadata is not recorded, and
+//! `WatchStudents` streams are not recorded or replayed.
+
+use proto::student_service_server::StudentService;
+use proto::{
+    BatchWriteRequest, BatchWriteResponse, CreateStudentRequest, CreateStudentResponse,
+    DeleteStudentRequest, DeleteStudentResponse, GetStudentRequest, GetStudentResponse,
+    ListStudentsByStandingRequest, ListStudentsRequest, ListStudentsResponse, UpdateStudentRequest,
+    UpdateStudentResponse, ValidateStudentRequest, ValidateStudentResponse, WatchStudentsRequest,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use tonic::{Code, Request, Response, Status};
+
+use crate::service::StudentEventStream;
+
+/// One recorded call, written as a single line of the recording.
+#[derive(Debug, Serialize, Deserialize)]
+struct Exchange {
+    method: String,
+    request: Value,
+    #[serde(flatten)]
+    outcome: Outcome,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Outcome {
+    Response(Value),
+    Error { code: i32, message: String },
+}
+
+// Serializing generated messages only fails on non-finite floats; record null
+// rather than losing the whole exchange
+fn to_value<T: Serialize>(message: &T) -> Value {
+    serde_json::to_value(message).unwrap_or(Value::Null)
+}
+
+/// Wraps a service and records every unary call it answers.
+#[derive(Debug)]
+pub struct Recorder<S> {
+    inner: S,
+    log: Mutex<BufWriter<File>>,
+}
+
+impl<S> Recorder<S> {
+    /// Record to `path`, appending if the file already exists.
+    pub fn to_file(inner: S, path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            inner,
+            log: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    fn record<Res: Serialize>(
+        &self,
+        method: &str,
+        request: Value,
+        result: &Result<Response<Res>, Status>,
+    ) {
+        let outcome = match result {
+            Ok(response) => Outcome::Response(to_value(response.get_ref())),
+            Err(status) => Outcome::Error {
+                code: status.code() as i32,
+                message: status.message().to_string(),
+            },
+        };
+        let exchange = Exchange {
+            method: method.to_string(),
+            request,
+            outcome,
+        };
+
+        // Flush every line so the recording is usable even if the server is killed
+        let mut log = self.log.lock().unwrap_or_else(|e| e.into_inner());
+        let written = serde_json::to_writer(&mut *log, &exchange)
+            .map_err(io::Error::from)
+            .and_then(|()| writeln!(log))
+            .and_then(|()| log.flush());
+        if let Err(e) = written {
+            warn!("⚠️  Failed to record {}: {}", method, e);
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl<S: StudentService> StudentService for Recorder<S> {
+    type WatchStudentsStream = S::WatchStudentsStream;
+
+    async fn create_student(
+        &self,
+        request: Request<CreateStudentRequest>,
+    ) -> Result<Response<CreateStudentResponse>, Status> {
+        let recorded = to_value(request.get_ref());
+        let result = self.inner.create_student(request).await;
+        self.record("CreateStudent", recorded, &result);
+        result
+    }
+
+    async fn get_student(
+        &self,
+        request: Request<GetStudentRequest>,
+    ) -> Result<Response<GetStudentResponse>, Status> {
+        let recorded = to_value(request.get_ref());
+        let result = self.inner.get_student(request).await;
+        self.record("GetStudent", recorded, &result);
+        result
+    }
+
+    async fn update_student(
+        &self,
+        request: Request<UpdateStudentRequest>,
+    ) -> Result<Response<UpdateStudentResponse>, Status> {
+        let recorded = to_value(request.get_ref());
+        let result = self.inner.update_student(request).await;
+        self.record("UpdateStudent", recorded, &result);
+        result
+    }
+
+    async fn delete_student(
+        &self,
+        request: Request<DeleteStudentRequest>,
+    ) -> Result<Response<DeleteStudentResponse>, Status> {
+        let recorded = to_value(request.get_ref());
+        let result = self.inner.delete_student(request).await;
+        self.record("DeleteStudent", recorded, &result);
+        result
+    }
+
+    async fn list_students(
+        &self,
+        request: Request<ListStudentsRequest>,
+    ) -> Result<Response<ListStudentsResponse>, Status> {
+        let recorded = to_value(request.get_ref());
+        let result = self.inner.list_students(request).await;
+        self.record("ListStudents", recorded, &result);
+        result
+    }
+
+    async fn list_students_by_standing(
+        &self,
+        request: Request<ListStudentsByStandingRequest>,
+    ) -> Result<Response<ListStudentsResponse>, Status> {
+        let recorded = to_value(request.get_ref());
+        let result = self.inner.list_students_by_standing(request).await;
+        self.record("ListStudentsByStanding", recorded, &result);
+        result
+    }
+
+    async fn validate_student(
+        &self,
+        request: Request<ValidateStudentRequest>,
+    ) -> Result<Response<ValidateStudentResponse>, Status> {
+        let recorded = to_value(request.get_ref());
+        let result = self.inner.validate_student(request).await;
+        self.record("ValidateStudent", recorded, &result);
+        result
+    }
+
+    async fn batch_write(
+        &self,
+        request: Request<BatchWriteRequest>,
+    ) -> Result<Response<BatchWriteResponse>, Status> {
+        let recorded = to_value(request.get_ref());
+        let result = self.inner.batch_write(request).await;
+        self.record("BatchWrite", recorded, &result);
+        result
+    }
+
+    // Events depend on timing, so streams pass through unrecorded
+    async fn watch_students(
+        &self,
+        request: Request<WatchStudentsRequest>,
+    ) -> Result<Response<Self::WatchStudentsStream>, Status> {
+        self.inner.watch_students(request).await
+    }
+}
+
+/// Serves the answers from a recording made by [`Recorder`].
+///
+/// Each call is matched by method and request body against the recorded
+/// exchanges, and each exchange is used once, in recorded order. A call with
+/// no matching exchange left fails with `UNIMPLEMENTED`.
+#[derive(Debug)]
+pub struct Replayer {
+    exchanges: Mutex<HashMap<String, VecDeque<Exchange>>>,
+}
+
+impl Replayer {
+    /// Load a recording from `path`.
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let mut exchanges: HashMap<String, VecDeque<Exchange>> = HashMap::new();
+        for (number, line) in (1..).zip(BufReader::new(File::open(path)?).lines()) {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let exchange: Exchange = serde_json::from_str(&line).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", number, e))
+            })?;
+            exchanges
+                .entry(exchange.method.clone())
+                .or_default()
+                .push_back(exchange);
+        }
+        Ok(Self {
+            exchanges: Mutex::new(exchanges),
+        })
+    }
+
+    fn replay<Req, Res>(&self, method: &str, request: Request<Req>) -> Result<Response<Res>, Status>
+    where
+        Req: Serialize,
+        Res: DeserializeOwned,
+    {
+        let request = to_value(request.get_ref());
+        let exchange = {
+            let mut exchanges = self.exchanges.lock().unwrap_or_else(|e| e.into_inner());
+            let queue = exchanges.entry(method.to_string()).or_default();
+            queue
+                .iter()
+                .position(|exchange| exchange.request == request)
+                .and_then(|index| queue.remove(index))
+        };
+
+        match exchange.map(|exchange| exchange.outcome) {
+            Some(Outcome::Response(response)) => serde_json::from_value(response)
+                .map(Response::new)
+                .map_err(|e| Status::internal(format!("Bad recorded {} response: {}", method, e))),
+            Some(Outcome::Error { code, message }) => Err(Status::new(Code::from(code), message)),
+            None => Err(Status::unimplemented(format!(
+                "No recorded {} call matches {}",
+                method, request
+            ))),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl StudentService for Replayer {
+    type WatchStudentsStream = StudentEventStream;
+
+    async fn create_student(
+        &self,
+        request: Request<CreateStudentRequest>,
+    ) -> Result<Response<CreateStudentResponse>, Status> {
+        self.replay("CreateStudent", request)
+    }
+
+    async fn get_student(
+        &self,
+        request: Request<GetStudentRequest>,
+    ) -> Result<Response<GetStudentResponse>, Status> {
+        self.replay("GetStudent", request)
+    }
+
+    async fn update_student(
+        &self,
+        request: Request<UpdateStudentRequest>,
+    ) -> Result<Response<UpdateStudentResponse>, Status> {
+        self.replay("UpdateStudent", request)
+    }
+
+    async fn delete_student(
+        &self,
+        request: Request<DeleteStudentRequest>,
+    ) -> Result<Response<DeleteStudentResponse>, Status> {
+        self.replay("DeleteStudent", request)
+    }
+
+    async fn list_students(
+        &self,
+        request: Request<ListStudentsRequest>,
+    ) -> Result<Response<ListStudentsResponse>, Status> {
+        self.replay("ListStudents", request)
+    }
+
+    async fn list_students_by_standing(
+        &self,
+        request: Request<ListStudentsByStandingRequest>,
+    ) -> Result<Response<ListStudentsResponse>, Status> {
+        self.replay("ListStudentsByStanding", request)
+    }
+
+    async fn validate_student(
+        &self,
+        request: Request<ValidateStudentRequest>,
+    ) -> Result<Response<ValidateStudentResponse>, Status> {
+        self.replay("ValidateStudent", request)
+    }
+
+    async fn batch_write(
+        &self,
+        request: Request<BatchWriteRequest>,
+    ) -> Result<Response<BatchWriteResponse>, Status> {
+        self.replay("BatchWrite", request)
+    }
+
+    async fn watch_students(
+        &self,
+        _request: Request<WatchStudentsRequest>,
+    ) -> Result<Response<Self::WatchStudentsStream>, Status> {
+        Err(Status::unimplemented("WatchStudents is not recorded"))
+    }
+}
@@ -0,0 +1,334 @@
+//! Asynchronous replication from a primary server, e.g. in another region.
+//!
+//! A [`Replicator`] follows the primary's change feed (`WatchStudents`)
+//! and applies each change to the store here. On first connecting, or when
+//! the feed cannot resume where it left off, it copies every student from
+//! the primary first. Changes arrive after the primary has answered the
+//! write, so the replica lags behind it; `replication_lag_seconds` reports
+//! by how much, from the time of each change on the primary.
+//!
+//! The replica takes writes of its own too, so a change from the primary
+//! may meet a student written here since. A [`ConflictPolicy`] decides:
+//! the last write wins, going by each version's `update_time`, or the
+//! primary always wins. A change that loses is dropped and counted.
+//!
+//! Students the primary deletes while the feed is lost for longer than it
+//! keeps changes stay on the replica, since a fresh copy only adds and
+//! updates.
+
+use crate::clock::{self, Clock, SystemClock};
+use crate::events::EventLog;
+use crate::metrics::{Kind, Metric, Metrics};
+use crate::repository::StudentRepository;
+use proto::student_service_client::StudentServiceClient;
+use proto::{ChangeType, ListStudentsRequest, Student, StudentEvent, WatchStudentsRequest};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Status};
+
+const RETRY_MIN: Duration = Duration::from_millis(100);
+const RETRY_MAX: Duration = Duration::from_secs(10);
+// Students read per page when copying everything
+const COPY_PAGE_SIZE: i32 = 500;
+
+/// How long after its write on the primary the last change was applied.
+pub const REPLICATION_LAG: Metric = Metric {
+    name: "replication_lag_seconds",
+    help: "Seconds between the last replicated change's write on the primary and its replication",
+    kind: Kind::Gauge,
+};
+
+/// Changes from the primary, by what was done with them.
+pub const REPLICATED_CHANGES: Metric = Metric {
+    name: "replicated_changes_total",
+    help: "Changes from the primary, by outcome: applied, conflict, or unchanged",
+    kind: Kind::Counter,
+};
+
+/// 1 while following the primary's change feed, 0 while not.
+pub const REPLICATION_CONNECTED: Metric = Metric {
+    name: "replication_connected",
+    help: "Whether the replica is following the primary's change feed",
+    kind: Kind::Gauge,
+};
+
+/// Which version of a student is kept when the replica has written it too.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// The version with the later `update_time`; the primary's on a tie.
+    #[default]
+    LastWriteWins,
+    /// The primary's, always.
+    PrimaryWins,
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.trim() {
+            "last-write-wins" => Ok(ConflictPolicy::LastWriteWins),
+            "primary-wins" => Ok(ConflictPolicy::PrimaryWins),
+            _ => Err(format!(
+                "{}: expected last-write-wins or primary-wins",
+                text
+            )),
+        }
+    }
+}
+
+/// What applying one change did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The store now has the primary's version.
+    Applied,
+    /// The replica's own version is newer and was kept.
+    Conflict,
+    /// The store had the primary's version already.
+    Unchanged,
+}
+
+impl Outcome {
+    fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Applied => "applied",
+            Outcome::Conflict => "conflict",
+            Outcome::Unchanged => "unchanged",
+        }
+    }
+}
+
+// The parts of a student both sides store, leaving out the etag each store
+// gives it and the standing servers derive
+fn stored(mut student: Student) -> Student {
+    student.etag.clear();
+    student.standing = 0;
+    student
+}
+
+/// Copies a primary's students, and every change to them, into a store.
+#[derive(Debug)]
+pub struct Replicator {
+    primary: String,
+    client: StudentServiceClient<Channel>,
+    store: Arc<dyn StudentRepository>,
+    policy: ConflictPolicy,
+    events: Option<Arc<EventLog>>,
+    metrics: Metrics,
+    clock: Arc<dyn Clock>,
+}
+
+impl Replicator {
+    /// Replicate the server at `primary`, e.g. `students.eu.example.edu:50051`,
+    /// into `store`, connecting on first use.
+    pub fn connect_lazy(primary: &str, store: Arc<dyn StudentRepository>) -> Result<Self, String> {
+        let uri = match primary.contains("://") {
+            true => primary.to_string(),
+            false => format!("http://{}", primary),
+        };
+        let endpoint =
+            Endpoint::from_shared(uri).map_err(|e| format!("Primary {}: {}", primary, e))?;
+        Ok(Self {
+            primary: primary.to_string(),
+            client: StudentServiceClient::new(endpoint.connect_lazy()),
+            store,
+            policy: ConflictPolicy::default(),
+            events: None,
+            metrics: Metrics::new(),
+            clock: Arc::new(SystemClock),
+        })
+    }
+
+    pub fn with_policy(mut self, policy: ConflictPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Tell watchers here about the changes applied, for stores that do not
+    /// record events themselves.
+    pub fn with_events(mut self, events: Arc<EventLog>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Report lag and outcomes in `metrics`.
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn publish(&self, change_type: ChangeType, student: &Student) {
+        if let Some(events) = &self.events {
+            events.publish(change_type, student);
+        }
+    }
+
+    // Whether the replica's version of a student beats the primary's
+    fn keeps_local(&self, local: &Student, primary: &Student) -> bool {
+        match (self.policy, &local.update_time, &primary.update_time) {
+            (ConflictPolicy::LastWriteWins, Some(local), Some(primary)) => {
+                clock::is_after(local, primary)
+            }
+            (ConflictPolicy::LastWriteWins, Some(_), None) => true,
+            _ => false,
+        }
+    }
+
+    /// Apply one change from the primary's feed, by the conflict policy.
+    pub async fn apply(&self, event: &StudentEvent) -> Result<Outcome, Status> {
+        let Some(incoming) = event.student.clone().map(stored) else {
+            return Ok(Outcome::Unchanged);
+        };
+        let local = match self.store.get(&incoming.id).await {
+            Ok(student) => Some(student),
+            Err(status) if status.code() == Code::NotFound => None,
+            Err(status) => return Err(status),
+        };
+
+        let change = event.change_type();
+        let outcome = match (change, local) {
+            (ChangeType::Deleted, None) => Outcome::Unchanged,
+            (ChangeType::Deleted, Some(local)) if self.keeps_local(&local, &incoming) => {
+                Outcome::Conflict
+            }
+            (ChangeType::Deleted, Some(_)) => {
+                let deleted = self.store.delete(&incoming.id).await?;
+                self.publish(ChangeType::Deleted, &deleted);
+                Outcome::Applied
+            }
+            (_, None) => {
+                let created = self.store.create(incoming.clone()).await?;
+                self.publish(ChangeType::Created, &created);
+                Outcome::Applied
+            }
+            (_, Some(local)) if stored(local.clone()) == incoming => Outcome::Unchanged,
+            (_, Some(local)) if self.keeps_local(&local, &incoming) => Outcome::Conflict,
+            (_, Some(_)) => {
+                let updated = self.store.update(incoming.clone()).await?;
+                self.publish(ChangeType::Updated, &updated);
+                Outcome::Applied
+            }
+        };
+
+        if outcome == Outcome::Conflict {
+            info!(
+                "🔀 Kept this replica's version of student {}, newer than the primary's",
+                incoming.id
+            );
+        }
+        self.metrics
+            .increment(&REPLICATED_CHANGES, &[("outcome", outcome.as_str())]);
+        // A deletion carries the student as last written, not when it went
+        if let (false, Some(written)) = (change == ChangeType::Deleted, &incoming.update_time) {
+            let now = clock::timestamp(self.clock.now());
+            let lag =
+                (now.seconds - written.seconds) as f64 + (now.nanos - written.nanos) as f64 / 1e9;
+            self.metrics.set(&REPLICATION_LAG, &[], lag.max(0.0));
+        }
+        Ok(outcome)
+    }
+
+    // Every student on the primary, applied as if just created
+    async fn copy_all(&self) -> Result<usize, Status> {
+        let mut copied = 0;
+        let mut page_token = String::new();
+        loop {
+            let request = ListStudentsRequest {
+                page_size: COPY_PAGE_SIZE,
+                page_token,
+                ..Default::default()
+            };
+            let page = self
+                .client
+                .clone()
+                .list_students(request)
+                .await?
+                .into_inner();
+            for student in page.students {
+                let event = StudentEvent {
+                    change_type: ChangeType::Created as i32,
+                    student: Some(student),
+                    resume_token: String::new(),
+                };
+                self.apply(&event).await?;
+                copied += 1;
+            }
+            if page.next_page_token.is_empty() {
+                return Ok(copied);
+            }
+            page_token = page.next_page_token;
+        }
+    }
+
+    // Follow the feed from `resume_token`, or from a fresh copy if it is
+    // empty, keeping it at the last change applied, until the connection
+    // ends
+    async fn follow(&self, resume_token: &mut String, retry: &mut Duration) -> Status {
+        let request = WatchStudentsRequest {
+            resume_token: resume_token.clone(),
+            ..Default::default()
+        };
+        // Watching before copying, so no change made meanwhile is missed
+        let mut feed = match self.client.clone().watch_students(request).await {
+            Ok(response) => response.into_inner(),
+            Err(status) => return status,
+        };
+        if resume_token.is_empty() {
+            match self.copy_all().await {
+                Ok(copied) => info!("📥 Copied {} students from {}", copied, self.primary),
+                Err(status) => return status,
+            }
+        }
+        info!("📡 Following changes on {}", self.primary);
+        self.metrics.set(&REPLICATION_CONNECTED, &[], 1.0);
+        *retry = RETRY_MIN;
+        loop {
+            let event = match feed.message().await {
+                Ok(Some(event)) => event,
+                Ok(None) => return Status::unavailable("The primary ended its change feed"),
+                Err(status) => return status,
+            };
+            if event.change_type() == ChangeType::ShuttingDown {
+                *resume_token = event.resume_token;
+                return Status::unavailable("The primary is shutting down");
+            }
+            if let Err(status) = self.apply(&event).await {
+                return status;
+            }
+            *resume_token = event.resume_token;
+        }
+    }
+
+    /// Replicate for as long as the server runs, reconnecting with backoff.
+    pub async fn run(self: Arc<Self>) {
+        let mut resume_token = String::new();
+        let mut retry = RETRY_MIN;
+        loop {
+            let status = self.follow(&mut resume_token, &mut retry).await;
+            self.metrics.set(&REPLICATION_CONNECTED, &[], 0.0);
+            // The primary no longer has the changes since the last one
+            // applied, e.g. after a restart
+            if matches!(
+                status.code(),
+                Code::OutOfRange | Code::InvalidArgument | Code::DataLoss
+            ) {
+                resume_token.clear();
+            }
+            warn!(
+                "⚠️  Lost the change feed from {}, retrying in {:?}: {}",
+                self.primary,
+                retry,
+                status.message()
+            );
+            tokio::time::sleep(retry).await;
+            retry = (retry * 2).min(RETRY_MAX);
+        }
+    }
+}
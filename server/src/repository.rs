@@ -0,0 +1,548 @@
+//! Storage behind the service.
+//!
+//! [`StudentRepository`] is the contract every storage backend implements.
+//! The service handles validation, IDs, timestamps and events; a repository
+//! only stores students. Every implementation must pass the suite in
+//! [`crate::conformance`].
+//!
+//! Each call stands alone, except [`StudentRepository::commit`], which
+//! applies a [`Transaction`] of several writes all together or not at all.
+//! A transaction with an [`IdempotencyKey`] is committed at most once per
+//! key, and what it wrote is remembered for [`KEY_RETENTION`].
+
+use crate::errors::Error;
+use crate::locale::{self, Text};
+use crate::memory::{self, StoreMemory};
+use proto::{ListStudentsResponse, Student};
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+use tokio::sync::RwLock;
+use tonic::{Code, Status};
+
+/// Storage for students, keyed by ID.
+#[tonic::async_trait]
+pub trait StudentRepository: Debug + Send + Sync {
+    /// Store a new student with a fresh `etag`. Fails with `ALREADY_EXISTS`
+    /// if the ID is taken.
+    async fn create(&self, student: Student) -> Result<Student, Status>;
+
+    /// Fetch a student. Fails with `NOT_FOUND` if there is none.
+    async fn get(&self, id: &str) -> Result<Student, Status>;
+
+    /// Replace a stored student, keeping its stored `create_time` and giving
+    /// it a new `etag`. Fails with `NOT_FOUND` if there is none, and with
+    /// `ABORTED` if `student.etag` is set but is not the stored one.
+    async fn update(&self, student: Student) -> Result<Student, Status>;
+
+    /// Remove a student and return it. Fails with `NOT_FOUND` if there is none.
+    async fn delete(&self, id: &str) -> Result<Student, Status>;
+
+    /// One page of students in a stable order.
+    ///
+    /// An empty `page_token` starts from the beginning; any other token must
+    /// come from a previous page's `next_page_token`, or the call fails with
+    /// `INVALID_ARGUMENT`. The last page has an empty `next_page_token`.
+    /// Tokens are the backend's own: an offset in memory, a snapshot and
+    /// the last ID in PostgreSQL, which keeps writes made between pages
+    /// from repeating or skipping students.
+    async fn list(
+        &self,
+        page_size: usize,
+        page_token: &str,
+    ) -> Result<ListStudentsResponse, Status>;
+
+    /// Store several new students, as [`create`](Self::create) would one by
+    /// one, with one result per student in the same order. Backends may
+    /// write them together.
+    async fn create_many(&self, students: Vec<Student>) -> Vec<Result<Student, Status>> {
+        let mut results = Vec::with_capacity(students.len());
+        for student in students {
+            results.push(self.create(student).await);
+        }
+        results
+    }
+
+    /// Fetch a student to read but not change. Backends that keep students
+    /// in memory hand out the stored copy instead of cloning it.
+    async fn get_shared(&self, id: &str) -> Result<Arc<Student>, Status> {
+        self.get(id).await.map(Arc::new)
+    }
+
+    /// Every student, in the order `list` pages through them, shared like
+    /// [`get_shared`](Self::get_shared).
+    async fn all(&self) -> Result<Vec<Arc<Student>>, Status> {
+        let mut students = Vec::new();
+        let mut page_token = String::new();
+        loop {
+            let page = self.list(SCAN_PAGE_SIZE, &page_token).await?;
+            students.extend(page.students.into_iter().map(Arc::new));
+            if page.next_page_token.is_empty() {
+                return Ok(students);
+            }
+            page_token = page.next_page_token;
+        }
+    }
+
+    /// Apply every write in `transaction`, in order, as the single calls
+    /// would, and return what each returned. If any write fails, none of
+    /// them is kept, and the failure says which one it was.
+    ///
+    /// With an idempotency key that was already committed, nothing is
+    /// written and the call fails, for the transaction as a whole, with
+    /// `ALREADY_EXISTS`; [`remembered`](Self::remembered) has what the
+    /// first one wrote.
+    ///
+    /// Backends that cannot undo writes fail with `UNIMPLEMENTED`.
+    async fn commit(&self, _transaction: Transaction) -> Result<Vec<Student>, TransactionFailure> {
+        Err(TransactionFailure {
+            index: None,
+            status: Status::unimplemented("This storage backend does not support transactions"),
+        })
+    }
+
+    /// What the transaction committed with idempotency key `key` wrote, if
+    /// it was committed in the last [`KEY_RETENTION`].
+    async fn remembered(&self, _key: &str) -> Result<Option<Remembered>, Status> {
+        Ok(None)
+    }
+}
+
+/// One write in a [`Transaction`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Write {
+    /// As [`StudentRepository::create`]
+    Create(Student),
+    /// As [`StudentRepository::update`]
+    Update(Student),
+    /// As [`StudentRepository::delete`], by ID
+    Delete(String),
+}
+
+impl Write {
+    /// The ID of the student written.
+    pub fn id(&self) -> &str {
+        match self {
+            Write::Create(student) | Write::Update(student) => &student.id,
+            Write::Delete(id) => id,
+        }
+    }
+}
+
+/// Writes that [`StudentRepository::commit`] keeps all or none of.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Transaction {
+    writes: Vec<Write>,
+    idempotency_key: Option<IdempotencyKey>,
+}
+
+/// How long what a transaction wrote is remembered under its idempotency key.
+pub const KEY_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A client's key for one request, so that retrying it commits nothing new.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdempotencyKey {
+    pub key: String,
+    /// Tells the request the key was first used for from a different one
+    pub fingerprint: String,
+}
+
+/// What a transaction committed with an idempotency key wrote.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Remembered {
+    pub fingerprint: String,
+    /// One per write, as `commit` returned them
+    pub students: Vec<Student>,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `write` after the writes so far.
+    pub fn with(mut self, write: Write) -> Self {
+        self.writes.push(write);
+        self
+    }
+
+    pub fn with_create(self, student: Student) -> Self {
+        self.with(Write::Create(student))
+    }
+
+    pub fn with_update(self, student: Student) -> Self {
+        self.with(Write::Update(student))
+    }
+
+    pub fn with_delete(self, id: impl Into<String>) -> Self {
+        self.with(Write::Delete(id.into()))
+    }
+
+    /// Commit at most once per `key`.
+    pub fn with_idempotency_key(mut self, key: IdempotencyKey) -> Self {
+        self.idempotency_key = Some(key);
+        self
+    }
+
+    pub fn idempotency_key(&self) -> Option<&IdempotencyKey> {
+        self.idempotency_key.as_ref()
+    }
+
+    pub fn writes(&self) -> &[Write] {
+        &self.writes
+    }
+
+    pub fn into_writes(self) -> Vec<Write> {
+        self.writes
+    }
+}
+
+impl From<Vec<Write>> for Transaction {
+    fn from(writes: Vec<Write>) -> Self {
+        Self {
+            writes,
+            idempotency_key: None,
+        }
+    }
+}
+
+/// Why a transaction was rolled back.
+#[derive(Debug)]
+pub struct TransactionFailure {
+    /// The write that failed, or `None` if the transaction as a whole did
+    pub index: Option<usize>,
+    pub status: Status,
+}
+
+/// Students held in memory, ordered by ID.
+///
+/// Each is kept behind an `Arc`, so reads under the lock only bump a count;
+/// students are cloned, if at all, after the lock is released.
+///
+/// The store keeps an estimate of the memory it holds (see
+/// [`crate::memory`]) and, with a limit, refuses creates that would go over
+/// it with `RESOURCE_EXHAUSTED`.
+#[derive(Debug, Default)]
+pub struct InMemoryRepository {
+    students: RwLock<BTreeMap<String, Arc<Student>>>,
+    // Estimated bytes, only changed under the write lock
+    record_bytes: AtomicUsize,
+    index_bytes: AtomicUsize,
+    limit: Option<usize>,
+    // By idempotency key, with when it was committed; only changed under
+    // the students' write lock
+    remembered: Mutex<HashMap<String, (SystemTime, Remembered)>>,
+    etags: Etags,
+}
+
+// Etags no two writes share, even to a student deleted and created again:
+// a count of the store's writes, qualified by the store, so a restarted
+// server does not hand out an earlier run's
+#[derive(Debug)]
+struct Etags {
+    store: String,
+    last: AtomicU64,
+}
+
+impl Default for Etags {
+    fn default() -> Self {
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        Self {
+            store: id[..8].to_string(),
+            last: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Etags {
+    fn next(&self) -> String {
+        let write = self.last.fetch_add(1, Ordering::Relaxed) + 1;
+        format!("{}-{}", self.store, write)
+    }
+}
+
+impl InMemoryRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Refuse creates once the students and their index would take more
+    /// than `bytes`. Updates and deletes are always allowed.
+    pub fn with_memory_limit(mut self, bytes: usize) -> Self {
+        self.limit = Some(bytes);
+        self
+    }
+
+    /// What the store holds now.
+    pub fn memory(&self) -> StoreMemory {
+        StoreMemory {
+            records: self.record_bytes.load(Ordering::Relaxed),
+            index: self.index_bytes.load(Ordering::Relaxed),
+            limit: self.limit,
+        }
+    }
+
+    fn keys(&self) -> MutexGuard<'_, HashMap<String, (SystemTime, Remembered)>> {
+        self.remembered.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Under the write lock: store a new student, if there is room for it
+    fn insert(
+        &self,
+        students: &mut BTreeMap<String, Arc<Student>>,
+        mut student: Student,
+    ) -> Result<Student, Status> {
+        if students.contains_key(&student.id) {
+            return Err(already_exists());
+        }
+        student.etag = self.etags.next();
+        let record = memory::student_size(&student);
+        let index = memory::index_entry_size(&student.id);
+        if let Some(limit) = self.limit {
+            let used = self.memory().used();
+            if used + record + index > limit {
+                return Err(Error::StoreFull(used, limit).into());
+            }
+        }
+        self.replace(students, &student.id, Some(Arc::new(student.clone())));
+        Ok(student)
+    }
+
+    // Under the write lock: replace a stored student after checking its etag
+    fn modify(
+        &self,
+        students: &mut BTreeMap<String, Arc<Student>>,
+        mut student: Student,
+    ) -> Result<Student, Status> {
+        let existing = students.get(&student.id).ok_or_else(not_found)?;
+        if !student.etag.is_empty() && student.etag != existing.etag {
+            return Err(etag_mismatch());
+        }
+
+        student.etag = self.etags.next();
+        student.create_time = existing.create_time.clone();
+        self.replace(students, &student.id, Some(Arc::new(student.clone())));
+        Ok(student)
+    }
+
+    // Under the write lock: remove a stored student
+    fn remove(
+        &self,
+        students: &mut BTreeMap<String, Arc<Student>>,
+        id: &str,
+    ) -> Result<Arc<Student>, Status> {
+        self.replace(students, id, None).ok_or_else(not_found)
+    }
+
+    // Under the write lock: put `student` under `id`, or nothing if `None`,
+    // keeping the byte counts in step, and return what was there
+    fn replace(
+        &self,
+        students: &mut BTreeMap<String, Arc<Student>>,
+        id: &str,
+        student: Option<Arc<Student>>,
+    ) -> Option<Arc<Student>> {
+        if let Some(student) = &student {
+            self.record_bytes
+                .fetch_add(memory::student_size(student), Ordering::Relaxed);
+        }
+        let previous = match student {
+            Some(student) => students.insert(id.to_string(), student),
+            None => students.remove(id),
+        };
+        if let Some(previous) = &previous {
+            self.record_bytes
+                .fetch_sub(memory::student_size(previous), Ordering::Relaxed);
+        }
+        let index = memory::index_entry_size(id);
+        match (previous.is_some(), students.contains_key(id)) {
+            (false, true) => self.index_bytes.fetch_add(index, Ordering::Relaxed),
+            (true, false) => self.index_bytes.fetch_sub(index, Ordering::Relaxed),
+            _ => 0,
+        };
+        previous
+    }
+}
+
+pub(crate) fn not_found() -> Status {
+    locale::status(Code::NotFound, Text::StudentNotFound)
+}
+
+pub(crate) fn already_exists() -> Status {
+    locale::status(Code::AlreadyExists, Text::StudentExists)
+}
+
+pub(crate) fn etag_mismatch() -> Status {
+    locale::status(Code::Aborted, Text::StudentChanged)
+}
+
+// Whether a key committed at `committed` is still remembered at `now`
+fn fresh(committed: SystemTime, now: SystemTime) -> bool {
+    now.duration_since(committed).unwrap_or_default() < KEY_RETENTION
+}
+
+pub(crate) fn key_committed() -> Status {
+    Error::TransactionCommitted.into()
+}
+
+// Students read per page when scanning the whole store
+pub(crate) const SCAN_PAGE_SIZE: usize = 100;
+
+/// Offset of the page that `page_token` points at, for repositories whose
+/// tokens are offsets into `total` students ordered by ID.
+pub(crate) fn page_offset(page_token: &str, total: usize) -> Result<usize, Status> {
+    match page_token {
+        "" => Ok(0),
+        token => token
+            .parse::<usize>()
+            .ok()
+            .filter(|&start| start <= total)
+            .ok_or_else(|| locale::status(Code::InvalidArgument, Text::PageTokenInvalid)),
+    }
+}
+
+/// The token for the page after one ending at `end`; empty after the last page.
+pub(crate) fn next_page_token(end: usize, total: usize) -> String {
+    if end < total {
+        end.to_string()
+    } else {
+        String::new()
+    }
+}
+
+#[tonic::async_trait]
+impl StudentRepository for InMemoryRepository {
+    async fn create(&self, student: Student) -> Result<Student, Status> {
+        let mut students = self.students.write().await;
+        self.insert(&mut students, student)
+    }
+
+    async fn get(&self, id: &str) -> Result<Student, Status> {
+        let student = self.get_shared(id).await?;
+        Ok(Student::clone(&student))
+    }
+
+    async fn update(&self, student: Student) -> Result<Student, Status> {
+        let mut students = self.students.write().await;
+        self.modify(&mut students, student)
+    }
+
+    async fn delete(&self, id: &str) -> Result<Student, Status> {
+        let student = self.remove(&mut *self.students.write().await, id)?;
+        Ok(Arc::unwrap_or_clone(student))
+    }
+
+    async fn list(
+        &self,
+        page_size: usize,
+        page_token: &str,
+    ) -> Result<ListStudentsResponse, Status> {
+        let (page, end, total) = {
+            let students = self.students.read().await;
+            let start = page_offset(page_token, students.len())?;
+            let end = start.saturating_add(page_size).min(students.len());
+            let page: Vec<Arc<Student>> = students
+                .values()
+                .skip(start)
+                .take(end - start)
+                .cloned()
+                .collect();
+            (page, end, students.len())
+        };
+
+        Ok(ListStudentsResponse {
+            students: page.iter().map(|student| Student::clone(student)).collect(),
+            next_page_token: next_page_token(end, total),
+            total_count: total as i32,
+        })
+    }
+
+    // The whole batch under one lock
+    async fn create_many(&self, students: Vec<Student>) -> Vec<Result<Student, Status>> {
+        let mut stored = self.students.write().await;
+        students
+            .into_iter()
+            .map(|student| self.insert(&mut stored, student))
+            .collect()
+    }
+
+    async fn get_shared(&self, id: &str) -> Result<Arc<Student>, Status> {
+        self.students
+            .read()
+            .await
+            .get(id)
+            .cloned()
+            .ok_or_else(not_found)
+    }
+
+    async fn all(&self) -> Result<Vec<Arc<Student>>, Status> {
+        Ok(self.students.read().await.values().cloned().collect())
+    }
+
+    // Under one lock, putting back what each write replaced if one fails
+    async fn commit(&self, transaction: Transaction) -> Result<Vec<Student>, TransactionFailure> {
+        let mut students = self.students.write().await;
+        let key = transaction.idempotency_key().cloned();
+        if let Some(key) = &key {
+            let mut keys = self.keys();
+            let now = SystemTime::now();
+            keys.retain(|_, (committed, _)| fresh(*committed, now));
+            if keys.contains_key(&key.key) {
+                return Err(TransactionFailure {
+                    index: None,
+                    status: key_committed(),
+                });
+            }
+        }
+
+        let mut undo = Vec::new();
+        let mut written = Vec::new();
+        for (index, write) in transaction.into_writes().into_iter().enumerate() {
+            let id = write.id().to_string();
+            let previous = students.get(&id).cloned();
+            let result = match write {
+                Write::Create(student) => self.insert(&mut students, student),
+                Write::Update(student) => self.modify(&mut students, student),
+                Write::Delete(id) => self
+                    .remove(&mut students, &id)
+                    .map(|student| Student::clone(&student)),
+            };
+            match result {
+                Ok(student) => {
+                    undo.push((id, previous));
+                    written.push(student);
+                }
+                Err(status) => {
+                    for (id, previous) in undo.into_iter().rev() {
+                        self.replace(&mut students, &id, previous);
+                    }
+                    return Err(TransactionFailure {
+                        index: Some(index),
+                        status,
+                    });
+                }
+            }
+        }
+        if let Some(key) = key {
+            let remembered = Remembered {
+                fingerprint: key.fingerprint,
+                students: written.clone(),
+            };
+            self.keys().insert(key.key, (SystemTime::now(), remembered));
+        }
+        Ok(written)
+    }
+
+    async fn remembered(&self, key: &str) -> Result<Option<Remembered>, Status> {
+        let _students = self.students.read().await;
+        let now = SystemTime::now();
+        Ok(self
+            .keys()
+            .get(key)
+            .filter(|(committed, _)| fresh(*committed, now))
+            .map(|(_, remembered)| remembered.clone()))
+    }
+}
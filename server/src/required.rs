@@ -0,0 +1,207 @@
+//! Required fields, checked before any handler runs.
+//!
+//! Request fields marked `[(required) = true]`, and oneofs marked
+//! `option (required_oneof) = true;` (see `options.proto`), must be set.
+//! [`RequiredLayer`] decodes each request against the descriptor set
+//! compiled into the `proto` crate and turns one that leaves such a field
+//! unset away with `INVALID_ARGUMENT` (reason `FIELD_REQUIRED`, the field's
+//! path as `field` in the `ErrorInfo` metadata), so handlers need not check.
+//! Requirements in nested messages apply wherever those messages are set,
+//! e.g. `entries[2].write` of a `BatchWrite`.
+//!
+//! Only requests the server reads as a single message are checked; client
+//! streams, compressed messages, and gRPC-Web text requests go to the
+//! handler as they are.
+
+use crate::locale::{self, Text};
+use hyper::Body;
+use prost_reflect::{
+    DescriptorPool, DynamicMessage, ExtensionDescriptor, MessageDescriptor, ReflectMessage, Value,
+};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tonic::body::BoxBody;
+use tonic::codegen::http::{header, Request, Response};
+use tonic::codegen::Service;
+use tower_layer::Layer;
+
+// A gRPC message frame starts with a compression flag and a 4-byte length
+const FRAME_HEADER: usize = 5;
+
+/// Rejects requests that leave a required field unset.
+#[derive(Debug, Clone)]
+pub struct RequiredLayer {
+    pool: DescriptorPool,
+    required: ExtensionDescriptor,
+    required_oneof: ExtensionDescriptor,
+}
+
+impl RequiredLayer {
+    pub fn new() -> Self {
+        let pool = DescriptorPool::decode(proto::FILE_DESCRIPTOR_SET)
+            .expect("the proto crate's descriptor set is valid");
+        let extension = |name| {
+            pool.get_extension_by_name(name)
+                .expect("options.proto is compiled in")
+        };
+        Self {
+            required: extension("student.required"),
+            required_oneof: extension("student.required_oneof"),
+            pool,
+        }
+    }
+
+    // The request message of the unary or server-streaming method at `path`
+    fn input(&self, path: &str) -> Option<MessageDescriptor> {
+        let (service, method) = path.strip_prefix('/')?.split_once('/')?;
+        let method = self
+            .pool
+            .get_service_by_name(service)?
+            .methods()
+            .find(|candidate| candidate.name() == method)?;
+        (!method.is_client_streaming()).then(|| method.input())
+    }
+
+    /// The path of the first required field `message` leaves unset, if any.
+    pub fn missing(&self, message: &DynamicMessage) -> Option<String> {
+        self.missing_in(message, "")
+    }
+
+    fn missing_in(&self, message: &DynamicMessage, prefix: &str) -> Option<String> {
+        let descriptor = message.descriptor();
+        for oneof in descriptor.oneofs() {
+            let required = oneof
+                .options()
+                .get_extension(&self.required_oneof)
+                .as_bool()
+                == Some(true);
+            if required && !oneof.fields().any(|field| message.has_field(&field)) {
+                return Some(format!("{}{}", prefix, oneof.name()));
+            }
+        }
+        for field in descriptor.fields() {
+            let path = format!("{}{}", prefix, field.name());
+            if !message.has_field(&field) {
+                if field.options().get_extension(&self.required).as_bool() == Some(true) {
+                    return Some(path);
+                }
+                continue;
+            }
+            let missing = match &*message.get_field(&field) {
+                Value::Message(nested) => self.missing_in(nested, &format!("{}.", path)),
+                Value::List(items) => items.iter().enumerate().find_map(|(index, item)| {
+                    let nested = item.as_message()?;
+                    self.missing_in(nested, &format!("{}[{}].", path, index))
+                }),
+                Value::Map(entries) => entries.iter().find_map(|(key, value)| {
+                    let nested = value.as_message()?;
+                    self.missing_in(nested, &format!("{}[{:?}].", path, key))
+                }),
+                _ => None,
+            };
+            if missing.is_some() {
+                return missing;
+            }
+        }
+        None
+    }
+}
+
+impl Default for RequiredLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for RequiredLayer {
+    type Service = Required<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Required {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// The service produced by [`RequiredLayer`].
+#[derive(Debug, Clone)]
+pub struct Required<S> {
+    inner: S,
+    layer: RequiredLayer,
+}
+
+impl<S> Service<Request<Body>> for Required<S>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let input = self
+            .layer
+            .input(request.uri().path())
+            .filter(|_| binary(&request));
+        let Some(input) = input else {
+            return Box::pin(self.inner.call(request));
+        };
+
+        // The clone that was made ready is the one that must be called
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+        Box::pin(async move {
+            let (parts, body) = request.into_parts();
+            let bytes = match hyper::body::to_bytes(body).await {
+                Ok(bytes) => bytes,
+                Err(error) => return Ok(tonic::Status::from_error(Box::new(error)).to_http()),
+            };
+            let missing = message(&bytes)
+                .and_then(|message| DynamicMessage::decode(input, message).ok())
+                .and_then(|message| layer.missing(&message));
+            if let Some(field) = missing {
+                let status = locale::invalid(&[(field.clone(), Text::FieldRequired(field))]);
+                return Ok(status.to_http());
+            }
+            inner
+                .call(Request::from_parts(parts, Body::from(bytes)))
+                .await
+        })
+    }
+}
+
+// Whether `request` carries binary gRPC (or gRPC-Web) messages
+pub(crate) fn binary<B>(request: &Request<B>) -> bool {
+    request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|content_type| {
+            content_type.starts_with("application/grpc") && !content_type.contains("-text")
+        })
+}
+
+// The one uncompressed message in `bytes`, if that is what they hold
+pub(crate) fn message(bytes: &[u8]) -> Option<&[u8]> {
+    let header = bytes.get(..FRAME_HEADER)?;
+    let length = u32::from_be_bytes(header[1..].try_into().ok()?) as usize;
+    (header[0] == 0 && bytes.len() == FRAME_HEADER + length).then(|| &bytes[FRAME_HEADER..])
+}
+
+// `message` as the one uncompressed message of a request body
+#[cfg(feature = "policies")]
+pub(crate) fn frame(message: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(FRAME_HEADER + message.len());
+    bytes.push(0);
+    bytes.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(message);
+    bytes
+}
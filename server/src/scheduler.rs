@@ -0,0 +1,337 @@
+//! Tasks the server runs on a schedule.
+//!
+//! A [`Schedule`] is either a fixed interval (`every 15m`) or a time of day
+//! in UTC (`daily 03:00`). Each run of a task is an [`Operations`] job, so
+//! its progress and outcome show up in `OperationsService`. A task that is
+//! still running when its next run comes round skips that run. With
+//! [`Scheduler::with_alerts`], scheduled runs that fail are reported.
+
+use crate::clock::{self, Clock, SystemClock};
+use crate::errors::Error;
+use crate::notify::Alerts;
+use crate::operations::{Operations, Progress};
+use proto::scheduler_service_server::SchedulerService;
+use proto::{
+    ListScheduledTasksRequest, ListScheduledTasksResponse, Operation, RunTaskNowRequest,
+    ScheduledTask,
+};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tonic::{Request, Response, Status};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// When a task runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    /// At this interval after the server starts
+    Every(Duration),
+    /// Once a day at this many seconds past midnight UTC
+    Daily(u64),
+    /// Only when asked to with `RunTaskNow`
+    Off,
+}
+
+impl Schedule {
+    /// The first run strictly after `time`, if any.
+    pub fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        match *self {
+            Schedule::Every(interval) => time.checked_add(interval),
+            Schedule::Daily(offset) => {
+                let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+                let midnight = since_epoch.as_secs() / SECONDS_PER_DAY * SECONDS_PER_DAY;
+                let today = UNIX_EPOCH + Duration::from_secs(midnight + offset);
+                Some(if today > time {
+                    today
+                } else {
+                    today + Duration::from_secs(SECONDS_PER_DAY)
+                })
+            }
+            Schedule::Off => None,
+        }
+    }
+}
+
+// "15m" and the like
+fn parse_interval(text: &str) -> Option<Duration> {
+    let split = text.find(|c: char| !c.is_ascii_digit())?;
+    let (count, unit) = text.split_at(split);
+    let count: u64 = count.parse().ok()?;
+    let seconds = match unit {
+        "s" => count,
+        "m" => count.checked_mul(60)?,
+        "h" => count.checked_mul(60 * 60)?,
+        "d" => count.checked_mul(SECONDS_PER_DAY)?,
+        _ => return None,
+    };
+    (seconds > 0).then(|| Duration::from_secs(seconds))
+}
+
+/// Parse a duration written as for `every`, such as `30m` or `24h`.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    parse_interval(text).ok_or_else(|| {
+        format!(
+            "Invalid duration {:?}; expected e.g. \"30m\" or \"24h\"",
+            text
+        )
+    })
+}
+
+// "03:00"
+fn parse_time_of_day(text: &str) -> Option<u64> {
+    let (hour, minute) = text.split_once(':')?;
+    let (hour, minute): (u64, u64) = (hour.parse().ok()?, minute.parse().ok()?);
+    (hour < 24 && minute < 60).then_some(hour * 60 * 60 + minute * 60)
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let schedule = match words.as_slice() {
+            ["off"] => Some(Schedule::Off),
+            ["every", interval] => parse_interval(interval).map(Schedule::Every),
+            ["daily", time] => parse_time_of_day(time).map(Schedule::Daily),
+            _ => None,
+        };
+        schedule.ok_or_else(|| {
+            format!(
+                "Invalid schedule {:?}; expected e.g. \"every 15m\", \"daily 03:00\", or \"off\"",
+                text
+            )
+        })
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Schedule::Every(interval) => {
+                let seconds = interval.as_secs();
+                match seconds {
+                    _ if seconds % SECONDS_PER_DAY == 0 => {
+                        write!(f, "every {}d", seconds / SECONDS_PER_DAY)
+                    }
+                    _ if seconds % 3600 == 0 => write!(f, "every {}h", seconds / 3600),
+                    _ if seconds % 60 == 0 => write!(f, "every {}m", seconds / 60),
+                    _ => write!(f, "every {}s", seconds),
+                }
+            }
+            Schedule::Daily(offset) => {
+                write!(f, "daily {:02}:{:02}", offset / 3600, offset % 3600 / 60)
+            }
+            Schedule::Off => write!(f, "off"),
+        }
+    }
+}
+
+type TaskFuture = Pin<Box<dyn Future<Output = Result<(), Status>> + Send>>;
+type TaskFn = Box<dyn Fn(Progress) -> TaskFuture + Send + Sync>;
+
+#[derive(Debug, Default)]
+struct TaskState {
+    next_run: Option<SystemTime>,
+    last_operation_id: String,
+}
+
+struct Task {
+    name: String,
+    schedule: Schedule,
+    run: TaskFn,
+    state: Mutex<TaskState>,
+}
+
+impl fmt::Debug for Task {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Task")
+            .field("name", &self.name)
+            .field("schedule", &self.schedule)
+            .field("state", &self.state)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Task {
+    fn state(&self) -> MutexGuard<'_, TaskState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The server's scheduled tasks.
+#[derive(Debug)]
+pub struct Scheduler {
+    operations: Operations,
+    clock: Arc<dyn Clock>,
+    alerts: Option<Alerts>,
+    // Ordered by name
+    tasks: Vec<Task>,
+}
+
+impl Scheduler {
+    /// Run tasks as operations in `operations`.
+    pub fn new(operations: Operations) -> Self {
+        Self {
+            operations,
+            clock: Arc::new(SystemClock),
+            alerts: None,
+            tasks: Vec::new(),
+        }
+    }
+
+    /// Work out run times with `clock` instead of the system clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Send an alert whenever a scheduled run fails.
+    pub fn with_alerts(mut self, alerts: Alerts) -> Self {
+        self.alerts = Some(alerts);
+        self
+    }
+
+    /// Add task `name`, replacing any task of that name, to run `task` on `schedule`.
+    pub fn add<F>(
+        &mut self,
+        name: &str,
+        schedule: Schedule,
+        task: impl Fn(Progress) -> F + Send + Sync + 'static,
+    ) where
+        F: Future<Output = Result<(), Status>> + Send + 'static,
+    {
+        self.tasks.retain(|task| task.name != name);
+        self.tasks.push(Task {
+            name: name.to_string(),
+            schedule,
+            run: Box::new(move |progress| Box::pin(task(progress))),
+            state: Mutex::default(),
+        });
+        self.tasks.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    fn task(&self, name: &str) -> Result<&Task, Status> {
+        self.tasks
+            .iter()
+            .find(|task| task.name == name)
+            .ok_or_else(|| Error::TaskNotFound(name.to_string()).into())
+    }
+
+    /// Start a run of task `name` unless one is already going.
+    pub fn run_now(&self, name: &str) -> Result<Operation, Status> {
+        let task = self.task(name)?;
+        let mut state = task.state();
+        let running = self.operations.get(&state.last_operation_id);
+        if running.is_ok_and(|operation| !operation.done) {
+            return Err(Error::TaskRunning(name.to_string()).into());
+        }
+        let operation = self.operations.start(name, &task.run);
+        state.last_operation_id = operation.id.clone();
+        Ok(operation)
+    }
+
+    /// The tasks, ordered by name.
+    pub fn list(&self) -> Vec<ScheduledTask> {
+        self.tasks
+            .iter()
+            .map(|task| {
+                let state = task.state();
+                ScheduledTask {
+                    name: task.name.clone(),
+                    schedule: task.schedule.to_string(),
+                    next_run_time: state.next_run.map(clock::timestamp),
+                    last_operation_id: state.last_operation_id.clone(),
+                }
+            })
+            .collect()
+    }
+
+    /// Spawn a timer for each task that has a schedule.
+    pub fn start(self: &Arc<Self>) {
+        for index in 0..self.tasks.len() {
+            let task = &self.tasks[index];
+            if task.schedule == Schedule::Off {
+                continue;
+            }
+            info!("⏰ Scheduled {} ({})", task.name, task.schedule);
+            let scheduler = self.clone();
+            tokio::spawn(async move { scheduler.run_on_schedule(index).await });
+        }
+    }
+
+    // Nobody is waiting on a scheduled run, so tell the admin if it fails
+    fn alert_on_failure(&self, name: &str, operation_id: String) {
+        let Some(alerts) = self.alerts.clone() else {
+            return;
+        };
+        let operations = self.operations.clone();
+        let name = name.to_string();
+        tokio::spawn(async move {
+            let Ok(operation) = operations.wait(&operation_id).await else {
+                return;
+            };
+            if let Some(error) = operation.error {
+                alerts
+                    .send(
+                        &format!("Scheduled task {} failed", name),
+                        &format!("Operation {} failed: {}", operation_id, error.message),
+                    )
+                    .await;
+            }
+        });
+    }
+
+    async fn run_on_schedule(&self, index: usize) {
+        let task = &self.tasks[index];
+        let mut last = self.clock.now();
+        while let Some(next) = task.schedule.next_after(last) {
+            task.state().next_run = Some(next);
+            let wait = next.duration_since(self.clock.now()).unwrap_or_default();
+            tokio::time::sleep(wait).await;
+            match self.run_now(&task.name) {
+                Ok(operation) => self.alert_on_failure(&task.name, operation.id),
+                Err(status) => info!("⏭️  Skipping {}: {}", task.name, status.message()),
+            }
+            // After a stall (e.g. a suspended machine), carry on from now
+            // rather than catching up on every missed run
+            last = next.max(self.clock.now());
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SchedulerServiceImpl {
+    scheduler: Arc<Scheduler>,
+}
+
+impl SchedulerServiceImpl {
+    pub fn new(scheduler: Arc<Scheduler>) -> Self {
+        Self { scheduler }
+    }
+}
+
+#[tonic::async_trait]
+impl SchedulerService for SchedulerServiceImpl {
+    async fn list_scheduled_tasks(
+        &self,
+        _request: Request<ListScheduledTasksRequest>,
+    ) -> Result<Response<ListScheduledTasksResponse>, Status> {
+        Ok(Response::new(ListScheduledTasksResponse {
+            tasks: self.scheduler.list(),
+        }))
+    }
+
+    async fn run_task_now(
+        &self,
+        request: Request<RunTaskNowRequest>,
+    ) -> Result<Response<Operation>, Status> {
+        let name = request.into_inner().name;
+        let operation = self.scheduler.run_now(&name)?;
+        info!("▶️  Running {} now", name);
+        Ok(Response::new(operation))
+    }
+}
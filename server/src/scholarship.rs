@@ -0,0 +1,172 @@
+//! Scholarship eligibility, evaluated as a long-running operation.
+//!
+//! `EvaluateScholarships` starts an [`Operations`] job that reads the
+//! students a page at a time, reporting progress after each page. The
+//! results are kept as the operation's result for `StreamScholarshipResults`.
+
+use crate::errors::Error;
+use crate::gpa::{self, Gpa};
+use crate::operations::{Operations, Progress};
+use crate::repository::{StudentRepository, SCAN_PAGE_SIZE};
+use crate::standing::StandingRules;
+use proto::scholarship_service_server::ScholarshipService;
+use proto::{
+    AcademicStanding, EvaluateScholarshipsRequest, Operation, ScholarshipCriteria,
+    ScholarshipResult, StreamScholarshipResultsRequest, Student,
+};
+use std::sync::Arc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+/// The kind of operation `EvaluateScholarships` starts.
+pub const KIND: &str = "scholarship-evaluation";
+
+#[derive(Debug)]
+pub struct ScholarshipServiceImpl {
+    store: Arc<dyn StudentRepository>,
+    rules: Arc<StandingRules>,
+    operations: Operations,
+}
+
+fn check_criteria(criteria: &ScholarshipCriteria) -> Result<(), Status> {
+    if !Gpa::from_f64(criteria.min_gpa).is_some_and(Gpa::is_valid) {
+        return Err(Error::MinGpaOutOfRange.into());
+    }
+    if criteria.min_credits < 0 {
+        return Err(Error::MinCreditsNegative.into());
+    }
+    Ok(())
+}
+
+/// How `student` fares against `criteria`.
+pub fn evaluate(
+    student: &Student,
+    criteria: &ScholarshipCriteria,
+    rules: &StandingRules,
+) -> ScholarshipResult {
+    let mut reasons = Vec::new();
+    // Criteria were checked when the evaluation started
+    let min_gpa = Gpa::from_f64(criteria.min_gpa).unwrap_or_default();
+    let gpa = gpa::of(student);
+    if gpa < min_gpa {
+        reasons.push(format!("GPA {} is below {}", gpa, min_gpa));
+    }
+    if student.credits < criteria.min_credits {
+        reasons.push(format!(
+            "{} credits, {} required",
+            student.credits, criteria.min_credits
+        ));
+    }
+    if rules.standing(student) == AcademicStanding::Probation {
+        reasons.push("On academic probation".to_string());
+    }
+    ScholarshipResult {
+        student_id: student.id.clone(),
+        student_name: student.name.clone(),
+        eligible: reasons.is_empty(),
+        reasons,
+    }
+}
+
+impl ScholarshipServiceImpl {
+    /// Evaluate the students in `store`, with probation decided by `rules`,
+    /// as operations in `operations`.
+    pub fn new(
+        store: Arc<dyn StudentRepository>,
+        rules: StandingRules,
+        operations: Operations,
+    ) -> Self {
+        Self {
+            store,
+            rules: Arc::new(rules),
+            operations,
+        }
+    }
+}
+
+// Every student in the major (or every student), ordered by ID
+async fn evaluate_all(
+    criteria: ScholarshipCriteria,
+    store: Arc<dyn StudentRepository>,
+    rules: Arc<StandingRules>,
+    progress: Progress,
+) -> Result<Vec<ScholarshipResult>, Status> {
+    let in_major =
+        |student: &&Student| criteria.major_id.is_empty() || student.major_id == criteria.major_id;
+    let mut results = Vec::new();
+    let mut page_token = String::new();
+    loop {
+        let page = store.list(SCAN_PAGE_SIZE, &page_token).await?;
+        results.extend(
+            page.students
+                .iter()
+                .filter(in_major)
+                .map(|student| evaluate(student, &criteria, &rules)),
+        );
+        progress.set_total(page.total_count.max(0) as usize);
+        progress.advance(page.students.len());
+
+        if page.next_page_token.is_empty() {
+            info!(
+                "🏅 Scholarship evaluation: {} of {} eligible",
+                results.iter().filter(|result| result.eligible).count(),
+                results.len()
+            );
+            return Ok(results);
+        }
+        page_token = page.next_page_token;
+    }
+}
+
+#[tonic::async_trait]
+impl ScholarshipService for ScholarshipServiceImpl {
+    async fn evaluate_scholarships(
+        &self,
+        request: Request<EvaluateScholarshipsRequest>,
+    ) -> Result<Response<Operation>, Status> {
+        let criteria = request.into_inner().criteria.unwrap_or_default();
+        check_criteria(&criteria)?;
+
+        let (store, rules) = (self.store.clone(), self.rules.clone());
+        let operation = self.operations.start(KIND, move |progress| {
+            evaluate_all(criteria, store, rules, progress)
+        });
+        Ok(Response::new(operation))
+    }
+
+    type StreamScholarshipResultsStream = ReceiverStream<Result<ScholarshipResult, Status>>;
+
+    async fn stream_scholarship_results(
+        &self,
+        request: Request<StreamScholarshipResultsRequest>,
+    ) -> Result<Response<Self::StreamScholarshipResultsStream>, Status> {
+        let request = request.into_inner();
+        let id = &request.operation_id;
+        if self.operations.get(id)?.kind != KIND {
+            return Err(Error::WrongOperationKind(id.clone(), "scholarship evaluation").into());
+        }
+
+        let operation = self.operations.wait(id).await?;
+        if let Some(error) = operation.error {
+            return Err(Status::new(error.code.into(), error.message));
+        }
+        let results = self
+            .operations
+            .result::<Vec<ScholarshipResult>>(id)
+            .ok_or_else(|| Status::internal("The operation has no results"))?;
+
+        let (tx, rx) = tokio::sync::mpsc::channel(16);
+        let eligible_only = request.eligible_only;
+        tokio::spawn(async move {
+            let wanted = results
+                .iter()
+                .filter(|result| result.eligible || !eligible_only);
+            for result in wanted {
+                if tx.send(Ok(result.clone())).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
@@ -0,0 +1,239 @@
+//! Reads limited to the students a caller may see.
+//!
+//! [`ScopedRepository`] wraps the store behind `StudentService` and hides
+//! every student outside the caller's scope from `get`, `list`, and `all`,
+//! so no filter or ordering of `ListStudents` can reach past it:
+//!
+//! - admins and staff see every student;
+//! - a professor sees their advisees, and the students whose major is in
+//!   their department;
+//! - a student sees only themselves;
+//! - anonymous callers see nobody.
+//!
+//! A hidden student is `NOT_FOUND`, as if there were none, and is not
+//! counted in `total_count`. The same goes for writes: updating or deleting
+//! a hidden student, alone or in a transaction, fails with `NOT_FOUND` and
+//! changes nothing. Work outside of any request, such as scheduled tasks,
+//! sees every student. Streams outlive their request, so `WatchStudents`
+//! takes a [`Viewer`] for its caller and shows only the events it sees.
+
+use crate::catalog::Catalog;
+use crate::identity::{self, Principal, Role};
+use crate::professor::ProfessorServiceImpl;
+use crate::repository::{
+    self, Remembered, StudentRepository, Transaction, TransactionFailure, Write,
+};
+use proto::{ListStudentsResponse, Student};
+use std::sync::Arc;
+use tonic::Status;
+
+/// Who a caller may see.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Scope {
+    Everyone,
+    Nobody,
+    Advisees {
+        professor: String,
+        department: Option<String>,
+    },
+    Only(String),
+}
+
+/// Who one caller may see, kept beyond the request that asked.
+#[derive(Debug, Clone)]
+pub struct Viewer {
+    scope: Scope,
+    professors: Arc<ProfessorServiceImpl>,
+    catalog: Arc<Catalog>,
+}
+
+impl Viewer {
+    /// Whether the caller may see `student`.
+    pub fn sees(&self, student: &Student) -> bool {
+        match &self.scope {
+            Scope::Everyone => true,
+            Scope::Nobody => false,
+            Scope::Only(id) => *id == student.id,
+            Scope::Advisees {
+                professor,
+                department,
+            } => {
+                self.professors.advisor(&student.id).as_ref() == Some(professor)
+                    || department.as_ref().is_some_and(|department| {
+                        self.catalog
+                            .major(&student.major_id)
+                            .is_some_and(|major| major.department_id == *department)
+                    })
+            }
+        }
+    }
+}
+
+/// A store whose reads show each caller only the students in their scope.
+#[derive(Debug)]
+pub struct ScopedRepository {
+    inner: Arc<dyn StudentRepository>,
+    professors: Arc<ProfessorServiceImpl>,
+    catalog: Arc<Catalog>,
+}
+
+impl ScopedRepository {
+    /// Advisors and departments come from `professors`, majors' departments
+    /// from `catalog`.
+    pub fn new(
+        inner: Arc<dyn StudentRepository>,
+        professors: Arc<ProfessorServiceImpl>,
+        catalog: Arc<Catalog>,
+    ) -> Self {
+        Self {
+            inner,
+            professors,
+            catalog,
+        }
+    }
+
+    // The scope of the request being handled
+    fn scope(&self) -> Scope {
+        let Some(caller) = identity::caller() else {
+            return Scope::Everyone;
+        };
+        match caller {
+            None => Scope::Nobody,
+            Some(Principal {
+                role: Role::Admin | Role::Staff,
+                ..
+            }) => Scope::Everyone,
+            Some(Principal {
+                role: Role::Professor,
+                id,
+            }) => Scope::Advisees {
+                department: self
+                    .professors
+                    .professor(&id)
+                    .map(|professor| professor.department_id)
+                    .filter(|department| !department.is_empty()),
+                professor: id,
+            },
+            Some(Principal {
+                role: Role::Student,
+                id,
+            }) => Scope::Only(id),
+        }
+    }
+
+    /// Who the caller of the request being handled may see.
+    pub fn viewer(&self) -> Viewer {
+        Viewer {
+            scope: self.scope(),
+            professors: self.professors.clone(),
+            catalog: self.catalog.clone(),
+        }
+    }
+
+    // Every student the viewer sees
+    async fn visible(&self, viewer: &Viewer) -> Result<Vec<Arc<Student>>, Status> {
+        let students = self.inner.all().await?;
+        Ok(students
+            .into_iter()
+            .filter(|student| viewer.sees(student))
+            .collect())
+    }
+
+    // `NOT_FOUND` unless the caller sees the student stored as `id`
+    async fn check_sees(&self, viewer: &Viewer, id: &str) -> Result<(), Status> {
+        if viewer.scope == Scope::Everyone {
+            return Ok(());
+        }
+        let student = self.inner.get_shared(id).await?;
+        match viewer.sees(&student) {
+            true => Ok(()),
+            false => Err(repository::not_found()),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl StudentRepository for ScopedRepository {
+    async fn create(&self, student: Student) -> Result<Student, Status> {
+        self.inner.create(student).await
+    }
+
+    async fn get(&self, id: &str) -> Result<Student, Status> {
+        let student = self.inner.get(id).await?;
+        if !self.viewer().sees(&student) {
+            return Err(repository::not_found());
+        }
+        Ok(student)
+    }
+
+    async fn update(&self, student: Student) -> Result<Student, Status> {
+        self.check_sees(&self.viewer(), &student.id).await?;
+        self.inner.update(student).await
+    }
+
+    async fn delete(&self, id: &str) -> Result<Student, Status> {
+        self.check_sees(&self.viewer(), id).await?;
+        self.inner.delete(id).await
+    }
+
+    // Pages of the visible students only, by offset, so a page is never
+    // short of the students after it
+    async fn list(
+        &self,
+        page_size: usize,
+        page_token: &str,
+    ) -> Result<ListStudentsResponse, Status> {
+        let viewer = self.viewer();
+        if viewer.scope == Scope::Everyone {
+            return self.inner.list(page_size, page_token).await;
+        }
+        let visible = self.visible(&viewer).await?;
+        let start = repository::page_offset(page_token, visible.len())?;
+        let end = start.saturating_add(page_size).min(visible.len());
+        Ok(ListStudentsResponse {
+            students: visible[start..end]
+                .iter()
+                .map(|student| Student::clone(student))
+                .collect(),
+            next_page_token: repository::next_page_token(end, visible.len()),
+            total_count: visible.len() as i32,
+        })
+    }
+
+    async fn create_many(&self, students: Vec<Student>) -> Vec<Result<Student, Status>> {
+        self.inner.create_many(students).await
+    }
+
+    async fn get_shared(&self, id: &str) -> Result<Arc<Student>, Status> {
+        let student = self.inner.get_shared(id).await?;
+        if !self.viewer().sees(&student) {
+            return Err(repository::not_found());
+        }
+        Ok(student)
+    }
+
+    async fn all(&self) -> Result<Vec<Arc<Student>>, Status> {
+        self.visible(&self.viewer()).await
+    }
+
+    // Updates and deletes of hidden students fail the whole transaction,
+    // as ones of missing students would
+    async fn commit(&self, transaction: Transaction) -> Result<Vec<Student>, TransactionFailure> {
+        let viewer = self.viewer();
+        for (index, write) in transaction.writes().iter().enumerate() {
+            if let Write::Update(_) | Write::Delete(_) = write {
+                if let Err(status) = self.check_sees(&viewer, write.id()).await {
+                    return Err(TransactionFailure {
+                        index: Some(index),
+                        status,
+                    });
+                }
+            }
+        }
+        self.inner.commit(transaction).await
+    }
+
+    async fn remembered(&self, key: &str) -> Result<Option<Remembered>, Status> {
+        self.inner.remembered(key).await
+    }
+}
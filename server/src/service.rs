@@ -0,0 +1,827 @@
+use crate::catalog::Catalog;
+use crate::clock::{self, Clock, SystemClock};
+use crate::collation;
+use crate::events::EventLog;
+use crate::flags::{FeatureFlags, STRICT_VALIDATION};
+use crate::gpa;
+use crate::hooks::{Verdict, WriteHook};
+use crate::idempotency;
+use crate::ids::{IdGenerator, UuidGenerator};
+use crate::locale::{self, Locale, Text};
+use crate::outbox::{self, Outbox};
+use crate::pagination::PageTokens;
+use crate::repository::{
+    next_page_token, page_offset, IdempotencyKey, InMemoryRepository, StudentRepository,
+    Transaction, TransactionFailure, Write,
+};
+use crate::scope::ScopedRepository;
+use crate::standing::StandingRules;
+use crate::timing::TimedRepository;
+use crate::trash::Trash;
+use crate::validation::{self, Profile};
+use phonenumber::country::Id;
+use proto::student_service_server::StudentService;
+use proto::batch_write_entry::Write as Entry;
+use proto::{
+    AcademicStanding, BatchWriteEntry, BatchWriteRequest, BatchWriteResponse, ChangeType, CreateStudentRequest, CreateStudentResponse, DeleteStudentRequest,
+    DeleteStudentResponse, FieldViolation, GetStudentRequest, GetStudentResponse,
+    ListStudentsByStandingRequest, ListStudentsRequest, ListStudentsResponse, Student, StudentEvent,
+    TrashReason, UpdateStudentRequest, UpdateStudentResponse, ValidateStudentRequest, ValidateStudentResponse,
+    WatchStudentsRequest,
+};
+use std::collections::HashSet;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::Notify;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{self as stream, Stream, StreamExt};
+use tonic::{Code, Request, Response, Status};
+
+/// Stream type returned by `WatchStudents`.
+pub type StudentEventStream = Pin<Box<dyn Stream<Item = Result<StudentEvent, Status>> + Send>>;
+
+#[derive(Debug)]
+pub struct StudentServiceImpl {
+    store: Arc<dyn StudentRepository>,
+    events: Arc<EventLog>,
+    // Wakes the outbox relay, if events come from an outbox
+    relay: Option<Arc<Notify>>,
+    ids: Arc<dyn IdGenerator>,
+    clock: Arc<dyn Clock>,
+    catalog: Arc<Catalog>,
+    standing: Arc<StandingRules>,
+    // Where deleted students go, if anywhere
+    trash: Option<Trash>,
+    // Whether email changes must go through EmailService
+    verify_emails: bool,
+    page_tokens: PageTokens,
+    validation: Profile,
+    flags: FeatureFlags,
+    // Run in order on each student about to be written
+    hooks: Vec<Arc<dyn WriteHook>>,
+    // Where phone numbers without a country code are from, if anywhere
+    phone_region: Option<Id>,
+    // Who each caller may see, with scoped reads
+    scoped: Option<Arc<ScopedRepository>>,
+}
+
+impl StudentServiceImpl {
+    pub fn new() -> Self {
+        Self {
+            store: Arc::new(TimedRepository(Arc::new(InMemoryRepository::new()))),
+            events: Arc::new(EventLog::new()),
+            relay: None,
+            ids: Arc::new(UuidGenerator),
+            clock: Arc::new(SystemClock),
+            catalog: Arc::new(Catalog::new()),
+            standing: Arc::new(StandingRules::default()),
+            trash: None,
+            verify_emails: false,
+            page_tokens: PageTokens::random(),
+            validation: Profile::default(),
+            flags: FeatureFlags::new(),
+            hooks: Vec::new(),
+            phone_region: None,
+            scoped: None,
+        }
+    }
+
+    /// Store students in `store` instead of in memory.
+    pub fn with_repository(mut self, store: Arc<dyn StudentRepository>) -> Self {
+        self.store = Arc::new(TimedRepository(store));
+        self
+    }
+
+    /// Show each caller only the students `scoped` lets them see, in reads,
+    /// writes, and `WatchStudents`; its store becomes the service's.
+    pub fn with_scoped_reads(mut self, scoped: Arc<ScopedRepository>) -> Self {
+        self.store = Arc::new(TimedRepository(scoped.clone()));
+        self.scoped = Some(scoped);
+        self
+    }
+
+    /// Use `ids` for students created without an ID.
+    pub fn with_id_generator(mut self, ids: Arc<dyn IdGenerator>) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    /// Use `clock` for create and update times.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Check `major_id` against `catalog` (by default an empty one).
+    pub fn with_catalog(mut self, catalog: Arc<Catalog>) -> Self {
+        self.catalog = catalog;
+        self
+    }
+
+    /// Work out each student's standing with `rules` instead of the defaults.
+    pub fn with_standing_rules(mut self, rules: StandingRules) -> Self {
+        self.standing = Arc::new(rules);
+        self
+    }
+
+    /// Keep deleted students in `trash` so they can be restored.
+    pub fn with_trash(mut self, trash: Trash) -> Self {
+        self.trash = Some(trash);
+        self
+    }
+
+    /// Refuse email changes in `UpdateStudent`, which must then be verified
+    /// with `RequestEmailChange`.
+    pub fn with_verified_emails(mut self) -> Self {
+        self.verify_emails = true;
+        self
+    }
+
+    /// Tidy up or reject non-canonical input as `profile` says (lenient by
+    /// default).
+    pub fn with_validation(mut self, profile: Profile) -> Self {
+        self.validation = profile;
+        self
+    }
+
+    /// Check `flags` for behaviors being rolled out, e.g.
+    /// [`STRICT_VALIDATION`] (by default all off).
+    pub fn with_feature_flags(mut self, flags: FeatureFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Run `hook` on each student about to be created or updated, after
+    /// the built-in validation and any hooks added before it.
+    pub fn with_write_hook(mut self, hook: Arc<dyn WriteHook>) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    /// Read phone numbers given without a country code as numbers of
+    /// `region`; by default they are refused.
+    pub fn with_phone_region(mut self, region: Id) -> Self {
+        self.phone_region = Some(region);
+        self
+    }
+
+    /// Sign page tokens with `page_tokens`, e.g. to share a secret with
+    /// other instances (by default a random one).
+    pub fn with_page_tokens(mut self, page_tokens: PageTokens) -> Self {
+        self.page_tokens = page_tokens;
+        self
+    }
+
+    /// Send events to watchers through `events`, e.g. one the store also
+    /// publishes to. Call before [`with_outbox`](Self::with_outbox).
+    pub fn with_events(mut self, events: Arc<EventLog>) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// The events sent to watchers, e.g. to [`EventLog::shut_down`] before
+    /// the server stops.
+    pub fn events(&self) -> Arc<EventLog> {
+        self.events.clone()
+    }
+
+    /// Publish events recorded in `outbox` (by the repository, along with
+    /// each change) instead of publishing them directly after each change.
+    ///
+    /// Spawns the relay task, so it must be called within a Tokio runtime.
+    pub fn with_outbox(mut self, outbox: Arc<dyn Outbox>) -> Self {
+        let wake = Arc::new(Notify::new());
+        tokio::spawn(outbox::relay(outbox, self.events.clone(), wake.clone()));
+        self.relay = Some(wake);
+        self
+    }
+
+    fn publish(&self, change_type: ChangeType, student: &Student) {
+        match &self.relay {
+            // The event is already in the outbox; have the relay pick it up now
+            Some(wake) => wake.notify_one(),
+            None => self.events.publish(change_type, student),
+        }
+    }
+
+    // Helper method to validate student data, rejecting it with every violation
+    // in the details and the first as the message
+    fn check_student(&self, student: &mut Student, kept_major_id: &str) -> Result<(), Status> {
+        let problems = self.problems(student, kept_major_id);
+        if !problems.is_empty() {
+            return Err(locale::invalid(&problems));
+        }
+        match self.run_hooks(student, kept_major_id)? {
+            Some(problem) => Err(locale::invalid(&[problem])),
+            None => Ok(()),
+        }
+    }
+
+    // `student` as the write hooks leave it, or the first problem they find
+    fn run_hooks(
+        &self,
+        student: &mut Student,
+        kept_major_id: &str,
+    ) -> Result<Option<(String, Text)>, Status> {
+        for hook in &self.hooks {
+            let verdict = hook.check(student).map_err(|e| {
+                error!("❌ Write hook {} failed: {}", hook.name(), e);
+                Status::internal(format!("Write hook {} failed", hook.name()))
+            })?;
+            match verdict {
+                Verdict::Accept => {}
+                Verdict::Replace(mut replaced) => {
+                    // A hook may enrich a student but not turn it into another one
+                    replaced.id = std::mem::take(&mut student.id);
+                    replaced.etag = std::mem::take(&mut student.etag);
+                    *student = *replaced;
+                    let problems = self.problems(student, kept_major_id);
+                    if let Some((field, text)) = problems.into_iter().next() {
+                        return Ok(Some((field.to_string(), text)));
+                    }
+                }
+                Verdict::Reject { field, message } => {
+                    let text = Text::HookRejected(hook.name().to_string(), message);
+                    return Ok(Some((field, text)));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    // Strict for tenants being moved to strict validation
+    fn validation(&self) -> Profile {
+        if self.flags.enabled(STRICT_VALIDATION) {
+            return Profile::Strict;
+        }
+        self.validation
+    }
+
+    // Tidies `student` up first if validation is lenient; `kept_major_id`,
+    // already stored, is not looked up in the catalog
+    fn problems(&self, student: &mut Student, kept_major_id: &str) -> Vec<(&'static str, Text)> {
+        let profile = self.validation();
+        let mut problems = validation::texts(profile.check(student));
+        problems.extend(validation::texts(
+            profile.phone_numbers(student, self.phone_region),
+        ));
+        if !student.major_id.is_empty()
+            && student.major_id != kept_major_id
+            && self.catalog.major(&student.major_id).is_none()
+        {
+            problems.push(("major_id", Text::UnknownMajor(student.major_id.clone())));
+        }
+        problems
+    }
+
+    fn violations(&self, student: &mut Student) -> Result<Vec<FieldViolation>, Status> {
+        let violations: Vec<_> = self
+            .problems(student, "")
+            .iter()
+            .map(|(field, text)| validation::violation(field, text))
+            .collect();
+        if !violations.is_empty() {
+            return Ok(violations);
+        }
+        Ok(self
+            .run_hooks(student, "")?
+            .iter()
+            .map(|(field, text)| validation::violation(field, text))
+            .collect())
+    }
+
+    // A catalog major's current name replaces whatever `major` says
+    fn with_major_name(&self, mut student: Student) -> Student {
+        if let Some(major) = self.catalog.major(&student.major_id) {
+            student.major = major.name;
+        }
+        student
+    }
+
+    // Standing is derived on the way out and never taken from the store
+    fn with_standing(&self, student: Student) -> Student {
+        with_standing(&self.standing, student)
+    }
+
+    // `student` checked and ready to create
+    fn new_student(&self, mut student: Student) -> Result<Student, Status> {
+        // Validate student data
+        self.check_student(&mut student, "")?;
+        
+        // Generate a new ID if not provided
+        if student.id.is_empty() {
+            student.id = self.ids.next_id();
+        }
+
+        let now = clock::timestamp(self.clock.now());
+        student.create_time = Some(now.clone());
+        student.update_time = Some(now);
+        // Kept by the grades, and a new student has none
+        student.credits_attempted = 0;
+        student.credits_earned = 0;
+        Ok(self.with_major_name(student))
+    }
+
+    // `student` checked and ready to build on the stored version with `on_stored`
+    async fn updated_student(&self, mut student: Student) -> Result<Student, Status> {
+        if student.id.trim().is_empty() {
+            return Err(locale::status(Code::InvalidArgument, Text::IdEmpty));
+        }
+        
+        // A client that changed only the double sends back the decimal it read
+        if gpa::forms_disagree(&student) {
+            let stored = self.store.get_shared(&student.id).await?;
+            if stored.gpa_decimal == student.gpa_decimal.trim() {
+                student.gpa_decimal.clear();
+            }
+        }
+
+        // The catalog is in memory, so after a restart it may no longer have
+        // the majors stored students declared; those stay as they are
+        let mut kept_major_id = String::new();
+        if !student.major_id.is_empty() && self.catalog.major(&student.major_id).is_none() {
+            kept_major_id = self.store.get_shared(&student.id).await?.major_id.clone();
+        }
+
+        // Validate student data
+        self.check_student(&mut student, &kept_major_id)?;
+
+        // The repository keeps the stored creation time; whatever the client sent is ignored
+        student.update_time = Some(clock::timestamp(self.clock.now()));
+        Ok(self.with_major_name(student))
+    }
+
+    // `student` as a replacement for the version stored now
+    async fn on_stored(&self, mut student: Student) -> Result<Student, Status> {
+        let stored = self.store.get_shared(&student.id).await?;
+        // Tidying the case or spaces of an address does not change it
+        let email_changed = !stored.email.trim().eq_ignore_ascii_case(student.email.trim());
+        if self.verify_emails && email_changed {
+            return Err(locale::status(Code::FailedPrecondition, Text::EmailChangeUnverified));
+        }
+        // Credits are kept by the grades. Writing what was just read
+        // goes through only if no grade was recorded since; an
+        // unconditional update then tries again instead of undoing it
+        student.credits_attempted = stored.credits_attempted;
+        student.credits_earned = stored.credits_earned;
+        if student.etag.is_empty() {
+            student.etag = stored.etag.clone();
+        }
+        Ok(student)
+    }
+
+    // The write a `BatchWrite` entry asks for, checked as its single call would
+    async fn batch_write_entry(&self, entry: BatchWriteEntry) -> Result<Write, Status> {
+        match entry.write {
+            Some(Entry::Create(student)) => Ok(Write::Create(self.new_student(student)?)),
+            Some(Entry::Update(student)) => Ok(Write::Update(self.updated_student(student).await?)),
+            Some(Entry::DeleteId(id)) if id.trim().is_empty() => {
+                Err(locale::status(Code::InvalidArgument, Text::IdEmpty))
+            }
+            Some(Entry::DeleteId(id)) => Ok(Write::Delete(id)),
+            None => Err(locale::status(Code::InvalidArgument, Text::BatchEntryEmpty)),
+        }
+    }
+
+    // What an earlier request with `key` wrote, if there was one
+    async fn replay(&self, key: Option<&IdempotencyKey>) -> Result<Option<Vec<Student>>, Status> {
+        idempotency::replay(self.store.as_ref(), key).await
+    }
+
+    // Commits `transaction`, or, if a request with the same idempotency key
+    // got there first, gives back what that one wrote; flags which it was
+    async fn commit_once(&self, transaction: Transaction) -> Result<(Vec<Student>, bool), TransactionFailure> {
+        let key = transaction.idempotency_key().cloned();
+        match self.store.commit(transaction).await {
+            Err(TransactionFailure { index: None, status })
+                if key.is_some() && status.code() == Code::AlreadyExists =>
+            {
+                match self.replay(key.as_ref()).await {
+                    Ok(Some(students)) => Ok((students, true)),
+                    // Committed, then already forgotten
+                    Ok(None) => Err(TransactionFailure { index: None, status }),
+                    Err(status) => Err(TransactionFailure { index: None, status }),
+                }
+            }
+            result => result.map(|students| (students, false)),
+        }
+    }
+
+    // The students in the place `req` asks for, by ID or else by preferred
+    // name, falling back to name, in the collation of the request's locale.
+    // Every student is read; page tokens are offsets into the result, only
+    // good for the same place and, ordered by name, the same locale
+    async fn list_matching(
+        &self,
+        req: &ListStudentsRequest,
+        page_size: usize,
+        by_name: bool,
+    ) -> Result<ListStudentsResponse, Status> {
+        let country = req.country.trim().to_ascii_uppercase();
+        let region = req.region.trim();
+        let mut students: Vec<Arc<Student>> = self
+            .store
+            .all()
+            .await?
+            .into_iter()
+            .filter(|student| lives_in(student, &country, region))
+            .collect();
+        let mut listing = "students".to_string();
+        if by_name {
+            let locale = Locale::current();
+            students.sort_by(|a, b| {
+                collation::compare(locale, sort_name(a), sort_name(b)).then_with(|| a.id.cmp(&b.id))
+            });
+            listing = format!("students/name/{}", locale.tag());
+        }
+        if !country.is_empty() || !region.is_empty() {
+            let region = collation::fold(region);
+            listing = format!("{}?country={:?}&region={:?}", listing, country, region);
+        }
+        let page_token = self.page_tokens.verify(&listing, &req.page_token)?;
+        let start = page_offset(&page_token, students.len())?;
+        let end = (start + page_size).min(students.len());
+
+        Ok(ListStudentsResponse {
+            students: students[start..end]
+                .iter()
+                .map(|student| self.with_standing(self.with_major_name(Student::clone(student))))
+                .collect(),
+            next_page_token: self.page_tokens.sign(&listing, next_page_token(end, students.len())),
+            total_count: students.len() as i32,
+        })
+    }
+}
+
+// What a student is listed by when ordered by name
+fn sort_name(student: &Student) -> &str {
+    if student.preferred_name.is_empty() {
+        &student.name
+    } else {
+        &student.preferred_name
+    }
+}
+
+// Whether `student`'s address is in `country` (a code) and `region` (in any
+// case or accents); an empty one matches any
+fn lives_in(student: &Student, country: &str, region: &str) -> bool {
+    if country.is_empty() && region.is_empty() {
+        return true;
+    }
+    let Some(address) = &student.address else {
+        return false;
+    };
+    (country.is_empty() || address.country == country)
+        && (region.is_empty() || collation::fold(&address.region) == collation::fold(region))
+}
+
+fn with_standing(rules: &StandingRules, mut student: Student) -> Student {
+    student.standing = rules.standing(&student) as i32;
+    // Students stored before GPAs were decimals only have the double
+    if student.gpa_decimal.is_empty() {
+        let gpa = gpa::of(&student);
+        gpa::set(&mut student, gpa);
+    }
+    student
+}
+
+impl Default for StudentServiceImpl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[tonic::async_trait]
+impl StudentService for StudentServiceImpl {
+    type WatchStudentsStream = StudentEventStream;
+
+    async fn create_student(
+        &self,
+        request: Request<CreateStudentRequest>,
+    ) -> Result<Response<CreateStudentResponse>, Status> {
+        let key = idempotency::key(&request, "CreateStudent")?;
+        if let Some(mut students) = self.replay(key.as_ref()).await? {
+            let student = self.with_standing(students.remove(0));
+            info!("Replayed creating student: {} ({})", student.name, student.id);
+            return Ok(Response::new(CreateStudentResponse {
+                student: Some(student),
+            }));
+        }
+        let student = self.new_student(request.into_inner().student.unwrap_or_default())?;
+
+        // With a key the student is created as a transaction, which stores
+        // the key with it; a backend without transactions just creates it
+        let (student, replayed) = match key {
+            Some(key) => {
+                let transaction = Transaction::new().with_create(student.clone()).with_idempotency_key(key);
+                match self.commit_once(transaction).await {
+                    Ok((mut students, replayed)) => (students.remove(0), replayed),
+                    Err(failure) if failure.status.code() == Code::Unimplemented => {
+                        (self.store.create(student).await?, false)
+                    }
+                    Err(failure) => return Err(failure.status),
+                }
+            }
+            None => (self.store.create(student).await?, false),
+        };
+        let student = self.with_standing(student);
+        if replayed {
+            info!("Replayed creating student: {} ({})", student.name, student.id);
+        } else {
+            self.publish(ChangeType::Created, &student);
+            info!("Created student: {} ({})", student.name, student.id);
+        }
+
+        Ok(Response::new(CreateStudentResponse {
+            student: Some(student),
+        }))
+    }
+
+    async fn get_student(
+        &self,
+        request: Request<GetStudentRequest>,
+    ) -> Result<Response<GetStudentResponse>, Status> {
+        let student_id = request.into_inner().id;
+        
+        if student_id.trim().is_empty() {
+            return Err(locale::status(Code::InvalidArgument, Text::IdEmpty));
+        }
+
+        let student = self.with_standing(self.with_major_name(self.store.get(&student_id).await?));
+        info!("Retrieved student: {} ({})", student.name, student.id);
+
+        Ok(Response::new(GetStudentResponse {
+            student: Some(student),
+        }))
+    }
+
+    async fn update_student(
+        &self,
+        request: Request<UpdateStudentRequest>,
+    ) -> Result<Response<UpdateStudentResponse>, Status> {
+        let student = self.updated_student(request.into_inner().student.unwrap_or_default()).await?;
+        let unconditional = student.etag.is_empty();
+        let student = loop {
+            match self.store.update(self.on_stored(student.clone()).await?).await {
+                Err(status) if unconditional && status.code() == Code::Aborted => continue,
+                result => break self.with_standing(result?),
+            }
+        };
+        self.publish(ChangeType::Updated, &student);
+        info!("Updated student: {} ({})", student.name, student.id);
+
+        Ok(Response::new(UpdateStudentResponse {
+            student: Some(student),
+        }))
+    }
+
+    async fn delete_student(
+        &self,
+        request: Request<DeleteStudentRequest>,
+    ) -> Result<Response<DeleteStudentResponse>, Status> {
+        let student_id = request.into_inner().id;
+        
+        if student_id.trim().is_empty() {
+            return Err(locale::status(Code::InvalidArgument, Text::IdEmpty));
+        }
+
+        let student = self.store.delete(&student_id).await?;
+        self.publish(ChangeType::Deleted, &student);
+        info!("Deleted student: {} ({})", student.name, student.id);
+
+        let message = format!("Student {} deleted successfully", student.name);
+        let operation_id = match &self.trash {
+            Some(trash) => trash.put(student, TrashReason::Deleted, "", None).operation_id,
+            None => String::new(),
+        };
+        Ok(Response::new(DeleteStudentResponse {
+            success: true,
+            message,
+            operation_id,
+        }))
+    }
+
+    async fn list_students(
+        &self,
+        request: Request<ListStudentsRequest>,
+    ) -> Result<Response<ListStudentsResponse>, Status> {
+        let req = request.into_inner();
+        let page_size = if req.page_size <= 0 { 10 } else { req.page_size as usize };
+        
+        let in_place = !req.country.trim().is_empty() || !req.region.trim().is_empty();
+        let page = match req.order_by.trim() {
+            "" | "id" if in_place => self.list_matching(&req, page_size, false).await?,
+            "" | "id" => {
+                let page_token = self.page_tokens.verify("students", &req.page_token)?;
+                let mut page = self.store.list(page_size, &page_token).await?;
+                page.next_page_token = self.page_tokens.sign("students", page.next_page_token);
+                page.students = page
+                    .students
+                    .into_iter()
+                    .map(|student| self.with_standing(self.with_major_name(student)))
+                    .collect();
+                page
+            }
+            "name" => self.list_matching(&req, page_size, true).await?,
+            order_by => {
+                return Err(locale::status(
+                    Code::InvalidArgument,
+                    Text::UnknownOrderBy(order_by.to_string()),
+                ))
+            }
+        };
+
+        info!("Listed {} of {} students", page.students.len(), page.total_count);
+
+        Ok(Response::new(page))
+    }
+
+    async fn list_students_by_standing(
+        &self,
+        request: Request<ListStudentsByStandingRequest>,
+    ) -> Result<Response<ListStudentsResponse>, Status> {
+        let req = request.into_inner();
+        let standing = req.standing();
+        if standing == AcademicStanding::Unspecified {
+            return Err(locale::status(Code::InvalidArgument, Text::StandingRequired));
+        }
+        let page_size = if req.page_size <= 0 { 10 } else { req.page_size as usize };
+
+        // Standing is not stored, so every student is read and filtered; page
+        // tokens are offsets into the matching students
+        let matching: Vec<Arc<Student>> = self
+            .store
+            .all()
+            .await?
+            .into_iter()
+            .filter(|student| self.standing.standing(student) == standing)
+            .collect();
+        let listing = format!("students/{}", standing.as_str_name());
+        let page_token = self.page_tokens.verify(&listing, &req.page_token)?;
+        let start = page_offset(&page_token, matching.len())?;
+        let end = (start + page_size).min(matching.len());
+
+        info!(
+            "Listed {} of {} students in standing {}",
+            end - start,
+            matching.len(),
+            standing.as_str_name()
+        );
+
+        Ok(Response::new(ListStudentsResponse {
+            students: matching[start..end]
+                .iter()
+                .map(|student| self.with_standing(self.with_major_name(Student::clone(student))))
+                .collect(),
+            next_page_token: self.page_tokens.sign(&listing, next_page_token(end, matching.len())),
+            total_count: matching.len() as i32,
+        }))
+    }
+
+    async fn validate_student(
+        &self,
+        request: Request<ValidateStudentRequest>,
+    ) -> Result<Response<ValidateStudentResponse>, Status> {
+        let mut student = request.into_inner().student.unwrap_or_default();
+
+        let violations = self.violations(&mut student)?;
+        let message = violations
+            .iter()
+            .map(|violation| violation.description.as_str())
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        let response = ValidateStudentResponse {
+            valid: violations.is_empty(),
+            message,
+            violations,
+        };
+
+        info!("Validated student: {} (valid: {})", student.name, response.valid);
+
+        Ok(Response::new(response))
+    }
+
+    async fn batch_write(
+        &self,
+        request: Request<BatchWriteRequest>,
+    ) -> Result<Response<BatchWriteResponse>, Status> {
+        let key = idempotency::key(&request, "BatchWrite")?;
+        if let Some(students) = self.replay(key.as_ref()).await? {
+            info!("Replayed batch writing {} students", students.len());
+            let students = students.into_iter().map(|student| self.with_standing(student)).collect();
+            return Ok(Response::new(BatchWriteResponse { students }));
+        }
+        let mut writes = Vec::new();
+        let mut ids = HashSet::new();
+        for (index, entry) in request.into_inner().entries.into_iter().enumerate() {
+            let write = self
+                .batch_write_entry(entry)
+                .await
+                .map_err(|status| locale::in_entry(status, index))?;
+            if !ids.insert(write.id().to_string()) {
+                let repeated = Text::BatchIdRepeated(write.id().to_string());
+                return Err(locale::in_entry(locale::status(Code::InvalidArgument, repeated), index));
+            }
+            writes.push(write);
+        }
+
+        // Updates build on the versions stored when the transaction starts;
+        // as in `update_student`, unconditional ones try again on a conflict
+        let (written, replayed) = loop {
+            let mut transaction = Transaction::new();
+            if let Some(key) = &key {
+                transaction = transaction.with_idempotency_key(key.clone());
+            }
+            for (index, write) in writes.iter().enumerate() {
+                transaction = transaction.with(match write {
+                    Write::Update(student) => Write::Update(
+                        self.on_stored(student.clone())
+                            .await
+                            .map_err(|status| locale::in_entry(status, index))?,
+                    ),
+                    write => write.clone(),
+                });
+            }
+            match self.commit_once(transaction).await {
+                Ok(written) => break written,
+                Err(TransactionFailure { index: Some(index), status }) => {
+                    let unconditional = matches!(&writes[index], Write::Update(student) if student.etag.is_empty());
+                    if unconditional && status.code() == Code::Aborted {
+                        continue;
+                    }
+                    return Err(locale::in_entry(status, index));
+                }
+                Err(TransactionFailure { index: None, status }) => return Err(status),
+            }
+        };
+
+        if replayed {
+            info!("Replayed batch writing {} students", written.len());
+            let students = written.into_iter().map(|student| self.with_standing(student)).collect();
+            return Ok(Response::new(BatchWriteResponse { students }));
+        }
+        let mut students = Vec::with_capacity(written.len());
+        for (write, student) in writes.iter().zip(written) {
+            let change_type = match write {
+                Write::Create(_) => ChangeType::Created,
+                Write::Update(_) => ChangeType::Updated,
+                Write::Delete(_) => ChangeType::Deleted,
+            };
+            self.publish(change_type, &student);
+            if let (Write::Delete(_), Some(trash)) = (write, &self.trash) {
+                trash.put(student.clone(), TrashReason::Deleted, "", None);
+            }
+            students.push(self.with_standing(student));
+        }
+        info!("Batch wrote {} students", students.len());
+
+        Ok(Response::new(BatchWriteResponse { students }))
+    }
+
+    async fn watch_students(
+        &self,
+        request: Request<WatchStudentsRequest>,
+    ) -> Result<Response<Self::WatchStudentsStream>, Status> {
+        let request = request.into_inner();
+        let standing = request.standing();
+        let WatchStudentsRequest {
+            major,
+            resume_token,
+            ..
+        } = request;
+
+        info!("Watching students (major: {})", if major.is_empty() { "any" } else { &major });
+
+        let (missed, live) = self.events.subscribe(&resume_token)?;
+        // The shutdown event has no student and goes to every watcher
+        let viewer = self.scoped.as_ref().map(|scoped| scoped.viewer());
+        let matches = move |event: &StudentEvent| {
+            event.student.as_ref().is_none_or(|student| {
+                (major.is_empty() || student.major == major)
+                    && (standing == AcademicStanding::Unspecified
+                        || student.standing() == standing)
+                    && viewer.as_ref().is_none_or(|viewer| viewer.sees(student))
+            })
+        };
+        // Events recorded through an outbox come straight from the store
+        let rules = self.standing.clone();
+        let derive = move |mut event: StudentEvent| {
+            event.student = event.student.map(|student| with_standing(&rules, student));
+            event
+        };
+        let missed: Vec<_> = missed.into_iter().map(&derive).filter(&matches).map(Ok).collect();
+        let live = BroadcastStream::new(live).filter_map(move |event| {
+            match event {
+                Ok(event) => Some(derive(event)).filter(&matches).map(Ok),
+                // The watcher fell too far behind; it has to resubscribe and re-read
+                Err(BroadcastStreamRecvError::Lagged(missed)) => Some(Err(Status::data_loss(
+                    format!("Watcher fell behind and missed {} events", missed),
+                ))),
+            }
+        });
+
+        Ok(Response::new(Box::pin(stream::iter(missed).chain(live))))
+    }
+}
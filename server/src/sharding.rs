@@ -0,0 +1,385 @@
+//! Spreading students over several stores by consistent hashing.
+//!
+//! [`ShardedRepository`] is a store made of shards, each a store of its own,
+//! usually a [`RemoteRepository`]: another server reached over gRPC. A
+//! [`HashRing`] places each student on one shard by a hash of its ID, so
+//! creates, reads, updates, and deletes go to that shard alone. Each shard
+//! has many points on the ring, so students spread evenly, and adding a
+//! shard only moves the students it takes over.
+//!
+//! Listings go to every shard and are merged in ID order, the order each
+//! shard lists in. A merged page token keeps every shard's own token and
+//! how far into that page the merge got, so pages neither repeat nor skip
+//! students. Whatever the service reads through [`StudentRepository::all`],
+//! such as the listings by name, country, or standing, is read from every
+//! shard at once.
+//!
+//! A transaction is committed by the shard its students are on; one that
+//! would write students on more than one shard fails with
+//! `FAILED_PRECONDITION`, as shards cannot commit together.
+
+use crate::encryption::from_hex;
+use crate::errors::Error;
+use crate::locale::{self, Text};
+use crate::repository::{Remembered, StudentRepository, Transaction, TransactionFailure};
+use futures::future::{join_all, try_join_all};
+use proto::student_service_client::StudentServiceClient;
+use proto::{
+    CreateStudentRequest, DeleteStudentRequest, GetStudentRequest, ListStudentsRequest,
+    ListStudentsResponse, Student, UpdateStudentRequest,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Status};
+
+/// Points each shard has on the ring.
+pub const POINTS_PER_SHARD: usize = 128;
+
+fn hash(key: &str) -> u64 {
+    let digest = Sha256::digest(key.as_bytes());
+    u64::from_be_bytes(digest[..8].try_into().expect("a SHA-256 has 32 bytes"))
+}
+
+/// Shards placed on a ring of hashes, by name.
+#[derive(Debug, Clone)]
+pub struct HashRing {
+    // Sorted by point, with the index of the shard there
+    points: Vec<(u64, usize)>,
+}
+
+impl HashRing {
+    /// A ring with `POINTS_PER_SHARD` points for each of `shards`, placed by
+    /// their names, so the same names place students the same way.
+    pub fn new<S: AsRef<str>>(shards: &[S]) -> Self {
+        let mut points: Vec<(u64, usize)> = shards
+            .iter()
+            .enumerate()
+            .flat_map(|(index, name)| {
+                (0..POINTS_PER_SHARD)
+                    .map(move |point| (hash(&format!("{}#{}", name.as_ref(), point)), index))
+            })
+            .collect();
+        points.sort_unstable();
+        Self { points }
+    }
+
+    /// The index of the shard the student with `id` is on: the one at the
+    /// first point at or after the ID's hash, going round.
+    pub fn shard(&self, id: &str) -> usize {
+        let at = hash(id);
+        let next = self.points.partition_point(|(point, _)| *point < at);
+        self.points[next % self.points.len()].1
+    }
+}
+
+// How far a merged listing has got in one shard: the shard's token for the
+// page the merge is in, and how many students of that page it has used
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ShardCursor {
+    token: String,
+    used: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Cursor {
+    // The page size asked of shards, the same on every page so their pages
+    // come back the same when read again
+    size: usize,
+    shards: Vec<ShardCursor>,
+}
+
+impl Cursor {
+    fn decode(token: &str, shards: usize) -> Result<Self, Status> {
+        from_hex(token)
+            .and_then(|json| serde_json::from_slice::<Cursor>(&json).ok())
+            .filter(|cursor| cursor.shards.len() == shards && cursor.size > 0)
+            .ok_or_else(|| locale::status(Code::InvalidArgument, Text::PageTokenInvalid))
+    }
+
+    fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).expect("a cursor is always JSON");
+        json.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+// One shard's page in a merge: the students not used yet, and the token
+// for the shard's next page
+#[derive(Debug)]
+struct Buffer {
+    students: VecDeque<Student>,
+    next: String,
+}
+
+/// A store whose students are spread over shards.
+#[derive(Debug)]
+pub struct ShardedRepository {
+    shards: Vec<Arc<dyn StudentRepository>>,
+    ring: HashRing,
+}
+
+impl ShardedRepository {
+    /// Spread students over `shards`, each named, e.g. by its address. The
+    /// names place the shards on the ring.
+    pub fn new(shards: Vec<(String, Arc<dyn StudentRepository>)>) -> Self {
+        assert!(!shards.is_empty(), "a sharded store needs a shard");
+        let names: Vec<&String> = shards.iter().map(|(name, _)| name).collect();
+        let ring = HashRing::new(&names);
+        Self {
+            shards: shards.into_iter().map(|(_, shard)| shard).collect(),
+            ring,
+        }
+    }
+
+    fn shard(&self, id: &str) -> &Arc<dyn StudentRepository> {
+        &self.shards[self.ring.shard(id)]
+    }
+
+    // The students of one shard's page left after `used`
+    async fn page(
+        &self,
+        shard: usize,
+        size: usize,
+        token: &str,
+        used: usize,
+    ) -> Result<(Buffer, i32), Status> {
+        let page = self.shards[shard].list(size, token).await?;
+        let mut students = VecDeque::from(page.students);
+        students.drain(..used.min(students.len()));
+        let buffer = Buffer {
+            students,
+            next: page.next_page_token,
+        };
+        Ok((buffer, page.total_count))
+    }
+}
+
+#[tonic::async_trait]
+impl StudentRepository for ShardedRepository {
+    async fn create(&self, student: Student) -> Result<Student, Status> {
+        self.shard(&student.id).create(student).await
+    }
+
+    async fn get(&self, id: &str) -> Result<Student, Status> {
+        self.shard(id).get(id).await
+    }
+
+    async fn update(&self, student: Student) -> Result<Student, Status> {
+        self.shard(&student.id).update(student).await
+    }
+
+    async fn delete(&self, id: &str) -> Result<Student, Status> {
+        self.shard(id).delete(id).await
+    }
+
+    async fn list(
+        &self,
+        page_size: usize,
+        page_token: &str,
+    ) -> Result<ListStudentsResponse, Status> {
+        let mut cursor = match page_token {
+            "" => Cursor {
+                size: page_size.max(1),
+                shards: vec![ShardCursor::default(); self.shards.len()],
+            },
+            token => Cursor::decode(token, self.shards.len())?,
+        };
+
+        // Every shard's page where the last one left off, read at once
+        let pages = try_join_all(
+            cursor
+                .shards
+                .iter()
+                .enumerate()
+                .map(|(shard, at)| self.page(shard, cursor.size, &at.token, at.used)),
+        )
+        .await?;
+        let total_count = pages.iter().map(|(_, total)| total).sum();
+        let mut buffers: Vec<Buffer> = pages.into_iter().map(|(buffer, _)| buffer).collect();
+
+        // Take the lowest ID of all until the page is full, reading a
+        // shard's next page when the merge has used up its last
+        let mut students = Vec::new();
+        while students.len() < page_size {
+            for (shard, buffer) in buffers.iter_mut().enumerate() {
+                if buffer.students.is_empty() && !buffer.next.is_empty() {
+                    let token = std::mem::take(&mut buffer.next);
+                    let (next, _) = self.page(shard, cursor.size, &token, 0).await?;
+                    *buffer = next;
+                    cursor.shards[shard] = ShardCursor { token, used: 0 };
+                }
+            }
+            let lowest = buffers
+                .iter()
+                .enumerate()
+                .filter_map(|(shard, buffer)| Some((buffer.students.front()?, shard)))
+                .min_by(|(a, _), (b, _)| a.id.cmp(&b.id))
+                .map(|(_, shard)| shard);
+            let Some(shard) = lowest else {
+                break;
+            };
+            students.extend(buffers[shard].students.pop_front());
+            cursor.shards[shard].used += 1;
+        }
+
+        let done = buffers
+            .iter()
+            .all(|buffer| buffer.students.is_empty() && buffer.next.is_empty());
+        Ok(ListStudentsResponse {
+            students,
+            next_page_token: if done { String::new() } else { cursor.encode() },
+            total_count,
+        })
+    }
+
+    // Each shard gets its students together
+    async fn create_many(&self, students: Vec<Student>) -> Vec<Result<Student, Status>> {
+        let mut by_shard: HashMap<usize, (Vec<usize>, Vec<Student>)> = HashMap::new();
+        for (index, student) in students.into_iter().enumerate() {
+            let (indexes, students) = by_shard.entry(self.ring.shard(&student.id)).or_default();
+            indexes.push(index);
+            students.push(student);
+        }
+        let count = by_shard.values().map(|(indexes, _)| indexes.len()).sum();
+        let created = join_all(by_shard.into_iter().map(
+            |(shard, (indexes, students))| async move {
+                (indexes, self.shards[shard].create_many(students).await)
+            },
+        ))
+        .await;
+
+        let mut results: Vec<Option<Result<Student, Status>>> = (0..count).map(|_| None).collect();
+        for (indexes, created) in created {
+            for (index, result) in indexes.into_iter().zip(created) {
+                results[index] = Some(result);
+            }
+        }
+        results
+            .into_iter()
+            .map(|result| {
+                result.unwrap_or_else(|| Err(Status::internal("Shard skipped a student")))
+            })
+            .collect()
+    }
+
+    async fn get_shared(&self, id: &str) -> Result<Arc<Student>, Status> {
+        self.shard(id).get_shared(id).await
+    }
+
+    // Every shard at once, merged in ID order as `list` pages through them
+    async fn all(&self) -> Result<Vec<Arc<Student>>, Status> {
+        let shards = try_join_all(self.shards.iter().map(|shard| shard.all())).await?;
+        let mut students: Vec<Arc<Student>> = shards.into_iter().flatten().collect();
+        students.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(students)
+    }
+
+    async fn commit(&self, transaction: Transaction) -> Result<Vec<Student>, TransactionFailure> {
+        let mut shards = transaction
+            .writes()
+            .iter()
+            .map(|write| self.ring.shard(write.id()));
+        let Some(shard) = shards.next() else {
+            return Ok(Vec::new());
+        };
+        if let Some(index) = shards.position(|other| other != shard) {
+            return Err(TransactionFailure {
+                index: Some(index + 1),
+                status: Error::CrossShardTransaction.into(),
+            });
+        }
+        self.shards[shard].commit(transaction).await
+    }
+
+    // The shard that committed a key is not known, so every one is asked
+    async fn remembered(&self, key: &str) -> Result<Option<Remembered>, Status> {
+        let remembered =
+            try_join_all(self.shards.iter().map(|shard| shard.remembered(key))).await?;
+        Ok(remembered.into_iter().flatten().next())
+    }
+}
+
+/// A store that is another server, reached through its `StudentService`.
+///
+/// The server checks and stamps students as it would for any client, and
+/// keeps its own trash and events. It has no call that commits a
+/// transaction with the store's semantics, so transactions fail with
+/// `UNIMPLEMENTED`.
+#[derive(Debug, Clone)]
+pub struct RemoteRepository {
+    client: StudentServiceClient<Channel>,
+}
+
+impl RemoteRepository {
+    /// The server at `address`, e.g. `shard-1:50051` or
+    /// `https://shard-1.example.edu`, connected to on first use and again
+    /// whenever the connection drops.
+    pub fn connect_lazy(address: &str) -> Result<Self, String> {
+        let uri = match address.contains("://") {
+            true => address.to_string(),
+            false => format!("http://{}", address),
+        };
+        let endpoint =
+            Endpoint::from_shared(uri).map_err(|e| format!("Shard {}: {}", address, e))?;
+        Ok(Self {
+            client: StudentServiceClient::new(endpoint.connect_lazy()),
+        })
+    }
+}
+
+fn returned(student: Option<Student>) -> Result<Student, Status> {
+    student.ok_or_else(|| Status::internal("Shard returned no student"))
+}
+
+#[tonic::async_trait]
+impl StudentRepository for RemoteRepository {
+    async fn create(&self, student: Student) -> Result<Student, Status> {
+        let request = CreateStudentRequest {
+            student: Some(student),
+        };
+        let response = self.client.clone().create_student(request).await?;
+        returned(response.into_inner().student)
+    }
+
+    async fn get(&self, id: &str) -> Result<Student, Status> {
+        let request = GetStudentRequest { id: id.to_string() };
+        let response = self.client.clone().get_student(request).await?;
+        returned(response.into_inner().student)
+    }
+
+    async fn update(&self, student: Student) -> Result<Student, Status> {
+        let request = UpdateStudentRequest {
+            student: Some(student),
+        };
+        let response = self.client.clone().update_student(request).await?;
+        returned(response.into_inner().student)
+    }
+
+    // DeleteStudent does not return the student, so it is read first
+    async fn delete(&self, id: &str) -> Result<Student, Status> {
+        let student = self.get(id).await?;
+        let request = DeleteStudentRequest { id: id.to_string() };
+        self.client.clone().delete_student(request).await?;
+        Ok(student)
+    }
+
+    async fn list(
+        &self,
+        page_size: usize,
+        page_token: &str,
+    ) -> Result<ListStudentsResponse, Status> {
+        let request = ListStudentsRequest {
+            page_size: page_size.min(i32::MAX as usize) as i32,
+            page_token: page_token.to_string(),
+            ..Default::default()
+        };
+        Ok(self
+            .client
+            .clone()
+            .list_students(request)
+            .await?
+            .into_inner())
+    }
+}
@@ -0,0 +1,588 @@
+//! Snapshots of the student store.
+//!
+//! A snapshot is every student as canonical proto3 JSON, one per line, in a
+//! file named after the time it was taken. It is written under a temporary
+//! name and renamed once complete, so a snapshot file is never partial.
+//!
+//! Given a [`Keyring`], the file is encrypted as a whole and named
+//! `.jsonl.enc` instead (see [`crate::encryption`]).
+//!
+//! Beside each snapshot is a [`Manifest`], `students-<seconds>.manifest.json`,
+//! written first: the student count, a checksum of every chunk of lines, the
+//! snapshot format's version, and the time. Reading a snapshot checks it against the
+//! manifest, so a truncated or damaged file is refused instead of restored
+//! in part.
+//!
+//! Given the [`EventLog`], a snapshot's manifest also records the last change
+//! it includes. [`write_changes`] then writes the changes after a sequence
+//! number to `changes-<seconds>.jsonl`, an incremental snapshot with a
+//! manifest of its own, and [`restore`] applies those that follow on from a
+//! snapshot, in order. Stopping at the last change before a given time
+//! restores the store as it was then.
+
+use crate::clock;
+use crate::encryption::{self, Keyring};
+use crate::events::{Change, EventLog};
+use crate::migrations;
+use crate::operations::Progress;
+use crate::repository::{StudentRepository, SCAN_PAGE_SIZE};
+use proto::{ChangeType, Student, Timestamp};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tonic::Status;
+
+const PLAIN: &str = "jsonl";
+const ENCRYPTED: &str = "jsonl.enc";
+const MANIFEST: &str = "manifest.json";
+const SNAPSHOT: &str = "students";
+const CHANGES: &str = "changes";
+// Lines per checksummed chunk
+const CHUNK_SIZE: usize = 1000;
+
+/// The version of the snapshot format, its lines and manifest. It changes
+/// only when they do, not with the SQL schema (see [`migrations`]).
+pub const FORMAT_VERSION: i32 = 1;
+
+// Manifests from before the format was versioned
+fn first_format() -> i32 {
+    1
+}
+
+/// What a snapshot holds, to check it against before restoring it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub created: Timestamp,
+    /// The [`FORMAT_VERSION`] it was written in.
+    #[serde(default = "first_format")]
+    pub format_version: i32,
+    /// The latest migration of the server that wrote it (see
+    /// [`migrations::latest`]), for reference only.
+    pub schema_version: i32,
+    /// Lines in the file: students, or changes in an incremental snapshot.
+    pub student_count: usize,
+    /// The lines of the plain snapshot, [`CHUNK_SIZE`] to a chunk.
+    pub chunks: Vec<Chunk>,
+    /// The change log the snapshot is a point in (see [`EventLog::id`]);
+    /// empty if it was taken without one.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub log: String,
+    /// The sequence number of the last change in the log it includes.
+    #[serde(default)]
+    pub sequence: u64,
+    /// For an incremental snapshot, the sequence number its changes follow.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<u64>,
+}
+
+/// A run of lines in a snapshot.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Chunk {
+    pub students: usize,
+    /// SHA-256 of the lines, each with its newline, in hex.
+    pub sha256: String,
+}
+
+fn hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// The manifest's chunks for `lines`, each ending in a newline
+fn chunks(lines: &[&str]) -> Vec<Chunk> {
+    lines
+        .chunks(CHUNK_SIZE)
+        .map(|chunk| Chunk {
+            students: chunk.len(),
+            sha256: hex(chunk.concat().as_bytes()),
+        })
+        .collect()
+}
+
+/// Where the manifest of the snapshot at `path` is: the same name up to
+/// the first dot, then `.manifest.json`.
+pub fn manifest_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let stem = name.split_once('.').map_or(name, |(stem, _)| stem);
+    path.with_file_name(format!("{}.{}", stem, MANIFEST))
+}
+
+/// The manifest of the snapshot at `path`, if it has one.
+pub fn manifest(path: &Path) -> Result<Option<Manifest>, String> {
+    let path = manifest_path(path);
+    match std::fs::read(&path) {
+        Ok(data) => serde_json::from_slice(&data)
+            .map(Some)
+            .map_err(|e| format!("{}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Cannot read {}: {}", path.display(), e)),
+    }
+}
+
+// Check the lines of `path` against its manifest; `force` lets through
+// another snapshot format, or a snapshot from before manifests
+fn verify(path: &Path, lines: &[&str], force: bool) -> Result<(), String> {
+    let Some(manifest) = manifest(path)? else {
+        if force {
+            warn!(
+                "⚠️  {} has no manifest; restoring it unchecked",
+                path.display()
+            );
+            return Ok(());
+        }
+        return Err(format!(
+            "{} has no manifest to check it against; restore it with --force",
+            path.display()
+        ));
+    };
+    if manifest.format_version != FORMAT_VERSION {
+        if !force {
+            return Err(format!(
+                "{} is in snapshot format {}, not this server's {}; restore it with --force",
+                path.display(),
+                manifest.format_version,
+                FORMAT_VERSION
+            ));
+        }
+        warn!(
+            "⚠️  Restoring {} from snapshot format {} into {}",
+            path.display(),
+            manifest.format_version,
+            FORMAT_VERSION
+        );
+    }
+    if lines.len() != manifest.student_count {
+        return Err(format!(
+            "{} has {} students, but its manifest lists {}",
+            path.display(),
+            lines.len(),
+            manifest.student_count
+        ));
+    }
+    let found = chunks(lines);
+    if let Some(index) = (0..manifest.chunks.len().max(found.len()))
+        .find(|index| manifest.chunks.get(*index) != found.get(*index))
+    {
+        return Err(format!(
+            "{}: students {} to {} do not match the manifest's checksum",
+            path.display(),
+            index * CHUNK_SIZE + 1,
+            ((index + 1) * CHUNK_SIZE).min(lines.len())
+        ));
+    }
+    Ok(())
+}
+
+// Write `contents` to `path` under a temporary name, then rename it
+fn replace(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    std::fs::write(&partial, contents)?;
+    std::fs::rename(&partial, path)
+}
+
+// Write `lines` and their manifest to `<prefix>-<seconds>.jsonl` in `dir`,
+// encrypted if given `keys`, returning the path
+async fn write_file(
+    dir: &Path,
+    prefix: &str,
+    now: SystemTime,
+    lines: &[String],
+    manifest: Manifest,
+    keys: Option<&Keyring>,
+) -> Result<PathBuf, Status> {
+    let manifest = serde_json::to_vec_pretty(&manifest)
+        .map_err(|e| Status::internal(format!("Cannot encode manifest: {}", e)))?;
+    let lines = lines.concat();
+    let (extension, contents) = match keys {
+        Some(keys) => (ENCRYPTED, keys.seal(lines.as_bytes())),
+        None => (PLAIN, lines.into_bytes()),
+    };
+    let path = dir.join(format!(
+        "{}-{}.{}",
+        prefix,
+        clock::timestamp(now).seconds,
+        extension
+    ));
+    // The manifest goes first, so a snapshot is never found without one
+    let written = path.clone();
+    tokio::task::spawn_blocking(move || {
+        replace(&manifest_path(&written), &manifest)?;
+        replace(&written, &contents)
+    })
+    .await
+    .map_err(|e| Status::internal(e.to_string()))?
+    .map_err(|e| Status::internal(format!("Cannot write snapshot: {}", e)))?;
+    Ok(path)
+}
+
+fn manifest_for(lines: &[String], now: SystemTime) -> Manifest {
+    let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+    Manifest {
+        created: clock::timestamp(now),
+        format_version: FORMAT_VERSION,
+        schema_version: migrations::latest(),
+        student_count: lines.len(),
+        chunks: chunks(&lines),
+        log: String::new(),
+        sequence: 0,
+        since: None,
+    }
+}
+
+/// Write every student in `store` to a new snapshot in `dir`, encrypted
+/// if given `keys`, and its manifest, returning the snapshot's path. Given
+/// `events`, the manifest records where in the log the snapshot was taken.
+pub async fn write(
+    store: &dyn StudentRepository,
+    events: Option<&EventLog>,
+    dir: &Path,
+    now: SystemTime,
+    keys: Option<&Keyring>,
+    progress: &Progress,
+) -> Result<PathBuf, Status> {
+    // Taken before reading, so changes made meanwhile are replayed on top
+    // of the snapshot even if it already has them
+    let position = events.map(|events| (events.id().to_string(), events.last()));
+    let mut lines = Vec::new();
+    let mut page_token = String::new();
+    loop {
+        let page = store.list(SCAN_PAGE_SIZE, &page_token).await?;
+        for student in &page.students {
+            let line = serde_json::to_string(student)
+                .map_err(|e| Status::internal(format!("Cannot encode student: {}", e)))?;
+            lines.push(line + "\n");
+        }
+        progress.set_total(page.total_count.max(0) as usize);
+        progress.advance(page.students.len());
+        if page.next_page_token.is_empty() {
+            break;
+        }
+        page_token = page.next_page_token;
+    }
+
+    let (log, sequence) = position.unwrap_or_default();
+    let manifest = Manifest {
+        log,
+        sequence,
+        ..manifest_for(&lines, now)
+    };
+    let path = write_file(dir, SNAPSHOT, now, &lines, manifest, keys).await?;
+    info!("📸 Wrote snapshot {}", path.display());
+    Ok(path)
+}
+
+/// Write the changes in `events` after sequence number `since` to a new
+/// incremental snapshot in `dir`, returning its path; none if there are
+/// no changes. Fails with `OUT_OF_RANGE` once the log has dropped some.
+pub async fn write_changes(
+    events: &EventLog,
+    since: u64,
+    dir: &Path,
+    now: SystemTime,
+    keys: Option<&Keyring>,
+) -> Result<Option<PathBuf>, Status> {
+    let changes = events.since(since)?;
+    let Some(last) = changes.last().map(|change| change.sequence) else {
+        return Ok(None);
+    };
+    let lines = changes
+        .iter()
+        .map(|change| serde_json::to_string(change).map(|line| line + "\n"))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Status::internal(format!("Cannot encode change: {}", e)))?;
+    let manifest = Manifest {
+        log: events.id().to_string(),
+        sequence: last,
+        since: Some(since),
+        ..manifest_for(&lines, now)
+    };
+    let path = write_file(dir, CHANGES, now, &lines, manifest, keys).await?;
+    info!(
+        "📸 Wrote {} changes to incremental snapshot {}",
+        changes.len(),
+        path.display()
+    );
+    Ok(Some(path))
+}
+
+/// The sequence number of the latest change from `log` that a snapshot in
+/// `dir` includes, incremental or not; none if no snapshot is from `log`.
+pub fn position(dir: &Path, log: &str) -> Result<Option<u64>, String> {
+    let mut latest = None;
+    for path in files(dir, SNAPSHOT)?
+        .into_iter()
+        .chain(files(dir, CHANGES)?)
+    {
+        if let Some(manifest) = manifest(&path)?.filter(|manifest| manifest.log == log) {
+            latest = latest.max(Some(manifest.sequence));
+        }
+    }
+    Ok(latest)
+}
+
+// The snapshots in `dir` whose names start with `prefix`, plain or encrypted
+fn files(dir: &Path, prefix: &str) -> Result<Vec<PathBuf>, String> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("Cannot read {}: {}", dir.display(), e))?;
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        let is_snapshot = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix(prefix)?.strip_prefix('-'))
+            .and_then(|rest| rest.split_once('.'))
+            .is_some_and(|(_, extension)| extension == PLAIN || extension == ENCRYPTED);
+        if is_snapshot {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+// The lines of the file at `path`, decrypted with `keys` if it is encrypted,
+// once they match its manifest
+fn read_lines(
+    path: &Path,
+    keys: Option<&Keyring>,
+    force: bool,
+) -> Result<(Option<Manifest>, Vec<String>), String> {
+    let data = std::fs::read(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    let data = match (encryption::sealed_with(&data), keys) {
+        (None, _) => data,
+        (Some(_), Some(keys)) => keys
+            .open(&data)
+            .map_err(|e| format!("{}: {}", path.display(), e))?,
+        (Some(id), None) => {
+            return Err(format!(
+                "{} is encrypted with key {}; no keys were given",
+                path.display(),
+                id
+            ))
+        }
+    };
+    let text = String::from_utf8(data).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    verify(path, &lines, force)?;
+    let lines = lines
+        .into_iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(str::to_string)
+        .collect();
+    Ok((manifest(path)?, lines))
+}
+
+fn parse<T: serde::de::DeserializeOwned>(path: &Path, lines: &[String]) -> Result<Vec<T>, String> {
+    lines
+        .iter()
+        .map(|line| serde_json::from_str(line).map_err(|e| format!("{}: {}", path.display(), e)))
+        .collect()
+}
+
+/// The students in the snapshot at `path`, decrypting it with `keys` if it
+/// is encrypted, once it matches its manifest.
+pub fn read(path: &Path, keys: Option<&Keyring>) -> Result<Vec<Student>, String> {
+    let (_, lines) = read_lines(path, keys, false)?;
+    parse(path, &lines)
+}
+
+/// The changes in the incremental snapshot at `path`, and its manifest.
+pub fn read_changes(
+    path: &Path,
+    keys: Option<&Keyring>,
+    force: bool,
+) -> Result<(Manifest, Vec<Change>), String> {
+    let (manifest, lines) = read_lines(path, keys, force)?;
+    let manifest = manifest
+        .filter(|manifest| manifest.since.is_some())
+        .ok_or_else(|| format!("{} is not an incremental snapshot", path.display()))?;
+    Ok((manifest, parse(path, &lines)?))
+}
+
+/// How to read snapshots to restore them.
+#[derive(Debug, Clone, Default)]
+pub struct Restore {
+    /// Read a snapshot in another format, or without a manifest;
+    /// a damaged one is never read.
+    pub force: bool,
+    /// Restore the store as it was at this time instead of as of the
+    /// latest changes.
+    pub until: Option<Timestamp>,
+}
+
+/// The latest snapshot in `dir`, other than incremental ones, taken no
+/// later than `until` if given.
+pub fn latest(dir: &Path, until: Option<&Timestamp>) -> Result<PathBuf, String> {
+    let mut latest: Option<(Timestamp, PathBuf)> = None;
+    for path in files(dir, SNAPSHOT)? {
+        let Some(manifest) = manifest(&path)? else {
+            continue;
+        };
+        let created = manifest.created;
+        if until.is_some_and(|until| clock::is_after(&created, until)) {
+            continue;
+        }
+        if latest
+            .as_ref()
+            .is_none_or(|(newest, _)| clock::is_after(&created, newest))
+        {
+            latest = Some((created, path));
+        }
+    }
+    latest.map(|(_, path)| path).ok_or_else(|| match until {
+        Some(until) => format!(
+            "{} has no snapshot taken by {}",
+            dir.display(),
+            clock::rfc3339(until)
+        ),
+        None => format!("{} has no snapshots with a manifest", dir.display()),
+    })
+}
+
+/// The students in the snapshot at `path`, or the latest one in `path` if
+/// it is a directory, as of the latest incremental snapshot beside it: each
+/// that follows on from it is read and applied in order, up to the last
+/// change no later than `options.until` if given.
+pub fn restore(
+    path: &Path,
+    keys: Option<&Keyring>,
+    options: &Restore,
+) -> Result<Vec<Student>, String> {
+    let until = options.until.as_ref();
+    let path = if path.is_dir() {
+        latest(path, until)?
+    } else {
+        path.to_path_buf()
+    };
+    let (base, lines) = read_lines(&path, keys, options.force)?;
+    if let (Some(base), Some(until)) = (&base, until) {
+        if clock::is_after(&base.created, until) {
+            return Err(format!(
+                "{} was taken after the time to restore to; restore from an earlier one",
+                path.display()
+            ));
+        }
+    }
+    let students: Vec<Student> = parse(&path, &lines)?;
+    let Some(base) = base.filter(|manifest| !manifest.log.is_empty()) else {
+        return Ok(students);
+    };
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut later = Vec::new();
+    for file in files(dir, CHANGES)? {
+        if let Some(changes) = manifest(&file)?
+            .filter(|changes| changes.log == base.log && changes.sequence > base.sequence)
+        {
+            later.push((changes.since.unwrap_or_default(), file));
+        }
+    }
+    later.sort();
+
+    let mut students: BTreeMap<String, Student> = students
+        .into_iter()
+        .map(|student| (student.id.clone(), student))
+        .collect();
+    let mut position = base.sequence;
+    'files: for (since, file) in later {
+        let (_, changes) = read_changes(&file, keys, options.force)?;
+        let is_late =
+            |change: &Change| until.is_some_and(|until| clock::is_after(&change.time, until));
+        if since > position {
+            // Missing changes only matter if they came before the time
+            if changes.first().is_some_and(is_late) {
+                break;
+            }
+            return Err(format!(
+                "Changes {} to {} are missing before {}; move it and later ones aside to restore up to change {}",
+                position + 1,
+                since,
+                file.display(),
+                position
+            ));
+        }
+        for change in changes {
+            if is_late(&change) {
+                break 'files;
+            }
+            // Overlapping files repeat changes already applied
+            if change.sequence > position {
+                position = change.sequence;
+                apply(&mut students, change);
+            }
+        }
+        info!("📸 Applied {} up to change {}", file.display(), position);
+    }
+    info!(
+        "📸 Restored {} and its changes up to change {}",
+        path.display(),
+        position
+    );
+    Ok(students.into_values().collect())
+}
+
+fn apply(students: &mut BTreeMap<String, Student>, change: Change) {
+    let change_type = change.event.change_type();
+    let Some(student) = change.event.student else {
+        return;
+    };
+    match change_type {
+        ChangeType::Created | ChangeType::Updated => {
+            students.insert(student.id.clone(), student);
+        }
+        ChangeType::Deleted => {
+            students.remove(&student.id);
+        }
+        ChangeType::Unspecified | ChangeType::ShuttingDown => {}
+    }
+}
+
+/// Encrypt every snapshot in `dir`, incremental ones included, with the
+/// first of `keys`: plain ones
+/// for the first time, and ones sealed with an older key again, so that
+/// key can be retired. Returns how many files changed.
+pub fn reencrypt(dir: &Path, keys: &Keyring) -> Result<usize, String> {
+    let mut changed = 0;
+    for path in files(dir, SNAPSHOT)?
+        .into_iter()
+        .chain(files(dir, CHANGES)?)
+    {
+        let Some((stem, _)) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.split_once('.'))
+        else {
+            continue;
+        };
+        let data =
+            std::fs::read(&path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        let plain = match encryption::sealed_with(&data) {
+            Some(id) if id == keys.primary() => continue,
+            Some(_) => keys
+                .open(&data)
+                .map_err(|e| format!("{}: {}", path.display(), e))?,
+            None => data,
+        };
+        let target = dir.join(format!("{}.{}", stem, ENCRYPTED));
+        replace(&target, &keys.seal(&plain))
+            .map_err(|e| format!("Cannot write {}: {}", target.display(), e))?;
+        if target != path {
+            std::fs::remove_file(&path)
+                .map_err(|e| format!("Cannot remove {}: {}", path.display(), e))?;
+        }
+        info!(
+            "🔐 Re-encrypted {} with key {}",
+            target.display(),
+            keys.primary()
+        );
+        changed += 1;
+    }
+    Ok(changed)
+}
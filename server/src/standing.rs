@@ -0,0 +1,66 @@
+//! Academic standing, derived from a student's GPA and credits.
+//!
+//! Standing is never stored: the student service works it out from the
+//! current [`StandingRules`] whenever it returns a student, so changing a
+//! threshold takes effect for everyone without a migration.
+
+use crate::gpa::{self, Gpa};
+use proto::{AcademicStanding, Student};
+
+/// Thresholds for each standing. The Dean's list is checked first, then
+/// probation; everyone else is in good standing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StandingRules {
+    /// Lowest GPA that makes the Dean's list
+    pub deans_list_min_gpa: Gpa,
+    /// Fewest credits that make the Dean's list
+    pub deans_list_min_credits: i32,
+    /// Students with a GPA below this are on probation...
+    pub probation_below_gpa: Gpa,
+    /// ...once they have at least this many credits
+    pub probation_min_credits: i32,
+}
+
+impl Default for StandingRules {
+    fn default() -> Self {
+        Self {
+            deans_list_min_gpa: Gpa::from_hundredths(350),
+            deans_list_min_credits: 12,
+            probation_below_gpa: Gpa::from_hundredths(200),
+            probation_min_credits: 1,
+        }
+    }
+}
+
+impl StandingRules {
+    /// Check that the thresholds make sense together.
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, gpa) in [
+            ("Dean's list GPA", self.deans_list_min_gpa),
+            ("probation GPA", self.probation_below_gpa),
+        ] {
+            if !gpa.is_valid() {
+                return Err(format!("{} must be between 0.0 and 4.0", name));
+            }
+        }
+        if self.deans_list_min_credits < 0 || self.probation_min_credits < 0 {
+            return Err("Credit thresholds cannot be negative".to_string());
+        }
+        if self.probation_below_gpa > self.deans_list_min_gpa {
+            return Err("Probation GPA cannot be above the Dean's list GPA".to_string());
+        }
+        Ok(())
+    }
+
+    /// The standing `student` has under these rules.
+    pub fn standing(&self, student: &Student) -> AcademicStanding {
+        let gpa = gpa::of(student);
+        if gpa >= self.deans_list_min_gpa && student.credits >= self.deans_list_min_credits {
+            AcademicStanding::DeansList
+        } else if gpa < self.probation_below_gpa && student.credits >= self.probation_min_credits {
+            AcademicStanding::Probation
+        } else {
+            AcademicStanding::Good
+        }
+    }
+}
@@ -0,0 +1,331 @@
+//! Aggregate statistics, kept up to date as students change.
+//!
+//! [`CountedRepository`] wraps the store and adjusts the [`Statistics`]
+//! counters on every create, update, and delete, so `GetStatistics` only
+//! reads them and never scans the store. Only writes made through the
+//! wrapper are counted; what the store already holds is counted once with
+//! [`CountedRepository::count_existing`].
+//!
+//! When students are kept in memory, `GetStatistics` also reports the memory
+//! they take (see [`crate::memory`]).
+
+use crate::events::EventLog;
+use crate::gpa;
+use crate::repository::{
+    InMemoryRepository, Remembered, StudentRepository, Transaction, TransactionFailure, Write,
+};
+use crate::standing::StandingRules;
+use proto::statistics_service_server::StatisticsService;
+use proto::{
+    AcademicStanding, GetStatisticsRequest, GetStatisticsResponse, ListStudentsResponse,
+    MajorStatistics, MemoryUsage, Student,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tonic::{Request, Response, Status};
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Totals {
+    count: i64,
+    // In hundredths of a point, so adding and taking away the same
+    // students always gets back to exactly where it was
+    gpa: i64,
+    credits: i64,
+}
+
+impl Totals {
+    fn add(&mut self, student: &Student, sign: i64) {
+        self.count += sign;
+        self.gpa += sign * gpa::of(student).hundredths();
+        self.credits += sign * i64::from(student.credits);
+    }
+
+    fn average_gpa(&self) -> f64 {
+        match self.count {
+            0 => 0.0,
+            count => self.gpa as f64 / 100.0 / count as f64,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    all: Totals,
+    majors: HashMap<String, Totals>,
+    standings: HashMap<i32, i64>,
+}
+
+/// Counters over every student, shared by the store wrapper and the service.
+#[derive(Debug)]
+pub struct Statistics {
+    rules: StandingRules,
+    counters: Mutex<Counters>,
+}
+
+impl Statistics {
+    /// Count standings with `rules`.
+    pub fn new(rules: StandingRules) -> Self {
+        Self {
+            rules,
+            counters: Mutex::default(),
+        }
+    }
+
+    fn counters(&self) -> MutexGuard<'_, Counters> {
+        self.counters.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Count `student` in (sign 1) or out (sign -1)
+    fn add(&self, student: &Student, sign: i64) {
+        let standing = self.rules.standing(student) as i32;
+        let mut counters = self.counters();
+        counters.all.add(student, sign);
+        let major = counters.majors.entry(student.major.clone()).or_default();
+        major.add(student, sign);
+        if major.count == 0 {
+            counters.majors.remove(&student.major);
+        }
+        *counters.standings.entry(standing).or_default() += sign;
+    }
+
+    /// The statistics as they stand.
+    pub fn get(&self) -> GetStatisticsResponse {
+        let counters = self.counters();
+        let standing = |standing: AcademicStanding| {
+            counters
+                .standings
+                .get(&(standing as i32))
+                .copied()
+                .unwrap_or_default()
+        };
+        let mut majors: Vec<MajorStatistics> = counters
+            .majors
+            .iter()
+            .map(|(major, totals)| MajorStatistics {
+                major: major.clone(),
+                student_count: totals.count,
+                average_gpa: totals.average_gpa(),
+            })
+            .collect();
+        majors.sort_by(|a, b| a.major.cmp(&b.major));
+
+        let all = counters.all;
+        GetStatisticsResponse {
+            student_count: all.count,
+            average_gpa: all.average_gpa(),
+            average_credits: match all.count {
+                0 => 0.0,
+                count => all.credits as f64 / count as f64,
+            },
+            majors,
+            deans_list_count: standing(AcademicStanding::DeansList),
+            good_standing_count: standing(AcademicStanding::Good),
+            probation_count: standing(AcademicStanding::Probation),
+            memory: None,
+        }
+    }
+}
+
+/// Wraps a repository and counts every change made through it.
+#[derive(Debug)]
+pub struct CountedRepository {
+    inner: Arc<dyn StudentRepository>,
+    statistics: Arc<Statistics>,
+    // A lock per student being updated, held from reading its old version
+    // until its new one is counted, so two updates of one student cannot
+    // both take away the same old version
+    updating: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+// The locks on some students' updates, given back when dropped
+struct Updating<'a> {
+    locks: &'a Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    held: Vec<(String, tokio::sync::OwnedMutexGuard<()>)>,
+}
+
+impl Drop for Updating<'_> {
+    fn drop(&mut self) {
+        let mut locks = self.locks.lock().unwrap();
+        for (id, guard) in self.held.drain(..) {
+            drop(guard);
+            // Forget the lock once no other update is waiting on it
+            if locks
+                .get(&id)
+                .is_some_and(|lock| Arc::strong_count(lock) == 1)
+            {
+                locks.remove(&id);
+            }
+        }
+    }
+}
+
+impl CountedRepository {
+    /// Count changes to `inner` in `statistics`.
+    pub fn new(inner: Arc<dyn StudentRepository>, statistics: Arc<Statistics>) -> Self {
+        Self {
+            inner,
+            statistics,
+            updating: Mutex::default(),
+        }
+    }
+
+    // Lock updates of the students with `ids`, in ID order so two
+    // transactions can't each hold what the other waits for
+    async fn updating<'a>(&self, ids: impl IntoIterator<Item = &'a str>) -> Updating<'_> {
+        let mut ids: Vec<&str> = ids.into_iter().collect();
+        ids.sort_unstable();
+        ids.dedup();
+        let mut updating = Updating {
+            locks: &self.updating,
+            held: Vec::new(),
+        };
+        for id in ids {
+            let lock = self
+                .updating
+                .lock()
+                .unwrap()
+                .entry(id.to_string())
+                .or_default()
+                .clone();
+            updating
+                .held
+                .push((id.to_string(), lock.lock_owned().await));
+        }
+        updating
+    }
+
+    /// Count the students already stored, once, before serving.
+    pub async fn count_existing(&self) -> Result<(), Status> {
+        for student in self.inner.all().await? {
+            self.statistics.add(&student, 1);
+        }
+        Ok(())
+    }
+}
+
+#[tonic::async_trait]
+impl StudentRepository for CountedRepository {
+    async fn create(&self, student: Student) -> Result<Student, Status> {
+        let created = self.inner.create(student).await?;
+        self.statistics.add(&created, 1);
+        Ok(created)
+    }
+
+    async fn get(&self, id: &str) -> Result<Student, Status> {
+        self.inner.get(id).await
+    }
+
+    async fn update(&self, student: Student) -> Result<Student, Status> {
+        let _updating = self.updating([student.id.as_str()]).await;
+        let old = self.inner.get_shared(&student.id).await?;
+        let updated = self.inner.update(student).await?;
+        self.statistics.add(&old, -1);
+        self.statistics.add(&updated, 1);
+        Ok(updated)
+    }
+
+    async fn delete(&self, id: &str) -> Result<Student, Status> {
+        let deleted = self.inner.delete(id).await?;
+        self.statistics.add(&deleted, -1);
+        Ok(deleted)
+    }
+
+    async fn list(
+        &self,
+        page_size: usize,
+        page_token: &str,
+    ) -> Result<ListStudentsResponse, Status> {
+        self.inner.list(page_size, page_token).await
+    }
+
+    async fn create_many(&self, students: Vec<Student>) -> Vec<Result<Student, Status>> {
+        let results = self.inner.create_many(students).await;
+        for created in results.iter().flatten() {
+            self.statistics.add(created, 1);
+        }
+        results
+    }
+
+    async fn get_shared(&self, id: &str) -> Result<Arc<Student>, Status> {
+        self.inner.get_shared(id).await
+    }
+
+    async fn all(&self) -> Result<Vec<Arc<Student>>, Status> {
+        self.inner.all().await
+    }
+
+    async fn commit(&self, transaction: Transaction) -> Result<Vec<Student>, TransactionFailure> {
+        let updated = transaction.writes().iter().filter_map(|write| match write {
+            Write::Update(student) => Some(student.id.as_str()),
+            Write::Create(_) | Write::Delete(_) => None,
+        });
+        let _updating = self.updating(updated).await;
+        // How each written student counts, and the old version of an
+        // updated one, which no longer does
+        let mut changes = Vec::new();
+        for write in transaction.writes() {
+            changes.push(match write {
+                Write::Create(_) => (1, None),
+                Write::Update(student) => (1, self.inner.get_shared(&student.id).await.ok()),
+                Write::Delete(_) => (-1, None),
+            });
+        }
+
+        let written = self.inner.commit(transaction).await?;
+        for (student, (sign, old)) in written.iter().zip(changes) {
+            if let Some(old) = old {
+                self.statistics.add(&old, -1);
+            }
+            self.statistics.add(student, sign);
+        }
+        Ok(written)
+    }
+
+    async fn remembered(&self, key: &str) -> Result<Option<Remembered>, Status> {
+        self.inner.remembered(key).await
+    }
+}
+
+#[derive(Debug)]
+pub struct StatisticsServiceImpl {
+    statistics: Arc<Statistics>,
+    // What memory is reported for, when students are kept in memory
+    memory: Option<(Arc<InMemoryRepository>, Arc<EventLog>)>,
+}
+
+impl StatisticsServiceImpl {
+    pub fn new(statistics: Arc<Statistics>) -> Self {
+        Self {
+            statistics,
+            memory: None,
+        }
+    }
+
+    /// Report the memory held by `store` and by the history in `events`.
+    pub fn with_memory(mut self, store: Arc<InMemoryRepository>, events: Arc<EventLog>) -> Self {
+        self.memory = Some((store, events));
+        self
+    }
+}
+
+#[tonic::async_trait]
+impl StatisticsService for StatisticsServiceImpl {
+    async fn get_statistics(
+        &self,
+        _request: Request<GetStatisticsRequest>,
+    ) -> Result<Response<GetStatisticsResponse>, Status> {
+        let mut response = self.statistics.get();
+        if let Some((store, events)) = &self.memory {
+            let store = store.memory();
+            let history = events.history_size();
+            response.memory = Some(MemoryUsage {
+                records_bytes: store.records as i64,
+                index_bytes: store.index as i64,
+                history_bytes: history as i64,
+                total_bytes: (store.used() + history) as i64,
+                limit_bytes: store.limit.unwrap_or(0) as i64,
+            });
+        }
+        Ok(Response::new(response))
+    }
+}
@@ -0,0 +1,130 @@
+//! `StorageService`: the state of the server's storage, copying it to
+//! another backend (see [`crate::transfer`]), and exporting it for
+//! analytics (see `crate::export`, with the `analytics` feature).
+
+use crate::encryption::KeyProvider;
+use crate::errors::Error;
+#[cfg(feature = "analytics")]
+use crate::export;
+use crate::migrations::{self, SchemaStore};
+use crate::operations::Operations;
+use crate::repository::StudentRepository;
+use crate::transfer::{self, Location};
+use proto::storage_service_server::StorageService;
+use proto::{
+    ExportChunk, ExportStudentsRequest, GetSchemaVersionRequest, GetSchemaVersionResponse,
+    GetStorageMigrationResultRequest, MigrateStorageRequest, MigrateStorageResponse, Migration,
+    Operation,
+};
+use std::sync::Arc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+#[derive(Debug)]
+pub struct StorageServiceImpl {
+    store: Arc<dyn StudentRepository>,
+    operations: Operations,
+    schema: Option<Arc<dyn SchemaStore>>,
+    keys: Option<Arc<dyn KeyProvider>>,
+}
+
+impl StorageServiceImpl {
+    /// Copies the students in `store`, as operations in `operations`.
+    pub fn new(store: Arc<dyn StudentRepository>, operations: Operations) -> Self {
+        Self {
+            store,
+            operations,
+            schema: None,
+            keys: None,
+        }
+    }
+
+    /// Report the schema version of `schema`'s database.
+    pub fn with_schema(mut self, schema: Arc<dyn SchemaStore>) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// Encrypt snapshots copied to with the first of these keys.
+    pub fn with_keys(mut self, keys: Arc<dyn KeyProvider>) -> Self {
+        self.keys = Some(keys);
+        self
+    }
+}
+
+#[tonic::async_trait]
+impl StorageService for StorageServiceImpl {
+    type ExportStudentsStream = ReceiverStream<Result<ExportChunk, Status>>;
+
+    async fn get_schema_version(
+        &self,
+        _request: Request<GetSchemaVersionRequest>,
+    ) -> Result<Response<GetSchemaVersionResponse>, Status> {
+        let Some(schema) = &self.schema else {
+            return Ok(Response::new(GetSchemaVersionResponse::default()));
+        };
+        let version = schema.schema_version().await?;
+        let pending = migrations::pending(version)
+            .iter()
+            .map(|migration| Migration {
+                version: migration.version,
+                name: migration.name.to_string(),
+            })
+            .collect();
+        Ok(Response::new(GetSchemaVersionResponse {
+            version,
+            latest_version: migrations::latest(),
+            pending,
+        }))
+    }
+
+    async fn migrate_storage(
+        &self,
+        request: Request<MigrateStorageRequest>,
+    ) -> Result<Response<Operation>, Status> {
+        let destination: Location = request
+            .into_inner()
+            .destination
+            .parse()
+            .map_err(Error::DestinationInvalid)?;
+        // Read the keys afresh, so a newly added one is used
+        let keys = self
+            .keys
+            .as_ref()
+            .map(|provider| provider.keys())
+            .transpose()
+            .map_err(Status::failed_precondition)?;
+        Ok(Response::new(transfer::start(
+            &self.operations,
+            self.store.clone(),
+            destination,
+            keys,
+        )))
+    }
+
+    async fn get_storage_migration_result(
+        &self,
+        request: Request<GetStorageMigrationResultRequest>,
+    ) -> Result<Response<MigrateStorageResponse>, Status> {
+        let id = request.into_inner().operation_id;
+        Ok(Response::new(
+            transfer::outcome(&self.operations, &id).await?,
+        ))
+    }
+
+    #[cfg_attr(not(feature = "analytics"), allow(unused_variables))]
+    async fn export_students(
+        &self,
+        request: Request<ExportStudentsRequest>,
+    ) -> Result<Response<Self::ExportStudentsStream>, Status> {
+        #[cfg(feature = "analytics")]
+        return Ok(Response::new(export::stream(
+            self.store.clone(),
+            request.into_inner().format(),
+        )));
+        #[cfg(not(feature = "analytics"))]
+        Err(Status::unimplemented(
+            "This server was built without the analytics feature",
+        ))
+    }
+}
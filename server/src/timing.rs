@@ -0,0 +1,491 @@
+//! Server-side timing metadata.
+//!
+//! [`TimingLayer`] wraps the whole gRPC server and adds two metadata entries
+//! to every response:
+//!
+//! - `server-timing`: where the time went, in the W3C Server-Timing format,
+//!   e.g. `queue;dur=0.08, storage;dur=0.31, total;dur=0.52` (milliseconds).
+//!   `queue` runs from the request reaching the server to the handler
+//!   starting (including the other layers and reading the request), `storage` is time spent in
+//!   the repository, and `total` runs until the response headers are ready.
+//! - `server-instance`: which server instance answered.
+//!
+//! They are sent with the response headers, so streaming calls report the
+//! time until the stream started, and `server-timing` again in the
+//! trailers, with the time until the call ended. [`StartLayer`], innermost
+//! of the server's layers, marks where the handlers start;
+//! [`TimedRepository`] measures storage calls.
+//!
+//! At debug level the same timings are logged, with the method and any
+//! `x-request-id` the caller sent, for the share of requests the
+//! [logging](crate::logging) configuration samples. Each of those is
+//! logged with a trace ID: the one in the caller's W3C `traceparent`, or
+//! else a new one.
+//!
+//! Given [`Metrics`], the layer also counts every call's total time into
+//! [`HANDLING_SECONDS`] by method. A sampled call leaves its trace ID as
+//! the exemplar of its bucket, so a slow bucket in a dashboard leads to the
+//! log line of a call that landed there. Each call is also counted in
+//! [`HANDLED`] by method, status code, and tenant. Only the tenants
+//! [`TenantLabels`] picks are labeled as themselves, so a flood of tenants
+//! cannot flood Prometheus with series. The code is the one in the response
+//! headers, which is where a failed unary call has it; a stream that fails
+//! after it started counts as `OK`.
+
+use crate::flags;
+use crate::logging;
+use crate::metrics::{Kind, Metric, Metrics};
+use crate::repository::{Remembered, StudentRepository, Transaction, TransactionFailure};
+use proto::{ListStudentsResponse, Student};
+use std::collections::HashSet;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tonic::body::BoxBody;
+use tonic::codegen::http::{HeaderMap, HeaderValue, Request, Response};
+use tonic::codegen::{Body, BoxFuture, Bytes, Service};
+use tonic::{Code, Status};
+use tower_layer::Layer;
+
+pub const SERVER_TIMING: &str = "server-timing";
+pub const SERVER_INSTANCE: &str = "server-instance";
+pub const REQUEST_ID: &str = "x-request-id";
+pub const TRACEPARENT: &str = "traceparent";
+
+pub const HANDLING_SECONDS: Metric = Metric {
+    name: "grpc_server_handling_seconds",
+    help: "Time from a call reaching the server to its response headers",
+    kind: Kind::Histogram,
+};
+
+pub const HANDLED: Metric = Metric {
+    name: "grpc_server_handled_total",
+    help: "Calls answered, by method, status code, and tenant",
+    kind: Kind::Counter,
+};
+
+/// The `tenant` label of calls from tenants without one of their own.
+pub const OTHER_TENANTS: &str = "other";
+
+/// Tenants labeled as themselves unless `--metrics-tenant` names them.
+pub const DEFAULT_TENANT_LIMIT: usize = 20;
+
+// The status of a call to a method the server does not have, left out of
+// the metrics so made-up paths add no labels
+const UNIMPLEMENTED: &str = "12";
+
+/// Which tenants [`HANDLED`] labels as themselves: those allowed, or else
+/// the first so many to make calls. Calls from the others are labeled
+/// [`OTHER_TENANTS`], and calls without a tenant `""`.
+#[derive(Debug)]
+pub struct TenantLabels {
+    allowed: Option<HashSet<String>>,
+    limit: usize,
+    seen: Mutex<HashSet<String>>,
+}
+
+impl TenantLabels {
+    /// Only `tenants`.
+    pub fn allow(tenants: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            allowed: Some(tenants.into_iter().collect()),
+            limit: 0,
+            seen: Mutex::default(),
+        }
+    }
+
+    /// The first `limit` tenants to make calls, as long as the server runs.
+    pub fn first(limit: usize) -> Self {
+        Self {
+            allowed: None,
+            limit,
+            seen: Mutex::default(),
+        }
+    }
+
+    /// The `tenant` label of a call from `tenant`.
+    pub fn label<'a>(&self, tenant: Option<&'a str>) -> &'a str {
+        let Some(tenant) = tenant else {
+            return "";
+        };
+        if let Some(allowed) = &self.allowed {
+            return match allowed.contains(tenant) {
+                true => tenant,
+                false => OTHER_TENANTS,
+            };
+        }
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        if seen.contains(tenant) {
+            return tenant;
+        }
+        if seen.len() < self.limit {
+            seen.insert(tenant.to_string());
+            return tenant;
+        }
+        OTHER_TENANTS
+    }
+}
+
+impl Default for TenantLabels {
+    fn default() -> Self {
+        Self::first(DEFAULT_TENANT_LIMIT)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Timings {
+    handler_started: Option<Instant>,
+    storage: Duration,
+}
+
+tokio::task_local! {
+    static TIMINGS: Arc<Mutex<Timings>>;
+}
+
+// Outside a timed request (e.g. the service called directly in tests) there
+// is nothing to record into
+fn record(update: impl FnOnce(&mut Timings)) {
+    let _ = TIMINGS.try_with(|timings| {
+        let mut timings = timings.lock().unwrap_or_else(|e| e.into_inner());
+        update(&mut timings)
+    });
+}
+
+fn handler_started() {
+    record(|timings| {
+        timings.handler_started.get_or_insert_with(Instant::now);
+    });
+}
+
+async fn storage<F: Future>(call: F) -> F::Output {
+    let started = Instant::now();
+    let output = call.await;
+    record(|timings| timings.storage += started.elapsed());
+    output
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+// The `server-timing` of a call that reached the server at `received`, as
+// of now, and its total so far
+fn server_timing(timings: &Mutex<Timings>, received: Instant) -> (String, Duration) {
+    let total = received.elapsed();
+    let timings = timings.lock().unwrap_or_else(|e| e.into_inner());
+    let queue = timings
+        .handler_started
+        .map_or(total, |started| started - received);
+    let value = format!(
+        "queue;dur={:.2}, storage;dur={:.2}, total;dur={:.2}",
+        millis(queue),
+        millis(timings.storage),
+        millis(total)
+    );
+    (value, total)
+}
+
+// The trace ID in a `traceparent` such as
+// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`: 32 lower-case
+// hex digits, not all zero
+fn parent_trace_id(traceparent: &str) -> Option<&str> {
+    let id = traceparent.trim().split('-').nth(1)?;
+    let valid = id.len() == 32
+        && id.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+        && id.bytes().any(|b| b != b'0');
+    valid.then_some(id)
+}
+
+fn trace_id<B>(request: &Request<B>) -> String {
+    request
+        .headers()
+        .get(TRACEPARENT)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parent_trace_id)
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string())
+}
+
+// `/student.StudentService/GetStudent` as the service and the method
+fn method_labels(path: &str) -> (String, String) {
+    let path = path.trim_start_matches('/');
+    let (service, method) = path.split_once('/').unwrap_or((path, ""));
+    (service.to_string(), method.to_string())
+}
+
+/// Adds `server-timing` and `server-instance` to every response.
+#[derive(Debug, Clone)]
+pub struct TimingLayer {
+    instance: HeaderValue,
+    metrics: Option<Metrics>,
+    tenants: Arc<TenantLabels>,
+}
+
+impl TimingLayer {
+    /// `instance` identifies this server in responses; it must be printable ASCII.
+    pub fn new(instance: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            instance: HeaderValue::from_str(instance)?,
+            metrics: None,
+            tenants: Arc::default(),
+        })
+    }
+
+    /// Count each call into [`HANDLING_SECONDS`] and [`HANDLED`] in
+    /// `metrics`.
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Label calls in [`HANDLED`] by the tenants `tenants` picks, instead of
+    /// the first [`DEFAULT_TENANT_LIMIT`].
+    pub fn with_tenant_labels(mut self, tenants: TenantLabels) -> Self {
+        self.tenants = Arc::new(tenants);
+        self
+    }
+}
+
+impl<S> Layer<S> for TimingLayer {
+    type Service = Timed<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Timed {
+            inner,
+            instance: self.instance.clone(),
+            metrics: self.metrics.clone(),
+            tenants: self.tenants.clone(),
+        }
+    }
+}
+
+/// The service produced by [`TimingLayer`].
+#[derive(Debug, Clone)]
+pub struct Timed<S> {
+    inner: S,
+    instance: HeaderValue,
+    metrics: Option<Metrics>,
+    tenants: Arc<TenantLabels>,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for Timed<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<BoxBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let received = Instant::now();
+        let path = request.uri().path().to_string();
+        let tenant = flags::request_tenant(&request);
+        // What to log the call as, and its trace ID
+        let trace = logging::trace(module_path!()).then(|| {
+            let call = match request
+                .headers()
+                .get(REQUEST_ID)
+                .and_then(|id| id.to_str().ok())
+            {
+                Some(id) => format!("{} ({})", path, id),
+                None => path.clone(),
+            };
+            (call, trace_id(&request))
+        });
+        let timings = Arc::new(Mutex::new(Timings::default()));
+        let response = TIMINGS.scope(timings.clone(), self.inner.call(request));
+        let instance = self.instance.clone();
+        let metrics = self.metrics.clone();
+        let tenants = self.tenants.clone();
+
+        Box::pin(async move {
+            let mut response = response.await?;
+            let (value, total) = server_timing(&timings, received);
+            if let Some((call, trace_id)) = &trace {
+                debug!("🔍 {} trace_id={} {}", call, trace_id, value);
+            }
+            let status = response.headers().get("grpc-status");
+            let unimplemented = status.is_some_and(|status| status == UNIMPLEMENTED);
+            if let (Some(metrics), false) = (metrics, unimplemented) {
+                let (service, method) = method_labels(&path);
+                let code = status.map_or(Code::Ok, |status| Code::from_bytes(status.as_bytes()));
+                metrics.increment(
+                    &HANDLED,
+                    &[
+                        ("grpc_service", &service),
+                        ("grpc_method", &method),
+                        ("grpc_code", &format!("{:?}", code)),
+                        ("tenant", tenants.label(tenant.as_deref())),
+                    ],
+                );
+                let labels = [("grpc_service", &*service), ("grpc_method", &*method)];
+                let seconds = total.as_secs_f64();
+                match &trace {
+                    Some((_, trace_id)) => metrics.observe_with_exemplar(
+                        &HANDLING_SECONDS,
+                        &labels,
+                        seconds,
+                        &[("trace_id", trace_id)],
+                    ),
+                    None => metrics.observe(&HANDLING_SECONDS, &labels, seconds),
+                }
+            }
+            let headers = response.headers_mut();
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(SERVER_TIMING, value);
+            }
+            headers.insert(SERVER_INSTANCE, instance);
+            Ok(response.map(|inner| {
+                TimedBody {
+                    inner,
+                    timings,
+                    received,
+                }
+                .boxed_unsync()
+            }))
+        })
+    }
+}
+
+// A response body that adds `server-timing` to its trailers. Storage
+// calls made while it is read, as a stream is, count too.
+struct TimedBody {
+    inner: BoxBody,
+    timings: Arc<Mutex<Timings>>,
+    received: Instant,
+}
+
+impl Body for TimedBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Status>>> {
+        let timings = self.timings.clone();
+        TIMINGS.sync_scope(timings, || Pin::new(&mut self.inner).poll_data(cx))
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Status>> {
+        let timings = self.timings.clone();
+        let trailers = TIMINGS.sync_scope(timings, || Pin::new(&mut self.inner).poll_trailers(cx));
+        // A call answered in its headers alone has no trailers to add to
+        let Poll::Ready(Ok(Some(mut trailers))) = trailers else {
+            return trailers;
+        };
+        let (value, _) = server_timing(&self.timings, self.received);
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            trailers.insert(SERVER_TIMING, value);
+        }
+        Poll::Ready(Ok(Some(trailers)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+}
+
+/// Marks, for [`TimingLayer`], the point where the handlers start: the
+/// server's other layers, and reading the request, count as `queue`. Goes
+/// innermost, after every other layer.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StartLayer;
+
+impl<S> Layer<S> for StartLayer {
+    type Service = Started<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Started { inner }
+    }
+}
+
+/// The service produced by [`StartLayer`].
+#[derive(Debug, Clone)]
+pub struct Started<S> {
+    inner: S,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for Started<S>
+where
+    S: Service<Request<ReqBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let response = self.inner.call(request);
+        // Run inside the call, where the timings are
+        Box::pin(async move {
+            handler_started();
+            response.await
+        })
+    }
+}
+
+/// Wraps a repository and counts the time spent in it as `storage`.
+#[derive(Debug)]
+pub struct TimedRepository(pub Arc<dyn StudentRepository>);
+
+#[tonic::async_trait]
+impl StudentRepository for TimedRepository {
+    async fn create(&self, student: Student) -> Result<Student, Status> {
+        storage(self.0.create(student)).await
+    }
+
+    async fn get(&self, id: &str) -> Result<Student, Status> {
+        storage(self.0.get(id)).await
+    }
+
+    async fn update(&self, student: Student) -> Result<Student, Status> {
+        storage(self.0.update(student)).await
+    }
+
+    async fn delete(&self, id: &str) -> Result<Student, Status> {
+        storage(self.0.delete(id)).await
+    }
+
+    async fn list(
+        &self,
+        page_size: usize,
+        page_token: &str,
+    ) -> Result<ListStudentsResponse, Status> {
+        storage(self.0.list(page_size, page_token)).await
+    }
+
+    async fn create_many(&self, students: Vec<Student>) -> Vec<Result<Student, Status>> {
+        storage(self.0.create_many(students)).await
+    }
+
+    async fn get_shared(&self, id: &str) -> Result<Arc<Student>, Status> {
+        storage(self.0.get_shared(id)).await
+    }
+
+    async fn all(&self) -> Result<Vec<Arc<Student>>, Status> {
+        storage(self.0.all()).await
+    }
+
+    async fn commit(&self, transaction: Transaction) -> Result<Vec<Student>, TransactionFailure> {
+        storage(self.0.commit(transaction)).await
+    }
+
+    async fn remembered(&self, key: &str) -> Result<Option<Remembered>, Status> {
+        storage(self.0.remembered(key)).await
+    }
+}
@@ -0,0 +1,107 @@
+//! Transcript documents.
+//!
+//! Rendering is CPU-bound, so the enrollment service runs it on the blocking
+//! thread pool. It checks `is_cancelled` between lines and gives up once it
+//! returns true, so a client that hangs up does not keep a thread busy.
+//!
+//! PDF rendering is built with the `pdf` feature; without it only HTML
+//! transcripts are available.
+
+#[cfg(feature = "pdf")]
+mod pdf;
+
+use proto::{Student, TranscriptFormat};
+
+/// One course on a transcript.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranscriptLine {
+    pub course_id: String,
+    pub title: String,
+    pub grade: String,
+}
+
+/// Everything that goes on a transcript.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transcript {
+    pub student: Student,
+    /// Ordered by course ID
+    pub lines: Vec<TranscriptLine>,
+}
+
+/// The MIME type of documents in `format`.
+pub fn content_type(format: TranscriptFormat) -> &'static str {
+    match format {
+        TranscriptFormat::Html => "text/html; charset=utf-8",
+        TranscriptFormat::Pdf | TranscriptFormat::Unspecified => "application/pdf",
+    }
+}
+
+/// Whether this build can render documents in `format`.
+pub fn supported(format: TranscriptFormat) -> bool {
+    format == TranscriptFormat::Html || cfg!(feature = "pdf")
+}
+
+/// Render `transcript` in `format`, or `None` if `is_cancelled` returned true
+/// first or the format is not [`supported`].
+pub fn render(
+    transcript: &Transcript,
+    format: TranscriptFormat,
+    is_cancelled: impl Fn() -> bool,
+) -> Option<Vec<u8>> {
+    match format {
+        TranscriptFormat::Html => render_html(transcript, is_cancelled),
+        #[cfg(feature = "pdf")]
+        TranscriptFormat::Pdf | TranscriptFormat::Unspecified => {
+            pdf::render(transcript, is_cancelled)
+        }
+        #[cfg(not(feature = "pdf"))]
+        TranscriptFormat::Pdf | TranscriptFormat::Unspecified => None,
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn render_html(transcript: &Transcript, is_cancelled: impl Fn() -> bool) -> Option<Vec<u8>> {
+    let student = &transcript.student;
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Transcript: {name}</title>\n</head>\n<body>\n\
+         <h1>Academic Transcript</h1>\n\
+         <p>{name}<br>Student ID: {id}<br>Major: {major}</p>\n",
+        name = escape_html(&student.name),
+        id = escape_html(&student.id),
+        major = escape_html(&student.major),
+    );
+    if transcript.lines.is_empty() {
+        html.push_str("<p>No courses recorded.</p>\n");
+    } else {
+        html.push_str("<table>\n<tr><th>Course</th><th>Title</th><th>Grade</th></tr>\n");
+        for line in &transcript.lines {
+            if is_cancelled() {
+                return None;
+            }
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                escape_html(&line.course_id),
+                escape_html(&line.title),
+                escape_html(&line.grade)
+            ));
+        }
+        html.push_str("</table>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    Some(html.into_bytes())
+}
@@ -0,0 +1,134 @@
+//! PDF transcripts.
+
+use super::{Transcript, TranscriptLine};
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str};
+
+// A4 in points, with one-inch margins
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 72.0;
+const LINE_HEIGHT: f32 = 16.0;
+const TITLE_X: f32 = MARGIN + 100.0;
+const GRADE_X: f32 = 450.0;
+// Course lines below the header on each page
+const LINES_PER_PAGE: usize = 36;
+
+// The standard 14 fonts only cover WinAnsiEncoding, which matches Latin-1
+// for printable characters; anything else is shown as '?'
+fn pdf_text(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c as u32 {
+            code @ (0x20..=0x7e | 0xa0..=0xff) => code as u8,
+            _ => b'?',
+        })
+        .collect()
+}
+
+fn show(content: &mut Content, font: Name, size: f32, x: f32, y: f32, text: &str) {
+    content
+        .begin_text()
+        .set_font(font, size)
+        .next_line(x, y)
+        .show(Str(&pdf_text(text)))
+        .end_text();
+}
+
+pub(super) fn render(transcript: &Transcript, is_cancelled: impl Fn() -> bool) -> Option<Vec<u8>> {
+    let catalog_id = Ref::new(1);
+    let page_tree_id = Ref::new(2);
+    let font_id = Ref::new(3);
+    let bold_font_id = Ref::new(4);
+    let font = Name(b"F1");
+    let bold_font = Name(b"F2");
+
+    let student = &transcript.student;
+    let pages: Vec<&[TranscriptLine]> = if transcript.lines.is_empty() {
+        vec![&[]]
+    } else {
+        transcript.lines.chunks(LINES_PER_PAGE).collect()
+    };
+    // Each page is followed by its content stream
+    let page_ids: Vec<Ref> = (0..pages.len())
+        .map(|index| Ref::new(5 + 2 * index as i32))
+        .collect();
+
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.pages(page_tree_id)
+        .kids(page_ids.iter().copied())
+        .count(pages.len() as i32);
+    pdf.type1_font(font_id)
+        .base_font(Name(b"Helvetica"))
+        .encoding_predefined(Name(b"WinAnsiEncoding"));
+    pdf.type1_font(bold_font_id)
+        .base_font(Name(b"Helvetica-Bold"))
+        .encoding_predefined(Name(b"WinAnsiEncoding"));
+
+    for (index, (lines, page_id)) in pages.iter().zip(&page_ids).enumerate() {
+        let content_id = Ref::new(page_id.get() + 1);
+        let mut page = pdf.page(*page_id);
+        page.media_box(Rect::new(0.0, 0.0, PAGE_WIDTH, PAGE_HEIGHT))
+            .parent(page_tree_id)
+            .contents(content_id);
+        page.resources()
+            .fonts()
+            .pair(font, font_id)
+            .pair(bold_font, bold_font_id);
+        page.finish();
+
+        let mut content = Content::new();
+        let mut y = PAGE_HEIGHT - MARGIN;
+        show(
+            &mut content,
+            bold_font,
+            18.0,
+            MARGIN,
+            y,
+            "Academic Transcript",
+        );
+        y -= 2.0 * LINE_HEIGHT;
+        show(&mut content, bold_font, 12.0, MARGIN, y, &student.name);
+        y -= LINE_HEIGHT;
+        show(
+            &mut content,
+            font,
+            11.0,
+            MARGIN,
+            y,
+            &format!("Student ID: {}", student.id),
+        );
+        y -= LINE_HEIGHT;
+        show(
+            &mut content,
+            font,
+            11.0,
+            MARGIN,
+            y,
+            &format!("Major: {}", student.major),
+        );
+        y -= 2.0 * LINE_HEIGHT;
+
+        if lines.is_empty() {
+            show(&mut content, font, 11.0, MARGIN, y, "No courses recorded.");
+        } else {
+            for (x, heading) in [(MARGIN, "Course"), (TITLE_X, "Title"), (GRADE_X, "Grade")] {
+                show(&mut content, bold_font, 11.0, x, y, heading);
+            }
+            for line in lines.iter() {
+                if is_cancelled() {
+                    return None;
+                }
+                y -= LINE_HEIGHT;
+                show(&mut content, font, 11.0, MARGIN, y, &line.course_id);
+                show(&mut content, font, 11.0, TITLE_X, y, &line.title);
+                show(&mut content, font, 11.0, GRADE_X, y, &line.grade);
+            }
+        }
+
+        let footer = format!("Page {} of {}", index + 1, pages.len());
+        show(&mut content, font, 9.0, MARGIN, MARGIN / 2.0, &footer);
+        pdf.stream(content_id, &content.finish());
+    }
+
+    Some(pdf.finish())
+}
@@ -0,0 +1,217 @@
+//! The trash: removed students kept for a while so they can be restored.
+//!
+//! `DeleteStudent` and `MergeStudents` put the student they remove here
+//! under a new operation ID, which `Undo` takes. An entry expires once the
+//! undo window has passed and is then dropped the next time the trash is
+//! looked at. Restoring creates the student again in the store, so it fails
+//! if the ID has been taken since. The trash is kept in memory.
+
+use crate::clock::{self, Clock, SystemClock};
+use crate::errors::Error;
+use crate::events::EventLog;
+use crate::pagination::PageTokens;
+use crate::repository::{next_page_token, page_offset, StudentRepository};
+use proto::trash_service_server::TrashService;
+use proto::{
+    ChangeType, ListTrashRequest, ListTrashResponse, Student, TrashEntry, TrashReason, UndoRequest,
+    UndoResponse,
+};
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+use tonic::{Request, Response, Status};
+
+/// How long removed students are kept, unless set with [`Trash::with_window`].
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Work to redo once a student is restored, e.g. moving a merge's records back.
+pub type AfterRestore = Box<dyn FnOnce(&Student) + Send>;
+
+struct Entry {
+    entry: TrashEntry,
+    expires: SystemTime,
+    after_restore: Option<AfterRestore>,
+}
+
+impl fmt::Debug for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Entry")
+            .field("entry", &self.entry)
+            .field("expires", &self.expires)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Removed students, shared by the services that remove them.
+#[derive(Debug, Clone)]
+pub struct Trash {
+    store: Arc<dyn StudentRepository>,
+    window: Duration,
+    clock: Arc<dyn Clock>,
+    // Publishes a Created event for each restored student, when the store
+    // does not record its own events
+    events: Option<Arc<EventLog>>,
+    // Oldest first
+    entries: Arc<Mutex<Vec<Entry>>>,
+}
+
+impl Trash {
+    /// Restore students into `store`.
+    pub fn new(store: Arc<dyn StudentRepository>) -> Self {
+        Self {
+            store,
+            window: DEFAULT_WINDOW,
+            clock: Arc::new(SystemClock),
+            events: None,
+            entries: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Keep removed students for `window` instead of a day.
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Time expiry with `clock` instead of the system clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Tell watchers in `events` about restored students.
+    pub fn with_events(mut self, events: Arc<EventLog>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    // Expired entries are dropped whenever this is taken
+    fn entries(&self) -> MutexGuard<'_, Vec<Entry>> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let now = self.clock.now();
+        entries.retain(|entry| entry.expires > now);
+        entries
+    }
+
+    /// Keep `student`, just removed for `reason`, running `after_restore`
+    /// if it is restored.
+    pub fn put(
+        &self,
+        student: Student,
+        reason: TrashReason,
+        merged_into_id: &str,
+        after_restore: Option<AfterRestore>,
+    ) -> TrashEntry {
+        let now = self.clock.now();
+        let expires = now + self.window;
+        let entry = TrashEntry {
+            operation_id: uuid::Uuid::new_v4().to_string(),
+            student: Some(student),
+            reason: reason as i32,
+            merged_into_id: merged_into_id.to_string(),
+            delete_time: Some(clock::timestamp(now)),
+            expire_time: Some(clock::timestamp(expires)),
+        };
+        self.entries().push(Entry {
+            entry: entry.clone(),
+            expires,
+            after_restore,
+        });
+        entry
+    }
+
+    /// What is in the trash, most recently removed first.
+    pub fn list(&self) -> Vec<TrashEntry> {
+        self.entries()
+            .iter()
+            .rev()
+            .map(|entry| entry.entry.clone())
+            .collect()
+    }
+
+    /// Put back the student removed by `operation_id`.
+    pub async fn restore(&self, operation_id: &str) -> Result<Student, Status> {
+        let (index, entry) = {
+            let mut entries = self.entries();
+            let index = entries
+                .iter()
+                .position(|entry| entry.entry.operation_id == operation_id)
+                .ok_or_else(|| Error::NothingInTrash(operation_id.to_string()))?;
+            (index, entries.remove(index))
+        };
+
+        let removed = entry.entry.student.clone().unwrap_or_default();
+        let student = match self.store.create(removed).await {
+            Ok(student) => student,
+            Err(status) => {
+                let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+                let index = index.min(entries.len());
+                entries.insert(index, entry);
+                return Err(status);
+            }
+        };
+        if let Some(after_restore) = entry.after_restore {
+            after_restore(&student);
+        }
+        if let Some(events) = &self.events {
+            events.publish(ChangeType::Created, &student);
+        }
+        Ok(student)
+    }
+}
+
+#[derive(Debug)]
+pub struct TrashServiceImpl {
+    trash: Trash,
+    page_tokens: PageTokens,
+}
+
+impl TrashServiceImpl {
+    pub fn new(trash: Trash) -> Self {
+        Self {
+            trash,
+            page_tokens: PageTokens::random(),
+        }
+    }
+
+    /// Sign page tokens with `page_tokens` instead of a random secret.
+    pub fn with_page_tokens(mut self, page_tokens: PageTokens) -> Self {
+        self.page_tokens = page_tokens;
+        self
+    }
+}
+
+#[tonic::async_trait]
+impl TrashService for TrashServiceImpl {
+    async fn list_trash(
+        &self,
+        request: Request<ListTrashRequest>,
+    ) -> Result<Response<ListTrashResponse>, Status> {
+        let request = request.into_inner();
+        let page_size = if request.page_size <= 0 {
+            10
+        } else {
+            request.page_size as usize
+        };
+
+        let entries = self.trash.list();
+        let page_token = self.page_tokens.verify("trash", &request.page_token)?;
+        let start = page_offset(&page_token, entries.len())?;
+        let end = start.saturating_add(page_size).min(entries.len());
+        Ok(Response::new(ListTrashResponse {
+            next_page_token: self
+                .page_tokens
+                .sign("trash", next_page_token(end, entries.len())),
+            entries: entries[start..end].to_vec(),
+        }))
+    }
+
+    async fn undo(&self, request: Request<UndoRequest>) -> Result<Response<UndoResponse>, Status> {
+        let operation_id = request.into_inner().operation_id;
+        let student = self.trash.restore(&operation_id).await?;
+        info!("↩️  Restored student {} ({})", student.name, student.id);
+        Ok(Response::new(UndoResponse {
+            student: Some(student),
+        }))
+    }
+}
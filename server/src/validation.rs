@@ -0,0 +1,37 @@
+use crate::locale::Text;
+use proto::{FieldViolation, Student};
+use student_core::Problem;
+
+// Shared with the client, which checks input the same way before sending it
+pub use student_core::Profile;
+
+/// `field` breaks a rule, described in the current request's locale.
+pub fn violation(field: &str, text: &Text) -> FieldViolation {
+    FieldViolation {
+        field: field.to_string(),
+        description: text.localized(),
+    }
+}
+
+/// Every rule `student` breaks, as the field and what is wrong with it.
+pub fn problems(student: &Student) -> Vec<(&'static str, Text)> {
+    texts(student_core::problems(student))
+}
+
+/// `problems` by field, to say in any locale.
+pub fn texts(problems: Vec<(&'static str, Problem)>) -> Vec<(&'static str, Text)> {
+    problems
+        .into_iter()
+        .map(|(field, problem)| (field, Text::from(problem)))
+        .collect()
+}
+
+/// Check a student against every rule, returning all violations found.
+///
+/// An empty result means the student is valid.
+pub fn violations(student: &Student) -> Vec<FieldViolation> {
+    problems(student)
+        .iter()
+        .map(|(field, text)| violation(field, text))
+        .collect()
+}
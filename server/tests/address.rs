@@ -0,0 +1,237 @@
+use proto::student_service_server::StudentService;
+use proto::{Address, CreateStudentRequest, ListStudentsRequest, Student};
+use server::address;
+use server::locale::Text;
+use server::validation::{self, Profile};
+use server::StudentServiceImpl;
+use student_core::Problem;
+use tonic::{Code, Request, Status};
+
+fn address(city: &str, region: &str, postal_code: &str, country: &str) -> Address {
+    Address {
+        line1: "1 Main St".to_string(),
+        city: city.to_string(),
+        region: region.to_string(),
+        postal_code: postal_code.to_string(),
+        country: country.to_string(),
+        ..Default::default()
+    }
+}
+
+fn student(id: &str, name: &str, address: Option<Address>) -> Student {
+    Student {
+        id: id.to_string(),
+        name: name.to_string(),
+        email: format!("{}@university.edu", id),
+        age: 20,
+        address,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn postal_codes_fit_their_countrys_formats() {
+    let us = address::country("us").unwrap();
+    assert_eq!(us.postal_code("94105"), Some("94105".to_string()));
+    assert_eq!(us.postal_code("94105 1234"), Some("94105-1234".to_string()));
+    assert_eq!(us.postal_code("9410"), None);
+    assert_eq!(us.region("ca"), Some("CA".to_string()));
+    assert_eq!(us.region("Ontario"), None);
+
+    let ca = address::country("CA").unwrap();
+    assert_eq!(ca.postal_code("k1a0b1"), Some("K1A 0B1".to_string()));
+    assert_eq!(ca.postal_code("K1A 0B"), None);
+    let gb = address::country("GB").unwrap();
+    assert_eq!(gb.postal_code("sw1a 1aa"), Some("SW1A 1AA".to_string()));
+    assert_eq!(gb.postal_code("M1 1AE"), Some("M1 1AE".to_string()));
+    assert_eq!(
+        gb.region("Greater London"),
+        Some("Greater London".to_string())
+    );
+
+    // Known countries without rules of their own
+    assert!(address::is_country("it"));
+    assert!(address::country("IT").is_none());
+    assert!(!address::is_country("XX"));
+}
+
+#[test]
+fn addresses_are_checked_against_their_country() {
+    let problems = |address: Address| {
+        validation::problems(&student("s1", "Ada", Some(address)))
+            .into_iter()
+            .map(|(field, text)| (field, text.reason()))
+            .collect::<Vec<_>>()
+    };
+    assert!(problems(address("San Francisco", "CA", "94105", "US")).is_empty());
+    assert!(problems(address("Roma", "", "", "IT")).is_empty());
+    assert_eq!(
+        problems(address("San Francisco", "Calif.", "941", "US")),
+        [
+            ("address.region", "REGION_UNKNOWN"),
+            ("address.postal_code", "POSTAL_CODE_INVALID"),
+        ]
+    );
+    assert_eq!(
+        problems(Address {
+            country: "Atlantis".to_string(),
+            ..Default::default()
+        }),
+        [
+            ("address.line1", "ADDRESS_LINE_EMPTY"),
+            ("address.city", "CITY_EMPTY"),
+            ("address.country", "COUNTRY_UNKNOWN"),
+        ]
+    );
+
+    let text = Text::PostalCodeInvalid("US".to_string(), "941".to_string());
+    assert_eq!(text.metadata()["formats"], "99999 | 99999-9999");
+}
+
+#[test]
+fn profiles_tidy_or_reject_addresses() {
+    let messy = student(
+        "s1",
+        "Ada",
+        Some(Address {
+            line1: " 24  Sussex Dr ".to_string(),
+            city: "Ottawa".to_string(),
+            region: "on".to_string(),
+            postal_code: "k1m1m4".to_string(),
+            country: "ca".to_string(),
+            ..Default::default()
+        }),
+    );
+    let fields: Vec<_> = Profile::Strict
+        .check(&mut messy.clone())
+        .into_iter()
+        .map(|(field, text)| {
+            assert_eq!(text, Problem::AddressNotCanonical);
+            field
+        })
+        .collect();
+    assert_eq!(
+        fields,
+        [
+            "address.line1",
+            "address.region",
+            "address.postal_code",
+            "address.country"
+        ]
+    );
+
+    let mut tidy = messy.clone();
+    assert!(Profile::Lenient.check(&mut tidy).is_empty());
+    assert_eq!(
+        tidy.address,
+        Some(Address {
+            line1: "24 Sussex Dr".to_string(),
+            city: "Ottawa".to_string(),
+            region: "ON".to_string(),
+            postal_code: "K1M 1M4".to_string(),
+            country: "CA".to_string(),
+            ..Default::default()
+        })
+    );
+    assert!(Profile::Strict.check(&mut tidy).is_empty());
+
+    // Blank parts are no address at all
+    let mut blank = student("s1", "Ada", Some(address(" ", "", "", "")));
+    blank.address.as_mut().unwrap().line1.clear();
+    assert!(Profile::Lenient.check(&mut blank).is_empty());
+    assert_eq!(blank.address, None);
+}
+
+async fn service() -> StudentServiceImpl {
+    let service = StudentServiceImpl::new();
+    let students = [
+        student(
+            "s1",
+            "Zoe",
+            Some(address("San Francisco", "CA", "94105", "US")),
+        ),
+        student("s2", "Yan", Some(address("Ottawa", "ON", "K1A 0B1", "CA"))),
+        student("s3", "Xia", Some(address("Portland", "OR", "97201", "US"))),
+        student("s4", "Will", None),
+        student(
+            "s5",
+            "Val",
+            Some(address("Los Angeles", "CA", "90012", "US")),
+        ),
+    ];
+    for student in students {
+        service
+            .create_student(Request::new(CreateStudentRequest {
+                student: Some(student),
+            }))
+            .await
+            .unwrap();
+    }
+    service
+}
+
+async fn list(
+    service: &StudentServiceImpl,
+    request: ListStudentsRequest,
+) -> Result<(Vec<String>, String), Status> {
+    let page = service
+        .list_students(Request::new(ListStudentsRequest {
+            page_size: 2,
+            ..request
+        }))
+        .await?
+        .into_inner();
+    let ids = page
+        .students
+        .into_iter()
+        .map(|student| student.id)
+        .collect();
+    Ok((ids, page.next_page_token))
+}
+
+#[tokio::test]
+async fn students_are_filtered_by_place() {
+    let service = service().await;
+    let in_us = ListStudentsRequest {
+        country: "us".to_string(),
+        ..Default::default()
+    };
+
+    let (first, token) = list(&service, in_us.clone()).await.unwrap();
+    assert_eq!(first, ["s1", "s3"]);
+    let next = ListStudentsRequest {
+        page_token: token.clone(),
+        ..in_us.clone()
+    };
+    let (rest, last) = list(&service, next).await.unwrap();
+    assert_eq!(rest, ["s5"]);
+    assert!(last.is_empty());
+
+    let in_california = ListStudentsRequest {
+        country: "US".to_string(),
+        region: "ca".to_string(),
+        order_by: "name".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(
+        list(&service, in_california.clone()).await.unwrap().0,
+        ["s5", "s1"]
+    );
+
+    // A page token only goes with the place it was handed out for
+    let status = list(
+        &service,
+        ListStudentsRequest {
+            page_token: token,
+            country: "CA".to_string(),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(
+        client::error_info(&status).unwrap().reason,
+        "PAGE_TOKEN_INVALID"
+    );
+}
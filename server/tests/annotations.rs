@@ -0,0 +1,141 @@
+use proto::student_service_server::StudentService;
+use proto::{CreateStudentRequest, GetStudentRequest, Student, UpdateStudentRequest};
+use server::annotations::{self, MAX_BYTES};
+use server::locale::Text;
+use server::validation::{self, Profile};
+use server::StudentServiceImpl;
+use std::collections::HashMap;
+use tonic::{Code, Request};
+
+fn student(annotations: &[(&str, &str)]) -> Student {
+    Student {
+        id: "s1".to_string(),
+        name: "Ada Lovelace".to_string(),
+        email: "ada@university.edu".to_string(),
+        age: 20,
+        annotations: annotations
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn keys_are_names_with_an_optional_owner() {
+    for key in [
+        "batch",
+        "Batch_2024.fall",
+        "a",
+        "registrar.example.edu/batch-id",
+        "x1/y",
+    ] {
+        assert!(annotations::is_key(key), "{:?}", key);
+    }
+    for key in [
+        "",
+        "-batch",
+        "batch.",
+        "two words",
+        "ünicode",
+        "Example.edu/batch",
+        "example..edu/batch",
+        "/batch",
+        "example.edu/",
+        "a/b/c",
+        &"n".repeat(64),
+    ] {
+        assert!(!annotations::is_key(key), "{:?}", key);
+    }
+    assert!(annotations::is_key(&"n".repeat(63)));
+}
+
+#[test]
+fn annotations_are_checked_but_never_tidied() {
+    let problems = validation::problems(&student(&[("bad key", "x"), ("ok", "y"), ("-", "")]));
+    assert_eq!(
+        problems,
+        [
+            ("annotations", Text::AnnotationKeyInvalid("-".to_string())),
+            (
+                "annotations",
+                Text::AnnotationKeyInvalid("bad key".to_string())
+            ),
+        ]
+    );
+    assert_eq!(
+        Text::AnnotationKeyInvalid("-".to_string()).metadata()["key"],
+        "-"
+    );
+
+    let large = "v".repeat(MAX_BYTES - 1);
+    assert!(validation::problems(&student(&[("k", &large)])).is_empty());
+    assert_eq!(
+        validation::problems(&student(&[("k", &large), ("k2", "")])),
+        [("annotations", Text::AnnotationsTooLarge)]
+    );
+
+    // Values are the client's, spaces and all
+    let messy = student(&[("note", "  Two  spaces\u{301} ")]);
+    for profile in [Profile::Lenient, Profile::Strict] {
+        let mut checked = messy.clone();
+        assert!(profile.check(&mut checked).is_empty());
+        assert_eq!(checked.annotations, messy.annotations);
+    }
+}
+
+#[tokio::test]
+async fn annotations_are_stored_as_given() {
+    let service = StudentServiceImpl::new();
+    let given = student(&[("example.edu/batch", "2024-fall"), ("note", " as is ")]);
+    let created = service
+        .create_student(Request::new(CreateStudentRequest {
+            student: Some(given.clone()),
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .student
+        .unwrap();
+    assert_eq!(created.annotations, given.annotations);
+
+    // An update replaces the whole map
+    let updated = service
+        .update_student(Request::new(UpdateStudentRequest {
+            student: Some(Student {
+                annotations: HashMap::from([("note".to_string(), "kept".to_string())]),
+                ..created
+            }),
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .student
+        .unwrap();
+    let fetched = service
+        .get_student(Request::new(GetStudentRequest {
+            id: "s1".to_string(),
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .student
+        .unwrap();
+    assert_eq!(fetched.annotations, updated.annotations);
+    assert_eq!(fetched.annotations.len(), 1);
+
+    let status = service
+        .create_student(Request::new(CreateStudentRequest {
+            student: Some(Student {
+                id: "s2".to_string(),
+                ..student(&[("k", &"v".repeat(MAX_BYTES))])
+            }),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(
+        client::error_info(&status).unwrap().reason,
+        "ANNOTATIONS_TOO_LARGE"
+    );
+}
@@ -0,0 +1,263 @@
+use proto::attendance_service_client::AttendanceServiceClient;
+use proto::attendance_service_server::AttendanceServiceServer;
+use proto::enrollment_service_client::EnrollmentServiceClient;
+use proto::enrollment_service_server::EnrollmentServiceServer;
+use proto::{
+    AttendanceRecord, AttendanceStatus, Course, CreateCourseRequest, EnrollRequest,
+    GetAttendanceSummaryRequest, GetAttendanceSummaryResponse, ListAttendanceRequest,
+    RecordAttendanceResponse, RejectedRecord, Student, Timestamp,
+};
+use server::attendance::AttendanceServiceImpl;
+use server::enrollment::EnrollmentServiceImpl;
+use server::repository::{InMemoryRepository, StudentRepository};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::{Channel, Server};
+use tonic::Code;
+
+const DAY: i64 = 24 * 60 * 60;
+// 2024-09-02 09:00 UTC
+const MONDAY: i64 = 1_725_267_600;
+
+// Ada and Grace hold seats in "math"; Alan is on its waitlist
+async fn start() -> AttendanceServiceClient<Channel> {
+    let repository = Arc::new(InMemoryRepository::new());
+    for id in ["ada", "grace", "alan"] {
+        repository
+            .create(Student {
+                id: id.to_string(),
+                name: id.to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+    let enrollment = Arc::new(EnrollmentServiceImpl::new(repository));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .add_service(EnrollmentServiceServer::from_arc(enrollment.clone()))
+            .add_service(AttendanceServiceServer::new(AttendanceServiceImpl::new(
+                enrollment,
+            )))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+
+    let channel = Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    let mut courses = EnrollmentServiceClient::new(channel.clone());
+    courses
+        .create_course(CreateCourseRequest {
+            course: Some(Course {
+                id: "math".to_string(),
+                title: "Mathematics".to_string(),
+                capacity: 2,
+                waitlist_capacity: 1,
+                prerequisite_ids: vec![],
+                credits: 4,
+            }),
+        })
+        .await
+        .unwrap();
+    for id in ["ada", "grace", "alan"] {
+        courses
+            .enroll(EnrollRequest {
+                student_id: id.to_string(),
+                course_id: "math".to_string(),
+            })
+            .await
+            .unwrap();
+    }
+    AttendanceServiceClient::new(channel)
+}
+
+fn at(seconds: i64) -> Option<Timestamp> {
+    Some(Timestamp { seconds, nanos: 0 })
+}
+
+fn record(student_id: &str, seconds: i64, status: AttendanceStatus) -> AttendanceRecord {
+    AttendanceRecord {
+        student_id: student_id.to_string(),
+        course_id: "math".to_string(),
+        check_in_time: at(seconds),
+        status: status.into(),
+    }
+}
+
+async fn send(
+    client: &mut AttendanceServiceClient<Channel>,
+    records: Vec<AttendanceRecord>,
+) -> RecordAttendanceResponse {
+    client
+        .record_attendance(tokio_stream::iter(records))
+        .await
+        .unwrap()
+        .into_inner()
+}
+
+async fn summary(
+    client: &mut AttendanceServiceClient<Channel>,
+    start_time: Option<Timestamp>,
+    end_time: Option<Timestamp>,
+) -> GetAttendanceSummaryResponse {
+    client
+        .get_attendance_summary(GetAttendanceSummaryRequest {
+            student_id: "ada".to_string(),
+            course_id: "math".to_string(),
+            start_time,
+            end_time,
+        })
+        .await
+        .unwrap()
+        .into_inner()
+}
+
+#[tokio::test]
+async fn bad_records_are_rejected_without_failing_the_batch() {
+    let mut client = start().await;
+
+    let response = send(
+        &mut client,
+        vec![
+            record("ada", MONDAY, AttendanceStatus::Present),
+            record("alan", MONDAY, AttendanceStatus::Present),
+            AttendanceRecord {
+                course_id: "art".to_string(),
+                ..record("ada", MONDAY, AttendanceStatus::Present)
+            },
+            record("grace", MONDAY, AttendanceStatus::Unspecified),
+            AttendanceRecord {
+                check_in_time: None,
+                ..record("grace", MONDAY, AttendanceStatus::Late)
+            },
+            record("grace", MONDAY + 600, AttendanceStatus::Late),
+        ],
+    )
+    .await;
+
+    assert_eq!(response.recorded_count, 2);
+    let rejected: Vec<i32> = response
+        .rejected
+        .iter()
+        .map(|rejected| rejected.index)
+        .collect();
+    assert_eq!(rejected, vec![1, 2, 3, 4]);
+    assert_eq!(
+        response.rejected[0],
+        RejectedRecord {
+            index: 1,
+            reason: "Student alan does not hold a seat in math".to_string(),
+        }
+    );
+}
+
+#[tokio::test]
+async fn summaries_count_one_record_per_day() {
+    let mut client = start().await;
+
+    send(
+        &mut client,
+        vec![
+            record("ada", MONDAY, AttendanceStatus::Absent),
+            record("ada", MONDAY + DAY, AttendanceStatus::Late),
+            record("ada", MONDAY + 2 * DAY, AttendanceStatus::Present),
+            record("grace", MONDAY, AttendanceStatus::Present),
+        ],
+    )
+    .await;
+    // A late scan on Monday replaces the absence; resending Wednesday changes nothing
+    send(
+        &mut client,
+        vec![
+            record("ada", MONDAY + 3600, AttendanceStatus::Late),
+            record("ada", MONDAY + 2 * DAY, AttendanceStatus::Present),
+        ],
+    )
+    .await;
+
+    assert_eq!(
+        summary(&mut client, None, None).await,
+        GetAttendanceSummaryResponse {
+            sessions: 3,
+            present: 1,
+            late: 2,
+            absent: 0,
+            excused: 0,
+        }
+    );
+    assert_eq!(
+        summary(&mut client, at(MONDAY + DAY), at(MONDAY + 2 * DAY)).await,
+        GetAttendanceSummaryResponse {
+            sessions: 1,
+            late: 1,
+            ..Default::default()
+        }
+    );
+}
+
+#[tokio::test]
+async fn attendance_is_listed_by_date_range() {
+    let mut client = start().await;
+    send(
+        &mut client,
+        vec![
+            record("grace", MONDAY + DAY, AttendanceStatus::Present),
+            record("ada", MONDAY + DAY + 60, AttendanceStatus::Late),
+            record("ada", MONDAY, AttendanceStatus::Present),
+            record("ada", MONDAY + 2 * DAY, AttendanceStatus::Excused),
+        ],
+    )
+    .await;
+
+    let list = |student_id: &str, start_time, end_time| ListAttendanceRequest {
+        student_id: student_id.to_string(),
+        course_id: String::new(),
+        start_time,
+        end_time,
+    };
+    let checked_in = |response: tonic::Response<proto::ListAttendanceResponse>| {
+        response
+            .into_inner()
+            .records
+            .into_iter()
+            .map(|record| (record.student_id, record.check_in_time.unwrap().seconds))
+            .collect::<Vec<_>>()
+    };
+
+    let everyone = client
+        .list_attendance(list("", at(MONDAY + DAY), None))
+        .await
+        .unwrap();
+    assert_eq!(
+        checked_in(everyone),
+        vec![
+            ("grace".to_string(), MONDAY + DAY),
+            ("ada".to_string(), MONDAY + DAY + 60),
+            ("ada".to_string(), MONDAY + 2 * DAY),
+        ]
+    );
+
+    let ada = client
+        .list_attendance(list("ada", None, at(MONDAY + 2 * DAY)))
+        .await
+        .unwrap();
+    assert_eq!(
+        checked_in(ada),
+        vec![
+            ("ada".to_string(), MONDAY),
+            ("ada".to_string(), MONDAY + DAY + 60),
+        ]
+    );
+
+    let status = client
+        .list_attendance(list("ada", at(MONDAY), at(MONDAY - 1)))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
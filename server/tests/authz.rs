@@ -0,0 +1,195 @@
+use proto::professor_service_server::{ProfessorService, ProfessorServiceServer};
+use proto::student_service_client::StudentServiceClient;
+use proto::student_service_server::{StudentService, StudentServiceServer};
+use proto::{
+    AssignAdvisorRequest, CreateProfessorRequest, CreateStudentRequest, GetStudentRequest,
+    ListStudentsRequest, Professor, Student,
+};
+use server::authz::{AuthorizationLayer, Directory};
+use server::catalog::Catalog;
+use server::clock::FixedClock;
+use server::identity::{IdentityLayer, Principal, Tokens};
+use server::professor::ProfessorServiceImpl;
+use server::repository::{InMemoryRepository, StudentRepository};
+use server::StudentServiceImpl;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::metadata::MetadataValue;
+use tonic::transport::{Channel, Server};
+use tonic::{Code, Request};
+
+const POLICIES: &str = r#"
+    permit(principal in Role::"admin", action, resource);
+    permit(principal in Role::"professor", action == Action::"GetStudent", resource)
+        when { resource has advisor && resource.advisor == principal };
+    permit(principal, action == Action::"GetStudent", resource)
+        when { principal == resource };
+"#;
+
+// A server with students s1 and s2, where professor p1 advises s1
+async fn start(tokens: Tokens) -> StudentServiceClient<Channel> {
+    let store: Arc<dyn StudentRepository> = Arc::new(InMemoryRepository::new());
+    let catalog = Arc::new(Catalog::new());
+    let students = StudentServiceImpl::new().with_repository(store.clone());
+    for id in ["s1", "s2"] {
+        let student = Student {
+            id: id.to_string(),
+            name: "Ada".to_string(),
+            email: format!("{}@university.edu", id),
+            age: 20,
+            ..Default::default()
+        };
+        students
+            .create_student(Request::new(CreateStudentRequest {
+                student: Some(student),
+            }))
+            .await
+            .unwrap();
+    }
+    let professors = Arc::new(ProfessorServiceImpl::new(catalog.clone(), store.clone()));
+    let professor = Professor {
+        id: "p1".to_string(),
+        name: "Grace".to_string(),
+        ..Default::default()
+    };
+    professors
+        .create_professor(Request::new(CreateProfessorRequest {
+            professor: Some(professor),
+        }))
+        .await
+        .unwrap();
+    professors
+        .assign_advisor(Request::new(AssignAdvisorRequest {
+            student_id: "s1".to_string(),
+            professor_id: "p1".to_string(),
+        }))
+        .await
+        .unwrap();
+
+    let authorization = AuthorizationLayer::new(
+        POLICIES.parse().unwrap(),
+        Directory {
+            professors: professors.clone(),
+            catalog,
+            students: store,
+        },
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .layer(IdentityLayer::new(tokens))
+            .layer(authorization)
+            .add_service(StudentServiceServer::new(students))
+            .add_service(ProfessorServiceServer::from_arc(professors))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    let channel = Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    StudentServiceClient::new(channel)
+}
+
+fn request<T>(message: T, token: Option<&str>) -> Request<T> {
+    let mut request = Request::new(message);
+    if let Some(token) = token {
+        let value = MetadataValue::try_from(format!("Bearer {}", token)).unwrap();
+        request.metadata_mut().insert("authorization", value);
+    }
+    request
+}
+
+async fn get(
+    client: &mut StudentServiceClient<Channel>,
+    id: &str,
+    token: Option<&str>,
+) -> Result<(), Code> {
+    let message = GetStudentRequest { id: id.to_string() };
+    match client.get_student(request(message, token)).await {
+        Ok(_) => Ok(()),
+        Err(status) => Err(status.code()),
+    }
+}
+
+#[tokio::test]
+async fn advisors_read_only_their_advisees() {
+    let tokens = Tokens::new(b"a secret of sixteen bytes");
+    let mut client = start(tokens.clone()).await;
+    let token = |principal: &str| tokens.issue(&Principal::from_str(principal).unwrap());
+
+    let advisor = token("professor:p1");
+    assert_eq!(get(&mut client, "s1", Some(&advisor)).await, Ok(()));
+    assert_eq!(
+        get(&mut client, "s2", Some(&advisor)).await,
+        Err(Code::PermissionDenied)
+    );
+    let status = client
+        .list_students(request(ListStudentsRequest::default(), Some(&advisor)))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    assert_eq!(status.message(), "You are not allowed to call ListStudents");
+
+    // Students may read themselves, and admins anything
+    let student = token("student:s2");
+    assert_eq!(get(&mut client, "s2", Some(&student)).await, Ok(()));
+    assert_eq!(
+        get(&mut client, "s1", Some(&student)).await,
+        Err(Code::PermissionDenied)
+    );
+    let admin = token("admin:a1");
+    assert_eq!(get(&mut client, "s2", Some(&admin)).await, Ok(()));
+    client
+        .list_students(request(ListStudentsRequest::default(), Some(&admin)))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn callers_need_good_tokens() {
+    let clock = Arc::new(FixedClock::new(SystemTime::now()));
+    let tokens = Tokens::new(b"a secret of sixteen bytes")
+        .with_ttl(Duration::from_secs(60))
+        .with_clock(clock.clone());
+    let mut client = start(tokens.clone()).await;
+    let principal = Principal::from_str("admin:a1").unwrap();
+    let admin = tokens.issue(&principal);
+
+    // Anonymous callers are refused by the policies, not the tokens
+    assert_eq!(
+        get(&mut client, "s1", None).await,
+        Err(Code::PermissionDenied)
+    );
+    let forged = admin.replacen("admin:a1", "admin:a2", 1);
+    assert_eq!(
+        get(&mut client, "s1", Some(&forged)).await,
+        Err(Code::Unauthenticated)
+    );
+    let other = Tokens::new(b"another secret of sixteen bytes").issue(&principal);
+    assert_eq!(
+        get(&mut client, "s1", Some(&other)).await,
+        Err(Code::Unauthenticated)
+    );
+
+    assert_eq!(get(&mut client, "s1", Some(&admin)).await, Ok(()));
+    clock.advance(Duration::from_secs(60));
+    let status = client
+        .get_student(request(
+            GetStudentRequest {
+                id: "s1".to_string(),
+            },
+            Some(&admin),
+        ))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+    assert_eq!(
+        client::error_info(&status).unwrap().reason,
+        "AUTH_TOKEN_EXPIRED"
+    );
+}
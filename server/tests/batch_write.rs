@@ -0,0 +1,203 @@
+use proto::batch_write_entry::Write as Entry;
+use proto::student_service_server::StudentService;
+use proto::{BatchWriteEntry, BatchWriteRequest, ChangeType, Student};
+use server::repository::{InMemoryRepository, StudentRepository, Transaction};
+use server::standing::StandingRules;
+use server::statistics::{CountedRepository, Statistics};
+use server::StudentServiceImpl;
+use std::sync::Arc;
+use tonic::{Code, Request, Status};
+
+fn student(id: &str, name: &str) -> Student {
+    Student {
+        id: id.to_string(),
+        name: name.to_string(),
+        email: format!("{}@university.edu", name.to_lowercase()),
+        age: 20,
+        ..Default::default()
+    }
+}
+
+fn entry(write: Entry) -> BatchWriteEntry {
+    BatchWriteEntry { write: Some(write) }
+}
+
+async fn batch_write(
+    service: &StudentServiceImpl,
+    entries: Vec<BatchWriteEntry>,
+) -> Result<Vec<Student>, Status> {
+    service
+        .batch_write(Request::new(BatchWriteRequest { entries }))
+        .await
+        .map(|response| response.into_inner().students)
+}
+
+// A service over a counted store holding Ada and Grace
+async fn start() -> (StudentServiceImpl, Arc<InMemoryRepository>, Arc<Statistics>) {
+    let store = Arc::new(InMemoryRepository::new());
+    let statistics = Arc::new(Statistics::new(StandingRules::default()));
+    let counted = Arc::new(CountedRepository::new(store.clone(), statistics.clone()));
+    for (id, name) in [("s1", "Ada"), ("s2", "Grace")] {
+        let mut stored = student(id, name);
+        stored.credits_attempted = 4;
+        counted.create(stored).await.unwrap();
+    }
+    let service = StudentServiceImpl::new().with_repository(counted);
+    (service, store, statistics)
+}
+
+#[tokio::test]
+async fn every_entry_is_written_together() {
+    let (service, store, statistics) = start().await;
+    let (_, mut events) = service.events().subscribe("").unwrap();
+
+    let written = batch_write(
+        &service,
+        vec![
+            entry(Entry::Create(student("", "Alan"))),
+            entry(Entry::Update(Student {
+                major: "Mathematics".to_string(),
+                ..student("s1", "Ada")
+            })),
+            entry(Entry::DeleteId("s2".to_string())),
+        ],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(written.len(), 3);
+    let created = store.get(&written[0].id).await.unwrap();
+    assert_eq!(created.name, "Alan");
+    assert!(created.create_time.is_some());
+    let updated = store.get("s1").await.unwrap();
+    assert_eq!(updated.major, "Mathematics");
+    // Credits stay the grades', as in UpdateStudent
+    assert_eq!(updated.credits_attempted, 4);
+    assert_eq!(written[2].name, "Grace");
+    assert_eq!(store.get("s2").await.unwrap_err().code(), Code::NotFound);
+    assert_eq!(statistics.get().student_count, 2);
+
+    let published: Vec<_> = (0..3)
+        .map(|_| {
+            let event = events.try_recv().unwrap();
+            (event.change_type(), event.student.unwrap().name)
+        })
+        .collect();
+    assert_eq!(
+        published,
+        [
+            (ChangeType::Created, "Alan".to_string()),
+            (ChangeType::Updated, "Ada".to_string()),
+            (ChangeType::Deleted, "Grace".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn one_failing_entry_keeps_nothing() {
+    let (service, store, statistics) = start().await;
+    let before = store.all().await.unwrap();
+    let memory = store.memory();
+
+    let stale = Student {
+        etag: "stale".to_string(),
+        ..student("s1", "Ada")
+    };
+    let status = batch_write(
+        &service,
+        vec![
+            entry(Entry::Create(student("s3", "Alan"))),
+            entry(Entry::DeleteId("s2".to_string())),
+            entry(Entry::Update(stale)),
+        ],
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(status.code(), Code::Aborted);
+    assert!(status.message().starts_with("entries[2]: "));
+    let info = client::error_info(&status).unwrap();
+    assert_eq!(info.reason, "STUDENT_CHANGED");
+    assert_eq!(info.metadata["entry"], "2");
+
+    assert_eq!(store.all().await.unwrap(), before);
+    assert_eq!(store.memory(), memory);
+    assert_eq!(statistics.get().student_count, 2);
+
+    // Bad entries are turned away before anything is written
+    let invalid = [
+        (
+            vec![
+                entry(Entry::Create(student("s3", "Alan"))),
+                entry(Entry::Create(student("s4", ""))),
+            ],
+            "NAME_EMPTY",
+            "1",
+        ),
+        (
+            vec![BatchWriteEntry { write: None }],
+            "BATCH_ENTRY_EMPTY",
+            "0",
+        ),
+        (
+            vec![
+                entry(Entry::Update(student("s1", "Ada"))),
+                entry(Entry::DeleteId("s1".to_string())),
+            ],
+            "BATCH_ID_REPEATED",
+            "1",
+        ),
+    ];
+    for (entries, reason, index) in invalid {
+        let status = batch_write(&service, entries).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        let info = client::error_info(&status).unwrap();
+        assert_eq!(
+            (info.reason.as_str(), info.metadata["entry"].as_str()),
+            (reason, index)
+        );
+    }
+    assert_eq!(store.all().await.unwrap(), before);
+    assert!(batch_write(&service, Vec::new()).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn backends_without_transactions_say_so() {
+    #[derive(Debug)]
+    struct Plain(InMemoryRepository);
+
+    #[tonic::async_trait]
+    impl StudentRepository for Plain {
+        async fn create(&self, student: Student) -> Result<Student, Status> {
+            self.0.create(student).await
+        }
+
+        async fn get(&self, id: &str) -> Result<Student, Status> {
+            self.0.get(id).await
+        }
+
+        async fn update(&self, student: Student) -> Result<Student, Status> {
+            self.0.update(student).await
+        }
+
+        async fn delete(&self, id: &str) -> Result<Student, Status> {
+            self.0.delete(id).await
+        }
+
+        async fn list(
+            &self,
+            page_size: usize,
+            page_token: &str,
+        ) -> Result<proto::ListStudentsResponse, Status> {
+            self.0.list(page_size, page_token).await
+        }
+    }
+
+    let plain = Plain(InMemoryRepository::new());
+    let failure = plain
+        .commit(Transaction::new().with_create(student("s1", "Ada")))
+        .await
+        .unwrap_err();
+    assert_eq!(failure.index, None);
+    assert_eq!(failure.status.code(), Code::Unimplemented);
+    assert_eq!(plain.get("s1").await.unwrap_err().code(), Code::NotFound);
+}
@@ -0,0 +1,420 @@
+use proto::bulk_service_client::BulkServiceClient;
+use proto::bulk_service_server::BulkServiceServer;
+use proto::{
+    AcademicStanding, BulkCreateStudentsRequest, ChangeType, FieldMask, GetBulkUpdateResultRequest,
+    Student, StudentFilter, UpdateStudentsMatchingRequest, UpdateStudentsMatchingResponse,
+};
+use server::bulk::BulkServiceImpl;
+use server::catalog::Catalog;
+use server::events::EventLog;
+use server::ids::SequentialIds;
+use server::operations::Operations;
+use server::repository::{InMemoryRepository, StudentRepository};
+use server::standing::StandingRules;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::error::TryRecvError;
+use tokio::sync::{mpsc, Semaphore};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::transport::{Channel, Server};
+use tonic::{Code, Status};
+
+async fn start(
+    store: Arc<InMemoryRepository>,
+    operations: Operations,
+) -> BulkServiceClient<Channel> {
+    let service = BulkServiceImpl::new(
+        store,
+        Arc::new(Catalog::new()),
+        StandingRules::default(),
+        operations,
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .add_service(BulkServiceServer::new(service))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    BulkServiceClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap()
+}
+
+// 150 students, so the job reads more than one page
+async fn store() -> Arc<InMemoryRepository> {
+    let store = Arc::new(InMemoryRepository::new());
+    for index in 0..150 {
+        let major = match index % 3 {
+            0 => "Comp Sci",
+            1 => "comp sci",
+            _ => "Physics",
+        };
+        store
+            .create(Student {
+                id: format!("s{:03}", index),
+                name: format!("Student {}", index),
+                email: format!("s{}@university.edu", index),
+                age: 20,
+                major: major.to_string(),
+                gpa: if index < 10 { 1.5 } else { 3.0 },
+                credits: 30,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+    store
+}
+
+fn update(
+    filter: StudentFilter,
+    paths: &[&str],
+    new_values: Student,
+) -> UpdateStudentsMatchingRequest {
+    UpdateStudentsMatchingRequest {
+        filter: Some(filter),
+        update_mask: Some(FieldMask {
+            paths: paths.iter().map(|path| path.to_string()).collect(),
+        }),
+        new_values: Some(new_values),
+        dry_run: false,
+    }
+}
+
+async fn run(
+    client: &mut BulkServiceClient<Channel>,
+    request: UpdateStudentsMatchingRequest,
+) -> Result<UpdateStudentsMatchingResponse, Status> {
+    let operation = client.update_students_matching(request).await?.into_inner();
+    assert_eq!(operation.kind, server::bulk::KIND);
+    client
+        .get_bulk_update_result(GetBulkUpdateResultRequest {
+            operation_id: operation.id,
+        })
+        .await
+        .map(|response| response.into_inner())
+}
+
+fn comp_sci() -> StudentFilter {
+    StudentFilter {
+        major: "COMP SCI".to_string(),
+        ..Default::default()
+    }
+}
+
+fn renamed() -> Student {
+    Student {
+        major: "Computer Science".to_string(),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn a_major_is_renamed_across_the_board() {
+    let store = store().await;
+    let operations = Operations::new();
+    let mut client = start(store.clone(), operations.clone()).await;
+
+    let mut dry_run = update(comp_sci(), &["major"], renamed());
+    dry_run.dry_run = true;
+    let result = run(&mut client, dry_run).await.unwrap();
+    assert_eq!((result.matched_count, result.updated_count), (100, 100));
+    assert_eq!(store.get("s000").await.unwrap().major, "Comp Sci");
+
+    let before = store.get("s001").await.unwrap();
+    let result = run(&mut client, update(comp_sci(), &["major"], renamed()))
+        .await
+        .unwrap();
+    assert_eq!((result.matched_count, result.updated_count), (100, 100));
+    assert!(result.failures.is_empty());
+    let student = store.get("s001").await.unwrap();
+    assert_eq!(student.major, "Computer Science");
+    assert_ne!(student.etag, before.etag);
+    assert!(student.update_time.is_some());
+    assert_eq!(store.get("s002").await.unwrap().major, "Physics");
+
+    let finished = operations.list(server::bulk::KIND);
+    let last = finished.last().unwrap();
+    assert_eq!((last.processed_count, last.total_count), (150, 150));
+
+    // Nothing left to match
+    let result = run(&mut client, update(comp_sci(), &["major"], renamed()))
+        .await
+        .unwrap();
+    assert_eq!(result.matched_count, 0);
+}
+
+#[tokio::test]
+async fn students_the_new_values_are_invalid_for_are_left_alone() {
+    let store = store().await;
+    let mut client = start(store.clone(), Operations::new()).await;
+
+    let on_probation = StudentFilter {
+        standing: AcademicStanding::Probation as i32,
+        ..Default::default()
+    };
+    let result = run(
+        &mut client,
+        update(
+            on_probation.clone(),
+            &["gpa", "credits"],
+            Student {
+                gpa: 2.5,
+                credits: -1,
+                ..Default::default()
+            },
+        ),
+    )
+    .await
+    .unwrap();
+    assert_eq!((result.matched_count, result.updated_count), (10, 0));
+    assert_eq!(result.failures.len(), 10);
+    assert_eq!(result.failures[0].student_id, "s000");
+    assert_eq!(
+        result.failures[0].reason,
+        "Student credits cannot be negative"
+    );
+    assert_eq!(store.get("s000").await.unwrap().gpa, 1.5);
+
+    let result = run(
+        &mut client,
+        update(
+            StudentFilter {
+                ids: vec!["s000".to_string(), "s001".to_string()],
+                ..on_probation
+            },
+            &["gpa"],
+            Student {
+                gpa: 2.5,
+                ..Default::default()
+            },
+        ),
+    )
+    .await
+    .unwrap();
+    assert_eq!((result.matched_count, result.updated_count), (2, 2));
+    assert_eq!(store.get("s001").await.unwrap().gpa, 2.5);
+}
+
+#[tokio::test]
+async fn bad_requests_are_rejected_up_front() {
+    let store = store().await;
+    let operations = Operations::new();
+    let mut client = start(store, operations.clone()).await;
+
+    for request in [
+        update(StudentFilter::default(), &["major"], renamed()),
+        update(comp_sci(), &[], renamed()),
+        update(comp_sci(), &["email"], renamed()),
+        update(
+            comp_sci(),
+            &["major_id"],
+            Student {
+                major_id: "nope".to_string(),
+                ..Default::default()
+            },
+        ),
+    ] {
+        let status = client.update_students_matching(request).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument, "{:?}", status);
+    }
+    assert!(operations.list(server::bulk::KIND).is_empty());
+
+    let other = operations.start("other", |_| async { Ok::<_, Status>(()) });
+    let status = client
+        .get_bulk_update_result(GetBulkUpdateResultRequest {
+            operation_id: other.id,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
+
+fn new_student(index: usize) -> Student {
+    Student {
+        name: format!("New {}", index),
+        email: format!("new{}@university.edu", index),
+        age: 19,
+        gpa: 3.5,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn streamed_students_are_created_in_batches() {
+    let store = store().await;
+    let events = Arc::new(EventLog::new());
+    let service = BulkServiceImpl::new(
+        store.clone(),
+        Arc::new(Catalog::new()),
+        StandingRules::default(),
+        Operations::new(),
+    )
+    .with_id_generator(Arc::new(SequentialIds::new("new-")))
+    .with_events(events.clone());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .add_service(BulkServiceServer::new(service))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    let mut client = BulkServiceClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap();
+    let (_, mut watcher) = events.subscribe("").unwrap();
+
+    // More than a batch, with an existing ID, an ID given twice, and an
+    // invalid student among them
+    let mut students: Vec<Student> = (0..1200).map(new_student).collect();
+    students[10].id = "s005".to_string();
+    students[20].id = "twice".to_string();
+    students[700].id = "twice".to_string();
+    students[900].gpa = 5.0;
+    let requests: Vec<BulkCreateStudentsRequest> = students
+        .chunks(300)
+        .map(|chunk| BulkCreateStudentsRequest {
+            students: chunk.to_vec(),
+        })
+        .collect();
+
+    let response = client
+        .bulk_create_students(tokio_stream::iter(requests))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(response.created_count, 1197);
+    let failures: Vec<(i32, &str)> = response
+        .failures
+        .iter()
+        .map(|failure| (failure.index, failure.student_id.as_str()))
+        .collect();
+    // Either copy of "twice" may be the one created
+    assert_eq!(failures.len(), 3, "{:?}", failures);
+    assert_eq!(failures[0], (10, "s005"));
+    assert!(failures[1] == (20, "twice") || failures[1] == (700, "twice"));
+    assert_eq!(failures[2], (900, ""));
+    assert!(response.failures[2].reason.contains("GPA"));
+
+    assert_eq!(store.list(1, "").await.unwrap().total_count, 150 + 1197);
+    assert!(store.get("twice").await.unwrap().create_time.is_some());
+    let generated = store.get("new-1").await.unwrap();
+    assert!(generated.name.starts_with("New "));
+
+    // One Created event per student, more than the watcher keeps
+    let mut published = 0;
+    loop {
+        match watcher.try_recv() {
+            Ok(event) => {
+                assert_eq!(event.change_type, ChangeType::Created as i32);
+                published += 1;
+            }
+            Err(TryRecvError::Lagged(missed)) => published += missed,
+            Err(_) => break,
+        }
+    }
+    assert_eq!(published, 1197);
+}
+
+#[tokio::test]
+async fn imports_cut_off_store_no_more_students() {
+    // Holds every create_many until let through
+    #[derive(Debug)]
+    struct Gated {
+        inner: InMemoryRepository,
+        gate: Semaphore,
+    }
+
+    #[tonic::async_trait]
+    impl StudentRepository for Gated {
+        async fn create(&self, student: Student) -> Result<Student, Status> {
+            self.inner.create(student).await
+        }
+
+        async fn get(&self, id: &str) -> Result<Student, Status> {
+            self.inner.get(id).await
+        }
+
+        async fn update(&self, student: Student) -> Result<Student, Status> {
+            self.inner.update(student).await
+        }
+
+        async fn delete(&self, id: &str) -> Result<Student, Status> {
+            self.inner.delete(id).await
+        }
+
+        async fn list(
+            &self,
+            page_size: usize,
+            page_token: &str,
+        ) -> Result<proto::ListStudentsResponse, Status> {
+            self.inner.list(page_size, page_token).await
+        }
+
+        async fn create_many(&self, students: Vec<Student>) -> Vec<Result<Student, Status>> {
+            self.gate.acquire().await.unwrap().forget();
+            self.inner.create_many(students).await
+        }
+    }
+
+    let store = Arc::new(Gated {
+        inner: InMemoryRepository::new(),
+        gate: Semaphore::new(0),
+    });
+    let service = BulkServiceImpl::new(
+        store.clone(),
+        Arc::new(Catalog::new()),
+        StandingRules::default(),
+        Operations::new(),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .add_service(BulkServiceServer::new(service))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    // The client's connection goes through a proxy, to be cut
+    let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = proxy.local_addr().unwrap();
+    let connection = tokio::spawn(async move {
+        let (mut client, _) = proxy.accept().await.unwrap();
+        let mut server = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let _ = tokio::io::copy_bidirectional(&mut client, &mut server).await;
+    });
+    let mut client = BulkServiceClient::connect(format!("http://{}", proxy_addr))
+        .await
+        .unwrap();
+
+    let (requests, to_send) = mpsc::channel(4);
+    let call = tokio::spawn(async move {
+        client
+            .bulk_create_students(ReceiverStream::new(to_send))
+            .await
+    });
+    let pause = || tokio::time::sleep(Duration::from_millis(200));
+    for range in [0..10, 10..20] {
+        let students = range.map(new_student).collect();
+        requests
+            .send(BulkCreateStudentsRequest { students })
+            .await
+            .unwrap();
+        // The first students are held at the gate, the others read behind them
+        pause().await;
+    }
+    connection.abort();
+    assert!(call.await.unwrap().is_err());
+    pause().await;
+    store.gate.add_permits(10);
+    pause().await;
+
+    // Only what the writer had started on is stored
+    let stored = store.inner.all().await.unwrap();
+    assert!(!stored.is_empty());
+    for student in stored {
+        let index: usize = student.name["New ".len()..].parse().unwrap();
+        assert!(index < 10, "{} was stored", student.name);
+    }
+}
@@ -0,0 +1,327 @@
+use proto::catalog_service_client::CatalogServiceClient;
+use proto::catalog_service_server::CatalogServiceServer;
+use proto::student_service_client::StudentServiceClient;
+use proto::student_service_server::StudentServiceServer;
+use proto::{
+    ChangeType, CreateDepartmentRequest, CreateMajorRequest, CreateStudentRequest,
+    DeleteDepartmentRequest, DeleteMajorRequest, Department, GetStudentRequest, Major,
+    MajorMapping, MigrateMajorsRequest, Student, UpdateMajorRequest, UpdateStudentRequest,
+    WatchStudentsRequest,
+};
+use server::catalog::{Catalog, CatalogServiceImpl};
+use server::repository::{InMemoryRepository, StudentRepository};
+use server::StudentServiceImpl;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::{Channel, Server};
+use tonic::Code;
+
+struct Clients {
+    students: StudentServiceClient<Channel>,
+    catalog: CatalogServiceClient<Channel>,
+    store: Arc<dyn StudentRepository>,
+}
+
+async fn start() -> Clients {
+    let store: Arc<dyn StudentRepository> = Arc::new(InMemoryRepository::new());
+    let catalog = Arc::new(Catalog::new());
+    let students = StudentServiceImpl::new()
+        .with_repository(store.clone())
+        .with_catalog(catalog.clone());
+    let catalog = CatalogServiceImpl::new(catalog, store.clone()).with_events(students.events());
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .add_service(StudentServiceServer::new(students))
+            .add_service(CatalogServiceServer::new(catalog))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+
+    let channel = Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    Clients {
+        students: StudentServiceClient::new(channel.clone()),
+        catalog: CatalogServiceClient::new(channel),
+        store,
+    }
+}
+
+fn computer_science() -> Major {
+    Major {
+        id: "cs".to_string(),
+        name: "Computer Science".to_string(),
+        department_id: "eng".to_string(),
+        aliases: vec!["CS".to_string(), "CompSci".to_string()],
+    }
+}
+
+async fn create_catalog(clients: &mut Clients) {
+    clients
+        .catalog
+        .create_department(CreateDepartmentRequest {
+            department: Some(Department {
+                id: "eng".to_string(),
+                name: "Engineering".to_string(),
+            }),
+        })
+        .await
+        .unwrap();
+    clients
+        .catalog
+        .create_major(CreateMajorRequest {
+            major: Some(computer_science()),
+        })
+        .await
+        .unwrap();
+}
+
+async fn create_student(
+    clients: &mut Clients,
+    id: &str,
+    major: &str,
+    major_id: &str,
+) -> Result<Student, tonic::Status> {
+    let response = clients
+        .students
+        .create_student(CreateStudentRequest {
+            student: Some(Student {
+                id: id.to_string(),
+                name: id.to_string(),
+                email: format!("{}@university.edu", id),
+                age: 20,
+                major: major.to_string(),
+                major_id: major_id.to_string(),
+                ..Default::default()
+            }),
+        })
+        .await?;
+    Ok(response.into_inner().student.unwrap())
+}
+
+async fn get_student(clients: &mut Clients, id: &str) -> Student {
+    clients
+        .students
+        .get_student(GetStudentRequest { id: id.to_string() })
+        .await
+        .unwrap()
+        .into_inner()
+        .student
+        .unwrap()
+}
+
+#[tokio::test]
+async fn major_id_sets_the_major_name() {
+    let mut clients = start().await;
+    create_catalog(&mut clients).await;
+
+    let ada = create_student(&mut clients, "ada", "whatever", "cs")
+        .await
+        .unwrap();
+    assert_eq!(ada.major, "Computer Science");
+
+    let status = create_student(&mut clients, "grace", "", "nope")
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    // Renaming the major shows up on the students who declared it
+    clients
+        .catalog
+        .update_major(UpdateMajorRequest {
+            major: Some(Major {
+                name: "Computing".to_string(),
+                ..computer_science()
+            }),
+        })
+        .await
+        .unwrap();
+    assert_eq!(get_student(&mut clients, "ada").await.major, "Computing");
+}
+
+#[tokio::test]
+async fn migrate_maps_legacy_majors_by_name_and_alias() {
+    let mut clients = start().await;
+    create_catalog(&mut clients).await;
+    for (id, major) in [("ada", "cs"), ("alan", "cs"), ("dan", "Basket Weaving")] {
+        create_student(&mut clients, id, major, "").await.unwrap();
+    }
+    // Stored before the service tidied input up
+    clients
+        .store
+        .create(Student {
+            id: "grace".to_string(),
+            name: "grace".to_string(),
+            email: "grace@university.edu".to_string(),
+            age: 20,
+            major: " computer science ".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let expected = vec![
+        MajorMapping {
+            legacy_major: " computer science ".to_string(),
+            major_id: "cs".to_string(),
+            student_count: 1,
+        },
+        MajorMapping {
+            legacy_major: "Basket Weaving".to_string(),
+            major_id: String::new(),
+            student_count: 1,
+        },
+        MajorMapping {
+            legacy_major: "cs".to_string(),
+            major_id: "cs".to_string(),
+            student_count: 2,
+        },
+    ];
+
+    let dry_run = clients
+        .catalog
+        .migrate_majors(MigrateMajorsRequest { dry_run: true })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(dry_run.mappings, expected);
+    assert_eq!(dry_run.updated_count, 0);
+    assert_eq!(get_student(&mut clients, "ada").await.major_id, "");
+
+    let mut watch = clients
+        .students
+        .watch_students(WatchStudentsRequest::default())
+        .await
+        .unwrap()
+        .into_inner();
+    let migrated = clients
+        .catalog
+        .migrate_majors(MigrateMajorsRequest { dry_run: false })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(migrated.mappings, expected);
+    assert_eq!(migrated.updated_count, 3);
+    let mut updated = Vec::new();
+    for _ in 0..3 {
+        let event = watch.message().await.unwrap().unwrap();
+        assert_eq!(event.change_type(), ChangeType::Updated);
+        let student = event.student.unwrap();
+        assert_eq!(student.major_id, "cs");
+        updated.push(student.id);
+    }
+    updated.sort();
+    assert_eq!(updated, ["ada", "alan", "grace"]);
+
+    let grace = get_student(&mut clients, "grace").await;
+    assert_eq!(grace.major_id, "cs");
+    assert_eq!(grace.major, "Computer Science");
+    let dan = get_student(&mut clients, "dan").await;
+    assert_eq!(dan.major_id, "");
+    assert_eq!(dan.major, "Basket Weaving");
+
+    // Only the unmatched student is left
+    let again = clients
+        .catalog
+        .migrate_majors(MigrateMajorsRequest { dry_run: false })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(again.mappings, vec![expected[1].clone()]);
+    assert_eq!(again.updated_count, 0);
+}
+
+#[tokio::test]
+async fn majors_and_departments_in_use_cannot_be_deleted() {
+    let mut clients = start().await;
+    create_catalog(&mut clients).await;
+    create_student(&mut clients, "ada", "", "cs").await.unwrap();
+
+    let status = clients
+        .catalog
+        .delete_major(DeleteMajorRequest {
+            id: "cs".to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+
+    let status = clients
+        .catalog
+        .delete_department(DeleteDepartmentRequest {
+            id: "eng".to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+
+    // Aliases must not name two majors
+    let status = clients
+        .catalog
+        .create_major(CreateMajorRequest {
+            major: Some(Major {
+                id: "cs2".to_string(),
+                name: "Computing Sciences".to_string(),
+                department_id: "eng".to_string(),
+                aliases: vec!["compsci".to_string()],
+            }),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn students_keep_majors_the_catalog_no_longer_has() {
+    let mut clients = start().await;
+    // Stored with a catalog since lost in a restart
+    clients
+        .store
+        .create(Student {
+            id: "ada".to_string(),
+            name: "ada".to_string(),
+            email: "ada@university.edu".to_string(),
+            age: 20,
+            major: "Computer Science".to_string(),
+            major_id: "cs".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let ada = get_student(&mut clients, "ada").await;
+    let renamed = clients
+        .students
+        .update_student(UpdateStudentRequest {
+            student: Some(Student {
+                name: "Ada Lovelace".to_string(),
+                ..ada.clone()
+            }),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .student
+        .unwrap();
+    assert_eq!(renamed.major_id, "cs");
+    assert_eq!(renamed.major, "Computer Science");
+
+    // A new one must still be in the catalog
+    let status = clients
+        .students
+        .update_student(UpdateStudentRequest {
+            student: Some(Student {
+                major_id: "physics".to_string(),
+                etag: String::new(),
+                ..ada
+            }),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
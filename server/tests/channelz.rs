@@ -0,0 +1,76 @@
+use client::StudentClient;
+use proto::student_service_server::StudentServiceServer;
+use proto::Student;
+use server::channelz::{Channelz, ChannelzLayer};
+use server::connection::ConnectionSettings;
+use server::StudentServiceImpl;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tonic::transport::server::Routes;
+use tonic::transport::Server;
+
+const WATCH: &str = "/student.StudentService/WatchStudents";
+
+async fn start(channelz: Channelz) -> String {
+    // A port no one is using
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let server = Server::builder().layer(ChannelzLayer::new(channelz.clone()));
+    let routes = Routes::new(StudentServiceServer::new(StudentServiceImpl::new()));
+    tokio::spawn(async move {
+        ConnectionSettings::default()
+            .serve(server, routes, addr, &channelz, std::future::pending())
+            .await
+            .unwrap()
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    format!("http://{}", addr)
+}
+
+#[tokio::test]
+async fn open_connections_list_their_calls_and_bytes() {
+    let channelz = Channelz::new();
+    let addr = start(channelz.clone()).await;
+    let mut client = StudentClient::connect(addr).await.unwrap();
+    let mut events = client.watch_students("").await.unwrap();
+    client
+        .create_student(Student {
+            id: "a".to_string(),
+            name: "Student a".to_string(),
+            email: "a@university.edu".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    events.message().await.unwrap().unwrap();
+
+    // One connection, carrying both calls; only the watch is still open
+    let sockets = channelz.sockets();
+    assert_eq!(sockets.len(), 1, "{:?}", sockets);
+    let socket = &sockets[0];
+    assert_eq!(socket.calls_started, 2);
+    assert_eq!(
+        socket.streams.iter().collect::<Vec<_>>(),
+        [(&WATCH.to_string(), &1)]
+    );
+    assert!(socket.bytes_received > 0 && socket.bytes_sent > 0);
+    assert!(socket.remote.is_some());
+    let text = channelz.render();
+    assert!(
+        text.starts_with("1 connections\n\n#1 127.0.0.1:"),
+        "{}",
+        text
+    );
+    assert!(text.ends_with(&format!("  1 open {}\n", WATCH)), "{}", text);
+
+    // Ended calls and closed connections are forgotten
+    drop(events);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(channelz.sockets()[0].streams.is_empty());
+    drop(client);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(channelz.sockets().is_empty());
+}
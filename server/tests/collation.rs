@@ -0,0 +1,171 @@
+use proto::student_service_server::StudentService;
+use proto::{CreateStudentRequest, ListStudentsRequest, Student};
+use server::collation::{self, compare};
+use server::locale::Locale;
+use server::validation::Profile;
+use server::StudentServiceImpl;
+use std::cmp::Ordering;
+use student_core::Problem;
+use tonic::{Code, Request, Status};
+
+fn sorted(locale: Locale, names: &[&str]) -> Vec<String> {
+    let mut names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+    names.sort_by(|a, b| compare(locale, a, b));
+    names
+}
+
+#[test]
+fn accents_and_case_only_break_ties() {
+    assert_eq!(
+        sorted(
+            Locale::En,
+            &["Zoe", "Åsa", "adam", "Álvarez", "alvarez", "Bo"]
+        ),
+        ["adam", "alvarez", "Álvarez", "Åsa", "Bo", "Zoe"]
+    );
+    assert_eq!(compare(Locale::En, "resume", "résumé"), Ordering::Less);
+    assert_eq!(compare(Locale::En, "résumé", "Resume"), Ordering::Greater);
+    assert_eq!(compare(Locale::En, "Straße", "strasse"), Ordering::Greater);
+    assert_eq!(compare(Locale::En, "Straße", "strasset"), Ordering::Less);
+    // Composed or not, it is the same letter
+    assert_eq!(compare(Locale::En, "Å", "A\u{30a}"), Ordering::Equal);
+}
+
+#[test]
+fn spanish_orders_n_with_tilde_after_n() {
+    let names = ["Nuñez", "Ñandú", "Nunez", "Oliva", "Nuno"];
+    assert_eq!(
+        sorted(Locale::Es, &names),
+        ["Nunez", "Nuno", "Nuñez", "Ñandú", "Oliva"]
+    );
+    assert_eq!(
+        sorted(Locale::En, &names),
+        ["Ñandú", "Nunez", "Nuñez", "Nuno", "Oliva"]
+    );
+}
+
+#[test]
+fn chinese_orders_by_pinyin() {
+    // By code point they would come as Zhāng, Lǐ, Wáng
+    assert_eq!(
+        sorted(Locale::Zh, &["张伟", "李娜", "王芳"]),
+        ["李娜", "王芳", "张伟"]
+    );
+}
+
+#[test]
+fn folding_ignores_case_and_accents() {
+    assert_eq!(collation::fold("Åsa Ødegård"), "asa odegard");
+    assert_eq!(collation::fold("JOSÉ"), collation::fold("jose\u{301}"));
+}
+
+#[test]
+fn names_are_composed() {
+    // "José" typed as "e" and a combining acute accent
+    let decomposed = "Jose\u{301} Nun\u{303}ez";
+    let mut student = Student {
+        name: decomposed.to_string(),
+        preferred_name: " Pepe ".to_string(),
+        email: "jose@university.edu".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(
+        Profile::Strict.check(&mut student.clone()),
+        [
+            ("name", Problem::NameNotCanonical),
+            ("preferred_name", Problem::PreferredNameNotCanonical),
+        ]
+    );
+    assert!(Profile::Lenient.check(&mut student).is_empty());
+    assert_eq!(student.name, "José Nuñez");
+    assert_eq!(student.preferred_name, "Pepe");
+}
+
+async fn service() -> StudentServiceImpl {
+    let service = StudentServiceImpl::new();
+    let students = [
+        ("s1", "Zoe Adams", ""),
+        ("s2", "Robert Smith", "Bob"),
+        ("s3", "Åsa Berg", ""),
+        ("s4", "nuria Ortiz", ""),
+        ("s5", "Ñoño Pérez", ""),
+    ];
+    for (id, name, preferred_name) in students {
+        service
+            .create_student(Request::new(CreateStudentRequest {
+                student: Some(Student {
+                    id: id.to_string(),
+                    name: name.to_string(),
+                    preferred_name: preferred_name.to_string(),
+                    email: format!("{}@university.edu", id),
+                    age: 20,
+                    ..Default::default()
+                }),
+            }))
+            .await
+            .unwrap();
+    }
+    service
+}
+
+async fn list(
+    service: &StudentServiceImpl,
+    order_by: &str,
+    page_token: &str,
+) -> Result<(Vec<String>, String), Status> {
+    let page = service
+        .list_students(Request::new(ListStudentsRequest {
+            page_size: 3,
+            page_token: page_token.to_string(),
+            order_by: order_by.to_string(),
+            ..Default::default()
+        }))
+        .await?
+        .into_inner();
+    let ids = page
+        .students
+        .into_iter()
+        .map(|student| student.id)
+        .collect();
+    Ok((ids, page.next_page_token))
+}
+
+#[tokio::test]
+async fn students_are_listed_by_preferred_name() {
+    let service = service().await;
+
+    let (first, token) = list(&service, "name", "").await.unwrap();
+    assert_eq!(first, ["s3", "s2", "s5"]);
+    let (rest, token) = list(&service, "name", &token).await.unwrap();
+    assert_eq!(rest, ["s4", "s1"]);
+    assert!(token.is_empty());
+
+    // IDs are still the default order
+    assert_eq!(list(&service, "", "").await.unwrap().0, ["s1", "s2", "s3"]);
+
+    let status = list(&service, "gpa", "").await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(
+        client::error_info(&status).unwrap().reason,
+        "UNKNOWN_ORDER_BY"
+    );
+}
+
+#[tokio::test]
+async fn name_order_follows_the_callers_locale() {
+    let service = service().await;
+
+    let (es, token) = Locale::Es.scope(list(&service, "name", "")).await.unwrap();
+    assert_eq!(es, ["s3", "s2", "s4"]);
+    let (es, _) = Locale::Es
+        .scope(list(&service, "name", &token))
+        .await
+        .unwrap();
+    assert_eq!(es, ["s5", "s1"]);
+    // An order in one locale cannot be paged through in another
+    let status = list(&service, "name", &token).await.unwrap_err();
+    assert_eq!(
+        client::error_info(&status).unwrap().reason,
+        "PAGE_TOKEN_INVALID"
+    );
+}
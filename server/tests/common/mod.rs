@@ -0,0 +1,17 @@
+//! What the integration tests share: a server on a free port.
+
+use std::future::Future;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+
+/// Serve on a free local port, in the background, with the server
+/// `serve` builds on the connections it is given; the URL to reach it at.
+pub async fn serve<F>(serve: impl FnOnce(TcpListenerStream) -> F) -> String
+where
+    F: Future<Output = Result<(), tonic::transport::Error>> + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(serve(TcpListenerStream::new(listener)));
+    url
+}
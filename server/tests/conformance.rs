@@ -0,0 +1,45 @@
+use server::repository::InMemoryRepository;
+
+server::repository_conformance!(InMemoryRepository::new());
+
+// The statistics wrapper must behave exactly like the store it wraps
+mod counted {
+    use server::repository::InMemoryRepository;
+    use server::standing::StandingRules;
+    use server::statistics::{CountedRepository, Statistics};
+    use std::sync::Arc;
+
+    server::repository_conformance!(CountedRepository::new(
+        Arc::new(InMemoryRepository::new()),
+        Arc::new(Statistics::new(StandingRules::default())),
+    ));
+}
+
+// So must the eviction wrapper, as long as nothing is due for eviction
+mod evicting {
+    use server::eviction::{EvictingRepository, Eviction};
+    use server::repository::InMemoryRepository;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    server::repository_conformance!(EvictingRepository::new(
+        Arc::new(InMemoryRepository::new()),
+        Eviction {
+            ttl: Some(Duration::from_secs(3600)),
+            max_students: Some(100_000),
+        },
+    ));
+}
+
+// A sharded store with one shard must too; several shards cannot commit
+// transactions together, so those are checked in tests/sharding.rs
+mod sharded {
+    use server::repository::{InMemoryRepository, StudentRepository};
+    use server::sharding::ShardedRepository;
+    use std::sync::Arc;
+
+    server::repository_conformance!(ShardedRepository::new(vec![(
+        "shard".to_string(),
+        Arc::new(InMemoryRepository::new()) as Arc<dyn StudentRepository>,
+    )]));
+}
@@ -0,0 +1,151 @@
+use client::StudentClient;
+use proto::student_service_server::StudentServiceServer;
+use proto::{ChangeType, Student};
+use server::channelz::Channelz;
+use server::connection::ConnectionSettings;
+use server::StudentServiceImpl;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::server::Routes;
+use tonic::transport::Server;
+
+fn student(id: &str) -> Student {
+    Student {
+        id: id.to_string(),
+        name: format!("Student {}", id),
+        email: format!("{}@university.edu", id),
+        ..Default::default()
+    }
+}
+
+async fn start(settings: ConnectionSettings) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        settings
+            .apply(Server::builder())
+            .add_service(StudentServiceServer::new(StudentServiceImpl::new()))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    format!("http://{}", addr)
+}
+
+// Serves with `settings` until `stop` fires; returns the address and
+// its task, which ends once it has stopped
+async fn serve(
+    settings: ConnectionSettings,
+    stop: tokio::sync::oneshot::Receiver<()>,
+) -> (String, tokio::task::JoinHandle<()>) {
+    // A port no one is using
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let routes = Routes::new(StudentServiceServer::new(StudentServiceImpl::new()));
+    let stop = async move {
+        let _ = stop.await;
+    };
+    let serving = tokio::spawn(async move {
+        settings
+            .serve(Server::builder(), routes, addr, &Channelz::new(), stop)
+            .await
+            .unwrap()
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    (format!("http://{}", addr), serving)
+}
+
+#[tokio::test]
+async fn a_quiet_watch_stream_survives_keepalive_pings() {
+    let addr = start(ConnectionSettings {
+        keepalive_interval: Some(Duration::from_millis(50)),
+        keepalive_timeout: Some(Duration::from_millis(200)),
+        ..Default::default()
+    })
+    .await;
+    let settings = client::ConnectionSettings {
+        keepalive_interval: Some(Duration::from_millis(50)),
+        keepalive_timeout: Some(Duration::from_millis(200)),
+        keepalive_while_idle: true,
+        ..Default::default()
+    };
+    let mut watcher = StudentClient::connect_with(addr.clone(), &settings)
+        .await
+        .unwrap();
+    let mut events = watcher.watch_students("").await.unwrap();
+
+    // Many pings go both ways while nothing happens
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let mut writer = StudentClient::connect(addr).await.unwrap();
+    writer.create_student(student("a")).await.unwrap();
+    let event = events.message().await.unwrap().unwrap();
+    assert_eq!(event.change_type(), ChangeType::Created);
+}
+
+#[tokio::test]
+async fn calls_past_the_stream_limit_wait_for_a_free_stream() {
+    let addr = start(ConnectionSettings {
+        max_concurrent_streams: Some(1),
+        ..Default::default()
+    })
+    .await;
+    let mut client = StudentClient::connect(addr).await.unwrap();
+    let events = client.watch_students("").await.unwrap();
+
+    // The watch stream takes the connection's only stream
+    let blocked = tokio::time::timeout(
+        Duration::from_millis(200),
+        client.create_student(student("a")),
+    );
+    assert!(blocked.await.is_err());
+    drop(events);
+    client.create_student(student("b")).await.unwrap();
+
+    assert_eq!(client::parse_duration("30s"), Ok(Duration::from_secs(30)));
+    assert_eq!(
+        client::parse_duration("250ms"),
+        Ok(Duration::from_millis(250))
+    );
+    for bad in ["", "30", "s", "1.5s", "30 days"] {
+        assert!(client::parse_duration(bad).is_err(), "{:?}", bad);
+    }
+}
+
+#[tokio::test]
+async fn old_connections_are_sent_goaway_and_clients_reconnect() {
+    let (stop, stopping) = tokio::sync::oneshot::channel();
+    let (addr, serving) = serve(
+        ConnectionSettings {
+            max_connection_age: Some(Duration::from_millis(200)),
+            max_connection_age_grace: Some(Duration::from_millis(600)),
+            ..Default::default()
+        },
+        stopping,
+    )
+    .await;
+    let mut client = StudentClient::connect(addr.clone()).await.unwrap();
+    let mut events = client.watch_students("").await.unwrap();
+
+    // Past its age the connection still carries the open watch, for now
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let mut writer = StudentClient::connect(addr.clone()).await.unwrap();
+    writer.create_student(student("a")).await.unwrap();
+    let event = events.message().await.unwrap().unwrap();
+    assert_eq!(event.student.unwrap().id, "a");
+
+    // Then it is cut, and the client's next call goes over a new one
+    let ended = tokio::time::timeout(Duration::from_secs(2), events.message()).await;
+    assert!(matches!(ended, Ok(Err(_)) | Ok(Ok(None))), "{:?}", ended);
+    client.create_student(student("b")).await.unwrap();
+    let mut events = client.watch_students("").await.unwrap();
+
+    // On shutdown every connection is sent GOAWAY and drained at once
+    stop.send(()).unwrap();
+    let _ = events.message().await;
+    tokio::time::timeout(Duration::from_secs(2), serving)
+        .await
+        .unwrap()
+        .unwrap();
+}
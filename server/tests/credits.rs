@@ -0,0 +1,198 @@
+use proto::enrollment_service_server::EnrollmentService;
+use proto::student_service_server::StudentService;
+use proto::{
+    Course, CreateCourseRequest, GetStudentRequest, RecomputeGpaRequest, RecordGradeRequest,
+    Student, TranscriptEntry, UpdateStudentRequest,
+};
+use server::enrollment::EnrollmentServiceImpl;
+use server::gpa::{self, Gpa};
+use server::repository::{InMemoryRepository, StudentRepository};
+use server::StudentServiceImpl;
+use std::sync::Arc;
+use tonic::{Code, Request};
+
+async fn start(students: &[(&str, &str)]) -> (Arc<InMemoryRepository>, EnrollmentServiceImpl) {
+    let store = Arc::new(InMemoryRepository::new());
+    for (id, gpa) in students {
+        let mut student = Student {
+            id: id.to_string(),
+            name: id.to_string(),
+            ..Default::default()
+        };
+        gpa::set(&mut student, gpa.parse().unwrap());
+        store.create(student).await.unwrap();
+    }
+    let enrollment = EnrollmentServiceImpl::new(store.clone());
+    for (id, credits) in [("cs101", 4), ("cs102", 3), ("seminar", 0), ("lab", 1)] {
+        enrollment
+            .create_course(Request::new(CreateCourseRequest {
+                course: Some(Course {
+                    id: id.to_string(),
+                    title: id.to_uppercase(),
+                    capacity: 10,
+                    credits,
+                    ..Default::default()
+                }),
+            }))
+            .await
+            .unwrap();
+    }
+    (store, enrollment)
+}
+
+async fn record_grade(
+    enrollment: &EnrollmentServiceImpl,
+    student_id: &str,
+    course: &str,
+    grade: &str,
+) {
+    enrollment
+        .record_grade(Request::new(RecordGradeRequest {
+            student_id: student_id.to_string(),
+            entry: Some(TranscriptEntry {
+                course_id: course.to_string(),
+                grade: grade.to_string(),
+            }),
+        }))
+        .await
+        .unwrap();
+}
+
+async fn credits(store: &InMemoryRepository, id: &str) -> (i32, i32) {
+    let student = store.get(id).await.unwrap();
+    (student.credits_attempted, student.credits_earned)
+}
+
+#[tokio::test]
+async fn grades_keep_credits_current() {
+    let (store, enrollment) = start(&[("s1", "2.00")]).await;
+
+    record_grade(&enrollment, "s1", "cs101", "A").await;
+    assert_eq!(credits(&store, "s1").await, (4, 4));
+    record_grade(&enrollment, "s1", "cs102", "F").await;
+    assert_eq!(credits(&store, "s1").await, (7, 4));
+    // A new grade replaces the old one, credits and all
+    record_grade(&enrollment, "s1", "cs102", "B").await;
+    assert_eq!(credits(&store, "s1").await, (7, 7));
+    record_grade(&enrollment, "s1", "seminar", "P").await;
+    record_grade(&enrollment, "s1", "lab", "P").await;
+    assert_eq!(credits(&store, "s1").await, (8, 8));
+
+    // Recording grades leaves the GPA to clients until it is recomputed
+    assert_eq!(gpa::of(&store.get("s1").await.unwrap()).to_string(), "2.00");
+
+    let status = enrollment
+        .create_course(Request::new(CreateCourseRequest {
+            course: Some(Course {
+                id: "cs999".to_string(),
+                title: "Negative".to_string(),
+                capacity: 10,
+                credits: -1,
+                ..Default::default()
+            }),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn gpa_is_recomputed_from_credit_weighted_grades() {
+    let (store, enrollment) = start(&[("s1", "2.00"), ("s2", "3.70"), ("s3", "3.00")]).await;
+    // (4 × 4.0 + 3 × 2.7) / 7 = 3.442…; pass/fail grades do not count
+    record_grade(&enrollment, "s1", "cs101", "A").await;
+    record_grade(&enrollment, "s1", "cs102", "B-").await;
+    record_grade(&enrollment, "s1", "lab", "P").await;
+    // Already right
+    record_grade(&enrollment, "s2", "cs102", "A-").await;
+
+    let recompute = |student_ids: &[&str], dry_run| {
+        enrollment.recompute_gpa(Request::new(RecomputeGpaRequest {
+            student_ids: student_ids.iter().map(|id| id.to_string()).collect(),
+            dry_run,
+        }))
+    };
+    let dry_run = recompute(&[], true).await.unwrap().into_inner().students;
+    let summary: Vec<_> = dry_run
+        .iter()
+        .map(|student| {
+            (
+                student.student_id.as_str(),
+                student.previous_gpa.as_str(),
+                student.gpa.as_str(),
+                student.changed,
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [("s1", "2.00", "3.44", true), ("s2", "3.70", "3.70", false)]
+    );
+    assert_eq!(
+        (dry_run[0].credits_attempted, dry_run[0].credits_earned),
+        (8, 8)
+    );
+    assert_eq!(gpa::of(&store.get("s1").await.unwrap()).to_string(), "2.00");
+
+    recompute(&[], false).await.unwrap();
+    let s1 = store.get("s1").await.unwrap();
+    assert_eq!((s1.gpa_decimal.as_str(), s1.gpa), ("3.44", 3.44));
+    assert!(
+        !recompute(&["s1"], false)
+            .await
+            .unwrap()
+            .into_inner()
+            .students[0]
+            .changed
+    );
+
+    // A student without grades keeps their GPA
+    let s3 = &recompute(&["s3"], false)
+        .await
+        .unwrap()
+        .into_inner()
+        .students[0];
+    assert_eq!((s3.gpa.as_str(), s3.changed), ("3.00", false));
+
+    let missing = recompute(&["nobody"], false).await.unwrap_err();
+    assert_eq!(missing.code(), Code::NotFound);
+    let empty = recompute(&[" "], false).await.unwrap_err();
+    assert_eq!(empty.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn updates_cannot_overwrite_credits() {
+    let (store, enrollment) = start(&[("s1", "3.00")]).await;
+    let students = StudentServiceImpl::new().with_repository(store.clone());
+    let read = students
+        .get_student(Request::new(GetStudentRequest {
+            id: "s1".to_string(),
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .student
+        .unwrap();
+
+    // A grade lands between the client's read and its write
+    record_grade(&enrollment, "s1", "cs101", "C").await;
+    let updated = students
+        .update_student(Request::new(UpdateStudentRequest {
+            student: Some(Student {
+                name: "Ada".to_string(),
+                email: "ada@university.edu".to_string(),
+                age: 20,
+                credits_attempted: 99,
+                etag: String::new(),
+                ..read
+            }),
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .student
+        .unwrap();
+    assert_eq!(updated.name, "Ada");
+    assert_eq!((updated.credits_attempted, updated.credits_earned), (4, 4));
+    assert_eq!(gpa::of(&updated), Gpa::from_hundredths(300));
+}
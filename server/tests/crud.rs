@@ -0,0 +1,103 @@
+use proto::catalog_service_server::CatalogService;
+use proto::{CreateDepartmentRequest, Department, ListDepartmentsRequest};
+use server::catalog::{Catalog, CatalogServiceImpl};
+use server::crud::Collection;
+use server::ids::SequentialIds;
+use server::repository::InMemoryRepository;
+use std::sync::Arc;
+use tonic::{Code, Request};
+
+fn department(id: &str, name: &str) -> Department {
+    Department {
+        id: id.to_string(),
+        name: name.to_string(),
+    }
+}
+
+#[test]
+fn collections_keep_entities_by_id() {
+    let mut departments =
+        Collection::<Department>::new().with_ids(Arc::new(SequentialIds::new("d")));
+
+    let math = departments.create(department("", "Mathematics")).unwrap();
+    assert_eq!(math.id, "d1");
+    let status = departments.create(department("d1", "Physics")).unwrap_err();
+    assert_eq!(status.code(), Code::AlreadyExists);
+    assert_eq!(status.message(), "Department with this ID already exists");
+
+    let renamed = departments
+        .update(department("d1", "Applied Mathematics"))
+        .unwrap();
+    assert_eq!(departments.find("d1").unwrap(), &renamed);
+    let status = departments.update(department("d2", "Physics")).unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    assert_eq!(status.message(), "Department not found");
+
+    assert_eq!(departments.delete("d1").unwrap(), renamed);
+    assert!(!departments.contains("d1"));
+    assert_eq!(departments.delete("d1").unwrap_err().code(), Code::NotFound);
+}
+
+#[test]
+fn pages_follow_id_order() {
+    let mut departments = Collection::new();
+    for (id, name) in [
+        ("c", "Chemistry"),
+        ("a", "Art"),
+        ("b", "Biology"),
+        ("d", "Drama"),
+    ] {
+        departments.create(department(id, name)).unwrap();
+    }
+
+    let (page, token) = departments.page(|_| true, 3, "").unwrap();
+    let ids: Vec<_> = page.iter().map(|d| d.id.as_str()).collect();
+    assert_eq!(ids, ["a", "b", "c"]);
+    let (page, token) = departments.page(|_| true, 3, &token).unwrap();
+    assert_eq!(page, [department("d", "Drama")]);
+    assert!(token.is_empty());
+
+    // Filtered, and everything at once without a page size
+    let (page, token) = departments.page(|d| d.name.contains('a'), 0, "").unwrap();
+    let ids: Vec<_> = page.iter().map(|d| d.id.as_str()).collect();
+    assert_eq!(ids, ["d"]);
+    assert!(token.is_empty());
+
+    let status = departments.page(|_| true, 3, "bogus").unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn list_rpcs_page_through_a_collection() {
+    let service = CatalogServiceImpl::new(
+        Arc::new(Catalog::new()),
+        Arc::new(InMemoryRepository::new()),
+    );
+    for (id, name) in [("a", "Art"), ("b", "Biology"), ("c", "Chemistry")] {
+        service
+            .create_department(Request::new(CreateDepartmentRequest {
+                department: Some(department(id, name)),
+            }))
+            .await
+            .unwrap();
+    }
+
+    let mut names = Vec::new();
+    let mut page_token = String::new();
+    loop {
+        let page = service
+            .list_departments(Request::new(ListDepartmentsRequest {
+                page_size: 2,
+                page_token,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        names.extend(page.departments.into_iter().map(|d| d.name));
+        if page.next_page_token.is_empty() {
+            break;
+        }
+        page_token = page.next_page_token;
+    }
+    assert_eq!(names, ["Art", "Biology", "Chemistry"]);
+}
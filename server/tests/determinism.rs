@@ -0,0 +1,90 @@
+use proto::student_service_server::StudentService;
+use proto::{CreateStudentRequest, Student, Timestamp, UpdateStudentRequest};
+use server::clock::FixedClock;
+use server::ids::SequentialIds;
+use server::StudentServiceImpl;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tonic::Request;
+
+const START: u64 = 1_767_225_600;
+
+fn student(name: &str) -> Student {
+    Student {
+        name: name.to_string(),
+        email: format!("{}@university.edu", name.to_lowercase()),
+        age: 20,
+        gpa: 3.0,
+        ..Default::default()
+    }
+}
+
+// Two students created, then the first updated a minute and a half later,
+// as a fresh service answers
+async fn run() -> Vec<Student> {
+    let clock = Arc::new(FixedClock::new(UNIX_EPOCH + Duration::from_secs(START)));
+    let service = StudentServiceImpl::new()
+        .with_id_generator(Arc::new(SequentialIds::new("s")))
+        .with_clock(clock.clone());
+
+    let mut students = Vec::new();
+    for name in ["Ada", "Grace"] {
+        let created = service
+            .create_student(Request::new(CreateStudentRequest {
+                student: Some(student(name)),
+            }))
+            .await
+            .unwrap();
+        students.push(created.into_inner().student.unwrap());
+    }
+    clock.advance(Duration::from_secs(90));
+    let updated = service
+        .update_student(Request::new(UpdateStudentRequest {
+            student: Some(Student {
+                major: "Mathematics".to_string(),
+                ..students[0].clone()
+            }),
+        }))
+        .await
+        .unwrap();
+    students.push(updated.into_inner().student.unwrap());
+    students
+}
+
+fn at(seconds: u64) -> Option<Timestamp> {
+    Some(server::clock::timestamp(
+        SystemTime::UNIX_EPOCH + Duration::from_secs(seconds),
+    ))
+}
+
+#[tokio::test]
+async fn ids_and_times_are_known_in_advance() {
+    let students = run().await;
+    let ids: Vec<_> = students.iter().map(|student| student.id.as_str()).collect();
+    assert_eq!(ids, ["s1", "s2", "s1"]);
+    let times: Vec<_> = students
+        .iter()
+        .map(|student| (student.create_time.clone(), student.update_time.clone()))
+        .collect();
+    assert_eq!(
+        times,
+        [
+            (at(START), at(START)),
+            (at(START), at(START)),
+            (at(START), at(START + 90)),
+        ]
+    );
+
+    // So a second run answers as the first did, but for the etags, which
+    // each store qualifies with an ID of its own, so no two runs share one
+    let without_etags = |students: Vec<Student>| -> Vec<Student> {
+        students
+            .into_iter()
+            .map(|student| Student {
+                etag: String::new(),
+                ..student
+            })
+            .collect()
+    };
+    assert_eq!(without_etags(run().await), without_etags(students));
+}
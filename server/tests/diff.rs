@@ -0,0 +1,110 @@
+use proto::{AcademicStanding, Address, Student};
+use serde_json::json;
+use server::diff::{self, FieldChange};
+use server::transfer;
+use std::collections::HashMap;
+
+fn student(id: &str, name: &str) -> Student {
+    Student {
+        id: id.to_string(),
+        name: name.to_string(),
+        email: format!("{}@university.edu", id),
+        age: 20,
+        gpa: 3.5,
+        address: Some(Address {
+            line1: "1 Main St".to_string(),
+            city: "Springfield".to_string(),
+            postal_code: "62701".to_string(),
+            country: "US".to_string(),
+            ..Default::default()
+        }),
+        annotations: HashMap::from([("term".to_string(), "fall".to_string())]),
+        ..Default::default()
+    }
+}
+
+fn before() -> Vec<Student> {
+    vec![
+        student("s1", "Ada Lovelace"),
+        student("s2", "Grace Hopper"),
+        student("s3", "Alan Turing"),
+    ]
+}
+
+fn after() -> Vec<Student> {
+    let mut changed = student("s2", "Grace Hopper");
+    changed.gpa = 3.75;
+    changed.phone_numbers = vec!["+12025550123".to_string()];
+    changed.address.as_mut().unwrap().postal_code = "62702".to_string();
+    changed.annotations.clear();
+    vec![
+        student("s1", "Ada Lovelace"),
+        changed,
+        student("s4", "Dan Lee"),
+    ]
+}
+
+#[test]
+fn students_are_compared_field_by_field() {
+    let diff = diff::diff(&before(), &after());
+
+    assert_eq!(diff.added, [student("s4", "Dan Lee")]);
+    assert_eq!(diff.removed, [student("s3", "Alan Turing")]);
+    assert_eq!(diff.unchanged, 1);
+    assert_eq!(diff.changed.len(), 1);
+    assert_eq!(diff.changed[0].id, "s2");
+    let change = |field: &str, before, after| FieldChange {
+        field: field.to_string(),
+        before,
+        after,
+    };
+    assert_eq!(
+        diff.changed[0].fields,
+        [
+            change("address.postal_code", json!("62701"), json!("62702")),
+            change("annotations.term", json!("fall"), json!(null)),
+            change("gpa", json!(3.5), json!(3.75)),
+            change("phone_numbers", json!(null), json!(["+12025550123"])),
+        ]
+    );
+    assert_eq!(
+        diff.to_string(),
+        "+ s4  Dan Lee\n\
+         - s3  Alan Turing\n\
+         ~ s2  Grace Hopper\n    \
+         address.postal_code: \"62701\" → \"62702\"\n    \
+         annotations.term: \"fall\" → null\n    \
+         gpa: 3.5 → 3.75\n    \
+         phone_numbers: null → [\"+12025550123\"]\n\
+         1 added, 1 removed, 1 changed, 1 unchanged"
+    );
+
+    let json = serde_json::to_value(&diff).unwrap();
+    assert_eq!(json["added"][0]["id"], "s4");
+    assert_eq!(json["changed"][0]["fields"][2]["field"], "gpa");
+    assert_eq!(json["unchanged"], 1);
+}
+
+#[test]
+fn stores_with_the_same_checksum_do_not_differ() {
+    // What each backend derives for itself
+    let copied: Vec<_> = before()
+        .into_iter()
+        .map(|student| Student {
+            etag: "\"7\"".to_string(),
+            standing: AcademicStanding::DeansList as i32,
+            gpa_decimal: "3.50".to_string(),
+            ..student
+        })
+        .collect();
+    assert_eq!(transfer::checksum(&before()), transfer::checksum(&copied));
+    let diff = diff::diff(&before(), &copied);
+    assert!(diff.is_empty());
+    assert_eq!(diff.unchanged, 3);
+    assert_eq!(
+        diff.to_string(),
+        "0 added, 0 removed, 0 changed, 3 unchanged"
+    );
+
+    assert!(!diff::diff(&before(), &after()).is_empty());
+}
@@ -0,0 +1,449 @@
+use proto::attendance_service_client::AttendanceServiceClient;
+use proto::attendance_service_server::AttendanceServiceServer;
+use proto::duplicate_service_client::DuplicateServiceClient;
+use proto::duplicate_service_server::DuplicateServiceServer;
+use proto::enrollment_service_client::EnrollmentServiceClient;
+use proto::enrollment_service_server::EnrollmentServiceServer;
+use proto::professor_service_client::ProfessorServiceClient;
+use proto::professor_service_server::ProfessorServiceServer;
+use proto::trash_service_client::TrashServiceClient;
+use proto::trash_service_server::TrashServiceServer;
+use proto::{
+    AssignAdvisorRequest, AttendanceRecord, AttendanceStatus, ChangeType, Course,
+    CreateCourseRequest, CreateProfessorRequest, EnrollRequest, FindDuplicatesRequest,
+    GetAdvisorRequest, GetTranscriptRequest, ListAttendanceRequest, ListEnrollmentsRequest,
+    MergeStudentsRequest, Professor, RecordGradeRequest, Student, Timestamp, TranscriptEntry,
+    UndoRequest,
+};
+use server::attendance::AttendanceServiceImpl;
+use server::catalog::Catalog;
+use server::clock::FixedClock;
+use server::duplicates::DuplicateServiceImpl;
+use server::enrollment::EnrollmentServiceImpl;
+use server::events::EventLog;
+use server::professor::ProfessorServiceImpl;
+use server::repository::{InMemoryRepository, StudentRepository};
+use server::trash::{Trash, TrashServiceImpl};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::{Channel, Server};
+use tonic::Code;
+
+// 2024-09-02 09:00 UTC
+const MONDAY: u64 = 1_725_267_600;
+
+struct Clients {
+    duplicates: DuplicateServiceClient<Channel>,
+    enrollment: EnrollmentServiceClient<Channel>,
+    attendance: AttendanceServiceClient<Channel>,
+    professors: ProfessorServiceClient<Channel>,
+    trash: TrashServiceClient<Channel>,
+    events: Arc<EventLog>,
+}
+
+fn student(id: &str, name: &str, email: &str) -> Student {
+    Student {
+        id: id.to_string(),
+        name: name.to_string(),
+        email: email.to_string(),
+        ..Default::default()
+    }
+}
+
+async fn start(store: Arc<InMemoryRepository>, clock: Arc<FixedClock>) -> Clients {
+    let enrollment = Arc::new(EnrollmentServiceImpl::new(store.clone()));
+    let attendance = Arc::new(AttendanceServiceImpl::new(enrollment.clone()));
+    let professors = Arc::new(ProfessorServiceImpl::new(
+        Arc::new(Catalog::new()),
+        store.clone(),
+    ));
+    let events = Arc::new(EventLog::new());
+    let trash = Trash::new(store.clone())
+        .with_clock(clock)
+        .with_window(Duration::from_secs(60 * 60))
+        .with_events(events.clone());
+    let duplicates = DuplicateServiceImpl::new(
+        store,
+        enrollment.clone(),
+        attendance.clone(),
+        professors.clone(),
+        trash.clone(),
+    )
+    .with_events(events.clone());
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .add_service(DuplicateServiceServer::new(duplicates))
+            .add_service(TrashServiceServer::new(TrashServiceImpl::new(trash)))
+            .add_service(EnrollmentServiceServer::from_arc(enrollment))
+            .add_service(AttendanceServiceServer::from_arc(attendance))
+            .add_service(ProfessorServiceServer::from_arc(professors))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    let channel = Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    Clients {
+        duplicates: DuplicateServiceClient::new(channel.clone()),
+        enrollment: EnrollmentServiceClient::new(channel.clone()),
+        attendance: AttendanceServiceClient::new(channel.clone()),
+        professors: ProfessorServiceClient::new(channel.clone()),
+        trash: TrashServiceClient::new(channel),
+        events,
+    }
+}
+
+fn clock() -> Arc<FixedClock> {
+    Arc::new(FixedClock::new(UNIX_EPOCH + Duration::from_secs(MONDAY)))
+}
+
+fn merge(primary_id: &str, duplicate_id: &str) -> MergeStudentsRequest {
+    MergeStudentsRequest {
+        primary_id: primary_id.to_string(),
+        duplicate_id: duplicate_id.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn similar_names_and_emails_are_found() {
+    let store = Arc::new(InMemoryRepository::new());
+    for student in [
+        student("s1", "Ada Lovelace", "ada@uni.edu"),
+        student("s2", "Lovelace, Ada", "countess@example.com"),
+        student("s3", "Augusta King", "ADA+old@uni.edu"),
+        student("s4", "Grace Hopper", "grace@uni.edu"),
+        student("s5", "Grace Hoper", "ghopper@example.com"),
+    ] {
+        store.create(student).await.unwrap();
+    }
+    let mut clients = start(store, clock()).await;
+
+    let matches = clients
+        .duplicates
+        .find_duplicates(FindDuplicatesRequest::default())
+        .await
+        .unwrap()
+        .into_inner()
+        .matches;
+    let pairs: Vec<(&str, &str, &[String])> = matches
+        .iter()
+        .map(|found| {
+            (
+                found.student.as_ref().unwrap().id.as_str(),
+                found.other.as_ref().unwrap().id.as_str(),
+                found.reasons.as_slice(),
+            )
+        })
+        .collect();
+    assert_eq!(pairs.len(), 3, "{:?}", pairs);
+    assert_eq!(pairs[0], ("s1", "s2", &["Same name".to_string()][..]));
+    assert_eq!(
+        pairs[1],
+        ("s1", "s3", &["Same email address".to_string()][..])
+    );
+    assert_eq!((pairs[2].0, pairs[2].1), ("s4", "s5"));
+    assert!(pairs[2].2[0].starts_with("Similar names"));
+    assert!(matches[2].score < 1.0);
+
+    let status = clients
+        .duplicates
+        .find_duplicates(FindDuplicatesRequest { min_score: 1.5 })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn merging_moves_history_and_undo_moves_it_back() {
+    let store = Arc::new(InMemoryRepository::new());
+    for id in ["ada", "ada2", "alan"] {
+        store.create(student(id, id, "")).await.unwrap();
+    }
+    let mut clients = start(store.clone(), clock()).await;
+
+    for (id, capacity) in [("math", 1), ("art", 1), ("history", 5), ("logic", 5)] {
+        clients
+            .enrollment
+            .create_course(CreateCourseRequest {
+                course: Some(Course {
+                    id: id.to_string(),
+                    title: id.to_string(),
+                    capacity,
+                    waitlist_capacity: 5,
+                    prerequisite_ids: vec![],
+                    credits: 3,
+                }),
+            })
+            .await
+            .unwrap();
+    }
+    // ada2 holds the only math seat with alan waiting; ada holds the art
+    // seat with ada2 waiting
+    for (student_id, course_id) in [
+        ("ada2", "math"),
+        ("alan", "math"),
+        ("ada", "art"),
+        ("ada2", "art"),
+    ] {
+        clients
+            .enrollment
+            .enroll(EnrollRequest {
+                student_id: student_id.to_string(),
+                course_id: course_id.to_string(),
+            })
+            .await
+            .unwrap();
+    }
+    for (student_id, course_id, grade) in [
+        ("ada2", "history", "A"),
+        ("ada2", "logic", "A"),
+        ("ada", "logic", "B"),
+    ] {
+        clients
+            .enrollment
+            .record_grade(RecordGradeRequest {
+                student_id: student_id.to_string(),
+                entry: Some(TranscriptEntry {
+                    course_id: course_id.to_string(),
+                    grade: grade.to_string(),
+                }),
+            })
+            .await
+            .unwrap();
+    }
+    clients
+        .attendance
+        .record_attendance(tokio_stream::iter(vec![AttendanceRecord {
+            student_id: "ada2".to_string(),
+            course_id: "math".to_string(),
+            check_in_time: Some(Timestamp {
+                seconds: MONDAY as i64,
+                nanos: 0,
+            }),
+            status: AttendanceStatus::Present as i32,
+        }]))
+        .await
+        .unwrap();
+    clients
+        .professors
+        .create_professor(CreateProfessorRequest {
+            professor: Some(Professor {
+                id: "knuth".to_string(),
+                name: "Donald Knuth".to_string(),
+                ..Default::default()
+            }),
+        })
+        .await
+        .unwrap();
+    clients
+        .professors
+        .assign_advisor(AssignAdvisorRequest {
+            student_id: "ada2".to_string(),
+            professor_id: "knuth".to_string(),
+        })
+        .await
+        .unwrap();
+    let duplicate = store.get("ada2").await.unwrap();
+    assert_eq!(
+        (duplicate.credits_attempted, duplicate.credits_earned),
+        (6, 6)
+    );
+
+    let (_, mut watcher) = clients.events.subscribe("").unwrap();
+    let merged = clients
+        .duplicates
+        .merge_students(merge("ada", "ada2"))
+        .await
+        .unwrap()
+        .into_inner();
+    let deleted = watcher.try_recv().unwrap();
+    assert_eq!(deleted.change_type(), ChangeType::Deleted);
+    assert_eq!(deleted.student.unwrap().id, "ada2");
+    let primary = merged.primary.unwrap();
+    assert_eq!(primary.id, "ada");
+    // The grade moved from ada2 now counts for ada
+    assert_eq!((primary.credits_attempted, primary.credits_earned), (6, 6));
+    assert_eq!(
+        (
+            merged.moved_enrollments,
+            merged.moved_grades,
+            merged.moved_attendance,
+            merged.moved_advisor
+        ),
+        (1, 1, 1, true)
+    );
+    assert_eq!(
+        merged.undo_deadline.unwrap().seconds,
+        MONDAY as i64 + 60 * 60
+    );
+    assert_eq!(store.get("ada2").await.unwrap_err().code(), Code::NotFound);
+
+    let roster = |course_id: &str| ListEnrollmentsRequest {
+        course_id: course_id.to_string(),
+    };
+    let student_ids = |enrollments: &[proto::Enrollment]| -> Vec<String> {
+        enrollments
+            .iter()
+            .map(|enrollment| enrollment.student_id.clone())
+            .collect()
+    };
+    let math = clients
+        .enrollment
+        .list_enrollments(roster("math"))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(student_ids(&math.enrolled), vec!["ada"]);
+    assert_eq!(student_ids(&math.waitlisted), vec!["alan"]);
+    // ada already had art, so ada2's place in line is gone
+    let art = clients
+        .enrollment
+        .list_enrollments(roster("art"))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(student_ids(&art.enrolled), vec!["ada"]);
+    assert!(art.waitlisted.is_empty());
+
+    let transcript = |student_id: &str| GetTranscriptRequest {
+        student_id: student_id.to_string(),
+    };
+    let grades = clients
+        .enrollment
+        .get_transcript(transcript("ada"))
+        .await
+        .unwrap()
+        .into_inner()
+        .entries;
+    let grades: Vec<(&str, &str)> = grades
+        .iter()
+        .map(|entry| (entry.course_id.as_str(), entry.grade.as_str()))
+        .collect();
+    assert_eq!(grades, vec![("history", "A"), ("logic", "B")]);
+    let advisor = clients
+        .professors
+        .get_advisor(GetAdvisorRequest {
+            student_id: "ada".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(advisor.professor.unwrap().id, "knuth");
+
+    let restored = clients
+        .trash
+        .undo(UndoRequest {
+            operation_id: merged.operation_id.clone(),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .student
+        .unwrap();
+    let created = watcher.try_recv().unwrap();
+    assert_eq!(created.change_type(), ChangeType::Created);
+    assert_eq!(created.student.as_ref(), Some(&restored));
+    // Restoring stores the student afresh, with a new etag
+    assert_eq!(
+        restored,
+        Student {
+            etag: restored.etag.clone(),
+            ..duplicate
+        }
+    );
+    let math = clients
+        .enrollment
+        .list_enrollments(roster("math"))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(student_ids(&math.enrolled), vec!["ada2"]);
+    let grades = clients
+        .enrollment
+        .get_transcript(transcript("ada2"))
+        .await
+        .unwrap()
+        .into_inner()
+        .entries;
+    assert_eq!(grades.len(), 1);
+    assert_eq!(grades[0].course_id, "history");
+    let records = clients
+        .attendance
+        .list_attendance(ListAttendanceRequest {
+            student_id: "ada2".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .records;
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].student_id, "ada2");
+    let status = clients
+        .professors
+        .get_advisor(GetAdvisorRequest {
+            student_id: "ada".to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    // A merge is undone once
+    let status = clients
+        .trash
+        .undo(UndoRequest {
+            operation_id: merged.operation_id,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn merges_are_checked_and_undo_expires() {
+    let store = Arc::new(InMemoryRepository::new());
+    for id in ["ada", "ada2"] {
+        store.create(student(id, id, "")).await.unwrap();
+    }
+    let clock = clock();
+    let mut clients = start(store.clone(), clock.clone()).await;
+
+    for (request, code) in [
+        (merge("ada", "ada"), Code::InvalidArgument),
+        (merge("", "ada2"), Code::InvalidArgument),
+        (merge("nobody", "ada2"), Code::NotFound),
+        (merge("ada", "nobody"), Code::NotFound),
+    ] {
+        let status = clients
+            .duplicates
+            .merge_students(request)
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), code);
+    }
+    // Failed merges leave both students alone
+    assert!(store.get("ada2").await.is_ok());
+
+    let merged = clients
+        .duplicates
+        .merge_students(merge("ada", "ada2"))
+        .await
+        .unwrap()
+        .into_inner();
+    clock.advance(Duration::from_secs(2 * 60 * 60));
+    let status = clients
+        .trash
+        .undo(UndoRequest {
+            operation_id: merged.operation_id,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    assert_eq!(store.get("ada2").await.unwrap_err().code(), Code::NotFound);
+}
@@ -0,0 +1,176 @@
+use proto::email_service_server::EmailService;
+use proto::student_service_server::StudentService;
+use proto::{ConfirmEmailChangeRequest, RequestEmailChangeRequest, Student, UpdateStudentRequest};
+use server::clock::FixedClock;
+use server::email::{EmailServiceImpl, MAX_ATTEMPTS};
+use server::notify::MockNotifier;
+use server::repository::{InMemoryRepository, StudentRepository};
+use server::StudentServiceImpl;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use tonic::{Code, Request, Status};
+
+struct Services {
+    store: Arc<InMemoryRepository>,
+    email: EmailServiceImpl,
+    sent: Arc<MockNotifier>,
+    clock: Arc<FixedClock>,
+}
+
+async fn services() -> Services {
+    let store = Arc::new(InMemoryRepository::new());
+    store
+        .create(Student {
+            id: "ada".to_string(),
+            name: "Ada".to_string(),
+            email: "ada@university.edu".to_string(),
+            age: 20,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let sent = Arc::new(MockNotifier::new());
+    let clock = Arc::new(FixedClock::new(UNIX_EPOCH + Duration::from_secs(1_000_000)));
+    let email = EmailServiceImpl::new(store.clone(), sent.clone()).with_clock(clock.clone());
+    Services {
+        store,
+        email,
+        sent,
+        clock,
+    }
+}
+
+async fn request_change(services: &Services, new_email: &str) -> Result<(), Status> {
+    services
+        .email
+        .request_email_change(Request::new(RequestEmailChangeRequest {
+            student_id: "ada".to_string(),
+            new_email: new_email.to_string(),
+        }))
+        .await
+        .map(|_| ())
+}
+
+async fn confirm(services: &Services, code: &str) -> Result<Student, Status> {
+    services
+        .email
+        .confirm_email_change(Request::new(ConfirmEmailChangeRequest {
+            student_id: "ada".to_string(),
+            code: code.to_string(),
+        }))
+        .await
+        .map(|response| response.into_inner().student.unwrap())
+}
+
+// The code in the last message sent
+fn code(services: &Services) -> String {
+    let body = services.sent.sent().pop().unwrap().body;
+    body.split(" is ").nth(1).unwrap()[..6].to_string()
+}
+
+async fn email(services: &Services) -> String {
+    services.store.get("ada").await.unwrap().email
+}
+
+#[tokio::test]
+async fn the_old_address_is_kept_until_the_code_is_confirmed() {
+    let services = services().await;
+    request_change(&services, "ada@lovelace.org").await.unwrap();
+    let sent = services.sent.sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].to, "ada@lovelace.org");
+    assert_eq!(email(&services).await, "ada@university.edu");
+
+    let code = code(&services);
+    let wrong = if code == "000000" { "111111" } else { "000000" };
+    let status = confirm(&services, wrong).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(email(&services).await, "ada@university.edu");
+
+    let before = services.store.get("ada").await.unwrap();
+    let student = confirm(&services, &code).await.unwrap();
+    assert_eq!(student.email, "ada@lovelace.org");
+    assert_ne!(student.etag, before.etag);
+    assert_eq!(email(&services).await, "ada@lovelace.org");
+
+    // Used up
+    let status = confirm(&services, &code).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn codes_expire_and_cannot_be_guessed() {
+    let services = services().await;
+    request_change(&services, "ada@lovelace.org").await.unwrap();
+    services.clock.advance(Duration::from_secs(15 * 60));
+    let status = confirm(&services, &code(&services)).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    request_change(&services, "ada@lovelace.org").await.unwrap();
+    let code = code(&services);
+    let wrong = if code == "000000" { "111111" } else { "000000" };
+    for _ in 0..MAX_ATTEMPTS {
+        let status = confirm(&services, wrong).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+    let status = confirm(&services, &code).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    assert_eq!(email(&services).await, "ada@university.edu");
+
+    for bad in ["not an address", "ADA@university.edu"] {
+        let status = request_change(&services, bad).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument, "{}", bad);
+    }
+}
+
+#[tokio::test]
+async fn nothing_waits_on_a_code_that_was_never_sent() {
+    let services = services().await;
+    services
+        .sent
+        .fail_with(Some(Status::unavailable("relay down")));
+    let status = request_change(&services, "ada@lovelace.org")
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unavailable);
+    let status = confirm(&services, "000000").await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn update_student_cannot_change_a_verified_email() {
+    let store = Arc::new(InMemoryRepository::new());
+    let students = StudentServiceImpl::new()
+        .with_repository(store.clone())
+        .with_verified_emails();
+    let mut ada = store
+        .create(Student {
+            id: "ada".to_string(),
+            name: "Ada".to_string(),
+            email: "ada@university.edu".to_string(),
+            age: 20,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    ada.email = "ada@lovelace.org".to_string();
+    let status = students
+        .update_student(Request::new(UpdateStudentRequest {
+            student: Some(ada.clone()),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+
+    ada.email = "ada@university.edu".to_string();
+    ada.age = 21;
+    let updated = students
+        .update_student(Request::new(UpdateStudentRequest { student: Some(ada) }))
+        .await
+        .unwrap()
+        .into_inner()
+        .student
+        .unwrap();
+    assert_eq!(updated.age, 21);
+}
@@ -0,0 +1,135 @@
+use proto::Student;
+use server::encryption::{self, Keyring};
+use server::operations::Operations;
+use server::repository::{InMemoryRepository, StudentRepository};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+const OLD_KEY: &str = "0000000000000000000000000000000000000000000000000000000000000001";
+const NEW_KEY: &str = "00000000000000000000000000000000000000000000000000000000000000ff";
+
+fn keys(text: &str) -> Keyring {
+    Keyring::parse(text).unwrap()
+}
+
+fn temp_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("encryption-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir(&dir).unwrap();
+    dir
+}
+
+async fn snapshot(store: Arc<InMemoryRepository>, dir: &Path, keys: Option<Keyring>) -> PathBuf {
+    let operations = Operations::new();
+    let dir = dir.to_path_buf();
+    let operation = operations.start("snapshot", move |progress| async move {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        server::snapshot::write(store.as_ref(), None, &dir, now, keys.as_ref(), &progress).await
+    });
+    let operation = operations.wait(&operation.id).await.unwrap();
+    (*operations.result::<PathBuf>(&operation.id).unwrap()).clone()
+}
+
+#[test]
+fn keyrings_need_well_formed_keys() {
+    let ring = keys(&format!(
+        "2024:{}\n# retired soon\n2023:{}",
+        NEW_KEY, OLD_KEY
+    ));
+    assert_eq!(ring.primary(), "2024");
+    assert!(!format!("{:?}", ring).contains(NEW_KEY));
+
+    assert!(Keyring::parse("").is_err());
+    assert!(Keyring::parse(NEW_KEY).is_err());
+    assert!(Keyring::parse("a:abcd").is_err());
+    assert!(Keyring::parse(&format!("a:{},a:{}", NEW_KEY, OLD_KEY)).is_err());
+    assert!(encryption::from_url("vault:students").is_err());
+}
+
+#[test]
+fn only_the_right_key_opens_sealed_data() {
+    let ring = keys(&format!("new:{}", NEW_KEY));
+    let sealed = ring.seal(b"Ada Lovelace");
+    assert_eq!(encryption::sealed_with(&sealed), Some("new"));
+    assert!(!sealed.windows(3).any(|window| window == b"Ada"));
+    assert_eq!(ring.open(&sealed).unwrap(), b"Ada Lovelace");
+    // The same data sealed twice looks different
+    assert_ne!(ring.seal(b"Ada Lovelace"), sealed);
+
+    // A ring without that key, the right name on another key, or damage
+    assert!(keys(&format!("old:{}", OLD_KEY)).open(&sealed).is_err());
+    assert!(keys(&format!("new:{}", OLD_KEY)).open(&sealed).is_err());
+    let mut damaged = sealed.clone();
+    *damaged.last_mut().unwrap() ^= 1;
+    assert!(ring.open(&damaged).is_err());
+    assert!(ring.open(b"Ada Lovelace").is_err());
+}
+
+#[tokio::test]
+async fn snapshots_are_encrypted_and_rotated() {
+    let store = Arc::new(InMemoryRepository::new());
+    for index in 0..3 {
+        store
+            .create(Student {
+                id: format!("s{}", index),
+                name: format!("Student {}", index),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+    let dir = temp_dir();
+    let old = keys(&format!("old:{}", OLD_KEY));
+
+    let path = snapshot(store.clone(), &dir, Some(old.clone())).await;
+    assert_eq!(path, dir.join("students-1700000000.jsonl.enc"));
+    let data = std::fs::read(&path).unwrap();
+    assert_eq!(encryption::sealed_with(&data), Some("old"));
+    assert!(server::snapshot::read(&path, None).is_err());
+    let students = server::snapshot::read(&path, Some(&old)).unwrap();
+    assert_eq!(students.len(), 3);
+    assert_eq!(students[1], store.get("s1").await.unwrap());
+
+    // Rotate: a new key first, the old one kept to read with
+    std::fs::write(dir.join("notes.txt"), "not a snapshot").unwrap();
+    let rotated = keys(&format!("new:{},old:{}", NEW_KEY, OLD_KEY));
+    assert_eq!(server::snapshot::reencrypt(&dir, &rotated).unwrap(), 1);
+    assert_eq!(server::snapshot::reencrypt(&dir, &rotated).unwrap(), 0);
+    let new = keys(&format!("new:{}", NEW_KEY));
+    assert_eq!(server::snapshot::read(&path, Some(&new)).unwrap(), students);
+    assert!(server::snapshot::read(&path, Some(&old)).is_err());
+    assert_eq!(
+        std::fs::read_to_string(dir.join("notes.txt")).unwrap(),
+        "not a snapshot"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn plain_snapshots_can_be_encrypted_later() {
+    let store = Arc::new(InMemoryRepository::new());
+    store
+        .create(Student {
+            id: "ada".to_string(),
+            name: "Ada".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let dir = temp_dir();
+
+    let plain = snapshot(store, &dir, None).await;
+    let students = server::snapshot::read(&plain, None).unwrap();
+    let ring = keys(&format!("new:{}", NEW_KEY));
+    assert_eq!(server::snapshot::reencrypt(&dir, &ring).unwrap(), 1);
+
+    // Only the encrypted file and the manifest are left
+    assert!(!plain.exists());
+    let encrypted = dir.join("students-1700000000.jsonl.enc");
+    assert_eq!(
+        server::snapshot::read(&encrypted, Some(&ring)).unwrap(),
+        students
+    );
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,228 @@
+use proto::enrollment_service_client::EnrollmentServiceClient;
+use proto::enrollment_service_server::EnrollmentServiceServer;
+use proto::{
+    Course, CreateCourseRequest, DropCourseRequest, EnrollRequest, Enrollment, EnrollmentStatus,
+    GetTranscriptRequest, ListEnrollmentsRequest, RecordGradeRequest, Student, TranscriptEntry,
+};
+use server::enrollment::{
+    EnrollmentServiceImpl, ALREADY_ENROLLED, PREREQUISITE_NOT_MET, WAITLIST_FULL,
+};
+use server::repository::{InMemoryRepository, StudentRepository};
+use std::sync::Arc;
+use tonic::transport::{Channel, Server};
+use tonic::{Code, Status};
+
+mod common;
+
+async fn start(students: &[&str]) -> EnrollmentServiceClient<Channel> {
+    let repository = Arc::new(InMemoryRepository::new());
+    for id in students {
+        repository
+            .create(Student {
+                id: id.to_string(),
+                name: id.to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+
+    let url = common::serve(|incoming| {
+        Server::builder()
+            .add_service(EnrollmentServiceServer::new(EnrollmentServiceImpl::new(
+                repository,
+            )))
+            .serve_with_incoming(incoming)
+    })
+    .await;
+    EnrollmentServiceClient::connect(url).await.unwrap()
+}
+
+async fn create_course(
+    client: &mut EnrollmentServiceClient<Channel>,
+    id: &str,
+    capacity: i32,
+    waitlist_capacity: i32,
+    prerequisites: &[&str],
+) {
+    client
+        .create_course(CreateCourseRequest {
+            course: Some(Course {
+                id: id.to_string(),
+                title: id.to_uppercase(),
+                capacity,
+                waitlist_capacity,
+                prerequisite_ids: prerequisites.iter().map(|id| id.to_string()).collect(),
+                credits: 3,
+            }),
+        })
+        .await
+        .unwrap();
+}
+
+async fn enroll(
+    client: &mut EnrollmentServiceClient<Channel>,
+    student_id: &str,
+    course_id: &str,
+) -> Result<Enrollment, Status> {
+    let response = client
+        .enroll(EnrollRequest {
+            student_id: student_id.to_string(),
+            course_id: course_id.to_string(),
+        })
+        .await?;
+    Ok(response.into_inner().enrollment.unwrap())
+}
+
+// (type, subject) of every violation in a FAILED_PRECONDITION status
+fn violations(status: &Status) -> Vec<(String, String)> {
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    client::precondition_failure(status)
+        .expect("status has no PreconditionFailure")
+        .violations
+        .into_iter()
+        .map(|violation| (violation.r#type, violation.subject))
+        .collect()
+}
+
+async fn record_grade(
+    client: &mut EnrollmentServiceClient<Channel>,
+    student_id: &str,
+    course_id: &str,
+    grade: &str,
+) {
+    client
+        .record_grade(RecordGradeRequest {
+            student_id: student_id.to_string(),
+            entry: Some(TranscriptEntry {
+                course_id: course_id.to_string(),
+                grade: grade.to_string(),
+            }),
+        })
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn full_courses_waitlist_and_promote_on_drop() {
+    let mut client = start(&["ada", "grace", "alan"]).await;
+    create_course(&mut client, "cs101", 1, 1, &[]).await;
+
+    let ada = enroll(&mut client, "ada", "cs101").await.unwrap();
+    assert_eq!(ada.status(), EnrollmentStatus::Enrolled);
+    let grace = enroll(&mut client, "grace", "cs101").await.unwrap();
+    assert_eq!(grace.status(), EnrollmentStatus::Waitlisted);
+    assert_eq!(grace.waitlist_position, 1);
+
+    let status = enroll(&mut client, "alan", "cs101").await.unwrap_err();
+    assert_eq!(
+        violations(&status),
+        vec![(WAITLIST_FULL.to_string(), "courses/cs101".to_string())]
+    );
+
+    let dropped = client
+        .drop_course(DropCourseRequest {
+            student_id: "ada".to_string(),
+            course_id: "cs101".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    let promoted = dropped.promoted.unwrap();
+    assert_eq!(promoted.student_id, "grace");
+    assert_eq!(promoted.status(), EnrollmentStatus::Enrolled);
+
+    let roster = client
+        .list_enrollments(ListEnrollmentsRequest {
+            course_id: "cs101".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(roster.enrolled, vec![promoted]);
+    assert!(roster.waitlisted.is_empty());
+}
+
+#[tokio::test]
+async fn prerequisites_come_from_the_transcript() {
+    let mut client = start(&["ada"]).await;
+    create_course(&mut client, "cs101", 10, 0, &[]).await;
+    create_course(&mut client, "math101", 10, 0, &[]).await;
+    create_course(&mut client, "cs201", 10, 0, &["cs101", "math101"]).await;
+
+    // Every missing prerequisite is reported
+    let status = enroll(&mut client, "ada", "cs201").await.unwrap_err();
+    assert_eq!(
+        violations(&status),
+        vec![
+            (
+                PREREQUISITE_NOT_MET.to_string(),
+                "courses/cs101".to_string()
+            ),
+            (
+                PREREQUISITE_NOT_MET.to_string(),
+                "courses/math101".to_string()
+            ),
+        ]
+    );
+
+    // A failing grade does not count
+    record_grade(&mut client, "ada", "cs101", "F").await;
+    record_grade(&mut client, "ada", "math101", "B+").await;
+    let status = enroll(&mut client, "ada", "cs201").await.unwrap_err();
+    assert_eq!(
+        violations(&status),
+        vec![(
+            PREREQUISITE_NOT_MET.to_string(),
+            "courses/cs101".to_string()
+        )]
+    );
+
+    record_grade(&mut client, "ada", "cs101", "A").await;
+    let enrollment = enroll(&mut client, "ada", "cs201").await.unwrap();
+    assert_eq!(enrollment.status(), EnrollmentStatus::Enrolled);
+
+    let status = enroll(&mut client, "ada", "cs201").await.unwrap_err();
+    assert_eq!(
+        violations(&status),
+        vec![(ALREADY_ENROLLED.to_string(), "students/ada".to_string())]
+    );
+
+    let transcript = client
+        .get_transcript(GetTranscriptRequest {
+            student_id: "ada".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    let grades: Vec<_> = transcript
+        .entries
+        .iter()
+        .map(|entry| (entry.course_id.as_str(), entry.grade.as_str()))
+        .collect();
+    assert_eq!(grades, vec![("cs101", "A"), ("math101", "B+")]);
+}
+
+#[tokio::test]
+async fn unknown_students_and_courses() {
+    let mut client = start(&["ada"]).await;
+    create_course(&mut client, "cs101", 10, 0, &[]).await;
+
+    let status = enroll(&mut client, "nobody", "cs101").await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    let status = enroll(&mut client, "ada", "cs999").await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    let status = client
+        .create_course(CreateCourseRequest {
+            course: Some(Course {
+                title: "Compilers".to_string(),
+                capacity: 10,
+                prerequisite_ids: vec!["cs999".to_string()],
+                ..Default::default()
+            }),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
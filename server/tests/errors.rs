@@ -0,0 +1,106 @@
+use client::{ErrorKind, StudentClient};
+use proto::enrollment_service_server::EnrollmentService;
+use proto::student_service_server::StudentServiceServer;
+use proto::{GetCourseRequest, Student};
+use server::enrollment::EnrollmentServiceImpl;
+use server::errors::{Error, ERROR_DOMAIN};
+use server::repository::InMemoryRepository;
+use server::StudentServiceImpl;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::Server;
+use tonic::{Code, Request, Status};
+
+async fn start() -> StudentClient {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .add_service(StudentServiceServer::new(StudentServiceImpl::new()))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    StudentClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn errors_carry_their_code_in_error_info() {
+    let service = EnrollmentServiceImpl::new(Arc::new(InMemoryRepository::new()));
+    let status = service
+        .get_course(Request::new(GetCourseRequest {
+            id: "cs999".to_string(),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    assert_eq!(status.message(), "Course not found");
+    let info = client::error_info(&status).unwrap();
+    assert_eq!(info.reason, "COURSE_NOT_FOUND");
+    assert_eq!(info.domain, ERROR_DOMAIN);
+}
+
+#[test]
+fn the_values_in_a_message_are_sent_as_metadata() {
+    let error = Error::QuotaExceeded("acme".to_string(), 100);
+    assert_eq!(error.code(), "QUOTA_EXCEEDED");
+    assert_eq!(error.status_code(), Code::ResourceExhausted);
+
+    let status = Status::from(error.clone());
+    assert_eq!(status.code(), Code::ResourceExhausted);
+    assert_eq!(status.message(), error.to_string());
+    let info = client::error_info(&status).unwrap();
+    assert_eq!(info.reason, "QUOTA_EXCEEDED");
+    assert_eq!(
+        info.metadata,
+        HashMap::from([
+            ("tenant".to_string(), "acme".to_string()),
+            ("limit".to_string(), "100".to_string()),
+        ])
+    );
+}
+
+#[tokio::test]
+async fn the_client_reads_statuses_into_typed_errors() {
+    let mut client = start().await;
+
+    let invalid = Student {
+        id: "s1".to_string(),
+        age: 20,
+        ..Default::default()
+    };
+    let error = client::Error::from(client.create_student(invalid).await.unwrap_err());
+    assert!(error.is_invalid());
+    assert!(error.is("NAME_EMPTY"));
+    assert!(error.is_invalid_email());
+    let fields: Vec<_> = error
+        .field_violations()
+        .iter()
+        .map(|violation| violation.field.as_str())
+        .collect();
+    assert_eq!(fields, ["name", "email"]);
+    assert_eq!(
+        error.to_string(),
+        "InvalidArgument: Student name cannot be empty\n   name: Student name cannot be empty\n   email: Student email cannot be empty"
+    );
+
+    let error = client::Error::from(client.get_student("nobody").await.unwrap_err());
+    assert!(error.is_not_found());
+    assert!(error.is("STUDENT_NOT_FOUND"));
+    assert_eq!(error.to_string(), "NotFound: Student not found");
+
+    let status = Status::from(Error::QuotaExceeded("acme".to_string(), 100));
+    assert_eq!(
+        client::Error::from(status).kind(),
+        &ErrorKind::QuotaExceeded {
+            tenant: "acme".to_string(),
+            limit: 100,
+        }
+    );
+    // A status without details still has a kind
+    let error = client::Error::from(Status::unavailable("Connection refused"));
+    assert_eq!(error.kind(), &ErrorKind::Unavailable);
+    assert_eq!(error.reason(), None);
+}
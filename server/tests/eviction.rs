@@ -0,0 +1,122 @@
+use proto::{ChangeType, Student};
+use server::clock::FixedClock;
+use server::events::EventLog;
+use server::eviction::{EvictingRepository, Eviction};
+use server::repository::{InMemoryRepository, StudentRepository};
+use server::standing::StandingRules;
+use server::statistics::{CountedRepository, Statistics};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tonic::Code;
+
+fn student(id: &str) -> Student {
+    Student {
+        id: id.to_string(),
+        name: id.to_string(),
+        email: format!("{}@university.edu", id),
+        age: 20,
+        ..Default::default()
+    }
+}
+
+async fn ids(store: &EvictingRepository) -> Vec<String> {
+    store
+        .all()
+        .await
+        .unwrap()
+        .iter()
+        .map(|student| student.id.clone())
+        .collect()
+}
+
+#[tokio::test]
+async fn students_expire_after_their_last_write() {
+    let clock = Arc::new(FixedClock::new(SystemTime::UNIX_EPOCH));
+    let statistics = Arc::new(Statistics::new(StandingRules::default()));
+    let counted = CountedRepository::new(Arc::new(InMemoryRepository::new()), statistics.clone());
+    let events = Arc::new(EventLog::new());
+    let store = EvictingRepository::new(
+        Arc::new(counted),
+        Eviction {
+            ttl: Some(Duration::from_secs(3600)),
+            max_students: None,
+        },
+    )
+    .with_clock(clock.clone())
+    .with_events(events.clone());
+    let (_, mut watcher) = events.subscribe("").unwrap();
+
+    store.create(student("ada")).await.unwrap();
+    clock.advance(Duration::from_secs(40 * 60));
+    store.create(student("alan")).await.unwrap();
+    clock.advance(Duration::from_secs(30 * 60));
+
+    // Ada was written 70 minutes ago; reading does not keep her
+    let status = store.get("ada").await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    assert_eq!(ids(&store).await, ["alan"]);
+    assert_eq!(statistics.get().student_count, 1);
+    let event = watcher.recv().await.unwrap();
+    assert_eq!(event.change_type, ChangeType::Deleted as i32);
+    assert_eq!(event.student.unwrap().id, "ada");
+
+    // An update starts Alan's hour again
+    let alan = store.get("alan").await.unwrap();
+    store.update(alan).await.unwrap();
+    clock.advance(Duration::from_secs(50 * 60));
+    assert_eq!(ids(&store).await, ["alan"]);
+    clock.advance(Duration::from_secs(20 * 60));
+    assert!(ids(&store).await.is_empty());
+}
+
+#[tokio::test]
+async fn the_least_recently_used_students_make_room() {
+    let store = EvictingRepository::new(
+        Arc::new(InMemoryRepository::new()),
+        Eviction {
+            ttl: None,
+            max_students: Some(3),
+        },
+    );
+    for id in ["a", "b", "c"] {
+        store.create(student(id)).await.unwrap();
+    }
+    store.get_shared("a").await.unwrap();
+
+    store.create(student("d")).await.unwrap();
+    assert_eq!(ids(&store).await, ["a", "c", "d"]);
+
+    let results = store
+        .create_many(vec![student("e"), student("d"), student("f")])
+        .await;
+    assert_eq!(results[1].as_ref().unwrap_err().code(), Code::AlreadyExists);
+    assert_eq!(ids(&store).await, ["d", "e", "f"]);
+
+    // Deleting makes room without evicting anyone
+    store.delete("e").await.unwrap();
+    store.create(student("g")).await.unwrap();
+    assert_eq!(ids(&store).await, ["d", "f", "g"]);
+}
+
+#[tokio::test]
+async fn students_stored_before_the_wrapper_are_tracked() {
+    let clock = Arc::new(FixedClock::new(SystemTime::UNIX_EPOCH));
+    let inner = Arc::new(InMemoryRepository::new());
+    for id in ["a", "b"] {
+        inner.create(student(id)).await.unwrap();
+    }
+    let store = EvictingRepository::new(
+        inner,
+        Eviction {
+            ttl: Some(Duration::from_secs(60)),
+            max_students: Some(2),
+        },
+    )
+    .with_clock(clock.clone());
+    store.track_existing().await.unwrap();
+
+    store.create(student("c")).await.unwrap();
+    assert_eq!(ids(&store).await, ["b", "c"]);
+    clock.advance(Duration::from_secs(61));
+    assert!(ids(&store).await.is_empty());
+}
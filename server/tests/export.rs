@@ -0,0 +1,123 @@
+use arrow_array::{Array, Decimal128Array, RecordBatch, StringArray, StructArray};
+use arrow_ipc::reader::FileReader;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use proto::storage_service_server::StorageService;
+use proto::{Address, ExportFormat, ExportStudentsRequest, Student};
+use server::export;
+use server::operations::Operations;
+use server::repository::{InMemoryRepository, StudentRepository};
+use server::storage::StorageServiceImpl;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+use tonic::Request;
+
+async fn store() -> Arc<InMemoryRepository> {
+    let students = (1..=250).map(|n| Student {
+        id: format!("s{:03}", n),
+        name: format!("Student {}", n),
+        email: format!("s{}@university.edu", n),
+        age: 20,
+        gpa_decimal: "3.25".to_string(),
+        phone_numbers: vec!["+12025550123".to_string()],
+        address: (n % 2 == 0).then(|| Address {
+            city: "Springfield".to_string(),
+            ..Default::default()
+        }),
+        annotations: HashMap::from([("batch".to_string(), n.to_string())]),
+        ..Default::default()
+    });
+    let store = InMemoryRepository::new();
+    for result in store.create_many(students.collect()).await {
+        result.unwrap();
+    }
+    Arc::new(store)
+}
+
+fn temp_path(extension: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("export-{}.{}", uuid::Uuid::new_v4(), extension))
+}
+
+fn read_parquet(path: &Path) -> Vec<RecordBatch> {
+    ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap())
+        .unwrap()
+        .build()
+        .unwrap()
+        .map(Result::unwrap)
+        .collect()
+}
+
+fn column<'a, T: 'static>(batch: &'a RecordBatch, name: &str) -> &'a T {
+    batch
+        .column_by_name(name)
+        .unwrap()
+        .as_any()
+        .downcast_ref()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn students_are_written_as_parquet_and_arrow_files() {
+    let path = temp_path("parquet");
+    let format = export::format_for(&path).unwrap();
+    let count = export::write(store().await, format, &path).await.unwrap();
+    assert_eq!(count, 250);
+    let batches = read_parquet(&path);
+    assert_eq!(batches.iter().map(RecordBatch::num_rows).sum::<usize>(), 250);
+    let batch = &batches[0];
+    assert_eq!(batch.schema(), export::schema());
+    assert_eq!(column::<StringArray>(batch, "id").value(1), "s002");
+    let gpas = column::<Decimal128Array>(batch, "gpa");
+    assert_eq!(gpas.value_as_string(1), "3.25");
+    let addresses = column::<StructArray>(batch, "address");
+    assert!(addresses.is_null(0));
+    let cities = addresses
+        .column_by_name("city")
+        .unwrap()
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .unwrap();
+    assert_eq!(cities.value(1), "Springfield");
+    std::fs::remove_file(path).unwrap();
+
+    let path = temp_path("arrow");
+    let format = export::format_for(&path).unwrap();
+    assert_eq!(format, ExportFormat::Arrow);
+    export::write(store().await, format, &path).await.unwrap();
+    let reader = FileReader::try_new(File::open(&path).unwrap(), None).unwrap();
+    assert_eq!(reader.schema(), export::schema());
+    let rows: usize = reader.map(|batch| batch.unwrap().num_rows()).sum();
+    assert_eq!(rows, 250);
+    std::fs::remove_file(path).unwrap();
+
+    assert!(export::format_for(Path::new("students.csv")).is_err());
+}
+
+#[tokio::test]
+async fn the_server_streams_an_export() {
+    let service = StorageServiceImpl::new(store().await, Operations::new());
+    let mut chunks = service
+        .export_students(Request::new(ExportStudentsRequest {
+            format: ExportFormat::Unspecified.into(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    let mut file = Vec::new();
+    let mut content_types = Vec::new();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk.unwrap();
+        file.extend(chunk.data);
+        content_types.push(chunk.content_type);
+    }
+    assert_eq!(content_types[0], "application/vnd.apache.parquet");
+    assert!(content_types[1..].iter().all(String::is_empty));
+
+    let path = temp_path("parquet");
+    std::fs::write(&path, file).unwrap();
+    let batches = read_parquet(&path);
+    assert_eq!(batches.iter().map(RecordBatch::num_rows).sum::<usize>(), 250);
+    std::fs::remove_file(path).unwrap();
+}
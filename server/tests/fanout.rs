@@ -0,0 +1,138 @@
+use client::{FanOut, Target};
+use proto::statistics_service_server::StatisticsServiceServer;
+use proto::student_service_server::StudentServiceServer;
+use proto::Student;
+use server::flags::TenantLayer;
+use server::quota::{Limits, QuotaRepository, Quotas};
+use server::repository::InMemoryRepository;
+use server::standing::StandingRules;
+use server::statistics::{CountedRepository, Statistics, StatisticsServiceImpl};
+use server::StudentServiceImpl;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::Server;
+use tonic::Code;
+
+// A server counting students per tenant; returns its address
+async fn start(quotas: Arc<Quotas>) -> String {
+    let statistics = Arc::new(Statistics::new(StandingRules::default()));
+    let store = QuotaRepository::new(Arc::new(InMemoryRepository::new()), quotas);
+    let store = CountedRepository::new(Arc::new(store), statistics.clone());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .layer(TenantLayer)
+            .add_service(StudentServiceServer::new(
+                StudentServiceImpl::new().with_repository(Arc::new(store)),
+            ))
+            .add_service(StatisticsServiceServer::new(StatisticsServiceImpl::new(
+                statistics,
+            )))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    addr.to_string()
+}
+
+#[tokio::test]
+async fn every_target_is_called_and_failures_are_reported_together() {
+    let quotas = Arc::new(Quotas::new(Limits::default()));
+    let first = start(quotas.clone()).await;
+    let second = start(Arc::new(Quotas::new(Limits::default()))).await;
+    let targets: Vec<Target> = [
+        format!("acme@{}", first),
+        format!("globex@{}", first),
+        format!("http://{}", second),
+        "127.0.0.1:1".to_string(),
+        "not an endpoint".to_string(),
+    ]
+    .iter()
+    .map(|target| target.parse().unwrap())
+    .collect();
+
+    let report = FanOut::new(targets.clone())
+        .with_concurrency(2)
+        .run(|mut client, target| async move {
+            let id = target.tenant.unwrap_or_else(|| "anyone".to_string());
+            let student = Student {
+                id: id.clone(),
+                name: format!("Student {}", id),
+                email: format!("{}@university.edu", id),
+                ..Default::default()
+            };
+            client.create_student(student).await?;
+            client.get_statistics().await
+        })
+        .await;
+
+    let called: Vec<&Target> = report.results.iter().map(|(target, _)| target).collect();
+    assert_eq!(called, targets.iter().collect::<Vec<_>>());
+    assert_eq!(report.successes().count(), 3);
+    let counts: Vec<i64> = report
+        .successes()
+        .map(|(_, statistics)| statistics.student_count)
+        .collect();
+    assert!(counts.iter().all(|count| (1..=2).contains(count)));
+    // Each tenant's create counted against that tenant
+    assert_eq!(quotas.usage("acme").student_count, 1);
+    assert_eq!(quotas.usage("globex").student_count, 1);
+
+    let failures: Vec<(String, Code)> = report
+        .failures()
+        .map(|(target, status)| (target.to_string(), status.code()))
+        .collect();
+    assert_eq!(
+        failures,
+        [
+            ("127.0.0.1:1".to_string(), Code::Unavailable),
+            ("not an endpoint".to_string(), Code::InvalidArgument),
+        ]
+    );
+    assert!(!report.is_success());
+    let error = report.into_result().unwrap_err();
+    assert!(error
+        .to_string()
+        .starts_with("2 of 5 targets failed\n  127.0.0.1:1: Unavailable"));
+}
+
+#[tokio::test]
+async fn targets_are_called_a_few_at_a_time_and_given_up_on() {
+    let targets = (0..6).map(|n| Target::new("127.0.0.1:9").with_tenant(&format!("t{}", n)));
+    let (running, most) = (AtomicUsize::new(0), AtomicUsize::new(0));
+    let report = FanOut::new(targets)
+        .with_concurrency(2)
+        .with_timeout(Duration::from_millis(200))
+        .run(|_, target| {
+            let (running, most) = (&running, &most);
+            async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(now, Ordering::SeqCst);
+                let slow = target.tenant.as_deref() == Some("t5");
+                let wait = if slow { 10_000 } else { 20 };
+                tokio::time::sleep(Duration::from_millis(wait)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                Ok(())
+            }
+        })
+        .await;
+    assert_eq!(most.load(Ordering::SeqCst), 2);
+    let failures: Vec<(String, Code)> = report
+        .failures()
+        .map(|(target, status)| (target.to_string(), status.code()))
+        .collect();
+    assert_eq!(
+        failures,
+        [("t5@127.0.0.1:9".to_string(), Code::DeadlineExceeded)]
+    );
+
+    assert_eq!(
+        "acme@students.example.edu:50051".parse(),
+        Ok(Target::new("students.example.edu:50051").with_tenant("acme"))
+    );
+    for bad in ["", "acme@", "@students.example.edu:50051"] {
+        assert!(bad.parse::<Target>().is_err(), "{:?}", bad);
+    }
+}
@@ -0,0 +1,158 @@
+use proto::feature_flag_service_client::FeatureFlagServiceClient;
+use proto::feature_flag_service_server::FeatureFlagServiceServer;
+use proto::student_service_client::StudentServiceClient;
+use proto::student_service_server::StudentServiceServer;
+use proto::{CreateStudentRequest, ListFeatureFlagsRequest, SetFeatureFlagRequest, Student};
+use server::flags::{
+    parse_setting, FeatureFlagServiceImpl, FeatureFlags, TenantLayer, STRICT_VALIDATION,
+};
+use server::StudentServiceImpl;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::{Channel, Server};
+use tonic::{Code, Request, Status};
+
+#[test]
+fn tenants_can_be_set_apart() {
+    let flags = FeatureFlags::new();
+    assert!(!flags.enabled_for(STRICT_VALIDATION, None));
+
+    flags.set(STRICT_VALIDATION, None, true).unwrap();
+    flags.set(STRICT_VALIDATION, Some("acme"), false).unwrap();
+    assert!(flags.enabled_for(STRICT_VALIDATION, None));
+    assert!(flags.enabled_for(STRICT_VALIDATION, Some("globex")));
+    assert!(!flags.enabled_for(STRICT_VALIDATION, Some("acme")));
+
+    flags.clear(STRICT_VALIDATION, "acme").unwrap();
+    assert!(flags.enabled_for(STRICT_VALIDATION, Some("acme")));
+    assert_eq!(
+        flags.set("v2-api", None, true).unwrap_err().code(),
+        Code::NotFound
+    );
+    assert!(!flags.enabled_for("v2-api", None));
+
+    assert_eq!(
+        parse_setting("strict-validation@acme=on"),
+        Ok((
+            STRICT_VALIDATION.to_string(),
+            Some("acme".to_string()),
+            true
+        ))
+    );
+    assert_eq!(
+        parse_setting("strict-validation=off"),
+        Ok((STRICT_VALIDATION.to_string(), None, false))
+    );
+    for bad in ["strict-validation", "strict-validation=maybe", "v2-api=on"] {
+        assert!(parse_setting(bad).is_err(), "{}", bad);
+    }
+}
+
+async fn start() -> (
+    StudentServiceClient<Channel>,
+    FeatureFlagServiceClient<Channel>,
+) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let flags = FeatureFlags::new();
+    tokio::spawn(
+        Server::builder()
+            .layer(TenantLayer)
+            .add_service(StudentServiceServer::new(
+                StudentServiceImpl::new().with_feature_flags(flags.clone()),
+            ))
+            .add_service(FeatureFlagServiceServer::new(FeatureFlagServiceImpl::new(
+                flags,
+            )))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    let channel = Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    (
+        StudentServiceClient::new(channel.clone()),
+        FeatureFlagServiceClient::new(channel),
+    )
+}
+
+// An email lenient validation tidies up and strict validation refuses
+async fn create(
+    client: &mut StudentServiceClient<Channel>,
+    tenant: Option<&str>,
+) -> Result<Student, Status> {
+    let mut request = Request::new(CreateStudentRequest {
+        student: Some(Student {
+            name: "Ada Lovelace".to_string(),
+            email: "ADA@University.edu".to_string(),
+            age: 20,
+            ..Default::default()
+        }),
+    });
+    if let Some(tenant) = tenant {
+        request
+            .metadata_mut()
+            .insert("tenant", tenant.parse().unwrap());
+    }
+    Ok(client
+        .create_student(request)
+        .await?
+        .into_inner()
+        .student
+        .unwrap())
+}
+
+#[tokio::test]
+async fn strict_validation_is_rolled_out_by_tenant() {
+    let (mut students, mut flags) = start().await;
+    let set = |tenant: &str, enabled: bool, clear: bool| SetFeatureFlagRequest {
+        name: STRICT_VALIDATION.to_string(),
+        tenant: tenant.to_string(),
+        enabled,
+        clear,
+    };
+
+    let flag = flags
+        .set_feature_flag(set("acme", true, false))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!flag.enabled);
+    assert_eq!(flag.tenants.get("acme"), Some(&true));
+
+    let status = create(&mut students, Some("acme")).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let created = create(&mut students, Some("globex")).await.unwrap();
+    assert_eq!(created.email, "ada@university.edu");
+    assert!(create(&mut students, None).await.is_ok());
+
+    // Cleared, acme follows everyone again
+    flags
+        .set_feature_flag(set("acme", false, true))
+        .await
+        .unwrap();
+    assert!(create(&mut students, Some("acme")).await.is_ok());
+    let listed = flags
+        .list_feature_flags(ListFeatureFlagsRequest {})
+        .await
+        .unwrap()
+        .into_inner()
+        .flags;
+    assert_eq!(listed.len(), 1);
+    assert!(listed[0].tenants.is_empty());
+
+    let status = flags
+        .set_feature_flag(set("", false, true))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let status = flags
+        .set_feature_flag(SetFeatureFlagRequest {
+            name: "v2-api".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
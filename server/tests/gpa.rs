@@ -0,0 +1,159 @@
+use proto::student_service_server::StudentService;
+use proto::{CreateStudentRequest, GetStudentRequest, Student, UpdateStudentRequest};
+use server::gpa::{self, Gpa};
+use server::locale::Text;
+use server::validation::{self, Profile};
+use server::StudentServiceImpl;
+use student_core::Problem;
+use tonic::{Code, Request};
+
+fn student(gpa: f64, gpa_decimal: &str) -> Student {
+    Student {
+        id: "s1".to_string(),
+        name: "Ada Lovelace".to_string(),
+        email: "ada@university.edu".to_string(),
+        age: 20,
+        gpa,
+        gpa_decimal: gpa_decimal.to_string(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn decimals_round_half_up_to_hundredths() {
+    assert_eq!(Gpa::round("3.5"), Some(Gpa::from_hundredths(350)));
+    assert_eq!(Gpa::round(" .125 "), Some(Gpa::from_hundredths(13)));
+    assert_eq!(Gpa::round("3.9949"), Some(Gpa::from_hundredths(399)));
+    assert_eq!(Gpa::round("-0.5"), Some(Gpa::from_hundredths(-50)));
+    for bad in ["", ".", "3,5", "1e2", "NaN", "three"] {
+        assert_eq!(Gpa::round(bad), None, "{:?}", bad);
+    }
+
+    // The double nearest 1.005 is just below it, but it prints as 1.005
+    assert_eq!((1.005_f64 * 100.0).round(), 100.0);
+    assert_eq!(Gpa::from_f64(1.005), Some(Gpa::from_hundredths(101)));
+    assert_eq!(Gpa::from_f64(f64::INFINITY), None);
+
+    assert_eq!("3.50".parse(), Ok(Gpa::from_hundredths(350)));
+    assert_eq!("3.500".parse(), Ok(Gpa::from_hundredths(350)));
+    assert!("3.505".parse::<Gpa>().is_err());
+    assert_eq!(Gpa::from_hundredths(305).to_string(), "3.05");
+    assert_eq!(Gpa::from_hundredths(-5).to_string(), "-0.05");
+    assert_eq!(Gpa::from_hundredths(386).to_f64(), 3.86);
+}
+
+#[test]
+fn the_decimal_takes_precedence_over_the_double() {
+    let mut both = student(1.0, " 3.456 ");
+    assert!(Profile::Lenient.check(&mut both).is_empty());
+    assert_eq!(both.gpa_decimal, "3.46");
+    assert_eq!(both.gpa, 3.46);
+
+    let mut v1 = student(1.005, "");
+    assert!(Profile::Lenient.check(&mut v1).is_empty());
+    assert_eq!(v1.gpa_decimal, "1.01");
+
+    // Strict takes any exact value, and keeps both forms in step
+    let mut exact = student(0.0, "3.5");
+    assert!(Profile::Strict.check(&mut exact).is_empty());
+    assert_eq!((exact.gpa_decimal.as_str(), exact.gpa), ("3.50", 3.5));
+    assert_eq!(
+        Profile::Strict.check(&mut student(0.0, "3.456")),
+        [("gpa_decimal", Problem::GpaNotCanonical)]
+    );
+    assert_eq!(
+        Profile::Strict.check(&mut student(3.456, "")),
+        [("gpa", Problem::GpaNotCanonical)]
+    );
+
+    assert_eq!(
+        validation::problems(&student(3.0, "4.01")),
+        [("gpa_decimal", Text::GpaOutOfRange)]
+    );
+    assert_eq!(
+        validation::problems(&student(3.0, "A-")),
+        [("gpa_decimal", Text::GpaInvalid("A-".to_string()))]
+    );
+    assert_eq!(Text::GpaInvalid("A-".to_string()).metadata()["gpa"], "A-");
+    assert_eq!(
+        validation::problems(&student(f64::NAN, "")),
+        [("gpa", Text::GpaInvalid("NaN".to_string()))]
+    );
+}
+
+#[tokio::test]
+async fn v1_clients_still_send_and_get_the_double() {
+    let service = StudentServiceImpl::new();
+    let created = service
+        .create_student(Request::new(CreateStudentRequest {
+            student: Some(student(3.1, "")),
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .student
+        .unwrap();
+    assert_eq!((created.gpa_decimal.as_str(), created.gpa), ("3.10", 3.1));
+    assert_eq!(gpa::of(&created), Gpa::from_hundredths(310));
+
+    // A v1 client's update carries no decimal, so its double counts
+    let mut update = created.clone();
+    update.gpa_decimal.clear();
+    update.gpa = 3.25;
+    service
+        .update_student(Request::new(UpdateStudentRequest {
+            student: Some(update),
+        }))
+        .await
+        .unwrap();
+    let fetched = service
+        .get_student(Request::new(GetStudentRequest {
+            id: "s1".to_string(),
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .student
+        .unwrap();
+    assert_eq!((fetched.gpa_decimal.as_str(), fetched.gpa), ("3.25", 3.25));
+
+    // Either form may change while the other comes back as it was read
+    let fetched = Student {
+        etag: String::new(),
+        ..fetched
+    };
+    for (gpa, gpa_decimal, stored) in [(3.5, "3.25", "3.50"), (3.5, "3.75", "3.75")] {
+        let updated = service
+            .update_student(Request::new(UpdateStudentRequest {
+                student: Some(Student {
+                    gpa,
+                    gpa_decimal: gpa_decimal.to_string(),
+                    ..fetched.clone()
+                }),
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .student
+            .unwrap();
+        assert_eq!(updated.gpa_decimal, stored);
+        service
+            .update_student(Request::new(UpdateStudentRequest {
+                student: Some(fetched.clone()),
+            }))
+            .await
+            .unwrap();
+    }
+
+    let status = service
+        .create_student(Request::new(CreateStudentRequest {
+            student: Some(Student {
+                id: "s2".to_string(),
+                ..student(0.0, "3.2.1")
+            }),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(client::error_info(&status).unwrap().reason, "GPA_INVALID");
+}
@@ -0,0 +1,131 @@
+use proto::grpc::health::v1::health_check_response::ServingStatus;
+use proto::grpc::health::v1::health_server::Health as HealthService;
+use proto::grpc::health::v1::HealthCheckRequest;
+use server::channelz::Channelz;
+use server::health::{
+    self, Health, HealthServiceImpl, MaintenanceFile, ReadinessCheck, StorageCheck,
+};
+use server::metrics::{Kind, Metric, Metrics};
+use server::repository::InMemoryRepository;
+use std::sync::Arc;
+use tonic::{Code, Request};
+
+// Never ready, to stand in for a store that cannot be reached
+#[derive(Debug)]
+struct Unreachable;
+
+#[tonic::async_trait]
+impl ReadinessCheck for Unreachable {
+    async fn check(&self) -> Result<(), String> {
+        Err("connection refused".to_string())
+    }
+}
+
+async fn status(service: &HealthServiceImpl, name: &str) -> Result<ServingStatus, Code> {
+    let request = Request::new(HealthCheckRequest {
+        service: name.to_string(),
+    });
+    match service.check(request).await {
+        Ok(response) => Ok(response.into_inner().status()),
+        Err(status) => Err(status.code()),
+    }
+}
+
+#[tokio::test]
+async fn readiness_follows_the_maintenance_file_and_shutdown() {
+    let dir = std::env::temp_dir().join(format!("health-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let maintenance = dir.join("maintenance");
+    let health = Health::new()
+        .with_check(
+            "storage",
+            Arc::new(StorageCheck(Arc::new(InMemoryRepository::new()))),
+        )
+        .with_check(
+            "maintenance",
+            Arc::new(MaintenanceFile(maintenance.clone())),
+        );
+    let service = HealthServiceImpl::new(health.clone(), ["student.StudentService".to_string()]);
+
+    assert_eq!(status(&service, "").await, Ok(ServingStatus::Serving));
+    assert_eq!(
+        status(&service, "student.StudentService").await,
+        Ok(ServingStatus::Serving)
+    );
+    assert_eq!(
+        status(&service, "student.Nothing").await,
+        Err(Code::NotFound)
+    );
+
+    // In maintenance the server is still live, just not ready
+    std::fs::write(&maintenance, "").unwrap();
+    assert_eq!(status(&service, "").await, Ok(ServingStatus::NotServing));
+    assert_eq!(
+        status(&service, health::LIVENESS).await,
+        Ok(ServingStatus::Serving)
+    );
+    std::fs::remove_file(&maintenance).unwrap();
+    assert_eq!(status(&service, "").await, Ok(ServingStatus::Serving));
+
+    health.shut_down();
+    assert_eq!(status(&service, "").await, Ok(ServingStatus::NotServing));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn probes_are_served_over_http() {
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let health = Health::new().with_check("storage", Arc::new(Unreachable));
+    let metrics = Metrics::new();
+    let requests = Metric {
+        name: "requests_total",
+        help: "Requests served",
+        kind: Kind::Counter,
+    };
+    metrics.increment(&requests, &[("tenant", "a \"quoted\" name")]);
+    tokio::spawn(health::serve_http(
+        addr,
+        health,
+        metrics,
+        Channelz::new(),
+        std::future::pending(),
+    ));
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let client = hyper::Client::new();
+    let get = |path: &str| {
+        let uri = format!("http://{}{}", addr, path).parse().unwrap();
+        let response = client.get(uri);
+        async move {
+            let response = response.await.unwrap();
+            let status = response.status().as_u16();
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            (status, String::from_utf8(body.to_vec()).unwrap())
+        }
+    };
+
+    assert_eq!(get("/livez").await, (200, "ok\n".to_string()));
+    assert_eq!(
+        get("/readyz").await,
+        (
+            503,
+            "[-]storage failed: connection refused\n[+]shutdown ok\nnot ready\n".to_string()
+        )
+    );
+    assert_eq!(
+        get("/metrics").await,
+        (
+            200,
+            "# HELP requests_total Requests served\n# TYPE requests_total counter\nrequests_total{tenant=\"a \\\"quoted\\\" name\"} 1\n"
+                .to_string()
+        )
+    );
+    assert_eq!(
+        get("/debug/connections").await,
+        (200, "0 connections\n".to_string())
+    );
+    assert_eq!(get("/tracez").await.0, 404);
+}
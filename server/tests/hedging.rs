@@ -0,0 +1,82 @@
+use client::StudentClient;
+use proto::student_service_server::StudentServiceServer;
+use server::StudentServiceImpl;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::{Channel, Server};
+use tonic::{Code, Request, Status};
+
+// A server failing every call with `status`, or answering when it is None,
+// and the number of calls it has seen
+#[allow(clippy::result_large_err)]
+async fn start(status: Option<Status>) -> (Channel, Arc<AtomicUsize>) {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let interceptor = {
+        let calls = calls.clone();
+        move |request: Request<()>| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            match &status {
+                Some(status) => Err(status.clone()),
+                None => Ok(request),
+            }
+        }
+    };
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .add_service(StudentServiceServer::with_interceptor(
+                StudentServiceImpl::new(),
+                interceptor,
+            ))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    let channel = Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect_lazy();
+    (channel, calls)
+}
+
+async fn hedged(
+    primary: Option<Status>,
+    alternate: Option<Status>,
+) -> (StudentClient, Arc<AtomicUsize>) {
+    let (primary, _) = start(primary).await;
+    let (alternate, calls) = start(alternate).await;
+    let client = StudentClient::new(primary).with_hedging(Duration::from_secs(5), [alternate]);
+    (client, calls)
+}
+
+#[tokio::test]
+async fn unavailable_reads_are_hedged() {
+    let (mut client, calls) = hedged(Some(Status::unavailable("down")), None).await;
+    let status = client.get_student("s1").await.unwrap_err();
+    // The alternate answers for itself, without waiting out the delay
+    assert_eq!(status.code(), Code::NotFound);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn other_errors_are_the_answer() {
+    let denied = Status::permission_denied("not yours");
+    let (mut client, calls) = hedged(Some(denied), None).await;
+    let status = client.get_student("s1").await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn the_first_attempts_error_is_reported() {
+    let (mut client, calls) = hedged(
+        Some(Status::unavailable("primary")),
+        Some(Status::resource_exhausted("alternate")),
+    )
+    .await;
+    let status = client.get_student("s1").await.unwrap_err();
+    assert_eq!(status.code(), Code::Unavailable);
+    assert_eq!(status.message(), "primary");
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
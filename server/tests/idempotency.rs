@@ -0,0 +1,125 @@
+use proto::batch_write_entry::Write as Entry;
+use proto::student_service_server::StudentService;
+use proto::{BatchWriteEntry, BatchWriteRequest, CreateStudentRequest, Student};
+use server::idempotency::METADATA_KEY;
+use server::repository::{InMemoryRepository, StudentRepository};
+use server::StudentServiceImpl;
+use std::sync::Arc;
+use tonic::{Code, Request, Status};
+
+fn student(name: &str) -> Student {
+    Student {
+        name: name.to_string(),
+        email: format!("{}@university.edu", name.to_lowercase()),
+        age: 20,
+        ..Default::default()
+    }
+}
+
+fn with_key<T>(message: T, key: &str) -> Request<T> {
+    let mut request = Request::new(message);
+    request
+        .metadata_mut()
+        .insert(METADATA_KEY, key.parse().unwrap());
+    request
+}
+
+async fn create(service: &StudentServiceImpl, name: &str, key: &str) -> Result<Student, Status> {
+    let request = CreateStudentRequest {
+        student: Some(student(name)),
+    };
+    service
+        .create_student(with_key(request, key))
+        .await
+        .map(|response| response.into_inner().student.unwrap())
+}
+
+#[tokio::test]
+async fn retried_create_is_created_once() {
+    let store = Arc::new(InMemoryRepository::new());
+    let service = StudentServiceImpl::new().with_repository(store.clone());
+    let (_, mut events) = service.events().subscribe("").unwrap();
+
+    let first = create(&service, "Ada", "k1").await.unwrap();
+    let again = create(&service, "Ada", "k1").await.unwrap();
+    assert_eq!(again, first);
+    assert_eq!(store.list(10, "").await.unwrap().total_count, 1);
+    events.try_recv().unwrap();
+    assert!(events.try_recv().is_err());
+
+    // Another server on the same store, or this one restarted on it, remembers too
+    let replica = StudentServiceImpl::new().with_repository(store.clone());
+    assert_eq!(create(&replica, "Ada", "k1").await.unwrap(), first);
+
+    // A new key is a new student
+    let second = create(&service, "Ada", "k2").await.unwrap();
+    assert_ne!(second.id, first.id);
+    assert_eq!(store.list(10, "").await.unwrap().total_count, 2);
+}
+
+#[tokio::test]
+async fn key_belongs_to_one_request() {
+    let service = StudentServiceImpl::new();
+    create(&service, "Ada", "k1").await.unwrap();
+
+    let status = create(&service, "Grace", "k1").await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let info = client::error_info(&status).unwrap();
+    assert_eq!(info.reason, "IDEMPOTENCY_KEY_REUSED");
+
+    // Nor is it shared with another method
+    let entries = vec![BatchWriteEntry {
+        write: Some(Entry::Create(student("Ada"))),
+    }];
+    let status = service
+        .batch_write(with_key(BatchWriteRequest { entries }, "k1"))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        client::error_info(&status).unwrap().reason,
+        "IDEMPOTENCY_KEY_REUSED"
+    );
+
+    let status = create(&service, "Ada", " ").await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let info = client::error_info(&status).unwrap();
+    assert_eq!(info.reason, "IDEMPOTENCY_KEY_INVALID");
+    assert_eq!(info.metadata["max_length"], "255");
+}
+
+#[tokio::test]
+async fn retried_batch_write_is_written_once() {
+    let store = Arc::new(InMemoryRepository::new());
+    let service = StudentServiceImpl::new().with_repository(store.clone());
+    let ada = store
+        .create(Student {
+            id: "s1".to_string(),
+            ..student("Ada")
+        })
+        .await
+        .unwrap();
+
+    let request = BatchWriteRequest {
+        entries: vec![
+            BatchWriteEntry {
+                write: Some(Entry::Create(student("Grace"))),
+            },
+            BatchWriteEntry {
+                write: Some(Entry::DeleteId(ada.id.clone())),
+            },
+        ],
+    };
+    let first = service
+        .batch_write(with_key(request.clone(), "k1"))
+        .await
+        .unwrap()
+        .into_inner();
+    // Deleting Ada again would fail; the retry gets the first answer instead
+    let again = service
+        .batch_write(with_key(request, "k1"))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(again, first);
+    assert_eq!(store.list(10, "").await.unwrap().total_count, 1);
+}
@@ -0,0 +1,201 @@
+use prost::Message;
+use proto::google::rpc::{ErrorInfo, Status as StatusDetails};
+use proto::student_service_client::StudentServiceClient;
+use proto::student_service_server::StudentServiceServer;
+use proto::{CreateStudentRequest, GetStudentRequest, ListStudentsRequest, Student};
+use server::clock::FixedClock;
+use server::leader::{
+    served_by_followers, Election, FileLease, LeaderLayer, LeaseStore, FORWARDED_BY,
+};
+use server::StudentServiceImpl;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::metadata::MetadataMap;
+use tonic::transport::{Channel, Server};
+use tonic::{Code, Request};
+
+const TTL: Duration = Duration::from_secs(10);
+
+fn lease_file() -> PathBuf {
+    std::env::temp_dir().join(format!("lease-{}.json", uuid::Uuid::new_v4()))
+}
+
+fn replica(store: &Arc<dyn LeaseStore>, name: &str, clock: &Arc<FixedClock>) -> Arc<Election> {
+    let address = format!("{}.example.edu:50051", name);
+    Arc::new(Election::new(store.clone(), name, address, TTL).with_clock(clock.clone()))
+}
+
+#[tokio::test]
+async fn the_lease_passes_on_only_once_it_expires() {
+    let path = lease_file();
+    let store: Arc<dyn LeaseStore> = Arc::new(FileLease::new(&path));
+    let clock = Arc::new(FixedClock::new(SystemTime::now()));
+    let (a, b) = (replica(&store, "a", &clock), replica(&store, "b", &clock));
+
+    assert!(a.campaign().await.unwrap());
+    assert!(!b.campaign().await.unwrap());
+    assert!(a.is_leader() && !b.is_leader());
+    assert_eq!(b.leader().unwrap().address, "a.example.edu:50051");
+
+    // Renewed, the lease stays; left to run out, it is taken over
+    clock.advance(TTL / 2);
+    assert!(a.campaign().await.unwrap());
+    clock.advance(TTL / 2 + Duration::from_secs(1));
+    assert!(!b.campaign().await.unwrap());
+    clock.advance(TTL);
+    assert!(!a.is_leader());
+    assert!(b.campaign().await.unwrap());
+    assert!(!a.campaign().await.unwrap());
+
+    // Resigning hands the lease over without waiting
+    b.resign().await;
+    assert!(!b.is_leader());
+    assert!(a.campaign().await.unwrap());
+    std::fs::remove_file(&path).unwrap();
+}
+
+async fn follower() -> StudentServiceClient<Channel> {
+    let path = lease_file();
+    let store: Arc<dyn LeaseStore> = Arc::new(FileLease::new(&path));
+    let clock = Arc::new(FixedClock::new(SystemTime::now()));
+    replica(&store, "leader", &clock).campaign().await.unwrap();
+    let election = replica(&store, "follower", &clock);
+    election.campaign().await.unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .layer(LeaderLayer::new(election))
+            .add_service(StudentServiceServer::new(StudentServiceImpl::new()))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    connect(addr).await
+}
+
+#[tokio::test]
+async fn followers_serve_reads_and_send_writes_to_the_leader() {
+    let mut client = follower().await;
+    client
+        .list_students(ListStudentsRequest::default())
+        .await
+        .unwrap();
+
+    let status = client.create_student(ada()).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unavailable);
+    let details = StatusDetails::decode(status.details()).unwrap();
+    let info = ErrorInfo::decode(&details.details[0].value[..]).unwrap();
+    assert_eq!(info.reason, "NOT_LEADER");
+    assert_eq!(info.metadata["leader"], "leader.example.edu:50051");
+
+    assert!(served_by_followers("/student.StudentService/GetStudent"));
+    assert!(served_by_followers(
+        "/student.LoggingService/UpdateLogConfig"
+    ));
+    assert!(!served_by_followers(
+        "/student.StudentService/DeleteStudent"
+    ));
+    assert!(!served_by_followers(
+        "/student.StorageService/MigrateStorage"
+    ));
+}
+
+async fn connect(addr: SocketAddr) -> StudentServiceClient<Channel> {
+    let channel = Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    StudentServiceClient::new(channel)
+}
+
+fn ada() -> Request<CreateStudentRequest> {
+    Request::new(CreateStudentRequest {
+        student: Some(Student {
+            name: "Ada Lovelace".to_string(),
+            email: "ada@university.edu".to_string(),
+            ..Default::default()
+        }),
+    })
+}
+
+#[tokio::test]
+#[allow(clippy::result_large_err)]
+async fn followers_forward_writes_to_the_leader() {
+    let path = lease_file();
+    let store: Arc<dyn LeaseStore> = Arc::new(FileLease::new(&path));
+    let clock = Arc::new(FixedClock::new(SystemTime::now()));
+
+    // The leader keeps the metadata of each call it serves
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let leader_addr = listener.local_addr().unwrap();
+    let leader = Election::new(store.clone(), "leader", leader_addr.to_string(), TTL);
+    let leader = Arc::new(leader.with_clock(clock.clone()));
+    leader.campaign().await.unwrap();
+    let seen = Arc::new(Mutex::new(Vec::<MetadataMap>::new()));
+    let record = seen.clone();
+    tokio::spawn(
+        Server::builder()
+            .layer(LeaderLayer::new(leader))
+            .add_service(StudentServiceServer::with_interceptor(
+                StudentServiceImpl::new(),
+                move |request: Request<()>| {
+                    record.lock().unwrap().push(request.metadata().clone());
+                    Ok(request)
+                },
+            ))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let follower_addr = listener.local_addr().unwrap();
+    let follower = replica(&store, "follower", &clock);
+    follower.campaign().await.unwrap();
+    tokio::spawn(
+        Server::builder()
+            .layer(LeaderLayer::new(follower).with_forwarding())
+            .add_service(StudentServiceServer::new(StudentServiceImpl::new()))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+
+    let mut client = connect(follower_addr).await;
+    let mut request = ada();
+    request
+        .metadata_mut()
+        .insert("x-request-id", "req-1".parse().unwrap());
+    request.set_timeout(Duration::from_secs(5));
+    let created = client
+        .create_student(request)
+        .await
+        .unwrap()
+        .into_inner()
+        .student
+        .unwrap();
+
+    // Written on the leader, with the caller's metadata and deadline
+    let mut leader_client = connect(leader_addr).await;
+    let get = GetStudentRequest {
+        id: created.id.clone(),
+    };
+    leader_client.get_student(get.clone()).await.unwrap();
+    let status = client.get_student(get).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    let metadata = seen.lock().unwrap()[0].clone();
+    assert_eq!(metadata.get("x-request-id").unwrap(), "req-1");
+    assert_eq!(metadata.get(FORWARDED_BY).unwrap(), "follower");
+    assert!(metadata.get("grpc-timeout").is_some());
+
+    // An RPC forwarded once is not forwarded again
+    let mut request = ada();
+    request
+        .metadata_mut()
+        .insert(FORWARDED_BY, "elsewhere".parse().unwrap());
+    let status = client.create_student(request).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unavailable);
+    assert_eq!(seen.lock().unwrap().len(), 2);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,219 @@
+use client::{Problem, StudentClient};
+use proto::batch_write_entry::Write;
+use proto::student_service_server::StudentServiceServer;
+use proto::{Address, BatchWriteEntry, Student};
+use server::locale::{Locale, LocaleLayer, Text};
+use server::StudentServiceImpl;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::Server;
+use tonic::{Code, Request, Status};
+
+// A client of a fresh server, and the number of calls that reached it
+#[allow(clippy::result_large_err)]
+async fn start() -> (StudentClient, Arc<AtomicUsize>) {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counted = calls.clone();
+    let interceptor = move |request: Request<()>| {
+        counted.fetch_add(1, Ordering::SeqCst);
+        Ok(request)
+    };
+    let service = StudentServiceServer::with_interceptor(StudentServiceImpl::new(), interceptor);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .layer(LocaleLayer)
+            .add_service(service)
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    let client = StudentClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap();
+    (client, calls)
+}
+
+fn invalid() -> Student {
+    Student {
+        id: "s1".to_string(),
+        name: " ".to_string(),
+        email: "ada@university.edu".to_string(),
+        age: 200,
+        gpa_decimal: "A-".to_string(),
+        address: Some(Address {
+            line1: "1 Main St".to_string(),
+            city: "Springfield".to_string(),
+            region: "ZZ".to_string(),
+            postal_code: "1234".to_string(),
+            country: "us".to_string(),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+// What a caller can see of a failure
+fn seen(status: Status) -> (Code, String, Option<String>, Vec<(String, String)>) {
+    let error = client::Error::from(status);
+    let violations = error
+        .field_violations()
+        .iter()
+        .map(|violation| (violation.field.clone(), violation.description.clone()))
+        .collect();
+    (
+        error.code(),
+        error.message().to_string(),
+        error.reason().map(str::to_string),
+        violations,
+    )
+}
+
+#[tokio::test]
+async fn invalid_students_fail_before_they_are_sent() {
+    let (client, calls) = start().await;
+
+    let local = client.clone().create_student(invalid()).await.unwrap_err();
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+    let sent = client
+        .clone()
+        .with_local_validation(false)
+        .create_student(invalid())
+        .await
+        .unwrap_err();
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // Just as the server would have refused it
+    assert_eq!(seen(local.clone()), seen(sent.clone()));
+    assert_eq!(client::error_info(&local), client::error_info(&sent));
+    let fields: Vec<_> = seen(local).3.into_iter().map(|(field, _)| field).collect();
+    assert_eq!(
+        fields,
+        [
+            "name",
+            "age",
+            "gpa_decimal",
+            "address.region",
+            "address.postal_code"
+        ]
+    );
+
+    // Tidied up as lenient validation would: an address with nothing in
+    // it is dropped, and a GPA rounded, not refused
+    let messy = Student {
+        name: "  Ada  Lovelace ".to_string(),
+        age: 20,
+        gpa_decimal: "3.456".to_string(),
+        address: Some(Address::default()),
+        ..invalid()
+    };
+    client.clone().create_student(messy).await.unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn batch_entries_are_checked_as_the_server_checks_them() {
+    let (client, calls) = start().await;
+    let entries = vec![
+        BatchWriteEntry {
+            write: Some(Write::DeleteId("s9".to_string())),
+        },
+        BatchWriteEntry {
+            write: Some(Write::Create(invalid())),
+        },
+    ];
+
+    let local = client
+        .clone()
+        .batch_write(entries.clone())
+        .await
+        .unwrap_err();
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+    let sent = client
+        .clone()
+        .with_local_validation(false)
+        .batch_write(entries)
+        .await
+        .unwrap_err();
+    assert_eq!(seen(local.clone()), seen(sent.clone()));
+    assert_eq!(client::error_info(&local), client::error_info(&sent));
+    assert!(local.message().starts_with("entries[1]: "));
+}
+
+#[tokio::test]
+async fn dry_runs_are_checked_before_they_are_sent() {
+    let (client, calls) = start().await;
+
+    let local = client.clone().validate_student(invalid()).await.unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+    let sent = client
+        .clone()
+        .with_local_validation(false)
+        .validate_student(invalid())
+        .await
+        .unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert!(!local.valid);
+    assert_eq!(local, sent);
+
+    // Students the rules here let through are still the server's to judge
+    let valid = Student {
+        name: "Ada Lovelace".to_string(),
+        age: 20,
+        gpa_decimal: "3.5".to_string(),
+        address: None,
+        ..invalid()
+    };
+    assert!(client.clone().validate_student(valid).await.unwrap().valid);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn other_languages_are_left_to_the_server() {
+    let (client, calls) = start().await;
+    let status = client
+        .with_language("zh")
+        .create_student(invalid())
+        .await
+        .unwrap_err();
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert_eq!(status.message(), "学生姓名不能为空");
+}
+
+#[test]
+fn the_shared_rules_read_as_the_server_says_them() {
+    let problems = [
+        Problem::NameEmpty,
+        Problem::EmailEmpty,
+        Problem::AgeOutOfRange,
+        Problem::GpaOutOfRange,
+        Problem::GpaInvalid("A-".to_string()),
+        Problem::CreditsNegative,
+        Problem::AddressLineEmpty,
+        Problem::CityEmpty,
+        Problem::CountryUnknown("XX".to_string()),
+        Problem::RegionUnknown("US".to_string(), "ZZ".to_string()),
+        Problem::PostalCodeInvalid("CA".to_string(), "123".to_string()),
+        Problem::AnnotationKeyInvalid("bad key".to_string()),
+        Problem::AnnotationsTooLarge,
+        Problem::IdNotCanonical,
+        Problem::NameNotCanonical,
+        Problem::PreferredNameNotCanonical,
+        Problem::EmailNotCanonical,
+        Problem::EmailInvalid,
+        Problem::MajorNotCanonical,
+        Problem::MajorIdNotCanonical,
+        Problem::GpaNotCanonical,
+        Problem::AddressNotCanonical,
+        Problem::PhoneInvalid("12".to_string()),
+        Problem::PhoneRegionRequired("650 253 0000".to_string()),
+        Problem::PhoneNotCanonical("(650) 253-0000".to_string()),
+    ];
+    for problem in problems {
+        let text = Text::from(problem.clone());
+        assert_eq!(problem.reason(), text.reason());
+        assert_eq!(problem.to_string(), text.render(Locale::En));
+        assert_eq!(problem.metadata(), text.metadata());
+    }
+}
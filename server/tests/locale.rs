@@ -0,0 +1,122 @@
+use proto::student_service_client::StudentServiceClient;
+use proto::student_service_server::StudentServiceServer;
+use proto::{CreateStudentRequest, GetStudentRequest, Student, ValidateStudentRequest};
+use server::locale::{Locale, LocaleLayer, ERROR_DOMAIN};
+use server::StudentServiceImpl;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::{Channel, Server};
+use tonic::{Code, Request};
+
+async fn start() -> StudentServiceClient<Channel> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .layer(LocaleLayer)
+            .add_service(StudentServiceServer::new(StudentServiceImpl::new()))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    StudentServiceClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap()
+}
+
+fn in_language<T>(message: T, accept_language: &str) -> Request<T> {
+    let mut request = Request::new(message);
+    request
+        .metadata_mut()
+        .insert("accept-language", accept_language.parse().unwrap());
+    request
+}
+
+#[test]
+fn the_best_supported_language_wins() {
+    assert_eq!(Locale::negotiate("zh-CN"), Locale::Zh);
+    assert_eq!(Locale::negotiate("es-MX, en;q=0.5"), Locale::Es);
+    assert_eq!(Locale::negotiate("en;q=0.4, zh;q=0.8"), Locale::Zh);
+    assert_eq!(Locale::negotiate("fr, es;q=0.5"), Locale::Es);
+    // Nothing we speak, or only languages the caller refuses
+    assert_eq!(Locale::negotiate("fr, de"), Locale::En);
+    assert_eq!(Locale::negotiate("zh;q=0"), Locale::En);
+    assert_eq!(Locale::negotiate(""), Locale::En);
+}
+
+#[tokio::test]
+async fn errors_are_in_the_callers_language() {
+    let mut client = start().await;
+
+    let status = client
+        .create_student(in_language(
+            CreateStudentRequest {
+                student: Some(Student::default()),
+            },
+            "zh-CN, en;q=0.8",
+        ))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(status.message(), "学生姓名不能为空");
+    // The reason does not change with the language
+    let info = client::error_info(&status).unwrap();
+    assert_eq!(info.reason, "NAME_EMPTY");
+    assert_eq!(info.domain, ERROR_DOMAIN);
+
+    let status = client
+        .get_student(in_language(
+            GetStudentRequest {
+                id: "nobody".to_string(),
+            },
+            "fr, es;q=0.5",
+        ))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    assert_eq!(status.message(), "No se encontró el estudiante");
+    assert_eq!(
+        client::error_info(&status).unwrap().reason,
+        "STUDENT_NOT_FOUND"
+    );
+
+    // English without a preference
+    let status = client
+        .get_student(GetStudentRequest {
+            id: "nobody".to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.message(), "Student not found");
+}
+
+#[tokio::test]
+async fn violations_keep_their_field() {
+    let mut client = start().await;
+
+    let response = client
+        .validate_student(in_language(
+            ValidateStudentRequest {
+                student: Some(Student {
+                    name: "Ada".to_string(),
+                    age: 200,
+                    ..Default::default()
+                }),
+            },
+            "es",
+        ))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!response.valid);
+    let violations: Vec<_> = response
+        .violations
+        .iter()
+        .map(|violation| (violation.field.as_str(), violation.description.as_str()))
+        .collect();
+    assert_eq!(
+        violations,
+        [
+            ("email", "El correo del estudiante no puede estar vacío"),
+            ("age", "La edad del estudiante debe estar entre 0 y 150"),
+        ]
+    );
+}
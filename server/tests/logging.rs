@@ -0,0 +1,100 @@
+use proto::logging_service_server::LoggingService;
+use proto::{FieldMask, GetLogConfigRequest, LogConfig, UpdateLogConfigRequest};
+use server::logging::{self, Filter, Level, LoggingServiceImpl};
+use tonic::{Code, Request};
+
+#[test]
+fn filters_pick_the_closest_module() {
+    let filter: Filter = "warn, bulk=debug, server::bulk::jobs=off".parse().unwrap();
+    assert_eq!(filter.to_string(), "warn,bulk=debug,bulk::jobs=off");
+    assert_eq!(filter.level("server::service"), Level::Warn);
+    assert_eq!(filter.level("server::bulk"), Level::Debug);
+    assert_eq!(filter.level("server::bulk::jobs"), Level::Off);
+    // Only whole module names match
+    assert_eq!(filter.level("server::bulky"), Level::Warn);
+
+    for bad in ["", "loud", "bulk=loud", "=debug"] {
+        assert!(bad.parse::<Filter>().is_err(), "{:?}", bad);
+    }
+}
+
+// The configuration is process-wide, so everything that changes it is here
+#[tokio::test]
+async fn logging_is_reconfigured_at_runtime() {
+    let service = LoggingServiceImpl;
+    let update = |config: LogConfig, paths: &[&str]| UpdateLogConfigRequest {
+        config: Some(config),
+        update_mask: Some(FieldMask {
+            paths: paths.iter().map(|path| path.to_string()).collect(),
+        }),
+    };
+
+    let config = service
+        .update_log_config(Request::new(update(
+            LogConfig {
+                filter: "error,timing=debug".to_string(),
+                trace_sample_rate: 0.25,
+            },
+            &[],
+        )))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(config.filter, "error,timing=debug");
+    assert!(logging::enabled(Level::Debug, "server::timing"));
+    assert!(!logging::enabled(Level::Warn, "server::service"));
+
+    // One request in four is traced
+    let traced = (0..100)
+        .filter(|_| logging::trace("server::timing"))
+        .count();
+    assert_eq!(traced, 25);
+    assert!(!logging::trace("server::service"));
+
+    // Masked fields change on their own
+    service
+        .update_log_config(Request::new(update(
+            LogConfig {
+                filter: "ignored".to_string(),
+                trace_sample_rate: 1.0,
+            },
+            &["traceSampleRate"],
+        )))
+        .await
+        .unwrap();
+    let config = service
+        .get_log_config(Request::new(GetLogConfigRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(
+        (config.filter.as_str(), config.trace_sample_rate),
+        ("error,timing=debug", 1.0)
+    );
+
+    // Bad values leave the configuration as it was
+    for (config, paths) in [
+        (
+            LogConfig {
+                filter: "loud".to_string(),
+                trace_sample_rate: 1.0,
+            },
+            &[][..],
+        ),
+        (
+            LogConfig {
+                filter: "info".to_string(),
+                trace_sample_rate: 1.5,
+            },
+            &[][..],
+        ),
+        (LogConfig::default(), &["level"][..]),
+    ] {
+        let status = service
+            .update_log_config(Request::new(update(config, paths)))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+    assert_eq!(logging::config().filter.to_string(), "error,timing=debug");
+}
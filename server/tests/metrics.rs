@@ -0,0 +1,216 @@
+use proto::student_service_client::StudentServiceClient;
+use proto::student_service_server::StudentServiceServer;
+use proto::{GetStudentRequest, ListStudentsRequest};
+use server::channelz::Channelz;
+use server::health::{self, Health};
+use server::logging::{self, Config};
+use server::metrics::{Kind, Metric, Metrics, OPENMETRICS};
+use server::timing::{
+    StartLayer, TenantLabels, TimingLayer, HANDLED, HANDLING_SECONDS, OTHER_TENANTS,
+};
+use server::StudentServiceImpl;
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::Server;
+
+const LATENCY: Metric = Metric {
+    name: "request_seconds",
+    help: "Time to answer",
+    kind: Kind::Histogram,
+};
+
+#[test]
+fn histograms_count_into_buckets_and_keep_exemplars() {
+    let metrics = Metrics::new();
+    let labels = [("method", "GetStudent")];
+    metrics.observe(&LATENCY, &labels, 0.00390625);
+    metrics.observe_with_exemplar(&LATENCY, &labels, 0.1875, &[("trace_id", "abc")]);
+    metrics.observe(&LATENCY, &labels, 0.125);
+    metrics.observe_with_exemplar(&LATENCY, &labels, 32.0, &[("trace_id", "def")]);
+    assert_eq!(metrics.get(&LATENCY, &labels), 4.0);
+    assert_eq!(metrics.get(&LATENCY, &[("method", "ListStudents")]), 0.0);
+
+    let bucket = |le: &str, count: u32| {
+        format!(
+            "request_seconds_bucket{{method=\"GetStudent\",le=\"{}\"}} {}",
+            le, count
+        )
+    };
+    let text = metrics.render();
+    assert!(text
+        .starts_with("# HELP request_seconds Time to answer\n# TYPE request_seconds histogram\n"));
+    for line in [
+        bucket("0.005", 1),
+        bucket("0.1", 1),
+        bucket("0.25", 3),
+        bucket("10", 3),
+        bucket("+Inf", 4),
+        "request_seconds_sum{method=\"GetStudent\"} 32.31640625".to_string(),
+        "request_seconds_count{method=\"GetStudent\"} 4".to_string(),
+    ] {
+        assert!(text.lines().any(|l| l == line), "{} not in\n{}", line, text);
+    }
+    assert!(!text.contains("trace_id"));
+
+    // Only OpenMetrics carries exemplars, each on the bucket it fell in
+    let text = metrics.render_openmetrics();
+    for line in [
+        bucket("0.25", 3) + " # {trace_id=\"abc\"} 0.1875",
+        bucket("+Inf", 4) + " # {trace_id=\"def\"} 32",
+        bucket("0.5", 3),
+    ] {
+        assert!(text.lines().any(|l| l == line), "{} not in\n{}", line, text);
+    }
+    assert!(text.ends_with("# EOF\n"));
+}
+
+#[test]
+fn openmetrics_names_counters_without_their_total() {
+    let metrics = Metrics::new();
+    let requests = Metric {
+        name: "requests_total",
+        help: "Requests served",
+        kind: Kind::Counter,
+    };
+    metrics.increment(&requests, &[]);
+    assert_eq!(
+        metrics.render_openmetrics(),
+        "# HELP requests Requests served\n# TYPE requests counter\nrequests_total 1\n# EOF\n"
+    );
+}
+
+async fn start(metrics: &Metrics, tenants: TenantLabels) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let timing = TimingLayer::new("test")
+        .unwrap()
+        .with_metrics(metrics.clone())
+        .with_tenant_labels(tenants);
+    tokio::spawn(
+        Server::builder()
+            .layer(timing)
+            .layer(StartLayer)
+            .add_service(StudentServiceServer::new(StudentServiceImpl::new()))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    addr
+}
+
+#[tokio::test]
+async fn traced_calls_leave_their_trace_id_as_an_exemplar() {
+    logging::set_config(Config {
+        filter: "info,timing=debug".parse().unwrap(),
+        trace_sample_rate: 1.0,
+    });
+    let metrics = Metrics::new();
+    let addr = start(&metrics, TenantLabels::default()).await;
+    let mut client = StudentServiceClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap();
+
+    let mut request = tonic::Request::new(GetStudentRequest {
+        id: "missing".to_string(),
+    });
+    request.metadata_mut().insert(
+        "traceparent",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+            .parse()
+            .unwrap(),
+    );
+    client.get_student(request).await.unwrap_err();
+
+    let labels = [
+        ("grpc_service", "student.StudentService"),
+        ("grpc_method", "GetStudent"),
+    ];
+    assert_eq!(metrics.get(&HANDLING_SECONDS, &labels), 1.0);
+    let handled = [
+        ("grpc_service", "student.StudentService"),
+        ("grpc_method", "GetStudent"),
+        ("grpc_code", "NotFound"),
+        ("tenant", ""),
+    ];
+    assert_eq!(metrics.get(&HANDLED, &handled), 1.0);
+    let text = metrics.render_openmetrics();
+    assert!(
+        text.contains("# {trace_id=\"4bf92f3577b34da6a3ce929d0e0e4736\"} "),
+        "{}",
+        text
+    );
+
+    // Served in OpenMetrics to a scraper that asks for it
+    let probes = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    tokio::spawn(health::serve_http(
+        probes,
+        Health::new(),
+        metrics.clone(),
+        Channelz::new(),
+        std::future::pending(),
+    ));
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    let request = hyper::Request::get(format!("http://{}/metrics", probes))
+        .header("accept", "application/openmetrics-text; version=1.0.0")
+        .body(hyper::Body::empty())
+        .unwrap();
+    let response = hyper::Client::new().request(request).await.unwrap();
+    assert_eq!(response.headers()["content-type"], OPENMETRICS);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(String::from_utf8(body.to_vec()).unwrap(), text);
+}
+
+#[test]
+fn only_some_tenants_are_labeled_as_themselves() {
+    let first = TenantLabels::first(2);
+    assert_eq!(first.label(Some("acme")), "acme");
+    assert_eq!(first.label(Some("globex")), "globex");
+    assert_eq!(first.label(Some("initech")), OTHER_TENANTS);
+    assert_eq!(first.label(Some("acme")), "acme");
+    assert_eq!(first.label(None), "");
+
+    let allowed = TenantLabels::allow(["globex".to_string()]);
+    assert_eq!(allowed.label(Some("acme")), OTHER_TENANTS);
+    assert_eq!(allowed.label(Some("globex")), "globex");
+}
+
+#[tokio::test]
+async fn calls_are_counted_by_code_and_tenant() {
+    let metrics = Metrics::new();
+    let addr = start(&metrics, TenantLabels::first(1)).await;
+    let mut client = StudentServiceClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap();
+    for tenant in ["acme", "acme", "globex", "initech"] {
+        let mut request = tonic::Request::new(ListStudentsRequest::default());
+        request
+            .metadata_mut()
+            .insert("tenant", tenant.parse().unwrap());
+        client.list_students(request).await.unwrap();
+    }
+    let mut request = tonic::Request::new(GetStudentRequest {
+        id: "missing".to_string(),
+    });
+    request
+        .metadata_mut()
+        .insert("tenant", "acme".parse().unwrap());
+    client.get_student(request).await.unwrap_err();
+
+    let handled = |method: &str, code: &str, tenant: &str| {
+        metrics.get(
+            &HANDLED,
+            &[
+                ("grpc_service", "student.StudentService"),
+                ("grpc_method", method),
+                ("grpc_code", code),
+                ("tenant", tenant),
+            ],
+        )
+    };
+    assert_eq!(handled("ListStudents", "Ok", "acme"), 2.0);
+    assert_eq!(handled("ListStudents", "Ok", OTHER_TENANTS), 2.0);
+    assert_eq!(handled("GetStudent", "NotFound", "acme"), 1.0);
+    assert!(!metrics.render().contains("globex"));
+}
@@ -0,0 +1,73 @@
+use proto::storage_service_server::StorageService;
+use proto::{GetSchemaVersionRequest, GetSchemaVersionResponse, Migration};
+use server::health::ReadinessCheck;
+use server::migrations::{self, MigrationsCheck, SchemaStore, MIGRATIONS};
+use server::operations::Operations;
+use server::repository::{InMemoryRepository, StudentRepository};
+use server::storage::StorageServiceImpl;
+use std::sync::Arc;
+use tonic::{Request, Status};
+
+// A database at a fixed schema version
+#[derive(Debug)]
+struct AtVersion(i32);
+
+#[tonic::async_trait]
+impl SchemaStore for AtVersion {
+    async fn schema_version(&self) -> Result<i32, Status> {
+        Ok(self.0)
+    }
+}
+
+fn store() -> Arc<dyn StudentRepository> {
+    Arc::new(InMemoryRepository::new())
+}
+
+async fn schema_version(service: &StorageServiceImpl) -> GetSchemaVersionResponse {
+    service
+        .get_schema_version(Request::new(GetSchemaVersionRequest {}))
+        .await
+        .unwrap()
+        .into_inner()
+}
+
+#[test]
+fn migrations_are_numbered_in_order_from_one() {
+    for (index, migration) in MIGRATIONS.iter().enumerate() {
+        assert_eq!(migration.version, index as i32 + 1);
+        assert!(!migration.sql.trim().is_empty());
+    }
+    assert_eq!(migrations::latest(), MIGRATIONS.len() as i32);
+    assert_eq!(migrations::pending(0), MIGRATIONS);
+    assert_eq!(migrations::pending(1), &MIGRATIONS[1..]);
+    assert!(migrations::pending(migrations::latest()).is_empty());
+    assert!(migrations::pending(migrations::latest() + 1).is_empty());
+}
+
+#[tokio::test]
+async fn schema_version_lists_pending_migrations() {
+    let behind = Arc::new(AtVersion(migrations::latest() - 1));
+    let service = StorageServiceImpl::new(store(), Operations::new()).with_schema(behind.clone());
+    let last = MIGRATIONS.last().unwrap();
+    assert_eq!(
+        schema_version(&service).await,
+        GetSchemaVersionResponse {
+            version: last.version - 1,
+            latest_version: last.version,
+            pending: vec![Migration {
+                version: last.version,
+                name: last.name.to_string(),
+            }],
+        }
+    );
+    assert!(MigrationsCheck(behind).check().await.is_err());
+    let current = Arc::new(AtVersion(migrations::latest()));
+    assert_eq!(MigrationsCheck(current).check().await, Ok(()));
+
+    // The in-memory store has no schema
+    let service = StorageServiceImpl::new(store(), Operations::new());
+    assert_eq!(
+        schema_version(&service).await,
+        GetSchemaVersionResponse::default()
+    );
+}
@@ -0,0 +1,101 @@
+use client::{StudentClient, MUTATION_ATTEMPTS};
+use proto::student_service_server::StudentServiceServer;
+use proto::Student;
+use server::repository::{InMemoryRepository, StudentRepository};
+use server::StudentServiceImpl;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::Server;
+use tonic::{Code, Request, Status};
+
+// A server on `repository` whose first `failures` calls are UNAVAILABLE
+#[allow(clippy::result_large_err)]
+async fn start(repository: Arc<InMemoryRepository>, failures: usize) -> StudentClient {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let interceptor = move |request: Request<()>| match calls.fetch_add(1, Ordering::SeqCst) {
+        call if call < failures => Err(Status::unavailable("Starting up")),
+        _ => Ok(request),
+    };
+    let service = StudentServiceImpl::new().with_repository(repository);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .add_service(StudentServiceServer::with_interceptor(service, interceptor))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    StudentClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap()
+}
+
+fn student(id: &str) -> Student {
+    Student {
+        id: id.to_string(),
+        name: format!("Student {}", id),
+        email: format!("{}@university.edu", id),
+        age: 20,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn idempotent_creates_are_retried_and_created_once() {
+    let repository = Arc::new(InMemoryRepository::new());
+    let mut client = start(repository.clone(), 2).await;
+
+    let mut new = student("");
+    new.name = "Ada Lovelace".to_string();
+    let created = client
+        .create_idempotent(new.clone(), "enroll-ada")
+        .await
+        .unwrap();
+    // Again, as after a crash: the same student, not a second one
+    let again = client.create_idempotent(new, "enroll-ada").await.unwrap();
+    assert_eq!(again.id, created.id);
+    assert_eq!(repository.list(10, "").await.unwrap().students.len(), 1);
+}
+
+#[tokio::test]
+async fn updates_are_applied_again_to_what_someone_else_wrote() {
+    let repository = Arc::new(InMemoryRepository::new());
+    let mut client = start(repository.clone(), 0).await;
+    client.create_student(student("a")).await.unwrap();
+
+    let mut runs = 0;
+    let updated = client
+        .update_with_retry("a", |current| {
+            runs += 1;
+            if runs == 1 {
+                // Someone else writes between our read and our write
+                let other = Student {
+                    age: 30,
+                    ..current.clone()
+                };
+                futures::executor::block_on(repository.update(other)).unwrap();
+            }
+            current.credits += 3;
+        })
+        .await
+        .unwrap();
+    assert_eq!(runs, 2);
+    assert_eq!((updated.age, updated.credits), (30, 3));
+
+    // Against a student that changes every time, it gives up
+    let mut runs = 0;
+    let status = client
+        .update_with_retry("a", |current| {
+            runs += 1;
+            let other = Student {
+                credits: current.credits + 1,
+                ..current.clone()
+            };
+            futures::executor::block_on(repository.update(other)).unwrap();
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Aborted);
+    assert_eq!(runs, MUTATION_ATTEMPTS);
+}
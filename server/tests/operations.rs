@@ -0,0 +1,167 @@
+use proto::operations_service_client::OperationsServiceClient;
+use proto::operations_service_server::OperationsServiceServer;
+use proto::{CancelOperationRequest, ListOperationsRequest};
+use server::operations::{Operations, OperationsServiceImpl};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::{Channel, Server};
+use tonic::{Code, Status};
+
+async fn start(operations: Operations) -> OperationsServiceClient<Channel> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .add_service(OperationsServiceServer::new(OperationsServiceImpl::new(
+                operations,
+            )))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    OperationsServiceClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn jobs_report_progress_and_keep_their_result() {
+    let operations = Operations::new();
+    let (go, wait_for_go) = oneshot::channel::<()>();
+    let started = operations.start("count", |progress| async move {
+        progress.set_total(10);
+        progress.advance(4);
+        wait_for_go.await.unwrap();
+        progress.advance(6);
+        Ok::<_, Status>(42u32)
+    });
+    assert_eq!(started.kind, "count");
+    assert!(!started.done);
+
+    // Wait for the job to reach the point where it waits for us
+    while operations.get(&started.id).unwrap().processed_count < 4 {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    let running = operations.get(&started.id).unwrap();
+    assert_eq!((running.processed_count, running.total_count), (4, 10));
+    assert!(!running.done);
+    assert_eq!(operations.result::<u32>(&started.id), None);
+
+    go.send(()).unwrap();
+    let finished = operations.wait(&started.id).await.unwrap();
+    assert!(finished.done);
+    assert_eq!(finished.error, None);
+    assert_eq!(finished.processed_count, 10);
+    assert!(finished.end_time.is_some());
+    assert_eq!(operations.result::<u32>(&started.id).as_deref(), Some(&42));
+    // Asking for the wrong type gets nothing
+    assert_eq!(operations.result::<String>(&started.id), None);
+}
+
+#[tokio::test]
+async fn failed_and_panicking_jobs_set_an_error() {
+    let operations = Operations::new();
+    let failed = operations.start("fail", |_| async {
+        Err::<(), _>(Status::unavailable("Database is down"))
+    });
+    let panicked = operations.start("panic", |_| async {
+        if true {
+            panic!("bug");
+        }
+        Ok::<(), Status>(())
+    });
+
+    let failed = operations.wait(&failed.id).await.unwrap();
+    let error = failed.error.unwrap();
+    assert_eq!(error.code, Code::Unavailable as i32);
+    assert_eq!(error.message, "Database is down");
+    assert_eq!(operations.result::<()>(&failed.id), None);
+
+    let panicked = operations.wait(&panicked.id).await.unwrap();
+    assert_eq!(panicked.error.unwrap().code, Code::Internal as i32);
+}
+
+#[tokio::test]
+async fn operations_can_be_cancelled() {
+    let operations = Operations::new();
+    let mut client = start(operations.clone()).await;
+    let forever = operations.start("forever", |_| async {
+        std::future::pending::<()>().await;
+        Ok::<(), Status>(())
+    });
+    let quick = operations.start("quick", |_| async { Ok::<(), Status>(()) });
+    operations.wait(&quick.id).await.unwrap();
+
+    client
+        .cancel_operation(CancelOperationRequest {
+            id: forever.id.clone(),
+        })
+        .await
+        .unwrap();
+    let cancelled = operations.wait(&forever.id).await.unwrap();
+    assert_eq!(cancelled.error.unwrap().code, Code::Cancelled as i32);
+
+    // Too late to cancel
+    let quick_after = client
+        .cancel_operation(CancelOperationRequest {
+            id: quick.id.clone(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(quick_after.done);
+    assert_eq!(quick_after.error, None);
+
+    let status = client
+        .cancel_operation(CancelOperationRequest {
+            id: "nope".to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn operations_are_listed_oldest_first() {
+    let operations = Operations::new();
+    let mut client = start(operations.clone()).await;
+    let mut ids = Vec::new();
+    for kind in ["a", "b", "a", "a"] {
+        let operation = operations.start(kind, |_| async { Ok::<(), Status>(()) });
+        ids.push(operation.id);
+    }
+
+    let list = |kind: &str, page_token: &str| ListOperationsRequest {
+        kind: kind.to_string(),
+        page_size: 2,
+        page_token: page_token.to_string(),
+    };
+    let first = client
+        .list_operations(list("a", ""))
+        .await
+        .unwrap()
+        .into_inner();
+    let second = client
+        .list_operations(list("a", &first.next_page_token))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(second.next_page_token.is_empty());
+    let listed: Vec<String> = first
+        .operations
+        .into_iter()
+        .chain(second.operations)
+        .map(|operation| operation.id)
+        .collect();
+    assert_eq!(listed, vec![ids[0].clone(), ids[2].clone(), ids[3].clone()]);
+
+    let everything = client
+        .list_operations(ListOperationsRequest {
+            page_size: 10,
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(everything.operations.len(), 4);
+}
@@ -0,0 +1,139 @@
+use proto::student_service_server::StudentService;
+use proto::{
+    AcademicStanding, CreateStudentRequest, ListStudentsByStandingRequest, ListStudentsRequest,
+    Student,
+};
+use server::clock::FixedClock;
+use server::pagination::PageTokens;
+use server::StudentServiceImpl;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tonic::{Code, Request, Status};
+
+const SECRET: &[u8] = b"a secret shared by every instance";
+
+async fn service(page_tokens: PageTokens) -> StudentServiceImpl {
+    let service = StudentServiceImpl::new().with_page_tokens(page_tokens);
+    for index in 0..5 {
+        service
+            .create_student(Request::new(CreateStudentRequest {
+                student: Some(Student {
+                    id: format!("s{}", index),
+                    name: format!("Student {}", index),
+                    email: format!("s{}@university.edu", index),
+                    age: 20,
+                    ..Default::default()
+                }),
+            }))
+            .await
+            .unwrap();
+    }
+    service
+}
+
+async fn list(service: &StudentServiceImpl, page_token: &str) -> Result<Vec<String>, Status> {
+    let page = service
+        .list_students(Request::new(ListStudentsRequest {
+            page_size: 2,
+            page_token: page_token.to_string(),
+            ..Default::default()
+        }))
+        .await?
+        .into_inner();
+    let mut ids: Vec<String> = page
+        .students
+        .into_iter()
+        .map(|student| student.id)
+        .collect();
+    ids.push(page.next_page_token);
+    Ok(ids)
+}
+
+fn reason(status: &Status) -> String {
+    assert_eq!(status.code(), Code::InvalidArgument);
+    client::error_info(status).unwrap().reason
+}
+
+#[tokio::test]
+async fn only_tokens_handed_out_are_accepted() {
+    let service = service(PageTokens::new(SECRET)).await;
+
+    let first = list(&service, "").await.unwrap();
+    assert_eq!(first[..2], ["s0", "s1"]);
+    let token = first[2].clone();
+    assert_eq!(list(&service, &token).await.unwrap()[..2], ["s2", "s3"]);
+
+    // A bare offset, or the signed one pointed elsewhere
+    let status = list(&service, "4").await.unwrap_err();
+    assert_eq!(reason(&status), "PAGE_TOKEN_INVALID");
+    let (expires, rest) = token.split_once('.').unwrap();
+    let forged = format!("{}.{}", expires, rest.replacen('2', "4", 1));
+    let status = list(&service, &forged).await.unwrap_err();
+    assert_eq!(reason(&status), "PAGE_TOKEN_INVALID");
+    // A later expiry on the same token
+    let extended = format!(
+        "{}{}",
+        expires.parse::<u64>().unwrap() + 3600,
+        &token[expires.len()..]
+    );
+    let status = list(&service, &extended).await.unwrap_err();
+    assert_eq!(reason(&status), "PAGE_TOKEN_INVALID");
+}
+
+#[tokio::test]
+async fn tokens_belong_to_their_listing() {
+    let service = service(PageTokens::new(SECRET)).await;
+
+    let page = service
+        .list_students_by_standing(Request::new(ListStudentsByStandingRequest {
+            standing: AcademicStanding::Good.into(),
+            page_size: 2,
+            page_token: String::new(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!page.next_page_token.is_empty());
+
+    let status = list(&service, &page.next_page_token).await.unwrap_err();
+    assert_eq!(reason(&status), "PAGE_TOKEN_INVALID");
+    let status = service
+        .list_students_by_standing(Request::new(ListStudentsByStandingRequest {
+            standing: AcademicStanding::Probation.into(),
+            page_size: 2,
+            page_token: page.next_page_token,
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(reason(&status), "PAGE_TOKEN_INVALID");
+}
+
+#[tokio::test]
+async fn tokens_expire() {
+    let clock = Arc::new(FixedClock::new(
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000),
+    ));
+    let page_tokens = PageTokens::new(SECRET)
+        .with_ttl(Duration::from_secs(600))
+        .with_clock(clock.clone());
+    let service = service(page_tokens).await;
+
+    let token = list(&service, "").await.unwrap()[2].clone();
+    clock.advance(Duration::from_secs(599));
+    assert!(list(&service, &token).await.is_ok());
+    clock.advance(Duration::from_secs(1));
+    let status = list(&service, &token).await.unwrap_err();
+    assert_eq!(reason(&status), "PAGE_TOKEN_EXPIRED");
+}
+
+#[tokio::test]
+async fn instances_sharing_a_secret_share_tokens() {
+    let one = service(PageTokens::new(SECRET)).await;
+    let other = service(PageTokens::new(SECRET)).await;
+    let stranger = service(PageTokens::random()).await;
+
+    let token = list(&one, "").await.unwrap()[2].clone();
+    assert_eq!(list(&other, &token).await.unwrap()[..2], ["s2", "s3"]);
+    let status = list(&stranger, &token).await.unwrap_err();
+    assert_eq!(reason(&status), "PAGE_TOKEN_INVALID");
+}
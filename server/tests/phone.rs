@@ -0,0 +1,138 @@
+use proto::student_service_server::StudentService;
+use proto::{CreateStudentRequest, Student, ValidateStudentRequest};
+use server::phone::{self, normalize};
+use server::validation::Profile;
+use server::StudentServiceImpl;
+use student_core::Problem;
+use tonic::{Code, Request};
+
+fn student(phone_numbers: &[&str]) -> Student {
+    Student {
+        id: "s1".to_string(),
+        name: "Ada Lovelace".to_string(),
+        email: "ada@university.edu".to_string(),
+        age: 36,
+        phone_numbers: phone_numbers
+            .iter()
+            .map(|number| number.to_string())
+            .collect(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn numbers_are_stored_in_e164_form() {
+    let us = phone::parse_region("us").unwrap();
+    assert_eq!(
+        normalize("(650) 253-0000", Some(us)),
+        Ok("+16502530000".to_string())
+    );
+    assert_eq!(
+        normalize("+44 20 7031 3000", Some(us)),
+        Ok("+442070313000".to_string())
+    );
+    assert_eq!(
+        normalize("+44 20 7031 3000", None),
+        Ok("+442070313000".to_string())
+    );
+    let gb = phone::parse_region("GB").unwrap();
+    assert_eq!(
+        normalize("020 7031 3000", Some(gb)),
+        Ok("+442070313000".to_string())
+    );
+    assert!(phone::parse_region("Narnia").is_err());
+}
+
+#[test]
+fn bad_numbers_say_what_is_wrong() {
+    let us = phone::parse_region("US").unwrap();
+    assert_eq!(
+        normalize("650 253 0000", None),
+        Err(Problem::PhoneRegionRequired("650 253 0000".to_string()))
+    );
+    for bad in [
+        "12",
+        "+1 555",
+        "+999 1234 5678",
+        "call me",
+        "+1 650 253 0000 ext. 12",
+    ] {
+        assert_eq!(
+            normalize(bad, Some(us)),
+            Err(Problem::PhoneInvalid(bad.to_string())),
+            "{}",
+            bad
+        );
+    }
+    assert_eq!(
+        Problem::PhoneInvalid("12".to_string()).metadata()["phone_number"],
+        "12"
+    );
+}
+
+#[test]
+fn profiles_tidy_or_reject_phone_numbers() {
+    let us = phone::parse_region("US").unwrap();
+    let mut lenient = student(&["(650) 253-0000", " ", "+1 650 253 0000", "+442070313000"]);
+    assert!(Profile::Lenient
+        .phone_numbers(&mut lenient, Some(us))
+        .is_empty());
+    assert_eq!(lenient.phone_numbers, ["+16502530000", "+442070313000"]);
+    // Already canonical
+    assert!(Profile::Strict.phone_numbers(&mut lenient, None).is_empty());
+
+    let mut strict = student(&["(650) 253-0000", "+442070313000", "+442070313000"]);
+    assert_eq!(
+        Profile::Strict.phone_numbers(&mut strict, Some(us)),
+        [
+            (
+                "phone_numbers",
+                Problem::PhoneNotCanonical("(650) 253-0000".to_string())
+            ),
+            (
+                "phone_numbers",
+                Problem::PhoneNotCanonical("+442070313000".to_string())
+            ),
+        ]
+    );
+    assert_eq!(strict.phone_numbers.len(), 3);
+}
+
+#[tokio::test]
+async fn the_service_reads_national_numbers_in_its_region() {
+    let without_region = StudentServiceImpl::new();
+    let status = without_region
+        .create_student(Request::new(CreateStudentRequest {
+            student: Some(student(&["650-253-0000"])),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let info = client::error_info(&status).unwrap();
+    assert_eq!(info.reason, "PHONE_REGION_REQUIRED");
+    assert_eq!(info.metadata["phone_number"], "650-253-0000");
+
+    let us = StudentServiceImpl::new().with_phone_region(phone::parse_region("US").unwrap());
+    let created = us
+        .create_student(Request::new(CreateStudentRequest {
+            student: Some(student(&["650-253-0000"])),
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .student
+        .unwrap();
+    assert_eq!(created.phone_numbers, ["+16502530000"]);
+
+    let response = us
+        .validate_student(Request::new(ValidateStudentRequest {
+            student: Some(student(&["+16502530000", "not a number"])),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!response.valid);
+    assert_eq!(response.violations.len(), 1);
+    assert_eq!(response.violations[0].field, "phone_numbers");
+    assert!(response.violations[0].description.contains("not a number"));
+}
@@ -0,0 +1,139 @@
+use proto::student_service_server::StudentService;
+use proto::{CreateStudentRequest, Student, ValidateStudentRequest};
+use server::plugins::Plugin;
+use server::StudentServiceImpl;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tonic::{Code, Request};
+
+fn ada() -> Student {
+    Student {
+        id: "s1".to_string(),
+        name: "Ada".to_string(),
+        email: "ada@gmail.com".to_string(),
+        age: 20,
+        ..Default::default()
+    }
+}
+
+// Where this run's plugins are written
+fn dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("plugins-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// A plugin whose `check` runs `body`, with `reply` at address 0 of its memory
+fn plugin(name: &str, reply: &str, body: &str) -> Result<Plugin, String> {
+    let path = dir().join(format!("{}.wat", name));
+    let wat = format!(
+        r#"(module
+             (import "host" "log" (func $log (param i32 i32)))
+             (memory (export "memory") 1)
+             (data (i32.const 0) "{}")
+             (func (export "alloc") (param i32) (result i32) (i32.const 4096))
+             (func (export "check") (param i32 i32) (result i64) {}))"#,
+        reply.replace('"', "\\\""),
+        body
+    );
+    std::fs::write(&path, wat).unwrap();
+    let plugin = Plugin::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    plugin
+}
+
+// Replies with the `len` bytes at address 0
+fn reply(len: usize) -> String {
+    format!("(i64.const {})", len)
+}
+
+fn service(plugins: Vec<Plugin>) -> StudentServiceImpl {
+    plugins
+        .into_iter()
+        .fold(StudentServiceImpl::new(), |service, plugin| {
+            service.with_write_hook(Arc::new(plugin))
+        })
+}
+
+#[tokio::test]
+async fn plugins_reject_and_enrich_students() {
+    let rejection = r#"{"field":"email","message":"Use a university.edu address"}"#;
+    let reject = plugin("reject", rejection, &reply(rejection.len())).unwrap();
+    let status = service(vec![reject])
+        .create_student(Request::new(CreateStudentRequest {
+            student: Some(ada()),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(
+        status.message(),
+        "Rejected by reject: Use a university.edu address"
+    );
+    assert_eq!(client::error_info(&status).unwrap().reason, "HOOK_REJECTED");
+
+    let reject = plugin("reject", rejection, &reply(rejection.len())).unwrap();
+    let response = service(vec![reject])
+        .validate_student(Request::new(ValidateStudentRequest {
+            student: Some(ada()),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!response.valid);
+    assert_eq!(response.violations[0].field, "email");
+
+    // The plugin's student is written, under the ID it was given
+    let enriched =
+        r#"{"student":{"id":"s2","name":"Ada Lovelace","email":"ada@university.edu","age":20}}"#;
+    let log = format!(
+        "(call $log (i32.const 0) (i32.const 10)) {}",
+        reply(enriched.len())
+    );
+    let enrich = plugin("enrich", enriched, &log).unwrap();
+    let created = service(vec![enrich])
+        .create_student(Request::new(CreateStudentRequest {
+            student: Some(ada()),
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .student
+        .unwrap();
+    assert_eq!(
+        (
+            created.id.as_str(),
+            created.name.as_str(),
+            created.email.as_str()
+        ),
+        ("s1", "Ada Lovelace", "ada@university.edu")
+    );
+}
+
+#[tokio::test]
+async fn broken_plugins_fail_the_write() {
+    // Out of fuel instead of running forever
+    let spin = plugin("spin", "", "(loop (br 0)) (i64.const 0)").unwrap();
+    let status = service(vec![spin])
+        .create_student(Request::new(CreateStudentRequest {
+            student: Some(ada()),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Internal);
+
+    let garbled = plugin("garbled", "not json", &reply(8)).unwrap();
+    let status = service(vec![garbled])
+        .create_student(Request::new(CreateStudentRequest {
+            student: Some(ada()),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Internal);
+
+    // A module without the exports is refused when it is loaded
+    let path = dir().join("empty.wat");
+    std::fs::write(&path, "(module)").unwrap();
+    assert!(Plugin::load(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
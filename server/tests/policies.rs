@@ -0,0 +1,153 @@
+use proto::student_service_client::StudentServiceClient;
+use proto::student_service_server::StudentServiceServer;
+use proto::{CreateStudentRequest, Student};
+use server::policies::{Policy, PolicyLayer};
+use server::StudentServiceImpl;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::{Channel, Server};
+use tonic::Code;
+
+const UNIVERSITY_ONLY: &str = r#"
+    if !request.student.email.ends_with("@university.edu") {
+        "Use a university.edu address"
+    }
+"#;
+
+fn student(id: &str, email: &str) -> Student {
+    Student {
+        id: id.to_string(),
+        name: "Ada".to_string(),
+        email: email.to_string(),
+        age: 20,
+        ..Default::default()
+    }
+}
+
+// Writes `script` to `path`, marked as changed `age` from now so that
+// rewrites within the same second are seen
+fn write(path: &Path, script: &str, age: u64) {
+    std::fs::write(path, script).unwrap();
+    File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(age))
+        .unwrap();
+}
+
+fn script(name: &str, script: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("policies-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{}.rhai", name));
+    write(&path, script, 0);
+    path
+}
+
+async fn start(layer: PolicyLayer) -> StudentServiceClient<Channel> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .layer(layer)
+            .add_service(StudentServiceServer::new(StudentServiceImpl::new()))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    let channel = Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    StudentServiceClient::new(channel)
+}
+
+async fn create(
+    client: &mut StudentServiceClient<Channel>,
+    student: Student,
+) -> Result<Student, tonic::Status> {
+    let response = client
+        .create_student(CreateStudentRequest {
+            student: Some(student),
+        })
+        .await?;
+    Ok(response.into_inner().student.unwrap())
+}
+
+#[tokio::test]
+async fn policies_allow_deny_and_change_requests() {
+    let path = script("create", UNIVERSITY_ONLY);
+    let policy = Arc::new(Policy::load(&path).unwrap());
+    let mut client = start(
+        PolicyLayer::new()
+            .with_policy("CreateStudent", policy)
+            .unwrap(),
+    )
+    .await;
+
+    let status = create(&mut client, student("s1", "ada@gmail.com"))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    assert_eq!(
+        status.message(),
+        "Rejected by create: Use a university.edu address"
+    );
+    create(&mut client, student("s1", "ada@university.edu"))
+        .await
+        .unwrap();
+
+    // Changes to the script apply to the next request
+    write(
+        &path,
+        "request.student.name = request.student.name.to_upper(); request",
+        10,
+    );
+    let created = create(&mut client, student("s2", "ada@gmail.com"))
+        .await
+        .unwrap();
+    assert_eq!(created.name, "ADA");
+
+    // A script that no longer compiles leaves the last good one in effect
+    write(&path, "if {", 20);
+    let created = create(&mut client, student("s3", "ada@gmail.com"))
+        .await
+        .unwrap();
+    assert_eq!(created.name, "ADA");
+
+    // Scripts that fail, or return something else, fail the call
+    write(&path, "request.nickname = 1; request", 30);
+    let status = create(&mut client, student("s4", "ada@gmail.com"))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Internal);
+    write(&path, "loop {}", 40);
+    let status = create(&mut client, student("s4", "ada@gmail.com"))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Internal);
+}
+
+#[test]
+fn policies_need_a_known_rpc() {
+    let path = script("any", UNIVERSITY_ONLY);
+    let policy = Arc::new(Policy::load(&path).unwrap());
+    let layer = PolicyLayer::new();
+    assert!(layer
+        .clone()
+        .with_policy("student.StudentService/CreateStudent", policy.clone())
+        .is_ok());
+    assert!(layer
+        .clone()
+        .with_policy("CreateStudents", policy.clone())
+        .is_err());
+    assert!(layer
+        .with_policy("student.BulkService/CreateStudent", policy)
+        .is_err());
+
+    let broken = script("broken", "if {");
+    assert!(Policy::load(&broken).is_err());
+}
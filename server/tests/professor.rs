@@ -0,0 +1,264 @@
+use proto::professor_service_client::ProfessorServiceClient;
+use proto::professor_service_server::ProfessorServiceServer;
+use proto::student_service_client::StudentServiceClient;
+use proto::student_service_server::StudentServiceServer;
+use proto::{
+    AdviseePolicy, AssignAdvisorRequest, CreateProfessorRequest, CreateStudentRequest,
+    DeleteProfessorRequest, DeleteStudentRequest, GetAdvisorRequest, ListAdviseesRequest,
+    Professor, Student,
+};
+use server::catalog::Catalog;
+use server::professor::ProfessorServiceImpl;
+use server::repository::{InMemoryRepository, StudentRepository};
+use server::StudentServiceImpl;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::{Channel, Server};
+use tonic::Code;
+
+struct Clients {
+    students: StudentServiceClient<Channel>,
+    professors: ProfessorServiceClient<Channel>,
+}
+
+async fn start() -> Clients {
+    let store: Arc<dyn StudentRepository> = Arc::new(InMemoryRepository::new());
+    let catalog = Arc::new(Catalog::new());
+    let students = StudentServiceImpl::new()
+        .with_repository(store.clone())
+        .with_catalog(catalog.clone());
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .add_service(StudentServiceServer::new(students))
+            .add_service(ProfessorServiceServer::new(ProfessorServiceImpl::new(
+                catalog, store,
+            )))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+
+    let channel = Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    Clients {
+        students: StudentServiceClient::new(channel.clone()),
+        professors: ProfessorServiceClient::new(channel),
+    }
+}
+
+async fn create_student(clients: &mut Clients, id: &str) {
+    clients
+        .students
+        .create_student(CreateStudentRequest {
+            student: Some(Student {
+                id: id.to_string(),
+                name: id.to_string(),
+                email: format!("{}@university.edu", id),
+                age: 20,
+                major: "Mathematics".to_string(),
+                ..Default::default()
+            }),
+        })
+        .await
+        .unwrap();
+}
+
+async fn create_professor(clients: &mut Clients, id: &str) {
+    clients
+        .professors
+        .create_professor(CreateProfessorRequest {
+            professor: Some(Professor {
+                id: id.to_string(),
+                name: id.to_string(),
+                email: format!("{}@university.edu", id),
+                ..Default::default()
+            }),
+        })
+        .await
+        .unwrap();
+}
+
+async fn assign(clients: &mut Clients, student_id: &str, professor_id: &str) -> String {
+    clients
+        .professors
+        .assign_advisor(AssignAdvisorRequest {
+            student_id: student_id.to_string(),
+            professor_id: professor_id.to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .previous_professor_id
+}
+
+async fn advisees(clients: &mut Clients, professor_id: &str) -> Vec<String> {
+    clients
+        .professors
+        .list_advisees(ListAdviseesRequest {
+            professor_id: professor_id.to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .students
+        .into_iter()
+        .map(|student| student.id)
+        .collect()
+}
+
+async fn delete(
+    clients: &mut Clients,
+    id: &str,
+    policy: AdviseePolicy,
+    reassign_to: &str,
+) -> Result<i32, tonic::Status> {
+    let response = clients
+        .professors
+        .delete_professor(DeleteProfessorRequest {
+            id: id.to_string(),
+            advisee_policy: policy.into(),
+            reassign_to: reassign_to.to_string(),
+        })
+        .await?;
+    Ok(response.into_inner().affected_advisees)
+}
+
+#[tokio::test]
+async fn advisors_are_assigned_and_listed() {
+    let mut clients = start().await;
+    for id in ["ada", "grace", "alan"] {
+        create_student(&mut clients, id).await;
+    }
+    create_professor(&mut clients, "knuth").await;
+    create_professor(&mut clients, "hopper").await;
+
+    assert_eq!(assign(&mut clients, "grace", "knuth").await, "");
+    assert_eq!(assign(&mut clients, "ada", "knuth").await, "");
+    assert_eq!(assign(&mut clients, "alan", "knuth").await, "");
+    assert_eq!(assign(&mut clients, "alan", "hopper").await, "knuth");
+    assert_eq!(advisees(&mut clients, "knuth").await, vec!["ada", "grace"]);
+
+    let advisor = clients
+        .professors
+        .get_advisor(GetAdvisorRequest {
+            student_id: "alan".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .professor
+        .unwrap();
+    assert_eq!(advisor.id, "hopper");
+
+    // An empty professor ID removes the advisor
+    assert_eq!(assign(&mut clients, "alan", "").await, "hopper");
+    let status = clients
+        .professors
+        .get_advisor(GetAdvisorRequest {
+            student_id: "alan".to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    // Unknown students and professors are rejected
+    let status = clients
+        .professors
+        .assign_advisor(AssignAdvisorRequest {
+            student_id: "nobody".to_string(),
+            professor_id: "knuth".to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    let status = clients
+        .professors
+        .assign_advisor(AssignAdvisorRequest {
+            student_id: "ada".to_string(),
+            professor_id: "nobody".to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    // Deleted students drop out of the list
+    clients
+        .students
+        .delete_student(DeleteStudentRequest {
+            id: "grace".to_string(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(advisees(&mut clients, "knuth").await, vec!["ada"]);
+}
+
+#[tokio::test]
+async fn professors_with_advisees_need_a_policy_to_be_deleted() {
+    let mut clients = start().await;
+    create_student(&mut clients, "ada").await;
+    create_professor(&mut clients, "knuth").await;
+    create_professor(&mut clients, "hopper").await;
+    assign(&mut clients, "ada", "knuth").await;
+
+    let status = delete(&mut clients, "knuth", AdviseePolicy::Unspecified, "")
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+
+    // Reassigning needs another professor that exists
+    for target in ["", "knuth", "nobody"] {
+        let status = delete(&mut clients, "knuth", AdviseePolicy::Reassign, target)
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+    assert_eq!(advisees(&mut clients, "knuth").await, vec!["ada"]);
+
+    // Without advisees no policy is needed
+    assert_eq!(
+        delete(&mut clients, "hopper", AdviseePolicy::Unspecified, "")
+            .await
+            .unwrap(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn deleting_a_professor_reassigns_or_unassigns_advisees() {
+    let mut clients = start().await;
+    for id in ["ada", "grace"] {
+        create_student(&mut clients, id).await;
+    }
+    create_professor(&mut clients, "knuth").await;
+    create_professor(&mut clients, "hopper").await;
+    assign(&mut clients, "ada", "knuth").await;
+    assign(&mut clients, "grace", "knuth").await;
+
+    assert_eq!(
+        delete(&mut clients, "knuth", AdviseePolicy::Reassign, "hopper")
+            .await
+            .unwrap(),
+        2
+    );
+    assert_eq!(advisees(&mut clients, "hopper").await, vec!["ada", "grace"]);
+
+    assert_eq!(
+        delete(&mut clients, "hopper", AdviseePolicy::Unassign, "")
+            .await
+            .unwrap(),
+        2
+    );
+    let status = clients
+        .professors
+        .get_advisor(GetAdvisorRequest {
+            student_id: "ada".to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
@@ -0,0 +1,150 @@
+use proto::quota_service_server::QuotaService;
+use proto::{GetUsageRequest, Student};
+use server::flags;
+use server::metrics::Metrics;
+use server::quota::{
+    parse_quota, Limits, QuotaRepository, QuotaServiceImpl, Quotas, QUOTA_WARNINGS, TENANT_STUDENTS,
+};
+use server::repository::{InMemoryRepository, StudentRepository, Transaction};
+use std::sync::Arc;
+use tonic::{Code, Request, Status};
+
+fn student(id: &str) -> Student {
+    Student {
+        id: id.to_string(),
+        name: format!("Student {}", id),
+        ..Default::default()
+    }
+}
+
+fn quotas(metrics: &Metrics) -> Arc<Quotas> {
+    let limits = Limits {
+        default: None,
+        tenants: [("acme".to_string(), 5)].into(),
+    };
+    Arc::new(Quotas::new(limits).with_metrics(metrics.clone()))
+}
+
+async fn create(store: &QuotaRepository, tenant: &str, id: &str) -> Result<Student, Status> {
+    flags::scope(Some(tenant.to_string()), store.create(student(id))).await
+}
+
+#[tokio::test]
+async fn tenants_are_warned_before_their_quota_runs_out() {
+    let metrics = Metrics::new();
+    let quotas = quotas(&metrics);
+    let store = QuotaRepository::new(Arc::new(InMemoryRepository::new()), quotas.clone());
+    let warnings = || metrics.get(&QUOTA_WARNINGS, &[("tenant", "acme")]);
+
+    for id in ["a", "b", "c"] {
+        create(&store, "acme", id).await.unwrap();
+    }
+    assert!(!quotas.usage("acme").warning);
+    create(&store, "acme", "d").await.unwrap();
+    let usage = quotas.usage("acme");
+    assert_eq!(
+        (usage.student_count, usage.quota, usage.warning),
+        (4, 5, true)
+    );
+    assert_eq!(warnings(), 1.0);
+
+    // Warned about once, until it drops below the warning share again
+    create(&store, "acme", "e").await.unwrap();
+    let error = create(&store, "acme", "f").await.unwrap_err();
+    assert_eq!(error.code(), Code::ResourceExhausted);
+    assert_eq!(
+        error.message(),
+        "Tenant acme has reached its quota of 5 students"
+    );
+    assert_eq!(warnings(), 1.0);
+    store.delete("e").await.unwrap();
+    store.delete("d").await.unwrap();
+    assert!(!quotas.usage("acme").warning);
+    create(&store, "acme", "d").await.unwrap();
+    assert_eq!(warnings(), 2.0);
+    assert_eq!(metrics.get(&TENANT_STUDENTS, &[("tenant", "acme")]), 4.0);
+    assert!(metrics
+        .render()
+        .contains("student_quota_warnings_total{tenant=\"acme\"} 2\n"));
+
+    // Other tenants, and requests without one, have no quota here
+    for id in ["g", "h", "i", "j", "k", "l"] {
+        create(&store, "globex", id).await.unwrap();
+    }
+    store.create(student("m")).await.unwrap();
+    let usage = quotas.usage("globex");
+    assert_eq!(
+        (usage.student_count, usage.quota, usage.warning),
+        (6, 0, false)
+    );
+}
+
+#[tokio::test]
+async fn batches_and_transactions_count_against_the_quota() {
+    let quotas = quotas(&Metrics::new());
+    let store = QuotaRepository::new(Arc::new(InMemoryRepository::new()), quotas.clone());
+    let acme = |call| flags::scope(Some("acme".to_string()), call);
+
+    let batch = ["a", "b", "c", "a"].map(student).to_vec();
+    let results = acme(store.create_many(batch)).await;
+    let codes: Vec<Code> = results
+        .iter()
+        .map(|result| result.as_ref().map_or_else(Status::code, |_| Code::Ok))
+        .collect();
+    assert_eq!(codes, [Code::Ok, Code::Ok, Code::Ok, Code::AlreadyExists]);
+    assert_eq!(quotas.usage("acme").student_count, 3);
+
+    let too_many = Transaction::new()
+        .with_delete("a")
+        .with_create(student("d"))
+        .with_create(student("e"))
+        .with_create(student("f"));
+    let failure = flags::scope(Some("acme".to_string()), store.commit(too_many))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        (failure.index, failure.status.code()),
+        (Some(3), Code::ResourceExhausted)
+    );
+    assert_eq!(quotas.usage("acme").student_count, 3);
+
+    let fits = Transaction::new()
+        .with_delete("a")
+        .with_create(student("d"))
+        .with_create(student("e"));
+    flags::scope(Some("acme".to_string()), store.commit(fits))
+        .await
+        .unwrap();
+    assert_eq!(quotas.usage("acme").student_count, 4);
+}
+
+#[tokio::test]
+async fn usage_is_reported_for_the_tenant_asked_about() {
+    let quotas = quotas(&Metrics::new());
+    let store = QuotaRepository::new(Arc::new(InMemoryRepository::new()), quotas.clone());
+    create(&store, "acme", "a").await.unwrap();
+    let service = QuotaServiceImpl::new(quotas);
+
+    let named = GetUsageRequest {
+        tenant: "acme".to_string(),
+    };
+    let usage = service.get_usage(Request::new(named)).await.unwrap();
+    assert_eq!(usage.get_ref().student_count, 1);
+    let own = flags::scope(
+        Some("acme".to_string()),
+        service.get_usage(Request::new(GetUsageRequest::default())),
+    )
+    .await
+    .unwrap();
+    assert_eq!(own.into_inner(), usage.into_inner());
+    let error = service
+        .get_usage(Request::new(GetUsageRequest::default()))
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), Code::InvalidArgument);
+
+    assert_eq!(parse_quota("acme=5000"), Ok(("acme".to_string(), 5000)));
+    for bad in ["acme", "=5", "acme=lots", "acme=0"] {
+        assert!(parse_quota(bad).is_err(), "{}", bad);
+    }
+}
@@ -0,0 +1,128 @@
+use proto::student_service_server::StudentService;
+use proto::{
+    CreateStudentRequest, DeleteStudentRequest, GetStudentRequest, ListStudentsRequest, Student,
+    UpdateStudentRequest, ValidateStudentRequest,
+};
+use serde::Serialize;
+use serde_json::Value;
+use server::recording::{Recorder, Replayer};
+use server::StudentServiceImpl;
+use tonic::{Code, Request, Response, Status};
+
+type Outcome = Result<Value, (Code, String)>;
+
+fn outcome<T: Serialize>(result: Result<Response<T>, Status>) -> Outcome {
+    result
+        .map(|response| serde_json::to_value(response.get_ref()).unwrap())
+        .map_err(|status| (status.code(), status.message().to_string()))
+}
+
+// The same calls, against a live service or a replay of one: each request
+// follows from the answers before it, as a client's would
+async fn session(service: &impl StudentService) -> Vec<Outcome> {
+    let ada = Student {
+        name: "Ada Lovelace".to_string(),
+        email: "ada@university.edu".to_string(),
+        age: 36,
+        gpa_decimal: "3.90".to_string(),
+        ..Default::default()
+    };
+    let mut outcomes = Vec::new();
+
+    let created = service
+        .create_student(Request::new(CreateStudentRequest {
+            student: Some(ada.clone()),
+        }))
+        .await;
+    let mut student = created
+        .as_ref()
+        .map(|response| response.get_ref().student.clone().unwrap())
+        .unwrap();
+    outcomes.push(outcome(created));
+
+    student.major = "Mathematics".to_string();
+    let update = UpdateStudentRequest {
+        student: Some(student.clone()),
+    };
+    outcomes.push(outcome(
+        service.update_student(Request::new(update.clone())).await,
+    ));
+    // A stale etag the second time
+    outcomes.push(outcome(service.update_student(Request::new(update)).await));
+    outcomes.push(outcome(
+        service
+            .get_student(Request::new(GetStudentRequest {
+                id: student.id.clone(),
+            }))
+            .await,
+    ));
+    outcomes.push(outcome(
+        service
+            .list_students(Request::new(ListStudentsRequest {
+                page_size: 10,
+                ..Default::default()
+            }))
+            .await,
+    ));
+    outcomes.push(outcome(
+        service
+            .validate_student(Request::new(ValidateStudentRequest {
+                student: Some(Student {
+                    name: String::new(),
+                    ..ada
+                }),
+            }))
+            .await,
+    ));
+    outcomes.push(outcome(
+        service
+            .delete_student(Request::new(DeleteStudentRequest { id: student.id }))
+            .await,
+    ));
+    outcomes.push(outcome(
+        service
+            .get_student(Request::new(GetStudentRequest {
+                id: "missing".to_string(),
+            }))
+            .await,
+    ));
+    outcomes
+}
+
+#[tokio::test]
+async fn replays_answer_as_the_recorded_service_did() {
+    let path = std::env::temp_dir().join(format!("recording-{}.jsonl", uuid::Uuid::new_v4()));
+    let recorder = Recorder::to_file(StudentServiceImpl::new(), &path).unwrap();
+    let recorded = session(&recorder).await;
+    drop(recorder);
+    let codes: Vec<_> = recorded
+        .iter()
+        .map(|outcome| outcome.as_ref().err().map(|(code, _)| *code))
+        .collect();
+    assert_eq!(
+        codes,
+        [
+            None,
+            None,
+            Some(Code::Aborted),
+            None,
+            None,
+            None,
+            None,
+            Some(Code::NotFound)
+        ]
+    );
+
+    let replayer = Replayer::from_file(&path).unwrap();
+    assert_eq!(session(&replayer).await, recorded);
+
+    // Each exchange answers once
+    let status = replayer
+        .get_student(Request::new(GetStudentRequest {
+            id: "missing".to_string(),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unimplemented);
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,160 @@
+use proto::student_service_client::StudentServiceClient;
+use proto::student_service_server::StudentServiceServer;
+use proto::{
+    ChangeType, CreateStudentRequest, DeleteStudentRequest, Student, StudentEvent, Timestamp,
+    UpdateStudentRequest,
+};
+use server::metrics::Metrics;
+use server::replication::{
+    ConflictPolicy, Outcome, Replicator, REPLICATED_CHANGES, REPLICATION_CONNECTED,
+};
+use server::repository::{InMemoryRepository, StudentRepository};
+use server::StudentServiceImpl;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::{Channel, Server};
+
+fn student(id: &str, updated: i64) -> Student {
+    Student {
+        id: id.to_string(),
+        name: format!("Student {}", id),
+        email: format!("{}@university.edu", id),
+        update_time: Some(Timestamp {
+            seconds: updated,
+            nanos: 0,
+        }),
+        ..Default::default()
+    }
+}
+
+fn event(change: ChangeType, student: Student) -> StudentEvent {
+    StudentEvent {
+        change_type: change as i32,
+        student: Some(student),
+        resume_token: String::new(),
+    }
+}
+
+// Waits for the replica to have `ids`, in order
+async fn eventually_has(store: &InMemoryRepository, ids: &[&str]) {
+    for _ in 0..200 {
+        let page = store.list(100, "").await.unwrap();
+        let stored: Vec<&str> = page.students.iter().map(|s| s.id.as_str()).collect();
+        if stored == ids {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("The replica never had {:?}", ids);
+}
+
+#[tokio::test]
+async fn the_replica_copies_the_primary_then_follows_its_changes() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .add_service(StudentServiceServer::new(StudentServiceImpl::new()))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    let channel = Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    let mut primary = StudentServiceClient::new(channel);
+    let create = |id: &str| CreateStudentRequest {
+        student: Some(student(id, 0)),
+    };
+    primary.create_student(create("a")).await.unwrap();
+    primary.create_student(create("b")).await.unwrap();
+
+    let replica = Arc::new(InMemoryRepository::new());
+    let metrics = Metrics::new();
+    let replicator = Replicator::connect_lazy(&addr.to_string(), replica.clone())
+        .unwrap()
+        .with_metrics(metrics.clone());
+    tokio::spawn(Arc::new(replicator).run());
+    eventually_has(&replica, &["a", "b"]).await;
+
+    primary.create_student(create("c")).await.unwrap();
+    let mut renamed = replica.get("a").await.unwrap();
+    renamed.name = "Renamed".to_string();
+    renamed.etag.clear();
+    let request = UpdateStudentRequest {
+        student: Some(renamed),
+    };
+    primary.update_student(request).await.unwrap();
+    let request = DeleteStudentRequest {
+        id: "b".to_string(),
+    };
+    primary.delete_student(request).await.unwrap();
+    eventually_has(&replica, &["a", "c"]).await;
+    for _ in 0..200 {
+        if replica.get("a").await.unwrap().name == "Renamed" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(replica.get("a").await.unwrap().name, "Renamed");
+
+    assert_eq!(metrics.get(&REPLICATION_CONNECTED, &[]), 1.0);
+    assert_eq!(
+        metrics.get(&REPLICATED_CHANGES, &[("outcome", "applied")]),
+        5.0
+    );
+    assert!(metrics.render().contains("replication_lag_seconds "));
+}
+
+#[tokio::test]
+async fn conflicts_are_settled_by_the_policy() {
+    let replica = Arc::new(InMemoryRepository::new());
+    // Never connected to; changes are applied directly
+    let replicator = |policy| {
+        Replicator::connect_lazy("127.0.0.1:9", replica.clone())
+            .unwrap()
+            .with_policy(policy)
+    };
+    let last_write_wins = replicator(ConflictPolicy::LastWriteWins);
+    let primary_wins = replicator(ConflictPolicy::PrimaryWins);
+
+    // Written here after the primary's version
+    let mut local = student("a", 2000);
+    local.name = "Written here".to_string();
+    replica.create(local).await.unwrap();
+    let older = event(ChangeType::Updated, student("a", 1000));
+    assert_eq!(
+        last_write_wins.apply(&older).await.unwrap(),
+        Outcome::Conflict
+    );
+    assert_eq!(replica.get("a").await.unwrap().name, "Written here");
+    let deleted = event(ChangeType::Deleted, student("a", 1000));
+    assert_eq!(
+        last_write_wins.apply(&deleted).await.unwrap(),
+        Outcome::Conflict
+    );
+
+    // A newer version from the primary wins, and applying it twice is harmless
+    let newer = event(ChangeType::Updated, student("a", 3000));
+    assert_eq!(
+        last_write_wins.apply(&newer).await.unwrap(),
+        Outcome::Applied
+    );
+    assert_eq!(
+        last_write_wins.apply(&newer).await.unwrap(),
+        Outcome::Unchanged
+    );
+    assert_eq!(replica.get("a").await.unwrap().name, "Student a");
+
+    assert_eq!(primary_wins.apply(&older).await.unwrap(), Outcome::Applied);
+    assert_eq!(
+        primary_wins.apply(&deleted).await.unwrap(),
+        Outcome::Applied
+    );
+    assert!(replica.get("a").await.is_err());
+
+    assert_eq!("primary-wins".parse(), Ok(ConflictPolicy::PrimaryWins));
+    assert!("newest".parse::<ConflictPolicy>().is_err());
+}
@@ -0,0 +1,115 @@
+use proto::batch_write_entry::Write as Entry;
+use proto::student_service_client::StudentServiceClient;
+use proto::student_service_server::StudentServiceServer;
+use proto::{
+    BatchWriteEntry, BatchWriteRequest, CreateStudentRequest, ListStudentsRequest, Student,
+};
+use server::locale::LocaleLayer;
+use server::required::RequiredLayer;
+use server::StudentServiceImpl;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::{Channel, Server};
+use tonic::{Code, Request, Status};
+
+async fn start() -> StudentServiceClient<Channel> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .layer(LocaleLayer)
+            .layer(RequiredLayer::new())
+            .add_service(StudentServiceServer::new(StudentServiceImpl::new()))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    StudentServiceClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap()
+}
+
+fn student(name: &str) -> Student {
+    Student {
+        name: name.to_string(),
+        email: format!("{}@university.edu", name.to_lowercase()),
+        age: 20,
+        ..Default::default()
+    }
+}
+
+fn missing(status: &Status) -> String {
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let info = client::error_info(status).unwrap();
+    assert_eq!(info.reason, "FIELD_REQUIRED");
+    info.metadata["field"].clone()
+}
+
+#[tokio::test]
+async fn unset_required_fields_are_turned_away() {
+    let mut client = start().await;
+
+    let status = client
+        .create_student(CreateStudentRequest { student: None })
+        .await
+        .unwrap_err();
+    assert_eq!(missing(&status), "student");
+    assert_eq!(status.message(), "student is required");
+
+    // Nested requirements are checked wherever the message is set
+    let entries = vec![
+        BatchWriteEntry {
+            write: Some(Entry::Create(student("Ada"))),
+        },
+        BatchWriteEntry { write: None },
+    ];
+    let status = client
+        .batch_write(BatchWriteRequest { entries })
+        .await
+        .unwrap_err();
+    assert_eq!(missing(&status), "entries[1].write");
+
+    // Nothing reached the handlers
+    let listed = client
+        .list_students(ListStudentsRequest::default())
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(listed.total_count, 0);
+}
+
+#[tokio::test]
+async fn complete_requests_reach_the_handler() {
+    let mut client = start().await;
+
+    let created = client
+        .create_student(CreateStudentRequest {
+            student: Some(student("Ada")),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .student
+        .unwrap();
+    assert_eq!(created.name, "Ada");
+
+    // A set but empty student is the handler's to judge
+    let status = client
+        .create_student(CreateStudentRequest {
+            student: Some(Student::default()),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(client::error_info(&status).unwrap().reason, "NAME_EMPTY");
+}
+
+#[tokio::test]
+async fn errors_are_in_the_callers_language() {
+    let mut client = start().await;
+
+    let mut request = Request::new(CreateStudentRequest { student: None });
+    request
+        .metadata_mut()
+        .insert("accept-language", "es".parse().unwrap());
+    let status = client.create_student(request).await.unwrap_err();
+    assert_eq!(missing(&status), "student");
+    assert_eq!(status.message(), "student es obligatorio");
+}
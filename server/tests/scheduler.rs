@@ -0,0 +1,254 @@
+use proto::scheduler_service_client::SchedulerServiceClient;
+use proto::scheduler_service_server::SchedulerServiceServer;
+use proto::{ListScheduledTasksRequest, RunTaskNowRequest, Student};
+use server::notify::{Alerts, MockNotifier};
+use server::operations::Operations;
+use server::repository::{InMemoryRepository, StudentRepository};
+use server::scheduler::{Schedule, Scheduler, SchedulerServiceImpl};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::TcpListener;
+use tokio::sync::Notify;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::{Channel, Server};
+use tonic::{Code, Status};
+
+// 2024-09-02 09:00 UTC
+const MONDAY: u64 = 1_725_267_600;
+
+fn at(seconds: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(seconds)
+}
+
+#[test]
+fn schedules_parse_and_print() {
+    for text in [
+        "every 15m",
+        "every 2h",
+        "every 90s",
+        "every 1d",
+        "daily 03:00",
+        "off",
+    ] {
+        let schedule: Schedule = text.parse().unwrap();
+        assert_eq!(schedule.to_string(), text);
+    }
+    assert_eq!(
+        "every 60m".parse::<Schedule>().unwrap().to_string(),
+        "every 1h"
+    );
+    for bad in [
+        "",
+        "every",
+        "every 0m",
+        "every 5",
+        "every 5w",
+        "every 18446744073709551615d",
+        "every 307445734561825861h",
+        "daily 24:00",
+        "weekly",
+    ] {
+        assert!(bad.parse::<Schedule>().is_err(), "{:?}", bad);
+    }
+}
+
+#[test]
+fn next_runs_follow_the_schedule() {
+    let every = Schedule::Every(Duration::from_secs(15 * 60));
+    assert_eq!(every.next_after(at(MONDAY)), Some(at(MONDAY + 15 * 60)));
+
+    let nightly: Schedule = "daily 03:00".parse().unwrap();
+    let tonight = MONDAY - 9 * 3600 + 27 * 3600;
+    assert_eq!(nightly.next_after(at(MONDAY)), Some(at(tonight)));
+    // Exactly at the time, the next run is a day later
+    assert_eq!(
+        nightly.next_after(at(tonight)),
+        Some(at(tonight + 24 * 3600))
+    );
+    let morning: Schedule = "daily 09:30".parse().unwrap();
+    assert_eq!(morning.next_after(at(MONDAY)), Some(at(MONDAY + 30 * 60)));
+
+    assert_eq!(Schedule::Off.next_after(at(MONDAY)), None);
+    // An interval too long to add to the clock never comes due
+    let never = Schedule::Every(Duration::from_secs(u64::MAX));
+    assert_eq!(never.next_after(at(MONDAY)), None);
+}
+
+#[tokio::test]
+async fn tasks_run_on_demand_as_operations() {
+    let operations = Operations::new();
+    let release = Arc::new(Notify::new());
+    let mut scheduler = Scheduler::new(operations.clone());
+    let waiting = release.clone();
+    scheduler.add("slow", Schedule::Off, move |progress| {
+        let waiting = waiting.clone();
+        async move {
+            progress.set_total(1);
+            waiting.notified().await;
+            progress.advance(1);
+            Ok::<_, Status>(())
+        }
+    });
+    scheduler.add("daily", "daily 03:00".parse().unwrap(), |_| async {
+        Ok::<_, Status>(())
+    });
+    let scheduler = Arc::new(scheduler);
+    scheduler.start();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .add_service(SchedulerServiceServer::new(SchedulerServiceImpl::new(
+                scheduler,
+            )))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    let mut client: SchedulerServiceClient<Channel> =
+        SchedulerServiceClient::connect(format!("http://{}", addr))
+            .await
+            .unwrap();
+    let run = |name: &str| RunTaskNowRequest {
+        name: name.to_string(),
+    };
+
+    let operation = client.run_task_now(run("slow")).await.unwrap().into_inner();
+    assert_eq!(operation.kind, "slow");
+    let status = client.run_task_now(run("slow")).await.unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    let status = client.run_task_now(run("nope")).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    let tasks = client
+        .list_scheduled_tasks(ListScheduledTasksRequest {})
+        .await
+        .unwrap()
+        .into_inner()
+        .tasks;
+    let names: Vec<&str> = tasks.iter().map(|task| task.name.as_str()).collect();
+    assert_eq!(names, vec!["daily", "slow"]);
+    assert_eq!(tasks[0].schedule, "daily 03:00");
+    assert!(tasks[0].next_run_time.is_some());
+    assert!(tasks[0].last_operation_id.is_empty());
+    assert_eq!(tasks[1].schedule, "off");
+    assert_eq!(tasks[1].next_run_time, None);
+    assert_eq!(tasks[1].last_operation_id, operation.id);
+
+    release.notify_one();
+    let finished = operations.wait(&operation.id).await.unwrap();
+    assert_eq!(finished.error, None);
+    assert_eq!(finished.processed_count, 1);
+    // Finished, so it can run again
+    client.run_task_now(run("slow")).await.unwrap();
+}
+
+#[tokio::test]
+async fn interval_tasks_run_by_themselves() {
+    let operations = Operations::new();
+    let ran = Arc::new(Notify::new());
+    let mut scheduler = Scheduler::new(operations.clone());
+    let notify = ran.clone();
+    scheduler.add("tick", "every 1s".parse().unwrap(), move |_| {
+        notify.notify_one();
+        async { Ok::<_, Status>(()) }
+    });
+    Arc::new(scheduler).start();
+
+    tokio::time::timeout(Duration::from_secs(5), ran.notified())
+        .await
+        .unwrap();
+    assert_eq!(operations.list("tick").len(), 1);
+}
+
+#[tokio::test]
+async fn failed_scheduled_runs_alert_the_admin() {
+    let notifier = Arc::new(MockNotifier::new());
+    let mut scheduler = Scheduler::new(Operations::new())
+        .with_alerts(Alerts::new(notifier.clone(), "admin@university.edu"));
+    scheduler.add("flaky", "every 1s".parse().unwrap(), |_| async {
+        Err::<(), _>(Status::unavailable("disk full"))
+    });
+    Arc::new(scheduler).start();
+
+    let sent = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let sent = notifier.sent();
+            if !sent.is_empty() {
+                return sent;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(sent[0].to, "admin@university.edu");
+    assert_eq!(sent[0].subject, "Scheduled task flaky failed");
+    assert!(sent[0].body.ends_with("failed: disk full"));
+}
+
+#[tokio::test]
+async fn finished_operations_are_purged() {
+    let operations = Operations::new();
+    let done = operations.start("done", |_| async { Ok::<_, Status>(()) });
+    operations.wait(&done.id).await.unwrap();
+    let running = operations.start("running", |_| async {
+        std::future::pending::<()>().await;
+        Ok::<_, Status>(())
+    });
+
+    assert_eq!(operations.purge(at(MONDAY)), 0);
+    assert_eq!(
+        operations.purge(SystemTime::now() + Duration::from_secs(1)),
+        1
+    );
+    assert_eq!(operations.get(&done.id).unwrap_err().code(), Code::NotFound);
+    assert!(operations.get(&running.id).is_ok());
+}
+
+#[tokio::test]
+async fn snapshots_hold_every_student() {
+    let store = Arc::new(InMemoryRepository::new());
+    for index in 0..150 {
+        store
+            .create(Student {
+                id: format!("s{:03}", index),
+                name: format!("Student {}", index),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+    let dir = std::env::temp_dir().join(format!("snapshots-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir(&dir).unwrap();
+
+    let operations = Operations::new();
+    let (task_store, task_dir) = (store.clone(), dir.clone());
+    let operation = operations.start("snapshot", move |progress| async move {
+        server::snapshot::write(
+            task_store.as_ref(),
+            None,
+            &task_dir,
+            at(MONDAY),
+            None,
+            &progress,
+        )
+        .await
+    });
+    let operation = operations.wait(&operation.id).await.unwrap();
+    assert_eq!(operation.processed_count, 150);
+    let path = operations
+        .result::<std::path::PathBuf>(&operation.id)
+        .unwrap();
+    assert_eq!(*path, dir.join(format!("students-{}.jsonl", MONDAY)));
+
+    let contents = std::fs::read_to_string(&*path).unwrap();
+    let students: Vec<Student> = contents
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(students.len(), 150);
+    assert_eq!(students[42], store.get("s042").await.unwrap());
+    // Nothing but the finished snapshot and its manifest is left behind
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
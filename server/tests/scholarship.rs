@@ -0,0 +1,285 @@
+use proto::operations_service_client::OperationsServiceClient;
+use proto::operations_service_server::OperationsServiceServer;
+use proto::scholarship_service_client::ScholarshipServiceClient;
+use proto::scholarship_service_server::ScholarshipServiceServer;
+use proto::{
+    EvaluateScholarshipsRequest, GetOperationRequest, ListStudentsResponse, Operation,
+    ScholarshipCriteria, ScholarshipResult, StreamScholarshipResultsRequest, Student,
+};
+use server::operations::{Operations, OperationsServiceImpl};
+use server::repository::{InMemoryRepository, StudentRepository};
+use server::scholarship::ScholarshipServiceImpl;
+use server::standing::StandingRules;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::{Channel, Server};
+use tonic::{Code, Status};
+
+fn student(id: &str, major_id: &str, gpa: f64, credits: i32) -> Student {
+    Student {
+        id: id.to_string(),
+        name: id.to_string(),
+        major_id: major_id.to_string(),
+        gpa,
+        credits,
+        ..Default::default()
+    }
+}
+
+struct Clients {
+    scholarships: ScholarshipServiceClient<Channel>,
+    operations: OperationsServiceClient<Channel>,
+}
+
+async fn start(store: Arc<dyn StudentRepository>) -> Clients {
+    let operations = Operations::new();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .add_service(ScholarshipServiceServer::new(ScholarshipServiceImpl::new(
+                store,
+                StandingRules::default(),
+                operations.clone(),
+            )))
+            .add_service(OperationsServiceServer::new(OperationsServiceImpl::new(
+                operations,
+            )))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    let channel = Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    Clients {
+        scholarships: ScholarshipServiceClient::new(channel.clone()),
+        operations: OperationsServiceClient::new(channel),
+    }
+}
+
+async fn evaluate(
+    client: &mut ScholarshipServiceClient<Channel>,
+    criteria: ScholarshipCriteria,
+) -> Operation {
+    client
+        .evaluate_scholarships(EvaluateScholarshipsRequest {
+            criteria: Some(criteria),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+}
+
+// Poll until the operation is done
+async fn wait(client: &mut OperationsServiceClient<Channel>, id: &str) -> Operation {
+    loop {
+        let operation = client
+            .get_operation(GetOperationRequest { id: id.to_string() })
+            .await
+            .unwrap()
+            .into_inner();
+        if operation.done {
+            return operation;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+async fn results(
+    client: &mut ScholarshipServiceClient<Channel>,
+    operation_id: &str,
+    eligible_only: bool,
+) -> Result<Vec<ScholarshipResult>, Status> {
+    let mut stream = client
+        .stream_scholarship_results(StreamScholarshipResultsRequest {
+            operation_id: operation_id.to_string(),
+            eligible_only,
+        })
+        .await?
+        .into_inner();
+    let mut results = Vec::new();
+    while let Some(result) = stream.message().await? {
+        results.push(result);
+    }
+    Ok(results)
+}
+
+#[tokio::test]
+async fn evaluation_runs_in_the_background() {
+    let store = Arc::new(InMemoryRepository::new());
+    for index in 0..250 {
+        let gpa = if index % 2 == 0 { 3.9 } else { 3.0 };
+        store
+            .create(student(&format!("s{:03}", index), "", gpa, 30))
+            .await
+            .unwrap();
+    }
+    let Clients {
+        scholarships: mut client,
+        mut operations,
+    } = start(store).await;
+
+    let started = evaluate(
+        &mut client,
+        ScholarshipCriteria {
+            min_gpa: 3.5,
+            ..Default::default()
+        },
+    )
+    .await;
+    assert!(!started.id.is_empty());
+    assert!(!started.done);
+    assert!(started.create_time.is_some());
+
+    let finished = wait(&mut operations, &started.id).await;
+    assert_eq!(finished.processed_count, 250);
+    assert_eq!(finished.total_count, 250);
+    assert_eq!(finished.error, None);
+    assert!(finished.end_time.is_some());
+
+    let all = results(&mut client, &started.id, false).await.unwrap();
+    assert_eq!(all.len(), 250);
+    assert_eq!(
+        all[1],
+        ScholarshipResult {
+            student_id: "s001".to_string(),
+            student_name: "s001".to_string(),
+            eligible: false,
+            reasons: vec!["GPA 3.00 is below 3.50".to_string()],
+        }
+    );
+    let eligible = results(&mut client, &started.id, true).await.unwrap();
+    assert_eq!(eligible.len(), 125);
+    assert!(eligible.iter().all(|result| result.eligible));
+}
+
+#[tokio::test]
+async fn results_wait_for_the_operation_and_apply_every_criterion() {
+    let store = Arc::new(InMemoryRepository::new());
+    store.create(student("ada", "math", 3.8, 40)).await.unwrap();
+    store
+        .create(student("grace", "math", 3.8, 4))
+        .await
+        .unwrap();
+    store
+        .create(student("alan", "math", 1.5, 40))
+        .await
+        .unwrap();
+    store
+        .create(student("edsger", "cs", 4.0, 40))
+        .await
+        .unwrap();
+    let mut client = start(store).await.scholarships;
+
+    let operation = evaluate(
+        &mut client,
+        ScholarshipCriteria {
+            min_gpa: 1.0,
+            min_credits: 12,
+            major_id: "math".to_string(),
+        },
+    )
+    .await;
+    // Asked for straight away, so this waits for the job to finish
+    let results = results(&mut client, &operation.id, false).await.unwrap();
+    let outcomes: Vec<(&str, bool, Vec<&str>)> = results
+        .iter()
+        .map(|result| {
+            (
+                result.student_id.as_str(),
+                result.eligible,
+                result.reasons.iter().map(String::as_str).collect(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        outcomes,
+        vec![
+            ("ada", true, vec![]),
+            ("alan", false, vec!["On academic probation"]),
+            ("grace", false, vec!["4 credits, 12 required"]),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn bad_requests_are_rejected() {
+    let Clients {
+        scholarships: mut client,
+        mut operations,
+    } = start(Arc::new(InMemoryRepository::new())).await;
+
+    let status = client
+        .evaluate_scholarships(EvaluateScholarshipsRequest {
+            criteria: Some(ScholarshipCriteria {
+                min_gpa: 5.0,
+                ..Default::default()
+            }),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    let status = operations
+        .get_operation(GetOperationRequest {
+            id: "nope".to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    let status = results(&mut client, "nope", false).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+// Stores nothing and cannot be listed
+#[derive(Debug)]
+struct BrokenRepository;
+
+#[tonic::async_trait]
+impl StudentRepository for BrokenRepository {
+    async fn create(&self, student: Student) -> Result<Student, Status> {
+        Ok(student)
+    }
+
+    async fn get(&self, _id: &str) -> Result<Student, Status> {
+        Err(Status::not_found("Student not found"))
+    }
+
+    async fn update(&self, student: Student) -> Result<Student, Status> {
+        Ok(student)
+    }
+
+    async fn delete(&self, _id: &str) -> Result<Student, Status> {
+        Err(Status::not_found("Student not found"))
+    }
+
+    async fn list(
+        &self,
+        _page_size: usize,
+        _page_token: &str,
+    ) -> Result<ListStudentsResponse, Status> {
+        Err(Status::unavailable("Database is down"))
+    }
+}
+
+#[tokio::test]
+async fn failures_are_reported_on_the_operation() {
+    let Clients {
+        scholarships: mut client,
+        mut operations,
+    } = start(Arc::new(BrokenRepository)).await;
+
+    let operation = evaluate(&mut client, ScholarshipCriteria::default()).await;
+    let failed = wait(&mut operations, &operation.id).await;
+    let error = failed.error.unwrap();
+    assert_eq!(error.code, Code::Unavailable as i32);
+    assert_eq!(error.message, "Database is down");
+
+    let status = results(&mut client, &operation.id, false)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unavailable);
+}
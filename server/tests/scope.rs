@@ -0,0 +1,266 @@
+use proto::batch_write_entry::Write;
+use proto::catalog_service_server::CatalogService;
+use proto::professor_service_server::ProfessorService;
+use proto::student_service_server::StudentService;
+use proto::{
+    AssignAdvisorRequest, BatchWriteEntry, BatchWriteRequest, CreateDepartmentRequest,
+    CreateMajorRequest, CreateProfessorRequest, CreateStudentRequest, DeleteStudentRequest,
+    Department, GetStudentRequest, ListStudentsRequest, Major, Professor, Student,
+    UpdateStudentRequest, WatchStudentsRequest,
+};
+use server::catalog::{Catalog, CatalogServiceImpl};
+use server::identity::{self, Principal};
+use server::professor::ProfessorServiceImpl;
+use server::repository::{InMemoryRepository, StudentRepository};
+use server::scope::ScopedRepository;
+use server::StudentServiceImpl;
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+use tonic::{Code, Request};
+
+// Students s1 (in engineering), s2 (advised by p1), and s3; p1 is in
+// engineering, p2 in no department
+async fn school() -> StudentServiceImpl {
+    let store: Arc<dyn StudentRepository> = Arc::new(InMemoryRepository::new());
+    let catalog = Arc::new(Catalog::new());
+    let catalog_service = CatalogServiceImpl::new(catalog.clone(), store.clone());
+    catalog_service
+        .create_department(Request::new(CreateDepartmentRequest {
+            department: Some(Department {
+                id: "eng".to_string(),
+                name: "Engineering".to_string(),
+            }),
+        }))
+        .await
+        .unwrap();
+    catalog_service
+        .create_major(Request::new(CreateMajorRequest {
+            major: Some(Major {
+                id: "cs".to_string(),
+                name: "Computer Science".to_string(),
+                department_id: "eng".to_string(),
+                ..Default::default()
+            }),
+        }))
+        .await
+        .unwrap();
+
+    let professors = Arc::new(ProfessorServiceImpl::new(catalog.clone(), store.clone()));
+    let scoped = ScopedRepository::new(store, professors.clone(), catalog.clone());
+    let students = StudentServiceImpl::new()
+        .with_scoped_reads(Arc::new(scoped))
+        .with_catalog(catalog);
+    for (id, major_id) in [("s1", "cs"), ("s2", ""), ("s3", "")] {
+        let student = Student {
+            id: id.to_string(),
+            name: id.to_string(),
+            email: format!("{}@university.edu", id),
+            age: 20,
+            major_id: major_id.to_string(),
+            ..Default::default()
+        };
+        students
+            .create_student(Request::new(CreateStudentRequest {
+                student: Some(student),
+            }))
+            .await
+            .unwrap();
+    }
+    for (id, department_id) in [("p1", "eng"), ("p2", "")] {
+        let professor = Professor {
+            id: id.to_string(),
+            name: id.to_string(),
+            department_id: department_id.to_string(),
+            ..Default::default()
+        };
+        professors
+            .create_professor(Request::new(CreateProfessorRequest {
+                professor: Some(professor),
+            }))
+            .await
+            .unwrap();
+    }
+    professors
+        .assign_advisor(Request::new(AssignAdvisorRequest {
+            student_id: "s2".to_string(),
+            professor_id: "p1".to_string(),
+        }))
+        .await
+        .unwrap();
+    students
+}
+
+fn caller(principal: &str) -> Option<Principal> {
+    Some(principal.parse().unwrap())
+}
+
+// The IDs of the students `principal` is shown, and how many it is told there are
+async fn listed(
+    students: &StudentServiceImpl,
+    principal: Option<Principal>,
+    request: ListStudentsRequest,
+) -> (Vec<String>, i32) {
+    let page = identity::scope(principal, students.list_students(Request::new(request)))
+        .await
+        .unwrap()
+        .into_inner();
+    let ids = page
+        .students
+        .into_iter()
+        .map(|student| student.id)
+        .collect();
+    (ids, page.total_count)
+}
+
+#[tokio::test]
+async fn callers_list_only_the_students_in_their_scope() {
+    let students = school().await;
+    let all = ListStudentsRequest::default;
+
+    assert_eq!(
+        listed(&students, caller("professor:p1"), all()).await,
+        (vec!["s1".to_string(), "s2".to_string()], 2)
+    );
+    assert_eq!(
+        listed(&students, caller("professor:p2"), all()).await,
+        (vec![], 0)
+    );
+    assert_eq!(
+        listed(&students, caller("student:s3"), all()).await,
+        (vec!["s3".to_string()], 1)
+    );
+    assert_eq!(listed(&students, None, all()).await, (vec![], 0));
+    assert_eq!(listed(&students, caller("staff:t1"), all()).await.1, 3);
+
+    // Ordering, filtering, and paging work on the visible students only
+    let by_name = ListStudentsRequest {
+        order_by: "name".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(
+        listed(&students, caller("student:s1"), by_name).await,
+        (vec!["s1".to_string()], 1)
+    );
+    let first = ListStudentsRequest {
+        page_size: 1,
+        ..Default::default()
+    };
+    let page = identity::scope(
+        caller("professor:p1"),
+        students.list_students(Request::new(first)),
+    )
+    .await
+    .unwrap()
+    .into_inner();
+    assert_eq!(page.students[0].id, "s1");
+    let rest = ListStudentsRequest {
+        page_size: 1,
+        page_token: page.next_page_token,
+        ..Default::default()
+    };
+    assert_eq!(
+        listed(&students, caller("professor:p1"), rest).await,
+        (vec!["s2".to_string()], 2)
+    );
+}
+
+#[tokio::test]
+async fn hidden_students_are_not_found() {
+    let students = school().await;
+    let get = |principal: &str, id: &str| {
+        identity::scope(
+            caller(principal),
+            students.get_student(Request::new(GetStudentRequest { id: id.to_string() })),
+        )
+    };
+
+    assert!(get("professor:p1", "s2").await.is_ok());
+    assert_eq!(
+        get("professor:p1", "s3").await.unwrap_err().code(),
+        Code::NotFound
+    );
+    assert_eq!(
+        get("student:s1", "s2").await.unwrap_err().code(),
+        Code::NotFound
+    );
+    assert!(get("admin:a1", "s3").await.is_ok());
+
+    // Outside of any request, such as in a scheduled task, nothing is hidden
+    let outside = students
+        .get_student(Request::new(GetStudentRequest {
+            id: "s3".to_string(),
+        }))
+        .await;
+    assert!(outside.is_ok());
+}
+
+#[tokio::test]
+async fn hidden_students_cannot_be_written() {
+    let students = school().await;
+    let stored =
+        |id: &str| students.get_student(Request::new(GetStudentRequest { id: id.to_string() }));
+    let s3 = stored("s3").await.unwrap().into_inner().student.unwrap();
+    let renamed = Student {
+        name: "Renamed".to_string(),
+        ..s3.clone()
+    };
+
+    let update = identity::scope(
+        caller("student:s1"),
+        students.update_student(Request::new(UpdateStudentRequest {
+            student: Some(renamed.clone()),
+        })),
+    )
+    .await;
+    assert_eq!(update.unwrap_err().code(), Code::NotFound);
+    let delete = identity::scope(
+        caller("professor:p1"),
+        students.delete_student(Request::new(DeleteStudentRequest {
+            id: "s3".to_string(),
+        })),
+    )
+    .await;
+    assert_eq!(delete.unwrap_err().code(), Code::NotFound);
+    let batch = identity::scope(
+        caller("student:s1"),
+        students.batch_write(Request::new(BatchWriteRequest {
+            entries: vec![BatchWriteEntry {
+                write: Some(Write::DeleteId("s3".to_string())),
+            }],
+        })),
+    )
+    .await;
+    assert_eq!(batch.unwrap_err().code(), Code::NotFound);
+    assert_eq!(stored("s3").await.unwrap().into_inner().student, Some(s3));
+
+    // Their own record is theirs to write
+    let own = identity::scope(
+        caller("student:s3"),
+        students.update_student(Request::new(UpdateStudentRequest {
+            student: Some(renamed),
+        })),
+    )
+    .await;
+    assert_eq!(own.unwrap().into_inner().student.unwrap().name, "Renamed");
+}
+
+#[tokio::test]
+async fn watchers_hear_only_of_students_in_their_scope() {
+    let students = school().await;
+    let mut events = identity::scope(
+        caller("student:s3"),
+        students.watch_students(Request::new(WatchStudentsRequest::default())),
+    )
+    .await
+    .unwrap()
+    .into_inner();
+
+    for id in ["s1", "s2", "s3"] {
+        students
+            .delete_student(Request::new(DeleteStudentRequest { id: id.to_string() }))
+            .await
+            .unwrap();
+    }
+    let event = events.next().await.unwrap().unwrap();
+    assert_eq!(event.student.unwrap().id, "s3");
+}
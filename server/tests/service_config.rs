@@ -0,0 +1,182 @@
+use client::{ServiceConfig, StudentClient};
+use proto::student_service_server::StudentServiceServer;
+use proto::Student;
+use server::StudentServiceImpl;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::Server;
+use tonic::{Code, Request, Status};
+
+const RETRY_UNAVAILABLE: &str = r#"{
+  "methodConfig": [
+    {
+      "name": [{"service": "student.StudentService"}],
+      "timeout": "5s",
+      "retryPolicy": {
+        "maxAttempts": 4,
+        "initialBackoff": "0.01s",
+        "maxBackoff": "0.05s",
+        "backoffMultiplier": 2,
+        "retryableStatusCodes": ["UNAVAILABLE"]
+      }
+    },
+    {
+      "name": [{"service": "student.StudentService", "method": "DeleteStudent"}],
+      "timeout": "0.2s"
+    }
+  ]
+}"#;
+
+// What the server saw: how many calls, and the deadline each carried
+#[derive(Debug, Default)]
+struct Seen {
+    calls: AtomicUsize,
+    timeouts: Mutex<Vec<String>>,
+}
+
+// A server whose first `failures` calls fail with `status`
+#[allow(clippy::result_large_err)]
+async fn start(failures: usize, status: Status) -> (StudentClient, Arc<Seen>) {
+    let seen = Arc::new(Seen::default());
+    let interceptor = {
+        let seen = seen.clone();
+        move |request: Request<()>| {
+            if let Some(timeout) = request.metadata().get("grpc-timeout") {
+                let timeout = timeout.to_str().unwrap().to_string();
+                seen.timeouts.lock().unwrap().push(timeout);
+            }
+            match seen.calls.fetch_add(1, Ordering::SeqCst) < failures {
+                true => Err(status.clone()),
+                false => Ok(request),
+            }
+        }
+    };
+    let service = StudentServiceImpl::new();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .add_service(StudentServiceServer::with_interceptor(service, interceptor))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    (client(&addr.to_string()), seen)
+}
+
+fn client(addr: &str) -> StudentClient {
+    StudentClient::connect_lazy(format!("http://{}", addr))
+        .unwrap()
+        .with_service_config(ServiceConfig::from_json(RETRY_UNAVAILABLE).unwrap())
+}
+
+fn student(id: &str) -> Student {
+    Student {
+        id: id.to_string(),
+        name: format!("Student {}", id),
+        email: format!("{}@university.edu", id),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn retryable_failures_are_retried_as_the_policy_says() {
+    let (mut client, seen) = start(2, Status::unavailable("Starting up")).await;
+    client.create_student(student("a")).await.unwrap();
+    assert_eq!(seen.calls.load(Ordering::SeqCst), 3);
+    // Every attempt carries what is left of the call's timeout
+    assert_eq!(seen.timeouts.lock().unwrap().len(), 3);
+
+    // The fourth attempt is the last
+    let (mut client, seen) = start(10, Status::unavailable("Starting up")).await;
+    let status = client.get_student("a").await.unwrap_err();
+    assert_eq!(status.code(), Code::Unavailable);
+    assert_eq!(seen.calls.load(Ordering::SeqCst), 4);
+
+    // Other codes are not retried, and neither is a call the server asks not to
+    let (mut client, seen) = start(10, Status::internal("Broken")).await;
+    client.get_student("a").await.unwrap_err();
+    assert_eq!(seen.calls.load(Ordering::SeqCst), 1);
+    let mut pushback = Status::unavailable("Overloaded");
+    pushback
+        .metadata_mut()
+        .insert("grpc-retry-pushback-ms", "-1".parse().unwrap());
+    let (mut client, seen) = start(10, pushback).await;
+    client.get_student("a").await.unwrap_err();
+    assert_eq!(seen.calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn a_method_config_overrides_its_services() {
+    // Takes connections and never answers
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((socket, _)) = listener.accept().await {
+            held.push(socket);
+        }
+    });
+    let started = tokio::time::Instant::now();
+    let status = client(&addr.to_string())
+        .delete_student("a")
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::DeadlineExceeded);
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    let config = ServiceConfig::from_json(RETRY_UNAVAILABLE).unwrap();
+    let delete = config.method("student.StudentService", "DeleteStudent");
+    assert_eq!(delete.unwrap().timeout, Some(Duration::from_millis(200)));
+    assert!(delete.unwrap().retry.is_none());
+    let get = config
+        .method("student.StudentService", "GetStudent")
+        .unwrap();
+    assert_eq!(get.retry.as_ref().unwrap().max_attempts, 4);
+    assert!(config.method("student.BulkService", "Export").is_none());
+}
+
+#[test]
+fn bad_service_configs_are_refused() {
+    let policy = |fields: &str| {
+        format!(
+            r#"{{"methodConfig": [{{"name": [{{}}], "retryPolicy": {{{}}}}}]}}"#,
+            fields
+        )
+    };
+    let complete = r#""maxAttempts": 9, "initialBackoff": "1s", "maxBackoff": "2s", "backoffMultiplier": 1.5, "retryableStatusCodes": ["UNAVAILABLE", 10]"#;
+    let config = ServiceConfig::from_json(&policy(complete)).unwrap();
+    let retry = config
+        .method("any.Service", "Any")
+        .unwrap()
+        .retry
+        .clone()
+        .unwrap();
+    assert_eq!(retry.max_attempts, 5);
+    assert_eq!(retry.retryable_codes, [Code::Unavailable, Code::Aborted]);
+    assert_eq!(retry.backoff(1), Duration::from_secs(1));
+    assert_eq!(retry.backoff(3), Duration::from_secs(2));
+
+    for (fields, error) in [
+        (
+            complete.replace("9", "1"),
+            "methodConfig[0].retryPolicy.maxAttempts",
+        ),
+        (
+            complete.replace("\"1s\"", "\"soon\""),
+            "methodConfig[0].retryPolicy.initialBackoff",
+        ),
+        (
+            complete.replace("\"UNAVAILABLE\"", "\"SLOW\""),
+            "methodConfig[0].retryPolicy.retryableStatusCodes",
+        ),
+    ] {
+        let refused = ServiceConfig::from_json(&policy(&fields)).unwrap_err();
+        assert!(refused.starts_with(error), "{}", refused);
+    }
+    let unnamed_service = r#"{"methodConfig": [{"name": [{"method": "GetStudent"}]}]}"#;
+    assert!(ServiceConfig::from_json(unnamed_service).is_err());
+    let twice = r#"{"methodConfig": [{"name": [{}]}, {"name": [{}]}]}"#;
+    assert!(ServiceConfig::from_json(twice).is_err());
+}
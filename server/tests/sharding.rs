@@ -0,0 +1,189 @@
+use proto::student_service_client::StudentServiceClient;
+use proto::student_service_server::StudentServiceServer;
+use proto::{CreateStudentRequest, GetStudentRequest, ListStudentsRequest, Student};
+use server::repository::{InMemoryRepository, StudentRepository, Transaction};
+use server::sharding::{HashRing, RemoteRepository, ShardedRepository};
+use server::StudentServiceImpl;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::{Channel, Server};
+use tonic::Code;
+
+fn student(id: &str) -> Student {
+    Student {
+        id: id.to_string(),
+        name: format!("Student {}", id),
+        email: format!("{}@university.edu", id),
+        ..Default::default()
+    }
+}
+
+fn sharded(count: usize) -> (ShardedRepository, Vec<Arc<InMemoryRepository>>) {
+    let shards: Vec<Arc<InMemoryRepository>> = (0..count)
+        .map(|_| Arc::new(InMemoryRepository::new()))
+        .collect();
+    let named = shards
+        .iter()
+        .enumerate()
+        .map(|(n, shard)| {
+            let shard: Arc<dyn StudentRepository> = shard.clone();
+            (format!("shard-{}", n), shard)
+        })
+        .collect();
+    (ShardedRepository::new(named), shards)
+}
+
+#[tokio::test]
+async fn students_spread_over_shards_and_list_merged_in_id_order() {
+    let (store, shards) = sharded(3);
+    let ids: Vec<String> = (0..100).map(|n| format!("s{:03}", n)).collect();
+    for result in store
+        .create_many(ids.iter().map(|id| student(id)).collect())
+        .await
+    {
+        result.unwrap();
+    }
+    for shard in &shards {
+        let count = shard.list(1, "").await.unwrap().total_count;
+        assert!(count > 15, "{} students on one shard", count);
+    }
+    store.get("s042").await.unwrap();
+
+    // Pages of 7 go through every student once, in ID order
+    let mut listed = Vec::new();
+    let mut page_token = String::new();
+    loop {
+        let page = store.list(7, &page_token).await.unwrap();
+        assert_eq!(page.total_count, 100);
+        assert!(page.students.len() <= 7);
+        listed.extend(page.students.into_iter().map(|student| student.id));
+        if page.next_page_token.is_empty() {
+            break;
+        }
+        page_token = page.next_page_token;
+    }
+    assert_eq!(listed, ids);
+    let all: Vec<String> = store
+        .all()
+        .await
+        .unwrap()
+        .iter()
+        .map(|student| student.id.clone())
+        .collect();
+    assert_eq!(all, ids);
+
+    let status = store.list(7, "not-a-token").await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn adding_a_shard_only_moves_the_students_it_takes_over() {
+    let three = HashRing::new(&["a", "b", "c"]);
+    let four = HashRing::new(&["a", "b", "c", "d"]);
+    let mut moved = 0;
+    for n in 0..1000 {
+        let id = format!("s{}", n);
+        if three.shard(&id) != four.shard(&id) {
+            assert_eq!(four.shard(&id), 3, "{} moved between old shards", id);
+            moved += 1;
+        }
+    }
+    assert!((150..350).contains(&moved), "{} of 1000 moved", moved);
+}
+
+#[tokio::test]
+async fn transactions_are_committed_by_one_shard() {
+    let (store, _) = sharded(2);
+    let ring = &HashRing::new(&["shard-0", "shard-1"]);
+    let on = |shard: usize| {
+        (0..)
+            .map(|n| format!("s{}", n))
+            .filter(move |id| ring.shard(id) == shard)
+    };
+    let (here, there): (Vec<String>, Vec<String>) =
+        (on(0).take(2).collect(), on(1).take(1).collect());
+
+    let together = Transaction::new()
+        .with_create(student(&here[0]))
+        .with_create(student(&here[1]));
+    assert_eq!(store.commit(together).await.unwrap().len(), 2);
+
+    let apart = Transaction::new()
+        .with_delete(&here[0])
+        .with_create(student(&there[0]));
+    let failure = store.commit(apart).await.unwrap_err();
+    assert_eq!(
+        (failure.index, failure.status.code()),
+        (Some(1), Code::FailedPrecondition)
+    );
+    store.get(&here[0]).await.unwrap();
+}
+
+async fn shard_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .add_service(StudentServiceServer::new(StudentServiceImpl::new()))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    addr.to_string()
+}
+
+#[tokio::test]
+async fn a_router_keeps_students_on_shard_servers() {
+    let mut shards: Vec<(String, Arc<dyn StudentRepository>)> = Vec::new();
+    for _ in 0..2 {
+        let address = shard_server().await;
+        let remote = RemoteRepository::connect_lazy(&address).unwrap();
+        shards.push((address, Arc::new(remote)));
+    }
+    let router =
+        StudentServiceImpl::new().with_repository(Arc::new(ShardedRepository::new(shards)));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .add_service(StudentServiceServer::new(router))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    let channel = Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    let mut client = StudentServiceClient::new(channel);
+
+    let mut created = HashSet::new();
+    for n in 0..20 {
+        let request = CreateStudentRequest {
+            student: Some(student(&format!("s{:02}", n))),
+        };
+        let student = client.create_student(request).await.unwrap().into_inner();
+        created.insert(student.student.unwrap().id);
+    }
+    let request = GetStudentRequest {
+        id: "s07".to_string(),
+    };
+    client.get_student(request).await.unwrap();
+
+    let mut listed = HashSet::new();
+    let mut page_token = String::new();
+    loop {
+        let request = ListStudentsRequest {
+            page_size: 6,
+            page_token,
+            ..Default::default()
+        };
+        let page = client.list_students(request).await.unwrap().into_inner();
+        assert_eq!(page.total_count, 20);
+        listed.extend(page.students.into_iter().map(|student| student.id));
+        if page.next_page_token.is_empty() {
+            break;
+        }
+        page_token = page.next_page_token;
+    }
+    assert_eq!(listed, created);
+}
@@ -0,0 +1,253 @@
+use proto::{ChangeType, Student};
+use server::events::{EventLog, HISTORY};
+use server::operations::Operations;
+use server::repository::{InMemoryRepository, StudentRepository};
+use server::snapshot::{self, Manifest, Restore};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tonic::Code;
+
+async fn snapshot_of(count: usize, dir: &Path) -> PathBuf {
+    let store = Arc::new(InMemoryRepository::new());
+    let students = (1..=count).map(|n| Student {
+        id: format!("s{:04}", n),
+        name: format!("Student {}", n),
+        ..Default::default()
+    });
+    for result in store.create_many(students.collect()).await {
+        result.unwrap();
+    }
+    take(store, None, dir, 1_700_000_000).await
+}
+
+async fn take(
+    store: Arc<InMemoryRepository>,
+    events: Option<Arc<EventLog>>,
+    dir: &Path,
+    seconds: u64,
+) -> PathBuf {
+    let operations = Operations::new();
+    let dir = dir.to_path_buf();
+    let operation = operations.start("snapshot", move |progress| async move {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+        snapshot::write(
+            store.as_ref(),
+            events.as_deref(),
+            &dir,
+            now,
+            None,
+            &progress,
+        )
+        .await
+    });
+    let operation = operations.wait(&operation.id).await.unwrap();
+    (*operations.result::<PathBuf>(&operation.id).unwrap()).clone()
+}
+
+fn temp_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("snapshot-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir(&dir).unwrap();
+    dir
+}
+
+fn forced() -> Restore {
+    Restore {
+        force: true,
+        ..Default::default()
+    }
+}
+
+fn rewrite_manifest(path: &Path, change: impl FnOnce(&mut Manifest)) {
+    let mut manifest = snapshot::manifest(path).unwrap().unwrap();
+    change(&mut manifest);
+    let json = serde_json::to_vec(&manifest).unwrap();
+    std::fs::write(snapshot::manifest_path(path), json).unwrap();
+}
+
+#[tokio::test]
+async fn snapshots_are_checked_against_their_manifest() {
+    let dir = temp_dir();
+    let path = snapshot_of(2500, &dir).await;
+    assert_eq!(
+        snapshot::manifest_path(&path),
+        dir.join("students-1700000000.manifest.json")
+    );
+    let manifest = snapshot::manifest(&path).unwrap().unwrap();
+    assert_eq!(manifest.created.seconds, 1_700_000_000);
+    assert_eq!(manifest.format_version, snapshot::FORMAT_VERSION);
+    assert_eq!(manifest.schema_version, server::migrations::latest());
+    assert_eq!(manifest.student_count, 2500);
+    let sizes: Vec<usize> = manifest.chunks.iter().map(|chunk| chunk.students).collect();
+    assert_eq!(sizes, [1000, 1000, 500]);
+    assert_eq!(snapshot::read(&path, None).unwrap().len(), 2500);
+
+    // A line changed in the second chunk
+    let original = std::fs::read_to_string(&path).unwrap();
+    std::fs::write(&path, original.replace("Student 1500\"", "Student 15\"")).unwrap();
+    let error = snapshot::restore(&path, None, &forced()).unwrap_err();
+    assert!(error.ends_with("students 1001 to 2000 do not match the manifest's checksum"));
+
+    // Cut short: a partial restore is refused, even when forced
+    let cut = original.lines().take(1200).collect::<Vec<_>>().join("\n") + "\n";
+    std::fs::write(&path, cut).unwrap();
+    let error = snapshot::restore(&path, None, &forced()).unwrap_err();
+    assert!(error.ends_with("has 1200 students, but its manifest lists 2500"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn other_formats_and_missing_manifests_need_force() {
+    let dir = temp_dir();
+    let path = snapshot_of(3, &dir).await;
+
+    rewrite_manifest(&path, |manifest| manifest.format_version += 1);
+    let error = snapshot::read(&path, None).unwrap_err();
+    assert!(error.contains("restore it with --force"));
+    assert_eq!(snapshot::restore(&path, None, &forced()).unwrap().len(), 3);
+
+    std::fs::remove_file(snapshot::manifest_path(&path)).unwrap();
+    assert!(snapshot::read(&path, None)
+        .unwrap_err()
+        .contains("has no manifest"));
+    assert_eq!(snapshot::restore(&path, None, &forced()).unwrap().len(), 3);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn snapshots_from_before_a_storage_migration_restore() {
+    let dir = temp_dir();
+    let path = snapshot_of(3, &dir).await;
+
+    // As a server one migration behind wrote it, before the format was
+    // versioned
+    let manifest_path = snapshot::manifest_path(&path);
+    let mut manifest: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&manifest_path).unwrap()).unwrap();
+    manifest["schema_version"] = (server::migrations::latest() - 1).into();
+    manifest.as_object_mut().unwrap().remove("format_version");
+    std::fs::write(&manifest_path, serde_json::to_vec(&manifest).unwrap()).unwrap();
+
+    assert_eq!(
+        snapshot::manifest(&path).unwrap().unwrap().format_version,
+        snapshot::FORMAT_VERSION
+    );
+    assert_eq!(snapshot::read(&path, None).unwrap().len(), 3);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+fn at(seconds: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+}
+
+fn named(id: &str, name: &str) -> Student {
+    Student {
+        id: id.to_string(),
+        name: name.to_string(),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn incremental_snapshots_are_applied_in_order() {
+    let dir = temp_dir();
+    let store = Arc::new(InMemoryRepository::new());
+    let events = Arc::new(EventLog::new());
+    for id in ["a", "b"] {
+        let created = store.create(named(id, "Before")).await.unwrap();
+        events.publish(ChangeType::Created, &created);
+    }
+    let full = take(store, Some(events.clone()), &dir, 100).await;
+    assert_eq!(snapshot::manifest(&full).unwrap().unwrap().sequence, 2);
+    assert_eq!(snapshot::position(&dir, events.id()).unwrap(), Some(2));
+    let none = snapshot::write_changes(&events, 2, &dir, at(110), None).await;
+    assert_eq!(none.unwrap(), None);
+
+    events.publish(ChangeType::Updated, &named("a", "After"));
+    events.publish(ChangeType::Created, &named("c", "New"));
+    let first = snapshot::write_changes(&events, 2, &dir, at(120), None)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(first, dir.join("changes-120.jsonl"));
+    events.publish(ChangeType::Deleted, &named("b", "Before"));
+    let since = snapshot::position(&dir, events.id()).unwrap().unwrap();
+    assert_eq!(since, 4);
+    snapshot::write_changes(&events, since, &dir, at(130), None)
+        .await
+        .unwrap();
+
+    // The changes file only holds the changes; the snapshot alone is as taken
+    let (manifest, changes) = snapshot::read_changes(&first, None, false).unwrap();
+    assert_eq!((manifest.since, manifest.sequence), (Some(2), 4));
+    assert_eq!(changes.len(), 2);
+    assert_eq!(snapshot::read(&full, None).unwrap().len(), 2);
+    let restored = snapshot::restore(&full, None, &Restore::default()).unwrap();
+    let names: Vec<(&str, &str)> = restored
+        .iter()
+        .map(|student| (student.id.as_str(), student.name.as_str()))
+        .collect();
+    assert_eq!(names, [("a", "After"), ("c", "New")]);
+
+    // A missing file in between stops the restore rather than skip changes
+    std::fs::remove_file(&first).unwrap();
+    let error = snapshot::restore(&full, None, &Restore::default()).unwrap_err();
+    assert!(error.starts_with("Changes 3 to 4 are missing"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn changes_are_kept_only_so_far_back() {
+    let events = EventLog::new();
+    for index in 0..HISTORY + 1 {
+        events.publish(ChangeType::Created, &named(&index.to_string(), "x"));
+    }
+    assert_eq!(events.since(1).unwrap().len(), HISTORY);
+    assert_eq!(events.since(0).unwrap_err().code(), Code::OutOfRange);
+}
+
+#[tokio::test]
+async fn snapshots_are_restored_to_a_point_in_time() {
+    let dir = temp_dir();
+    let store = Arc::new(InMemoryRepository::new());
+    let events = Arc::new(EventLog::new());
+    for id in ["a", "b"] {
+        let created = store.create(named(id, "Before")).await.unwrap();
+        events.publish(ChangeType::Created, &created);
+    }
+    take(store, Some(events.clone()), &dir, 100).await;
+    events.publish(ChangeType::Updated, &named("a", "After"));
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    let before_the_delete = server::clock::timestamp(SystemTime::now());
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    events.publish(ChangeType::Deleted, &named("b", "Before"));
+    snapshot::write_changes(&events, 2, &dir, at(120), None)
+        .await
+        .unwrap();
+
+    // Given the directory, the latest snapshot taken by then is used
+    let then = Restore {
+        until: Some(before_the_delete),
+        ..Default::default()
+    };
+    let restored = snapshot::restore(&dir, None, &then).unwrap();
+    let names: Vec<&str> = restored
+        .iter()
+        .map(|student| student.name.as_str())
+        .collect();
+    assert_eq!(names, ["After", "Before"]);
+    let now = snapshot::restore(&dir, None, &Restore::default()).unwrap();
+    assert_eq!(now.len(), 1);
+
+    let too_early = Restore {
+        until: Some(server::clock::timestamp(at(50))),
+        ..Default::default()
+    };
+    let error = snapshot::restore(&dir, None, &too_early).unwrap_err();
+    assert!(error.ends_with("has no snapshot taken by 1970-01-01T00:00:50+00:00"));
+    let full = dir.join("students-100.jsonl");
+    let error = snapshot::restore(&full, None, &too_early).unwrap_err();
+    assert!(error.ends_with("was taken after the time to restore to; restore from an earlier one"));
+    assert!(server::clock::parse_timestamp("yesterday").is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
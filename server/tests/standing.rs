@@ -0,0 +1,186 @@
+use proto::student_service_client::StudentServiceClient;
+use proto::student_service_server::StudentServiceServer;
+use proto::{
+    AcademicStanding, CreateStudentRequest, GetStudentRequest, ListStudentsByStandingRequest,
+    Student, UpdateStudentRequest,
+};
+use server::gpa::Gpa;
+use server::standing::StandingRules;
+use server::StudentServiceImpl;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::{Channel, Server};
+use tonic::Code;
+
+async fn start(rules: StandingRules) -> StudentServiceClient<Channel> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .add_service(StudentServiceServer::new(
+                StudentServiceImpl::new().with_standing_rules(rules),
+            ))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    StudentServiceClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap()
+}
+
+fn student(id: &str, gpa: f64, credits: i32) -> Student {
+    Student {
+        id: id.to_string(),
+        name: id.to_string(),
+        email: format!("{}@example.com", id),
+        age: 20,
+        gpa,
+        credits,
+        ..Default::default()
+    }
+}
+
+async fn create(client: &mut StudentServiceClient<Channel>, student: Student) -> Student {
+    client
+        .create_student(CreateStudentRequest {
+            student: Some(student),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .student
+        .unwrap()
+}
+
+async fn list(
+    client: &mut StudentServiceClient<Channel>,
+    standing: AcademicStanding,
+    page_size: i32,
+    page_token: &str,
+) -> proto::ListStudentsResponse {
+    client
+        .list_students_by_standing(ListStudentsByStandingRequest {
+            standing: standing.into(),
+            page_size,
+            page_token: page_token.to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+}
+
+#[tokio::test]
+async fn standing_follows_gpa_and_credits() {
+    let mut client = start(StandingRules::default()).await;
+
+    let ada = create(&mut client, student("ada", 3.9, 30)).await;
+    assert_eq!(ada.standing(), AcademicStanding::DeansList);
+    // Too few credits for the Dean's list, and none yet for probation
+    let grace = create(&mut client, student("grace", 3.9, 6)).await;
+    assert_eq!(grace.standing(), AcademicStanding::Good);
+    let alan = create(&mut client, student("alan", 1.5, 0)).await;
+    assert_eq!(alan.standing(), AcademicStanding::Good);
+
+    // A standing sent by the client is ignored
+    let alan = client
+        .update_student(UpdateStudentRequest {
+            student: Some(Student {
+                credits: 4,
+                standing: AcademicStanding::DeansList.into(),
+                ..alan
+            }),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .student
+        .unwrap();
+    assert_eq!(alan.standing(), AcademicStanding::Probation);
+
+    let fetched = client
+        .get_student(GetStudentRequest {
+            id: "alan".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .student
+        .unwrap();
+    assert_eq!(fetched.standing(), AcademicStanding::Probation);
+
+    let status = client
+        .create_student(CreateStudentRequest {
+            student: Some(student("bad", 3.0, -1)),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn thresholds_are_configurable() {
+    let mut client = start(StandingRules {
+        deans_list_min_gpa: Gpa::from_hundredths(300),
+        deans_list_min_credits: 0,
+        probation_below_gpa: Gpa::from_hundredths(250),
+        probation_min_credits: 0,
+    })
+    .await;
+
+    let ada = create(&mut client, student("ada", 3.2, 0)).await;
+    assert_eq!(ada.standing(), AcademicStanding::DeansList);
+    let grace = create(&mut client, student("grace", 2.2, 0)).await;
+    assert_eq!(grace.standing(), AcademicStanding::Probation);
+}
+
+#[tokio::test]
+async fn students_are_listed_by_standing() {
+    let mut client = start(StandingRules::default()).await;
+    for index in 0..5 {
+        create(&mut client, student(&format!("dean{}", index), 3.8, 24)).await;
+    }
+    create(&mut client, student("good", 3.0, 24)).await;
+    create(&mut client, student("probation", 1.0, 24)).await;
+
+    let first = list(&mut client, AcademicStanding::DeansList, 3, "").await;
+    assert_eq!(first.total_count, 5);
+    let second = list(
+        &mut client,
+        AcademicStanding::DeansList,
+        3,
+        &first.next_page_token,
+    )
+    .await;
+    assert!(second.next_page_token.is_empty());
+    let ids: Vec<String> = first
+        .students
+        .iter()
+        .chain(&second.students)
+        .map(|student| student.id.clone())
+        .collect();
+    assert_eq!(ids, vec!["dean0", "dean1", "dean2", "dean3", "dean4"]);
+
+    let probation = list(&mut client, AcademicStanding::Probation, 10, "").await;
+    assert_eq!(probation.total_count, 1);
+    assert_eq!(probation.students[0].id, "probation");
+
+    let status = client
+        .list_students_by_standing(ListStudentsByStandingRequest::default())
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
+
+#[test]
+fn rules_are_validated() {
+    assert!(StandingRules::default().validate().is_ok());
+    let upside_down = StandingRules {
+        probation_below_gpa: Gpa::from_hundredths(380),
+        ..StandingRules::default()
+    };
+    assert!(upside_down.validate().is_err());
+    let out_of_range = StandingRules {
+        deans_list_min_gpa: Gpa::from_hundredths(450),
+        ..StandingRules::default()
+    };
+    assert!(out_of_range.validate().is_err());
+}
@@ -0,0 +1,304 @@
+use proto::statistics_service_server::StatisticsService;
+use proto::ChangeType;
+use proto::{GetStatisticsRequest, GetStatisticsResponse, MajorStatistics, Student};
+use server::events::EventLog;
+use server::memory;
+use server::repository::{InMemoryRepository, StudentRepository};
+use server::standing::StandingRules;
+use server::statistics::{CountedRepository, Statistics, StatisticsServiceImpl};
+use std::sync::Arc;
+use tonic::{Code, Request};
+
+fn student(id: &str, major: &str, gpa: f64, credits: i32) -> Student {
+    Student {
+        id: id.to_string(),
+        name: id.to_string(),
+        email: format!("{}@university.edu", id),
+        age: 20,
+        major: major.to_string(),
+        gpa,
+        credits,
+        ..Default::default()
+    }
+}
+
+async fn get(service: &StatisticsServiceImpl) -> GetStatisticsResponse {
+    service
+        .get_statistics(Request::new(GetStatisticsRequest {}))
+        .await
+        .unwrap()
+        .into_inner()
+}
+
+fn major(major: &str, student_count: i64, average_gpa: f64) -> MajorStatistics {
+    MajorStatistics {
+        major: major.to_string(),
+        student_count,
+        average_gpa,
+    }
+}
+
+#[tokio::test]
+async fn statistics_follow_every_change() {
+    let statistics = Arc::new(Statistics::new(StandingRules::default()));
+    let service = StatisticsServiceImpl::new(statistics.clone());
+    let store = CountedRepository::new(Arc::new(InMemoryRepository::new()), statistics);
+    assert_eq!(get(&service).await, GetStatisticsResponse::default());
+
+    store.create(student("ada", "Math", 3.9, 30)).await.unwrap();
+    store
+        .create(student("alan", "Math", 2.1, 10))
+        .await
+        .unwrap();
+    store
+        .create(student("grace", "Physics", 1.5, 20))
+        .await
+        .unwrap();
+    let stats = get(&service).await;
+    assert_eq!(stats.student_count, 3);
+    assert!((stats.average_gpa - 2.5).abs() < 1e-9);
+    assert_eq!(stats.average_credits, 20.0);
+    assert_eq!(
+        stats.majors,
+        vec![major("Math", 2, 3.0), major("Physics", 1, 1.5)]
+    );
+    assert_eq!(
+        (
+            stats.deans_list_count,
+            stats.good_standing_count,
+            stats.probation_count
+        ),
+        (1, 1, 1)
+    );
+
+    // Grace changes major and gets off probation
+    let mut grace = store.get("grace").await.unwrap();
+    grace.major = "Math".to_string();
+    grace.gpa = 3.0;
+    store.update(grace).await.unwrap();
+    // Failed writes change nothing
+    let status = store
+        .create(student("ada", "Art", 4.0, 1))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::AlreadyExists);
+    let mut stale = store.get("alan").await.unwrap();
+    stale.etag = "stale".to_string();
+    stale.gpa = 4.0;
+    assert_eq!(store.update(stale).await.unwrap_err().code(), Code::Aborted);
+    store.delete("ada").await.unwrap();
+
+    let stats = get(&service).await;
+    assert_eq!(stats.student_count, 2);
+    assert_eq!(stats.majors, vec![major("Math", 2, 2.55)]);
+    assert_eq!(
+        (
+            stats.deans_list_count,
+            stats.good_standing_count,
+            stats.probation_count
+        ),
+        (0, 2, 0)
+    );
+
+    store.delete("alan").await.unwrap();
+    store.delete("grace").await.unwrap();
+    let stats = get(&service).await;
+    assert_eq!(stats.student_count, 0);
+    assert_eq!(stats.average_gpa, 0.0);
+    assert!(stats.majors.is_empty());
+}
+
+#[tokio::test]
+async fn students_already_stored_are_counted_once() {
+    let inner = Arc::new(InMemoryRepository::new());
+    for index in 0..250 {
+        let major = if index % 2 == 0 { "Math" } else { "" };
+        inner
+            .create(student(&format!("s{:03}", index), major, 3.0, 15))
+            .await
+            .unwrap();
+    }
+    let statistics = Arc::new(Statistics::new(StandingRules::default()));
+    let store = CountedRepository::new(inner, statistics.clone());
+    store.count_existing().await.unwrap();
+
+    let stats = statistics.get();
+    assert_eq!(stats.student_count, 250);
+    assert_eq!(
+        stats.majors,
+        vec![major("", 125, 3.0), major("Math", 125, 3.0)]
+    );
+    assert_eq!(stats.good_standing_count, 250);
+}
+
+#[tokio::test]
+async fn memory_follows_the_store_and_caps_creates() {
+    // As stored, with an etag like the store's first ones
+    let stored = Student {
+        etag: "0123abcd-1".to_string(),
+        ..student("s000", "Math", 3.0, 15)
+    };
+    let one = memory::student_size(&stored) + memory::index_entry_size("s000");
+    // Room for ten students and a bit
+    let store = Arc::new(InMemoryRepository::new().with_memory_limit(10 * one + one / 2));
+    let events = Arc::new(EventLog::new());
+    let statistics = Arc::new(Statistics::new(StandingRules::default()));
+    let service = StatisticsServiceImpl::new(statistics).with_memory(store.clone(), events.clone());
+    let empty = get(&service).await.memory.unwrap();
+    assert_eq!((empty.records_bytes, empty.index_bytes), (0, 0));
+    assert_eq!(empty.limit_bytes, (10 * one + one / 2) as i64);
+
+    for index in 0..10 {
+        let created = store
+            .create(student(&format!("s{:03}", index), "Math", 3.0, 15))
+            .await
+            .unwrap();
+        events.publish(ChangeType::Created, &created);
+    }
+    let status = store
+        .create(student("s010", "Math", 3.0, 15))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::ResourceExhausted);
+    let results = store
+        .create_many(vec![student("s011", "Math", 3.0, 15)])
+        .await;
+    assert_eq!(
+        results[0].as_ref().unwrap_err().code(),
+        Code::ResourceExhausted
+    );
+
+    let full = get(&service).await.memory.unwrap();
+    let sizes: usize = store
+        .all()
+        .await
+        .unwrap()
+        .iter()
+        .map(|student| memory::student_size(student) + memory::index_entry_size(&student.id))
+        .sum();
+    assert_eq!(full.records_bytes + full.index_bytes, sizes as i64);
+    assert!(full.history_bytes > empty.history_bytes);
+    assert_eq!(
+        full.total_bytes,
+        full.records_bytes + full.index_bytes + full.history_bytes
+    );
+
+    // Updates still go through and are counted at their new size
+    let mut longer = store.get("s000").await.unwrap();
+    longer.name = "A much longer name than before".to_string();
+    store.update(longer).await.unwrap();
+    let updated = store.memory();
+    assert!(updated.records > full.records_bytes as usize);
+
+    // Deleting makes room again, and deleting everything frees it all
+    store.delete("s001").await.unwrap();
+    store
+        .create(student("s010", "Math", 3.0, 15))
+        .await
+        .unwrap();
+    for student in store.all().await.unwrap() {
+        store.delete(&student.id).await.unwrap();
+    }
+    assert_eq!(store.memory().used(), 0);
+}
+
+#[test]
+fn memory_limits_are_parsed_with_units() {
+    assert_eq!(memory::parse_size("4096"), Ok(4096));
+    assert_eq!(memory::parse_size("64k"), Ok(64 << 10));
+    assert_eq!(memory::parse_size("256MiB"), Ok(256 << 20));
+    assert_eq!(memory::parse_size("1 GB"), Ok(1 << 30));
+    for bad in ["", "0", "MiB", "12 parsecs", "-5M", "99999999999999999999G"] {
+        assert!(memory::parse_size(bad).is_err(), "{:?}", bad);
+    }
+}
+
+// A store whose updates of `held` wait until `release` is notified
+#[derive(Debug)]
+struct Held {
+    inner: InMemoryRepository,
+    held: String,
+    release: tokio::sync::Notify,
+}
+
+#[tonic::async_trait]
+impl StudentRepository for Held {
+    async fn create(&self, student: Student) -> Result<Student, tonic::Status> {
+        self.inner.create(student).await
+    }
+
+    async fn get(&self, id: &str) -> Result<Student, tonic::Status> {
+        self.inner.get(id).await
+    }
+
+    async fn update(&self, student: Student) -> Result<Student, tonic::Status> {
+        if student.id == self.held {
+            self.release.notified().await;
+        }
+        self.inner.update(student).await
+    }
+
+    async fn delete(&self, id: &str) -> Result<Student, tonic::Status> {
+        self.inner.delete(id).await
+    }
+
+    async fn list(
+        &self,
+        page_size: usize,
+        page_token: &str,
+    ) -> Result<proto::ListStudentsResponse, tonic::Status> {
+        self.inner.list(page_size, page_token).await
+    }
+}
+
+#[tokio::test]
+async fn updates_wait_only_for_updates_of_the_same_student() {
+    let statistics = Arc::new(Statistics::new(StandingRules::default()));
+    let service = StatisticsServiceImpl::new(statistics.clone());
+    let held = Arc::new(Held {
+        inner: InMemoryRepository::new(),
+        held: "ada".to_string(),
+        release: tokio::sync::Notify::new(),
+    });
+    let store = Arc::new(CountedRepository::new(held.clone(), statistics));
+    store.create(student("ada", "Math", 3.9, 30)).await.unwrap();
+    store
+        .create(student("alan", "Math", 2.1, 10))
+        .await
+        .unwrap();
+
+    let moving = |id: &str, major: &str| {
+        let (store, mut student) = (store.clone(), student(id, major, 3.0, 20));
+        student.etag.clear();
+        tokio::spawn(async move { store.update(student).await })
+    };
+    let ada = moving("ada", "Physics");
+    let ada_again = moving("ada", "Art");
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    // Alan's update goes ahead while Ada's are held up
+    tokio::time::timeout(std::time::Duration::from_secs(5), moving("alan", "Physics"))
+        .await
+        .expect("Alan's update waited on Ada's")
+        .unwrap()
+        .unwrap();
+
+    held.release.notify_one();
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    held.release.notify_one();
+    ada.await.unwrap().unwrap();
+    ada_again.await.unwrap().unwrap();
+    // Each old version of Ada was taken away once
+    let stats = get(&service).await;
+    assert_eq!(stats.student_count, 2);
+    let counts: Vec<(&str, i64)> = stats
+        .majors
+        .iter()
+        .map(|major| (major.major.as_str(), major.student_count))
+        .filter(|(_, count)| *count != 0)
+        .collect();
+    assert!(
+        counts == [("Art", 1), ("Physics", 1)] || counts == [("Physics", 2)],
+        "{:?}",
+        counts
+    );
+}
@@ -0,0 +1,94 @@
+use proto::enrollment_service_client::EnrollmentServiceClient;
+use proto::enrollment_service_server::EnrollmentServiceServer;
+use proto::{GenerateTranscriptRequest, GetCourseRequest, Student, TranscriptFormat};
+use server::enrollment::EnrollmentServiceImpl;
+use server::repository::{InMemoryRepository, StudentRepository};
+use server::timing::{StartLayer, TimedRepository, TimingLayer, SERVER_INSTANCE, SERVER_TIMING};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::metadata::MetadataMap;
+use tonic::transport::{Channel, Server};
+
+async fn start() -> EnrollmentServiceClient<Channel> {
+    let repository = Arc::new(InMemoryRepository::new());
+    repository
+        .create(Student {
+            id: "ada".to_string(),
+            name: "Ada Lovelace".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .layer(TimingLayer::new("test").unwrap())
+            .layer(StartLayer)
+            .add_service(EnrollmentServiceServer::new(EnrollmentServiceImpl::new(
+                Arc::new(TimedRepository(repository)),
+            )))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    EnrollmentServiceClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap()
+}
+
+// The names in `server-timing`, each with its duration
+fn timings(metadata: &MetadataMap) -> Vec<(String, f64)> {
+    metadata
+        .get(SERVER_TIMING)
+        .expect("server-timing")
+        .to_str()
+        .unwrap()
+        .split(", ")
+        .map(|timing| {
+            let (name, duration) = timing.split_once(";dur=").unwrap();
+            (name.to_string(), duration.parse().unwrap())
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn responses_carry_timings_and_the_instance() {
+    let mut client = start().await;
+    let status = client
+        .get_course(GetCourseRequest {
+            id: "missing".to_string(),
+        })
+        .await
+        .unwrap_err();
+
+    let names: Vec<_> = timings(status.metadata())
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    assert_eq!(names, ["queue", "storage", "total"]);
+    assert_eq!(status.metadata().get(SERVER_INSTANCE).unwrap(), "test");
+}
+
+#[tokio::test]
+async fn streams_send_their_timings_again_in_the_trailers() {
+    let mut client = start().await;
+    let mut stream = client
+        .generate_transcript(GenerateTranscriptRequest {
+            student_id: "ada".to_string(),
+            format: TranscriptFormat::Html.into(),
+        })
+        .await
+        .unwrap();
+    let started = timings(stream.metadata());
+    let stream = stream.get_mut();
+    while stream.message().await.unwrap().is_some() {}
+    let trailers = stream.trailers().await.unwrap().unwrap();
+
+    let ended = timings(&trailers);
+    let names: Vec<_> = ended.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["queue", "storage", "total"]);
+    // The total runs on to the end of the stream; the queue ended before it started
+    assert!(ended[2].1 >= started[2].1);
+    assert_eq!(ended[0].1, started[0].1);
+}
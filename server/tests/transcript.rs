@@ -0,0 +1,159 @@
+use proto::enrollment_service_client::EnrollmentServiceClient;
+use proto::enrollment_service_server::EnrollmentServiceServer;
+use proto::{
+    Course, CreateCourseRequest, GenerateTranscriptRequest, RecordGradeRequest, Student,
+    TranscriptEntry, TranscriptFormat,
+};
+use server::enrollment::EnrollmentServiceImpl;
+use server::repository::{InMemoryRepository, StudentRepository};
+use server::transcript::{self, Transcript, TranscriptLine};
+use std::cell::Cell;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::{Channel, Server};
+use tonic::Code;
+
+fn ada() -> Student {
+    Student {
+        id: "ada".to_string(),
+        name: "Ada <Lovelace>".to_string(),
+        major: "Mathematics".to_string(),
+        ..Default::default()
+    }
+}
+
+// Ada has a grade in each of `courses` courses
+async fn start(courses: usize) -> EnrollmentServiceClient<Channel> {
+    let repository = Arc::new(InMemoryRepository::new());
+    repository.create(ada()).await.unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::builder()
+            .add_service(EnrollmentServiceServer::new(EnrollmentServiceImpl::new(
+                repository,
+            )))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    let mut client = EnrollmentServiceClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap();
+
+    for index in 0..courses {
+        let id = format!("c{:03}", index);
+        client
+            .create_course(CreateCourseRequest {
+                course: Some(Course {
+                    id: id.clone(),
+                    title: format!("Course {}", index),
+                    capacity: 10,
+                    ..Default::default()
+                }),
+            })
+            .await
+            .unwrap();
+        client
+            .record_grade(RecordGradeRequest {
+                student_id: "ada".to_string(),
+                entry: Some(TranscriptEntry {
+                    course_id: id,
+                    grade: "A".to_string(),
+                }),
+            })
+            .await
+            .unwrap();
+    }
+    client
+}
+
+// The content types of the chunks and the document they make up
+async fn generate(
+    client: &mut EnrollmentServiceClient<Channel>,
+    format: TranscriptFormat,
+) -> (Vec<String>, Vec<u8>) {
+    let mut chunks = client
+        .generate_transcript(GenerateTranscriptRequest {
+            student_id: "ada".to_string(),
+            format: format.into(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    let mut content_types = Vec::new();
+    let mut document = Vec::new();
+    while let Some(chunk) = chunks.message().await.unwrap() {
+        content_types.push(chunk.content_type);
+        document.extend(chunk.data);
+    }
+    (content_types, document)
+}
+
+#[tokio::test]
+async fn transcripts_are_streamed_as_pdf_or_html() {
+    let mut client = start(2).await;
+
+    let (content_types, html) = generate(&mut client, TranscriptFormat::Html).await;
+    assert_eq!(content_types, vec!["text/html; charset=utf-8"]);
+    let html = String::from_utf8(html).unwrap();
+    assert!(html.contains("Ada &lt;Lovelace&gt;"));
+    assert!(html.contains("<tr><td>c001</td><td>Course 1</td><td>A</td></tr>"));
+
+    let (content_types, pdf) = generate(&mut client, TranscriptFormat::Unspecified).await;
+    assert_eq!(content_types, vec!["application/pdf"]);
+    assert!(pdf.starts_with(b"%PDF-"));
+    assert!(pdf.trim_ascii_end().ends_with(b"%%EOF"));
+
+    let status = client
+        .generate_transcript(GenerateTranscriptRequest {
+            student_id: "nobody".to_string(),
+            format: TranscriptFormat::Pdf.into(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn long_transcripts_span_pages_and_chunks() {
+    let mut client = start(400).await;
+
+    let (content_types, pdf) = generate(&mut client, TranscriptFormat::Pdf).await;
+    assert!(content_types.len() > 1);
+    assert_eq!(content_types[0], "application/pdf");
+    assert!(content_types[1..].iter().all(String::is_empty));
+
+    let pdf = String::from_utf8_lossy(&pdf);
+    assert!(pdf.contains("(Page 1 of 12) Tj"));
+    assert!(pdf.contains("(Page 12 of 12) Tj"));
+}
+
+#[test]
+fn rendering_stops_when_cancelled() {
+    let transcript = Transcript {
+        student: ada(),
+        lines: (0..100)
+            .map(|index| TranscriptLine {
+                course_id: format!("c{}", index),
+                title: String::new(),
+                grade: "B".to_string(),
+            })
+            .collect(),
+    };
+
+    for format in [TranscriptFormat::Pdf, TranscriptFormat::Html] {
+        let checks = Cell::new(0);
+        let cancel_after_ten = || {
+            checks.set(checks.get() + 1);
+            checks.get() > 10
+        };
+        assert_eq!(
+            transcript::render(&transcript, format, cancel_after_ten),
+            None
+        );
+        assert_eq!(checks.get(), 11);
+
+        assert!(transcript::render(&transcript, format, || false).is_some());
+    }
+}
@@ -0,0 +1,144 @@
+use proto::storage_service_server::StorageService;
+use proto::{
+    Address, GetStorageMigrationResultRequest, ListStudentsResponse, MigrateStorageRequest, Student,
+};
+use server::operations::Operations;
+use server::repository::{InMemoryRepository, StudentRepository};
+use server::storage::StorageServiceImpl;
+use server::transfer::{self, Location};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tonic::{Code, Request, Status};
+
+fn students() -> Vec<Student> {
+    (1..=250)
+        .map(|n| Student {
+            id: format!("s{:03}", n),
+            name: format!("Student {}", n),
+            email: format!("s{}@university.edu", n),
+            age: 20,
+            gpa: 3.25,
+            phone_numbers: vec!["+12025550123".to_string()],
+            address: Some(Address {
+                city: "Springfield".to_string(),
+                ..Default::default()
+            }),
+            annotations: HashMap::from([
+                ("a".to_string(), n.to_string()),
+                ("b".to_string(), "x".to_string()),
+            ]),
+            ..Default::default()
+        })
+        .collect()
+}
+
+async fn store_with(students: Vec<Student>) -> Arc<InMemoryRepository> {
+    let store = InMemoryRepository::new();
+    for result in store.create_many(students).await {
+        result.unwrap();
+    }
+    Arc::new(store)
+}
+
+// Stores every student without its address
+#[derive(Debug, Default)]
+struct Lossy(InMemoryRepository);
+
+#[tonic::async_trait]
+impl StudentRepository for Lossy {
+    async fn create(&self, student: Student) -> Result<Student, Status> {
+        self.0
+            .create(Student {
+                address: None,
+                ..student
+            })
+            .await
+    }
+
+    async fn get(&self, id: &str) -> Result<Student, Status> {
+        self.0.get(id).await
+    }
+
+    async fn update(&self, student: Student) -> Result<Student, Status> {
+        self.0.update(student).await
+    }
+
+    async fn delete(&self, id: &str) -> Result<Student, Status> {
+        self.0.delete(id).await
+    }
+
+    async fn list(
+        &self,
+        page_size: usize,
+        page_token: &str,
+    ) -> Result<ListStudentsResponse, Status> {
+        self.0.list(page_size, page_token).await
+    }
+}
+
+#[tokio::test]
+async fn copies_are_checked_against_the_source() {
+    let source = store_with(students()).await;
+    let destination = InMemoryRepository::new();
+    let copied = transfer::copy(source.as_ref(), &destination, None)
+        .await
+        .unwrap();
+    assert_eq!(copied.student_count, 250);
+    assert_eq!(copied.checksum, transfer::checksum(&students()));
+    assert_eq!(destination.get("s250").await.unwrap().name, "Student 250");
+
+    // Only into an empty store
+    let status = transfer::copy(source.as_ref(), &destination, None)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+
+    let status = transfer::copy(source.as_ref(), &Lossy::default(), None)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::DataLoss);
+    assert_eq!(
+        status.message(),
+        "Student s001 differs between the source and the destination"
+    );
+}
+
+#[tokio::test]
+async fn the_server_copies_its_store_to_a_snapshot() {
+    let dir = std::env::temp_dir().join(format!("transfer-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let service = StorageServiceImpl::new(store_with(students()).await, Operations::new());
+
+    let destination = format!("snapshot:{}", dir.display());
+    let operation = service
+        .migrate_storage(Request::new(MigrateStorageRequest { destination }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(operation.kind, transfer::KIND);
+    let copied = service
+        .get_storage_migration_result(Request::new(GetStorageMigrationResultRequest {
+            operation_id: operation.id,
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(copied.student_count, 250);
+
+    // The snapshot can be copied on, e.g. into another store
+    let location: Location = format!("snapshot:{}", copied.destination).parse().unwrap();
+    let snapshot = transfer::open(&location, None, &Default::default()).await.unwrap();
+    let again = transfer::copy(snapshot.as_ref(), &InMemoryRepository::new(), None)
+        .await
+        .unwrap();
+    assert_eq!(again.checksum, copied.checksum);
+
+    let status = service
+        .migrate_storage(Request::new(MigrateStorageRequest {
+            destination: "ftp://example.edu".to_string(),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    std::fs::remove_dir_all(dir).unwrap();
+}
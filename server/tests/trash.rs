@@ -0,0 +1,176 @@
+use proto::student_service_server::StudentService;
+use proto::trash_service_server::TrashService;
+use proto::{
+    ChangeType, CreateStudentRequest, DeleteStudentRequest, GetStudentRequest, ListTrashRequest,
+    Student, TrashReason, UndoRequest, WatchStudentsRequest,
+};
+use server::clock::FixedClock;
+use server::repository::{InMemoryRepository, StudentRepository};
+use server::trash::{Trash, TrashServiceImpl};
+use server::StudentServiceImpl;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use tokio_stream::StreamExt;
+use tonic::{Code, Request};
+
+// 2024-09-02 09:00 UTC
+const MONDAY: u64 = 1_725_267_600;
+
+struct Services {
+    store: Arc<InMemoryRepository>,
+    students: StudentServiceImpl,
+    trash: TrashServiceImpl,
+    clock: Arc<FixedClock>,
+}
+
+fn services() -> Services {
+    let store = Arc::new(InMemoryRepository::new());
+    let clock = Arc::new(FixedClock::new(UNIX_EPOCH + Duration::from_secs(MONDAY)));
+    let students = StudentServiceImpl::new().with_repository(store.clone());
+    let trash = Trash::new(store.clone())
+        .with_clock(clock.clone())
+        .with_window(Duration::from_secs(60 * 60))
+        .with_events(students.events());
+    Services {
+        store,
+        students: students.with_trash(trash.clone()),
+        trash: TrashServiceImpl::new(trash),
+        clock,
+    }
+}
+
+async fn create(services: &Services, id: &str) {
+    services
+        .students
+        .create_student(Request::new(CreateStudentRequest {
+            student: Some(Student {
+                id: id.to_string(),
+                name: id.to_string(),
+                email: format!("{}@university.edu", id),
+                age: 20,
+                ..Default::default()
+            }),
+        }))
+        .await
+        .unwrap();
+}
+
+// The operation ID to undo the deletion with
+async fn delete(services: &Services, id: &str) -> String {
+    services
+        .students
+        .delete_student(Request::new(DeleteStudentRequest { id: id.to_string() }))
+        .await
+        .unwrap()
+        .into_inner()
+        .operation_id
+}
+
+async fn undo(services: &Services, operation_id: &str) -> Result<Student, tonic::Status> {
+    services
+        .trash
+        .undo(Request::new(UndoRequest {
+            operation_id: operation_id.to_string(),
+        }))
+        .await
+        .map(|response| response.into_inner().student.unwrap())
+}
+
+async fn trash_ids(services: &Services) -> Vec<String> {
+    services
+        .trash
+        .list_trash(Request::new(ListTrashRequest::default()))
+        .await
+        .unwrap()
+        .into_inner()
+        .entries
+        .into_iter()
+        .map(|entry| entry.student.unwrap().id)
+        .collect()
+}
+
+#[tokio::test]
+async fn deleted_students_can_be_restored() {
+    let services = services();
+    for id in ["ada", "grace", "alan"] {
+        create(&services, id).await;
+    }
+    let ada = services.store.get("ada").await.unwrap();
+    let deleted_ada = delete(&services, "ada").await;
+    let deleted_grace = delete(&services, "grace").await;
+    assert!(!deleted_ada.is_empty());
+
+    let first = services
+        .trash
+        .list_trash(Request::new(ListTrashRequest {
+            page_size: 1,
+            page_token: String::new(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    let entry = &first.entries[0];
+    assert_eq!(entry.operation_id, deleted_grace);
+    assert_eq!(entry.reason, TrashReason::Deleted as i32);
+    assert_eq!(
+        entry.expire_time.as_ref().unwrap().seconds,
+        MONDAY as i64 + 60 * 60
+    );
+    assert!(!first.next_page_token.is_empty());
+    assert_eq!(trash_ids(&services).await, vec!["grace", "ada"]);
+
+    let mut events = services
+        .students
+        .watch_students(Request::new(WatchStudentsRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let restored = undo(&services, &deleted_ada).await.unwrap();
+    // As it was, but under a new etag
+    assert_ne!(restored.etag, ada.etag);
+    assert_eq!(
+        restored,
+        Student {
+            etag: restored.etag.clone(),
+            ..ada
+        }
+    );
+    let got = services
+        .students
+        .get_student(Request::new(GetStudentRequest {
+            id: "ada".to_string(),
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .student
+        .unwrap();
+    assert_eq!(got.name, "ada");
+    let event = events.next().await.unwrap().unwrap();
+    assert_eq!(event.change_type, ChangeType::Created as i32);
+    assert_eq!(event.student.unwrap().id, "ada");
+
+    assert_eq!(trash_ids(&services).await, vec!["grace"]);
+    let status = undo(&services, &deleted_ada).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn trash_expires_and_taken_ids_are_not_overwritten() {
+    let services = services();
+    create(&services, "ada").await;
+    create(&services, "grace").await;
+    let deleted_ada = delete(&services, "ada").await;
+
+    // A new student has taken the ID; the old one stays in the trash
+    create(&services, "ada").await;
+    let status = undo(&services, &deleted_ada).await.unwrap_err();
+    assert_eq!(status.code(), Code::AlreadyExists);
+    assert_eq!(trash_ids(&services).await, vec!["ada"]);
+
+    let deleted_grace = delete(&services, "grace").await;
+    services.clock.advance(Duration::from_secs(60 * 60));
+    assert!(trash_ids(&services).await.is_empty());
+    let status = undo(&services, &deleted_grace).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
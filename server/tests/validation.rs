@@ -0,0 +1,125 @@
+use proto::student_service_server::StudentService;
+use proto::{CreateStudentRequest, Student, ValidateStudentRequest};
+use server::validation::Profile;
+use server::StudentServiceImpl;
+use student_core::Problem;
+use tonic::{Code, Request};
+
+fn messy() -> Student {
+    Student {
+        id: " s1 ".to_string(),
+        name: "  Ada   Lovelace ".to_string(),
+        email: "mailto:Ada@University.EDU ".to_string(),
+        age: 36,
+        major: "Applied  Mathematics".to_string(),
+        gpa: 3.856,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn profiles_are_named_on_the_command_line() {
+    assert_eq!("lenient".parse(), Ok(Profile::Lenient));
+    assert_eq!("strict".parse(), Ok(Profile::Strict));
+    assert!("loose".parse::<Profile>().is_err());
+    assert_eq!(Profile::default(), Profile::Lenient);
+}
+
+#[test]
+fn lenient_tidies_input_up() {
+    let mut student = messy();
+    assert!(Profile::Lenient.check(&mut student).is_empty());
+    assert_eq!(student.id, "s1");
+    assert_eq!(student.name, "Ada Lovelace");
+    assert_eq!(student.email, "ada@university.edu");
+    assert_eq!(student.major, "Applied Mathematics");
+    assert_eq!(student.gpa, 3.86);
+
+    // Tidying cannot make up for what is missing or out of range
+    let mut student = Student {
+        name: "   ".to_string(),
+        email: "<>".to_string(),
+        age: 200,
+        ..Default::default()
+    };
+    let fields: Vec<_> = Profile::Lenient
+        .check(&mut student)
+        .into_iter()
+        .map(|(field, _)| field)
+        .collect();
+    assert_eq!(fields, ["name", "email", "age"]);
+}
+
+#[test]
+fn strict_rejects_anything_not_canonical() {
+    let mut student = messy();
+    let problems = Profile::Strict.check(&mut student);
+    assert_eq!(
+        problems,
+        [
+            ("id", Problem::IdNotCanonical),
+            ("name", Problem::NameNotCanonical),
+            ("email", Problem::EmailNotCanonical),
+            ("major", Problem::MajorNotCanonical),
+            ("gpa", Problem::GpaNotCanonical),
+        ]
+    );
+    // Left as it was
+    assert_eq!(student, messy());
+
+    let mut tidy = messy();
+    Profile::Lenient.check(&mut tidy);
+    assert!(Profile::Strict.check(&mut tidy).is_empty());
+
+    assert_eq!(
+        Profile::Strict.email("ada@localhost"),
+        Err(Problem::EmailInvalid)
+    );
+    assert_eq!(Profile::Strict.email("ada"), Err(Problem::EmailInvalid));
+    assert_eq!(Profile::Strict.email(" "), Err(Problem::EmailEmpty));
+    assert_eq!(
+        Profile::Lenient.email("<Ada@University.edu>"),
+        Ok("ada@university.edu".to_string())
+    );
+}
+
+#[tokio::test]
+async fn the_service_follows_its_profile() {
+    let lenient = StudentServiceImpl::new();
+    let created = lenient
+        .create_student(Request::new(CreateStudentRequest {
+            student: Some(messy()),
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .student
+        .unwrap();
+    assert_eq!(created.id, "s1");
+    assert_eq!(created.name, "Ada Lovelace");
+    assert_eq!(created.email, "ada@university.edu");
+
+    let strict = StudentServiceImpl::new().with_validation(Profile::Strict);
+    let status = strict
+        .create_student(Request::new(CreateStudentRequest {
+            student: Some(messy()),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(
+        client::error_info(&status).unwrap().reason,
+        "ID_NOT_CANONICAL"
+    );
+
+    let response = strict
+        .validate_student(Request::new(ValidateStudentRequest {
+            student: Some(messy()),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!response.valid);
+    assert_eq!(response.violations.len(), 5);
+    assert_eq!(response.violations[2].field, "email");
+}
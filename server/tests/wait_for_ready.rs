@@ -0,0 +1,76 @@
+use client::StudentClient;
+use proto::student_service_server::StudentServiceServer;
+use server::StudentServiceImpl;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::Server;
+use tonic::{Code, Request, Status};
+
+// Serves on `listener`, failing the first `failures` calls with UNAVAILABLE
+// as a server on its way down would; returns the number of calls seen
+fn serve(listener: TcpListener, failures: usize) -> Arc<AtomicUsize> {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let interceptor = {
+        let calls = calls.clone();
+        #[allow(clippy::result_large_err)]
+        move |request: Request<()>| match calls.fetch_add(1, Ordering::SeqCst) < failures {
+            true => Err(Status::unavailable("shutting down")),
+            false => Ok(request),
+        }
+    };
+    tokio::spawn(
+        Server::builder()
+            .add_service(StudentServiceServer::with_interceptor(
+                StudentServiceImpl::new(),
+                interceptor,
+            ))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    calls
+}
+
+fn client(addr: &str) -> StudentClient {
+    StudentClient::connect_lazy(format!("http://{}", addr))
+        .unwrap()
+        .with_wait_for_ready(Duration::from_secs(5))
+}
+
+#[tokio::test]
+async fn writes_wait_for_a_server_that_is_not_up_yet() {
+    // A port no one is listening on, for now
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let mut client = client(&addr.to_string());
+    let starting = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        serve(TcpListener::bind(addr).await.unwrap(), 0)
+    });
+
+    let status = client.delete_student("s1").await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    assert_eq!(starting.await.unwrap().load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn writes_the_server_may_have_seen_are_not_repeated() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let calls = serve(listener, 1);
+    let mut client = client(&addr.to_string());
+
+    let status = client.delete_student("s1").await.unwrap_err();
+    assert_eq!(status.code(), Code::Unavailable);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // Reads, though, are tried again
+    calls.store(0, Ordering::SeqCst);
+    let status = client.get_student("s1").await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,149 @@
+use client::StudentClient;
+use proto::student_service_server::{StudentService, StudentServiceServer};
+use proto::{AcademicStanding, ChangeType, CreateStudentRequest, Student, WatchStudentsRequest};
+use server::standing::StandingRules;
+use server::StudentServiceImpl;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tokio_stream::wrappers::TcpListenerStream;
+use tokio_stream::StreamExt;
+use tonic::transport::Server;
+use tonic::{Code, Request};
+
+fn student(name: &str) -> Student {
+    Student {
+        name: name.to_string(),
+        email: format!("{}@university.edu", name.to_lowercase()),
+        age: 20,
+        major: "History".to_string(),
+        gpa: 3.0,
+        ..Default::default()
+    }
+}
+
+async fn create(service: &StudentServiceImpl, name: &str) {
+    service
+        .create_student(Request::new(CreateStudentRequest {
+            student: Some(student(name)),
+        }))
+        .await
+        .unwrap();
+}
+
+async fn watch(
+    service: &StudentServiceImpl,
+    resume_token: &str,
+) -> Result<<StudentServiceImpl as StudentService>::WatchStudentsStream, tonic::Status> {
+    service
+        .watch_students(Request::new(WatchStudentsRequest {
+            resume_token: resume_token.to_string(),
+            ..Default::default()
+        }))
+        .await
+        .map(|response| response.into_inner())
+}
+
+#[tokio::test]
+async fn resume_replays_missed_events() {
+    let service = StudentServiceImpl::new();
+    let mut events = watch(&service, "").await.unwrap();
+    create(&service, "Ada").await;
+    let first = events.next().await.unwrap().unwrap();
+
+    // Missed while disconnected
+    drop(events);
+    create(&service, "Grace").await;
+    create(&service, "Alan").await;
+
+    let mut events = watch(&service, &first.resume_token).await.unwrap();
+    for name in ["Grace", "Alan"] {
+        let event = events.next().await.unwrap().unwrap();
+        assert_eq!(event.change_type(), ChangeType::Created);
+        assert_eq!(event.student.unwrap().name, name);
+    }
+}
+
+#[tokio::test]
+async fn unknown_resume_token_is_out_of_range() {
+    let service = StudentServiceImpl::new();
+    let other = StudentServiceImpl::new();
+    create(&other, "Ada").await;
+    let mut events = watch(&other, "").await.unwrap();
+    create(&other, "Grace").await;
+    let token = events.next().await.unwrap().unwrap().resume_token;
+
+    let status = watch(&service, &token).await.err().unwrap();
+    assert_eq!(status.code(), Code::OutOfRange);
+    let status = watch(&service, "not a token").await.err().unwrap();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn watchers_can_follow_one_standing() {
+    let service = StudentServiceImpl::new().with_standing_rules(StandingRules {
+        deans_list_min_credits: 0,
+        ..Default::default()
+    });
+    let mut events = service
+        .watch_students(Request::new(WatchStudentsRequest {
+            standing: AcademicStanding::DeansList.into(),
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+
+    create(&service, "Ada").await;
+    let top = Student {
+        gpa: 3.9,
+        ..student("Grace")
+    };
+    service
+        .create_student(Request::new(CreateStudentRequest { student: Some(top) }))
+        .await
+        .unwrap();
+
+    // Ada, with a 3.0, is in good standing and left out
+    let event = events.next().await.unwrap().unwrap();
+    let student = event.student.unwrap();
+    assert_eq!(student.name, "Grace");
+    assert_eq!(student.standing(), AcademicStanding::DeansList);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shutdown_ends_watch_with_resume_token() {
+    let service = StudentServiceImpl::new();
+    let events = service.events();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (stop, stopped) = oneshot::channel::<()>();
+    let server = tokio::spawn(
+        Server::builder()
+            .add_service(StudentServiceServer::new(service))
+            .serve_with_incoming_shutdown(TcpListenerStream::new(listener), async move {
+                let _ = stopped.await;
+                events.shut_down();
+            }),
+    );
+
+    let mut client = StudentClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap();
+    let mut watching = client.watch_students("").await.unwrap();
+    client.create_student(student("Ada")).await.unwrap();
+    let created = watching.next().await.unwrap().unwrap();
+
+    stop.send(()).unwrap();
+    let last = watching.next().await.unwrap().unwrap();
+    assert_eq!(last.change_type(), ChangeType::ShuttingDown);
+    assert_eq!(last.resume_token, created.resume_token);
+    assert!(watching.next().await.is_none());
+
+    // With the stream drained, graceful shutdown completes
+    tokio::time::timeout(Duration::from_secs(5), server)
+        .await
+        .expect("server did not shut down")
+        .unwrap()
+        .unwrap();
+}
@@ -0,0 +1,7 @@
+#!/bin/sh
+# Check that the web client, and the proto crate without its transport,
+# still compile to wasm32
+set -e
+cd "$(dirname "$0")"
+rustup target add wasm32-unknown-unknown
+cargo check --target wasm32-unknown-unknown
@@ -0,0 +1,68 @@
+//! The student service from the browser: the generated client, compiled to
+//! wasm32, speaking gRPC-Web to the server through `fetch`.
+//!
+//! `StudentWebClient` is exported to JavaScript with wasm-bindgen. Its
+//! methods return promises of canonical proto3 JSON, and reject with the
+//! gRPC code and message when the call fails.
+
+use js_sys::Promise;
+use proto::student_service_client::StudentServiceClient;
+use proto::{GetStudentRequest, ListStudentsRequest};
+use tonic::Status;
+use tonic_web_wasm_client::Client;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::future_to_promise;
+
+#[wasm_bindgen]
+pub struct StudentWebClient {
+    inner: StudentServiceClient<Client>,
+}
+
+#[wasm_bindgen]
+impl StudentWebClient {
+    /// A client of the server at `server`, e.g. `http://localhost:50051`,
+    /// which must allow the page's origin.
+    #[wasm_bindgen(constructor)]
+    pub fn new(server: String) -> StudentWebClient {
+        StudentWebClient {
+            inner: StudentServiceClient::new(Client::new(server)),
+        }
+    }
+
+    /// The student with `id`.
+    #[wasm_bindgen(js_name = getStudent)]
+    pub fn get_student(&self, id: String) -> Promise {
+        let mut inner = self.inner.clone();
+        future_to_promise(async move {
+            let response = inner
+                .get_student(GetStudentRequest { id })
+                .await
+                .map_err(to_error)?;
+            to_json(&response.into_inner().student)
+        })
+    }
+
+    /// One page of students, with the token of the next.
+    #[wasm_bindgen(js_name = listStudents)]
+    pub fn list_students(&self, page_size: i32, page_token: String) -> Promise {
+        let mut inner = self.inner.clone();
+        future_to_promise(async move {
+            let request = ListStudentsRequest {
+                page_size,
+                page_token,
+                ..Default::default()
+            };
+            let response = inner.list_students(request).await.map_err(to_error)?;
+            to_json(&response.into_inner())
+        })
+    }
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<JsValue, JsValue> {
+    let json = serde_json::to_string(value).map_err(|e| JsError::new(&e.to_string()))?;
+    js_sys::JSON::parse(&json)
+}
+
+fn to_error(status: Status) -> JsValue {
+    JsError::new(&format!("{:?}: {}", status.code(), status.message())).into()
+}